@@ -1,9 +1,11 @@
-//! `mfsrun` is a polymorphic binary that can operate in two modes: NFS server or supervisor.
+//! `mfsrun` is a polymorphic binary that can operate in three modes: NFS server, FUSE server or
+//! supervisor.
 //!
 //! # Overview
 //!
 //! This binary provides a unified interface for running either:
 //! - An NFS server that serves a monofs filesystem
+//! - A FUSE server that mounts a monofs filesystem directly
 //! - A supervisor process that can manage and monitor child processes
 //!
 //! ## Usage
@@ -23,6 +25,35 @@
 //! - `--host`: The address to bind to (default: "127.0.0.1")
 //! - `--port`: The port to listen on (default: 2049)
 //! - `--store-dir`: Directory path where the monofs store will be located
+//! - `--fs-db-path`: Optional database to restore and record the filesystem's head in
+//! - `--mount-dir`: Mount directory identifying the filesystem in the database
+//! - `--chunk-min-size`, `--chunk-desired-size`, `--chunk-max-size`: Content-defined chunking
+//!   sizes in bytes used to split file contents into blocks
+//! - `--allow-client`: A network clients may connect from, e.g. `10.0.0.0/8`, repeatable. Any
+//!   client may connect if none is given
+//! - `--squash`, `--anon-uid`, `--anon-gid`: Which owners set by clients (`none`, `root` or
+//!   `all`) are mapped to which anonymous user and group
+//! - `--max-ops-per-sec`, `--max-bytes-per-sec`: How many requests are served, and how many bytes
+//!   moved for them, each second across all clients
+//! - `--client-max-ops-per-sec`, `--client-max-bytes-per-sec`: The same limits for each client
+//!   connection on its own
+//!
+//! ### FUSE Server Mode
+//!
+//! To run as a FUSE server:
+//! ```bash
+//! mfsrun fuseserver \
+//!     --store-dir=/path/to/store \
+//!     --mount-dir=/path/to/mount
+//! ```
+//!
+//! #### FUSE Server Parameters
+//!
+//! - `--store-dir`: Directory path where the monofs store will be located
+//! - `--mount-dir`: Directory to mount the filesystem at
+//! - `--fs-db-path`: Optional database to restore and record the filesystem's head in
+//! - `--chunk-min-size`, `--chunk-desired-size`, `--chunk-max-size`: Content-defined chunking
+//!   sizes in bytes used to split file contents into blocks
 //!
 //! ### Supervisor Mode
 //!
@@ -46,6 +77,9 @@
 //! - `--port`: The port for the NFS server to listen on (default: 2049)
 //! - `--store-dir`: Directory path where the monofs store will be located
 //! - `--db-path`: Path to the metrics database file
+//! - `--backend`: The server to supervise, either `nfs` or `fuse` (default: "nfs")
+//! - `--metrics-addr`: Optional address to serve Prometheus metrics on, e.g. `127.0.0.1:9400`
+//! - `--chunk-min-size`, `--chunk-desired-size`, `--chunk-max-size`: Passed on to the server
 //!
 //! ## Examples
 //!
@@ -71,16 +105,57 @@
 //!
 //! Note: When running in supervisor mode, the supervisor will automatically use the current
 //! executable as the child process, allowing for self-supervision of the NFS server.
+//!
+//! The supervisor restarts the server with an exponential backoff whenever it exits without the
+//! supervisor being asked to stop. An NFS server is also probed on its port every few seconds and
+//! killed, and thereby restarted, if it stops accepting connections. Every restart is recorded in
+//! the `server_restarts` table of the filesystem database.
+//!
+//! The supervisor also takes the snapshots the filesystem's snapshot schedule asks for, reading
+//! the schedule from the filesystem database as it changes. See
+//! `monofs::management::set_snapshot_schedule`.
+//!
+//! Every process logs to stderr, filtered by `RUST_LOG`, which the supervisor passes on to its
+//! server. The NFS operations run in `debug` spans that are logged with their latency when they
+//! close, e.g. with `RUST_LOG=info,monofs=debug`. Built with the `otlp` feature, the spans are also
+//! exported to the OpenTelemetry collector at `OTEL_EXPORTER_OTLP_ENDPOINT` when it is set.
+//!
+//! Given `--metrics-addr`, the supervisor serves the metrics of the filesystem at `/metrics` in
+//! the Prometheus text format: the operations its server served and how long they took, the hit
+//! rates of the caches in front of the block store, the size of the store, and how often the root
+//! was updated and the server restarted.
+//!
+//! Built with the `grpc` feature, the supervisor also serves the `monofs.control.v1` gRPC API
+//! defined in `proto/control.proto` on a `grpc.sock` unix socket next to the filesystem database,
+//! through which orchestrators can query its status, sync it, snapshot it, change its quota and
+//! shut it down.
 
-use std::env;
+use std::{env, time::Instant};
 
 use anyhow::Result;
 use clap::Parser;
+use futures::FutureExt;
 use microsandbox_utils::runtime::Supervisor;
 use monofs::{
-    cli::{MfsRuntimeArgs, MfsRuntimeSubcommand},
-    runtime::NfsServerMonitor,
-    server::MonofsServer,
+    cli::{
+        ChunkerArgs, CompressionArgs, ExportArgs, MfsRuntimeArgs, MfsRuntimeSubcommand,
+        ThrottleArgs, TransferArgs, WriteBackArgs,
+    },
+    config::{EncryptionKey, MountBackend, RootSigningKey},
+    management,
+    runtime::{
+        self, BlockScrubber, LogRotator, MetricsServer, NfsServerMonitor, RestartBackoff,
+        SnapshotScheduler, Telemetry, DEFAULT_LOG_FILTER,
+    },
+    server::{MonofsFuseServer, MonofsServer},
+    utils::{ENCRYPTION_KEY_ENV_VAR, LOG_FILTER_ENV_VAR, SIGNING_KEY_ENV_VAR},
+};
+
+#[cfg(feature = "grpc")]
+use monofs::runtime::GrpcControlServer;
+use tokio::{
+    signal::{self, unix::SignalKind},
+    time,
 };
 
 //--------------------------------------------------------------------------------------------------
@@ -89,20 +164,51 @@ use monofs::{
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize logging without ANSI colors
-    tracing_subscriber::fmt().with_ansi(false).init();
+    // Initialize logging without ANSI colors, and span export if enabled
+    let telemetry = Telemetry::init("mfsrun")?;
 
     // Parse command line arguments
     let args = MfsRuntimeArgs::parse();
 
+    // Export the remaining spans however the process ends
+    let result = run(args).await;
+    telemetry.shutdown();
+    result
+}
+
+/// Runs the server or supervisor selected by `args` until it is shut down.
+async fn run(args: MfsRuntimeArgs) -> Result<()> {
     match args.subcommand {
         MfsRuntimeSubcommand::Nfsserver {
             host,
             port,
             store_dir,
+            fs_db_path,
+            mount_dir,
+            chunker,
+            compression,
+            write_back,
+            transfer,
+            exports,
+            throttle,
+            atime,
+            durability,
         } => {
             // Create and start NFS server
-            let server = MonofsServer::new(store_dir, host, port);
+            let mut server = MonofsServer::new(store_dir, host, port)
+                .with_chunker(chunker.into())
+                .with_compression(compression.into())
+                .with_encryption(EncryptionKey::from_env()?)
+                .with_write_back(write_back.into())
+                .with_transfer(transfer.into())
+                .with_exports(exports.into())
+                .with_throttle(throttle.into())
+                .with_atime_policy(atime)
+                .with_durability(durability);
+            if let (Some(fs_db_path), Some(mount_dir)) = (fs_db_path, mount_dir) {
+                server = server.with_fs_db(fs_db_path, mount_dir);
+            }
+
             tracing::info!(
                 "Starting NFS server on {}:{}",
                 server.get_host(),
@@ -112,6 +218,36 @@ async fn main() -> Result<()> {
 
             server.start().await?;
         }
+        MfsRuntimeSubcommand::Fuseserver {
+            store_dir,
+            mount_dir,
+            fs_db_path,
+            chunker,
+            compression,
+            write_back,
+            atime,
+            durability,
+        } => {
+            // Create and start FUSE server
+            let mut server = MonofsFuseServer::new(store_dir, mount_dir)
+                .with_chunker(chunker.into())
+                .with_compression(compression.into())
+                .with_encryption(EncryptionKey::from_env()?)
+                .with_write_back(write_back.into())
+                .with_atime_policy(atime)
+                .with_durability(durability);
+            if let Some(fs_db_path) = fs_db_path {
+                server = server.with_fs_db(fs_db_path);
+            }
+
+            tracing::info!(
+                "Starting FUSE server at {}",
+                server.get_mount_dir().display()
+            );
+            tracing::info!("Using store at: {}", server.get_store_dir().display());
+
+            server.start().await?;
+        }
         MfsRuntimeSubcommand::Supervisor {
             log_dir,
             child_name,
@@ -120,6 +256,16 @@ async fn main() -> Result<()> {
             store_dir,
             fs_db_path,
             mount_dir,
+            backend,
+            metrics_addr,
+            chunker,
+            compression,
+            write_back,
+            transfer,
+            exports,
+            throttle,
+            atime,
+            durability,
         } => {
             // Get current executable path
             let child_exe = env::current_exe()?;
@@ -127,32 +273,166 @@ async fn main() -> Result<()> {
             // Get supervisor PID
             let supervisor_pid = std::process::id();
 
-            // Create nfs server monitor
-            let process_monitor = NfsServerMonitor::new(
-                supervisor_pid,
-                fs_db_path,
-                child_name,
-                mount_dir,
-                log_dir.clone(),
-            )
-            .await?;
+            // Only the NFS server listens on a port
+            let server_port = match backend {
+                MountBackend::Nfs => Some(port),
+                MountBackend::Fuse => None,
+            };
 
             // Compose child arguments
-            let child_args = vec![
-                "nfsserver".to_string(),
-                format!("--host={}", host),
-                format!("--port={}", port),
-                format!("--store-dir={}", store_dir.display()),
-            ];
+            let mut child_args = match backend {
+                MountBackend::Nfs => vec![
+                    "nfsserver".to_string(),
+                    format!("--host={}", host),
+                    format!("--port={}", port),
+                    format!("--store-dir={}", store_dir.display()),
+                    format!("--fs-db-path={}", fs_db_path.display()),
+                    format!("--mount-dir={}", mount_dir.display()),
+                ],
+                MountBackend::Fuse => vec![
+                    "fuseserver".to_string(),
+                    format!("--store-dir={}", store_dir.display()),
+                    format!("--mount-dir={}", mount_dir.display()),
+                    format!("--fs-db-path={}", fs_db_path.display()),
+                ],
+            };
+            child_args.extend(ChunkerArgs::to_args(&chunker.into()));
+            child_args.extend(CompressionArgs::to_args(&compression.into()));
+            child_args.extend(WriteBackArgs::to_args(&write_back.into()));
+            child_args.push(format!("--atime={}", atime));
+            child_args.push(format!("--durability={}", durability));
+            if backend == MountBackend::Nfs {
+                child_args.extend(TransferArgs::to_args(&transfer.into()));
+                child_args.extend(ExportArgs::to_args(&exports.into()));
+                child_args.extend(ThrottleArgs::to_args(&throttle.into()));
+            }
+
+            // Compose child environment variables, passing on the encryption and signing keys
+            // wherever they were read from
+            let encryption_key = EncryptionKey::from_env()?.map(|key| key.to_hex());
+            let signing_key = RootSigningKey::from_env()?.map(|key| key.to_hex());
+            let log_filter =
+                env::var(LOG_FILTER_ENV_VAR).unwrap_or_else(|_| DEFAULT_LOG_FILTER.to_string());
+            let mut child_envs = vec![(LOG_FILTER_ENV_VAR, log_filter.as_str())];
+            if let Some(encryption_key) = &encryption_key {
+                child_envs.push((ENCRYPTION_KEY_ENV_VAR, encryption_key.as_str()));
+            }
+            if let Some(signing_key) = &signing_key {
+                child_envs.push((SIGNING_KEY_ENV_VAR, signing_key.as_str()));
+            }
+
+            // The supervisor stops its child on these signals, after which it must not restart it
+            let mut sigterm = signal::unix::signal(SignalKind::terminate())?;
+            let mut sigint = signal::unix::signal(SignalKind::interrupt())?;
+
+            // Serve metrics for as long as the supervisor runs, across restarts of the server
+            let metrics = match &metrics_addr {
+                Some(addr) => {
+                    let server =
+                        MetricsServer::new(addr, &fs_db_path, &mount_dir, &store_dir, backend)
+                            .await?;
+                    Some(tokio::spawn(async move {
+                        if let Err(e) = server.serve().await {
+                            tracing::error!(error = %e, "metrics server stopped");
+                        }
+                    }))
+                }
+                None => None,
+            };
+
+            // Take scheduled snapshots for as long as the supervisor runs, across restarts of the
+            // server
+            let scheduler =
+                tokio::spawn(SnapshotScheduler::new(&fs_db_path, &mount_dir).await?.run());
+
+            // Scrub the blocks a batch at a time for as long as the supervisor runs
+            let scrubber = tokio::spawn(BlockScrubber::new(&fs_db_path, &mount_dir).await?.run());
+
+            // Compress and expire the rotated logs for as long as the supervisor runs, and hand the
+            // rotation settings to the log of each start of the server
+            let log_rotator = LogRotator::new(&fs_db_path, &mount_dir, &log_dir).await?;
+            let log_rotation = log_rotator.subscribe();
+            let log_rotator = tokio::spawn(log_rotator.run());
+
+            // Serve the gRPC control API for as long as the supervisor runs
+            #[cfg(feature = "grpc")]
+            let grpc = {
+                let server = GrpcControlServer::new(&fs_db_path, &mount_dir);
+                tokio::spawn(async move {
+                    if let Err(e) = server.serve().await {
+                        tracing::error!(error = %e, "gRPC control API stopped");
+                    }
+                })
+            };
+
+            let fs_db = management::get_db_pool(&fs_db_path).await?;
+            let mut backoff = RestartBackoff::new();
+            loop {
+                // Create nfs server monitor
+                let process_monitor = NfsServerMonitor::new(
+                    supervisor_pid,
+                    &fs_db_path,
+                    child_name.clone(),
+                    mount_dir.clone(),
+                    log_dir.clone(),
+                    backend,
+                    server_port,
+                )
+                .await?
+                .with_health_check(&host)
+                .with_metrics_addr(metrics_addr.clone())
+                .with_log_rotation(log_rotation.clone());
+
+                // Create and start supervisor
+                let mut supervisor = Supervisor::new(
+                    child_exe.clone(),
+                    child_args.clone(),
+                    child_envs.clone(),
+                    log_dir.clone(),
+                    process_monitor,
+                );
+
+                let started_at = Instant::now();
+                let result = supervisor.start().await;
+                if sigterm.recv().now_or_never().is_some() || sigint.recv().now_or_never().is_some()
+                {
+                    result?;
+                    break;
+                }
+
+                // The child died or was killed for being unresponsive, restart it after a while
+                let uptime = started_at.elapsed();
+                let delay = backoff.next_delay(uptime);
+                if let Err(e) = result {
+                    tracing::error!(error = %e, "server failed");
+                }
+                tracing::warn!(
+                    "server stopped after {:?}, restarting in {:?}",
+                    uptime,
+                    delay
+                );
+
+                if let Err(e) = runtime::record_restart(&fs_db, &mount_dir, uptime, delay).await {
+                    tracing::warn!(error = %e, "failed to record server restart");
+                }
+
+                tokio::select! {
+                    _ = time::sleep(delay) => {}
+                    _ = sigterm.recv() => break,
+                    _ = sigint.recv() => break,
+                }
+            }
 
-            // Compose child environment variables
-            let child_envs = vec![("RUST_LOG", "info")];
+            if let Some(metrics) = metrics {
+                metrics.abort();
+            }
 
-            // Create and start supervisor
-            let mut supervisor =
-                Supervisor::new(child_exe, child_args, child_envs, log_dir, process_monitor);
+            scheduler.abort();
+            scrubber.abort();
+            log_rotator.abort();
 
-            supervisor.start().await?;
+            #[cfg(feature = "grpc")]
+            grpc.abort();
         }
     }
 
@@ -1,8 +1,20 @@
 use clap::{CommandFactory, Parser};
+use futures::StreamExt;
 use monofs::{
-    cli::{MonofsArgs, MonofsSubcommand},
-    management,
+    cli::{
+        BackupSubcommand, BranchSubcommand, MonofsArgs, MonofsSubcommand, PinSubcommand,
+        ReplicaSubcommand, SnapshotSubcommand,
+    },
+    config::{
+        InitOptions, LogRotationConfig, LogSource, RootSigningKey, ScrubConfig, SnapshotRetention,
+        SnapshotScheduleConfig, TailOptions,
+    },
+    management::{self, MountHealth, TreeSource},
 };
+use serde::Serialize;
+use serde_json::json;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 
 //--------------------------------------------------------------------------------------------------
 // Functions: main
@@ -15,16 +27,1028 @@ async fn main() -> anyhow::Result<()> {
 
     // Parse command line arguments
     let args = MonofsArgs::parse();
+    let json = args.json;
     match args.subcommand {
-        Some(MonofsSubcommand::Init { mount_dir }) => {
+        Some(MonofsSubcommand::Init {
+            mount_dir,
+            backend,
+            host,
+            port_range,
+            port,
+            metrics_addr,
+            chunker,
+            compression,
+            write_back,
+            transfer,
+            mount_options,
+            exports,
+            throttle,
+            atime,
+            durability,
+            hash_function,
+            quota,
+            remote,
+            memory,
+            mirrors,
+            subtree,
+            audit,
+            mount_timeout_secs,
+        }) => {
+            let options = InitOptions::builder()
+                .backend(backend)
+                .host(host)
+                .port_range(port_range)
+                .fixed_port(port)
+                .metrics_addr(metrics_addr)
+                .chunker(chunker.to_config())
+                .compression(compression.to_config())
+                .write_back(write_back.into())
+                .transfer(transfer.into())
+                .extra_mount_options(mount_options)
+                .exports(exports.to_config())
+                .throttle(throttle.to_config())
+                .atime(atime)
+                .durability(durability)
+                .hash_function(hash_function)
+                .quota(quota.to_config())
+                .remote(remote.into())
+                .memory(memory.into())
+                .mirrors(mirrors)
+                .subtree(subtree)
+                .audit(audit)
+                .mount_timeout(Duration::from_secs(mount_timeout_secs))
+                .build();
+
+            // Interrupting initialization stops the supervisor and cleans up after it
+            let cancel = CancellationToken::new();
+            tokio::spawn({
+                let cancel = cancel.clone();
+                async move {
+                    if tokio::signal::ctrl_c().await.is_ok() {
+                        cancel.cancel();
+                    }
+                }
+            });
+
             tracing::info!("initializing monofs...");
-            management::init_mfs(mount_dir).await?;
+            let mount = management::init_mfs_with_cancel(mount_dir, options, cancel).await?;
             tracing::info!("successfully initialized monofs");
+            if json {
+                print_json(&mount)?;
+            }
+        }
+        Some(MonofsSubcommand::Attach { mount_dir }) => {
+            tracing::info!("attaching monofs...");
+            let mount = management::attach_mfs(mount_dir).await?;
+            tracing::info!("successfully attached monofs");
+            if json {
+                print_json(&mount)?;
+            }
+        }
+        Some(MonofsSubcommand::History { mount_dir }) => {
+            let entries = management::list_history(mount_dir).await?;
+            if json {
+                return print_json(&entries);
+            }
+
+            for entry in entries {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    entry.get_version(),
+                    entry.get_created_at().to_rfc3339(),
+                    entry.get_branch(),
+                    entry.get_root(),
+                    entry.get_operation()
+                );
+            }
+        }
+        Some(MonofsSubcommand::AuditLog {
+            since,
+            path,
+            mount_dir,
+        }) => {
+            let entries = management::audit_log(mount_dir, since, path.as_deref()).await?;
+            if json {
+                return print_json(&entries);
+            }
+
+            for entry in entries {
+                let event = entry.get_event();
+                let caller = match (event.get_uid(), event.get_gid()) {
+                    (Some(uid), Some(gid)) => format!("{}:{}", uid, gid),
+                    _ => "-".to_string(),
+                };
+                let path = match event.get_new_path() {
+                    Some(new_path) => format!("{} -> {}", event.get_path(), new_path),
+                    None => event.get_path().clone(),
+                };
+                println!(
+                    "{}\t{}\t{}\t{}\t{}\t{}",
+                    entry.get_sequence(),
+                    event.get_occurred_at().to_rfc3339(),
+                    caller,
+                    event.get_operation(),
+                    path,
+                    event
+                        .get_cid()
+                        .map_or("-".to_string(), |cid| cid.to_string())
+                );
+            }
+        }
+        Some(MonofsSubcommand::Checkout { target, mount_dir }) => {
+            let entry = management::checkout(mount_dir, target).await?;
+            if json {
+                print_json(&entry)?;
+            }
+            tracing::info!(
+                "checked out version {} ({})",
+                entry.get_version(),
+                entry.get_root()
+            );
+        }
+        Some(MonofsSubcommand::VerifyHead {
+            public_key,
+            mount_dir,
+        }) => {
+            let entry = management::verify_head(mount_dir, &public_key).await?;
+            if json {
+                print_json(&entry)?;
+            }
+            tracing::info!(
+                "head {} is signed by {} (version {})",
+                entry.get_root(),
+                public_key,
+                entry.get_version()
+            );
+        }
+        Some(MonofsSubcommand::PublicKey) => {
+            let key = RootSigningKey::from_env()?
+                .ok_or_else(|| anyhow::anyhow!("no signing key is given in the environment"))?;
+            println!("{}", key.get_public_key());
+        }
+        Some(MonofsSubcommand::Clone { source, target }) => {
+            tracing::info!("cloning monofs...");
+            let mount = management::clone_mfs(source, target).await?;
+            tracing::info!("successfully cloned monofs");
+            if json {
+                print_json(&mount)?;
+            }
+        }
+        Some(MonofsSubcommand::Share {
+            source,
+            target,
+            root,
+        }) => {
+            tracing::info!("sharing monofs...");
+            let mount = management::share_mfs(source, target, root).await?;
+            tracing::info!("successfully shared monofs");
+            if json {
+                print_json(&mount)?;
+            }
+        }
+        Some(MonofsSubcommand::Log {
+            source,
+            lines,
+            follow,
+            filter,
+            mount_dir,
+        }) => {
+            let options = TailOptions::builder()
+                .source(source)
+                .lines(lines)
+                .follow(follow)
+                .filter(filter)
+                .build();
+            let mut lines = management::tail_logs(mount_dir, options).await?;
+            while let Some(line) = lines.next().await {
+                let line = line?;
+                if json {
+                    print_json(&line)?;
+                    continue;
+                }
+
+                match source {
+                    LogSource::All => println!("[{}] {}", line.get_source(), line.get_line()),
+                    _ => println!("{}", line.get_line()),
+                }
+            }
+        }
+        Some(MonofsSubcommand::LogRotation {
+            max_bytes,
+            max_age_secs,
+            compress,
+            max_files,
+            retention_secs,
+            reset,
+            mount_dir,
+        }) => {
+            // Without any setting, only the current settings are shown
+            if reset {
+                management::set_log_rotation(mount_dir.clone(), None).await?;
+            } else if max_bytes.is_some()
+                || max_age_secs.is_some()
+                || compress.is_some()
+                || max_files.is_some()
+                || retention_secs.is_some()
+            {
+                let current = management::get_log_rotation(mount_dir.clone()).await?;
+                let max_age_secs = match max_age_secs {
+                    Some(secs) => (secs > 0).then_some(secs),
+                    None => *current.get_max_age_secs(),
+                };
+                let retention_secs = match retention_secs {
+                    Some(secs) => (secs > 0).then_some(secs),
+                    None => *current.get_retention_secs(),
+                };
+                let config = LogRotationConfig::builder()
+                    .max_bytes(max_bytes.unwrap_or(*current.get_max_bytes()))
+                    .max_age_secs(max_age_secs)
+                    .compress(compress.unwrap_or(*current.get_compress()))
+                    .max_files(max_files.unwrap_or(*current.get_max_files()))
+                    .retention_secs(retention_secs)
+                    .build();
+                management::set_log_rotation(mount_dir.clone(), Some(config)).await?;
+            }
+
+            let config = management::get_log_rotation(mount_dir).await?;
+            if json {
+                return print_json(&config);
+            }
+
+            println!("max_bytes:\t{}", config.get_max_bytes());
+            println!(
+                "max_age_secs:\t{}",
+                display_or_none(config.get_max_age_secs())
+            );
+            println!("compress:\t{}", config.get_compress());
+            println!("max_files:\t{}", config.get_max_files());
+            println!(
+                "retention_secs:\t{}",
+                display_or_none(config.get_retention_secs())
+            );
+        }
+        Some(MonofsSubcommand::Watch {
+            path_prefix,
+            mount_dir,
+        }) => {
+            let mut changes = management::watch(mount_dir, path_prefix).await?;
+            while let Some(event) = changes.next().await {
+                match event {
+                    Ok(event) if json => print_json(&event)?,
+                    Ok(event) => println!(
+                        "{}\t{}\t{}",
+                        event.get_kind(),
+                        event.get_path(),
+                        display_or_none(event.get_cid())
+                    ),
+                    Err(e) => tracing::warn!("{}", e),
+                }
+            }
+        }
+        Some(MonofsSubcommand::Sync { mount_dir }) => {
+            let root = management::sync_mfs(mount_dir).await?;
+            if json {
+                print_json(&json!({ "root": root.map(|root| root.to_string()) }))?;
+            } else if let Some(root) = root {
+                println!("{}", root);
+            }
         }
         Some(MonofsSubcommand::Detach { mount_dir, force }) => {
             tracing::info!("detaching monofs...");
-            management::detach_mfs(mount_dir, force).await?;
+            let report = management::detach_mfs(mount_dir, force).await?;
             tracing::info!("successfully detached monofs");
+            if json {
+                print_json(&report)?;
+            }
+        }
+        Some(MonofsSubcommand::Repair { mount_dir, attach }) => {
+            let report = management::repair_mount(mount_dir, attach).await?;
+            if json {
+                return print_json(&report);
+            }
+
+            if *report.get_health() == MountHealth::Healthy {
+                tracing::info!("filesystem is healthy, nothing to repair");
+            } else {
+                tracing::info!("repaired filesystem that was {}", report.get_health());
+            }
+        }
+        Some(MonofsSubcommand::Snapshot { subcommand }) => match subcommand {
+            SnapshotSubcommand::Create { name, mount_dir } => {
+                let snapshot = management::snapshot_mfs(mount_dir, name).await?;
+                if json {
+                    return print_json(&snapshot);
+                }
+
+                println!("{}\t{}", snapshot.get_name(), snapshot.get_root());
+            }
+            SnapshotSubcommand::List { mount_dir } => {
+                let snapshots = management::list_snapshots(mount_dir).await?;
+                if json {
+                    return print_json(&snapshots);
+                }
+
+                for snapshot in snapshots {
+                    println!(
+                        "{}\t{}\t{}",
+                        snapshot.get_name(),
+                        snapshot.get_root(),
+                        snapshot.get_created_at().to_rfc3339()
+                    );
+                }
+            }
+            SnapshotSubcommand::Restore { name, mount_dir } => {
+                let snapshot = management::restore_snapshot(mount_dir, name).await?;
+                tracing::info!("restored snapshot {}", snapshot.get_name());
+                if json {
+                    print_json(&snapshot)?;
+                }
+            }
+            SnapshotSubcommand::Prune {
+                keep_last,
+                keep_daily,
+                keep_weekly,
+                scheduled_only,
+                mount_dir,
+            } => {
+                let policy = SnapshotRetention::builder()
+                    .keep_last(keep_last)
+                    .keep_daily(keep_daily)
+                    .keep_weekly(keep_weekly)
+                    .scheduled_only(scheduled_only)
+                    .build();
+                let report = management::prune_snapshots(mount_dir, policy).await?;
+                if json {
+                    return print_json(&report);
+                }
+
+                for snapshot in report.get_pruned() {
+                    println!("{}\t{}", snapshot.get_name(), snapshot.get_root());
+                }
+                tracing::info!(
+                    "pruned {} snapshots, removed {} blocks ({} bytes)",
+                    report.get_pruned().len(),
+                    report.get_reclaimed().get_removed_blocks(),
+                    report.get_reclaimed().get_freed_bytes()
+                );
+            }
+            SnapshotSubcommand::Schedule {
+                interval_secs,
+                every_changes,
+                keep_last,
+                keep_daily,
+                keep_weekly,
+                clear,
+                mount_dir,
+            } => {
+                let schedule = SnapshotScheduleConfig::builder()
+                    .interval_secs(interval_secs)
+                    .every_changes(every_changes)
+                    .keep_last(keep_last)
+                    .keep_daily(keep_daily)
+                    .keep_weekly(keep_weekly)
+                    .build();
+
+                // Without any setting, the current schedule is shown
+                let schedule = if clear {
+                    management::set_snapshot_schedule(mount_dir, None).await?;
+                    None
+                } else if schedule == SnapshotScheduleConfig::builder().build() {
+                    management::get_snapshot_schedule(mount_dir).await?
+                } else {
+                    management::set_snapshot_schedule(mount_dir, Some(schedule)).await?;
+                    Some(schedule)
+                };
+
+                if json {
+                    return print_json(&schedule);
+                }
+
+                match schedule {
+                    Some(schedule) => println!(
+                        "interval_secs={}\tevery_changes={}\tkeep_last={}\tkeep_daily={}\tkeep_weekly={}",
+                        display_or_none(schedule.get_interval_secs()),
+                        display_or_none(schedule.get_every_changes()),
+                        display_or_none(schedule.get_keep_last()),
+                        display_or_none(schedule.get_keep_daily()),
+                        display_or_none(schedule.get_keep_weekly()),
+                    ),
+                    None => println!("no snapshot schedule"),
+                }
+            }
+        },
+        Some(MonofsSubcommand::Branch { subcommand }) => match subcommand {
+            BranchSubcommand::Create { name, mount_dir } => {
+                let branch = management::create_branch(mount_dir, name).await?;
+                if json {
+                    return print_json(&branch);
+                }
+
+                println!("{}\t{}", branch.get_name(), branch.get_root());
+            }
+            BranchSubcommand::List { mount_dir } => {
+                let branches = management::list_branches(mount_dir).await?;
+                if json {
+                    return print_json(&branches);
+                }
+
+                for branch in branches {
+                    println!(
+                        "{} {}\t{}\t{}",
+                        if *branch.get_active() { "*" } else { " " },
+                        branch.get_name(),
+                        branch.get_root(),
+                        branch.get_modified_at().to_rfc3339()
+                    );
+                }
+            }
+            BranchSubcommand::Switch { name, mount_dir } => {
+                let branch = management::switch_branch(mount_dir, name).await?;
+                tracing::info!("switched to branch {}", branch.get_name());
+                if json {
+                    print_json(&branch)?;
+                }
+            }
+            BranchSubcommand::Delete { name, mount_dir } => {
+                let branch = management::delete_branch(mount_dir, name).await?;
+                if json {
+                    print_json(&branch)?;
+                }
+            }
+        },
+        Some(MonofsSubcommand::Pin { subcommand }) => match subcommand {
+            PinSubcommand::Add { cid, mount_dir } => {
+                let pin = management::pin(mount_dir, cid).await?;
+                if json {
+                    print_json(&pin)?;
+                }
+            }
+            PinSubcommand::Remove { cid, mount_dir } => {
+                let unpinned = cid.to_string();
+                management::unpin(mount_dir, cid).await?;
+                if json {
+                    print_json(&json!({ "cid": unpinned }))?;
+                }
+            }
+            PinSubcommand::List { mount_dir } => {
+                let pins = management::list_pins(mount_dir).await?;
+                if json {
+                    return print_json(&pins);
+                }
+
+                for pin in pins {
+                    println!("{}\t{}", pin.get_cid(), pin.get_created_at().to_rfc3339());
+                }
+            }
+        },
+        Some(MonofsSubcommand::Replica { subcommand }) => match subcommand {
+            ReplicaSubcommand::Add { url, mount_dir } => {
+                let replica = management::add_replica(mount_dir, &url).await?;
+                if json {
+                    print_json(&replica)?;
+                }
+            }
+            ReplicaSubcommand::Remove { url, mount_dir } => {
+                management::remove_replica(mount_dir, &url).await?;
+                if json {
+                    print_json(&json!({ "url": url }))?;
+                }
+            }
+            ReplicaSubcommand::List { mount_dir } => {
+                let replicas = management::list_replicas(mount_dir).await?;
+                if json {
+                    return print_json(&replicas);
+                }
+
+                for replica in replicas {
+                    println!(
+                        "{}\t{}",
+                        replica.get_url(),
+                        replica.get_created_at().to_rfc3339()
+                    );
+                }
+            }
+        },
+        Some(MonofsSubcommand::Status { mount_dir }) => {
+            let status = management::status_mfs(mount_dir).await?;
+            if json {
+                return print_json(&status);
+            }
+
+            println!("mount_dir:\t{}", status.get_mount_dir().display());
+            println!("mounted:\t{}", status.get_mounted());
+            println!("port:\t{}", display_or_none(status.get_port()));
+            println!(
+                "supervisor_pid:\t{}",
+                display_or_none(status.get_supervisor_pid())
+            );
+            println!("root_cid:\t{}", display_or_none(status.get_root_cid()));
+            println!("branch:\t{}", status.get_branch());
+            println!("block_count:\t{}", status.get_block_count());
+            println!("store_size:\t{}", status.get_store_size());
+            println!(
+                "max_logical_bytes:\t{}",
+                display_or_none(status.get_quota().get_max_logical_bytes())
+            );
+            println!(
+                "max_store_bytes:\t{}",
+                display_or_none(status.get_quota().get_max_store_bytes())
+            );
+            println!(
+                "logical_bytes:\t{}",
+                display_or_none(&status.get_usage().map(|usage| *usage.get_logical_bytes()))
+            );
+        }
+        Some(MonofsSubcommand::List) => {
+            let mounts = management::list_mounts().await?;
+            if json {
+                return print_json(&mounts);
+            }
+
+            for mount in mounts {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    mount.get_mount_dir().display(),
+                    mount.get_backend(),
+                    display_or_none(mount.get_port()),
+                    display_or_none(mount.get_supervisor_pid()),
+                    mount.get_health()
+                );
+            }
+        }
+        Some(MonofsSubcommand::Gc {
+            incremental,
+            mount_dir,
+        }) => {
+            let report = if incremental {
+                management::gc_mfs_incremental(mount_dir).await?
+            } else {
+                management::gc_mfs(mount_dir).await?
+            };
+            if json {
+                print_json(&report)?;
+            }
+            tracing::info!(
+                "removed {} blocks, freed {} bytes",
+                report.get_removed_blocks(),
+                report.get_freed_bytes()
+            );
+        }
+        Some(MonofsSubcommand::SetQuota { quota, mount_dir }) => {
+            let applied = management::set_quota(mount_dir, quota.into()).await?;
+            if json {
+                print_json(&json!({ "applied": applied }))?;
+            }
+            if applied {
+                tracing::info!("quota applied to the running server");
+            } else {
+                tracing::info!("quota recorded for the next start of the server");
+            }
+        }
+        Some(MonofsSubcommand::Compact { mount_dir }) => {
+            let report = management::compact_mfs(mount_dir).await?;
+            if json {
+                print_json(&report)?;
+            }
+            tracing::info!(
+                "packed {} blocks into {} packfiles",
+                report.get_packed_blocks(),
+                report.get_packs_created()
+            );
+        }
+        Some(MonofsSubcommand::Resilver { mount_dir }) => {
+            let report = management::resilver_mfs(mount_dir).await?;
+            if json {
+                print_json(&report)?;
+            }
+            tracing::info!(
+                "checked {} files, copied {}",
+                report.get_checked_files(),
+                report.get_copied_files()
+            );
+            for digest in report.get_lost_blocks() {
+                tracing::error!("no good copy of block {} is left", digest);
+            }
+        }
+        Some(MonofsSubcommand::Backup { subcommand }) => match subcommand {
+            BackupSubcommand::Create { dest, mount_dir } => {
+                let backup = management::backup_mfs(mount_dir, dest).await?;
+                if json {
+                    print_json(&backup)?;
+                }
+                tracing::info!(
+                    "backed up {} blocks of {} to {}",
+                    backup.get_blocks(),
+                    backup.get_root(),
+                    backup.get_dest().display()
+                );
+            }
+            BackupSubcommand::List { mount_dir } => {
+                let backups = management::list_backups(mount_dir).await?;
+                if json {
+                    return print_json(&backups);
+                }
+
+                for backup in backups {
+                    println!(
+                        "{}\t{}\t{}\t{}",
+                        backup.get_dest().display(),
+                        backup.get_snapshot(),
+                        backup.get_root(),
+                        backup.get_created_at().to_rfc3339()
+                    );
+                }
+            }
+        },
+        Some(MonofsSubcommand::Restore {
+            backup_path,
+            mount_dir,
+        }) => {
+            tracing::info!("restoring monofs...");
+            let mount = management::restore_mfs(backup_path, mount_dir).await?;
+            tracing::info!("successfully restored monofs");
+            if json {
+                print_json(&mount)?;
+            }
+        }
+        Some(MonofsSubcommand::Import {
+            tar_path,
+            mount_dir,
+        }) => {
+            let root = management::import_tar(mount_dir, &tar_path).await?;
+            tracing::info!("imported {} into {}", tar_path.display(), root);
+            if json {
+                print_json(&json!({ "root": root.to_string() }))?;
+            }
+        }
+        Some(MonofsSubcommand::ImportOci {
+            image_path,
+            name,
+            mount_dir,
+        }) => {
+            let snapshot = management::import_oci(mount_dir, &image_path, name).await?;
+            if json {
+                return print_json(&snapshot);
+            }
+
+            println!("{}\t{}", snapshot.get_name(), snapshot.get_root());
+        }
+        Some(MonofsSubcommand::Export {
+            source,
+            tar_path,
+            mount_dir,
+        }) => {
+            let file = tokio::fs::File::create(&tar_path).await?;
+            let count = management::export_tar(mount_dir, source, file).await?;
+            tracing::info!("exported {} entries to {}", count, tar_path.display());
+            if json {
+                print_json(&json!({ "entries": count, "tar_path": tar_path }))?;
+            }
+        }
+        Some(MonofsSubcommand::ExportOci {
+            source,
+            image_path,
+            tag,
+            base,
+            mount_dir,
+        }) => {
+            let file = tokio::fs::File::create(&image_path).await?;
+            let image = management::export_oci(mount_dir, source, base, &tag, file).await?;
+            if json {
+                return print_json(&image);
+            }
+
+            println!("{}\t{}", image.get_tag(), image.get_digest());
+        }
+        Some(MonofsSubcommand::SyncDir {
+            host_dir,
+            source,
+            direction,
+            delete,
+            mount_dir,
+        }) => {
+            let summary =
+                management::sync_dir(mount_dir, &host_dir, source, direction, delete).await?;
+            if json {
+                return print_json(&summary);
+            }
+
+            println!(
+                "copied {} ({} bytes), unchanged {}, removed {}",
+                summary.get_copied(),
+                summary.get_bytes(),
+                summary.get_unchanged(),
+                summary.get_removed()
+            );
+            if let Some(root) = summary.get_root() {
+                println!("root {}", root);
+            }
+        }
+        Some(MonofsSubcommand::Ls { source, mount_dir }) => {
+            let source = source.unwrap_or_else(|| TreeSource::Path(String::new()));
+            let entries = management::list_dir(mount_dir, source).await?;
+            if json {
+                return print_json(&entries);
+            }
+
+            for entry in entries {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    entry.get_entity_type(),
+                    entry.get_size(),
+                    entry.get_cid(),
+                    entry.get_name()
+                );
+            }
+        }
+        Some(MonofsSubcommand::Tree {
+            source,
+            depth,
+            mount_dir,
+        }) => {
+            let source = source.unwrap_or_else(|| TreeSource::Path(String::new()));
+            let entries = management::list_tree(mount_dir, source, depth).await?;
+            if json {
+                return print_json(&entries);
+            }
+
+            for entry in entries {
+                println!(
+                    "{}{}\t{}\t{}\t{}",
+                    "  ".repeat(*entry.get_depth()),
+                    entry.get_name(),
+                    entry.get_entity_type(),
+                    entry.get_size(),
+                    entry.get_cid()
+                );
+            }
+        }
+        Some(MonofsSubcommand::Cat { source, mount_dir }) => {
+            management::cat_file(mount_dir, source, tokio::io::stdout()).await?;
+        }
+        Some(MonofsSubcommand::Find {
+            glob,
+            source,
+            find_type,
+            mount_dir,
+        }) => {
+            let source = source.unwrap_or_else(|| TreeSource::Path(String::new()));
+            let entries = management::find_entries(mount_dir, source, &glob, find_type).await?;
+            if json {
+                return print_json(&entries);
+            }
+
+            for entry in entries {
+                println!(
+                    "{}\t{}\t{}",
+                    entry.get_path(),
+                    entry.get_entity_type(),
+                    entry.get_cid()
+                );
+            }
+        }
+        Some(MonofsSubcommand::Grep {
+            pattern,
+            source,
+            glob,
+            mount_dir,
+        }) => {
+            let mut matches =
+                management::grep(mount_dir, source, &pattern, glob.as_deref()).await?;
+            while let Some(found) = matches.next().await {
+                let found = found?;
+                if json {
+                    print_json(&found)?;
+                    continue;
+                }
+
+                println!(
+                    "{}:{}:{}",
+                    found.get_path(),
+                    found.get_line_number(),
+                    found.get_line()
+                );
+            }
+        }
+        Some(MonofsSubcommand::Write { target, mount_dir }) => {
+            let root = management::write_file(mount_dir, target, tokio::io::stdin()).await?;
+            if json {
+                return print_json(&json!({ "root": root.to_string() }));
+            }
+
+            println!("{}", root);
+        }
+        Some(MonofsSubcommand::Overlay { base, mount_dir }) => {
+            let root = management::overlay_mfs(mount_dir, base).await?;
+            if json {
+                return print_json(&json!({ "root": root.to_string() }));
+            }
+
+            println!("{}", root);
+        }
+        Some(MonofsSubcommand::Send {
+            stream_path,
+            since,
+            mount_dir,
+        }) => {
+            let report = management::send_file(mount_dir, since, &stream_path).await?;
+            if json {
+                return print_json(&report);
+            }
+
+            println!("root:\t{}", report.get_root());
+            println!("blocks:\t{}", report.get_blocks());
+            println!("bytes:\t{}", report.get_bytes());
+        }
+        Some(MonofsSubcommand::Receive {
+            stream_path,
+            mount_dir,
+        }) => {
+            let file = tokio::fs::File::open(&stream_path).await?;
+            let report = management::receive(mount_dir, file).await?;
+            if json {
+                return print_json(&report);
+            }
+
+            println!("root:\t{}", report.get_root());
+            println!("received_blocks:\t{}", report.get_received_blocks());
+            println!("skipped_blocks:\t{}", report.get_skipped_blocks());
+            println!("bytes:\t{}", report.get_bytes());
+        }
+        Some(MonofsSubcommand::Dedup { mount_dir }) => {
+            let report = management::dedup_report(mount_dir).await?;
+            if json {
+                return print_json(&report);
+            }
+
+            println!("logical_size:\t{}", report.get_logical_size());
+            println!("physical_size:\t{}", report.get_physical_size());
+            println!("store_size:\t{}", report.get_store_size());
+            println!("ratio:\t{:.2}", report.get_ratio());
+            for dir in report.get_directories() {
+                println!(
+                    "dir:\t{}\t{}\t{}\t{:.2}",
+                    dir.get_path(),
+                    dir.get_logical_size(),
+                    dir.get_physical_size(),
+                    dir.get_ratio()
+                );
+            }
+            for block in report.get_top_duplicates() {
+                println!(
+                    "duplicate:\t{}\t{}\t{}",
+                    block.get_cid(),
+                    block.get_size(),
+                    block.get_references()
+                );
+            }
+        }
+        Some(MonofsSubcommand::Du {
+            source,
+            max_depth,
+            mount_dir,
+        }) => {
+            let source = source.unwrap_or_else(|| TreeSource::Path(String::new()));
+            let usages = management::disk_usage(mount_dir, source, max_depth).await?;
+            if json {
+                return print_json(&usages);
+            }
+
+            for usage in usages {
+                let path = match usage.get_path().as_str() {
+                    "" => ".",
+                    path => path,
+                };
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    usage.get_logical_size(),
+                    usage.get_physical_size(),
+                    usage.get_unique_size(),
+                    usage.get_shared_size(),
+                    path
+                );
+            }
+        }
+        Some(MonofsSubcommand::Jobs { mount_dir }) => {
+            let jobs = management::list_jobs(mount_dir).await?;
+            if json {
+                return print_json(&jobs);
+            }
+
+            for job in jobs {
+                println!(
+                    "{}\t{}\t{}\t{}/{}\t{}",
+                    job.get_id(),
+                    job.get_kind(),
+                    job.get_state(),
+                    job.get_done(),
+                    display_or_none(job.get_total()),
+                    display_or_none(job.get_target())
+                );
+            }
+        }
+        Some(MonofsSubcommand::Job { id, mount_dir }) => {
+            let job = management::job_status(mount_dir, id).await?;
+            if json {
+                return print_json(&job);
+            }
+
+            println!("id:\t{}", job.get_id());
+            println!("kind:\t{}", job.get_kind());
+            println!("state:\t{}", job.get_state());
+            println!("done:\t{}", job.get_done());
+            println!("total:\t{}", display_or_none(job.get_total()));
+            println!("target:\t{}", display_or_none(job.get_target()));
+            println!("error:\t{}", display_or_none(job.get_error()));
+            println!("created_at:\t{}", job.get_created_at());
+            println!("modified_at:\t{}", job.get_modified_at());
+        }
+        Some(MonofsSubcommand::Verify { repair, mount_dir }) => {
+            let report = management::verify_mfs(mount_dir, repair).await?;
+            if json {
+                print_json(&report)?;
+            } else {
+                println!("checked_blocks:\t{}", report.get_checked_blocks());
+                println!("reachable_blocks:\t{}", report.get_reachable_blocks());
+                for block in report.get_corrupt_blocks() {
+                    let reachable = if *block.get_reachable() {
+                        "reachable"
+                    } else {
+                        "unreachable"
+                    };
+                    println!(
+                        "corrupt:\t{}\t{}\t{}",
+                        block.get_digest(),
+                        reachable,
+                        block.get_path().display()
+                    );
+                }
+                for block in report.get_missing_blocks() {
+                    println!(
+                        "missing:\t{}\t{}",
+                        block.get_cid(),
+                        display_or_none(block.get_parent())
+                    );
+                }
+                for path in report.get_dropped_entries() {
+                    println!("dropped:\t{}", path);
+                }
+                if let Some(root) = report.get_repaired_root() {
+                    println!("repaired_root:\t{}", root);
+                }
+            }
+
+            if !report.is_ok() && report.get_repaired_root().is_none() {
+                anyhow::bail!("filesystem is damaged, run `monofs verify --repair` to repair it");
+            }
+        }
+        Some(MonofsSubcommand::Scrub {
+            interval_secs,
+            batch_blocks,
+            clear,
+            mount_dir,
+        }) => {
+            // Without any setting, only the status is shown
+            if clear {
+                management::set_scrub_config(mount_dir.clone(), None).await?;
+            } else if interval_secs.is_some() || batch_blocks.is_some() {
+                let status = management::get_scrub_status(mount_dir.clone()).await?;
+                let current = status.get_config().unwrap_or_default();
+                let config = ScrubConfig::builder()
+                    .interval_secs(interval_secs.unwrap_or(*current.get_interval_secs()))
+                    .batch_blocks(batch_blocks.unwrap_or(*current.get_batch_blocks()))
+                    .build();
+                management::set_scrub_config(mount_dir.clone(), Some(config)).await?;
+            }
+
+            let status = management::get_scrub_status(mount_dir).await?;
+            if json {
+                return print_json(&status);
+            }
+
+            match status.get_config() {
+                Some(config) => println!(
+                    "interval_secs={}\tbatch_blocks={}",
+                    config.get_interval_secs(),
+                    config.get_batch_blocks()
+                ),
+                None => println!("not scrubbing"),
+            }
+            println!("checked_blocks:\t{}", status.get_checked_blocks());
+            println!("repaired_blocks:\t{}", status.get_repaired_blocks());
+            println!("passes:\t{}", status.get_passes());
+            println!(
+                "last_scrubbed_at:\t{}",
+                display_or_none(status.get_last_scrubbed_at())
+            );
+            for block in status.get_quarantined_blocks() {
+                println!(
+                    "quarantined:\t{}\t{}\t{}",
+                    block.get_digest(),
+                    block.get_created_at(),
+                    block.get_quarantine_path().display()
+                );
+            }
         }
         Some(_) => (), // TODO: implement other subcommands
         None => {
@@ -38,3 +1062,17 @@ async fn main() -> anyhow::Result<()> {
 //--------------------------------------------------------------------------------------------------
 // Functions: *
 //--------------------------------------------------------------------------------------------------
+
+/// Prints `value` as a line of JSON.
+fn print_json(value: &impl Serialize) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string(value)?);
+    Ok(())
+}
+
+/// Formats an optional value, using `-` when it is missing.
+fn display_or_none(value: &Option<impl std::fmt::Display>) -> String {
+    value
+        .as_ref()
+        .map(|value| value.to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
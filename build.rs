@@ -0,0 +1,7 @@
+fn main() {
+    println!("cargo:rerun-if-changed=proto");
+
+    // The control API is only generated when it is built, which needs `protoc`
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/control.proto").expect("failed to compile control.proto");
+}
@@ -78,7 +78,7 @@ async fn main() -> anyhow::Result<()> {
     drop(reader);
 
     // Truncate the file
-    file.truncate();
+    file.truncate(0).await?;
 
     println!("Truncated file");
     println!("File is empty after truncation: {}", file.is_empty().await?);
@@ -0,0 +1,67 @@
+use clap::Args;
+
+use crate::config::{
+    ChunkerConfig, DEFAULT_CDC_DESIRED_CHUNK_SIZE, DEFAULT_CDC_MAX_CHUNK_SIZE,
+    DEFAULT_CDC_MIN_CHUNK_SIZE,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Arguments controlling how file contents are split into blocks
+#[derive(Debug, Clone, Copy, Args)]
+pub struct ChunkerArgs {
+    /// Smallest size in bytes of a content-defined chunk
+    #[arg(long)]
+    pub chunk_min_size: Option<u32>,
+
+    /// Average size in bytes content-defined chunks are aimed at
+    #[arg(long)]
+    pub chunk_desired_size: Option<u32>,
+
+    /// Largest size in bytes of a content-defined chunk
+    #[arg(long)]
+    pub chunk_max_size: Option<u32>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ChunkerArgs {
+    /// Returns the configuration given on the command line, or `None` if no chunk size was given.
+    /// Sizes that were not given are the defaults.
+    pub fn to_config(&self) -> Option<ChunkerConfig> {
+        let given = self.chunk_min_size.is_some()
+            || self.chunk_desired_size.is_some()
+            || self.chunk_max_size.is_some();
+        given.then(|| (*self).into())
+    }
+
+    /// Returns the arguments that reproduce `config` on the command line.
+    pub fn to_args(config: &ChunkerConfig) -> Vec<String> {
+        vec![
+            format!("--chunk-min-size={}", config.get_min_size()),
+            format!("--chunk-desired-size={}", config.get_desired_size()),
+            format!("--chunk-max-size={}", config.get_max_size()),
+        ]
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl From<ChunkerArgs> for ChunkerConfig {
+    fn from(args: ChunkerArgs) -> Self {
+        ChunkerConfig::builder()
+            .min_size(args.chunk_min_size.unwrap_or(DEFAULT_CDC_MIN_CHUNK_SIZE))
+            .desired_size(
+                args.chunk_desired_size
+                    .unwrap_or(DEFAULT_CDC_DESIRED_CHUNK_SIZE),
+            )
+            .max_size(args.chunk_max_size.unwrap_or(DEFAULT_CDC_MAX_CHUNK_SIZE))
+            .build()
+    }
+}
@@ -0,0 +1,55 @@
+use clap::Args;
+
+use crate::config::{CompressionConfig, DEFAULT_COMPRESSION_LEVEL};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Arguments controlling how blocks are compressed at rest
+#[derive(Debug, Clone, Copy, Args)]
+pub struct CompressionArgs {
+    /// Store new blocks uncompressed
+    #[arg(long)]
+    pub no_compression: bool,
+
+    /// Zstd level new blocks are compressed with, from 1 (fastest) to 22 (smallest)
+    #[arg(long)]
+    pub compression_level: Option<i32>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl CompressionArgs {
+    /// Returns the configuration given on the command line, or `None` if no compression argument
+    /// was given. The level is the default if it was not given.
+    pub fn to_config(&self) -> Option<CompressionConfig> {
+        let given = self.no_compression || self.compression_level.is_some();
+        given.then(|| (*self).into())
+    }
+
+    /// Returns the arguments that reproduce `config` on the command line.
+    pub fn to_args(config: &CompressionConfig) -> Vec<String> {
+        let mut args = vec![format!("--compression-level={}", config.get_level())];
+        if !config.get_enabled() {
+            args.push("--no-compression".to_string());
+        }
+
+        args
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl From<CompressionArgs> for CompressionConfig {
+    fn from(args: CompressionArgs) -> Self {
+        CompressionConfig::builder()
+            .enabled(!args.no_compression)
+            .level(args.compression_level.unwrap_or(DEFAULT_COMPRESSION_LEVEL))
+            .build()
+    }
+}
@@ -0,0 +1,111 @@
+use clap::Args;
+
+use crate::config::{
+    ClientNetwork, ExportConfig, IdMapping, SquashMode, DEFAULT_ANON_GID, DEFAULT_ANON_UID,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Arguments restricting which NFS clients may use a filesystem, who they act as, and what they
+/// may do
+#[derive(Debug, Clone, Args)]
+pub struct ExportArgs {
+    /// Network NFS clients may connect from, as `address/prefix-length`, repeatable. Any client
+    /// may connect if none is given
+    #[arg(long = "allow-client")]
+    pub allowed_clients: Vec<ClientNetwork>,
+
+    /// Which owners set by NFS clients are mapped to the anonymous user and group
+    #[arg(long, value_enum)]
+    pub squash: Option<SquashMode>,
+
+    /// User squashed owners are mapped to
+    #[arg(long)]
+    pub anon_uid: Option<u32>,
+
+    /// Group squashed owners are mapped to
+    #[arg(long)]
+    pub anon_gid: Option<u32>,
+
+    /// Check the AUTH_SYS credentials of NFS calls against the mode and owner of the files they
+    /// act on, rather than trusting clients to
+    #[arg(long)]
+    pub check_permissions: bool,
+
+    /// User IDs of NFS clients to map to the user IDs files are stored with, as
+    /// `client:stored[:count]`, repeatable
+    #[arg(long = "map-uid")]
+    pub uid_map: Vec<IdMapping>,
+
+    /// Group IDs of NFS clients to map to the group IDs files are stored with, as
+    /// `client:stored[:count]`, repeatable
+    #[arg(long = "map-gid")]
+    pub gid_map: Vec<IdMapping>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ExportArgs {
+    /// Returns the export configuration given on the command line, or `None` if nothing was
+    /// given.
+    pub fn to_config(&self) -> Option<ExportConfig> {
+        let given = !self.allowed_clients.is_empty()
+            || self.squash.is_some()
+            || self.anon_uid.is_some()
+            || self.anon_gid.is_some()
+            || self.check_permissions
+            || !self.uid_map.is_empty()
+            || !self.gid_map.is_empty();
+        given.then(|| self.clone().into())
+    }
+
+    /// Returns the arguments that reproduce `config` on the command line.
+    pub fn to_args(config: &ExportConfig) -> Vec<String> {
+        let mut args = config
+            .get_allowed_clients()
+            .iter()
+            .map(|network| format!("--allow-client={}", network))
+            .collect::<Vec<_>>();
+        args.push(format!("--squash={}", config.get_squash()));
+        args.push(format!("--anon-uid={}", config.get_anon_uid()));
+        args.push(format!("--anon-gid={}", config.get_anon_gid()));
+        if *config.get_check_permissions() {
+            args.push("--check-permissions".to_string());
+        }
+        args.extend(
+            config
+                .get_uid_map()
+                .iter()
+                .map(|mapping| format!("--map-uid={}", mapping)),
+        );
+        args.extend(
+            config
+                .get_gid_map()
+                .iter()
+                .map(|mapping| format!("--map-gid={}", mapping)),
+        );
+        args
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl From<ExportArgs> for ExportConfig {
+    fn from(args: ExportArgs) -> Self {
+        ExportConfig::builder()
+            .allowed_clients(args.allowed_clients)
+            .squash(args.squash.unwrap_or_default())
+            .anon_uid(args.anon_uid.unwrap_or(DEFAULT_ANON_UID))
+            .anon_gid(args.anon_gid.unwrap_or(DEFAULT_ANON_GID))
+            .check_permissions(args.check_permissions)
+            .uid_map(args.uid_map)
+            .gid_map(args.gid_map)
+            .build()
+    }
+}
@@ -0,0 +1,38 @@
+use clap::Args;
+
+use crate::config::{MemoryStoreConfig, DEFAULT_MEMORY_STORE_MAX_BYTES};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Arguments configuring an in-memory store for the blocks a throwaway filesystem writes
+#[derive(Debug, Clone, Args)]
+pub struct MemoryArgs {
+    /// Keep written blocks in memory instead of on disk, discarding them on detach
+    #[arg(long)]
+    pub memory: bool,
+
+    /// Most bytes of blocks to keep in memory
+    #[arg(long, default_value_t = DEFAULT_MEMORY_STORE_MAX_BYTES, requires = "memory")]
+    pub memory_max_bytes: u64,
+
+    /// Write the final state to disk on detach instead of discarding it
+    #[arg(long, requires = "memory")]
+    pub spill_on_detach: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl From<MemoryArgs> for Option<MemoryStoreConfig> {
+    fn from(args: MemoryArgs) -> Self {
+        args.memory.then(|| {
+            MemoryStoreConfig::builder()
+                .max_bytes(args.memory_max_bytes)
+                .spill_on_detach(args.spill_on_detach)
+                .build()
+        })
+    }
+}
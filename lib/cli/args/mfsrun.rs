@@ -3,8 +3,10 @@ use std::path::PathBuf;
 use clap::{Parser, Subcommand};
 
 use crate::{
-    cli::styles,
-    config::{DEFAULT_HOST, DEFAULT_NFS_PORT},
+    cli::{
+        styles, ChunkerArgs, CompressionArgs, ExportArgs, ThrottleArgs, TransferArgs, WriteBackArgs,
+    },
+    config::{AtimePolicy, Durability, MountBackend, DEFAULT_HOST, DEFAULT_NFS_PORT},
 };
 
 //--------------------------------------------------------------------------------------------------
@@ -36,6 +38,80 @@ pub enum MfsRuntimeSubcommand {
         /// The directory to store the filesystem data
         #[arg(long)]
         store_dir: PathBuf,
+
+        /// Path to the filesystem metrics and metadata database file
+        #[arg(long, requires = "mount_dir")]
+        fs_db_path: Option<PathBuf>,
+
+        /// Directory where the filesystem is mounted
+        #[arg(long, requires = "fs_db_path")]
+        mount_dir: Option<PathBuf>,
+
+        /// How file contents are split into blocks
+        #[command(flatten)]
+        chunker: ChunkerArgs,
+
+        /// How blocks are compressed at rest
+        #[command(flatten)]
+        compression: CompressionArgs,
+
+        /// How writes are buffered before they are flushed to the store
+        #[command(flatten)]
+        write_back: WriteBackArgs,
+
+        /// How much data NFS clients move in a single request
+        #[command(flatten)]
+        transfer: TransferArgs,
+
+        /// Which NFS clients may connect, and who they act as
+        #[command(flatten)]
+        exports: ExportArgs,
+
+        /// How fast NFS clients are served
+        #[command(flatten)]
+        throttle: ThrottleArgs,
+
+        /// When reads record the time a file was last accessed
+        #[arg(long, value_enum, default_value_t = AtimePolicy::Noatime)]
+        atime: AtimePolicy,
+
+        /// When new block files are flushed to disk
+        #[arg(long, value_enum, default_value_t = Durability::Relaxed)]
+        durability: Durability,
+    },
+    /// Run as FUSE server
+    Fuseserver {
+        /// The directory to store the filesystem data
+        #[arg(long)]
+        store_dir: PathBuf,
+
+        /// Directory to mount the filesystem at
+        #[arg(long)]
+        mount_dir: PathBuf,
+
+        /// Path to the filesystem metrics and metadata database file
+        #[arg(long)]
+        fs_db_path: Option<PathBuf>,
+
+        /// How file contents are split into blocks
+        #[command(flatten)]
+        chunker: ChunkerArgs,
+
+        /// How blocks are compressed at rest
+        #[command(flatten)]
+        compression: CompressionArgs,
+
+        /// How writes are buffered before they are flushed to the store
+        #[command(flatten)]
+        write_back: WriteBackArgs,
+
+        /// When reads record the time a file was last accessed
+        #[arg(long, value_enum, default_value_t = AtimePolicy::Noatime)]
+        atime: AtimePolicy,
+
+        /// When new block files are flushed to disk
+        #[arg(long, value_enum, default_value_t = Durability::Relaxed)]
+        durability: Durability,
     },
     /// Run as supervisor
     Supervisor {
@@ -66,5 +142,45 @@ pub enum MfsRuntimeSubcommand {
         /// Directory where the filesystem is mounted
         #[arg(long)]
         mount_dir: PathBuf,
+
+        /// The mount backend the supervised server should use
+        #[arg(long, value_enum, default_value_t = MountBackend::Nfs)]
+        backend: MountBackend,
+
+        /// Address to serve Prometheus metrics on over HTTP, e.g. `127.0.0.1:9400`
+        #[arg(long)]
+        metrics_addr: Option<String>,
+
+        /// How the supervised server splits file contents into blocks
+        #[command(flatten)]
+        chunker: ChunkerArgs,
+
+        /// How the supervised server compresses blocks at rest
+        #[command(flatten)]
+        compression: CompressionArgs,
+
+        /// How the supervised server buffers writes before flushing them to the store
+        #[command(flatten)]
+        write_back: WriteBackArgs,
+
+        /// How much data clients of the supervised NFS server move in a single request
+        #[command(flatten)]
+        transfer: TransferArgs,
+
+        /// Which clients may connect to the supervised NFS server, and who they act as
+        #[command(flatten)]
+        exports: ExportArgs,
+
+        /// How fast the supervised NFS server serves its clients
+        #[command(flatten)]
+        throttle: ThrottleArgs,
+
+        /// When reads through the supervised server record the time a file was last accessed
+        #[arg(long, value_enum, default_value_t = AtimePolicy::Noatime)]
+        atime: AtimePolicy,
+
+        /// When the supervised server flushes new block files to disk
+        #[arg(long, value_enum, default_value_t = Durability::Relaxed)]
+        durability: Durability,
     },
 }
@@ -1,9 +1,27 @@
+mod chunker;
+mod compression;
+mod export;
+mod memory;
 mod mfsrun;
 mod monofs;
+mod quota;
+mod remote;
+mod throttle;
+mod transfer;
+mod writeback;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
 //--------------------------------------------------------------------------------------------------
 
+pub use chunker::*;
+pub use compression::*;
+pub use export::*;
+pub use memory::*;
 pub use mfsrun::*;
 pub use monofs::*;
+pub use quota::*;
+pub use remote::*;
+pub use throttle::*;
+pub use transfer::*;
+pub use writeback::*;
@@ -1,7 +1,19 @@
 use std::path::PathBuf;
 
-use crate::cli::styles;
-use clap::Parser;
+use crate::{
+    cli::{
+        styles, ChunkerArgs, CompressionArgs, ExportArgs, MemoryArgs, QuotaArgs, RemoteArgs,
+        ThrottleArgs, TransferArgs, WriteBackArgs,
+    },
+    config::{
+        AtimePolicy, Durability, FindType, HashFunction, LogSource, MountBackend, PortRange,
+        RootPublicKey, RootSubtree, SyncDirection, DEFAULT_MOUNT_TIMEOUT, DEFAULT_TAIL_LINES,
+    },
+    management::{CheckoutTarget, ExportSource, TreeSource},
+};
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
+use ipldstore::ipld::cid::Cid;
 use typed_path::Utf8UnixPathBuf;
 
 //-------------------------------------------------------------------------------------------------
@@ -23,6 +35,10 @@ pub struct MonofsArgs {
     /// Show version
     #[arg(short = 'v', long)]
     pub version: bool,
+
+    /// Print results as JSON instead of text, one JSON value per line
+    #[arg(long, global = true)]
+    pub json: bool,
 }
 
 /// Available subcommands for managing services
@@ -33,30 +49,186 @@ pub enum MonofsSubcommand {
     Init {
         /// Directory where the filesystem will be mounted
         mount_dir: Option<PathBuf>,
+
+        /// The mechanism used to mount the filesystem. Defaults to the one in the user's
+        /// configuration, or nfs, except on Linux without root, where it is fuse
+        #[arg(short = 'b', long, value_enum)]
+        backend: Option<MountBackend>,
+
+        /// Host address for the NFS server to bind to. Defaults to the one in the user's
+        /// configuration, or 127.0.0.1
+        #[arg(long)]
+        host: Option<String>,
+
+        /// Range of ports to search for a free NFS port, e.g. `2049-2148`. Defaults to the one in
+        /// the user's configuration, or 2049-2148
+        #[arg(long)]
+        port_range: Option<PortRange>,
+
+        /// Port for the NFS server to listen on, failing if it is taken
+        #[arg(short = 'p', long, conflicts_with = "port_range")]
+        port: Option<u32>,
+
+        /// Address for the supervisor to serve Prometheus metrics on, e.g. `127.0.0.1:9400`
+        #[arg(long)]
+        metrics_addr: Option<String>,
+
+        /// How file contents are split into blocks
+        #[command(flatten)]
+        chunker: ChunkerArgs,
+
+        /// How blocks are compressed at rest
+        #[command(flatten)]
+        compression: CompressionArgs,
+
+        /// How writes are buffered before they are flushed to the store
+        #[command(flatten)]
+        write_back: WriteBackArgs,
+
+        /// How much data NFS clients move in a single request
+        #[command(flatten)]
+        transfer: TransferArgs,
+
+        /// Option to mount the filesystem with over NFS, e.g. `hard` or `actimeo=1`, replacing the
+        /// platform's default of the same name. May be given more than once. Defaults to the ones
+        /// in the user's configuration
+        #[arg(short = 'o', long = "mount-option")]
+        mount_options: Vec<String>,
+
+        /// Which NFS clients may use the filesystem, and who they act as. Defaults to the exports
+        /// in the user's configuration
+        #[command(flatten)]
+        exports: ExportArgs,
+
+        /// How fast NFS clients are served. Defaults to the throttle in the user's configuration
+        #[command(flatten)]
+        throttle: ThrottleArgs,
+
+        /// When reads record the time a file was last accessed
+        #[arg(long, value_enum, default_value_t = AtimePolicy::Noatime)]
+        atime: AtimePolicy,
+
+        /// When new block files are flushed to disk. `batch` flushes the blocks of each file
+        /// together once they are written
+        #[arg(long, value_enum, default_value_t = Durability::Relaxed)]
+        durability: Durability,
+
+        /// The hash function the CIDs of new blocks are made with. Recorded for the filesystem,
+        /// so it is used whenever it is attached again
+        #[arg(long = "hash", value_enum, default_value_t = HashFunction::Blake3)]
+        hash_function: HashFunction,
+
+        /// How much the filesystem may hold
+        #[command(flatten)]
+        quota: QuotaArgs,
+
+        /// Where every block is kept besides the local cache
+        #[command(flatten)]
+        remote: RemoteArgs,
+
+        /// Whether written blocks are kept in memory instead of on disk
+        #[command(flatten)]
+        memory: MemoryArgs,
+
+        /// Directory, e.g. on another disk, to keep a copy of every block in. Can be given more
+        /// than once. Each must be an absolute path, and empty or missing
+        #[arg(long = "mirror")]
+        mirrors: Vec<PathBuf>,
+
+        /// Directory to serve as the root of the mount, as a path in the root or a CID
+        #[arg(long)]
+        subtree: Option<RootSubtree>,
+
+        /// Record every change made through the mount in an audit log
+        #[arg(long)]
+        audit: bool,
+
+        /// Longest time in seconds to wait for the filesystem to be mounted before giving up
+        #[arg(long, default_value_t = DEFAULT_MOUNT_TIMEOUT.as_secs())]
+        mount_timeout_secs: u64,
+    },
+
+    /// Remount an existing filesystem whose server is no longer running
+    #[command(name = "attach")]
+    Attach {
+        /// Directory where the filesystem was previously mounted
+        mount_dir: Option<PathBuf>,
     },
 
-    /// Create a temporary filesystem
-    #[command(name = "tmp")]
-    Tmp,
+    /// List the roots the filesystem has had, oldest first
+    #[command(name = "history")]
+    History {
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// List the changes recorded in the audit log of the filesystem, oldest first
+    #[command(name = "audit-log")]
+    AuditLog {
+        /// Only list changes made at or after this RFC 3339 timestamp
+        #[arg(long)]
+        since: Option<DateTime<Utc>>,
+
+        /// Only list changes to this path or anything below it
+        #[arg(long)]
+        path: Option<String>,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Roll the filesystem back to a root from its history
+    #[command(name = "checkout")]
+    Checkout {
+        /// Version number from the history, or an RFC 3339 timestamp
+        target: CheckoutTarget,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
 
-    /// Clone an existing filesystem
+    /// Check that the current root of the filesystem was signed with a key
+    #[command(name = "verify-head")]
+    VerifyHead {
+        /// Public key of the signing key, in hex
+        public_key: RootPublicKey,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Print the public key of the signing key given in the environment
+    #[command(name = "public-key")]
+    PublicKey,
+
+    /// Create a new mount that shares the blocks of an existing filesystem
     #[command(name = "clone")]
     Clone {
-        /// Remote or local path to clone from
-        uri: String,
+        /// Directory where the filesystem to clone is mounted
+        source: PathBuf,
+
+        /// Directory where the clone will be mounted
+        target: PathBuf,
     },
 
-    /// Sync a filesystem with another filesystem
-    #[command(name = "sync")]
-    Sync {
-        /// Remote or local path to sync with
-        uri: String,
+    /// Mount another view of an existing filesystem, served by the same server
+    #[command(name = "share")]
+    Share {
+        /// Directory where the filesystem to share is mounted
+        source: PathBuf,
+
+        /// Directory where the new view will be mounted
+        target: PathBuf,
 
-        /// Type of sync (e.g. backup, raft, crdt)
-        #[arg(short = 't', long)]
-        r#type: String,
+        /// Root to start the view from, e.g. of a snapshot. Defaults to the current root
+        #[arg(long)]
+        root: Option<Cid>,
     },
 
+    /// Create a temporary filesystem
+    #[command(name = "tmp")]
+    Tmp,
+
     /// Show the revisions of a filesystem
     #[command(name = "rev")]
     Rev {
@@ -81,18 +253,6 @@ pub enum MonofsSubcommand {
         path: Option<Utf8UnixPathBuf>,
     },
 
-    /// Checkout a revision of a file entity
-    #[command(name = "checkout")]
-    Checkout {
-        /// Revision to checkout
-        #[arg()]
-        revision: String,
-
-        /// Path to checkout
-        #[arg(short = 'p', long)]
-        path: Option<Utf8UnixPathBuf>,
-    },
-
     /// Show differences between two revisions of a file entity
     #[command(name = "diff")]
     Diff {
@@ -109,6 +269,457 @@ pub enum MonofsSubcommand {
         path: Option<Utf8UnixPathBuf>,
     },
 
+    /// Manage named snapshots of a filesystem
+    #[command(name = "snapshot")]
+    Snapshot {
+        /// The snapshot operation to perform
+        #[command(subcommand)]
+        subcommand: SnapshotSubcommand,
+    },
+
+    /// Manage named branches of a filesystem, each with its own line of roots
+    #[command(name = "branch")]
+    Branch {
+        /// The branch operation to perform
+        #[command(subcommand)]
+        subcommand: BranchSubcommand,
+    },
+
+    /// Manage CIDs that are protected from garbage collection
+    #[command(name = "pin")]
+    Pin {
+        /// The pin operation to perform
+        #[command(subcommand)]
+        subcommand: PinSubcommand,
+    },
+
+    /// Manage the stores corrupt and missing blocks are repaired from
+    #[command(name = "replica")]
+    Replica {
+        /// The replica operation to perform
+        #[command(subcommand)]
+        subcommand: ReplicaSubcommand,
+    },
+
+    /// Change how much the filesystem may hold. Limits left out are lifted
+    #[command(name = "set-quota")]
+    SetQuota {
+        /// The new limits
+        #[command(flatten)]
+        quota: QuotaArgs,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Show whether the filesystem is mounted and what state it is in
+    #[command(name = "status")]
+    Status {
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// List the filesystems mounted on this host, with their ports, supervisors and health
+    #[command(name = "list")]
+    List,
+
+    /// Remove blocks that are no longer reachable from the filesystem, its snapshots, its branches
+    /// or its pins
+    #[command(name = "gc")]
+    Gc {
+        /// Only walk the roots that changed since the last incremental collection, using the
+        /// reference counts it keeps. Blocks that never were part of a root are left behind
+        #[arg(long)]
+        incremental: bool,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Move the blocks of the filesystem from their own files into packfiles
+    #[command(name = "compact")]
+    Compact {
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Copy good copies of the blocks of the filesystem over those missing or corrupt in its
+    /// blocks directory or its mirrors, e.g. after replacing a disk
+    #[command(name = "resilver")]
+    Resilver {
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Take and list consistent backups of the filesystem, safe to take while it is mounted
+    #[command(name = "backup")]
+    Backup {
+        /// The backup operation to perform
+        #[command(subcommand)]
+        subcommand: BackupSubcommand,
+    },
+
+    /// Restore a backup or a CAR file into a new filesystem and mount it
+    #[command(name = "restore")]
+    Restore {
+        /// Backup directory or CAR file to restore from
+        backup_path: PathBuf,
+
+        /// Directory where the filesystem will be mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Import a tar archive into the filesystem
+    #[command(name = "import")]
+    Import {
+        /// Path to the tar archive to import
+        tar_path: PathBuf,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Import an OCI or Docker image archive as a stack of overlays, recorded as a snapshot
+    #[command(name = "import-oci")]
+    ImportOci {
+        /// Path to the image archive, as written by `docker save` or in the OCI image layout
+        image_path: PathBuf,
+
+        /// Name of the snapshot to record the image as
+        name: String,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Export a directory of the filesystem as a tar archive
+    #[command(name = "export")]
+    Export {
+        /// CID of the directory to export, or its path in the filesystem
+        source: ExportSource,
+
+        /// Path to write the tar archive to
+        tar_path: PathBuf,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Export a directory of the filesystem as an OCI image archive
+    #[command(name = "export-oci")]
+    ExportOci {
+        /// CID of the directory to export as the root of the image, or its path in the filesystem
+        source: ExportSource,
+
+        /// Path to write the image archive to
+        image_path: PathBuf,
+
+        /// Tag to record the image under, like `sandbox:latest`
+        tag: String,
+
+        /// CID of a directory to export as a layer of its own, below the changes from it
+        #[arg(long)]
+        base: Option<Cid>,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Sync a directory of the host with a directory of the filesystem, copying only what changed
+    #[command(name = "sync-dir")]
+    SyncDir {
+        /// Directory of the host to sync
+        host_dir: PathBuf,
+
+        /// Path of the directory in the filesystem, or a CID and a path below it to pull from
+        source: TreeSource,
+
+        /// Which way to copy changes. `push` copies the host directory into the filesystem, and
+        /// `pull` copies the directory of the filesystem into the host directory
+        #[arg(long, value_enum, default_value_t = SyncDirection::Push)]
+        direction: SyncDirection,
+
+        /// Remove the entries of the destination the source has no entry for
+        #[arg(long)]
+        delete: bool,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// List a directory of the filesystem, or of any CID in its store, without mounting it
+    #[command(name = "ls")]
+    Ls {
+        /// CID of the entity to list, optionally followed by a path below it, or its path in the
+        /// filesystem. Defaults to the root
+        source: Option<TreeSource>,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Print the tree of entities below a directory of the filesystem, or of any CID in its store,
+    /// without mounting it
+    #[command(name = "tree")]
+    Tree {
+        /// CID of the entity to print the tree of, optionally followed by a path below it, or its
+        /// path in the filesystem. Defaults to the root
+        source: Option<TreeSource>,
+
+        /// How many directories down to print at most
+        #[arg(short = 'L', long)]
+        depth: Option<usize>,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Write the content of a file of the filesystem, or below any CID in its store, to stdout
+    /// without mounting it
+    #[command(name = "cat")]
+    Cat {
+        /// CID of the entity the file is below followed by the path to the file, or its path in
+        /// the filesystem
+        source: TreeSource,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Print the paths and CIDs of the entities below a path of the filesystem, or below any CID
+    /// in its store, whose paths match a glob, without mounting it
+    #[command(name = "find")]
+    Find {
+        /// Glob to match the paths of the entities against, like `src/**/*.rs`
+        glob: String,
+
+        /// CID of the directory to search below, optionally followed by a path below it, or its
+        /// path in the filesystem. Defaults to the root
+        source: Option<TreeSource>,
+
+        /// Only find entities of this type
+        #[arg(long = "type")]
+        find_type: Option<FindType>,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Print the lines of the files below a path of the filesystem, or below any CID in its
+    /// store, that match a regular expression, without mounting it. Binary files are skipped
+    #[command(name = "grep")]
+    Grep {
+        /// Regular expression to match lines against
+        pattern: String,
+
+        /// CID of the entity to search below followed by a path below it, or a path in the
+        /// filesystem
+        source: TreeSource,
+
+        /// Only search files whose path matches this glob, like `**/*.rs`
+        #[arg(long)]
+        glob: Option<String>,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Replace the content of a file of the filesystem, or below any CID in its store, with stdin
+    /// and print the new root
+    #[command(name = "write")]
+    Write {
+        /// CID of the directory to write the file below followed by the path to the file, or its
+        /// path in the filesystem. Missing parent directories are created
+        target: TreeSource,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Replace the root of the filesystem with a writable overlay of a directory in its store, like
+    /// the root of a shared image, and print the new root
+    #[command(name = "overlay")]
+    Overlay {
+        /// CID of the directory to layer the filesystem over
+        base: Cid,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Write the current root of the filesystem and its blocks to a stream file, resuming an
+    /// earlier send to the same file that was interrupted
+    #[command(name = "send")]
+    Send {
+        /// Path to write the stream to
+        stream_path: PathBuf,
+
+        /// Leave out the blocks of this root, which the receiving filesystem already has
+        #[arg(long)]
+        since: Option<Cid>,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Apply a stream file written by `send` to the filesystem and move it to the stream's root
+    #[command(name = "receive")]
+    Receive {
+        /// Path to read the stream from
+        stream_path: PathBuf,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Report how much space block deduplication saves in the filesystem
+    #[command(name = "dedup")]
+    Dedup {
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Report how much space a directory and the directories below it take up, split into what
+    /// only they use and what they share with the rest of the store
+    #[command(name = "du")]
+    Du {
+        /// CID of the directory to report on, optionally followed by a path below it, or its path
+        /// in the filesystem. Defaults to the root
+        source: Option<TreeSource>,
+
+        /// How many directories down to report on at most
+        #[arg(short = 'd', long)]
+        max_depth: Option<usize>,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Check the blocks of the filesystem against their CIDs and look for missing blocks
+    #[command(name = "verify")]
+    Verify {
+        /// Drop the entries of the current root that lead to corrupt or missing blocks
+        #[arg(long)]
+        repair: bool,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Show or set how the supervisor scrubs the blocks of the filesystem in the background, and
+    /// list the corrupt blocks it quarantined
+    #[command(name = "scrub")]
+    Scrub {
+        /// Seconds between batches of blocks checked
+        #[arg(long)]
+        interval_secs: Option<u64>,
+
+        /// Number of blocks checked in each batch
+        #[arg(long)]
+        batch_blocks: Option<u64>,
+
+        /// Stop scrubbing the blocks
+        #[arg(long, conflicts_with_all = ["interval_secs", "batch_blocks"])]
+        clear: bool,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// List the long-running operations run on the filesystem, such as garbage collections and
+    /// sends, with how far they got
+    #[command(name = "jobs")]
+    Jobs {
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Show how far a long-running operation run on the filesystem got
+    #[command(name = "job")]
+    Job {
+        /// ID of the job, as logged when it started
+        id: i64,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Print the logs of the filesystem's server and supervisor
+    #[command(name = "log")]
+    Log {
+        /// Which processes to print the logs of
+        #[arg(long, value_enum, default_value_t)]
+        source: LogSource,
+
+        /// Number of lines to print from the end of each log
+        #[arg(short = 'n', long, default_value_t = DEFAULT_TAIL_LINES)]
+        lines: usize,
+
+        /// Keep printing lines as they are written
+        #[arg(short = 'f', long)]
+        follow: bool,
+
+        /// Only print lines containing this text
+        #[arg(long)]
+        filter: Option<String>,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Show or set how the logs of the filesystem's server and supervisor are rotated, compressed
+    /// and expired
+    #[command(name = "log-rotation")]
+    LogRotation {
+        /// Size in bytes the server's log is rotated at
+        #[arg(long)]
+        max_bytes: Option<u64>,
+
+        /// Seconds the server's log is written to before it is rotated, or 0 to only rotate it by
+        /// size
+        #[arg(long)]
+        max_age_secs: Option<u64>,
+
+        /// Whether rotated logs are compressed with gzip
+        #[arg(long)]
+        compress: Option<bool>,
+
+        /// Number of rotated logs kept
+        #[arg(long)]
+        max_files: Option<u64>,
+
+        /// Seconds rotated logs are kept, or 0 to keep them until there are too many
+        #[arg(long)]
+        retention_secs: Option<u64>,
+
+        /// Go back to the default settings
+        #[arg(long, conflicts_with_all = [
+            "max_bytes", "max_age_secs", "compress", "max_files", "retention_secs"
+        ])]
+        reset: bool,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Print the changes made to the filesystem as they happen
+    #[command(name = "watch")]
+    Watch {
+        /// Only print changes to entities at or below this path in the filesystem
+        #[arg(long, default_value = "/")]
+        path_prefix: String,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Force everything written to the filesystem so far onto disk
+    #[command(name = "sync")]
+    Sync {
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
     /// Safely unmount the filesystem and stop the NFS server
     #[command(name = "detach")]
     Detach {
@@ -120,11 +731,231 @@ pub enum MonofsSubcommand {
         force: bool,
     },
 
+    /// Clean up after a crashed or hung server, force-unmounting the filesystem if needed
+    #[command(name = "repair")]
+    Repair {
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+
+        /// Attach the filesystem again once it is repaired
+        #[arg(short = 'a', long)]
+        attach: bool,
+    },
+
     /// Show version information
     #[command(name = "version")]
     Version,
 }
 
+/// Available subcommands for managing snapshots
+#[derive(Debug, Subcommand)]
+pub enum SnapshotSubcommand {
+    /// Capture the current state of the filesystem as a named snapshot
+    #[command(name = "create")]
+    Create {
+        /// Name of the snapshot
+        name: String,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// List the snapshots of the filesystem
+    #[command(name = "list")]
+    List {
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Restore the filesystem to a named snapshot
+    #[command(name = "restore")]
+    Restore {
+        /// Name of the snapshot
+        name: String,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Delete the snapshots a retention policy does not keep, and the blocks only they used
+    #[command(name = "prune")]
+    Prune {
+        /// Number of the newest snapshots to keep
+        #[arg(long)]
+        keep_last: Option<u32>,
+
+        /// Number of days to keep the newest snapshot of
+        #[arg(long)]
+        keep_daily: Option<u32>,
+
+        /// Number of weeks to keep the newest snapshot of
+        #[arg(long)]
+        keep_weekly: Option<u32>,
+
+        /// Only prune scheduled snapshots, leaving the ones taken by hand alone
+        #[arg(long)]
+        scheduled_only: bool,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Show or set when the supervisor takes snapshots on its own, and how many it keeps
+    #[command(name = "schedule")]
+    Schedule {
+        /// Seconds between scheduled snapshots
+        #[arg(long)]
+        interval_secs: Option<u64>,
+
+        /// Changes made by clients between scheduled snapshots
+        #[arg(long)]
+        every_changes: Option<u64>,
+
+        /// Number of the newest scheduled snapshots to keep
+        #[arg(long)]
+        keep_last: Option<u32>,
+
+        /// Number of days to keep the newest scheduled snapshot of
+        #[arg(long)]
+        keep_daily: Option<u32>,
+
+        /// Number of weeks to keep the newest scheduled snapshot of
+        #[arg(long)]
+        keep_weekly: Option<u32>,
+
+        /// Stop taking scheduled snapshots
+        #[arg(long, conflicts_with_all = ["interval_secs", "every_changes", "keep_last", "keep_daily", "keep_weekly"])]
+        clear: bool,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+}
+
+/// Available subcommands for managing branches
+#[derive(Debug, Subcommand)]
+pub enum BranchSubcommand {
+    /// Create a branch at the current root of the filesystem
+    #[command(name = "create")]
+    Create {
+        /// Name of the branch
+        name: String,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// List the branches of the filesystem
+    #[command(name = "list")]
+    List {
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Switch the filesystem to another branch
+    #[command(name = "switch")]
+    Switch {
+        /// Name of the branch
+        name: String,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Delete a branch the filesystem is not on
+    #[command(name = "delete")]
+    Delete {
+        /// Name of the branch
+        name: String,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+}
+
+/// Available subcommands for managing pins
+#[derive(Debug, Subcommand)]
+pub enum PinSubcommand {
+    /// Pin a CID so that garbage collection keeps its blocks
+    #[command(name = "add")]
+    Add {
+        /// CID to pin
+        cid: Cid,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Unpin a CID so that garbage collection may remove its blocks
+    #[command(name = "remove")]
+    Remove {
+        /// CID to unpin
+        cid: Cid,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// List the pinned CIDs of the filesystem
+    #[command(name = "list")]
+    List {
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+}
+
+/// Available subcommands for managing replicas
+#[derive(Debug, Subcommand)]
+pub enum ReplicaSubcommand {
+    /// Add a store that corrupt and missing blocks are repaired from, tried after the others
+    #[command(name = "add")]
+    Add {
+        /// URL of the replica, file:///path or s3://bucket/prefix
+        url: String,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// Stop repairing blocks from a store
+    #[command(name = "remove")]
+    Remove {
+        /// URL of the replica
+        url: String,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// List the replicas of the filesystem, in the order they are tried
+    #[command(name = "list")]
+    List {
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+}
+
 //-------------------------------------------------------------------------------------------------
 // Methods
 //-------------------------------------------------------------------------------------------------
+
+/// Available subcommands for managing backups
+#[derive(Debug, Subcommand)]
+pub enum BackupSubcommand {
+    /// Snapshot the filesystem and copy its database and blocks to an empty directory
+    #[command(name = "create")]
+    Create {
+        /// Directory to write the backup to
+        dest: PathBuf,
+
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+
+    /// List the backups taken of the filesystem
+    #[command(name = "list")]
+    List {
+        /// Directory where the filesystem is mounted
+        mount_dir: Option<PathBuf>,
+    },
+}
@@ -0,0 +1,44 @@
+use clap::Args;
+
+use crate::config::QuotaConfig;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Arguments limiting how much a filesystem may hold
+#[derive(Debug, Clone, Copy, Args)]
+pub struct QuotaArgs {
+    /// Most bytes the files of the filesystem may take up in total
+    #[arg(long)]
+    pub max_logical_bytes: Option<u64>,
+
+    /// Most bytes the blocks of the filesystem may take up on disk
+    #[arg(long)]
+    pub max_store_bytes: Option<u64>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl QuotaArgs {
+    /// Returns the quota given on the command line, or `None` if no limit was given.
+    pub fn to_config(&self) -> Option<QuotaConfig> {
+        let given = self.max_logical_bytes.is_some() || self.max_store_bytes.is_some();
+        given.then(|| (*self).into())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl From<QuotaArgs> for QuotaConfig {
+    fn from(args: QuotaArgs) -> Self {
+        QuotaConfig::builder()
+            .max_logical_bytes(args.max_logical_bytes)
+            .max_store_bytes(args.max_store_bytes)
+            .build()
+    }
+}
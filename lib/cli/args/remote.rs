@@ -0,0 +1,35 @@
+use clap::Args;
+
+use crate::config::{RemoteConfig, DEFAULT_REMOTE_CACHE_MAX_BYTES};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Arguments configuring a remote store that holds every block, with the local blocks directory
+/// as a cache in front of it
+#[derive(Debug, Clone, Args)]
+pub struct RemoteArgs {
+    /// URL of the remote store, `file:///path` or `s3://bucket/prefix`
+    #[arg(long)]
+    pub remote: Option<String>,
+
+    /// Most bytes of blocks to keep on local disk when there is a remote store
+    #[arg(long, default_value_t = DEFAULT_REMOTE_CACHE_MAX_BYTES, requires = "remote")]
+    pub cache_max_bytes: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl From<RemoteArgs> for Option<RemoteConfig> {
+    fn from(args: RemoteArgs) -> Self {
+        args.remote.map(|url| {
+            RemoteConfig::builder()
+                .url(url)
+                .cache_max_bytes(args.cache_max_bytes)
+                .build()
+        })
+    }
+}
@@ -0,0 +1,76 @@
+use clap::Args;
+
+use crate::config::ThrottleConfig;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Arguments limiting how fast NFS clients of a filesystem are served
+#[derive(Debug, Clone, Copy, Args)]
+pub struct ThrottleArgs {
+    /// Most NFS requests served each second across all clients
+    #[arg(long)]
+    pub max_ops_per_sec: Option<u32>,
+
+    /// Most bytes moved for NFS requests each second across all clients
+    #[arg(long)]
+    pub max_bytes_per_sec: Option<u64>,
+
+    /// Most NFS requests served each second to a single client connection
+    #[arg(long)]
+    pub client_max_ops_per_sec: Option<u32>,
+
+    /// Most bytes moved for NFS requests each second for a single client connection
+    #[arg(long)]
+    pub client_max_bytes_per_sec: Option<u64>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ThrottleArgs {
+    /// Returns the throttle given on the command line, or `None` if no limit was given.
+    pub fn to_config(&self) -> Option<ThrottleConfig> {
+        let config = ThrottleConfig::from(*self);
+        config.is_limited().then_some(config)
+    }
+
+    /// Returns the arguments that reproduce `config` on the command line.
+    pub fn to_args(config: &ThrottleConfig) -> Vec<String> {
+        [
+            (
+                "max-ops-per-sec",
+                config.get_max_ops_per_sec().map(u64::from),
+            ),
+            ("max-bytes-per-sec", *config.get_max_bytes_per_sec()),
+            (
+                "client-max-ops-per-sec",
+                config.get_client_max_ops_per_sec().map(u64::from),
+            ),
+            (
+                "client-max-bytes-per-sec",
+                *config.get_client_max_bytes_per_sec(),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, limit)| Some(format!("--{}={}", name, limit?)))
+        .collect()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl From<ThrottleArgs> for ThrottleConfig {
+    fn from(args: ThrottleArgs) -> Self {
+        ThrottleConfig::builder()
+            .max_ops_per_sec(args.max_ops_per_sec)
+            .max_bytes_per_sec(args.max_bytes_per_sec)
+            .client_max_ops_per_sec(args.client_max_ops_per_sec)
+            .client_max_bytes_per_sec(args.client_max_bytes_per_sec)
+            .build()
+    }
+}
@@ -0,0 +1,52 @@
+use clap::Args;
+
+use crate::config::{TransferConfig, DEFAULT_NFS_DTPREF, DEFAULT_NFS_RSIZE, DEFAULT_NFS_WSIZE};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Arguments controlling how much data NFS clients move in a single request
+#[derive(Debug, Clone, Copy, Args)]
+pub struct TransferArgs {
+    /// Most bytes an NFS client reads in a single request
+    #[arg(long, default_value_t = DEFAULT_NFS_RSIZE)]
+    pub nfs_rsize: u32,
+
+    /// Most bytes an NFS client writes in a single request
+    #[arg(long, default_value_t = DEFAULT_NFS_WSIZE)]
+    pub nfs_wsize: u32,
+
+    /// Size in bytes of the directory listings NFS clients are asked to request
+    #[arg(long, default_value_t = DEFAULT_NFS_DTPREF)]
+    pub nfs_dtpref: u32,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl TransferArgs {
+    /// Returns the arguments that reproduce `config` on the command line.
+    pub fn to_args(config: &TransferConfig) -> Vec<String> {
+        vec![
+            format!("--nfs-rsize={}", config.get_rsize()),
+            format!("--nfs-wsize={}", config.get_wsize()),
+            format!("--nfs-dtpref={}", config.get_dtpref()),
+        ]
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl From<TransferArgs> for TransferConfig {
+    fn from(args: TransferArgs) -> Self {
+        TransferConfig::builder()
+            .rsize(args.nfs_rsize)
+            .wsize(args.nfs_wsize)
+            .dtpref(args.nfs_dtpref)
+            .build()
+    }
+}
@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use clap::Args;
+
+use crate::config::{
+    WriteBackConfig, DEFAULT_WRITE_BACK_FLUSH_INTERVAL, DEFAULT_WRITE_BACK_MAX_DIRTY_BYTES,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Arguments controlling how writes are buffered before they are flushed to the store
+#[derive(Debug, Clone, Copy, Args)]
+pub struct WriteBackArgs {
+    /// Bytes of writes to buffer across all files before flushing them, 0 to write through
+    #[arg(long, default_value_t = DEFAULT_WRITE_BACK_MAX_DIRTY_BYTES)]
+    pub write_back_max_dirty_bytes: u64,
+
+    /// Longest time in milliseconds a write stays buffered before it is flushed
+    #[arg(long, default_value_t = DEFAULT_WRITE_BACK_FLUSH_INTERVAL.as_millis() as u64)]
+    pub write_back_flush_interval_ms: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl WriteBackArgs {
+    /// Returns the arguments that reproduce `config` on the command line.
+    pub fn to_args(config: &WriteBackConfig) -> Vec<String> {
+        vec![
+            format!(
+                "--write-back-max-dirty-bytes={}",
+                config.get_max_dirty_bytes()
+            ),
+            format!(
+                "--write-back-flush-interval-ms={}",
+                config.get_flush_interval().as_millis()
+            ),
+        ]
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl From<WriteBackArgs> for WriteBackConfig {
+    fn from(args: WriteBackArgs) -> Self {
+        WriteBackConfig::builder()
+            .max_dirty_bytes(args.write_back_max_dirty_bytes)
+            .flush_interval(Duration::from_millis(args.write_back_flush_interval_ms))
+            .build()
+    }
+}
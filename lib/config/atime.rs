@@ -0,0 +1,138 @@
+use std::fmt::{self, Display};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How old an access time gets before [`AtimePolicy::Relatime`] updates it regardless of the
+/// other times of the entity.
+pub const RELATIME_INTERVAL: TimeDelta = TimeDelta::hours(24);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// When reading a file records the time it was accessed, like the `noatime`, `relatime` and
+/// `strictatime` mount options.
+///
+/// Recording an access time changes the file, so every read that records one makes the next
+/// checkpoint write the file's metadata and the directories above it to the store. Most sandboxes
+/// never look at access times, so they are not recorded by default.
+///
+/// ## Example
+/// ```
+/// use chrono::{TimeDelta, Utc};
+/// use monofs::config::AtimePolicy;
+///
+/// let now = Utc::now();
+/// let modified_at = now - TimeDelta::minutes(5);
+///
+/// // Files read since they were last modified are not updated again until a day has passed
+/// let accessed_at = now - TimeDelta::minutes(1);
+/// assert!(!AtimePolicy::Relatime.should_update(Some(accessed_at), modified_at, modified_at, now));
+/// assert!(AtimePolicy::Strictatime.should_update(Some(accessed_at), modified_at, modified_at, now));
+/// assert!(!AtimePolicy::Noatime.should_update(None, modified_at, modified_at, now));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AtimePolicy {
+    /// Never record access times.
+    #[default]
+    Noatime,
+
+    /// Record an access time when the previous one is older than the last modification or
+    /// change of the file, or older than [`RELATIME_INTERVAL`]. This keeps tools that compare
+    /// access and modification times working, while most reads record nothing.
+    Relatime,
+
+    /// Record an access time on every read.
+    Strictatime,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl AtimePolicy {
+    /// Returns whether reading an entity at `now` records a new access time, given the access
+    /// time it has, if any, and its modification and change times.
+    pub fn should_update(
+        &self,
+        accessed_at: Option<DateTime<Utc>>,
+        modified_at: DateTime<Utc>,
+        changed_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        match self {
+            Self::Noatime => false,
+            Self::Strictatime => true,
+            Self::Relatime => match accessed_at {
+                Some(accessed_at) => {
+                    accessed_at <= modified_at
+                        || accessed_at <= changed_at
+                        || now - accessed_at >= RELATIME_INTERVAL
+                }
+                None => true,
+            },
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Display for AtimePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AtimePolicy::Noatime => write!(f, "noatime"),
+            AtimePolicy::Relatime => write!(f, "relatime"),
+            AtimePolicy::Strictatime => write!(f, "strictatime"),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atime_policy_relatime() {
+        let now = Utc::now();
+        let modified_at = now - TimeDelta::hours(1);
+        let policy = AtimePolicy::Relatime;
+
+        // Never accessed, or accessed before the last modification or change
+        assert!(policy.should_update(None, modified_at, modified_at, now));
+        assert!(policy.should_update(
+            Some(modified_at - TimeDelta::minutes(1)),
+            modified_at,
+            modified_at,
+            now
+        ));
+        assert!(policy.should_update(
+            Some(modified_at + TimeDelta::minutes(1)),
+            modified_at,
+            now - TimeDelta::minutes(1),
+            now
+        ));
+
+        // Accessed since, until a day has passed
+        let accessed_at = now - TimeDelta::minutes(30);
+        assert!(!policy.should_update(Some(accessed_at), modified_at, modified_at, now));
+        assert!(policy.should_update(
+            Some(accessed_at),
+            modified_at,
+            modified_at,
+            accessed_at + RELATIME_INTERVAL
+        ));
+    }
+}
@@ -0,0 +1,67 @@
+use std::fmt::{self, Display};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The mechanism used to expose a monofs filesystem at its mount point.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MountBackend {
+    /// Serve the filesystem over a loopback NFSv3 server and mount it with the system's
+    /// `mount` command.
+    #[default]
+    Nfs,
+
+    /// Mount the filesystem directly through FUSE. This does not depend on the external
+    /// `mount`/`umount` binaries and does not require an NFS client on the host.
+    Fuse,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl MountBackend {
+    /// Returns the backend filesystems are mounted with when none is chosen.
+    ///
+    /// That is NFS, except on Linux for processes not running as root. Only root may mount NFS
+    /// there, even in a user namespace, while FUSE filesystems are mounted by anyone through the
+    /// setuid `fusermount3` helper.
+    pub fn get_preferred() -> Self {
+        if cfg!(target_os = "linux") && !utils::is_privileged() {
+            MountBackend::Fuse
+        } else {
+            MountBackend::Nfs
+        }
+    }
+
+    /// Returns whether the current process may mount filesystems with this backend.
+    ///
+    /// Mounting NFS on Linux takes root, while NFS on macOS is mounted by its owner without it, and
+    /// FUSE is mounted through `fusermount3` when the process cannot mount it itself.
+    pub fn is_permitted(&self) -> bool {
+        match self {
+            MountBackend::Nfs => !cfg!(target_os = "linux") || utils::is_privileged(),
+            MountBackend::Fuse => true,
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Display for MountBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MountBackend::Nfs => write!(f, "nfs"),
+            MountBackend::Fuse => write!(f, "fuse"),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use getset::Getters;
+use ipldstore::FastCDCChunker;
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+use crate::{FsError, FsResult};
+
+use super::{
+    DEFAULT_CDC_DESIRED_CHUNK_SIZE, DEFAULT_CDC_MAX_CHUNK_SIZE, DEFAULT_CDC_MIN_CHUNK_SIZE,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The smallest minimum chunk size supported by content-defined chunking.
+const CDC_MIN_CHUNK_SIZE_LIMIT: u32 = 64;
+
+/// The largest maximum chunk size supported by content-defined chunking.
+const CDC_MAX_CHUNK_SIZE_LIMIT: u32 = 16 * 1024 * 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Configures how file contents are split into blocks.
+///
+/// Contents are split with content-defined chunking (FastCDC), which places chunk boundaries
+/// based on the data itself rather than at fixed offsets. Inserting or removing bytes therefore
+/// only changes the chunks around the edit, and the rest of the file still deduplicates against
+/// earlier versions.
+///
+/// ## Example
+/// ```
+/// use monofs::config::ChunkerConfig;
+///
+/// let config = ChunkerConfig::builder()
+///     .min_size(4 * 1024)
+///     .desired_size(16 * 1024)
+///     .max_size(64 * 1024)
+///     .build();
+///
+/// assert!(config.validate().is_ok());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TypedBuilder, Getters)]
+#[serde(default)]
+#[getset(get = "pub with_prefix")]
+pub struct ChunkerConfig {
+    /// The smallest size a chunk can have, except for the last chunk of a file.
+    #[builder(default = DEFAULT_CDC_MIN_CHUNK_SIZE)]
+    min_size: u32,
+
+    /// The size chunks are aimed at on average.
+    #[builder(default = DEFAULT_CDC_DESIRED_CHUNK_SIZE)]
+    desired_size: u32,
+
+    /// The largest size a chunk can have.
+    #[builder(default = DEFAULT_CDC_MAX_CHUNK_SIZE)]
+    max_size: u32,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ChunkerConfig {
+    /// Checks that the sizes are ordered and within the limits supported by the chunker.
+    pub fn validate(&self) -> FsResult<()> {
+        if self.min_size < CDC_MIN_CHUNK_SIZE_LIMIT {
+            return Err(FsError::InvalidChunkerConfig(format!(
+                "minimum chunk size must be at least {} bytes",
+                CDC_MIN_CHUNK_SIZE_LIMIT
+            )));
+        }
+
+        if self.max_size > CDC_MAX_CHUNK_SIZE_LIMIT {
+            return Err(FsError::InvalidChunkerConfig(format!(
+                "maximum chunk size must be at most {} bytes",
+                CDC_MAX_CHUNK_SIZE_LIMIT
+            )));
+        }
+
+        if self.min_size > self.desired_size || self.desired_size > self.max_size {
+            return Err(FsError::InvalidChunkerConfig(format!(
+                "chunk sizes must satisfy min <= desired <= max, got {} <= {} <= {}",
+                self.min_size, self.desired_size, self.max_size
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Creates the chunker described by this configuration.
+    pub fn to_chunker(&self) -> FastCDCChunker {
+        FastCDCChunker::new(self.min_size, self.desired_size, self.max_size)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for ChunkerConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunker_config_validate() {
+        assert!(ChunkerConfig::default().validate().is_ok());
+
+        let config = ChunkerConfig::builder().min_size(32).build();
+        assert!(config.validate().is_err());
+
+        let config = ChunkerConfig::builder()
+            .min_size(8 * 1024)
+            .desired_size(4 * 1024)
+            .build();
+        assert!(config.validate().is_err());
+
+        let config = ChunkerConfig::builder().max_size(32 * 1024 * 1024).build();
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+use crate::{FsError, FsResult};
+
+use super::DEFAULT_COMPRESSION_LEVEL;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The lowest compression level supported by zstd, other than its negative fast levels.
+const COMPRESSION_MIN_LEVEL: i32 = 1;
+
+/// The highest compression level supported by zstd.
+const COMPRESSION_MAX_LEVEL: i32 = 22;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Configures how blocks are compressed at rest.
+///
+/// Compressed blocks are stored with zstd at the given level. A block is only stored compressed if
+/// that saves a meaningful amount of space, so already-compressed data such as images or archives
+/// costs a compression attempt when written and nothing when read. Reads decompress blocks
+/// transparently, whether or not compression is enabled for the store that reads them.
+///
+/// ## Example
+/// ```
+/// use monofs::config::CompressionConfig;
+///
+/// let config = CompressionConfig::builder().level(9).build();
+///
+/// assert!(config.validate().is_ok());
+/// assert!(!CompressionConfig::disabled().get_enabled());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TypedBuilder, Getters)]
+#[serde(default)]
+#[getset(get = "pub with_prefix")]
+pub struct CompressionConfig {
+    /// Whether new blocks are compressed.
+    #[builder(default = true)]
+    enabled: bool,
+
+    /// The zstd compression level, from 1 (fastest) to 22 (smallest).
+    #[builder(default = DEFAULT_COMPRESSION_LEVEL)]
+    level: i32,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl CompressionConfig {
+    /// Creates a configuration that stores new blocks uncompressed.
+    pub fn disabled() -> Self {
+        Self::builder().enabled(false).build()
+    }
+
+    /// Checks that the level is supported by zstd.
+    pub fn validate(&self) -> FsResult<()> {
+        if !(COMPRESSION_MIN_LEVEL..=COMPRESSION_MAX_LEVEL).contains(&self.level) {
+            return Err(FsError::InvalidCompressionConfig(format!(
+                "compression level must be between {} and {}, got {}",
+                COMPRESSION_MIN_LEVEL, COMPRESSION_MAX_LEVEL, self.level
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression_config_validate() {
+        assert!(CompressionConfig::default().validate().is_ok());
+        assert!(CompressionConfig::disabled().validate().is_ok());
+
+        let config = CompressionConfig::builder().level(0).build();
+        assert!(config.validate().is_err());
+
+        let config = CompressionConfig::builder().level(23).build();
+        assert!(config.validate().is_err());
+    }
+}
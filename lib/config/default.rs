@@ -1,4 +1,4 @@
-use std::{path::PathBuf, sync::LazyLock};
+use std::{path::PathBuf, sync::LazyLock, time::Duration};
 
 //--------------------------------------------------------------------------------------------------
 // Constants
@@ -13,6 +13,57 @@ pub const DEFAULT_HOST: &str = "127.0.0.1";
 /// The default NFS port number to use.
 pub const DEFAULT_NFS_PORT: u32 = 2049;
 
+/// The default number of ports, starting at [`DEFAULT_NFS_PORT`], to search for a free NFS port.
+pub const DEFAULT_NFS_PORT_RANGE_SIZE: u32 = 100;
+
+/// The default minimum chunk size for content-defined chunking.
+pub const DEFAULT_CDC_MIN_CHUNK_SIZE: u32 = 16 * 1024;
+
+/// The default desired chunk size for content-defined chunking.
+pub const DEFAULT_CDC_DESIRED_CHUNK_SIZE: u32 = 64 * 1024;
+
+/// The default maximum chunk size for content-defined chunking.
+pub const DEFAULT_CDC_MAX_CHUNK_SIZE: u32 = 256 * 1024;
+
+/// The default zstd level blocks are compressed with.
+pub const DEFAULT_COMPRESSION_LEVEL: i32 = 3;
+
+/// The default number of written bytes the NFS server buffers before flushing them to the store.
+pub const DEFAULT_WRITE_BACK_MAX_DIRTY_BYTES: u64 = 64 * 1024 * 1024;
+
+/// The default longest time the NFS server buffers a write before flushing it to the store.
+pub const DEFAULT_WRITE_BACK_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The default number of bytes NFS clients read from a file in a single READ.
+pub const DEFAULT_NFS_RSIZE: u32 = 1024 * 1024;
+
+/// The default number of bytes NFS clients write to a file in a single WRITE.
+pub const DEFAULT_NFS_WSIZE: u32 = 1024 * 1024;
+
+/// The default size of the READDIR replies NFS clients are asked to request.
+pub const DEFAULT_NFS_DTPREF: u32 = 64 * 1024;
+
+/// The default user squashed NFS clients act as, `nobody` on most systems.
+pub const DEFAULT_ANON_UID: u32 = 65534;
+
+/// The default group squashed NFS clients act as, `nogroup` on most systems.
+pub const DEFAULT_ANON_GID: u32 = 65534;
+
+/// The default number of bytes of blocks a filesystem with a remote store keeps on local disk.
+pub const DEFAULT_REMOTE_CACHE_MAX_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// The default number of bytes of blocks a filesystem with an in-memory store keeps in memory.
+pub const DEFAULT_MEMORY_STORE_MAX_BYTES: u64 = 512 * 1024 * 1024;
+
+/// The default longest time to wait for a new filesystem to be mounted.
+pub const DEFAULT_MOUNT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The name of the branch a filesystem starts out on.
+pub const DEFAULT_BRANCH_NAME: &str = "main";
+
+/// The default number of lines read from the end of a log before following it.
+pub const DEFAULT_TAIL_LINES: usize = 10;
+
 /// The default path for the mfsrun binary.
 pub static DEFAULT_MFSRUN_EXE_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
     let current_exe = std::env::current_exe().unwrap();
@@ -0,0 +1,55 @@
+use std::fmt::{self, Display};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// When the block files a store writes are flushed to disk.
+///
+/// Until a block file is flushed, a crash of the machine may lose it, and with it every root that
+/// links to it. Flushing every block file as it is written makes writing many small files slow,
+/// so [`Durability::Batch`] flushes the blocks of a write together once all of them are written
+/// instead, such as the chunks of a file's contents put with
+/// [`put_bytes`](ipldstore::IpldStore::put_bytes), or the blocks put with
+/// [`put_many`](crate::store::FlatFsStoreImpl::put_many). Blocks are not flushed by default.
+///
+/// ## Example
+/// ```
+/// use monofs::{config::Durability, store::FlatFsStore};
+///
+/// let store = FlatFsStore::new("blocks").with_durability(Durability::Batch);
+///
+/// assert_eq!(store.get_durability(), &Durability::Batch);
+/// assert_eq!(Durability::default().to_string(), "relaxed");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Durability {
+    /// Never flush block files, leaving it to the operating system.
+    #[default]
+    Relaxed,
+
+    /// Flush the block files of a write once all of them are written, along with the
+    /// directories they are in. Blocks written on their own are flushed as they are written.
+    Batch,
+
+    /// Flush every block file, and the directory it is in, as soon as it is written.
+    Block,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Display for Durability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Durability::Relaxed => write!(f, "relaxed"),
+            Durability::Batch => write!(f, "batch"),
+            Durability::Block => write!(f, "block"),
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::{env, fmt, fs, path::Path};
+
+use chacha20poly1305::{
+    aead::{KeyInit, OsRng},
+    XChaCha20Poly1305,
+};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{
+    utils::{ENCRYPTION_KEY_ENV_VAR, ENCRYPTION_KEY_FILE_ENV_VAR},
+    FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The size in bytes of an encryption key.
+pub const ENCRYPTION_KEY_SIZE: usize = 32;
+
+/// The label the key that names encrypted blocks is derived from a filesystem's key with.
+const BLOCK_NAMING_LABEL: &[u8] = b"monofs block names";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A key that encrypts a filesystem's blocks and database at rest.
+///
+/// Blocks are encrypted with XChaCha20-Poly1305 and the database with SQLCipher, so neither can
+/// be read or undetectably modified without the key. Encryption is chosen when a filesystem is
+/// initialized, and every process touching the filesystem afterwards needs the same key.
+///
+/// Encrypted block files are named after a keyed hash of their digest, derived from the key, so
+/// their names do not tell what they contain. Blocks are still compressed before they are
+/// encrypted, so someone without the key can learn how well the content of each block
+/// compresses. See [`FlatFsStoreImpl`](crate::store::FlatFsStoreImpl) for the details.
+///
+/// Keys are 32 bytes, written as 64 hex characters, and are usually taken from the environment
+/// with [`from_env`](Self::from_env). The key is never printed, not even in debug output.
+///
+/// ## Example
+/// ```
+/// use monofs::config::EncryptionKey;
+///
+/// let key = EncryptionKey::generate();
+/// let parsed = EncryptionKey::from_hex(&key.to_hex())?;
+///
+/// assert_eq!(parsed, key);
+/// assert_eq!(format!("{:?}", key), "EncryptionKey(..)");
+/// # Ok::<(), monofs::FsError>(())
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; ENCRYPTION_KEY_SIZE]);
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl EncryptionKey {
+    /// Creates a new random key.
+    pub fn generate() -> Self {
+        Self(XChaCha20Poly1305::generate_key(&mut OsRng).into())
+    }
+
+    /// Parses a key written as 64 hex characters.
+    pub fn from_hex(hex: &str) -> FsResult<Self> {
+        let bytes = hex::decode(hex.trim())
+            .map_err(|_| FsError::InvalidEncryptionKey("key must be written in hex".to_string()))?;
+
+        let bytes = <[u8; ENCRYPTION_KEY_SIZE]>::try_from(bytes).map_err(|bytes| {
+            FsError::InvalidEncryptionKey(format!(
+                "key must be {} bytes, got {}",
+                ENCRYPTION_KEY_SIZE,
+                bytes.len()
+            ))
+        })?;
+
+        Ok(Self(bytes))
+    }
+
+    /// Reads a key from the file at `path`, which holds the key as 64 hex characters.
+    pub fn from_file(path: impl AsRef<Path>) -> FsResult<Self> {
+        let path = path.as_ref();
+        let hex = fs::read_to_string(path).map_err(|e| {
+            FsError::InvalidEncryptionKey(format!("failed to read {}: {}", path.display(), e))
+        })?;
+
+        Self::from_hex(&hex)
+    }
+
+    /// Gets the key from the environment, if one is given.
+    ///
+    /// The key is taken from the `MONOFS_ENCRYPTION_KEY` environment variable, or else read from
+    /// the file named by the `MONOFS_ENCRYPTION_KEY_FILE` environment variable.
+    pub fn from_env() -> FsResult<Option<Self>> {
+        if let Ok(hex) = env::var(ENCRYPTION_KEY_ENV_VAR) {
+            return Self::from_hex(&hex).map(Some);
+        }
+
+        match env::var_os(ENCRYPTION_KEY_FILE_ENV_VAR) {
+            Some(path) => Self::from_file(path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the key written as 64 hex characters.
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0)
+    }
+
+    /// Returns the bytes of the key.
+    pub fn as_bytes(&self) -> &[u8; ENCRYPTION_KEY_SIZE] {
+        &self.0
+    }
+
+    /// Returns the name of the file holding the encrypted block with the given digest, without
+    /// its extension.
+    ///
+    /// The name is an HMAC-SHA256 of the digest, in hex, keyed with a subkey derived from this
+    /// key, so it cannot be told from the digest without the key.
+    pub(crate) fn get_block_name(&self, digest: &[u8]) -> String {
+        let mut naming_key = new_hmac(&self.0);
+        naming_key.update(BLOCK_NAMING_LABEL);
+
+        let mut name = new_hmac(&naming_key.finalize().into_bytes());
+        name.update(digest);
+        hex::encode(name.finalize().into_bytes())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns an HMAC-SHA256 keyed with `key`.
+fn new_hmac(key: &[u8]) -> Hmac<Sha256> {
+    <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC takes keys of any size")
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EncryptionKey(..)")
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_encryption_key_from_hex_and_file() -> anyhow::Result<()> {
+        let key = EncryptionKey::generate();
+        assert_ne!(key, EncryptionKey::generate());
+        assert_eq!(EncryptionKey::from_hex(&key.to_hex())?, key);
+
+        // Keys of the wrong size or encoding are rejected
+        assert!(EncryptionKey::from_hex("abcd").is_err());
+        assert!(EncryptionKey::from_hex(&"z".repeat(64)).is_err());
+
+        // Key files may end with a newline
+        let temp = TempDir::new()?;
+        let path = temp.path().join("key");
+        fs::write(&path, format!("{}\n", key.to_hex()))?;
+        assert_eq!(EncryptionKey::from_file(&path)?, key);
+        assert!(EncryptionKey::from_file(temp.path().join("missing")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encryption_key_block_name() {
+        let key = EncryptionKey::generate();
+        let digest = [7u8; 32];
+
+        // Names are stable for a key, differ between keys, and do not reveal the digest
+        let name = key.get_block_name(&digest);
+        assert_eq!(name, key.get_block_name(&digest));
+        assert_ne!(name, EncryptionKey::generate().get_block_name(&digest));
+        assert_ne!(name, key.get_block_name(&[8u8; 32]));
+        assert_ne!(name, hex::encode(digest));
+        assert_eq!(name.len(), 64);
+    }
+}
@@ -0,0 +1,471 @@
+use std::{
+    fmt::{self, Display},
+    net::IpAddr,
+    str::FromStr,
+};
+
+use clap::ValueEnum;
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+use crate::{FsError, FsResult};
+
+use super::{DEFAULT_ANON_GID, DEFAULT_ANON_UID};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Configures which NFS clients may use a filesystem, and who they act as, like an entry in
+/// `/etc/exports`.
+///
+/// The NFS server trusts the user IDs clients send, so a server bound to an address other hosts
+/// can reach lets any of them own files as any user, root included. Restricting the clients to
+/// some networks keeps everyone else from connecting at all. Connections from the host itself are
+/// always allowed, as the filesystem's own mount is one of them.
+///
+/// Squashing maps the owners clients give files to the anonymous user and group instead:
+/// [`SquashMode::Root`] does so for root only, and [`SquashMode::All`] for every owner, which also
+/// makes the anonymous user the owner of everything clients create. `nfsserve` does not pass the
+/// credentials of a request on to the filesystem, so squashing applies to the owners clients
+/// set, and to the credentials permissions are checked with if the server checks them.
+///
+/// Clients are trusted to check permissions themselves by default, so any process on a client can
+/// read and change every file. With `check_permissions`, the server checks the user and groups
+/// each request is made as, taken from its `AUTH_SYS` credentials, against the mode and owner of
+/// the entities it acts on, and refuses what they do not permit. The user and group IDs of a
+/// client can be mapped to the IDs files are stored with first, e.g. when the guest user 1000
+/// should act as the host user 501 who owns the files, with `uid_map` and `gid_map`.
+///
+/// ## Example
+/// ```
+/// use monofs::config::{ExportConfig, SquashMode};
+///
+/// let config = ExportConfig::builder()
+///     .allowed_clients(vec!["10.0.0.0/8".parse().unwrap()])
+///     .squash(SquashMode::Root)
+///     .build();
+///
+/// assert!(config.is_client_allowed("10.1.2.3".parse().unwrap()));
+/// assert!(!config.is_client_allowed("192.168.1.2".parse().unwrap()));
+/// assert_eq!(config.squash_uid(0), 65534);
+/// assert_eq!(config.squash_uid(1000), 1000);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TypedBuilder, Getters)]
+#[serde(default, deny_unknown_fields)]
+#[getset(get = "pub with_prefix")]
+pub struct ExportConfig {
+    /// The networks clients may connect from, any if empty.
+    #[builder(default)]
+    allowed_clients: Vec<ClientNetwork>,
+
+    /// Which owners set by clients are mapped to the anonymous user and group.
+    #[builder(default)]
+    squash: SquashMode,
+
+    /// The user squashed owners are mapped to.
+    #[builder(default = DEFAULT_ANON_UID)]
+    anon_uid: u32,
+
+    /// The group squashed owners are mapped to.
+    #[builder(default = DEFAULT_ANON_GID)]
+    anon_gid: u32,
+
+    /// Whether the server checks the credentials of requests against the permissions of the
+    /// entities they act on.
+    #[builder(default)]
+    check_permissions: bool,
+
+    /// How the user IDs in the credentials of clients map to the user IDs files are stored with.
+    /// IDs outside every mapping are kept.
+    #[builder(default)]
+    uid_map: Vec<IdMapping>,
+
+    /// How the group IDs in the credentials of clients map to the group IDs files are stored
+    /// with. IDs outside every mapping are kept.
+    #[builder(default)]
+    gid_map: Vec<IdMapping>,
+}
+
+/// Which owners set by NFS clients are mapped to the anonymous user and group, like the
+/// `no_root_squash`, `root_squash` and `all_squash` export options.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SquashMode {
+    /// Keep every owner as it is set.
+    #[default]
+    None,
+
+    /// Map root, user and group 0, to the anonymous user and group.
+    Root,
+
+    /// Map every owner to the anonymous user and group.
+    All,
+}
+
+/// A network NFS clients may connect from, written as `address/prefix-length`, or as a single
+/// address.
+///
+/// ## Example
+/// ```
+/// use monofs::config::ClientNetwork;
+///
+/// let network = "192.168.0.0/16".parse::<ClientNetwork>().unwrap();
+/// assert!(network.contains("192.168.4.2".parse().unwrap()));
+/// assert!(!network.contains("10.0.0.1".parse().unwrap()));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ClientNetwork {
+    /// The address of the network.
+    addr: IpAddr,
+
+    /// How many leading bits of an address have to match those of the network.
+    prefix_len: u8,
+}
+
+/// A range of user or group IDs of clients mapped to a range of stored IDs, written as
+/// `client:stored`, or `client:stored:count` for a range of `count` IDs, like a line of
+/// `/proc/<pid>/uid_map`.
+///
+/// ## Example
+/// ```
+/// use monofs::config::IdMapping;
+///
+/// let mapping = "100000:0:65536".parse::<IdMapping>().unwrap();
+/// assert_eq!(mapping.map(100000), Some(0));
+/// assert_eq!(mapping.map(101000), Some(1000));
+/// assert_eq!(mapping.map(1000), None);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct IdMapping {
+    /// The first ID of the range on clients.
+    client: u32,
+
+    /// The stored ID the first ID of the range maps to.
+    stored: u32,
+
+    /// How many IDs the range holds.
+    count: u32,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ExportConfig {
+    /// Returns whether a client connecting from `addr` may use the filesystem.
+    pub fn is_client_allowed(&self, addr: IpAddr) -> bool {
+        self.allowed_clients.is_empty()
+            || self
+                .allowed_clients
+                .iter()
+                .any(|network| network.contains(addr))
+    }
+
+    /// Returns whether the configuration restricts clients in any way.
+    pub fn is_restricted(&self) -> bool {
+        !self.allowed_clients.is_empty()
+            || self.squash != SquashMode::None
+            || self.check_permissions
+    }
+
+    /// Returns the user a client setting `uid` as the owner of a file sets instead.
+    pub fn squash_uid(&self, uid: u32) -> u32 {
+        match self.squash {
+            SquashMode::Root if uid == 0 => self.anon_uid,
+            SquashMode::All => self.anon_uid,
+            _ => uid,
+        }
+    }
+
+    /// Returns the group a client setting `gid` as the group of a file sets instead.
+    pub fn squash_gid(&self, gid: u32) -> u32 {
+        match self.squash {
+            SquashMode::Root if gid == 0 => self.anon_gid,
+            SquashMode::All => self.anon_gid,
+            _ => gid,
+        }
+    }
+
+    /// Returns the stored user a request made as the user `uid` of a client is checked as, which
+    /// is mapped by `uid_map` and then squashed.
+    pub fn map_uid(&self, uid: u32) -> u32 {
+        self.squash_uid(map_id(&self.uid_map, uid))
+    }
+
+    /// Returns the stored group a request made with the group `gid` of a client is checked with,
+    /// which is mapped by `gid_map` and then squashed.
+    pub fn map_gid(&self, gid: u32) -> u32 {
+        self.squash_gid(map_id(&self.gid_map, gid))
+    }
+
+    /// Checks that the ID mappings do not overlap, so that every ID maps to a single one.
+    pub fn validate(&self) -> FsResult<()> {
+        for (name, map) in [("uid_map", &self.uid_map), ("gid_map", &self.gid_map)] {
+            for (i, a) in map.iter().enumerate() {
+                for b in &map[i + 1..] {
+                    if a.overlaps(b) {
+                        return Err(FsError::InvalidExportConfig(format!(
+                            "{} maps the IDs of {} and {} more than once",
+                            name, a, b
+                        )));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl IdMapping {
+    /// Creates a mapping of the `count` IDs of clients from `client` to the stored IDs from
+    /// `stored`.
+    pub fn new(client: u32, stored: u32, count: u32) -> FsResult<Self> {
+        let fits = |start: u32| start.checked_add(count - 1).is_some();
+        if count == 0 || !fits(client) || !fits(stored) {
+            return Err(FsError::InvalidExportConfig(format!(
+                "{} IDs from {} cannot be mapped to {} IDs from {}",
+                count, client, count, stored
+            )));
+        }
+
+        Ok(Self {
+            client,
+            stored,
+            count,
+        })
+    }
+
+    /// Returns the stored ID the ID `id` of a client maps to, if the mapping covers it.
+    pub fn map(&self, id: u32) -> Option<u32> {
+        let offset = id.checked_sub(self.client)?;
+        (offset < self.count).then(|| self.stored + offset)
+    }
+
+    /// Returns whether this mapping and `other` cover any of the same IDs of clients.
+    fn overlaps(&self, other: &IdMapping) -> bool {
+        let last = |mapping: &IdMapping| mapping.client + (mapping.count - 1);
+        self.client <= last(other) && other.client <= last(self)
+    }
+}
+
+impl ClientNetwork {
+    /// Creates a network of the addresses whose first `prefix_len` bits are those of `addr`.
+    pub fn new(addr: IpAddr, prefix_len: u8) -> FsResult<Self> {
+        let max_prefix_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+
+        if prefix_len > max_prefix_len {
+            return Err(FsError::InvalidExportConfig(format!(
+                "prefix length of {} must be at most {}, got {}",
+                addr, max_prefix_len, prefix_len
+            )));
+        }
+
+        Ok(Self { addr, prefix_len })
+    }
+
+    /// Returns whether `addr` is in the network. IPv4 addresses written as IPv6 addresses, as
+    /// dual-stack sockets report them, are in the IPv4 networks they belong to.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the stored ID the ID `id` of a client maps to in `map`, or `id` itself if no mapping
+/// covers it.
+fn map_id(map: &[IdMapping], id: u32) -> u32 {
+    map.iter().find_map(|mapping| mapping.map(id)).unwrap_or(id)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Display for SquashMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SquashMode::None => write!(f, "none"),
+            SquashMode::Root => write!(f, "root"),
+            SquashMode::All => write!(f, "all"),
+        }
+    }
+}
+
+impl Display for ClientNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+impl FromStr for ClientNetwork {
+    type Err = FsError;
+
+    /// Parses a network written as `address/prefix-length`, or a single address.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || FsError::InvalidExportConfig(format!("invalid client network: {}", s));
+        let (addr, prefix_len) = match s.trim().split_once('/') {
+            Some((addr, prefix_len)) => (
+                addr.parse::<IpAddr>().map_err(|_| invalid())?,
+                Some(prefix_len.parse::<u8>().map_err(|_| invalid())?),
+            ),
+            None => (s.trim().parse::<IpAddr>().map_err(|_| invalid())?, None),
+        };
+
+        let prefix_len = prefix_len.unwrap_or(match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        });
+
+        Self::new(addr, prefix_len)
+    }
+}
+
+impl Display for IdMapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.client, self.stored, self.count)
+    }
+}
+
+impl FromStr for IdMapping {
+    type Err = FsError;
+
+    /// Parses a mapping written as `client:stored`, or `client:stored:count`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || FsError::InvalidExportConfig(format!("invalid ID mapping: {}", s));
+        let ids = s
+            .trim()
+            .split(':')
+            .map(|id| id.parse::<u32>().map_err(|_| invalid()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match ids[..] {
+            [client, stored] => Self::new(client, stored, 1),
+            [client, stored, count] => Self::new(client, stored, count),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl TryFrom<String> for IdMapping {
+    type Error = FsError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<IdMapping> for String {
+    fn from(mapping: IdMapping) -> Self {
+        mapping.to_string()
+    }
+}
+
+impl TryFrom<String> for ClientNetwork {
+    type Error = FsError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ClientNetwork> for String {
+    fn from(network: ClientNetwork) -> Self {
+        network.to_string()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_config() -> anyhow::Result<()> {
+        let network = "10.0.0.0/8".parse::<ClientNetwork>()?;
+        assert!(network.contains("10.255.0.1".parse()?));
+        assert!(network.contains("::ffff:10.0.0.1".parse()?));
+        assert!(!network.contains("11.0.0.1".parse()?));
+        assert!(!network.contains("fd00::1".parse()?));
+
+        let network = "fd00::/8".parse::<ClientNetwork>()?;
+        assert!(network.contains("fd12::1".parse()?));
+        assert!("0.0.0.0/0"
+            .parse::<ClientNetwork>()?
+            .contains("1.2.3.4".parse()?));
+        assert_eq!("::1".parse::<ClientNetwork>()?.to_string(), "::1/128");
+
+        for invalid in ["10.0.0.0/33", "10.0.0.0/", "localhost", "::/129"] {
+            assert!(invalid.parse::<ClientNetwork>().is_err());
+        }
+
+        // Any client is allowed and every owner is kept unless restricted
+        let config = ExportConfig::default();
+        assert!(!config.is_restricted());
+        assert!(config.is_client_allowed("203.0.113.1".parse()?));
+        assert_eq!((config.squash_uid(0), config.squash_gid(0)), (0, 0));
+
+        let config = ExportConfig::builder()
+            .squash(SquashMode::All)
+            .anon_uid(1000)
+            .anon_gid(100)
+            .build();
+        assert!(config.is_restricted());
+        assert_eq!((config.squash_uid(0), config.squash_gid(5)), (1000, 100));
+
+        // Credentials are mapped before they are squashed
+        let config = ExportConfig::builder()
+            .squash(SquashMode::Root)
+            .uid_map(vec!["1000:501".parse()?, "100000:0:65536".parse()?])
+            .build();
+        assert_eq!(config.map_uid(1000), 501);
+        assert_eq!(config.map_uid(100000), DEFAULT_ANON_UID);
+        assert_eq!(config.map_uid(101000), 1000);
+        assert_eq!(config.map_uid(2000), 2000);
+        assert_eq!(config.map_gid(0), DEFAULT_ANON_GID);
+        assert!(config.validate().is_ok());
+
+        let config = ExportConfig::builder()
+            .uid_map(vec!["0:1000:10".parse()?, "9:2000".parse()?])
+            .build();
+        assert!(config.validate().is_err());
+        for invalid in ["1000", "1000:501:0", "1:4294967295:2", "a:b"] {
+            assert!(invalid.parse::<IdMapping>().is_err());
+        }
+
+        Ok(())
+    }
+}
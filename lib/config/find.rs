@@ -0,0 +1,48 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::filesystem::EntityType;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The types of entities to find with [`find_entries`](crate::management::find_entries).
+///
+/// ## Example
+/// ```
+/// use monofs::{config::FindType, filesystem::EntityType};
+///
+/// assert!(FindType::Symlink.matches(EntityType::SymPathLink));
+/// assert!(!FindType::File.matches(EntityType::Dir));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum FindType {
+    /// Regular files.
+    File,
+
+    /// Directories.
+    Dir,
+
+    /// Symbolic links, to a CID or to a path.
+    Symlink,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FindType {
+    /// Returns whether entities of type `entity_type` are of this type.
+    pub fn matches(&self, entity_type: EntityType) -> bool {
+        match self {
+            FindType::File => entity_type == EntityType::File,
+            FindType::Dir => entity_type == EntityType::Dir,
+            FindType::Symlink => matches!(
+                entity_type,
+                EntityType::SymCidLink | EntityType::SymPathLink
+            ),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use clap::ValueEnum;
+use ipldstore::{
+    codetable::{Code, MultihashDigest},
+    ipld::cid::Cid,
+    Codec,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::FsError;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The hash function the CIDs of the blocks a store writes are made with.
+///
+/// A CID records the hash function it was made with, so blocks made with either can be read by
+/// any store, and a store can hold both. Blake3 is the faster of the two. SHA2-256 is there for
+/// sharing blocks with systems that only understand it. The codec of a CID is not configurable,
+/// as it follows from what the block holds: file contents are raw blocks and every other node is
+/// DAG-CBOR.
+///
+/// ## Example
+/// ```
+/// use ipldstore::Codec;
+/// use monofs::config::HashFunction;
+///
+/// let cid = HashFunction::Sha2_256.generate_cid(Codec::Raw, b"hello");
+///
+/// assert_eq!(HashFunction::of_cid(&cid), Some(HashFunction::Sha2_256));
+/// assert_eq!("blake3".parse::<HashFunction>().unwrap(), HashFunction::Blake3);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+pub enum HashFunction {
+    /// BLAKE3 with a 256-bit digest.
+    #[default]
+    #[serde(rename = "blake3")]
+    #[value(name = "blake3")]
+    Blake3,
+
+    /// SHA2-256.
+    #[serde(rename = "sha2-256")]
+    #[value(name = "sha2-256")]
+    Sha2_256,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl HashFunction {
+    /// Every supported hash function.
+    pub const ALL: [HashFunction; 2] = [HashFunction::Blake3, HashFunction::Sha2_256];
+
+    /// Returns the multihash code of the hash function.
+    pub fn get_code(&self) -> Code {
+        match self {
+            HashFunction::Blake3 => Code::Blake3_256,
+            HashFunction::Sha2_256 => Code::Sha2_256,
+        }
+    }
+
+    /// Returns the hash function the given CID was made with, if it is a supported one.
+    pub fn of_cid(cid: &Cid) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|hash| u64::from(hash.get_code()) == cid.hash().code())
+    }
+
+    /// Returns the CID of a block with the given codec holding `bytes`.
+    pub fn generate_cid(&self, codec: Codec, bytes: &[u8]) -> Cid {
+        match self {
+            HashFunction::Blake3 => ipldstore::generate_cid(codec, bytes),
+            HashFunction::Sha2_256 => Cid::new_v1(codec.into(), Code::Sha2_256.digest(bytes)),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Display for HashFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashFunction::Blake3 => write!(f, "blake3"),
+            HashFunction::Sha2_256 => write!(f, "sha2-256"),
+        }
+    }
+}
+
+impl FromStr for HashFunction {
+    type Err = FsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blake3" => Ok(HashFunction::Blake3),
+            "sha2-256" => Ok(HashFunction::Sha2_256),
+            _ => Err(FsError::UnsupportedHashFunction(s.to_string())),
+        }
+    }
+}
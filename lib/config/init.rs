@@ -0,0 +1,297 @@
+use std::{fmt, ops::RangeInclusive, path::PathBuf, str::FromStr, time::Duration};
+
+use getset::Getters;
+use serde::Deserialize;
+use typed_builder::TypedBuilder;
+
+use crate::FsError;
+
+use super::{
+    AtimePolicy, ChunkerConfig, CompressionConfig, Durability, ExportConfig, HashFunction,
+    MemoryStoreConfig, MountBackend, QuotaConfig, RemoteConfig, RootSubtree, ThrottleConfig,
+    TransferConfig, UserConfig, WriteBackConfig, DEFAULT_MOUNT_TIMEOUT, DEFAULT_NFS_PORT,
+    DEFAULT_NFS_PORT_RANGE_SIZE,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Options for initializing a monofs filesystem.
+///
+/// The mount backend, host, port range, chunker, compression, quota, extra mount options, exports
+/// and throttle can be left unset, in which case [`init_mfs`](crate::management::init_mfs) takes
+/// them from the user's [`UserConfig`], or else uses the built-in defaults.
+///
+/// ## Example
+/// ```
+/// use monofs::config::{InitOptions, PortRange, UserConfig};
+///
+/// let options = InitOptions::builder()
+///     .host(Some("0.0.0.0".into()))
+///     .build();
+///
+/// let defaults = UserConfig::from_toml(r#"port_range = "3000-3010""#).unwrap();
+/// let options = options.with_defaults(&defaults);
+///
+/// assert_eq!(options.get_host().as_deref(), Some("0.0.0.0"));
+/// assert_eq!(options.get_port_range(), &Some(PortRange::new(3000, 3010)));
+/// assert_eq!(options.get_fixed_port(), &None);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, TypedBuilder, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct InitOptions {
+    /// The mechanism used to mount the filesystem, [`MountBackend::get_preferred`] if unset.
+    #[builder(default)]
+    backend: Option<MountBackend>,
+
+    /// The address the NFS server binds to, [`DEFAULT_HOST`](super::DEFAULT_HOST) if unset.
+    #[builder(default)]
+    host: Option<String>,
+
+    /// The ports to search for a free one to serve NFS on, the default [`PortRange`] if unset.
+    #[builder(default)]
+    port_range: Option<PortRange>,
+
+    /// A port to serve NFS on. Takes precedence over `port_range`, and initialization fails if the
+    /// port is not free.
+    #[builder(default)]
+    fixed_port: Option<u32>,
+
+    /// The address the supervisor serves Prometheus metrics on, as `host:port`. The address is
+    /// recorded in the fs database, so the metrics are served again whenever the filesystem is
+    /// attached.
+    #[builder(default)]
+    metrics_addr: Option<String>,
+
+    /// How file contents are split into blocks, the default [`ChunkerConfig`] if unset.
+    #[builder(default)]
+    chunker: Option<ChunkerConfig>,
+
+    /// How blocks are compressed at rest, the default [`CompressionConfig`] if unset.
+    #[builder(default)]
+    compression: Option<CompressionConfig>,
+
+    /// How the server buffers writes before flushing them to the store.
+    #[builder(default)]
+    write_back: WriteBackConfig,
+
+    /// How much data NFS clients move in a single request. Only applies to the NFS backend.
+    #[builder(default)]
+    transfer: TransferConfig,
+
+    /// Options to mount the filesystem with on top of the platform's
+    /// [`NFS_MOUNT_PROFILE`](super::NFS_MOUNT_PROFILE), replacing the options of the same name.
+    /// Only applies to the NFS backend. Taken from the user's [`UserConfig`] if empty.
+    #[builder(default)]
+    extra_mount_options: Vec<String>,
+
+    /// Which NFS clients may use the filesystem, and who they act as, any client acting as
+    /// whoever it says it is if unset. Only applies to the NFS backend.
+    #[builder(default)]
+    exports: Option<ExportConfig>,
+
+    /// How many requests NFS clients may make, and how many bytes they may move, each second,
+    /// unlimited if unset. Only applies to the NFS backend.
+    #[builder(default)]
+    throttle: Option<ThrottleConfig>,
+
+    /// When reads record the time a file was last accessed.
+    #[builder(default)]
+    atime: AtimePolicy,
+
+    /// When the server flushes new block files to disk.
+    #[builder(default)]
+    durability: Durability,
+
+    /// The hash function the CIDs of new blocks are made with. The hash function is recorded in
+    /// the fs database, so it is used whenever the filesystem is attached again, and by its
+    /// clones.
+    #[builder(default)]
+    hash_function: HashFunction,
+
+    /// How much the filesystem may hold, unlimited if unset. The quota is recorded in the fs
+    /// database, so it also applies whenever the filesystem is attached again.
+    #[builder(default)]
+    quota: Option<QuotaConfig>,
+
+    /// The remote store that holds every block, with the local blocks directory as a cache in
+    /// front of it, if any. The remote store is recorded in the fs database, so it is used
+    /// whenever the filesystem is attached again.
+    #[builder(default)]
+    remote: Option<RemoteConfig>,
+
+    /// Keeps the blocks the filesystem writes in memory instead of in the blocks directory, if
+    /// set. The setting is recorded in the fs database, so it applies whenever the filesystem is
+    /// attached again.
+    #[builder(default)]
+    memory: Option<MemoryStoreConfig>,
+
+    /// The directories, e.g. on other disks, that keep a copy of every block file of the
+    /// filesystem, so its blocks survive losing any one disk. Each must be an absolute path
+    /// outside the filesystem's data directory, and empty or missing. The mirrors are recorded in
+    /// the fs database, so they are kept up to date whenever the filesystem is attached again.
+    #[builder(default)]
+    mirrors: Vec<PathBuf>,
+
+    /// The directory served as the root of the mount instead of the whole root, if any. A path is
+    /// recorded in the fs database, so the same subtree is served whenever the filesystem is
+    /// attached again.
+    #[builder(default)]
+    subtree: Option<RootSubtree>,
+
+    /// Whether the server records every change made through the mount in an audit log. The
+    /// setting is recorded in the fs database, so the log is kept whenever the filesystem is
+    /// attached again. See [`audit_log`](crate::management::audit_log).
+    #[builder(default)]
+    audit: bool,
+
+    /// The longest time to wait for the filesystem to be mounted once its supervisor is started,
+    /// after which the supervisor is stopped and initialization fails.
+    #[builder(default = DEFAULT_MOUNT_TIMEOUT)]
+    mount_timeout: Duration,
+}
+
+/// An inclusive range of ports.
+///
+/// Ranges are written as `start-end`, or as a single port, including in configuration files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct PortRange {
+    /// The first port in the range.
+    start: u32,
+
+    /// The last port in the range.
+    end: u32,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl InitOptions {
+    /// Fills in the options that are not set from `defaults`, leaving the ones that are.
+    pub fn with_defaults(mut self, defaults: &UserConfig) -> Self {
+        self.backend = self.backend.or(*defaults.get_backend());
+        self.host = self.host.or_else(|| defaults.get_host().clone());
+        self.port_range = self.port_range.or(*defaults.get_port_range());
+        self.chunker = self.chunker.or(*defaults.get_chunker());
+        self.compression = self.compression.or(*defaults.get_compression());
+        self.quota = self.quota.or(*defaults.get_quota());
+        if self.extra_mount_options.is_empty() {
+            self.extra_mount_options = defaults.get_mount_options().clone().unwrap_or_default();
+        }
+        self.exports = self.exports.or_else(|| defaults.get_exports().clone());
+        self.throttle = self.throttle.or(*defaults.get_throttle());
+        self
+    }
+}
+
+impl PortRange {
+    /// Creates a new range from `start` to `end`, both inclusive.
+    pub fn new(start: u32, end: u32) -> Self {
+        Self { start, end }
+    }
+
+    /// Creates a range that only contains `port`.
+    pub fn single(port: u32) -> Self {
+        Self::new(port, port)
+    }
+
+    /// Returns the first port in the range.
+    pub fn start(&self) -> u32 {
+        self.start
+    }
+
+    /// Returns the last port in the range.
+    pub fn end(&self) -> u32 {
+        self.end
+    }
+
+    /// Returns the ports in the range.
+    pub fn ports(&self) -> RangeInclusive<u32> {
+        self.start..=self.end
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for InitOptions {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Default for PortRange {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_NFS_PORT,
+            DEFAULT_NFS_PORT + DEFAULT_NFS_PORT_RANGE_SIZE - 1,
+        )
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = FsError;
+
+    /// Parses a range written as `start-end`, or a single port.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_port = |port: &str| {
+            port.trim()
+                .parse::<u16>()
+                .map(u32::from)
+                .map_err(|_| FsError::InvalidPortRange(s.to_string()))
+        };
+
+        let range = match s.split_once('-') {
+            Some((start, end)) => Self::new(parse_port(start)?, parse_port(end)?),
+            None => Self::single(parse_port(s)?),
+        };
+
+        if range.start > range.end {
+            return Err(FsError::InvalidPortRange(s.to_string()));
+        }
+
+        Ok(range)
+    }
+}
+
+impl TryFrom<String> for PortRange {
+    type Error = FsError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_port_range_from_str() -> anyhow::Result<()> {
+        assert_eq!(
+            "3000-3010".parse::<PortRange>()?,
+            PortRange::new(3000, 3010)
+        );
+        assert_eq!("3000".parse::<PortRange>()?, PortRange::single(3000));
+
+        assert!("3010-3000".parse::<PortRange>().is_err());
+        assert!("3000-".parse::<PortRange>().is_err());
+        assert!("70000".parse::<PortRange>().is_err());
+
+        Ok(())
+    }
+}
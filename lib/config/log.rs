@@ -0,0 +1,82 @@
+use std::fmt::{self, Display};
+
+use clap::ValueEnum;
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+use super::DEFAULT_TAIL_LINES;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The processes of a mounted filesystem that write logs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogSource {
+    /// Both the server and its supervisor.
+    #[default]
+    All,
+
+    /// The NFS or FUSE server serving the filesystem.
+    Server,
+
+    /// The supervisor that starts the server and restarts it when it fails.
+    Supervisor,
+}
+
+/// Options for reading the logs of a filesystem.
+///
+/// ## Example
+/// ```
+/// use monofs::config::{LogSource, TailOptions};
+///
+/// let options = TailOptions::builder()
+///     .source(LogSource::Server)
+///     .follow(true)
+///     .filter(Some("error".to_string()))
+///     .build();
+///
+/// assert_eq!(options.get_lines(), &10);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, TypedBuilder, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct TailOptions {
+    /// Which processes to read the logs of.
+    #[builder(default)]
+    source: LogSource,
+
+    /// How many of the last lines already in the logs to read.
+    #[builder(default = DEFAULT_TAIL_LINES)]
+    lines: usize,
+
+    /// Whether to keep reading the lines added to the logs, following the logs as they are
+    /// rotated and as the server is restarted.
+    #[builder(default)]
+    follow: bool,
+
+    /// Only read the lines that contain this text.
+    #[builder(default)]
+    filter: Option<String>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for TailOptions {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Display for LogSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogSource::All => write!(f, "all"),
+            LogSource::Server => write!(f, "server"),
+            LogSource::Supervisor => write!(f, "supervisor"),
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+use crate::{FsError, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The default size in bytes a log grows to before it is rotated.
+pub const DEFAULT_LOG_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+/// The default age in seconds a log reaches before it is rotated.
+pub const DEFAULT_LOG_MAX_AGE_SECS: u64 = 24 * 60 * 60;
+
+/// The default number of rotated logs kept.
+pub const DEFAULT_LOG_MAX_FILES: u64 = 10;
+
+/// The default age in seconds after which rotated logs are deleted.
+pub const DEFAULT_LOG_RETENTION_SECS: u64 = 7 * 24 * 60 * 60;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Configures how the logs under the `log` directory of a filesystem are rotated and expired.
+///
+/// The current log of the server is rotated once it holds `max_bytes` bytes, or once it was
+/// written to for `max_age_secs` seconds. Rotated logs, of the server and of the supervisor, are
+/// compressed with gzip if `compress` is set, and deleted once there are more than `max_files` of
+/// them or once they are older than `retention_secs` seconds, oldest first.
+///
+/// ## Example
+/// ```
+/// use monofs::config::LogRotationConfig;
+///
+/// let config = LogRotationConfig::builder()
+///     .max_bytes(1024 * 1024)
+///     .max_age_secs(None)
+///     .build();
+///
+/// assert!(config.validate().is_ok());
+/// assert_eq!(config.get_max_files(), &10);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TypedBuilder, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct LogRotationConfig {
+    /// The size in bytes a log is rotated at.
+    #[builder(default = DEFAULT_LOG_MAX_BYTES)]
+    max_bytes: u64,
+
+    /// The seconds a log is written to before it is rotated, or `None` to only rotate by size.
+    #[builder(default = Some(DEFAULT_LOG_MAX_AGE_SECS))]
+    max_age_secs: Option<u64>,
+
+    /// Whether rotated logs are compressed.
+    #[builder(default = true)]
+    compress: bool,
+
+    /// The number of rotated logs kept.
+    #[builder(default = DEFAULT_LOG_MAX_FILES)]
+    max_files: u64,
+
+    /// The seconds rotated logs are kept, or `None` to keep them until there are too many.
+    #[builder(default = Some(DEFAULT_LOG_RETENTION_SECS))]
+    retention_secs: Option<u64>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl LogRotationConfig {
+    /// Returns how long a log is written to before it is rotated, if it is rotated by age.
+    pub fn get_max_age(&self) -> Option<Duration> {
+        self.max_age_secs.map(Duration::from_secs)
+    }
+
+    /// Returns how long rotated logs are kept, if they expire.
+    pub fn get_retention(&self) -> Option<Duration> {
+        self.retention_secs.map(Duration::from_secs)
+    }
+
+    /// Checks that none of the settings is zero.
+    pub fn validate(&self) -> FsResult<()> {
+        for (name, value) in [
+            ("max_bytes", Some(self.max_bytes)),
+            ("max_age_secs", self.max_age_secs),
+            ("max_files", Some(self.max_files)),
+            ("retention_secs", self.retention_secs),
+        ] {
+            if value == Some(0) {
+                return Err(FsError::InvalidLogRotationConfig(format!(
+                    "{} must be greater than zero",
+                    name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for LogRotationConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
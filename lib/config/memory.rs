@@ -0,0 +1,76 @@
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+use crate::{FsError, FsResult};
+
+use super::DEFAULT_MEMORY_STORE_MAX_BYTES;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Configures a filesystem to keep the blocks it writes in memory instead of in the blocks
+/// directory, for throwaway sandboxes whose contents need not outlive the mount.
+///
+/// New blocks are kept in memory until they take up `max_bytes`, after which writes fail with
+/// `ENOSPC`. Blocks already in the blocks directory, e.g. those of the filesystem a sandbox was
+/// cloned from, are still read from it. Nothing written is logged for recovery after a crash.
+///
+/// When the filesystem is detached, its final root is discarded unless `spill_on_detach` is set,
+/// in which case the blocks reachable from it are written to the blocks directory and the root
+/// is recorded as the filesystem's head, as it would be for a filesystem kept on disk.
+/// Snapshots and pins taken while mounted are not spilled, so they only outlive the mount if the
+/// final root still refers to their blocks.
+///
+/// ## Example
+/// ```
+/// use monofs::config::MemoryStoreConfig;
+///
+/// let config = MemoryStoreConfig::builder()
+///     .max_bytes(64 * 1024 * 1024)
+///     .spill_on_detach(true)
+///     .build();
+///
+/// assert!(config.validate().is_ok());
+/// assert_eq!(*config.get_max_bytes(), 64 * 1024 * 1024);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TypedBuilder, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct MemoryStoreConfig {
+    /// The most bytes of blocks kept in memory.
+    #[builder(default = DEFAULT_MEMORY_STORE_MAX_BYTES)]
+    max_bytes: u64,
+
+    /// Whether the final root is written to the blocks directory when the filesystem is
+    /// detached.
+    #[builder(default)]
+    spill_on_detach: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl MemoryStoreConfig {
+    /// Checks that the store can hold any block.
+    pub fn validate(&self) -> FsResult<()> {
+        if self.max_bytes == 0 {
+            return Err(FsError::InvalidMemoryStoreConfig(
+                "size cap must be greater than zero".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for MemoryStoreConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
@@ -1,9 +1,61 @@
 //! Configuration types and helpers.
 
+mod atime;
+mod backend;
+mod chunker;
+mod compression;
 mod default;
+mod durability;
+mod encryption;
+mod export;
+mod find;
+mod hash;
+mod init;
+mod log;
+mod logrotation;
+mod memory;
+mod mount;
+mod quota;
+mod remote;
+mod retention;
+mod schedule;
+mod scrub;
+mod signing;
+mod subtree;
+mod sync;
+mod throttle;
+mod transfer;
+mod user;
+mod writeback;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
 //--------------------------------------------------------------------------------------------------
 
+pub use atime::*;
+pub use backend::*;
+pub use chunker::*;
+pub use compression::*;
 pub use default::*;
+pub use durability::*;
+pub use encryption::*;
+pub use export::*;
+pub use find::*;
+pub use hash::*;
+pub use init::*;
+pub use log::*;
+pub use logrotation::*;
+pub use memory::*;
+pub use mount::*;
+pub use quota::*;
+pub use remote::*;
+pub use retention::*;
+pub use schedule::*;
+pub use scrub::*;
+pub use signing::*;
+pub use subtree::*;
+pub use sync::*;
+pub use throttle::*;
+pub use transfer::*;
+pub use user::*;
+pub use writeback::*;
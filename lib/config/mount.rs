@@ -0,0 +1,177 @@
+use crate::{utils, FsError, FsResult};
+
+use super::TransferConfig;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The options NFS mounts start from on macOS.
+///
+/// - `nolocks`: disable NFS file locking.
+/// - `vers=3`, `tcp`: speak NFSv3 over TCP, the only protocol the server serves.
+/// - `soft`: return errors rather than hang when the server stops answering.
+/// - `async`: let the client buffer writes instead of committing each one before returning, as
+///   the server flushes its own write buffer, and the filesystem's blocks to disk, on COMMIT.
+#[cfg(target_os = "macos")]
+pub const NFS_MOUNT_PROFILE: &[&str] = &["nolocks", "vers=3", "tcp", "soft", "async"];
+
+/// The options NFS mounts start from on platforms other than macOS.
+///
+/// - `nolock`: disable NFS file locking.
+/// - `vers=3`, `tcp`: speak NFSv3 over TCP, the only protocol the server serves.
+/// - `soft`: return errors rather than hang when the server stops answering.
+#[cfg(not(target_os = "macos"))]
+pub const NFS_MOUNT_PROFILE: &[&str] = &["nolock", "vers=3", "tcp", "soft"];
+
+/// The options NFS mounts need on macOS on top of the profile when the process is not root.
+///
+/// - `noresvport`: connect from an unprivileged port, since only root may bind reserved ones. The
+///   server accepts connections from any port.
+#[cfg(target_os = "macos")]
+pub const NFS_UNPRIVILEGED_MOUNT_OPTIONS: &[&str] = &["noresvport"];
+
+/// The options NFS mounts need on top of the profile when the process is not root.
+///
+/// Only root may mount NFS on platforms other than macOS, so there are none.
+#[cfg(not(target_os = "macos"))]
+pub const NFS_UNPRIVILEGED_MOUNT_OPTIONS: &[&str] = &[];
+
+/// The options that only mean something when the other is not given, in pairs.
+const NFS_EXCLUSIVE_MOUNT_OPTIONS: &[(&str, &str)] =
+    &[("soft", "hard"), ("async", "sync"), ("ro", "rw")];
+
+/// The options that point the client at the server, which nothing may override.
+const NFS_RESERVED_MOUNT_OPTIONS: &[&str] = &["port", "mountport"];
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the options to mount an NFS server listening on `port` with.
+///
+/// The options start from the [`NFS_MOUNT_PROFILE`] of the platform, the port and the transfer
+/// sizes, followed by the [`NFS_UNPRIVILEGED_MOUNT_OPTIONS`] if the process is not root. Each of
+/// `extra_options` then replaces the option of the same name, the option it
+/// negates with a `no` prefix, or the other of a pair like `soft` and `hard`, and is added if
+/// there is none.
+///
+/// ## Examples
+/// ```
+/// use monofs::config::{self, TransferConfig};
+///
+/// let transfer = TransferConfig::builder().rsize(65536).wsize(65536).build();
+/// let extra = ["hard".to_string(), "rsize=32768".to_string(), "actimeo=1".to_string()];
+/// let options = config::get_nfs_mount_options(2049, &transfer, &extra).unwrap();
+///
+/// assert!(options.contains(",hard,"));
+/// assert!(!options.contains("soft"));
+/// assert!(options.contains("rsize=32768,wsize=65536"));
+/// assert!(options.ends_with(",actimeo=1"));
+/// ```
+pub fn get_nfs_mount_options(
+    port: u32,
+    transfer: &TransferConfig,
+    extra_options: &[String],
+) -> FsResult<String> {
+    validate_mount_options(extra_options)?;
+
+    let mut options = NFS_MOUNT_PROFILE
+        .iter()
+        .map(|option| option.to_string())
+        .collect::<Vec<_>>();
+    options.push(format!("port={}", port));
+    options.push(format!("mountport={}", port));
+    options.extend(transfer.to_mount_options().split(',').map(String::from));
+
+    let unprivileged = NFS_UNPRIVILEGED_MOUNT_OPTIONS
+        .iter()
+        .filter(|_| !utils::is_privileged())
+        .map(|option| option.to_string());
+    for extra in unprivileged.chain(extra_options.iter().cloned()) {
+        match options
+            .iter()
+            .position(|option| overrides_mount_option(&extra, option))
+        {
+            Some(index) => options[index] = extra,
+            None => options.push(extra),
+        }
+    }
+
+    Ok(options.join(","))
+}
+
+/// Checks that each of `options` is a single mount option that leaves the port alone.
+pub fn validate_mount_options(options: &[String]) -> FsResult<()> {
+    for option in options {
+        if option.is_empty() || option.contains([',', ' ', '\t', '\n']) {
+            return Err(FsError::InvalidMountOption(format!(
+                "{:?} is not a single mount option",
+                option
+            )));
+        }
+
+        if NFS_RESERVED_MOUNT_OPTIONS.contains(&get_mount_option_name(option)) {
+            return Err(FsError::InvalidMountOption(format!(
+                "{} is chosen by monofs and cannot be overridden",
+                option
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the name of a mount option, without its value.
+fn get_mount_option_name(option: &str) -> &str {
+    option.split_once('=').map_or(option, |(name, _)| name)
+}
+
+/// Returns whether `extra` takes the place of `option` rather than being added next to it.
+fn overrides_mount_option(extra: &str, option: &str) -> bool {
+    let (extra, option) = (get_mount_option_name(extra), get_mount_option_name(option));
+    extra == option
+        || extra.strip_prefix("no") == Some(option)
+        || option.strip_prefix("no") == Some(extra)
+        || NFS_EXCLUSIVE_MOUNT_OPTIONS
+            .iter()
+            .any(|(a, b)| (extra, option) == (a, b) || (extra, option) == (b, a))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_nfs_mount_options() -> anyhow::Result<()> {
+        let transfer = TransferConfig::default();
+        let options = get_nfs_mount_options(3000, &transfer, &[])?;
+        assert!(options.starts_with(&NFS_MOUNT_PROFILE.join(",")));
+        assert!(options.contains("port=3000,mountport=3000"));
+
+        // Overrides take the place of what they replace and the rest is added at the end
+        let extra = ["hard", "vers=4", "noac"].map(String::from);
+        let options = get_nfs_mount_options(3000, &transfer, &extra)?;
+        let options = options.split(',').collect::<Vec<_>>();
+        assert!(options.contains(&"hard") && !options.contains(&"soft"));
+        assert_eq!(options.iter().filter(|o| o.starts_with("vers=")).count(), 1);
+        assert_eq!(options.last(), Some(&"noac"));
+
+        for invalid in ["", "soft,hard", "port=2049", "mountport=2049"] {
+            assert!(matches!(
+                get_nfs_mount_options(3000, &transfer, &[invalid.to_string()]),
+                Err(FsError::InvalidMountOption(_))
+            ));
+        }
+
+        Ok(())
+    }
+}
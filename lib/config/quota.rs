@@ -0,0 +1,59 @@
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Limits how much a filesystem may hold.
+///
+/// The logical limit caps the total size of the files as clients see them, however well they
+/// deduplicate or compress. Writes that would take the files past it fail with `EDQUOT`.
+///
+/// The store limit caps the bytes the filesystem's blocks take up on the host disk. It is checked
+/// against an estimate before the data is stored, so the store may end up slightly past it, but
+/// once it is reached writes and new entries fail with `ENOSPC`. Blocks only leave the store
+/// through garbage collection, so removing files does not free up room under this limit until
+/// the store is collected and the filesystem is served again.
+///
+/// ## Example
+/// ```
+/// use monofs::config::QuotaConfig;
+///
+/// let config = QuotaConfig::builder()
+///     .max_logical_bytes(Some(1 << 30))
+///     .build();
+///
+/// assert!(config.is_limited());
+/// assert!(!QuotaConfig::unlimited().is_limited());
+/// ```
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TypedBuilder, Getters,
+)]
+#[getset(get = "pub with_prefix")]
+pub struct QuotaConfig {
+    /// The most bytes the files of the filesystem may take up in total, if limited.
+    #[builder(default)]
+    max_logical_bytes: Option<u64>,
+
+    /// The most bytes the blocks of the filesystem may take up on disk, if limited.
+    #[builder(default)]
+    max_store_bytes: Option<u64>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl QuotaConfig {
+    /// Creates a configuration that does not limit the filesystem.
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether either limit is set.
+    pub fn is_limited(&self) -> bool {
+        self.max_logical_bytes.is_some() || self.max_store_bytes.is_some()
+    }
+}
@@ -0,0 +1,204 @@
+use std::{fmt, path::PathBuf, str::FromStr};
+
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+use crate::{FsError, FsResult};
+
+use super::DEFAULT_REMOTE_CACHE_MAX_BYTES;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The URL scheme of remote stores in a directory of the host.
+const FILE_SCHEME: &str = "file://";
+
+/// The URL scheme of remote stores in an S3 bucket.
+const S3_SCHEME: &str = "s3://";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Configures a remote store that holds every block of a filesystem, with the local blocks
+/// directory as a cache in front of it.
+///
+/// Blocks are written to the remote store as they are written locally, so the remote store holds
+/// the whole filesystem at all times. The local copies are evicted, least recently used first,
+/// once they take up more than `cache_max_bytes`, and fetched back from the remote store when
+/// they are read again. This lets a filesystem whose image lives in object storage be served from
+/// a small local disk. Garbage collection only removes the local copies of blocks, so the remote
+/// store keeps every block it was given.
+///
+/// The remote store is given as a URL:
+/// - `file:///path/to/dir` keeps blocks in a directory of the host, e.g. a network share.
+/// - `s3://bucket/prefix` keeps blocks in an S3 bucket, under an optional prefix. Credentials,
+///   region and endpoint are read from the standard `AWS_*` environment variables. Only
+///   available when monofs is built with the `s3` feature.
+///
+/// ## Example
+/// ```
+/// use monofs::config::{RemoteConfig, RemoteLocation};
+///
+/// let config = RemoteConfig::builder()
+///     .url("s3://images/sandbox")
+///     .cache_max_bytes(256 * 1024 * 1024)
+///     .build();
+///
+/// assert_eq!(
+///     config.get_location()?,
+///     RemoteLocation::S3 {
+///         bucket: "images".to_string(),
+///         prefix: "sandbox".to_string(),
+///     }
+/// );
+/// # Ok::<(), monofs::FsError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TypedBuilder, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct RemoteConfig {
+    /// The URL of the remote store.
+    #[builder(setter(into))]
+    url: String,
+
+    /// The most bytes of blocks kept on local disk.
+    #[builder(default = DEFAULT_REMOTE_CACHE_MAX_BYTES)]
+    cache_max_bytes: u64,
+}
+
+/// Where a remote store keeps its blocks, as given by the URL of a [`RemoteConfig`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteLocation {
+    /// A directory of the host.
+    Dir(PathBuf),
+
+    /// An S3 bucket.
+    S3 {
+        /// The name of the bucket.
+        bucket: String,
+
+        /// The prefix of the keys of the blocks in the bucket, without leading or trailing
+        /// slashes. Empty if blocks are kept at the top of the bucket.
+        prefix: String,
+    },
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl RemoteConfig {
+    /// Returns where the remote store keeps its blocks.
+    pub fn get_location(&self) -> FsResult<RemoteLocation> {
+        self.url.parse()
+    }
+
+    /// Checks that the URL is supported and that the cache can hold any block.
+    pub fn validate(&self) -> FsResult<()> {
+        self.get_location()?;
+        if self.cache_max_bytes == 0 {
+            return Err(FsError::InvalidRemoteConfig(
+                "cache size must be greater than zero".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl FromStr for RemoteLocation {
+    type Err = FsError;
+
+    /// Parses a `file:///path` or `s3://bucket/prefix` URL.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix(FILE_SCHEME) {
+            if !path.starts_with('/') {
+                return Err(FsError::InvalidRemoteConfig(format!(
+                    "remote directory must be an absolute path: {}",
+                    s
+                )));
+            }
+
+            return Ok(RemoteLocation::Dir(PathBuf::from(path)));
+        }
+
+        if let Some(rest) = s.strip_prefix(S3_SCHEME) {
+            let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+            if bucket.is_empty() {
+                return Err(FsError::InvalidRemoteConfig(format!(
+                    "missing bucket name: {}",
+                    s
+                )));
+            }
+
+            return Ok(RemoteLocation::S3 {
+                bucket: bucket.to_string(),
+                prefix: prefix.trim_matches('/').to_string(),
+            });
+        }
+
+        Err(FsError::InvalidRemoteConfig(format!(
+            "unsupported remote store URL, expected {}<path> or {}<bucket>[/<prefix>]: {}",
+            FILE_SCHEME, S3_SCHEME, s
+        )))
+    }
+}
+
+impl fmt::Display for RemoteLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemoteLocation::Dir(path) => write!(f, "{}{}", FILE_SCHEME, path.display()),
+            RemoteLocation::S3 { bucket, prefix } if prefix.is_empty() => {
+                write!(f, "{}{}", S3_SCHEME, bucket)
+            }
+            RemoteLocation::S3 { bucket, prefix } => {
+                write!(f, "{}{}/{}", S3_SCHEME, bucket, prefix)
+            }
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remote_location_from_str() -> anyhow::Result<()> {
+        assert_eq!(
+            "file:///mnt/share/blocks".parse::<RemoteLocation>()?,
+            RemoteLocation::Dir(PathBuf::from("/mnt/share/blocks"))
+        );
+        assert_eq!(
+            "s3://images".parse::<RemoteLocation>()?,
+            RemoteLocation::S3 {
+                bucket: "images".to_string(),
+                prefix: String::new(),
+            }
+        );
+        assert_eq!(
+            "s3://images/sandbox/base/".parse::<RemoteLocation>()?,
+            RemoteLocation::S3 {
+                bucket: "images".to_string(),
+                prefix: "sandbox/base".to_string(),
+            }
+        );
+
+        assert!("file://relative/path".parse::<RemoteLocation>().is_err());
+        assert!("s3:///prefix".parse::<RemoteLocation>().is_err());
+        assert!("https://example.com/blocks"
+            .parse::<RemoteLocation>()
+            .is_err());
+
+        Ok(())
+    }
+}
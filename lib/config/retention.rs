@@ -0,0 +1,148 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Datelike, Utc};
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+use crate::{FsError, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Configures which snapshots of a filesystem are kept when its snapshots are pruned.
+///
+/// Snapshots are kept the way backup tools keep theirs: the newest `keep_last`, the newest of
+/// each of the last `keep_daily` days and the newest of each of the last `keep_weekly` weeks, by
+/// UTC. A snapshot kept by any of these is kept. With `scheduled_only` set, only scheduled
+/// snapshots are considered, and those taken by hand are neither deleted nor counted.
+///
+/// ## Example
+/// ```
+/// use monofs::config::SnapshotRetention;
+///
+/// let policy = SnapshotRetention::builder()
+///     .keep_last(Some(5))
+///     .keep_weekly(Some(4))
+///     .build();
+///
+/// assert!(policy.validate().is_ok());
+/// ```
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TypedBuilder, Getters,
+)]
+#[getset(get = "pub with_prefix")]
+pub struct SnapshotRetention {
+    /// How many of the newest snapshots to keep.
+    #[builder(default)]
+    keep_last: Option<u32>,
+
+    /// How many days to keep the newest snapshot of.
+    #[builder(default)]
+    keep_daily: Option<u32>,
+
+    /// How many weeks to keep the newest snapshot of.
+    #[builder(default)]
+    keep_weekly: Option<u32>,
+
+    /// Whether only scheduled snapshots are pruned.
+    #[builder(default)]
+    scheduled_only: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl SnapshotRetention {
+    /// Checks that the policy keeps something, so pruning never deletes every snapshot, and that
+    /// none of its settings is zero.
+    pub fn validate(&self) -> FsResult<()> {
+        if self.keep_last.is_none() && self.keep_daily.is_none() && self.keep_weekly.is_none() {
+            return Err(FsError::InvalidSnapshotRetention(
+                "keep_last, keep_daily or keep_weekly must be set".to_string(),
+            ));
+        }
+
+        for (name, value) in [
+            ("keep_last", self.keep_last),
+            ("keep_daily", self.keep_daily),
+            ("keep_weekly", self.keep_weekly),
+        ] {
+            if value == Some(0) {
+                return Err(FsError::InvalidSnapshotRetention(format!(
+                    "{} must be greater than zero",
+                    name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns which of the snapshots taken at `taken_at`, newest first, the policy keeps.
+    pub fn select(&self, taken_at: &[DateTime<Utc>]) -> Vec<bool> {
+        let keep_last = self.keep_last.unwrap_or(0) as usize;
+        let keep_daily = self.keep_daily.unwrap_or(0) as usize;
+        let keep_weekly = self.keep_weekly.unwrap_or(0) as usize;
+        let mut days = HashSet::new();
+        let mut weeks = HashSet::new();
+
+        taken_at
+            .iter()
+            .enumerate()
+            .map(|(index, at)| {
+                // The newest snapshot of a day or week is the first one seen of it
+                let last = index < keep_last;
+                let daily = days.len() < keep_daily && days.insert(at.date_naive());
+                let week = at.iso_week();
+                let weekly = weeks.len() < keep_weekly && weeks.insert((week.year(), week.week()));
+                last || daily || weekly
+            })
+            .collect()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_snapshot_retention_select() {
+        assert!(SnapshotRetention::default().validate().is_err());
+        let invalid = SnapshotRetention::builder().keep_daily(Some(0)).build();
+        assert!(invalid.validate().is_err());
+
+        // The newest snapshot of each day and week is kept as far back as asked
+        let taken_at = [
+            Utc.with_ymd_and_hms(2025, 3, 12, 18, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2025, 3, 12, 6, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2025, 3, 11, 18, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2025, 3, 10, 18, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2025, 3, 5, 18, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2025, 2, 26, 18, 0, 0).unwrap(),
+        ];
+        let policy = SnapshotRetention::builder()
+            .keep_daily(Some(2))
+            .keep_weekly(Some(2))
+            .build();
+        assert!(policy.validate().is_ok());
+        assert_eq!(
+            policy.select(&taken_at),
+            vec![true, false, true, false, true, false]
+        );
+
+        let policy = SnapshotRetention::builder().keep_last(Some(3)).build();
+        assert_eq!(
+            policy.select(&taken_at),
+            vec![true, true, true, false, false, false]
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+use crate::{config::SnapshotRetention, FsError, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Configures the snapshots the supervisor of a filesystem takes on its own, and how many of
+/// them it keeps.
+///
+/// A snapshot is taken once `interval_secs` have passed since the last scheduled one, or once
+/// clients have made `every_changes` changes since, whichever comes first. No snapshot is taken
+/// while the root is the same as in the last one.
+///
+/// Scheduled snapshots are kept by the [`SnapshotRetention`] made of `keep_last`, `keep_daily` and
+/// `keep_weekly`. The others are deleted after every new snapshot, along with the blocks only
+/// they referred to. Without any of these set, every scheduled snapshot is kept. Snapshots taken
+/// by hand are never deleted.
+///
+/// ## Example
+/// ```
+/// use monofs::config::SnapshotScheduleConfig;
+///
+/// let config = SnapshotScheduleConfig::builder()
+///     .interval_secs(Some(3600))
+///     .keep_last(Some(24))
+///     .keep_daily(Some(7))
+///     .build();
+///
+/// assert!(config.validate().is_ok());
+/// assert!(config.is_pruned());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TypedBuilder, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct SnapshotScheduleConfig {
+    /// The seconds between snapshots, if snapshots are taken on an interval.
+    #[builder(default)]
+    interval_secs: Option<u64>,
+
+    /// The changes clients make between snapshots, if snapshots are taken after changes.
+    #[builder(default)]
+    every_changes: Option<u64>,
+
+    /// How many of the newest scheduled snapshots to keep.
+    #[builder(default)]
+    keep_last: Option<u32>,
+
+    /// How many days to keep the newest scheduled snapshot of.
+    #[builder(default)]
+    keep_daily: Option<u32>,
+
+    /// How many weeks to keep the newest scheduled snapshot of.
+    #[builder(default)]
+    keep_weekly: Option<u32>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl SnapshotScheduleConfig {
+    /// Returns the time between snapshots, if snapshots are taken on an interval.
+    pub fn get_interval(&self) -> Option<Duration> {
+        self.interval_secs.map(Duration::from_secs)
+    }
+
+    /// Returns whether any scheduled snapshots are ever deleted.
+    pub fn is_pruned(&self) -> bool {
+        self.keep_last.is_some() || self.keep_daily.is_some() || self.keep_weekly.is_some()
+    }
+
+    /// Returns the retention policy of the scheduled snapshots, or `None` if they are all kept.
+    pub fn get_retention(&self) -> Option<SnapshotRetention> {
+        self.is_pruned().then(|| {
+            SnapshotRetention::builder()
+                .keep_last(self.keep_last)
+                .keep_daily(self.keep_daily)
+                .keep_weekly(self.keep_weekly)
+                .scheduled_only(true)
+                .build()
+        })
+    }
+
+    /// Checks that the schedule takes snapshots at all, and that none of its settings is zero.
+    pub fn validate(&self) -> FsResult<()> {
+        if self.interval_secs.is_none() && self.every_changes.is_none() {
+            return Err(FsError::InvalidSnapshotSchedule(
+                "interval_secs or every_changes must be set".to_string(),
+            ));
+        }
+
+        for (name, value) in [
+            ("interval_secs", self.interval_secs),
+            ("every_changes", self.every_changes),
+            ("keep_last", self.keep_last.map(u64::from)),
+            ("keep_daily", self.keep_daily.map(u64::from)),
+            ("keep_weekly", self.keep_weekly.map(u64::from)),
+        ] {
+            if value == Some(0) {
+                return Err(FsError::InvalidSnapshotSchedule(format!(
+                    "{} must be greater than zero",
+                    name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_schedule_config_validate() {
+        let config = SnapshotScheduleConfig::builder()
+            .every_changes(Some(100))
+            .build();
+        assert!(config.validate().is_ok());
+        assert!(!config.is_pruned());
+        assert_eq!(config.get_interval(), None);
+
+        // A schedule has to take snapshots
+        let config = SnapshotScheduleConfig::builder().keep_last(Some(5)).build();
+        assert!(config.validate().is_err());
+
+        let config = SnapshotScheduleConfig::builder()
+            .interval_secs(Some(0))
+            .build();
+        assert!(config.validate().is_err());
+
+        let config = SnapshotScheduleConfig::builder()
+            .interval_secs(Some(60))
+            .keep_weekly(Some(0))
+            .build();
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+use crate::{FsError, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The default seconds between the batches of blocks the scrubber checks.
+pub const DEFAULT_SCRUB_INTERVAL_SECS: u64 = 60;
+
+/// The default number of blocks the scrubber checks in each batch.
+pub const DEFAULT_SCRUB_BATCH_BLOCKS: u64 = 256;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Configures how the supervisor of a filesystem scrubs its blocks in the background.
+///
+/// Every `interval_secs`, the scrubber re-hashes the next `batch_blocks` block files, in the
+/// order of their digests, and starts over from the first one once it has checked them all. So
+/// a store of `n` block files is checked in full about every `n / batch_blocks * interval_secs`
+/// seconds, at a steady cost that does not disturb the clients of the filesystem.
+///
+/// ## Example
+/// ```
+/// use monofs::config::ScrubConfig;
+///
+/// let config = ScrubConfig::builder().batch_blocks(1024).build();
+///
+/// assert!(config.validate().is_ok());
+/// assert_eq!(config.get_interval().as_secs(), 60);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TypedBuilder, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct ScrubConfig {
+    /// The seconds between batches.
+    #[builder(default = DEFAULT_SCRUB_INTERVAL_SECS)]
+    interval_secs: u64,
+
+    /// The number of block files checked in each batch.
+    #[builder(default = DEFAULT_SCRUB_BATCH_BLOCKS)]
+    batch_blocks: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ScrubConfig {
+    /// Returns the time between batches.
+    pub fn get_interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+
+    /// Checks that none of the settings is zero.
+    pub fn validate(&self) -> FsResult<()> {
+        for (name, value) in [
+            ("interval_secs", self.interval_secs),
+            ("batch_blocks", self.batch_blocks),
+        ] {
+            if value == 0 {
+                return Err(FsError::InvalidScrubConfig(format!(
+                    "{} must be greater than zero",
+                    name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for ScrubConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
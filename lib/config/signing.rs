@@ -0,0 +1,229 @@
+use std::{env, fmt, fs, path::Path, str::FromStr};
+
+use chacha20poly1305::aead::OsRng;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey, SECRET_KEY_LENGTH};
+use ipldstore::ipld::cid::Cid;
+
+use crate::{
+    utils::{SIGNING_KEY_ENV_VAR, SIGNING_KEY_FILE_ENV_VAR},
+    FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// What the messages signing a root start with, so that a signature over a root cannot be passed
+/// off as one over anything else.
+const ROOT_SIGNATURE_CONTEXT: &[u8] = b"monofs-root-v1:";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A key that signs the roots a filesystem records, proving who produced them.
+///
+/// Every root recorded in a filesystem's history while a key is given is signed with Ed25519, and
+/// the signature is kept next to it. Anyone holding the [`RootPublicKey`] of the key can then
+/// check that a root, and in particular the current head, was produced by the owner of the key,
+/// which a root pointer changed in the fs database cannot fake.
+///
+/// Keys are 32 bytes, written as 64 hex characters, and are usually taken from the environment
+/// with [`from_env`](Self::from_env). The key is never printed, not even in debug output.
+///
+/// ## Example
+/// ```
+/// use monofs::config::RootSigningKey;
+///
+/// let key = RootSigningKey::generate();
+/// let root = "bafyreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku".parse()?;
+/// let signature = key.sign(&root);
+///
+/// assert!(key.get_public_key().verify(&root, &signature).is_ok());
+/// assert_eq!(format!("{:?}", key), "RootSigningKey(..)");
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct RootSigningKey(SigningKey);
+
+/// The public half of a [`RootSigningKey`], which verifies the roots it signed.
+///
+/// Public keys are written as 64 hex characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RootPublicKey(VerifyingKey);
+
+/// The signature of a root by a [`RootSigningKey`], written as 128 hex characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RootSignature(Signature);
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl RootSigningKey {
+    /// Creates a new random key.
+    pub fn generate() -> Self {
+        Self(SigningKey::generate(&mut OsRng))
+    }
+
+    /// Parses a key written as 64 hex characters.
+    pub fn from_hex(hex: &str) -> FsResult<Self> {
+        let bytes = hex::decode(hex.trim())
+            .map_err(|_| FsError::InvalidSigningKey("key must be written in hex".to_string()))?;
+
+        let bytes = <[u8; SECRET_KEY_LENGTH]>::try_from(bytes).map_err(|bytes| {
+            FsError::InvalidSigningKey(format!(
+                "key must be {} bytes, got {}",
+                SECRET_KEY_LENGTH,
+                bytes.len()
+            ))
+        })?;
+
+        Ok(Self(SigningKey::from_bytes(&bytes)))
+    }
+
+    /// Reads a key from the file at `path`, which holds the key as 64 hex characters.
+    pub fn from_file(path: impl AsRef<Path>) -> FsResult<Self> {
+        let path = path.as_ref();
+        let hex = fs::read_to_string(path).map_err(|e| {
+            FsError::InvalidSigningKey(format!("failed to read {}: {}", path.display(), e))
+        })?;
+
+        Self::from_hex(&hex)
+    }
+
+    /// Gets the key from the environment, if one is given.
+    ///
+    /// The key is taken from the `MONOFS_SIGNING_KEY` environment variable, or else read from the
+    /// file named by the `MONOFS_SIGNING_KEY_FILE` environment variable.
+    pub fn from_env() -> FsResult<Option<Self>> {
+        if let Ok(hex) = env::var(SIGNING_KEY_ENV_VAR) {
+            return Self::from_hex(&hex).map(Some);
+        }
+
+        match env::var_os(SIGNING_KEY_FILE_ENV_VAR) {
+            Some(path) => Self::from_file(path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the key written as 64 hex characters.
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0.to_bytes())
+    }
+
+    /// Returns the public key that verifies the roots this key signs.
+    pub fn get_public_key(&self) -> RootPublicKey {
+        RootPublicKey(self.0.verifying_key())
+    }
+
+    /// Signs the root at `root`.
+    pub fn sign(&self, root: &Cid) -> RootSignature {
+        RootSignature(self.0.sign(&get_root_message(root)))
+    }
+}
+
+impl RootPublicKey {
+    /// Checks that `signature` is the signature of the root at `root` by the key this is the
+    /// public key of.
+    pub fn verify(&self, root: &Cid, signature: &RootSignature) -> FsResult<()> {
+        self.0
+            .verify_strict(&get_root_message(root), &signature.0)
+            .map_err(|_| {
+                FsError::RootSignatureInvalid(format!("{} was not signed by {}", root, self))
+            })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the message signed for the root at `root`.
+fn get_root_message(root: &Cid) -> Vec<u8> {
+    [ROOT_SIGNATURE_CONTEXT, &root.to_bytes()].concat()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Debug for RootSigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RootSigningKey(..)")
+    }
+}
+
+impl fmt::Display for RootPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0.as_bytes()))
+    }
+}
+
+impl FromStr for RootPublicKey {
+    type Err = FsError;
+
+    /// Parses a public key written as 64 hex characters.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || FsError::InvalidSigningKey(format!("invalid public key: {}", s));
+        let bytes = hex::decode(s.trim()).map_err(|_| invalid())?;
+        let bytes = <[u8; 32]>::try_from(bytes).map_err(|_| invalid())?;
+
+        VerifyingKey::from_bytes(&bytes)
+            .map(Self)
+            .map_err(|_| invalid())
+    }
+}
+
+impl fmt::Display for RootSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0.to_bytes()))
+    }
+}
+
+impl FromStr for RootSignature {
+    type Err = FsError;
+
+    /// Parses a signature written as 128 hex characters.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || FsError::RootSignatureInvalid(format!("malformed signature: {}", s));
+        let bytes = hex::decode(s.trim()).map_err(|_| invalid())?;
+
+        Signature::from_slice(&bytes)
+            .map(Self)
+            .map_err(|_| invalid())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root_signing_key() -> anyhow::Result<()> {
+        let key = RootSigningKey::generate();
+        assert_eq!(RootSigningKey::from_hex(&key.to_hex())?, key);
+        assert!(RootSigningKey::from_hex("abcd").is_err());
+
+        // Public keys and signatures survive being written out
+        let public_key = key.get_public_key();
+        let root = "bafyreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku".parse::<Cid>()?;
+        let signature = key.sign(&root);
+        let public_key = public_key.to_string().parse::<RootPublicKey>()?;
+        let signature = signature.to_string().parse::<RootSignature>()?;
+        public_key.verify(&root, &signature)?;
+
+        // Signatures hold for one root and one key only
+        let other_root =
+            "bafkreigh2akiscaildcqabsyg3dfr6chu3fgpregiymsck7e7aqa4s52zy".parse::<Cid>()?;
+        assert!(public_key.verify(&other_root, &signature).is_err());
+        let other_key = RootSigningKey::generate().get_public_key();
+        assert!(other_key.verify(&root, &signature).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use std::{convert::Infallible, fmt, str::FromStr};
+
+use ipldstore::ipld::cid::Cid;
+use serde::{Deserialize, Serialize};
+use typed_path::{Utf8UnixComponent, Utf8UnixPath};
+
+use crate::{FsError, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The directory a filesystem serves as the root of its mount, instead of its whole root.
+///
+/// This confines the sandbox using a mount to part of the data, like `chroot` does for a
+/// process. Different sandboxes can then be served different subtrees of the same store.
+///
+/// ## Example
+/// ```
+/// use monofs::config::RootSubtree;
+///
+/// let subtree: RootSubtree = "/home/alice".parse()?;
+/// assert_eq!(subtree, RootSubtree::Path("/home/alice".to_string()));
+/// assert!(subtree.validate().is_ok());
+///
+/// let subtree: RootSubtree = "/home/../etc".parse()?;
+/// assert!(subtree.validate().is_err());
+/// # Ok::<(), std::convert::Infallible>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RootSubtree {
+    /// The directory stored at a CID, such as a directory of a snapshot. The filesystem starts
+    /// out with it as its root, and changes from there.
+    Cid(Cid),
+
+    /// A directory in the filesystem's root, created if it does not exist yet. The rest of the
+    /// root is kept as it is, and changes made through the mount are written back under the
+    /// path.
+    Path(String),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl RootSubtree {
+    /// Checks that a path only names directories below the root.
+    pub fn validate(&self) -> FsResult<()> {
+        let Self::Path(path) = self else {
+            return Ok(());
+        };
+
+        let mut components = Utf8UnixPath::new(path)
+            .components()
+            .filter(|component| !matches!(component, Utf8UnixComponent::RootDir))
+            .peekable();
+        if components.peek().is_none() {
+            return Err(FsError::InvalidSubtree(
+                "path must name a directory below the root".to_string(),
+            ));
+        }
+
+        if components.any(|component| !matches!(component, Utf8UnixComponent::Normal(_))) {
+            return Err(FsError::InvalidSubtree(format!(
+                "path must not contain `..`: {}",
+                path
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl FromStr for RootSubtree {
+    type Err = Infallible;
+
+    /// Parses a CID, or otherwise a path in the root.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match Cid::try_from(s) {
+            Ok(cid) => Self::Cid(cid),
+            Err(_) => Self::Path(s.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for RootSubtree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cid(cid) => write!(f, "{}", cid),
+            Self::Path(path) => write!(f, "{}", path),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::codetable::{Code, MultihashDigest};
+
+    use super::*;
+
+    #[test]
+    fn test_root_subtree_validate() -> anyhow::Result<()> {
+        assert!(RootSubtree::Path("home/alice".to_string())
+            .validate()
+            .is_ok());
+        assert!(RootSubtree::Path("/home/alice/".to_string())
+            .validate()
+            .is_ok());
+
+        assert!(RootSubtree::Path("/".to_string()).validate().is_err());
+        assert!(RootSubtree::Path(String::new()).validate().is_err());
+        assert!(RootSubtree::Path("../home".to_string()).validate().is_err());
+        assert!(RootSubtree::Path("home/../etc".to_string())
+            .validate()
+            .is_err());
+
+        let cid = Cid::new_v1(0x71, Code::Blake3_256.digest(b"subtree"));
+        let subtree: RootSubtree = cid.to_string().parse()?;
+        assert_eq!(subtree, RootSubtree::Cid(cid));
+        assert!(subtree.validate().is_ok());
+
+        Ok(())
+    }
+}
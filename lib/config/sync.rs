@@ -0,0 +1,25 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Which way [`sync_dir`](crate::management::sync_dir) copies changes.
+///
+/// ## Example
+/// ```
+/// use clap::ValueEnum;
+/// use monofs::config::SyncDirection;
+///
+/// assert_eq!(SyncDirection::from_str("pull", false), Ok(SyncDirection::Pull));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncDirection {
+    /// From the host directory into the filesystem.
+    Push,
+
+    /// From the filesystem into the host directory.
+    Pull,
+}
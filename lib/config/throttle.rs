@@ -0,0 +1,131 @@
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+use crate::{FsError, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Limits how many NFS requests a filesystem serves, and how many bytes it moves for them, each
+/// second.
+///
+/// The global limits apply to every client connection of the server together, including the
+/// filesystem's own mount and the mounts sharing its server, so a runaway client cannot saturate
+/// the host disk. The client limits apply to each connection on its own, so one client cannot
+/// starve the others of the global budget.
+///
+/// Every request counts as one operation, and the bytes of both the request and its reply count
+/// towards the bandwidth, so a READ counts the data it returns and a WRITE the data it sends.
+/// A request past a limit is not refused but delayed until the limit allows it, and requests
+/// larger than a second's worth of bandwidth are let through once the limit catches up with them.
+///
+/// ## Example
+/// ```
+/// use monofs::config::ThrottleConfig;
+///
+/// let config = ThrottleConfig::builder()
+///     .max_bytes_per_sec(Some(100 * 1024 * 1024))
+///     .client_max_ops_per_sec(Some(1000))
+///     .build();
+///
+/// assert!(config.validate().is_ok());
+/// assert!(config.is_limited());
+/// assert!(!ThrottleConfig::unlimited().is_limited());
+/// ```
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TypedBuilder, Getters,
+)]
+#[serde(default, deny_unknown_fields)]
+#[getset(get = "pub with_prefix")]
+pub struct ThrottleConfig {
+    /// The most requests served each second across all clients, if limited.
+    #[builder(default)]
+    max_ops_per_sec: Option<u32>,
+
+    /// The most bytes moved each second across all clients, if limited.
+    #[builder(default)]
+    max_bytes_per_sec: Option<u64>,
+
+    /// The most requests served each second to a single client connection, if limited.
+    #[builder(default)]
+    client_max_ops_per_sec: Option<u32>,
+
+    /// The most bytes moved each second for a single client connection, if limited.
+    #[builder(default)]
+    client_max_bytes_per_sec: Option<u64>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ThrottleConfig {
+    /// Creates a configuration that does not limit clients.
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether any limit is set.
+    pub fn is_limited(&self) -> bool {
+        self.max_ops_per_sec.is_some()
+            || self.max_bytes_per_sec.is_some()
+            || self.client_max_ops_per_sec.is_some()
+            || self.client_max_bytes_per_sec.is_some()
+    }
+
+    /// Checks that no limit is zero, which would never let a request through.
+    pub fn validate(&self) -> FsResult<()> {
+        for (name, limit) in [
+            ("max_ops_per_sec", self.max_ops_per_sec.map(u64::from)),
+            ("max_bytes_per_sec", self.max_bytes_per_sec),
+            (
+                "client_max_ops_per_sec",
+                self.client_max_ops_per_sec.map(u64::from),
+            ),
+            ("client_max_bytes_per_sec", self.client_max_bytes_per_sec),
+        ] {
+            if limit == Some(0) {
+                return Err(FsError::InvalidThrottleConfig(format!(
+                    "{} must be greater than zero",
+                    name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throttle_config_validate() {
+        assert!(ThrottleConfig::unlimited().validate().is_ok());
+
+        let config = ThrottleConfig::builder()
+            .max_ops_per_sec(Some(100))
+            .client_max_bytes_per_sec(Some(1024))
+            .build();
+        assert!(config.validate().is_ok());
+
+        let config = ThrottleConfig::builder().max_bytes_per_sec(Some(0)).build();
+        assert!(config.validate().is_err());
+
+        let config = ThrottleConfig::builder()
+            .client_max_ops_per_sec(Some(0))
+            .build();
+        assert!(config.validate().is_err());
+
+        let config: ThrottleConfig = toml::from_str("client_max_bytes_per_sec = 1048576").unwrap();
+        assert_eq!(config.get_client_max_bytes_per_sec(), &Some(1048576));
+        assert!(config.is_limited());
+    }
+}
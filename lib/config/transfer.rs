@@ -0,0 +1,126 @@
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+use crate::{FsError, FsResult};
+
+use super::{DEFAULT_NFS_DTPREF, DEFAULT_NFS_RSIZE, DEFAULT_NFS_WSIZE};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The size every NFS transfer size must be a multiple of.
+pub const NFS_TRANSFER_SIZE_MULTIPLE: u32 = 4 * 1024;
+
+/// The largest NFS transfer size, which is also the largest READ and WRITE NFS clients support.
+pub const NFS_MAX_TRANSFER_SIZE: u32 = 1024 * 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Configures how much data NFS clients move in a single request.
+///
+/// The NFS server advertises the sizes in its FSINFO reply as the largest and preferred sizes of
+/// a READ, a WRITE and a READDIR, and refuses to serve more than that in a single READ. The mount
+/// command is passed matching `rsize` and `wsize` options, since some clients, like the one on
+/// macOS, otherwise default to 32 KiB per request no matter what the server advertises.
+///
+/// ## Example
+/// ```
+/// use monofs::config::TransferConfig;
+///
+/// let config = TransferConfig::builder()
+///     .rsize(256 * 1024)
+///     .wsize(256 * 1024)
+///     .build();
+///
+/// assert!(config.validate().is_ok());
+/// assert_eq!(config.to_mount_options(), "rsize=262144,wsize=262144");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TypedBuilder, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct TransferConfig {
+    /// The most bytes a client reads from a file in a single READ.
+    #[builder(default = DEFAULT_NFS_RSIZE)]
+    rsize: u32,
+
+    /// The most bytes a client writes to a file in a single WRITE.
+    #[builder(default = DEFAULT_NFS_WSIZE)]
+    wsize: u32,
+
+    /// The size of the READDIR replies clients are asked to request.
+    #[builder(default = DEFAULT_NFS_DTPREF)]
+    dtpref: u32,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl TransferConfig {
+    /// Checks that the sizes are multiples of [`NFS_TRANSFER_SIZE_MULTIPLE`] no larger than
+    /// [`NFS_MAX_TRANSFER_SIZE`].
+    pub fn validate(&self) -> FsResult<()> {
+        for (name, size) in [
+            ("rsize", self.rsize),
+            ("wsize", self.wsize),
+            ("dtpref", self.dtpref),
+        ] {
+            if size == 0 || size % NFS_TRANSFER_SIZE_MULTIPLE != 0 {
+                return Err(FsError::InvalidTransferConfig(format!(
+                    "{} must be a non-zero multiple of {} bytes, got {}",
+                    name, NFS_TRANSFER_SIZE_MULTIPLE, size
+                )));
+            }
+
+            if size > NFS_MAX_TRANSFER_SIZE {
+                return Err(FsError::InvalidTransferConfig(format!(
+                    "{} must be at most {} bytes, got {}",
+                    name, NFS_MAX_TRANSFER_SIZE, size
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the options that make the mount command use these sizes.
+    pub fn to_mount_options(&self) -> String {
+        format!("rsize={},wsize={}", self.rsize, self.wsize)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for TransferConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer_config_validate() {
+        assert!(TransferConfig::default().validate().is_ok());
+
+        let config = TransferConfig::builder().rsize(0).build();
+        assert!(config.validate().is_err());
+
+        let config = TransferConfig::builder().wsize(64 * 1024 + 1).build();
+        assert!(config.validate().is_err());
+
+        let config = TransferConfig::builder().dtpref(2 * 1024 * 1024).build();
+        assert!(config.validate().is_err());
+    }
+}
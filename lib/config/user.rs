@@ -0,0 +1,250 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use getset::Getters;
+use serde::Deserialize;
+
+use crate::{
+    utils::{
+        BACKEND_ENV_VAR, HOST_ENV_VAR, PORT_RANGE_ENV_VAR, USER_CONFIG_ENV_VAR,
+        USER_CONFIG_FILENAME, USER_CONFIG_SUBDIR,
+    },
+    FsError, FsResult,
+};
+
+use super::{
+    ChunkerConfig, CompressionConfig, ExportConfig, MountBackend, PortRange, QuotaConfig,
+    ThrottleConfig,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The user's defaults for new filesystems, read from their configuration file.
+///
+/// The file is `$XDG_CONFIG_HOME/monofs/config.toml`, or `~/.config/monofs/config.toml` if
+/// `XDG_CONFIG_HOME` is not set, unless the `MONOFS_CONFIG` environment variable names another
+/// one. Every setting is optional, and the `MONOFS_HOST`, `MONOFS_PORT_RANGE` and
+/// `MONOFS_BACKEND` environment variables take precedence over the file. Whatever is left unset
+/// falls back to the built-in defaults.
+///
+/// ```toml
+/// host = "0.0.0.0"
+/// port_range = "3000-3099"
+/// backend = "fuse"
+/// mount_options = ["hard", "actimeo=1"]
+///
+/// [chunker]
+/// desired_size = 131072
+///
+/// [compression]
+/// level = 9
+///
+/// [quota]
+/// max_store_bytes = 10737418240
+///
+/// [exports]
+/// allowed_clients = ["10.0.0.0/8"]
+/// squash = "root"
+/// check_permissions = true
+/// uid_map = ["1000:501"]
+///
+/// [throttle]
+/// client_max_bytes_per_sec = 104857600
+/// ```
+///
+/// ## Example
+/// ```
+/// use monofs::config::{PortRange, UserConfig};
+///
+/// let config = UserConfig::from_toml(r#"port_range = "3000-3099""#).unwrap();
+/// assert_eq!(config.get_port_range(), &Some(PortRange::new(3000, 3099)));
+/// assert_eq!(config.get_host(), &None);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Getters)]
+#[serde(default, deny_unknown_fields)]
+#[getset(get = "pub with_prefix")]
+pub struct UserConfig {
+    /// The mechanism new filesystems are mounted with.
+    backend: Option<MountBackend>,
+
+    /// The address new filesystems serve NFS on.
+    host: Option<String>,
+
+    /// The ports new filesystems search for a free one to serve NFS on.
+    port_range: Option<PortRange>,
+
+    /// How new filesystems split file contents into blocks.
+    chunker: Option<ChunkerConfig>,
+
+    /// How new filesystems compress blocks at rest.
+    compression: Option<CompressionConfig>,
+
+    /// How much new filesystems may hold.
+    quota: Option<QuotaConfig>,
+
+    /// The options filesystems are mounted with over NFS on top of the platform's profile.
+    mount_options: Option<Vec<String>>,
+
+    /// Which NFS clients may use new filesystems, and who they act as.
+    exports: Option<ExportConfig>,
+
+    /// How many NFS requests new filesystems serve, and how many bytes they move, each second.
+    throttle: Option<ThrottleConfig>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl UserConfig {
+    /// Loads the user's configuration file, if there is one, with the settings the environment
+    /// overrides.
+    ///
+    /// A missing file at the default location is the same as an empty one, but a file named by
+    /// `MONOFS_CONFIG` has to exist.
+    pub fn load() -> FsResult<Self> {
+        let config = match env::var_os(USER_CONFIG_ENV_VAR) {
+            Some(path) => Self::from_file(path)?,
+            None => match Self::get_default_path() {
+                Some(path) if path.exists() => Self::from_file(path)?,
+                _ => Self::default(),
+            },
+        };
+
+        config.with_overrides(|name| env::var(name).ok())
+    }
+
+    /// Reads the configuration file at `path`.
+    pub fn from_file(path: impl AsRef<Path>) -> FsResult<Self> {
+        let path = path.as_ref();
+        let toml = fs::read_to_string(path).map_err(|e| {
+            FsError::InvalidUserConfig(format!("failed to read {}: {}", path.display(), e))
+        })?;
+
+        toml::from_str(&toml)
+            .map_err(|e| FsError::InvalidUserConfig(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Parses a configuration written in TOML.
+    pub fn from_toml(toml: &str) -> FsResult<Self> {
+        toml::from_str(toml).map_err(|e| FsError::InvalidUserConfig(e.to_string()))
+    }
+
+    /// Returns where the configuration file is looked for when `MONOFS_CONFIG` is not set, if the
+    /// user's configuration directory can be found.
+    pub fn get_default_path() -> Option<PathBuf> {
+        let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+
+        Some(
+            config_dir
+                .join(USER_CONFIG_SUBDIR)
+                .join(USER_CONFIG_FILENAME),
+        )
+    }
+
+    /// Replaces the settings that `var` returns a value for, given the name of the environment
+    /// variable overriding each.
+    fn with_overrides(mut self, var: impl Fn(&str) -> Option<String>) -> FsResult<Self> {
+        if let Some(host) = var(HOST_ENV_VAR) {
+            self.host = Some(host);
+        }
+
+        if let Some(port_range) = var(PORT_RANGE_ENV_VAR) {
+            self.port_range = Some(port_range.parse()?);
+        }
+
+        if let Some(backend) = var(BACKEND_ENV_VAR) {
+            let backend = <MountBackend as ValueEnum>::from_str(&backend, true).map_err(|_| {
+                FsError::InvalidUserConfig(format!(
+                    "{} must be nfs or fuse, got {}",
+                    BACKEND_ENV_VAR, backend
+                ))
+            })?;
+            self.backend = Some(backend);
+        }
+
+        Ok(self)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::config::SquashMode;
+
+    use super::*;
+
+    #[test]
+    fn test_user_config_from_toml_and_overrides() -> anyhow::Result<()> {
+        let config = UserConfig::from_toml(
+            r#"
+            host = "0.0.0.0"
+            port_range = "3000-3099"
+            mount_options = ["hard"]
+
+            [chunker]
+            desired_size = 131072
+
+            [quota]
+            max_store_bytes = 1024
+
+            [exports]
+            squash = "all"
+            uid_map = ["1000:501"]
+            "#,
+        )?;
+        assert_eq!(config.get_host().as_deref(), Some("0.0.0.0"));
+        assert_eq!(config.get_backend(), &None);
+        assert_eq!(config.get_compression(), &None);
+        assert_eq!(config.get_mount_options(), &Some(vec!["hard".to_string()]));
+        assert_eq!(
+            config.get_exports(),
+            &Some(
+                ExportConfig::builder()
+                    .squash(SquashMode::All)
+                    .uid_map(vec!["1000:501".parse()?])
+                    .build()
+            )
+        );
+
+        // Sections only need the settings that differ from the defaults
+        let chunker = config.get_chunker().unwrap();
+        assert_eq!(chunker.get_desired_size(), &131072);
+        assert_eq!(
+            chunker.get_min_size(),
+            ChunkerConfig::default().get_min_size()
+        );
+        assert_eq!(
+            config.get_quota().unwrap().get_max_store_bytes(),
+            &Some(1024)
+        );
+
+        // The environment takes precedence over the file
+        let vars = HashMap::from([(PORT_RANGE_ENV_VAR, "4000"), (BACKEND_ENV_VAR, "FUSE")]);
+        let config = config.with_overrides(|name| vars.get(name).map(|value| value.to_string()))?;
+        assert_eq!(config.get_host().as_deref(), Some("0.0.0.0"));
+        assert_eq!(config.get_port_range(), &Some(PortRange::single(4000)));
+        assert_eq!(config.get_backend(), &Some(MountBackend::Fuse));
+
+        assert!(UserConfig::from_toml("port = 3000").is_err());
+        assert!(UserConfig::from_toml(r#"port_range = "3099-3000""#).is_err());
+        assert!(UserConfig::default()
+            .with_overrides(|name| (name == BACKEND_ENV_VAR).then(|| "sshfs".to_string()))
+            .is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+use super::{DEFAULT_WRITE_BACK_FLUSH_INTERVAL, DEFAULT_WRITE_BACK_MAX_DIRTY_BYTES};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Configures how the server buffers writes before flushing them to the store.
+///
+/// Without buffering, every NFS WRITE rewrites the file's content in the store, so a file written
+/// in many small pieces is chunked and hashed once per piece. With buffering, sequential writes to
+/// a file are collected in memory and flushed together once there are `max_dirty_bytes` of them,
+/// once the oldest is `flush_interval` old, or before anything needs the file's stored content.
+///
+/// ## Example
+/// ```
+/// use std::time::Duration;
+///
+/// use monofs::config::WriteBackConfig;
+///
+/// let config = WriteBackConfig::builder()
+///     .max_dirty_bytes(16 * 1024 * 1024)
+///     .flush_interval(Duration::from_secs(1))
+///     .build();
+///
+/// assert!(config.is_enabled());
+/// assert!(!WriteBackConfig::disabled().is_enabled());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TypedBuilder, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct WriteBackConfig {
+    /// How many written bytes may be buffered across all files before they are flushed. Zero
+    /// disables buffering, so every write goes straight to the store.
+    #[builder(default = DEFAULT_WRITE_BACK_MAX_DIRTY_BYTES)]
+    max_dirty_bytes: u64,
+
+    /// The longest a write may stay buffered before it is flushed.
+    #[builder(default = DEFAULT_WRITE_BACK_FLUSH_INTERVAL)]
+    flush_interval: Duration,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl WriteBackConfig {
+    /// Creates a configuration that writes everything straight to the store.
+    pub fn disabled() -> Self {
+        Self::builder().max_dirty_bytes(0).build()
+    }
+
+    /// Returns whether writes are buffered at all.
+    pub fn is_enabled(&self) -> bool {
+        self.max_dirty_bytes > 0
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for WriteBackConfig {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
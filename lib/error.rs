@@ -8,7 +8,6 @@ use std::{
 use ipldstore::ipld::cid::Cid;
 use thiserror::Error;
 
-use crate::filesystem::Utf8UnixPathSegment;
 use microsandbox_utils::error::MicrosandboxUtilsError;
 
 //--------------------------------------------------------------------------------------------------
@@ -76,10 +75,6 @@ pub enum FsError {
     #[error("Invalid search path: {0}")]
     InvalidSearchPath(String),
 
-    /// Symbolic CID link not supported yet.
-    #[error("Symbolic CID link not supported yet: path: {0:?}")]
-    SymCidLinkNotSupportedYet(Vec<Utf8UnixPathSegment>),
-
     /// Invalid search path empty.
     #[error("Invalid search path empty")]
     InvalidSearchPathEmpty,
@@ -120,6 +115,10 @@ pub enum FsError {
     #[error("Broken symbolic CID link: {0}")]
     BrokenSymCidLink(Cid),
 
+    /// Symbolic path link leads outside of the directory a path is resolved in.
+    #[error("Symbolic path link escapes the root: {0}")]
+    SymPathLinkEscapesRoot(String),
+
     /// Invalid operation.
     #[error("Invalid operation: {0}")]
     InvalidOperation(String),
@@ -144,6 +143,10 @@ pub enum FsError {
     #[error("Unmount operation failed: {0}")]
     UnmountFailed(String),
 
+    /// A filesystem server did not shut down in time
+    #[error("Shutdown timed out: {0}")]
+    ShutdownTimedOut(String),
+
     /// No available ports found in range
     #[error("No available ports found in range {host}:{start}-{end}")]
     NoAvailablePorts {
@@ -196,6 +199,240 @@ pub enum FsError {
     /// Child IO must be piped
     #[error("Child IO must be piped")]
     ChildIoMustBePiped,
+
+    /// A request to a running filesystem server failed
+    #[error("Control request failed: {0}")]
+    ControlRequestFailed(String),
+
+    /// The filesystem has no entry in its fs database
+    #[error("Filesystem not registered in fs database: {0}")]
+    FilesystemNotRegistered(String),
+
+    /// Snapshot not found
+    #[error("Snapshot not found: {0}")]
+    SnapshotNotFound(String),
+
+    /// Snapshot already exists
+    #[error("Snapshot already exists: {0}")]
+    SnapshotExists(String),
+
+    /// A stream written by `send` is malformed or incomplete
+    #[error("Invalid send stream: {0}")]
+    InvalidSendStream(String),
+
+    /// Other writers kept recording heads for a filesystem while a root was being recorded
+    #[error("Head update conflict: {0}")]
+    HeadUpdateConflict(String),
+
+    /// Branch not found
+    #[error("Branch not found: {0}")]
+    BranchNotFound(String),
+
+    /// Branch already exists
+    #[error("Branch already exists: {0}")]
+    BranchExists(String),
+
+    /// Pin not found
+    #[error("Pin not found: {0}")]
+    PinNotFound(String),
+
+    /// No data directory exists for the filesystem
+    #[error("MFS data directory not found: {0}")]
+    MfsDataDirNotFound(String),
+
+    /// The filesystem is already mounted
+    #[error("Filesystem is already mounted: {0}")]
+    AlreadyMounted(String),
+
+    /// A port range could not be parsed
+    #[error("Invalid port range: {0}")]
+    InvalidPortRange(String),
+
+    /// A chunker configuration is invalid
+    #[error("Invalid chunker configuration: {0}")]
+    InvalidChunkerConfig(String),
+
+    /// A compression configuration is invalid
+    #[error("Invalid compression configuration: {0}")]
+    InvalidCompressionConfig(String),
+
+    /// An encryption key is invalid
+    #[error("Invalid encryption key: {0}")]
+    InvalidEncryptionKey(String),
+
+    /// A block is encrypted but no encryption key was given
+    #[error("Block is encrypted and no encryption key was given: {0}")]
+    MissingEncryptionKey(Cid),
+
+    /// A block could not be decrypted, because the key is wrong or the block was modified
+    #[error("Failed to decrypt block, the encryption key may be wrong: {0}")]
+    BlockDecryptionFailed(Cid),
+
+    /// A checkout target is neither a history version nor a timestamp
+    #[error("Invalid checkout target: {0}")]
+    InvalidCheckoutTarget(String),
+
+    /// No history entry matches a checkout target
+    #[error("History entry not found: {0}")]
+    HistoryEntryNotFound(String),
+
+    /// A write would take the files of a filesystem past their quota
+    #[error("Logical quota exceeded: {used} of {max} bytes used")]
+    LogicalQuotaExceeded {
+        /// The bytes the files would take up after the write
+        used: u64,
+
+        /// The most bytes the files may take up
+        max: u64,
+    },
+
+    /// The block store of a filesystem has reached its quota
+    #[error("Store quota exceeded: {used} of {max} bytes used")]
+    StoreQuotaExceeded {
+        /// The bytes the store would take up after the write
+        used: u64,
+
+        /// The most bytes the store may take up
+        max: u64,
+    },
+
+    /// An operation needs the server of a filesystem, but the filesystem is not mounted
+    #[error("Filesystem is not mounted: {0}")]
+    NotMounted(String),
+
+    /// A remote store configuration is invalid
+    #[error("Invalid remote store configuration: {0}")]
+    InvalidRemoteConfig(String),
+
+    /// An in-memory store configuration is invalid
+    #[error("Invalid in-memory store configuration: {0}")]
+    InvalidMemoryStoreConfig(String),
+
+    /// The in-memory store of a filesystem has no room left for a block
+    #[error("In-memory store full: {used} of {max} bytes used")]
+    MemoryStoreFull {
+        /// The bytes the store would take up after the write
+        used: u64,
+
+        /// The most bytes the store may take up
+        max: u64,
+    },
+
+    /// The subtree a filesystem is configured to serve as its root is invalid
+    #[error("Invalid subtree: {0}")]
+    InvalidSubtree(String),
+
+    /// An NFS transfer size configuration is invalid
+    #[error("Invalid transfer configuration: {0}")]
+    InvalidTransferConfig(String),
+
+    /// The user's configuration file or an environment variable overriding it is invalid
+    #[error("Invalid user configuration: {0}")]
+    InvalidUserConfig(String),
+
+    /// A filesystem was not mounted in time
+    #[error("Mount timed out: {0}")]
+    MountTimedOut(String),
+
+    /// Mounting a filesystem was cancelled
+    #[error("Mount cancelled: {0}")]
+    MountCancelled(String),
+
+    /// An option to mount a filesystem with is invalid
+    #[error("Invalid mount option: {0}")]
+    InvalidMountOption(String),
+
+    /// The current process is not allowed to mount a filesystem the way it was asked to
+    #[error("Mount not permitted: {0}")]
+    MountNotPermitted(String),
+
+    /// A key signing roots, or the public key verifying them, is invalid
+    #[error("Invalid signing key: {0}")]
+    InvalidSigningKey(String),
+
+    /// A root is not signed, or its signature does not check out
+    #[error("Invalid root signature: {0}")]
+    RootSignatureInvalid(String),
+
+    /// The export configuration of a filesystem is invalid
+    #[error("Invalid export configuration: {0}")]
+    InvalidExportConfig(String),
+
+    /// The filesystem does not record an audit log
+    #[error("Auditing is not enabled for the filesystem mounted at {0}")]
+    AuditNotEnabled(String),
+
+    /// An operation in the audit log is not one monofs knows
+    #[error("Invalid audit operation: {0}")]
+    InvalidAuditOperation(String),
+
+    /// An NFS throttling configuration is invalid
+    #[error("Invalid throttle configuration: {0}")]
+    InvalidThrottleConfig(String),
+
+    /// A snapshot schedule is invalid
+    #[error("Invalid snapshot schedule: {0}")]
+    InvalidSnapshotSchedule(String),
+
+    /// A snapshot retention policy is invalid
+    #[error("Invalid snapshot retention policy: {0}")]
+    InvalidSnapshotRetention(String),
+
+    /// Another garbage collection updated the block reference counts at the same time
+    #[error("Block reference counts conflict: {0}")]
+    BlockRefsConflict(String),
+
+    /// A store's hash function is not one this version of monofs supports
+    #[error("Unsupported hash function: {0}")]
+    UnsupportedHashFunction(String),
+
+    /// No job with a given ID was run on a filesystem
+    #[error("Job not found: {0}")]
+    JobNotFound(i64),
+
+    /// A job recorded in the database has a kind or state this version of monofs does not know
+    #[error("Invalid job record: {0}")]
+    InvalidJobRecord(String),
+
+    /// A search pattern or path glob could not be parsed
+    #[error("Invalid search pattern: {0}")]
+    InvalidSearchPattern(String),
+
+    /// An OCI or Docker image archive is malformed or uses a format monofs does not support
+    #[error("Invalid OCI image: {0}")]
+    InvalidOciImage(String),
+
+    /// A scrub configuration has invalid settings
+    #[error("Invalid scrub config: {0}")]
+    InvalidScrubConfig(String),
+
+    /// Replica not found
+    #[error("Replica not found: {0}")]
+    ReplicaNotFound(String),
+
+    /// A mirror directory cannot hold copies of the blocks of a filesystem
+    #[error("Invalid mirror: {0}")]
+    InvalidMirror(String),
+
+    /// The destination of a backup already holds files
+    #[error("Backup destination is not empty: {0}")]
+    BackupDestinationNotEmpty(String),
+
+    /// A backup or CAR file cannot be restored from
+    #[error("Invalid backup: {0}")]
+    InvalidBackup(String),
+
+    /// The fs database was migrated by a newer version of monofs
+    #[error("Database version too new: {0}")]
+    DbVersionTooNew(String),
+
+    /// The fs database needs migrations that cannot run while the filesystem is mounted
+    #[error("Database migration required: {0}")]
+    DbMigrationRequired(String),
+
+    /// A log rotation configuration has invalid settings
+    #[error("Invalid log rotation config: {0}")]
+    InvalidLogRotationConfig(String),
 }
 
 /// An error that can represent any error.
@@ -0,0 +1,410 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use futures::{stream, Stream};
+use getset::Getters;
+use ipldstore::{ipld::cid::Cid, IpldStore, Storable};
+use typed_path::Utf8UnixPathBuf;
+
+use crate::FsResult;
+
+use super::{Dir, Entity};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A change between two versions of a directory tree.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct DiffEntry {
+    /// The path of the changed entity relative to the root. For renames, this is the new path.
+    path: Utf8UnixPathBuf,
+
+    /// How the entity changed.
+    kind: DiffKind,
+}
+
+/// How an entity changed between two versions of a directory tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffKind {
+    /// The entity only exists in the new tree.
+    Added,
+
+    /// The entity only exists in the old tree.
+    Removed,
+
+    /// The entity exists in both trees with different contents or a different type.
+    Modified,
+
+    /// The entity moved to a new path with its contents unchanged.
+    Renamed {
+        /// The path of the entity in the old tree.
+        from: Utf8UnixPathBuf,
+    },
+}
+
+/// What an entity holds, ignoring its metadata and history.
+///
+/// Entities with equal content keys are considered unchanged, even when their CIDs differ
+/// because of timestamps or previous versions.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum ContentKey {
+    /// A file and the CID of its content, if it has any.
+    File(Option<Cid>),
+
+    /// A directory and the CIDs of its entries.
+    Dir(Vec<(String, Cid)>),
+
+    /// A symbolic CID link and its target.
+    SymCidLink(Option<Cid>),
+
+    /// A symbolic path link and its target.
+    SymPathLink(String),
+}
+
+/// An entity that only exists in one of the trees.
+struct OneSided {
+    /// The path of the entity.
+    path: Utf8UnixPathBuf,
+
+    /// The content of the entity, used to detect renames.
+    key: ContentKey,
+}
+
+/// The state of a diff in progress.
+struct DiffState<S>
+where
+    S: IpldStore,
+{
+    /// The store the trees are loaded from.
+    store: S,
+
+    /// Pairs of directories at the same path that still need to be compared.
+    pending: Vec<(Utf8UnixPathBuf, Cid, Cid)>,
+
+    /// Changes ready to be yielded.
+    ready: VecDeque<DiffEntry>,
+
+    /// Entities that only exist in the old tree, held back to detect renames.
+    removed: Vec<OneSided>,
+
+    /// Entities that only exist in the new tree, held back to detect renames.
+    added: Vec<OneSided>,
+
+    /// Whether the held back entities have been released.
+    finished: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> DiffState<S>
+where
+    S: IpldStore + Send + Sync,
+{
+    /// Compares the directories at `old` and `new`, queueing the changes between them and the
+    /// subdirectories that differ.
+    async fn compare(&mut self, path: Utf8UnixPathBuf, old: Cid, new: Cid) -> FsResult<()> {
+        let old_dir = Dir::load(&old, self.store.clone()).await?;
+        let new_dir = Dir::load(&new, self.store.clone()).await?;
+
+        let old_entries = old_dir
+            .get_entries()
+            .map(|(name, link)| (name.to_string(), link))
+            .collect::<BTreeMap<_, _>>();
+        let new_entries = new_dir
+            .get_entries()
+            .map(|(name, link)| (name.to_string(), link))
+            .collect::<BTreeMap<_, _>>();
+
+        for (name, old_link) in &old_entries {
+            let entity_path = path.join(name);
+            let old_entity = old_link.resolve_entity(self.store.clone()).await?;
+            let Some(new_link) = new_entries.get(name) else {
+                self.removed.push(OneSided {
+                    path: entity_path,
+                    key: get_content_key(old_entity).await?,
+                });
+                continue;
+            };
+
+            // Identical CIDs mean identical subtrees
+            if old_link.get_cid().is_some() && old_link.get_cid() == new_link.get_cid() {
+                continue;
+            }
+
+            let new_entity = new_link.resolve_entity(self.store.clone()).await?;
+            match (old_entity, new_entity) {
+                (Entity::Dir(_), Entity::Dir(_)) => {
+                    let old = old_link.resolve_cid::<S>().await?;
+                    let new = new_link.resolve_cid::<S>().await?;
+                    self.pending.push((entity_path, old, new));
+                }
+                (old_entity, new_entity) => {
+                    if get_content_key(old_entity).await? != get_content_key(new_entity).await? {
+                        self.ready.push_back(DiffEntry {
+                            path: entity_path,
+                            kind: DiffKind::Modified,
+                        });
+                    }
+                }
+            }
+        }
+
+        for (name, new_link) in &new_entries {
+            if old_entries.contains_key(name) {
+                continue;
+            }
+
+            let new_entity = new_link.resolve_entity(self.store.clone()).await?;
+            self.added.push(OneSided {
+                path: path.join(name),
+                key: get_content_key(new_entity).await?,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Pairs up removed and added entities with the same content as renames and queues every
+    /// held back change.
+    fn finish(&mut self) {
+        let mut removed_by_key = HashMap::<&ContentKey, VecDeque<usize>>::new();
+        for (index, removed) in self.removed.iter().enumerate() {
+            if !removed.key.is_empty() {
+                removed_by_key
+                    .entry(&removed.key)
+                    .or_default()
+                    .push_back(index);
+            }
+        }
+
+        let mut renamed = vec![false; self.removed.len()];
+        let mut added = Vec::new();
+        for entity in &self.added {
+            let from = removed_by_key
+                .get_mut(&entity.key)
+                .and_then(|indices| indices.pop_front());
+
+            match from {
+                Some(index) => {
+                    renamed[index] = true;
+                    self.ready.push_back(DiffEntry {
+                        path: entity.path.clone(),
+                        kind: DiffKind::Renamed {
+                            from: self.removed[index].path.clone(),
+                        },
+                    });
+                }
+                None => added.push(DiffEntry {
+                    path: entity.path.clone(),
+                    kind: DiffKind::Added,
+                }),
+            }
+        }
+
+        for (removed, renamed) in self.removed.iter().zip(renamed) {
+            if !renamed {
+                self.ready.push_back(DiffEntry {
+                    path: removed.path.clone(),
+                    kind: DiffKind::Removed,
+                });
+            }
+        }
+
+        self.ready.extend(added);
+        self.finished = true;
+    }
+}
+
+impl ContentKey {
+    /// Returns whether the key describes an empty file or directory, which are too common to
+    /// pair up as renames.
+    fn is_empty(&self) -> bool {
+        match self {
+            ContentKey::File(content) => content.is_none(),
+            ContentKey::Dir(entries) => entries.is_empty(),
+            _ => false,
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Computes the changes between the directory trees rooted at `old_root` and `new_root`.
+///
+/// The trees are compared structurally: subtrees whose CIDs match are skipped without being
+/// loaded, so the cost of a diff grows with the size of the changes rather than the size of the
+/// trees. Entities are compared by content, so an entity that was rewritten with the same content
+/// is not reported. Directories are never reported as modified; their changed contents are
+/// reported instead.
+///
+/// Modifications are yielded as they are found. Additions and removals are held back until both
+/// trees have been walked so that an entity that moved without changing can be reported as a
+/// rename. Added and removed directories are reported as a whole, without their contents.
+///
+/// ## Examples
+///
+/// ```
+/// use futures::TryStreamExt;
+/// use monofs::filesystem::{self, DiffKind, Dir, File};
+/// use ipldstore::MemoryStore;
+///
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let store = MemoryStore::default();
+/// let mut root = Dir::new(store.clone());
+/// let old = root.checkpoint().await?;
+///
+/// let file = File::with_content(store.clone(), b"hello".as_slice()).await?;
+/// root.put_adapted_file("hello.txt", file).await?;
+/// let new = root.checkpoint().await?;
+///
+/// let changes = filesystem::diff(old, new, store).try_collect::<Vec<_>>().await?;
+/// assert_eq!(changes.len(), 1);
+/// assert_eq!(changes[0].get_path().as_str(), "hello.txt");
+/// assert_eq!(changes[0].get_kind(), &DiffKind::Added);
+/// # Ok(())
+/// # }
+/// ```
+pub fn diff<S>(old_root: Cid, new_root: Cid, store: S) -> impl Stream<Item = FsResult<DiffEntry>>
+where
+    S: IpldStore + Send + Sync,
+{
+    let state = DiffState {
+        store,
+        pending: vec![(Utf8UnixPathBuf::new(), old_root, new_root)],
+        ready: VecDeque::new(),
+        removed: Vec::new(),
+        added: Vec::new(),
+        finished: false,
+    };
+
+    stream::try_unfold(state, |mut state| async move {
+        loop {
+            if let Some(entry) = state.ready.pop_front() {
+                return Ok(Some((entry, state)));
+            }
+
+            if let Some((path, old, new)) = state.pending.pop() {
+                if old != new {
+                    state.compare(path, old, new).await?;
+                }
+                continue;
+            }
+
+            if state.finished {
+                return Ok(None);
+            }
+
+            state.finish();
+        }
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns what `entity` holds, ignoring its metadata and history.
+pub(crate) async fn get_content_key<S>(entity: &Entity<S>) -> FsResult<ContentKey>
+where
+    S: IpldStore + Send + Sync,
+{
+    Ok(match entity {
+        Entity::File(file) => ContentKey::File(file.get_content().cloned()),
+        Entity::Dir(dir) => {
+            let mut entries = Vec::new();
+            for (name, link) in dir.get_entries() {
+                entries.push((name.to_string(), link.resolve_cid::<S>().await?));
+            }
+            ContentKey::Dir(entries)
+        }
+        Entity::SymCidLink(link) => ContentKey::SymCidLink(link.get_link().get_cid().cloned()),
+        Entity::SymPathLink(link) => {
+            ContentKey::SymPathLink(link.get_target_path().as_str().to_string())
+        }
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+    use ipldstore::MemoryStore;
+
+    use crate::filesystem::File;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_diff_reports_changes() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut root = Dir::new(store.clone());
+        for (path, content) in [
+            ("docs/readme.md", "readme"),
+            ("docs/guide.md", "guide"),
+            ("src/main.rs", "fn main() {}"),
+            ("old.txt", "old"),
+        ] {
+            let file = File::with_content(store.clone(), content.as_bytes()).await?;
+            let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+            match parent {
+                "" => root.put_adapted_file(name, file).await?,
+                parent => match root.find_or_create(parent, false).await? {
+                    Entity::Dir(dir) => dir.put_adapted_file(name, file).await?,
+                    _ => unreachable!(),
+                },
+            }
+        }
+        let old = root.checkpoint().await?;
+
+        // Modify, add, remove and rename
+        let file = File::with_content(store.clone(), b"fn main() { run() }".as_slice()).await?;
+        match root.find_mut("src").await? {
+            Some(Entity::Dir(src)) => src.put_adapted_file("main.rs", file).await?,
+            _ => unreachable!(),
+        }
+        let file = File::with_content(store.clone(), b"new".as_slice()).await?;
+        root.put_adapted_file("new.txt", file).await?;
+        root.remove("old.txt").await?;
+        root.rename("docs/guide.md", "guide.md").await?;
+        let new = root.checkpoint().await?;
+
+        let mut changes = diff(old, new, store.clone())
+            .try_collect::<Vec<_>>()
+            .await?;
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let changes = changes
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry.kind.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                (
+                    "guide.md",
+                    DiffKind::Renamed {
+                        from: Utf8UnixPathBuf::from("docs/guide.md")
+                    }
+                ),
+                ("new.txt", DiffKind::Added),
+                ("old.txt", DiffKind::Removed),
+                ("src/main.rs", DiffKind::Modified),
+            ]
+        );
+
+        // Nothing changes between a root and itself
+        let changes = diff(new, new, store).try_collect::<Vec<_>>().await?;
+        assert!(changes.is_empty());
+
+        Ok(())
+    }
+}
@@ -1,6 +1,10 @@
 mod find;
 mod ops;
+mod overlay;
+mod recursive;
 mod segment;
+mod shard;
+mod stream;
 
 use std::{
     collections::{BTreeMap, HashMap},
@@ -16,7 +20,7 @@ use serde::{Deserialize, Serialize};
 use crate::{
     filesystem::{
         kind::EntityType, Entity, EntityCidLink, File, Link, Metadata, MetadataSerializable,
-        SymCidLink,
+        SymCidLink, TypeField,
     },
     FsError, FsResult,
 };
@@ -67,6 +71,12 @@ where
 
     /// The entries in the directory.
     entries: HashMap<Utf8UnixPathSegment, Entry<S>>,
+
+    /// The CID of the directory this directory is an overlay of, if it is one.
+    base: Option<Cid>,
+
+    /// The trie of shards the entries of the directory were loaded from, if they were.
+    shards: Option<Arc<shard::DirShards>>,
 }
 
 /// Represents an entry in a directory.
@@ -78,6 +88,10 @@ where
     /// Whether the entry has been deleted.
     pub deleted: bool,
 
+    /// Whether the entry is read from the base of an overlay and unchanged, so that it is not
+    /// stored with the directory.
+    pub inherited: bool,
+
     /// The link to the entity.
     pub link: EntityCidLink<S>,
 }
@@ -95,9 +109,19 @@ pub struct DirSerializable {
     /// The metadata of the directory.
     metadata: MetadataSerializable,
 
-    /// The entries in the directory.
+    /// The entries in the directory, unless they are kept in shards.
     entries: BTreeMap<String, (bool, Cid)>,
 
+    /// The root shard holding the entries of a directory with more than
+    /// [`DIR_SHARD_THRESHOLD`] entries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shards: Option<Cid>,
+
+    /// The directory this directory is an overlay of, whose entries it has unless it has entries
+    /// of the same name, deleted or not.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    base: Option<Cid>,
+
     /// The CID of the previous version of the directory if there is one.
     previous: Option<Cid>,
 }
@@ -129,6 +153,8 @@ where
                 metadata: Metadata::new(EntityType::Dir, store.clone()),
                 entries: HashMap::new(),
                 store,
+                base: None,
+                shards: None,
             }),
         }
     }
@@ -390,7 +416,8 @@ where
             .ok_or(FsError::PathNotFound(name.to_string()))?;
 
         entry.deleted = true;
-        inner.metadata.set_modified_at(Utc::now());
+        entry.inherited = false;
+        inner.metadata.mark_modified(Utc::now());
 
         Ok(&mut entry.link)
     }
@@ -494,6 +521,9 @@ where
 
     /// Gets a mutable reference to the entry with the given name.
     ///
+    /// An entry read from the base of an overlay is copied up into the directory, as it may be
+    /// changed through the reference.
+    ///
     /// ## Examples
     ///
     /// ```
@@ -524,7 +554,10 @@ where
             .filter(|(_, entry)| !entry.deleted)
             .find(|(entry_name, _)| *entry_name == &name);
 
-        Ok(entry.map(|(_, entry)| &mut entry.link))
+        Ok(entry.map(|(_, entry)| {
+            entry.inherited = false;
+            &mut entry.link
+        }))
     }
 
     /// Adds or updates an entry in the directory, handling versioning.
@@ -534,6 +567,9 @@ where
     /// - For existing entries: Sets the previous version appropriately
     /// - For deleted entries: Restores them with proper versioning
     ///
+    /// The directory is marked as modified. The entity is only marked as changed, so moving an
+    /// entity keeps its modification time, like `rename(2)`.
+    ///
     /// ## Examples
     ///
     /// ```
@@ -591,16 +627,17 @@ where
             }
         }
 
-        // Update the modified timestamp
+        // Linking the entity changes it without modifying its content, and modifies the directory
         let now = Utc::now();
-        entity.get_metadata_mut().set_modified_at(now);
-        inner.metadata.set_modified_at(now);
+        entity.get_metadata_mut().set_changed_at(now);
+        inner.metadata.mark_modified(now);
 
         // Add or update the entry
         inner.entries.insert(
             name,
             Entry {
                 deleted: false,
+                inherited: false,
                 link,
             },
         );
@@ -759,8 +796,19 @@ where
         S: Send + Sync,
     {
         let store = self.inner.store.clone();
+        let inherited = self.is_inherited(&name)?;
         match self.get_entry_mut(name)? {
-            Some(link) => Ok(Some(link.resolve_entity_mut(store).await?)),
+            Some(link) => {
+                let base = link.get_cid().copied();
+                let entity = link.resolve_entity_mut(store).await?;
+
+                // A directory copied up from the base of an overlay becomes an overlay of it
+                if let (true, Some(base), Entity::Dir(dir)) = (inherited, base, &mut *entity) {
+                    dir.layer_over(base);
+                }
+
+                Ok(Some(entity))
+            }
             None => Ok(None),
         }
     }
@@ -851,11 +899,27 @@ where
     }
 
     /// Tries to create a new `Dir` from a serializable representation.
+    ///
+    /// The entries of a sharded directory and the entries an overlay inherits from its base are not
+    /// part of its serializable representation, so such a directory has to be loaded with
+    /// [`Storable::load`] instead.
     pub fn from_serializable(
         serializable: DirSerializable,
         store: S,
         load_cid: Cid,
     ) -> FsResult<Self> {
+        if serializable.shards.is_some() {
+            return Err(FsError::InvalidOperation(
+                "the entries of a sharded directory have to be loaded from its shards".to_string(),
+            ));
+        }
+
+        if serializable.base.is_some() {
+            return Err(FsError::InvalidOperation(
+                "the entries of an overlay have to be loaded with its base".to_string(),
+            ));
+        }
+
         let entries: HashMap<_, _> = serializable
             .entries
             .into_iter()
@@ -864,6 +928,7 @@ where
                     segment.parse()?,
                     Entry {
                         deleted,
+                        inherited: false,
                         link: Link::from(cid),
                     },
                 ))
@@ -877,6 +942,8 @@ where
                 metadata: Metadata::from_serializable(serializable.metadata, store.clone())?,
                 store,
                 entries,
+                base: None,
+                shards: None,
             }),
         })
     }
@@ -887,7 +954,7 @@ where
         S: Send + Sync,
     {
         let mut entries = BTreeMap::new();
-        for (k, v) in self.inner.entries.iter() {
+        for (k, v) in self.inner.entries.iter().filter(|(_, v)| !v.inherited) {
             entries.insert(
                 k.to_string(),
                 (
@@ -904,6 +971,8 @@ where
             previous: self.inner.initial_load_cid.get().cloned(),
             metadata,
             entries,
+            shards: None,
+            base: self.inner.base,
         })
     }
 
@@ -911,6 +980,45 @@ where
         let inner = Arc::make_mut(&mut self.inner);
         inner.previous = previous;
     }
+
+    /// Loads the CID of the entry named `name` of the directory at `cid`, without loading its
+    /// other entries.
+    ///
+    /// Only the shards on the way to the entry are read from a large directory, and the bases of
+    /// an overlay only when it has no entry of that name of its own. Returns `None` if there is no
+    /// such entry or it is deleted, and fails with [`FsError::NotADirectory`] if `cid` is not a
+    /// directory.
+    pub(crate) async fn load_entry_cid(cid: &Cid, store: &S, name: &str) -> FsResult<Option<Cid>>
+    where
+        S: Send + Sync,
+    {
+        let type_field: TypeField = store.get_node(cid).await?;
+        if type_field.r#type != DIR_TYPE_TAG {
+            return Err(FsError::NotADirectory(cid.to_string()));
+        }
+
+        let mut next = Some(*cid);
+        while let Some(cid) = next {
+            let layer: DirSerializable = store.get_node(&cid).await?;
+            if layer.r#type != DIR_TYPE_TAG {
+                return Err(FsError::NotADirectory(cid.to_string()));
+            }
+
+            let entry = match &layer.shards {
+                Some(shards) => shard::find_shard_entry(store, shards, name).await?,
+                None => layer.entries.get(name).copied(),
+            };
+
+            // Entries of an overlay, deleted or not, hide the entries of the same name in its base
+            if let Some((deleted, cid)) = entry {
+                return Ok((!deleted).then_some(cid));
+            }
+
+            next = layer.base;
+        }
+
+        Ok(None)
+    }
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -922,13 +1030,44 @@ where
     S: IpldStore + Send + Sync,
 {
     async fn store(&self) -> StoreResult<Cid> {
-        let serializable = self.get_serializable().await.map_err(StoreError::custom)?;
+        let mut serializable = self.get_serializable().await.map_err(StoreError::custom)?;
+
+        // Large directories keep their entries in shards, so that changing one entry does not
+        // create a new copy of all of them
+        if serializable.entries.len() > DIR_SHARD_THRESHOLD {
+            let entries = std::mem::take(&mut serializable.entries);
+            let shards = match &self.inner.shards {
+                Some(shards) => shard::update_shards(&self.inner.store, shards, entries).await?,
+                None => shard::store_shards(&self.inner.store, entries).await?,
+            };
+            serializable.shards = Some(shards);
+        }
+
         self.inner.store.put_node(&serializable).await
     }
 
     async fn load(cid: &Cid, store: S) -> StoreResult<Self> {
-        let serializable: DirSerializable = store.get_node(cid).await?;
-        Dir::from_serializable(serializable, store, *cid).map_err(StoreError::custom)
+        let mut serializable: DirSerializable = store.get_node(cid).await?;
+        let shards = match serializable.shards.take() {
+            Some(shards) => Some(shard::load_shards(&store, &shards).await?),
+            None => None,
+        };
+        if let Some(shards) = &shards {
+            serializable.entries = shards.get_entries().clone();
+        }
+
+        // Overlays inherit the entries of their base they have no entries of their own for
+        let base = serializable.base.take();
+        let mut dir = Dir::from_serializable(serializable, store.clone(), *cid)
+            .map_err(StoreError::custom)?;
+        Arc::make_mut(&mut dir.inner).shards = shards.map(Arc::new);
+        if let Some(base) = base {
+            let entries = overlay::load_base_entries(&store, &base).await?;
+            dir.inherit_entries(base, entries)
+                .map_err(StoreError::custom)?;
+        }
+
+        Ok(dir)
     }
 }
 
@@ -958,6 +1097,7 @@ where
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Entry")
             .field("deleted", &self.deleted)
+            .field("inherited", &self.inherited)
             .field("link", &self.link)
             .finish()
     }
@@ -965,7 +1105,13 @@ where
 
 impl IpldReferences for DirSerializable {
     fn get_references<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Cid> + Send + 'a> {
-        Box::new(self.entries.values().map(|(_, cid)| cid))
+        Box::new(
+            self.entries
+                .values()
+                .map(|(_, cid)| cid)
+                .chain(self.shards.iter())
+                .chain(self.base.iter()),
+        )
     }
 }
 
@@ -1291,6 +1437,9 @@ mod tests {
 //--------------------------------------------------------------------------------------------------
 
 pub use find::*;
+pub use recursive::*;
 pub use segment::*;
+pub use shard::*;
+pub use stream::*;
 
 use super::SymPathLink;
@@ -1,7 +1,7 @@
-use std::fmt::Debug;
+use std::{collections::VecDeque, fmt::Debug};
 
 use ipldstore::IpldStore;
-use typed_path::{Utf8UnixComponent, Utf8UnixPath};
+use typed_path::{Utf8UnixComponent, Utf8UnixPath, Utf8UnixPathBuf};
 
 use crate::{filesystem::entity::Entity, FsError, FsResult};
 
@@ -36,12 +36,86 @@ pub enum FindResult<T> {
     },
 }
 
+/// Which symbolic links are followed when a path is resolved, see
+/// [`Dir::find_with`](super::Dir::find_with).
+///
+/// Following a [`SymPathLink`](crate::filesystem::SymPathLink) never leads outside of the
+/// directory the path is resolved in, so a policy that follows links can still be used to confine
+/// lookups to a subtree. Links to absolute paths, or to relative paths that climb above that
+/// directory, fail with [`FsError::SymPathLinkEscapesRoot`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FollowPolicy {
+    /// No symbolic link is followed. A path that goes through one is not found, and one at the
+    /// final component of the path is found as it is.
+    Never,
+
+    /// Only a symbolic link at the final component of the path is followed, along with the links
+    /// it leads to.
+    FinalComponent {
+        /// The most links followed before the resolution fails.
+        max_depth: u32,
+    },
+
+    /// Symbolic links are followed wherever they are in the path.
+    Always {
+        /// The most links followed before the resolution fails.
+        max_depth: u32,
+    },
+}
+
+/// A path resolved by `resolve_path`, with every [`SymPathLink`](crate::filesystem::SymPathLink)
+/// on the way replaced by the path it leads to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ResolvedPath {
+    /// The components of the path, all of them directories or
+    /// [`SymCidLink`](crate::filesystem::SymCidLink)s to directories except the last one.
+    pub(crate) segments: Vec<Utf8UnixPathSegment>,
+
+    /// Whether the last component is a [`SymCidLink`](crate::filesystem::SymCidLink) to follow.
+    pub(crate) follow_last: bool,
+}
+
 /// Result type for `find_dir` function.
 pub type FindResultDir<'a, S> = FindResult<&'a Dir<S>>;
 
 /// Result type for `find_dir_mut` function.
 pub type FindResultDirMut<'a, S> = FindResult<&'a mut Dir<S>>;
 
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FollowPolicy {
+    /// Returns the most links the policy follows while resolving a path.
+    pub fn get_max_depth(&self) -> u32 {
+        match self {
+            Self::Never => 0,
+            Self::FinalComponent { max_depth } | Self::Always { max_depth } => *max_depth,
+        }
+    }
+
+    /// Returns whether the policy follows a link at a component of a path, given whether it is
+    /// the final component.
+    pub fn follows(&self, is_final: bool) -> bool {
+        match self {
+            Self::Never => false,
+            Self::FinalComponent { .. } => is_final,
+            Self::Always { .. } => true,
+        }
+    }
+}
+
+impl ResolvedPath {
+    /// Returns the resolved path.
+    pub(crate) fn get_path(&self) -> Utf8UnixPathBuf {
+        self.segments
+            .iter()
+            .fold(Utf8UnixPathBuf::new(), |path, segment| {
+                path.join(segment.as_str())
+            })
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
@@ -52,6 +126,10 @@ pub type FindResultDirMut<'a, S> = FindResult<&'a mut Dir<S>>;
 /// following the path specified by `path`. It attempts to resolve each component of the path
 /// until it either finds the target directory, encounters an error, or determines that the path
 /// is not found or invalid.
+///
+/// [`SymCidLink`](crate::filesystem::SymCidLink)s in the path are followed to the entity they
+/// resolve to, up to [`DEFAULT_SYMLINK_DEPTH`](crate::config::DEFAULT_SYMLINK_DEPTH) links deep.
+/// Content addressing rules out cycles, so this bounds the length of a chain.
 pub(crate) async fn find_dir<S>(
     mut dir: &Dir<S>,
     path: impl AsRef<str>,
@@ -78,10 +156,15 @@ where
             Some(Entity::Dir(d)) => {
                 dir = d;
             }
-            Some(Entity::SymCidLink(_)) => {
-                // SymCidLinks are not supported yet, so we return an error
-                return Err(FsError::SymCidLinkNotSupportedYet(components));
-            }
+            Some(Entity::SymCidLink(symlink)) => match symlink.resolve().await? {
+                Entity::Dir(d) => {
+                    dir = d;
+                }
+                _ => {
+                    // A SymCidLink to a non-directory entity is like the entity itself
+                    return Ok(FindResult::NotADir { depth });
+                }
+            },
             Some(_) => {
                 // If we encounter a non-directory entity in the middle of the path,
                 // we return NotADir result
@@ -104,6 +187,10 @@ where
 /// following the path specified by `path`. It attempts to resolve each component of the path
 /// until it either finds the target directory, encounters an error, or determines that the path
 /// is not found or invalid.
+///
+/// [`SymCidLink`](crate::filesystem::SymCidLink)s in the path are followed like in `find_dir`.
+/// Changes made below one are kept in the link, see
+/// [`SymCidLink::resolve_mut`](crate::filesystem::SymCidLink::resolve_mut).
 pub(crate) async fn find_dir_mut<S>(
     mut dir: &mut Dir<S>,
     path: impl AsRef<str>,
@@ -131,9 +218,21 @@ where
                 // A hack to get a mutable reference to the directory
                 dir = dir.get_dir_mut(segment).await?.unwrap();
             }
-            Some(Entity::SymCidLink(_)) => {
-                // SymCidLinks are not supported yet, so we return an error
-                return Err(FsError::SymCidLinkNotSupportedYet(components));
+            Some(Entity::SymCidLink(symlink)) => {
+                // Check the target before getting a mutable reference, which decodes the link
+                if !matches!(symlink.resolve().await?, Entity::Dir(_)) {
+                    return Ok(FindResult::NotADir { depth });
+                }
+
+                let Some(Entity::SymCidLink(symlink)) = dir.get_entity_mut(segment).await? else {
+                    unreachable!("entry was checked to be a SymCidLink");
+                };
+
+                let Entity::Dir(d) = symlink.resolve_mut().await? else {
+                    unreachable!("target was checked to be a directory");
+                };
+
+                dir = d;
             }
             Some(_) => {
                 // If we encounter a non-directory entity in the middle of the path,
@@ -151,6 +250,100 @@ where
     Ok(FindResult::Found { dir })
 }
 
+/// Resolves the symbolic links in `path` as `policy` allows.
+///
+/// Links are followed one at a time. A [`SymPathLink`](crate::filesystem::SymPathLink) is replaced
+/// by its target, relative to the directory holding it, and the resolution starts over from `dir`
+/// with the new path. A [`SymCidLink`](crate::filesystem::SymCidLink) stays in the path, as
+/// entities below it are only reached through it.
+///
+/// Returns `None` if an entity on the way is not found, is not a directory or is a symbolic link
+/// that `policy` does not follow.
+pub(crate) async fn resolve_path<S>(
+    dir: &Dir<S>,
+    path: impl AsRef<str>,
+    policy: FollowPolicy,
+) -> FsResult<Option<ResolvedPath>>
+where
+    S: IpldStore + Send + Sync,
+{
+    let mut pending = get_segments(path.as_ref())?;
+    let mut segments = Vec::new();
+    let mut dirs = vec![dir];
+    let mut follows = 0;
+
+    'components: while let Some(segment) = pending.pop_front() {
+        let is_final = pending.is_empty();
+        let follow = policy.follows(is_final);
+
+        let Some(mut entity) = dirs[dirs.len() - 1].get_entity(&segment).await? else {
+            return Ok(None);
+        };
+
+        let is_symcidlink = matches!(entity, Entity::SymCidLink(_));
+        loop {
+            match entity {
+                Entity::SymCidLink(symlink) if follow => {
+                    follows += 1;
+                    if follows > policy.get_max_depth() {
+                        return Err(FsError::MaxFollowDepthReached);
+                    }
+
+                    entity = symlink.follow_once().await?;
+                }
+                Entity::SymPathLink(symlink) if follow => {
+                    follows += 1;
+                    if follows > policy.get_max_depth() {
+                        return Err(FsError::MaxFollowDepthReached);
+                    }
+
+                    let target = symlink.get_target_path();
+                    let escapes = || FsError::SymPathLinkEscapesRoot(target.to_string());
+                    if target.has_root() {
+                        return Err(escapes());
+                    }
+
+                    let mut target_path = segments
+                        .iter()
+                        .fold(Utf8UnixPathBuf::new(), |path, segment| {
+                            path.join(segment.as_str())
+                        });
+                    target_path.push(target);
+
+                    let mut rewritten =
+                        get_segments(target_path.as_str()).map_err(|_| escapes())?;
+                    rewritten.extend(pending);
+                    pending = rewritten;
+
+                    segments.clear();
+                    dirs.truncate(1);
+                    continue 'components;
+                }
+                _ => break,
+            }
+        }
+
+        if is_final {
+            segments.push(segment);
+            return Ok(Some(ResolvedPath {
+                segments,
+                follow_last: follow && is_symcidlink,
+            }));
+        }
+
+        match entity {
+            Entity::Dir(d) => {
+                segments.push(segment);
+                dirs.push(d);
+            }
+            _ => return Ok(None),
+        }
+    }
+
+    // A link to the directory the path is resolved in leaves nothing to resolve
+    Err(FsError::InvalidSearchPath(path.as_ref().to_string()))
+}
+
 /// Retrieves an existing entity or creates a new one at the specified path.
 ///
 /// This function checks the existence of an entity at the given path. If the entity
@@ -207,21 +400,38 @@ where
     }
 }
 
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Normalizes `path` and splits it into its components.
+fn get_segments(path: &str) -> FsResult<VecDeque<Utf8UnixPathSegment>> {
+    let normalized_path =
+        microsandbox_utils::normalize_path(path, microsandbox_utils::SupportedPathType::Relative)
+            .map_err(|_| FsError::InvalidSearchPath(path.to_string()))?;
+
+    Utf8UnixPath::new(&normalized_path)
+        .components()
+        .filter_map(|c| match c {
+            Utf8UnixComponent::Normal(s) => Some(Utf8UnixPathSegment::try_from(s)),
+            _ => None,
+        })
+        .collect()
+}
+
 //--------------------------------------------------------------------------------------------------
 // Tests
 //--------------------------------------------------------------------------------------------------
 
 #[cfg(test)]
 mod tests {
-    use ipldstore::MemoryStore;
+    use ipldstore::{ipld::cid::Cid, MemoryStore, Storable};
 
-    use crate::filesystem::File;
+    use crate::filesystem::{File, SymCidLink};
 
     use super::*;
 
     mod fixtures {
-        use ipldstore::Storable;
-
         use super::*;
 
         pub(super) async fn setup_test_filesystem() -> anyhow::Result<Dir<MemoryStore>> {
@@ -361,6 +571,103 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_find_dir_through_symcidlink() -> anyhow::Result<()> {
+        let mut root = fixtures::setup_test_filesystem().await?;
+        let store = root.get_store().clone();
+
+        // A tree shared by CID, reached through a chain of two links
+        let mut shared = Dir::new(store.clone());
+        shared.find_or_create("bin/sh", true).await?;
+        let shared_cid = shared.store().await?;
+        let link = SymCidLink::with_cid(store.clone(), shared_cid);
+        root.create_symcidlink("image", link.store().await?).await?;
+
+        let result = find_dir(&root, "image/bin").await?;
+        assert!(matches!(result, FindResult::Found { dir } if dir.get_entry("sh")?.is_some()));
+
+        // Links to files and broken links
+        let file_cid = File::new(store.clone()).store().await?;
+        root.create_symcidlink("file", file_cid).await?;
+        root.create_symcidlink("broken", Cid::default()).await?;
+
+        let result = find_dir(&root, "file/invalid").await?;
+        assert!(matches!(result, FindResult::NotADir { depth: 0 }));
+
+        let result = find_dir(&root, "broken/invalid").await;
+        assert!(matches!(result, Err(FsError::BrokenSymCidLink(_))));
+
+        // Changes below a link are kept in the link and leave the shared tree as it was
+        let result = find_dir_mut(&mut root, "image/bin").await?;
+        let FindResult::Found { dir } = result else {
+            panic!("expected image/bin to be found");
+        };
+        dir.find_or_create("ls", true).await?;
+
+        assert!(root.find("image/bin/ls").await?.is_some());
+        let shared = Dir::load(&shared_cid, store).await?;
+        assert!(shared.find("bin/ls").await?.is_none());
+
+        let result = find_dir_mut(&mut root, "broken/invalid").await;
+        assert!(matches!(result, Err(FsError::BrokenSymCidLink(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resolve_path_follow_policy() -> anyhow::Result<()> {
+        let mut root = fixtures::setup_test_filesystem().await?;
+        root.create_sympathlink("link", "subdir1/subdir2").await?;
+        root.create_sympathlink("subdir1/up", "../link").await?;
+        root.create_sympathlink("subdir1/escaping", "../../etc")
+            .await?;
+        root.create_sympathlink("subdir1/absolute", "/etc").await?;
+        root.create_sympathlink("loop", "loop").await?;
+
+        let always = FollowPolicy::Always { max_depth: 10 };
+        let final_component = FollowPolicy::FinalComponent { max_depth: 10 };
+        let path_of = |resolved: Option<ResolvedPath>| resolved.map(|r| r.get_path().to_string());
+
+        // Links are followed where the policy allows
+        let resolved = resolve_path(&root, "subdir1/up/file2.txt", always).await?;
+        assert_eq!(
+            path_of(resolved).as_deref(),
+            Some("subdir1/subdir2/file2.txt")
+        );
+
+        let resolved = resolve_path(&root, "subdir1/up/file2.txt", final_component).await?;
+        assert_eq!(resolved, None);
+
+        let resolved = resolve_path(&root, "link", final_component).await?;
+        assert_eq!(path_of(resolved).as_deref(), Some("subdir1/subdir2"));
+
+        let resolved = resolve_path(&root, "link", FollowPolicy::Never).await?;
+        assert_eq!(path_of(resolved).as_deref(), Some("link"));
+
+        let resolved = resolve_path(&root, "link/file2.txt", FollowPolicy::Never).await?;
+        assert_eq!(resolved, None);
+
+        // Links do not lead outside of the directory the path is resolved in
+        let result = resolve_path(&root, "subdir1/escaping", always).await;
+        assert!(matches!(result, Err(FsError::SymPathLinkEscapesRoot(_))));
+
+        let result = resolve_path(&root, "subdir1/absolute", always).await;
+        assert!(matches!(result, Err(FsError::SymPathLinkEscapesRoot(_))));
+
+        let subdir1 = root.get_dir("subdir1").await?.unwrap();
+        let result = resolve_path(subdir1, "up", always).await;
+        assert!(matches!(result, Err(FsError::SymPathLinkEscapesRoot(_))));
+
+        // Cycles stop at the depth of the policy
+        let result = resolve_path(&root, "loop", always).await;
+        assert!(matches!(result, Err(FsError::MaxFollowDepthReached)));
+
+        let result = resolve_path(&root, "link", FollowPolicy::Always { max_depth: 0 }).await;
+        assert!(matches!(result, Err(FsError::MaxFollowDepthReached)));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_find_or_create_dir() -> anyhow::Result<()> {
         let mut root = fixtures::setup_test_filesystem().await?;
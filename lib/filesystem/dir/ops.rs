@@ -1,5 +1,5 @@
 use ipldstore::{ipld::cid::Cid, IpldStore};
-use typed_path::Utf8UnixPath;
+use typed_path::{Utf8UnixPath, Utf8UnixPathBuf};
 
 use crate::{
     filesystem::{dir::find, entity::Entity, file::File, SymCidLink, SymPathLink},
@@ -7,7 +7,7 @@ use crate::{
     FsError, FsResult,
 };
 
-use super::{Dir, FindResult, Utf8UnixPathSegment};
+use super::{Dir, FindResult, FollowPolicy, Utf8UnixPathSegment};
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -104,6 +104,127 @@ where
         self.get_entity_mut(&file_name).await
     }
 
+    /// Finds an entity in the directory structure given a path, following the symbolic links on
+    /// the way as `policy` allows.
+    ///
+    /// Unlike [`find`][Self::find], which goes through [`SymCidLink`]s but stops at any other
+    /// link, this method follows both kinds of links, and can also follow a link at the final
+    /// component of the path. See [`FollowPolicy`] for how far links are followed.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use monofs::filesystem::{Dir, Entity, FollowPolicy};
+    /// use ipldstore::MemoryStore;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut dir = Dir::new(MemoryStore::default());
+    /// dir.find_or_create("foo/bar.txt", true).await?;
+    /// dir.create_sympathlink("link", "foo").await?;
+    ///
+    /// let policy = FollowPolicy::Always { max_depth: 10 };
+    /// let entity = dir.find_with("link/bar.txt", policy).await?;
+    /// assert!(matches!(entity, Some(Entity::File(_))));
+    ///
+    /// // Without following links, the path does not lead anywhere
+    /// assert!(dir.find_with("link/bar.txt", FollowPolicy::Never).await?.is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn find_with(
+        &self,
+        path: impl AsRef<str>,
+        policy: FollowPolicy,
+    ) -> FsResult<Option<&Entity<S>>> {
+        let path = Utf8UnixPath::new(path.as_ref());
+
+        if path.has_root() {
+            return Err(FsError::PathHasRoot(path.to_string()));
+        }
+
+        let Some(resolved) = find::resolve_path(self, path, policy).await? else {
+            return Ok(None);
+        };
+
+        match self.find(resolved.get_path()).await? {
+            Some(Entity::SymCidLink(symlink)) if resolved.follow_last => {
+                Ok(Some(symlink.resolve().await?))
+            }
+            entity => Ok(entity),
+        }
+    }
+
+    /// Finds an entity in the directory structure given a path, following the symbolic links on
+    /// the way as `policy` allows, and returns a mutable reference.
+    ///
+    /// This method is similar to [`find_with`][Self::find_with], but it returns a mutable
+    /// reference to the found entity. Changes to entities reached through a [`SymCidLink`] are
+    /// kept in the link, see [`SymCidLink::resolve_mut`].
+    pub async fn find_mut_with(
+        &mut self,
+        path: impl AsRef<str>,
+        policy: FollowPolicy,
+    ) -> FsResult<Option<&mut Entity<S>>> {
+        let path = Utf8UnixPath::new(path.as_ref());
+
+        if path.has_root() {
+            return Err(FsError::PathHasRoot(path.to_string()));
+        }
+
+        let Some(resolved) = find::resolve_path(self, path, policy).await? else {
+            return Ok(None);
+        };
+
+        match self.find_mut(resolved.get_path()).await? {
+            Some(Entity::SymCidLink(symlink)) if resolved.follow_last => {
+                Ok(Some(symlink.resolve_mut().await?))
+            }
+            entity => Ok(entity),
+        }
+    }
+
+    /// Resolves the symbolic links in a path as `policy` allows, and returns the path they lead
+    /// to, or `None` if the path does not lead anywhere.
+    ///
+    /// Every [`SymPathLink`] on the way is replaced by the path it leads to. A [`SymCidLink`]
+    /// stays in the path though, as entities below it are only reached through it, so
+    /// [`find`][Self::find] on the returned path gives the link itself if it is the final
+    /// component.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use monofs::filesystem::{Dir, FollowPolicy};
+    /// use ipldstore::MemoryStore;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut dir = Dir::new(MemoryStore::default());
+    /// dir.find_or_create("foo/bar.txt", true).await?;
+    /// dir.create_sympathlink("foo/link", "bar.txt").await?;
+    ///
+    /// let policy = FollowPolicy::FinalComponent { max_depth: 10 };
+    /// let path = dir.resolve_path("foo/link", policy).await?;
+    /// assert_eq!(path.unwrap().as_str(), "foo/bar.txt");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn resolve_path(
+        &self,
+        path: impl AsRef<str>,
+        policy: FollowPolicy,
+    ) -> FsResult<Option<Utf8UnixPathBuf>> {
+        let path = Utf8UnixPath::new(path.as_ref());
+
+        if path.has_root() {
+            return Err(FsError::PathHasRoot(path.to_string()));
+        }
+
+        let resolved = find::resolve_path(self, path, policy).await?;
+        Ok(resolved.map(|resolved| resolved.get_path()))
+    }
+
     /// Finds an entity in the directory structure or creates it if it doesn't exist.
     ///
     /// This method traverses the directory structure to find the entity specified by the path.
@@ -423,6 +544,85 @@ where
         Ok(())
     }
 
+    /// Copies the entry at the source path to the target path by reference.
+    ///
+    /// Unlike [`copy`][Self::copy], which places the copy in a target **directory**, the copy is
+    /// created at exactly the target path, like `cp --reflink`. The copy links to the same
+    /// content as the source. Only the entry itself is loaded, and none of the file contents or
+    /// subdirectories below it are read or duplicated, so copying a large tree takes as long as
+    /// copying a single file. The two entries diverge as either of them is changed.
+    ///
+    /// The parent of the target path must be a directory, and nothing may exist at the target
+    /// path yet.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use monofs::filesystem::{Dir, Entity};
+    /// use ipldstore::MemoryStore;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut dir = Dir::new(MemoryStore::default());
+    /// dir.find_or_create("source/nested/file.txt", true).await?;
+    ///
+    /// dir.copy_entry("source", "backup").await?;
+    ///
+    /// assert!(dir.find("backup/nested/file.txt").await?.is_some());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn copy_entry(
+        &mut self,
+        src_path: impl AsRef<str>,
+        dst_path: impl AsRef<str>,
+    ) -> FsResult<()> {
+        tracing::trace!(
+            "copy_entry: src_path: {:?}, dst_path: {:?}",
+            src_path.as_ref(),
+            dst_path.as_ref()
+        );
+        let src_path = Utf8UnixPath::new(src_path.as_ref());
+        let dst_path = Utf8UnixPath::new(dst_path.as_ref());
+
+        if src_path.has_root() || dst_path.has_root() {
+            return Err(FsError::PathHasRoot(src_path.to_string()));
+        }
+
+        let (src_parent, src_filename) = path::split_last(src_path)?;
+        let (dst_parent, dst_filename) = path::split_last(dst_path)?;
+
+        // Get the link of the source entry, which is shared without resolving it
+        let src_link = if let Some(parent_path) = src_parent {
+            match find::find_dir(self, parent_path).await? {
+                find::FindResult::Found { dir } => dir.get_entry(&src_filename)?,
+                _ => return Err(FsError::PathNotFound(src_path.to_string())),
+            }
+        } else {
+            self.get_entry(&src_filename)?
+        };
+
+        let src_link = src_link
+            .ok_or_else(|| FsError::PathNotFound(src_path.to_string()))?
+            .clone();
+
+        // Add the link to the target directory
+        let dst_dir = if let Some(parent_path) = dst_parent {
+            match find::find_dir_mut(self, parent_path).await? {
+                find::FindResult::Found { dir } => dir,
+                _ => return Err(FsError::TargetIsNotADir(parent_path.to_string())),
+            }
+        } else {
+            self
+        };
+
+        if dst_dir.has_entry(&dst_filename)? {
+            return Err(FsError::PathExists(dst_path.to_string()));
+        }
+
+        dst_dir.put_adapted_entry(dst_filename, src_link).await
+    }
+
     /// Removes an entity at the specified path by marking it as deleted.
     ///
     /// ## Examples
@@ -762,6 +962,66 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_ops_copy_entry() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut dir = Dir::new(store.clone());
+
+        let file = File::with_content(store.clone(), b"Hello, World!".as_slice()).await?;
+        dir.find_or_create("source/nested", false).await?;
+        if let Some(Entity::Dir(nested)) = dir.find_mut("source/nested").await? {
+            nested.put_adapted_file("file.txt", file).await?;
+        }
+
+        // Copies of stored entries share their links
+        let cid = dir.checkpoint().await?;
+        let mut dir = Dir::load(&cid, store.clone()).await?;
+        dir.copy_entry("source", "backup").await?;
+        dir.copy_entry("source/nested/file.txt", "copy.txt").await?;
+
+        let source = match dir.find("source/nested").await? {
+            Some(Entity::Dir(dir)) => dir.get_entry("file.txt")?.unwrap().get_cid().cloned(),
+            _ => panic!("expected a directory"),
+        };
+        let backup = match dir.find("backup/nested").await? {
+            Some(Entity::Dir(dir)) => dir.get_entry("file.txt")?.unwrap().get_cid().cloned(),
+            _ => panic!("expected a directory"),
+        };
+        assert!(source.is_some());
+        assert_eq!(source, backup);
+
+        let (original, copy) = match (
+            dir.find("source/nested/file.txt").await?,
+            dir.find("copy.txt").await?,
+        ) {
+            (Some(Entity::File(original)), Some(Entity::File(copy))) => {
+                (original.get_content().cloned(), copy.get_content().cloned())
+            }
+            _ => panic!("expected files"),
+        };
+        assert_eq!(original, copy);
+
+        // The copies diverge from the source as they change
+        dir.remove("backup/nested/file.txt").await?;
+        assert!(dir.find("source/nested/file.txt").await?.is_some());
+
+        // The target must not exist and its parent must be a directory
+        assert!(matches!(
+            dir.copy_entry("source", "copy.txt").await,
+            Err(FsError::PathExists(_))
+        ));
+        assert!(matches!(
+            dir.copy_entry("source", "copy.txt/source").await,
+            Err(FsError::TargetIsNotADir(_))
+        ));
+        assert!(matches!(
+            dir.copy_entry("missing", "other").await,
+            Err(FsError::PathNotFound(_))
+        ));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_ops_remove() -> FsResult<()> {
         let mut dir = Dir::new(MemoryStore::default());
@@ -0,0 +1,232 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use ipldstore::{ipld::cid::Cid, IpldStore, Storable, StoreError, StoreResult};
+
+use crate::{
+    filesystem::{Dir, DirSerializable, Link, Utf8UnixPathSegment, DIR_TYPE_TAG},
+    FsResult,
+};
+
+use super::{
+    shard::{self, EntriesSerializable},
+    Entry,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+/// Overlay operations.
+///
+/// An overlay is a directory layered over a base directory, stored by its CID. Its entries are
+/// those of the base, unless the overlay has an entry of the same name of its own, which shadows
+/// the entry of the base. A deleted entry of its own is a whiteout, hiding the entry of the base.
+/// Only its own entries are stored with the overlay, so any number of overlays can share a large
+/// base, like a container image, while each only stores what changed in it.
+///
+/// Changing an entry of the base copies it up into the overlay. A directory copied up becomes an
+/// overlay of the directory it was in the base, so changing a file deep in the base only stores
+/// the directories on the way to it, and each of them only with the entries that changed.
+impl<S> Dir<S>
+where
+    S: IpldStore,
+{
+    /// Creates an empty overlay of the directory at `base`, with its entries and metadata.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use monofs::filesystem::Dir;
+    /// use ipldstore::MemoryStore;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let store = MemoryStore::default();
+    /// let mut image = Dir::new(store.clone());
+    /// image.find_or_create("etc/hosts", true).await?;
+    /// let base = image.checkpoint().await?;
+    ///
+    /// let mut overlay = Dir::overlay(&base, store.clone()).await?;
+    /// overlay.find_or_create("tmp/scratch", true).await?;
+    /// overlay.remove("etc/hosts").await?;
+    ///
+    /// assert_eq!(overlay.get_base(), Some(&base));
+    /// assert!(overlay.find("etc/hosts").await?.is_none());
+    /// assert!(Dir::load(&base, store).await?.find("etc/hosts").await?.is_some());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn overlay(base: &Cid, store: S) -> FsResult<Self>
+    where
+        S: Send + Sync,
+    {
+        let mut dir = Dir::load(base, store).await?;
+        dir.layer_over(*base);
+
+        let inner = Arc::make_mut(&mut dir.inner);
+        inner.initial_load_cid = Default::default();
+        inner.previous = None;
+
+        Ok(dir)
+    }
+
+    /// Returns the CID of the directory this directory is an overlay of, if it is one.
+    pub fn get_base(&self) -> Option<&Cid> {
+        self.inner.base.as_ref()
+    }
+
+    /// Returns whether the entry with the given name is read from the base of the directory and
+    /// has not been copied up.
+    pub fn is_inherited(&self, name: impl AsRef<str>) -> FsResult<bool> {
+        let name: Utf8UnixPathSegment = name.as_ref().parse()?;
+        Ok(self
+            .inner
+            .entries
+            .get(&name)
+            .is_some_and(|entry| entry.inherited))
+    }
+
+    /// Makes the directory, as loaded from `base`, an overlay of `base` without changes of its
+    /// own.
+    pub(super) fn layer_over(&mut self, base: Cid) {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.base = Some(base);
+        for entry in inner.entries.values_mut() {
+            entry.inherited = true;
+        }
+    }
+
+    /// Makes the directory an overlay of `base`, adding the `entries` it inherits from it where it
+    /// has no entry of its own.
+    pub(super) fn inherit_entries(
+        &mut self,
+        base: Cid,
+        entries: EntriesSerializable,
+    ) -> FsResult<()> {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.base = Some(base);
+        for (name, (deleted, cid)) in entries {
+            inner.entries.entry(name.parse()?).or_insert(Entry {
+                deleted,
+                inherited: true,
+                link: Link::from(cid),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Loads the entries an overlay of the directory at `base` inherits, which are the entries of
+/// `base` and those it inherits from its own base, and so on.
+///
+/// Whiteouts are included, so that they keep hiding the entries of the layers below them.
+pub(super) async fn load_base_entries<S>(store: &S, base: &Cid) -> StoreResult<EntriesSerializable>
+where
+    S: IpldStore + Send + Sync,
+{
+    let mut entries = BTreeMap::new();
+    let mut next = Some(*base);
+    while let Some(cid) = next {
+        let mut layer: DirSerializable = store.get_node(&cid).await?;
+        if layer.r#type != DIR_TYPE_TAG {
+            return Err(StoreError::custom(format!(
+                "block {} is not a directory",
+                cid
+            )));
+        }
+
+        if let Some(shards) = layer.shards.take() {
+            layer.entries = shard::load_shards(store, &shards).await?.into_entries();
+        }
+
+        for (name, entry) in layer.entries {
+            entries.entry(name).or_insert(entry);
+        }
+
+        next = layer.base;
+    }
+
+    Ok(entries)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::MemoryStore;
+
+    use crate::filesystem::{Entity, File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dir_overlay() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut image = Dir::new(store.clone());
+        for path in ["bin/sh", "etc/hosts", "etc/passwd", "usr/lib/libc.so"] {
+            image.find_or_create(path, true).await?;
+        }
+        let base = image.checkpoint().await?;
+
+        // Changes shadow the base, and deletions hide entries of the base
+        let mut overlay = Dir::overlay(&base, store.clone()).await?;
+        let file = File::with_content(store.clone(), b"127.0.0.1 sandbox".as_slice()).await?;
+        let Some(Entity::Dir(etc)) = overlay.find_mut("etc").await? else {
+            unreachable!()
+        };
+        etc.put_adapted_file("hosts", file).await?;
+        overlay.remove("etc/passwd").await?;
+        overlay.find_or_create("tmp/scratch", true).await?;
+        let cid = overlay.checkpoint().await?;
+
+        let loaded = Dir::load(&cid, store.clone()).await?;
+        let mut names = loaded
+            .get_entry_names()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["bin", "etc", "tmp", "usr"]);
+        assert!(loaded.find("etc/passwd").await?.is_none());
+        assert!(loaded.find("usr/lib/libc.so").await?.is_some());
+        let Some(Entity::File(hosts)) = loaded.find("etc/hosts").await? else {
+            unreachable!()
+        };
+        assert_eq!(hosts.get_size().await?, 17);
+
+        // Only the changes are stored with the overlay, at every level
+        let serializable: DirSerializable = store.get_node(&cid).await?;
+        assert_eq!(serializable.base, Some(base));
+        assert_eq!(
+            serializable.entries.keys().collect::<Vec<_>>(),
+            ["etc", "tmp"]
+        );
+        assert!(loaded.is_inherited("usr")?);
+        let Some(Entity::Dir(etc)) = loaded.find("etc").await? else {
+            unreachable!()
+        };
+        let serializable: DirSerializable = store.get_node(&etc.store().await?).await?;
+        assert!(serializable.base.is_some());
+        assert_eq!(
+            serializable.entries.keys().collect::<Vec<_>>(),
+            ["hosts", "passwd"]
+        );
+        assert!(serializable.entries["passwd"].0);
+
+        // The base is untouched, and overlays of overlays see every layer
+        let image = Dir::load(&base, store.clone()).await?;
+        assert!(image.find("etc/passwd").await?.is_some());
+        let upper = Dir::overlay(&cid, store.clone()).await?;
+        assert!(upper.find("tmp/scratch").await?.is_some());
+        assert!(upper.find("etc/passwd").await?.is_none());
+        assert!(upper.find("bin/sh").await?.is_some());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,344 @@
+use async_recursion::async_recursion;
+use getset::Getters;
+use ipldstore::IpldStore;
+use typed_path::{Utf8UnixPath, Utf8UnixPathBuf};
+
+use crate::{
+    filesystem::{entity::Entity, kind::EntityType},
+    utils::path,
+    FsError, FsResult,
+};
+
+use super::Dir;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An entity handled by a recursive directory operation, as reported to its progress callback.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct RecursiveProgress {
+    /// The path of the entity relative to the directory the operation was called on.
+    path: Utf8UnixPathBuf,
+
+    /// The type of the entity.
+    entity_type: EntityType,
+
+    /// The number of entities handled so far, including this one.
+    count: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+/// Recursive directory operations.
+///
+/// Symbolic links are handled as entries of their own. They are removed, copied and moved
+/// themselves, and the entities they point to are never visited.
+impl<S> Dir<S>
+where
+    S: IpldStore + Send + Sync,
+{
+    /// Removes the entity at the specified path along with everything below it.
+    ///
+    /// Every removed entity is reported to `progress` before its parent directory, ending with
+    /// the entity at `path` itself.
+    ///
+    /// ## Returns
+    /// The number of entities removed
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use monofs::filesystem::Dir;
+    /// use ipldstore::MemoryStore;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut dir = Dir::new(MemoryStore::default());
+    /// dir.find_or_create("foo/bar/baz.txt", true).await?;
+    ///
+    /// let mut removed = Vec::new();
+    /// let count = dir
+    ///     .remove_recursive("foo", |progress| removed.push(progress.get_path().to_string()))
+    ///     .await?;
+    ///
+    /// assert_eq!(count, 3);
+    /// assert_eq!(removed, ["foo/bar/baz.txt", "foo/bar", "foo"]);
+    /// assert!(dir.find("foo").await?.is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn remove_recursive(
+        &mut self,
+        path: impl AsRef<str>,
+        mut progress: impl FnMut(&RecursiveProgress) + Send,
+    ) -> FsResult<u64> {
+        tracing::trace!("remove_recursive: path: {:?}", path.as_ref());
+        let path = Utf8UnixPath::new(path.as_ref());
+
+        let entity = self
+            .find(path)
+            .await?
+            .ok_or_else(|| FsError::PathNotFound(path.to_string()))?;
+
+        let mut count = 0;
+        walk(entity, path, false, &mut count, &mut progress).await?;
+        self.remove(path).await?;
+
+        Ok(count)
+    }
+
+    /// Copies the entity at the source path, along with everything below it, to the target path.
+    ///
+    /// The copy is made by reference like [`copy_entry`][Self::copy_entry], so no file contents
+    /// are duplicated. Every copied entity is then reported to `progress` with its path in the
+    /// copy, before the entities below it. The target path must not be inside the source path.
+    ///
+    /// ## Returns
+    /// The number of entities copied
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use monofs::filesystem::Dir;
+    /// use ipldstore::MemoryStore;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut dir = Dir::new(MemoryStore::default());
+    /// dir.find_or_create("foo/bar/baz.txt", true).await?;
+    ///
+    /// let count = dir.copy_recursive("foo", "qux", |_| {}).await?;
+    ///
+    /// assert_eq!(count, 3);
+    /// assert!(dir.find("foo/bar/baz.txt").await?.is_some());
+    /// assert!(dir.find("qux/bar/baz.txt").await?.is_some());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn copy_recursive(
+        &mut self,
+        src_path: impl AsRef<str>,
+        dst_path: impl AsRef<str>,
+        mut progress: impl FnMut(&RecursiveProgress) + Send,
+    ) -> FsResult<u64> {
+        tracing::trace!(
+            "copy_recursive: src_path: {:?}, dst_path: {:?}",
+            src_path.as_ref(),
+            dst_path.as_ref()
+        );
+        let src_path = Utf8UnixPath::new(src_path.as_ref());
+        let dst_path = Utf8UnixPath::new(dst_path.as_ref());
+
+        if is_within(dst_path, src_path)? {
+            return Err(FsError::InvalidOperation(format!(
+                "cannot copy {} into itself at {}",
+                src_path, dst_path
+            )));
+        }
+
+        self.copy_entry(src_path, dst_path).await?;
+
+        let entity = self
+            .find(dst_path)
+            .await?
+            .ok_or_else(|| FsError::PathNotFound(dst_path.to_string()))?;
+
+        let mut count = 0;
+        walk(entity, dst_path, true, &mut count, &mut progress).await?;
+
+        Ok(count)
+    }
+
+    /// Moves the entity at the source path to the target path.
+    ///
+    /// If the target path is an existing directory, the entity is moved into it under its own
+    /// name, like `mv`. A symbolic link at the target path is not followed, so it makes the move
+    /// fail like any other existing entity. The target path must not be inside the source path.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use monofs::filesystem::Dir;
+    /// use ipldstore::MemoryStore;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut dir = Dir::new(MemoryStore::default());
+    /// dir.find_or_create("foo/bar.txt", true).await?;
+    /// dir.find_or_create("baz", false).await?;
+    ///
+    /// dir.move_entry("foo", "baz").await?;
+    ///
+    /// assert!(dir.find("foo").await?.is_none());
+    /// assert!(dir.find("baz/foo/bar.txt").await?.is_some());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn move_entry(
+        &mut self,
+        src_path: impl AsRef<str>,
+        dst_path: impl AsRef<str>,
+    ) -> FsResult<()> {
+        tracing::trace!(
+            "move_entry: src_path: {:?}, dst_path: {:?}",
+            src_path.as_ref(),
+            dst_path.as_ref()
+        );
+        let src_path = Utf8UnixPath::new(src_path.as_ref());
+        let mut dst_path = Utf8UnixPath::new(dst_path.as_ref()).to_path_buf();
+
+        if let Some(Entity::Dir(_)) = self.find(dst_path.as_str()).await? {
+            let (_, src_filename) = path::split_last(src_path)?;
+            dst_path = dst_path.join(src_filename.as_str());
+        }
+
+        if is_within(&dst_path, src_path)? {
+            return Err(FsError::InvalidOperation(format!(
+                "cannot move {} into itself at {}",
+                src_path, dst_path
+            )));
+        }
+
+        self.rename(src_path, dst_path.as_str()).await
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Reports `entity` at `path` and everything below it to `progress`, with directories reported
+/// before their entries if `parents_first` is set and after them otherwise.
+#[async_recursion]
+async fn walk<S, F>(
+    entity: &Entity<S>,
+    path: &Utf8UnixPath,
+    parents_first: bool,
+    count: &mut u64,
+    progress: &mut F,
+) -> FsResult<()>
+where
+    S: IpldStore + Send + Sync,
+    F: FnMut(&RecursiveProgress) + Send,
+{
+    let entity_type = *entity.get_metadata().get_entity_type();
+    if parents_first {
+        report(path, entity_type, count, progress);
+    }
+
+    if let Entity::Dir(dir) = entity {
+        let names = dir
+            .get_entry_names()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+
+        for name in names {
+            if let Some(child) = dir.get_entity(&name).await? {
+                walk(child, &path.join(&name), parents_first, count, progress).await?;
+            }
+        }
+    }
+
+    if !parents_first {
+        report(path, entity_type, count, progress);
+    }
+
+    Ok(())
+}
+
+/// Counts the entity at `path` as handled and reports it to `progress`.
+fn report(
+    path: &Utf8UnixPath,
+    entity_type: EntityType,
+    count: &mut u64,
+    progress: &mut impl FnMut(&RecursiveProgress),
+) {
+    *count += 1;
+    progress(&RecursiveProgress {
+        path: path.to_path_buf(),
+        entity_type,
+        count: *count,
+    });
+}
+
+/// Returns whether `path` is `ancestor` or inside it, after both are normalized.
+fn is_within(path: &Utf8UnixPath, ancestor: &Utf8UnixPath) -> FsResult<bool> {
+    let normalize = |path: &Utf8UnixPath| {
+        microsandbox_utils::normalize_path(
+            path.as_str(),
+            microsandbox_utils::SupportedPathType::Relative,
+        )
+        .map_err(|_| FsError::InvalidSearchPath(path.to_string()))
+    };
+
+    let path = Utf8UnixPathBuf::from(normalize(path)?);
+    let ancestor = Utf8UnixPathBuf::from(normalize(ancestor)?);
+    Ok(path.starts_with(&ancestor))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::MemoryStore;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_recursive_operations() -> anyhow::Result<()> {
+        let mut dir = Dir::new(MemoryStore::default());
+        dir.find_or_create("src/a.txt", true).await?;
+        dir.find_or_create("src/nested/b.txt", true).await?;
+        dir.find_or_create("target/c.txt", true).await?;
+        dir.create_sympathlink("src/link", "../target").await?;
+
+        // Copies report parents before their entries, and do not follow symbolic links
+        let mut copied = Vec::new();
+        let count = dir
+            .copy_recursive("src", "copy", |progress| {
+                copied.push((progress.get_path().to_string(), *progress.get_entity_type()))
+            })
+            .await?;
+        assert_eq!(count, 5);
+        assert_eq!(copied[0], ("copy".to_string(), EntityType::Dir));
+        assert!(copied.contains(&("copy/link".to_string(), EntityType::SymPathLink)));
+        assert!(!copied.iter().any(|(path, _)| path.contains("c.txt")));
+        assert!(dir.find("copy/nested/b.txt").await?.is_some());
+
+        assert!(matches!(
+            dir.copy_recursive("src", "src/nested/copy", |_| {}).await,
+            Err(FsError::InvalidOperation(_))
+        ));
+
+        // Moves into an existing directory keep their name
+        dir.find_or_create("moved", false).await?;
+        dir.move_entry("copy", "moved").await?;
+        assert!(dir.find("copy").await?.is_none());
+        assert!(dir.find("moved/copy/nested/b.txt").await?.is_some());
+
+        assert!(matches!(
+            dir.move_entry("moved", "moved/copy/nested").await,
+            Err(FsError::InvalidOperation(_))
+        ));
+
+        // Removals report entries before their parents and leave link targets alone
+        let mut removed = Vec::new();
+        let count = dir
+            .remove_recursive("src", |progress| {
+                removed.push(progress.get_path().to_string())
+            })
+            .await?;
+        assert_eq!(count, 5);
+        assert_eq!(removed.last().map(String::as_str), Some("src"));
+        assert!(dir.find("src").await?.is_none());
+        assert!(dir.find("target/c.txt").await?.is_some());
+
+        Ok(())
+    }
+}
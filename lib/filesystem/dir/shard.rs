@@ -0,0 +1,504 @@
+use std::collections::BTreeMap;
+
+use async_recursion::async_recursion;
+use ipldstore::{
+    codetable::{Code, MultihashDigest},
+    ipld::cid::Cid,
+    IpldReferences, IpldStore, StoreError, StoreResult,
+};
+use serde::{Deserialize, Serialize};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The type identifier for the shards holding the entries of large directories.
+pub const DIR_SHARD_TYPE_TAG: &str = "monofs.dir.shard";
+
+/// The number of entries above which a directory stores its entries in shards instead of its own
+/// block.
+pub const DIR_SHARD_THRESHOLD: usize = 1024;
+
+/// The number of entries a slot of a shard holds inline before they move to a shard of their own.
+const DIR_SHARD_BUCKET_SIZE: usize = 16;
+
+/// The number of bits of the hash of a name that pick its slot in a shard.
+const DIR_SHARD_SLOT_BITS: usize = 4;
+
+/// The depth of the deepest shards, at which the 256 bits of the hash of a name run out.
+const DIR_SHARD_MAX_DEPTH: usize = 256 / DIR_SHARD_SLOT_BITS - 1;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The serialized entries of a directory, keyed by name.
+pub(super) type EntriesSerializable = BTreeMap<String, (bool, Cid)>;
+
+/// A node of the hash array mapped trie that holds the entries of a large directory.
+///
+/// Entries are assigned to one of 16 slots by 4 bits of the hash of their name, the first 4 bits
+/// in the root shard, the next 4 in the shards below it, and so on. A slot holds its entries
+/// inline until there are too many of them, and then links to a shard that splits them up by the
+/// next bits. Adding or removing an entry therefore only creates new blocks for the shards on the
+/// way to its slot, whatever the size of the directory.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirShardSerializable {
+    /// The type of the node.
+    pub r#type: String,
+
+    /// The entries held inline, by slot.
+    buckets: BTreeMap<String, EntriesSerializable>,
+
+    /// The shards further down the trie, by slot.
+    shards: BTreeMap<String, Cid>,
+}
+
+/// The trie of shards a large directory was loaded from, kept so that storing the directory again
+/// only rewrites the shards on the way to the entries that changed.
+#[derive(Clone, Debug)]
+pub(super) struct DirShards {
+    /// The root shard of the trie.
+    root: DirShard,
+
+    /// The entries held by the trie, keyed by name.
+    entries: EntriesSerializable,
+}
+
+/// A loaded shard of a [`DirShards`] trie.
+#[derive(Clone, Debug)]
+struct DirShard {
+    /// The CID the shard is stored under.
+    cid: Cid,
+
+    /// The number of entries held by the shard and the shards below it.
+    len: usize,
+
+    /// The entries held inline, by slot.
+    buckets: BTreeMap<u8, EntriesSerializable>,
+
+    /// The shards further down the trie, by slot.
+    shards: BTreeMap<u8, DirShard>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl DirShards {
+    /// Returns the entries held by the trie.
+    pub(super) fn get_entries(&self) -> &EntriesSerializable {
+        &self.entries
+    }
+
+    /// Returns the entries held by the trie, dropping the trie.
+    pub(super) fn into_entries(self) -> EntriesSerializable {
+        self.entries
+    }
+}
+
+impl DirShard {
+    /// Adds the entries held by the shard and the shards below it to `entries`.
+    fn collect_entries(&self, entries: &mut EntriesSerializable) {
+        for bucket in self.buckets.values() {
+            entries.extend(bucket.iter().map(|(name, entry)| (name.clone(), *entry)));
+        }
+
+        for shard in self.shards.values() {
+            shard.collect_entries(entries);
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Stores `entries` in a trie of shards and returns the CID of its root shard.
+pub(super) async fn store_shards<S>(store: &S, entries: EntriesSerializable) -> StoreResult<Cid>
+where
+    S: IpldStore + Send + Sync,
+{
+    store_shard(store, entries, 0).await
+}
+
+/// Stores `entries` in place of the entries held by the loaded trie `shards` and returns the CID
+/// of the new root shard.
+///
+/// Only the shards on the way to the slots of the entries that were added, changed or removed are
+/// stored again, the CIDs of the others are reused as they are. The resulting trie is the same as
+/// the one [`store_shards`] would store for `entries`.
+pub(super) async fn update_shards<S>(
+    store: &S,
+    shards: &DirShards,
+    entries: EntriesSerializable,
+) -> StoreResult<Cid>
+where
+    S: IpldStore + Send + Sync,
+{
+    let mut changes: BTreeMap<String, Option<(bool, Cid)>> = shards
+        .entries
+        .keys()
+        .filter(|name| !entries.contains_key(*name))
+        .map(|name| (name.clone(), None))
+        .collect();
+
+    for (name, entry) in entries {
+        if shards.entries.get(&name) != Some(&entry) {
+            changes.insert(name, Some(entry));
+        }
+    }
+
+    update_shard(store, &shards.root, &shards.entries, changes, 0).await
+}
+
+/// Finds the entry named `name` in the trie of shards with the root shard `cid`, reading only the
+/// shards on the way to its slot.
+pub(super) async fn find_shard_entry<S>(
+    store: &S,
+    cid: &Cid,
+    name: &str,
+) -> StoreResult<Option<(bool, Cid)>>
+where
+    S: IpldStore + Send + Sync,
+{
+    let mut cid = *cid;
+    for depth in 0..=DIR_SHARD_MAX_DEPTH {
+        let shard: DirShardSerializable = store.get_node(&cid).await?;
+        if shard.r#type != DIR_SHARD_TYPE_TAG {
+            return Err(StoreError::custom(format!(
+                "block {} is not a directory shard",
+                cid
+            )));
+        }
+
+        let slot = get_slot(name, depth).to_string();
+        if let Some(bucket) = shard.buckets.get(&slot) {
+            return Ok(bucket.get(name).copied());
+        }
+
+        match shard.shards.get(&slot) {
+            Some(shard) => cid = *shard,
+            None => return Ok(None),
+        }
+    }
+
+    Ok(None)
+}
+
+/// Loads the trie of shards with the root shard `cid`, along with the entries it holds.
+pub(super) async fn load_shards<S>(store: &S, cid: &Cid) -> StoreResult<DirShards>
+where
+    S: IpldStore + Send + Sync,
+{
+    let mut entries = BTreeMap::new();
+    let root = load_shard(store, cid, &mut entries).await?;
+    Ok(DirShards { root, entries })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Stores the shard at `depth` of the trie holding `entries`, along with the shards below it.
+#[async_recursion]
+async fn store_shard<S>(store: &S, entries: EntriesSerializable, depth: usize) -> StoreResult<Cid>
+where
+    S: IpldStore + Send + Sync,
+{
+    let mut slots: BTreeMap<u8, EntriesSerializable> = BTreeMap::new();
+    for (name, entry) in entries {
+        slots
+            .entry(get_slot(&name, depth))
+            .or_default()
+            .insert(name, entry);
+    }
+
+    let mut shard = DirShardSerializable {
+        r#type: DIR_SHARD_TYPE_TAG.to_string(),
+        buckets: BTreeMap::new(),
+        shards: BTreeMap::new(),
+    };
+
+    for (slot, entries) in slots {
+        if entries.len() > DIR_SHARD_BUCKET_SIZE && depth < DIR_SHARD_MAX_DEPTH {
+            let cid = store_shard(store, entries, depth + 1).await?;
+            shard.shards.insert(slot.to_string(), cid);
+        } else {
+            shard.buckets.insert(slot.to_string(), entries);
+        }
+    }
+
+    store.put_node(&shard).await
+}
+
+/// Stores the shard at `depth` that results from applying `changes` to the loaded `shard`, along
+/// with the shards below it that change, and returns its CID.
+///
+/// `previous` holds the entries of the whole trie before the changes, where an entry that is
+/// removed has a `None` change.
+#[async_recursion]
+async fn update_shard<S>(
+    store: &S,
+    shard: &DirShard,
+    previous: &EntriesSerializable,
+    changes: BTreeMap<String, Option<(bool, Cid)>>,
+    depth: usize,
+) -> StoreResult<Cid>
+where
+    S: IpldStore + Send + Sync,
+{
+    if changes.is_empty() {
+        return Ok(shard.cid);
+    }
+
+    let mut slots: BTreeMap<u8, BTreeMap<String, Option<(bool, Cid)>>> = BTreeMap::new();
+    for (name, change) in changes {
+        slots
+            .entry(get_slot(&name, depth))
+            .or_default()
+            .insert(name, change);
+    }
+
+    let mut updated = DirShardSerializable {
+        r#type: DIR_SHARD_TYPE_TAG.to_string(),
+        buckets: shard
+            .buckets
+            .iter()
+            .map(|(slot, bucket)| (slot.to_string(), bucket.clone()))
+            .collect(),
+        shards: shard
+            .shards
+            .iter()
+            .map(|(slot, shard)| (slot.to_string(), shard.cid))
+            .collect(),
+    };
+
+    for (slot, changes) in slots {
+        let key = slot.to_string();
+        if let Some(subshard) = shard.shards.get(&slot) {
+            // The slot stays a shard of its own while it has too many entries to be held inline
+            let added = changes
+                .iter()
+                .filter(|(name, change)| change.is_some() && !previous.contains_key(*name))
+                .count();
+            let removed = changes.values().filter(|change| change.is_none()).count();
+            let len = subshard.len + added - removed;
+            if len > DIR_SHARD_BUCKET_SIZE {
+                let cid = update_shard(store, subshard, previous, changes, depth + 1).await?;
+                updated.shards.insert(key, cid);
+                continue;
+            }
+
+            let mut bucket = BTreeMap::new();
+            subshard.collect_entries(&mut bucket);
+            apply_changes(&mut bucket, changes);
+            updated.shards.remove(&key);
+            if !bucket.is_empty() {
+                updated.buckets.insert(key, bucket);
+            }
+        } else {
+            let mut bucket = updated.buckets.remove(&key).unwrap_or_default();
+            apply_changes(&mut bucket, changes);
+            if bucket.len() > DIR_SHARD_BUCKET_SIZE && depth < DIR_SHARD_MAX_DEPTH {
+                let cid = store_shard(store, bucket, depth + 1).await?;
+                updated.shards.insert(key, cid);
+            } else if !bucket.is_empty() {
+                updated.buckets.insert(key, bucket);
+            }
+        }
+    }
+
+    store.put_node(&updated).await
+}
+
+/// Loads the shard `cid` and the shards below it, adding the entries they hold to `entries`.
+#[async_recursion]
+async fn load_shard<S>(
+    store: &S,
+    cid: &Cid,
+    entries: &mut EntriesSerializable,
+) -> StoreResult<DirShard>
+where
+    S: IpldStore + Send + Sync,
+{
+    let serializable: DirShardSerializable = store.get_node(cid).await?;
+    if serializable.r#type != DIR_SHARD_TYPE_TAG {
+        return Err(StoreError::custom(format!(
+            "block {} is not a directory shard",
+            cid
+        )));
+    }
+
+    let mut shard = DirShard {
+        cid: *cid,
+        len: 0,
+        buckets: BTreeMap::new(),
+        shards: BTreeMap::new(),
+    };
+
+    for (slot, bucket) in serializable.buckets {
+        shard.len += bucket.len();
+        entries.extend(bucket.iter().map(|(name, entry)| (name.clone(), *entry)));
+        shard.buckets.insert(parse_slot(&slot)?, bucket);
+    }
+
+    for (slot, cid) in serializable.shards {
+        let subshard = load_shard(store, &cid, entries).await?;
+        shard.len += subshard.len;
+        shard.shards.insert(parse_slot(&slot)?, subshard);
+    }
+
+    Ok(shard)
+}
+
+/// Applies `changes` to the entries of `bucket`, removing the entries whose change is `None`.
+fn apply_changes(bucket: &mut EntriesSerializable, changes: BTreeMap<String, Option<(bool, Cid)>>) {
+    for (name, change) in changes {
+        match change {
+            Some(entry) => bucket.insert(name, entry),
+            None => bucket.remove(&name),
+        };
+    }
+}
+
+/// Parses the key of a slot of a serialized shard.
+fn parse_slot(slot: &str) -> StoreResult<u8> {
+    slot.parse()
+        .map_err(|_| StoreError::custom(format!("invalid directory shard slot: {}", slot)))
+}
+
+/// Returns the slot of the entry named `name` in a shard at `depth`.
+fn get_slot(name: &str, depth: usize) -> u8 {
+    let byte = Code::Blake3_256.digest(name.as_bytes()).digest()[depth / 2];
+    if depth % 2 == 0 {
+        byte >> DIR_SHARD_SLOT_BITS
+    } else {
+        byte & 0x0f
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl IpldReferences for DirShardSerializable {
+    fn get_references<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Cid> + Send + 'a> {
+        Box::new(
+            self.buckets
+                .values()
+                .flat_map(|bucket| bucket.values().map(|(_, cid)| cid))
+                .chain(self.shards.values()),
+        )
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::{MemoryStore, Storable};
+
+    use crate::filesystem::{Dir, DirSerializable, File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shard_large_directories() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut dir = Dir::new(store.clone());
+        for i in 0..DIR_SHARD_THRESHOLD * 3 {
+            dir.put_adapted_file(format!("file{}.txt", i), File::new(store.clone()))
+                .await?;
+        }
+
+        // Large directories keep their entries in shards
+        let cid = dir.checkpoint().await?;
+        let serializable: DirSerializable = store.get_node(&cid).await?;
+        assert!(serializable.entries.is_empty());
+        let shards = serializable.shards.unwrap();
+        let root: DirShardSerializable = store.get_node(&shards).await?;
+        assert!(!root.shards.is_empty());
+
+        // Which is not visible through the API
+        let mut loaded = Dir::load(&cid, store.clone()).await?;
+        assert_eq!(loaded.get_entries().count(), DIR_SHARD_THRESHOLD * 3);
+        assert!(loaded.get_file("file42.txt").await?.is_some());
+        assert!(Dir::load_entry_cid(&cid, &store, "file42.txt")
+            .await?
+            .is_some());
+
+        // A change only replaces the shards on the way to its entry
+        loaded.remove("file42.txt").await?;
+        let changed = loaded.checkpoint().await?;
+        let serializable: DirSerializable = store.get_node(&changed).await?;
+        let changed_root: DirShardSerializable =
+            store.get_node(&serializable.shards.unwrap()).await?;
+        let unchanged = root
+            .shards
+            .iter()
+            .filter(|(slot, cid)| changed_root.shards.get(*slot) == Some(*cid))
+            .count();
+        assert!(unchanged >= root.shards.len() - 1);
+
+        // Small directories keep their entries in their own block
+        let mut small = Dir::new(store.clone());
+        small
+            .put_adapted_file("file.txt", File::new(store.clone()))
+            .await?;
+        let cid = small.checkpoint().await?;
+        let serializable: DirSerializable = store.get_node(&cid).await?;
+        assert_eq!(serializable.entries.len(), 1);
+        assert!(serializable.shards.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_shard_updates() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let file = File::new(store.clone()).store().await?;
+        let other = File::new(store.clone()).store().await?;
+        let mut entries: EntriesSerializable = (0..DIR_SHARD_THRESHOLD * 3)
+            .map(|i| (format!("file{}.txt", i), (false, file)))
+            .collect();
+        let cid = store_shards(&store, entries.clone()).await?;
+        let shards = load_shards(&store, &cid).await?;
+        assert_eq!(shards.get_entries(), &entries);
+
+        // Single entries are found without loading the whole trie
+        let found = find_shard_entry(&store, &cid, "file42.txt").await?;
+        assert_eq!(found, Some((false, file)));
+        assert!(find_shard_entry(&store, &cid, "missing.txt")
+            .await?
+            .is_none());
+
+        // Unchanged entries keep the trie as it is
+        assert_eq!(update_shards(&store, &shards, entries.clone()).await?, cid);
+
+        // Changes only replace the shards on the way to their entries
+        entries.remove("file42.txt");
+        entries.insert("file7.txt".to_string(), (true, other));
+        entries.insert("new.txt".to_string(), (false, other));
+        let updated = update_shards(&store, &shards, entries.clone()).await?;
+        assert_eq!(updated, store_shards(&store, entries.clone()).await?);
+        let root: DirShardSerializable = store.get_node(&cid).await?;
+        let updated_root: DirShardSerializable = store.get_node(&updated).await?;
+        let unchanged = root
+            .shards
+            .iter()
+            .filter(|(slot, cid)| updated_root.shards.get(*slot) == Some(*cid))
+            .count();
+        assert!(unchanged >= root.shards.len() - 3);
+
+        // Shards left with few entries are folded back into their parent
+        let few: EntriesSerializable = entries.into_iter().take(100).collect();
+        let updated = update_shards(&store, &shards, few.clone()).await?;
+        assert_eq!(updated, store_shards(&store, few.clone()).await?);
+        assert_eq!(load_shards(&store, &updated).await?.into_entries(), few);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,200 @@
+use futures::{stream, Stream, StreamExt};
+use ipldstore::IpldStore;
+
+use crate::{filesystem::entity::Entity, FsResult};
+
+use super::{Dir, Utf8UnixPathSegment};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How many entities of a directory are loaded from the store at once when its entries are
+/// listed.
+pub const DIR_LOAD_CONCURRENCY: usize = 16;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A position in the listing of a directory, after which [`Dir::stream_entries`] resumes.
+///
+/// Entries are streamed in the order of their names and a cursor holds the name of the last
+/// entry a caller got, so a listing can be resumed from a cursor kept across requests, even if
+/// the directory changed in between.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DirCursor(Utf8UnixPathSegment);
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl DirCursor {
+    /// Returns the name of the entry the cursor points after.
+    pub fn get_name(&self) -> &Utf8UnixPathSegment {
+        &self.0
+    }
+}
+
+/// Directory streaming.
+impl<S> Dir<S>
+where
+    S: IpldStore + Send + Sync,
+{
+    /// Returns a stream of the entries of the directory in the order of their names, starting
+    /// after `after` if given.
+    ///
+    /// Entities are only resolved as the stream is polled, so a large directory can be paged
+    /// through by taking a page of entries at a time and resuming from the cursor of the last
+    /// one, without loading all of its entities. Up to [`DIR_LOAD_CONCURRENCY`] entities ahead
+    /// of the entry being polled are loaded from the store at once, so listing a directory is
+    /// not bound by the latency of the store for each entity in turn.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use futures::StreamExt;
+    /// use monofs::filesystem::{Dir, DirCursor};
+    /// use ipldstore::MemoryStore;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut dir = Dir::new(MemoryStore::default());
+    /// for name in ["c.txt", "a.txt", "b.txt"] {
+    ///     dir.find_or_create(name, true).await?;
+    /// }
+    ///
+    /// // Get the first page
+    /// let page = dir.stream_entries(None).take(2).collect::<Vec<_>>().await;
+    /// let (last, _) = page[1].as_ref().unwrap();
+    /// assert_eq!(last.as_str(), "b.txt");
+    ///
+    /// // Resume after its last entry
+    /// let cursor = DirCursor::from((*last).clone());
+    /// let rest = dir.stream_entries(Some(&cursor)).collect::<Vec<_>>().await;
+    /// assert_eq!(rest.len(), 1);
+    /// assert_eq!(rest[0].as_ref().unwrap().0.as_str(), "c.txt");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn stream_entries(
+        &self,
+        after: Option<&DirCursor>,
+    ) -> impl Stream<Item = FsResult<(&Utf8UnixPathSegment, &Entity<S>)>> + '_ {
+        let mut entries = self
+            .get_entries()
+            .filter(|(name, _)| after.is_none_or(|cursor| *name > cursor.get_name()))
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+        stream::iter(entries)
+            .map(move |(name, link)| async move {
+                let entity = link.resolve_entity(self.get_store().clone()).await?;
+                Ok((name, entity))
+            })
+            .buffered(DIR_LOAD_CONCURRENCY)
+    }
+
+    /// Returns the names of the entries of the directory in the order of their names, starting
+    /// after `after` if given, like [`stream_entries`](Self::stream_entries) but without
+    /// resolving any entity.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use monofs::filesystem::{Dir, DirCursor, Utf8UnixPathSegment};
+    /// use ipldstore::MemoryStore;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut dir = Dir::new(MemoryStore::default());
+    /// for name in ["c.txt", "a.txt", "b.txt"] {
+    ///     dir.find_or_create(name, true).await?;
+    /// }
+    ///
+    /// let cursor = DirCursor::from("a.txt".parse::<Utf8UnixPathSegment>()?);
+    /// let names = dir.list_after(Some(&cursor));
+    /// assert_eq!(names.len(), 2);
+    /// assert_eq!(names[0].as_str(), "b.txt");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn list_after(&self, after: Option<&DirCursor>) -> Vec<&Utf8UnixPathSegment> {
+        let mut names = self
+            .get_entries()
+            .map(|(name, _)| name)
+            .filter(|name| after.is_none_or(|cursor| *name > cursor.get_name()))
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl From<Utf8UnixPathSegment> for DirCursor {
+    fn from(name: Utf8UnixPathSegment) -> Self {
+        Self(name)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+
+    use ipldstore::MemoryStore;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stream_entries_pagination() -> anyhow::Result<()> {
+        let mut dir = Dir::new(MemoryStore::default());
+        for i in 0..25 {
+            dir.find_or_create(format!("file{:02}.txt", i), true)
+                .await?;
+        }
+        dir.remove("file03.txt").await?;
+
+        // Page through the directory five entries at a time
+        let mut names = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = dir
+                .stream_entries(cursor.as_ref())
+                .take(5)
+                .collect::<Vec<_>>()
+                .await;
+            if page.is_empty() {
+                break;
+            }
+
+            for entry in page {
+                let (name, entity) = entry?;
+                assert!(matches!(entity, Entity::File(_)));
+                names.push(name.to_string());
+                cursor = Some(DirCursor::from(name.clone()));
+            }
+        }
+
+        let mut expected = (0..25)
+            .filter(|i| *i != 3)
+            .map(|i| format!("file{:02}.txt", i))
+            .collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(names, expected);
+
+        // A cursor past a removed entry still resumes in place
+        let cursor = DirCursor::from("file03.txt".parse::<Utf8UnixPathSegment>()?);
+        let mut entries = pin!(dir.stream_entries(Some(&cursor)));
+        let (name, _) = entries.next().await.unwrap()?;
+        assert_eq!(name.as_str(), "file04.txt");
+
+        Ok(())
+    }
+}
@@ -5,6 +5,7 @@ use serde::Deserialize;
 
 use crate::{
     filesystem::{self, Dir, File, Metadata, SymCidLink, SymPathLink},
+    management::{EntityLock, FsLocks, LockKind},
     FsError, FsResult,
 };
 
@@ -12,9 +13,12 @@ use crate::{
 // Types
 //--------------------------------------------------------------------------------------------------
 
+/// The type field every entity is serialized with, read to tell what kind of entity is stored at
+/// a CID before loading it.
 #[derive(Deserialize, Debug)]
-struct TypeField {
-    r#type: String,
+pub(crate) struct TypeField {
+    /// The type of the entity.
+    pub(crate) r#type: String,
 }
 
 /// This is an entity in the file system.
@@ -157,6 +161,37 @@ where
         }
     }
 
+    /// Returns the CID of the entity as it currently stands, storing whatever part of it is not
+    /// stored yet.
+    ///
+    /// The CID covers the metadata of the entity along with its content, so it changes whenever
+    /// either does. Files with the same content share the CID of their content, which
+    /// [`File::get_content`] returns, even if their metadata differs.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use monofs::filesystem::{Entity, File};
+    /// use ipldstore::{MemoryStore, Storable};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let store = MemoryStore::default();
+    /// let file = File::with_content(store.clone(), b"Hello, World!".as_slice()).await?;
+    /// let entity = Entity::File(file);
+    ///
+    /// let cid = entity.get_cid().await?;
+    /// assert_eq!(Entity::load(&cid, store).await?.get_cid().await?, cid);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_cid(&self) -> StoreResult<Cid>
+    where
+        S: Send + Sync,
+    {
+        self.store().await
+    }
+
     /// Creates a checkpoint of the current entity state.
     ///
     /// This is equivalent to storing the entity and loading it back,
@@ -192,6 +227,32 @@ where
         Ok(cid)
     }
 
+    /// Takes an exclusive advisory lock on the entity, which is at `path` in the filesystem of
+    /// `locks`, waiting for as long as another process holds a lock on it.
+    ///
+    /// Processes updating the same entity of a shared store take the lock around reading, changing
+    /// and storing it, so that none of them overwrites the changes of another. See [`FsLocks`].
+    pub async fn lock_exclusive(
+        &self,
+        locks: &FsLocks,
+        path: impl AsRef<str>,
+    ) -> FsResult<EntityLock> {
+        locks.lock(path, LockKind::Exclusive).await
+    }
+
+    /// Takes a shared advisory lock on the entity, which is at `path` in the filesystem of
+    /// `locks`, waiting for as long as another process holds an exclusive lock on it.
+    ///
+    /// Processes that only read the entity take the lock to keep it from changing while they do.
+    /// See [`FsLocks`].
+    pub async fn lock_shared(
+        &self,
+        locks: &FsLocks,
+        path: impl AsRef<str>,
+    ) -> FsResult<EntityLock> {
+        locks.lock(path, LockKind::Shared).await
+    }
+
     pub(crate) fn set_previous(&mut self, previous: Option<Cid>) {
         match self {
             Entity::File(file) => file.set_previous(previous),
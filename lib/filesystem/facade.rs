@@ -0,0 +1,316 @@
+use chrono::{DateTime, Utc};
+use getset::Getters;
+use ipldstore::{ipld::cid::Cid, IpldStoreSeekable};
+use tokio::{io::AsyncReadExt, sync::RwLock};
+use typed_path::Utf8UnixPath;
+
+use crate::{utils::path, FsError, FsResult};
+
+use super::{Dir, Entity, EntityType, File};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A path-based view of a monofs tree, for library users who do not want to traverse [`Dir`]
+/// and [`File`] entities themselves.
+///
+/// Paths are resolved from the root, with or without a leading `/`. Every call that changes the
+/// tree works on a copy of the root and checkpoints it before it becomes the root of the
+/// facade, so a call either applies completely and returns the CID of the new root, or fails and
+/// leaves the tree as it was.
+///
+/// ## Examples
+///
+/// ```
+/// use monofs::filesystem::MonoFs;
+/// use ipldstore::MemoryStore;
+///
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let fs = MonoFs::new(MemoryStore::default());
+///
+/// fs.mkdir_all("/docs/guides").await?;
+/// let root = fs.write("/docs/guides/intro.md", b"# Intro").await?;
+///
+/// assert_eq!(fs.read("/docs/guides/intro.md").await?, b"# Intro");
+/// assert_eq!(fs.get_root_cid().await, Some(root));
+/// # Ok(())
+/// # }
+/// ```
+pub struct MonoFs<S>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    state: RwLock<MonoFsState<S>>,
+}
+
+/// The root of a [`MonoFs`] and the CID it was last committed under.
+struct MonoFsState<S>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    root: Dir<S>,
+    cid: Option<Cid>,
+}
+
+/// The metadata of an entity, as returned by [`MonoFs::metadata`].
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct PathMetadata {
+    /// The type of the entity.
+    entity_type: EntityType,
+
+    /// The size of the entity in bytes.
+    size: u64,
+
+    /// The permission bits of the entity, if set.
+    mode: Option<u32>,
+
+    /// The time the entity was created.
+    created_at: DateTime<Utc>,
+
+    /// The time of the last modification of the entity.
+    modified_at: DateTime<Utc>,
+}
+
+/// An entry of a directory, as returned by [`MonoFs::read_dir`].
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct DirEntry {
+    /// The name of the entry.
+    name: String,
+
+    /// The type of the entity the entry refers to.
+    entity_type: EntityType,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> MonoFs<S>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    /// Creates a facade over an empty tree in `store`.
+    pub fn new(store: S) -> Self {
+        Self {
+            state: RwLock::new(MonoFsState {
+                root: Dir::new(store),
+                cid: None,
+            }),
+        }
+    }
+
+    /// Creates a facade over the tree with the root `cid` in `store`.
+    pub async fn load(cid: &Cid, store: S) -> FsResult<Self> {
+        let root = Dir::load(cid, store).await?;
+        Ok(Self {
+            state: RwLock::new(MonoFsState {
+                root,
+                cid: Some(*cid),
+            }),
+        })
+    }
+
+    /// Returns the CID of the root, or `None` if nothing was written to a new tree yet.
+    pub async fn get_root_cid(&self) -> Option<Cid> {
+        self.state.read().await.cid
+    }
+
+    /// Reads the contents of the file at `path`.
+    pub async fn read(&self, path: impl AsRef<str>) -> FsResult<Vec<u8>> {
+        let path = relative(path.as_ref());
+        let state = self.state.read().await;
+        let file = match state.root.find(path).await? {
+            Some(Entity::File(file)) => file,
+            Some(_) => return Err(FsError::NotAFile(path.to_string())),
+            None => return Err(FsError::PathNotFound(path.to_string())),
+        };
+
+        let mut data = Vec::new();
+        file.get_input_stream()
+            .await?
+            .read_to_end(&mut data)
+            .await?;
+
+        Ok(data)
+    }
+
+    /// Replaces the contents of the file at `path` with `data`, creating the file if it does not
+    /// exist. Its parent directory has to exist.
+    ///
+    /// ## Returns
+    /// The CID of the new root
+    pub async fn write(&self, path: impl AsRef<str>, data: impl AsRef<[u8]>) -> FsResult<Cid> {
+        let path = relative(path.as_ref());
+        let mut state = self.state.write().await;
+        let mut root = state.root.clone();
+
+        let content = File::with_content(root.get_store().clone(), data.as_ref()).await?;
+        match root.find_mut(path).await? {
+            Some(Entity::File(file)) => {
+                file.set_content(content.get_content().cloned());
+                file.get_metadata_mut().mark_modified(Utc::now());
+            }
+            Some(_) => return Err(FsError::NotAFile(path.to_string())),
+            None => {
+                let (parent, name) = path::split_last(Utf8UnixPath::new(path))?;
+                let parent_dir = match parent {
+                    Some(parent) => match root.find_mut(parent).await? {
+                        Some(Entity::Dir(dir)) => dir,
+                        _ => return Err(FsError::PathNotFound(parent.to_string())),
+                    },
+                    None => &mut root,
+                };
+                parent_dir.put_adapted_file(name, content).await?;
+            }
+        }
+
+        state.commit(root).await
+    }
+
+    /// Creates the directory at `path` along with any missing parent directories. Directories
+    /// that already exist are left as they are.
+    ///
+    /// ## Returns
+    /// The CID of the new root
+    pub async fn mkdir_all(&self, path: impl AsRef<str>) -> FsResult<Cid> {
+        let path = relative(path.as_ref());
+        let mut state = self.state.write().await;
+        let mut root = state.root.clone();
+
+        if !path.is_empty() {
+            match root.find_or_create(path, false).await? {
+                Entity::Dir(_) => {}
+                _ => return Err(FsError::NotADirectory(path.to_string())),
+            }
+        }
+
+        state.commit(root).await
+    }
+
+    /// Returns the metadata of the entity at `path`.
+    pub async fn metadata(&self, path: impl AsRef<str>) -> FsResult<PathMetadata> {
+        let path = relative(path.as_ref());
+        let state = self.state.read().await;
+        let (metadata, size) = if path.is_empty() {
+            (state.root.get_metadata(), 0)
+        } else {
+            let entity = state
+                .root
+                .find(path)
+                .await?
+                .ok_or_else(|| FsError::PathNotFound(path.to_string()))?;
+            (entity.get_metadata(), entity.get_size().await?)
+        };
+
+        Ok(PathMetadata {
+            entity_type: *metadata.get_entity_type(),
+            size,
+            mode: metadata.get_mode().await?,
+            created_at: *metadata.get_created_at(),
+            modified_at: *metadata.get_modified_at(),
+        })
+    }
+
+    /// Lists the entries of the directory at `path`.
+    pub async fn read_dir(&self, path: impl AsRef<str>) -> FsResult<Vec<DirEntry>> {
+        let path = relative(path.as_ref());
+        let state = self.state.read().await;
+        let dir = if path.is_empty() {
+            &state.root
+        } else {
+            match state.root.find(path).await? {
+                Some(Entity::Dir(dir)) => dir,
+                Some(_) => return Err(FsError::NotADirectory(path.to_string())),
+                None => return Err(FsError::PathNotFound(path.to_string())),
+            }
+        };
+
+        let names = dir.get_entry_names().cloned().collect::<Vec<_>>();
+        let mut entries = Vec::with_capacity(names.len());
+        for name in names {
+            if let Some(entity) = dir.get_entity(&name).await? {
+                entries.push(DirEntry {
+                    name: name.to_string(),
+                    entity_type: *entity.get_metadata().get_entity_type(),
+                });
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+impl<S> MonoFsState<S>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    /// Checkpoints `root` and makes it the root, leaving the current root in place if that fails.
+    async fn commit(&mut self, mut root: Dir<S>) -> FsResult<Cid> {
+        let cid = root.checkpoint().await?;
+        self.root = root;
+        self.cid = Some(cid);
+        Ok(cid)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Strips the leading `/` of a path resolved from the root, leaving `""` for the root itself.
+fn relative(path: &str) -> &str {
+    path.trim_start_matches('/')
+}
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::MemoryStore;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_facade_operations() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let fs = MonoFs::new(store.clone());
+        assert_eq!(fs.get_root_cid().await, None);
+
+        fs.mkdir_all("/a/b").await?;
+        fs.write("a/b/file.txt", b"first").await?;
+        let root = fs.write("/a/b/file.txt", b"second").await?;
+        assert_eq!(fs.read("/a/b/file.txt").await?, b"second");
+
+        let metadata = fs.metadata("/a/b/file.txt").await?;
+        assert_eq!(*metadata.get_entity_type(), EntityType::File);
+        assert_eq!(*metadata.get_size(), 6);
+
+        let entries = fs.read_dir("/a").await?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].get_name(), "b");
+        assert_eq!(*entries[0].get_entity_type(), EntityType::Dir);
+
+        // Failed calls leave the root as it was
+        assert!(matches!(
+            fs.write("/missing/file.txt", b"data").await,
+            Err(FsError::PathNotFound(_))
+        ));
+        assert!(matches!(
+            fs.mkdir_all("/a/b/file.txt").await,
+            Err(FsError::NotADirectory(_))
+        ));
+        assert!(matches!(fs.read("/a").await, Err(FsError::NotAFile(_))));
+        assert_eq!(fs.get_root_cid().await, Some(root));
+
+        // The committed root can be loaded again
+        let loaded = MonoFs::load(&root, store).await?;
+        assert_eq!(loaded.read("a/b/file.txt").await?, b"second");
+
+        Ok(())
+    }
+}
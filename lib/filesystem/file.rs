@@ -1,4 +1,7 @@
 mod io;
+mod layout;
+mod stream;
+mod temp;
 
 use std::{
     fmt::{self, Debug},
@@ -362,31 +365,32 @@ where
         Ok(self.get_size().await? == 0)
     }
 
-    /// Truncates the file to zero bytes.
+    /// Replaces the content of the file with the content stored under `content`, without reading
+    /// or copying it, and marks the file as modified. `None` empties the file.
     ///
     /// ## Examples
     ///
     /// ```
     /// use monofs::filesystem::File;
-    /// use ipldstore::{MemoryStore, ipld::cid::Cid};
+    /// use ipldstore::MemoryStore;
     ///
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
     /// let store = MemoryStore::default();
-    /// let mut file = File::with_content(store.clone(), b"Hello, World!".as_slice()).await?;
-    ///
-    /// assert!(!file.is_empty().await?);
+    /// let source = File::with_content(store.clone(), b"Hello, World!".as_slice()).await?;
+    /// let mut file = File::new(store);
     ///
-    /// file.truncate();
+    /// file.set_content(source.get_content().cloned());
     ///
-    /// assert!(file.is_empty().await?);
-    /// assert!(file.get_content().is_none());
+    /// assert_eq!(file.get_content(), source.get_content());
+    /// assert_eq!(file.get_size().await?, 13);
     /// # Ok(())
     /// # }
     /// ```
-    pub fn truncate(&mut self) {
+    pub fn set_content(&mut self, content: Option<Cid>) {
         let inner = Arc::make_mut(&mut self.inner);
-        inner.content = None;
+        inner.content = content;
+        inner.metadata.mark_modified(Utc::now());
     }
 
     /// Tries to create a new `Dir` from a serializable representation.
@@ -422,12 +426,6 @@ where
         })
     }
 
-    pub(crate) fn set_content(&mut self, content: Option<Cid>) {
-        let inner = Arc::make_mut(&mut self.inner);
-        inner.content = content;
-        inner.metadata.set_modified_at(Utc::now());
-    }
-
     pub(crate) fn set_previous(&mut self, previous: Option<Cid>) {
         let inner = Arc::make_mut(&mut self.inner);
         inner.previous = previous;
@@ -545,7 +543,7 @@ mod tests {
         assert!(!file.is_empty().await?);
         assert_eq!(file.get_size().await?, 13);
 
-        file.truncate();
+        file.truncate(0).await?;
         assert!(file.is_empty().await?);
         assert!(file.get_content().is_none());
         assert_eq!(file.get_size().await?, 0);
@@ -627,3 +625,5 @@ mod tests {
 //--------------------------------------------------------------------------------------------------
 
 pub use io::*;
+pub use stream::*;
+pub use temp::*;
@@ -9,9 +9,9 @@ use chrono::Utc;
 use futures::{future::BoxFuture, FutureExt};
 use ipldstore::{ipld::cid::Cid, IpldStore, IpldStoreSeekable};
 use microsandbox_utils::{EmptySeekableReader, SeekableReader};
-use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, ReadBuf};
 
-use crate::filesystem::File;
+use crate::{filesystem::File, FsResult};
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -61,6 +61,54 @@ where
     pub fn get_output_stream(&mut self) -> FileOutputStream<'_, S> {
         FileOutputStream::new(self)
     }
+
+    /// Reads up to `len` bytes of the file's content starting at `offset`.
+    ///
+    /// Only the chunks overlapping the range are fetched from the store, so small reads stay
+    /// cheap however large the file is. Fewer than `len` bytes are returned only when the range
+    /// extends past the end of the file, and none when `offset` is at or past the end.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use monofs::filesystem::File;
+    /// use ipldstore::MemoryStore;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let store = MemoryStore::default();
+    /// let file = File::with_content(store, b"Hello, World!".as_slice()).await?;
+    ///
+    /// assert_eq!(file.read_at(7, 5).await?, b"World");
+    /// assert_eq!(file.read_at(7, 100).await?, b"World!");
+    /// assert!(file.read_at(100, 5).await?.is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn read_at(&self, offset: u64, len: usize) -> FsResult<Vec<u8>>
+    where
+        S: IpldStoreSeekable,
+    {
+        let size = self.get_size().await?;
+        if offset >= size || len == 0 {
+            return Ok(Vec::new());
+        }
+
+        // Never allocate more than what is left of the file
+        let len = len.min((size - offset) as usize);
+
+        let mut input_stream = self.get_input_stream().await?;
+        input_stream.seek(SeekFrom::Start(offset)).await?;
+
+        // A read may stop at a chunk boundary, so keep reading until the range is filled
+        let mut buffer = Vec::with_capacity(len);
+        (&mut input_stream)
+            .take(len as u64)
+            .read_to_end(&mut buffer)
+            .await?;
+
+        Ok(buffer)
+    }
 }
 
 impl<'a> FileInputStream<'a> {
@@ -162,7 +210,7 @@ where
                     Poll::Ready(Ok(maybe_cid)) => {
                         if let Some(cid) = maybe_cid {
                             self.file.set_content(Some(cid));
-                            self.file.get_metadata_mut().set_modified_at(Utc::now());
+                            self.file.get_metadata_mut().mark_modified(Utc::now());
                         }
                         self.flush_state = FlushState::Done;
                         continue;
@@ -245,6 +293,38 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_file_read_at() -> Result<()> {
+        let store = MemoryStore::default();
+
+        // Large enough to span several chunks
+        let content = (0..1024 * 1024)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let file = File::with_content(store, content.as_slice()).await?;
+
+        assert_eq!(file.read_at(0, 10).await?, &content[..10]);
+        assert_eq!(
+            file.read_at(300_000, 500_000).await?,
+            &content[300_000..800_000]
+        );
+
+        // Ranges past the end are cut short
+        let size = content.len() as u64;
+        assert_eq!(
+            file.read_at(size - 3, 10).await?,
+            &content[content.len() - 3..]
+        );
+        assert!(file.read_at(size, 10).await?.is_empty());
+        assert!(file.read_at(0, 0).await?.is_empty());
+
+        // Empty files have nothing to read
+        let empty = File::new(MemoryStore::default());
+        assert!(empty.read_at(0, 10).await?.is_empty());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_file_input_stream_seek() -> Result<()> {
         let store = MemoryStore::default();
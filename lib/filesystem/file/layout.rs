@@ -0,0 +1,578 @@
+use std::{collections::HashMap, ops::Range};
+
+use bytes::Bytes;
+use ipldstore::{ipld::cid::Cid, Codec, IpldReferences, IpldStore, IpldStoreSeekable, RawStore};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::{
+    filesystem::{File, OpenFlags},
+    FsError, FsResult,
+};
+
+use super::FILE_STREAM_BUFFER_SIZE;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The most zeros put in a single chunk when a file is extended.
+const ZERO_CHUNK_SIZE: usize = 64 * 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The node the store's flat layout keeps the content of a file under, listing the chunks of the
+/// content in order along with their sizes.
+///
+/// The store does not expose the type of that node, so it is mirrored here, and the tests check
+/// that both encode it the same way.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChunkList {
+    /// The size of the content.
+    size: usize,
+
+    /// The chunks of the content and their sizes.
+    dependencies: Vec<(Cid, usize)>,
+}
+
+/// Chunks of zeros put into a store, by size, so that every run of zeros of the same size shares
+/// a single block.
+struct ZeroChunks<'a, S> {
+    store: &'a S,
+    chunks: HashMap<usize, Cid>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> File<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// Truncates or extends the file to `new_len` bytes. Bytes past the old end of the file read
+    /// as zeros.
+    ///
+    /// Only the chunk the new end falls in is rewritten: the chunks before it are kept as they
+    /// are, and the zeros of an extension share a handful of blocks however many there are.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use monofs::filesystem::File;
+    /// use ipldstore::MemoryStore;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let store = MemoryStore::default();
+    /// let mut file = File::with_content(store, b"Hello, World!".as_slice()).await?;
+    ///
+    /// file.truncate(5).await?;
+    /// assert_eq!(file.read_at(0, 100).await?, b"Hello");
+    ///
+    /// file.truncate(8).await?;
+    /// assert_eq!(file.read_at(0, 100).await?, b"Hello\0\0\0");
+    ///
+    /// file.truncate(0).await?;
+    /// assert!(file.get_content().is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn truncate(&mut self, new_len: u64) -> FsResult<()> {
+        let size = self.get_size().await?;
+        if new_len == size {
+            return Ok(());
+        }
+
+        self.rewrite_with_zeros(size, size..size, new_len).await
+    }
+
+    /// Replaces `len` bytes of the file from `offset` with zeros, keeping the size of the file.
+    /// The part of the range past the end of the file is ignored.
+    ///
+    /// Only the chunks the range starts and ends in are rewritten. The chunks in between are
+    /// replaced by chunks of zeros, which share a block with every other chunk of zeros of the
+    /// same size, and the chunks outside the range are kept as they are.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use monofs::filesystem::File;
+    /// use ipldstore::MemoryStore;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let store = MemoryStore::default();
+    /// let mut file = File::with_content(store, b"Hello, World!".as_slice()).await?;
+    ///
+    /// file.punch_hole(5, 2).await?;
+    /// assert_eq!(file.read_at(0, 100).await?, b"Hello\0\0World!");
+    /// assert_eq!(file.get_size().await?, 13);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn punch_hole(&mut self, offset: u64, len: u64) -> FsResult<()> {
+        let size = self.get_size().await?;
+        let end = offset.saturating_add(len).min(size);
+        if offset >= end {
+            return Ok(());
+        }
+
+        self.rewrite_with_zeros(size, offset..end, size).await
+    }
+
+    /// Replaces the content of the file, which is `size` bytes, with `new_len` bytes of it where
+    /// the bytes in `hole` and past the old end are zeros.
+    ///
+    /// Content kept in the flat layout is edited a chunk at a time, and any other content is
+    /// streamed through the store again.
+    async fn rewrite_with_zeros(
+        &mut self,
+        size: u64,
+        hole: Range<u64>,
+        new_len: u64,
+    ) -> FsResult<()> {
+        if new_len == 0 {
+            self.set_content(None);
+            return Ok(());
+        }
+
+        let store = self.get_store().clone();
+        let chunks = match self.get_content() {
+            Some(cid) => ChunkList::load(&store, cid, size).await?,
+            None => None,
+        };
+
+        let content = match chunks {
+            Some(chunks) => chunks.rewrite(&store, hole, new_len).await?,
+            None => rewrite_bytes(&store, self.get_content(), size, hole, new_len).await?,
+        };
+
+        self.set_content(Some(content));
+        Ok(())
+    }
+
+    /// Adds `data` to the end of the file.
+    ///
+    /// Only the last chunk of the file is chunked again along with `data`, and every other chunk
+    /// is kept as it is, so appending costs the same however large the file is. Repeated small
+    /// appends, like the lines of a log, keep growing the last chunk until the store's chunker
+    /// splits it.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use monofs::filesystem::File;
+    /// use ipldstore::MemoryStore;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let store = MemoryStore::default();
+    /// let mut file = File::with_content(store, b"Hello".as_slice()).await?;
+    ///
+    /// file.append(b", World!").await?;
+    /// assert_eq!(file.read_at(0, 100).await?, b"Hello, World!");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn append(&mut self, data: &[u8]) -> FsResult<()>
+    where
+        S: IpldStoreSeekable,
+    {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let size = self.get_size().await?;
+        let store = self.get_store().clone();
+        let content = match self.get_content() {
+            Some(cid) => match ChunkList::load(&store, cid, size).await? {
+                Some(chunks) => chunks.append(&store, data).await?,
+                None => None,
+            },
+            None => Some(store.put_bytes(data).await?),
+        };
+
+        match content {
+            Some(content) => self.set_content(Some(content)),
+            None => {
+                // The content is not kept in the flat layout, so stream it through the store
+                let flags = OpenFlags::builder().write(true).append(true).build();
+                let mut stream = self.open_stream(flags).await?;
+                stream.write_all(data).await?;
+                stream.shutdown().await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ChunkList {
+    /// Loads the chunk list at `cid`, or returns `None` if the content of `size` bytes stored
+    /// there is not kept in the flat layout.
+    async fn load<S>(store: &S, cid: &Cid, size: u64) -> FsResult<Option<Self>>
+    where
+        S: IpldStore + Send + Sync,
+    {
+        let codec: Codec = cid.codec().try_into()?;
+        if !matches!(codec, Codec::DagCbor) {
+            return Ok(None);
+        }
+
+        let Ok(chunks) = store.get_node::<Self>(cid).await else {
+            return Ok(None);
+        };
+
+        let total = chunks
+            .dependencies
+            .iter()
+            .map(|(_, len)| *len)
+            .sum::<usize>();
+        if chunks.size as u64 != size || total != chunks.size {
+            return Ok(None);
+        }
+
+        Ok(Some(chunks))
+    }
+
+    /// Puts a chunk list for the first `new_len` bytes of the content into the store, with the
+    /// bytes in `hole` and past the end of the content replaced by zeros, and returns its CID.
+    async fn rewrite<S>(&self, store: &S, hole: Range<u64>, new_len: u64) -> FsResult<Cid>
+    where
+        S: IpldStore + Send + Sync,
+    {
+        let data_end = (self.size as u64).min(new_len);
+        let hole = clamp_hole(hole, data_end);
+        let mut zeros = ZeroChunks::new(store);
+        let mut dependencies = Vec::with_capacity(self.dependencies.len());
+
+        let mut start = 0;
+        for (cid, len) in &self.dependencies {
+            if start >= data_end {
+                break;
+            }
+
+            let end = start + *len as u64;
+            let kept = start..end.min(data_end);
+            let punched = hole.start < kept.end && hole.end > kept.start;
+            if !punched && kept.end == end {
+                dependencies.push((*cid, *len));
+            } else if punched && hole.start <= kept.start && hole.end >= kept.end {
+                let len = (kept.end - kept.start) as usize;
+                dependencies.push((zeros.get(len).await?, len));
+            } else {
+                // Only part of the chunk is kept or punched, so it has to be rewritten
+                let bytes = store.get_raw_block(cid).await?;
+                let mut data = bytes[..(kept.end - kept.start) as usize].to_vec();
+                if punched {
+                    let from = hole.start.max(kept.start) - kept.start;
+                    let to = hole.end.min(kept.end) - kept.start;
+                    data[from as usize..to as usize].fill(0);
+                }
+
+                let len = data.len();
+                dependencies.push((store.put_raw_block(Bytes::from(data)).await?, len));
+            }
+
+            start = end;
+        }
+
+        // Extend the content with zeros
+        let mut remaining = (new_len - data_end) as usize;
+        while remaining > 0 {
+            let len = remaining.min(ZERO_CHUNK_SIZE);
+            dependencies.push((zeros.get(len).await?, len));
+            remaining -= len;
+        }
+
+        let chunks = ChunkList {
+            size: new_len as usize,
+            dependencies,
+        };
+
+        Ok(store.put_node(&chunks).await?)
+    }
+
+    /// Puts a chunk list for the content followed by `data` into the store, chunking the last
+    /// chunk of the content again along with `data`, and returns its CID. Returns `None` if the
+    /// store does not keep the new tail in the flat layout.
+    async fn append<S>(mut self, store: &S, data: &[u8]) -> FsResult<Option<Cid>>
+    where
+        S: IpldStore + Send + Sync,
+    {
+        let mut tail = Vec::new();
+        if let Some((cid, _)) = self.dependencies.pop() {
+            tail.extend_from_slice(&store.get_raw_block(&cid).await?);
+        }
+        tail.extend_from_slice(data);
+
+        let tail_cid = store.put_bytes(tail.as_slice()).await?;
+        let Some(tail) = ChunkList::load(store, &tail_cid, tail.len() as u64).await? else {
+            return Ok(None);
+        };
+
+        self.dependencies.extend(tail.dependencies);
+        self.size += data.len();
+
+        Ok(Some(store.put_node(&self).await?))
+    }
+}
+
+impl<'a, S> ZeroChunks<'a, S>
+where
+    S: IpldStore + Send + Sync,
+{
+    /// Creates an empty set of chunks of zeros in `store`.
+    fn new(store: &'a S) -> Self {
+        Self {
+            store,
+            chunks: HashMap::new(),
+        }
+    }
+
+    /// Returns the CID of a chunk of `len` zeros, putting it into the store the first time.
+    async fn get(&mut self, len: usize) -> FsResult<Cid> {
+        if let Some(cid) = self.chunks.get(&len) {
+            return Ok(*cid);
+        }
+
+        let cid = self.store.put_raw_block(Bytes::from(vec![0; len])).await?;
+        self.chunks.insert(len, cid);
+
+        Ok(cid)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Limits `hole` to the first `data_end` bytes, leaving it empty if it starts past them.
+fn clamp_hole(hole: Range<u64>, data_end: u64) -> Range<u64> {
+    let start = hole.start.min(data_end);
+    start..hole.end.min(data_end).max(start)
+}
+
+/// Streams the first `new_len` bytes of the content at `content`, which is `size` bytes, into the
+/// store again with the bytes in `hole` and past the end of the content replaced by zeros, and
+/// returns the CID of the new content.
+async fn rewrite_bytes<S>(
+    store: &S,
+    content: Option<&Cid>,
+    size: u64,
+    hole: Range<u64>,
+    new_len: u64,
+) -> FsResult<Cid>
+where
+    S: IpldStore + Send + Sync,
+{
+    let data_end = size.min(new_len);
+    let hole = clamp_hole(hole, data_end);
+    let mut source = match content {
+        Some(cid) => Some(store.get_bytes(cid).await?),
+        None => None,
+    };
+
+    let (mut sink, reader) = tokio::io::duplex(FILE_STREAM_BUFFER_SIZE);
+    let copy = async move {
+        if let Some(source) = &mut source {
+            let punched = hole.end - hole.start;
+            tokio::io::copy(&mut (&mut *source).take(hole.start), &mut sink).await?;
+            tokio::io::copy(&mut (&mut *source).take(punched), &mut tokio::io::sink()).await?;
+            tokio::io::copy(&mut tokio::io::repeat(0).take(punched), &mut sink).await?;
+            tokio::io::copy(&mut (&mut *source).take(data_end - hole.end), &mut sink).await?;
+        }
+
+        tokio::io::copy(
+            &mut tokio::io::repeat(0).take(new_len - data_end),
+            &mut sink,
+        )
+        .await?;
+        sink.shutdown().await
+    };
+
+    let (cid, ()) = tokio::try_join!(
+        async { store.put_bytes(reader).await.map_err(FsError::from) },
+        async { copy.await.map_err(FsError::from) },
+    )?;
+
+    Ok(cid)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl IpldReferences for ChunkList {
+    fn get_references<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Cid> + Send + 'a> {
+        Box::new(self.dependencies.iter().map(|(cid, _)| cid))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::MemoryStore;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_truncate_and_punch_hole() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+
+        // Large enough to span several chunks
+        let content = (0..1024 * 1024)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let mut file = File::with_content(store.clone(), content.as_slice()).await?;
+        let size = content.len() as u64;
+        let original = ChunkList::load(&store, file.get_content().unwrap(), size)
+            .await?
+            .unwrap();
+        assert!(original.dependencies.len() > 2);
+
+        // Punching a hole keeps the chunks around it
+        file.punch_hole(1000, 300_000).await?;
+        let mut expected = content.clone();
+        expected[1000..301_000].fill(0);
+        assert_eq!(file.read_at(0, content.len()).await?, expected);
+        let punched = ChunkList::load(&store, file.get_content().unwrap(), size)
+            .await?
+            .unwrap();
+        assert_eq!(punched.dependencies.last(), original.dependencies.last());
+
+        // Truncating keeps the chunks before the new end
+        file.truncate(size / 2).await?;
+        expected.truncate(content.len() / 2);
+        assert_eq!(file.read_at(0, content.len()).await?, expected);
+        let truncated = ChunkList::load(&store, file.get_content().unwrap(), size / 2)
+            .await?
+            .unwrap();
+        let mut end = 0;
+        let kept = punched
+            .dependencies
+            .iter()
+            .take_while(|(_, len)| {
+                end += *len as u64;
+                end <= size / 2
+            })
+            .count();
+        assert_eq!(truncated.dependencies[..kept], punched.dependencies[..kept]);
+
+        // Extending fills the file with zeros
+        file.truncate(size + 100_000).await?;
+        expected.resize(content.len() + 100_000, 0);
+        assert_eq!(file.read_at(0, expected.len()).await?, expected);
+
+        // An empty file can be extended too
+        let mut empty = File::new(store.clone());
+        empty.truncate(10).await?;
+        assert_eq!(empty.read_at(0, 100).await?, vec![0; 10]);
+        empty.truncate(0).await?;
+        assert!(empty.get_content().is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_append() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+
+        // Large enough to span several chunks
+        let mut content = (0..1024 * 1024)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let mut file = File::with_content(store.clone(), content.as_slice()).await?;
+        let original = ChunkList::load(&store, file.get_content().unwrap(), content.len() as u64)
+            .await?
+            .unwrap();
+
+        for i in 0..100 {
+            let line = format!("line {}\n", i);
+            file.append(line.as_bytes()).await?;
+            content.extend_from_slice(line.as_bytes());
+        }
+        assert_eq!(file.read_at(0, content.len()).await?, content);
+
+        // Only the last chunk was chunked again
+        let appended = ChunkList::load(&store, file.get_content().unwrap(), content.len() as u64)
+            .await?
+            .unwrap();
+        let kept = original.dependencies.len() - 1;
+        assert_eq!(appended.dependencies[..kept], original.dependencies[..kept]);
+
+        // Empty files are appended to as well
+        let mut empty = File::new(store);
+        empty.append(b"Hello").await?;
+        assert_eq!(empty.read_at(0, 100).await?, b"Hello");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_chunk_list_round_trip() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+
+        // The node the store itself keeps content of several chunks under
+        let content = (0..1024 * 1024)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let cid = store.put_bytes(content.as_slice()).await?;
+        let chunks = ChunkList::load(&store, &cid, content.len() as u64)
+            .await?
+            .unwrap();
+        assert!(chunks.dependencies.len() > 1);
+
+        // Lists the chunks of the content in order, with their sizes
+        let mut read = Vec::new();
+        for (chunk, len) in &chunks.dependencies {
+            let bytes = store.get_raw_block(chunk).await?;
+            assert_eq!(bytes.len(), *len);
+            read.extend_from_slice(&bytes);
+        }
+        assert_eq!(read, content);
+
+        // And is encoded back to the same node
+        assert_eq!(store.put_node(&chunks).await?, cid);
+
+        // A chunk list made here is read by the store like one of its own
+        let (first, first_len) = chunks.dependencies[0];
+        let (last, last_len) = *chunks.dependencies.last().unwrap();
+        let swapped = ChunkList {
+            size: first_len + last_len,
+            dependencies: vec![(last, last_len), (first, first_len)],
+        };
+        let cid = store.put_node(&swapped).await?;
+        let mut bytes = Vec::new();
+        store.get_bytes(&cid).await?.read_to_end(&mut bytes).await?;
+        let mut expected = store.get_raw_block(&last).await?.to_vec();
+        expected.extend_from_slice(&store.get_raw_block(&first).await?);
+        assert_eq!(bytes, expected);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_rewrite_bytes() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let content = store.put_bytes(b"Hello, World!".as_slice()).await?;
+
+        let cid = rewrite_bytes(&store, Some(&content), 13, 5..7, 15).await?;
+        let mut bytes = Vec::new();
+        store.get_bytes(&cid).await?.read_to_end(&mut bytes).await?;
+        assert_eq!(bytes, b"Hello\0\0World!\0\0");
+
+        let cid = rewrite_bytes(&store, None, 0, 0..0, 3).await?;
+        let mut bytes = Vec::new();
+        store.get_bytes(&cid).await?.read_to_end(&mut bytes).await?;
+        assert_eq!(bytes, b"\0\0\0");
+
+        Ok(())
+    }
+}
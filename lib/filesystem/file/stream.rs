@@ -0,0 +1,697 @@
+use std::{
+    io::{self, SeekFrom},
+    mem,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use futures::{future::BoxFuture, FutureExt};
+use getset::Getters;
+use ipldstore::{ipld::cid::Cid, IpldStoreSeekable};
+use microsandbox_utils::SeekableReader;
+use tokio::{
+    io::{
+        AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt, DuplexStream,
+        ReadBuf,
+    },
+    task::JoinHandle,
+};
+use typed_builder::TypedBuilder;
+
+use crate::{filesystem::File, FsError, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How many written bytes a [`FileStream`] holds before waiting for the store to chunk them.
+pub const FILE_STREAM_BUFFER_SIZE: usize = 256 * 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// How a file is opened with [`File::open_stream`].
+///
+/// ## Examples
+///
+/// ```
+/// use monofs::filesystem::OpenFlags;
+///
+/// let flags = OpenFlags::builder().read(true).write(true).build();
+///
+/// assert!(*flags.get_write());
+/// assert!(!*flags.get_append());
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, TypedBuilder, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct OpenFlags {
+    /// Whether the stream can be read from.
+    #[builder(default)]
+    read: bool,
+
+    /// Whether the stream can be written to.
+    #[builder(default)]
+    write: bool,
+
+    /// Whether every write goes to the end of the file, wherever the stream is positioned.
+    #[builder(default)]
+    append: bool,
+
+    /// Whether the file is emptied when it is opened. Requires `write`.
+    #[builder(default)]
+    truncate: bool,
+}
+
+/// A readable, writable and seekable stream over the content of a [`File`].
+///
+/// Content is streamed to and from the store a chunk at a time, so memory use stays bounded no
+/// matter how large the file is. Since content is immutable, writing produces new content: the
+/// stream copies the old content up to where it is written to, then the written bytes, and keeps
+/// the rest of the old content when the write is finished. Writes are therefore cheapest when
+/// they move forward through the file. Writing before a position that was already written to, or
+/// reading after a write, finishes the current write first.
+///
+/// Writes are applied to the file when the stream is flushed or shut down. Dropping the stream
+/// discards writes since the last flush.
+pub struct FileStream<'a, S>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    file: &'a mut File<S>,
+    flags: OpenFlags,
+    state: StreamState<S>,
+}
+
+/// Whether a [`FileStream`] is ready for direct reads and writes or preparing for them.
+enum StreamState<S> {
+    Idle(Box<StreamInner<S>>),
+    Busy(BoxFuture<'static, (Box<StreamInner<S>>, io::Result<()>)>),
+    Poisoned,
+}
+
+/// The part of a [`FileStream`] that does not borrow the file, so it can be moved into futures.
+struct StreamInner<S> {
+    /// The store the content is read from and written to.
+    store: S,
+
+    /// The content as of the last finished write.
+    content: Option<Cid>,
+
+    /// The size of `content`.
+    size: u64,
+
+    /// The position of the stream.
+    pos: u64,
+
+    /// A reader over `content`, opened on the first read.
+    reader: Option<Pin<Box<dyn SeekableReader + Send>>>,
+
+    /// The position of `reader`.
+    reader_pos: u64,
+
+    /// The write in progress, if any.
+    rewrite: Option<Rewrite>,
+
+    /// Whether `content` has changed since it was last applied to the file.
+    changed: bool,
+}
+
+/// New content being streamed into the store.
+struct Rewrite {
+    /// Where the new content is written to.
+    sink: DuplexStream,
+
+    /// The task putting the new content into the store.
+    task: JoinHandle<io::Result<Cid>>,
+
+    /// A reader over the old content, to copy the parts that are not overwritten.
+    source: Option<Pin<Box<dyn SeekableReader + Send>>>,
+
+    /// How many bytes of new content have been written.
+    written: u64,
+}
+
+/// What a [`FileStream`] prepares for.
+#[derive(Debug, Clone, Copy)]
+enum Operation {
+    Read,
+    Write,
+    Flush,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> File<S>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    /// Opens a stream for reading and writing the file's content without loading it into memory.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use monofs::filesystem::{File, OpenFlags};
+    /// use ipldstore::MemoryStore;
+    /// use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, SeekFrom};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let store = MemoryStore::default();
+    /// let mut file = File::with_content(store, b"Hello, World!".as_slice()).await?;
+    ///
+    /// let flags = OpenFlags::builder().read(true).write(true).build();
+    /// let mut stream = file.open_stream(flags).await?;
+    ///
+    /// stream.seek(SeekFrom::Start(7)).await?;
+    /// stream.write_all(b"there").await?;
+    ///
+    /// let mut content = String::new();
+    /// stream.rewind().await?;
+    /// stream.read_to_string(&mut content).await?;
+    /// assert_eq!(content, "Hello, there!");
+    ///
+    /// stream.shutdown().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn open_stream(&mut self, flags: OpenFlags) -> FsResult<FileStream<'_, S>> {
+        if flags.truncate && !flags.write {
+            return Err(FsError::InvalidOperation(
+                "a file must be opened for writing to be truncated".to_string(),
+            ));
+        }
+
+        if flags.truncate {
+            self.set_content(None);
+        }
+
+        let size = self.get_size().await?;
+        let inner = StreamInner {
+            store: self.get_store().clone(),
+            content: self.get_content().cloned(),
+            size,
+            pos: 0,
+            reader: None,
+            reader_pos: 0,
+            rewrite: None,
+            changed: false,
+        };
+
+        Ok(FileStream {
+            file: self,
+            flags,
+            state: StreamState::Idle(Box::new(inner)),
+        })
+    }
+}
+
+impl<S> FileStream<'_, S>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    /// Gets the flags the stream was opened with.
+    pub fn get_flags(&self) -> &OpenFlags {
+        &self.flags
+    }
+
+    /// Waits for any preparation in progress, then prepares the stream for `operation` if needed.
+    fn poll_prepare(&mut self, cx: &mut Context<'_>, operation: Operation) -> Poll<io::Result<()>> {
+        loop {
+            match &mut self.state {
+                StreamState::Busy(future) => {
+                    let (inner, result) = ready!(future.as_mut().poll(cx));
+                    self.state = StreamState::Idle(inner);
+                    result?;
+                }
+                StreamState::Idle(inner) if inner.is_prepared(operation, self.flags.append) => {
+                    return Poll::Ready(Ok(()));
+                }
+                StreamState::Idle(_) => {
+                    let StreamState::Idle(mut inner) =
+                        mem::replace(&mut self.state, StreamState::Poisoned)
+                    else {
+                        unreachable!()
+                    };
+
+                    let append = self.flags.append;
+                    let future = async move {
+                        let result = inner.prepare(operation, append).await;
+                        (inner, result)
+                    };
+                    self.state = StreamState::Busy(future.boxed());
+                }
+                StreamState::Poisoned => {
+                    return Poll::Ready(Err(io::Error::other("file stream is unusable")));
+                }
+            }
+        }
+    }
+
+    /// Gets the state of a stream that is not preparing anything.
+    fn get_inner_mut(&mut self) -> &mut StreamInner<S> {
+        match &mut self.state {
+            StreamState::Idle(inner) => inner,
+            _ => unreachable!("the stream is prepared before it is used"),
+        }
+    }
+}
+
+impl<S> StreamInner<S>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    /// Returns the size of the content, including what has been written so far.
+    fn get_size(&self) -> u64 {
+        match &self.rewrite {
+            Some(rewrite) => self.size.max(rewrite.written),
+            None => self.size,
+        }
+    }
+
+    /// Returns whether reads or writes can go directly to the reader or the sink.
+    fn is_prepared(&self, operation: Operation, append: bool) -> bool {
+        match operation {
+            Operation::Read => {
+                self.rewrite.is_none()
+                    && (self.pos >= self.size
+                        || (self.reader.is_some() && self.reader_pos == self.pos))
+            }
+            Operation::Write => {
+                let pos = if append { self.get_size() } else { self.pos };
+                matches!(&self.rewrite, Some(rewrite) if rewrite.written == pos)
+            }
+            Operation::Flush => self.rewrite.is_none(),
+        }
+    }
+
+    /// Prepares for `operation`.
+    async fn prepare(&mut self, operation: Operation, append: bool) -> io::Result<()> {
+        match operation {
+            Operation::Read => self.prepare_read().await,
+            Operation::Write => self.prepare_write(append).await,
+            Operation::Flush => self.finish_rewrite().await,
+        }
+    }
+
+    /// Finishes the write in progress and positions the reader.
+    async fn prepare_read(&mut self) -> io::Result<()> {
+        self.finish_rewrite().await?;
+        if self.pos >= self.size {
+            return Ok(());
+        }
+
+        if self.reader.is_none() {
+            let Some(cid) = self.content else {
+                return Ok(());
+            };
+
+            let reader = self
+                .store
+                .get_seekable_bytes(&cid)
+                .await
+                .map_err(io::Error::other)?;
+            self.reader = Some(reader);
+            self.reader_pos = 0;
+        }
+
+        if let Some(reader) = &mut self.reader {
+            if self.reader_pos != self.pos {
+                reader.seek(SeekFrom::Start(self.pos)).await?;
+                self.reader_pos = self.pos;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Starts a write if needed and brings the new content up to the position to write at.
+    async fn prepare_write(&mut self, append: bool) -> io::Result<()> {
+        if append {
+            self.pos = self.get_size();
+        }
+
+        // Content that was already written cannot be changed, so start over from it
+        if matches!(&self.rewrite, Some(rewrite) if rewrite.written > self.pos) {
+            self.finish_rewrite().await?;
+        }
+
+        if self.rewrite.is_none() {
+            self.start_rewrite().await?;
+        }
+
+        let Some(rewrite) = &mut self.rewrite else {
+            unreachable!()
+        };
+
+        // Keep the old content before the position, and fill any gap past its end with zeros
+        rewrite.copy_source(self.pos.min(self.size)).await?;
+        if rewrite.written < self.pos {
+            let zeros = self.pos - rewrite.written;
+            tokio::io::copy(&mut tokio::io::repeat(0).take(zeros), &mut rewrite.sink).await?;
+            rewrite.written = self.pos;
+        }
+
+        Ok(())
+    }
+
+    /// Starts streaming new content into the store.
+    async fn start_rewrite(&mut self) -> io::Result<()> {
+        let source = match &self.content {
+            Some(cid) => Some(
+                self.store
+                    .get_seekable_bytes(cid)
+                    .await
+                    .map_err(io::Error::other)?,
+            ),
+            None => None,
+        };
+
+        let (sink, reader) = tokio::io::duplex(FILE_STREAM_BUFFER_SIZE);
+        let store = self.store.clone();
+        let task =
+            tokio::spawn(async move { store.put_bytes(reader).await.map_err(io::Error::other) });
+
+        self.rewrite = Some(Rewrite {
+            sink,
+            task,
+            source,
+            written: 0,
+        });
+
+        Ok(())
+    }
+
+    /// Finishes the write in progress, if any, making the new content the current content.
+    async fn finish_rewrite(&mut self) -> io::Result<()> {
+        let Some(mut rewrite) = self.rewrite.take() else {
+            return Ok(());
+        };
+
+        rewrite.copy_source(self.size).await?;
+        self.content = if rewrite.written > 0 {
+            rewrite.sink.shutdown().await?;
+            Some((&mut rewrite.task).await.map_err(io::Error::other)??)
+        } else {
+            None
+        };
+
+        self.size = rewrite.written;
+        self.reader = None;
+        self.changed = true;
+
+        Ok(())
+    }
+
+    /// Reads from the current content at the position.
+    fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let Some(reader) = &mut self.reader else {
+            return Poll::Ready(Ok(()));
+        };
+
+        if self.pos >= self.size {
+            return Poll::Ready(Ok(()));
+        }
+
+        let filled = buf.filled().len();
+        ready!(reader.as_mut().poll_read(cx, buf))?;
+
+        let read = (buf.filled().len() - filled) as u64;
+        self.pos += read;
+        self.reader_pos += read;
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Writes to the new content at the position.
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let Some(rewrite) = &mut self.rewrite else {
+            unreachable!("writes are prepared before they are polled")
+        };
+
+        let written = ready!(Pin::new(&mut rewrite.sink).poll_write(cx, buf))?;
+        rewrite.written += written as u64;
+        self.pos += written as u64;
+
+        Poll::Ready(Ok(written))
+    }
+
+    /// Moves the position, without touching the reader or the sink until they are used.
+    fn seek(&mut self, position: SeekFrom) -> io::Result<()> {
+        let pos = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.get_size().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+
+        self.pos = pos.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        Ok(())
+    }
+}
+
+impl Rewrite {
+    /// Copies the old content from where the new content ends up to `end`.
+    async fn copy_source(&mut self, end: u64) -> io::Result<()> {
+        let Some(source) = &mut self.source else {
+            return Ok(());
+        };
+
+        if end <= self.written {
+            return Ok(());
+        }
+
+        source.seek(SeekFrom::Start(self.written)).await?;
+        let mut source = (&mut *source).take(end - self.written);
+        self.written += tokio::io::copy(&mut source, &mut self.sink).await?;
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<S> AsyncRead for FileStream<'_, S>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.flags.read {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "file was not opened for reading",
+            )));
+        }
+
+        ready!(this.poll_prepare(cx, Operation::Read))?;
+        this.get_inner_mut().poll_read(cx, buf)
+    }
+}
+
+impl<S> AsyncWrite for FileStream<'_, S>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if !this.flags.write {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "file was not opened for writing",
+            )));
+        }
+
+        ready!(this.poll_prepare(cx, Operation::Write))?;
+        this.get_inner_mut().poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_prepare(cx, Operation::Flush))?;
+        let inner = this.get_inner_mut();
+        if mem::take(&mut inner.changed) {
+            let content = inner.content;
+            this.file.set_content(content);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl<S> AsyncSeek for FileStream<'_, S>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        match &mut self.get_mut().state {
+            StreamState::Idle(inner) => inner.seek(position),
+            _ => Err(io::Error::other(
+                "cannot seek while another operation is in progress",
+            )),
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        match &self.state {
+            StreamState::Idle(inner) => Poll::Ready(Ok(inner.pos)),
+            _ => Poll::Ready(Err(io::Error::other(
+                "cannot seek while another operation is in progress",
+            ))),
+        }
+    }
+}
+
+impl Drop for Rewrite {
+    fn drop(&mut self) {
+        // Content that is not finished is not kept
+        self.task.abort();
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use ipldstore::MemoryStore;
+
+    use super::*;
+
+    async fn read_all(file: &mut File<MemoryStore>) -> Result<Vec<u8>> {
+        let flags = OpenFlags::builder().read(true).build();
+        let mut stream = file.open_stream(flags).await?;
+        let mut content = Vec::new();
+        stream.read_to_end(&mut content).await?;
+        Ok(content)
+    }
+
+    #[tokio::test]
+    async fn test_file_stream_write_and_read() -> Result<()> {
+        let store = MemoryStore::default();
+        let mut file = File::new(store);
+
+        // Write more than the buffer holds, a piece at a time
+        let data = (0..FILE_STREAM_BUFFER_SIZE * 3)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let flags = OpenFlags::builder().write(true).build();
+        let mut stream = file.open_stream(flags).await?;
+        for piece in data.chunks(10_000) {
+            stream.write_all(piece).await?;
+        }
+        stream.shutdown().await?;
+        drop(stream);
+
+        assert_eq!(file.get_size().await?, data.len() as u64);
+        assert_eq!(read_all(&mut file).await?, data);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_stream_overwrite_and_seek() -> Result<()> {
+        let store = MemoryStore::default();
+        let mut file = File::with_content(store, b"Hello, world!".as_slice()).await?;
+
+        let flags = OpenFlags::builder().read(true).write(true).build();
+        let mut stream = file.open_stream(flags).await?;
+
+        // Overwrite in the middle, keeping the rest
+        stream.seek(SeekFrom::Start(7)).await?;
+        stream.write_all(b"there").await?;
+
+        // Reading finishes the write
+        let mut buf = [0u8; 1];
+        stream.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"!");
+
+        // Writing before what was already written starts over from the new content
+        stream.rewind().await?;
+        stream.write_all(b"J").await?;
+
+        // Writing past the end fills the gap with zeros
+        stream.seek(SeekFrom::End(2)).await?;
+        stream.write_all(b"?").await?;
+        stream.flush().await?;
+        drop(stream);
+
+        assert_eq!(read_all(&mut file).await?, b"Jello, there!\0\0?");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_stream_append_and_truncate() -> Result<()> {
+        let store = MemoryStore::default();
+        let mut file = File::with_content(store, b"Hello".as_slice()).await?;
+
+        let flags = OpenFlags::builder().write(true).append(true).build();
+        let mut stream = file.open_stream(flags).await?;
+        stream.rewind().await?;
+        stream.write_all(b", world").await?;
+        stream.write_all(b"!").await?;
+        stream.shutdown().await?;
+        drop(stream);
+        assert_eq!(read_all(&mut file).await?, b"Hello, world!");
+
+        let flags = OpenFlags::builder().write(true).truncate(true).build();
+        let mut stream = file.open_stream(flags).await?;
+        stream.shutdown().await?;
+        drop(stream);
+        assert!(file.is_empty().await?);
+        assert!(file.get_content().is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_stream_respects_flags() -> Result<()> {
+        let store = MemoryStore::default();
+        let mut file = File::with_content(store, b"Hello".as_slice()).await?;
+
+        let flags = OpenFlags::builder().read(true).build();
+        let mut stream = file.open_stream(flags).await?;
+        let result = stream.write_all(b"nope").await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        drop(stream);
+
+        // Dropping a stream without flushing it discards the writes
+        let flags = OpenFlags::builder().write(true).build();
+        let mut stream = file.open_stream(flags).await?;
+        stream.write_all(b"Jello").await?;
+        drop(stream);
+        assert_eq!(read_all(&mut file).await?, b"Hello");
+
+        let flags = OpenFlags::builder().read(true).truncate(true).build();
+        assert!(matches!(
+            file.open_stream(flags).await,
+            Err(FsError::InvalidOperation(_))
+        ));
+
+        Ok(())
+    }
+}
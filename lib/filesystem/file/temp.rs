@@ -0,0 +1,159 @@
+use std::ops::{Deref, DerefMut};
+
+use ipldstore::IpldStore;
+
+use crate::{
+    filesystem::{Dir, File},
+    FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A file that is not in any directory, like one opened with `O_TMPFILE`.
+///
+/// The file is written and read like any other [`File`], but is only reachable through this
+/// value. It is put in a directory when it is [linked](Self::link), and dropping it without
+/// linking it discards it, so short-lived files never become part of the tree. Content it wrote to
+/// the store is left unreferenced and reclaimed by garbage collection.
+///
+/// ## Examples
+///
+/// ```
+/// use monofs::filesystem::{Dir, TempFile};
+/// use ipldstore::MemoryStore;
+///
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let store = MemoryStore::default();
+/// let mut dir = Dir::new(store.clone());
+///
+/// let mut temp = TempFile::new(store);
+/// temp.truncate(4).await?;
+/// assert!(dir.is_empty());
+///
+/// temp.link(&mut dir, "file.txt").await?;
+/// assert!(dir.get_file("file.txt").await?.is_some());
+/// # Ok(())
+/// # }
+/// ```
+pub struct TempFile<S>
+where
+    S: IpldStore,
+{
+    file: File<S>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> TempFile<S>
+where
+    S: IpldStore,
+{
+    /// Creates a new empty temporary file.
+    pub fn new(store: S) -> Self {
+        Self {
+            file: File::new(store),
+        }
+    }
+
+    /// Returns the file.
+    pub fn get_file(&self) -> &File<S> {
+        &self.file
+    }
+
+    /// Returns the file, to be changed.
+    pub fn get_file_mut(&mut self) -> &mut File<S> {
+        &mut self.file
+    }
+
+    /// Puts the file in `dir` under `name`.
+    ///
+    /// Fails with [`FsError::PathExists`] if `dir` already has an entry called `name`, in which
+    /// case the file is discarded.
+    pub async fn link(self, dir: &mut Dir<S>, name: impl AsRef<str>) -> FsResult<()>
+    where
+        S: Send + Sync,
+    {
+        let name = name.as_ref();
+        if dir.has_entry(name)? {
+            return Err(FsError::PathExists(name.to_string()));
+        }
+
+        dir.put_adapted_file(name, self.file).await
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<S> Deref for TempFile<S>
+where
+    S: IpldStore,
+{
+    type Target = File<S>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.file
+    }
+}
+
+impl<S> DerefMut for TempFile<S>
+where
+    S: IpldStore,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.file
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::MemoryStore;
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_temp_file_link() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut dir = Dir::new(store.clone());
+        dir.put_adapted_file("taken.txt", File::new(store.clone()))
+            .await?;
+
+        // Dropped temporary files never show up in the directory
+        let mut temp = TempFile::new(store.clone());
+        temp.set_content(Some(store.put_bytes(b"discarded".as_slice()).await?));
+        drop(temp);
+        assert_eq!(dir.len(), 1);
+
+        // A name that is taken cannot be linked to
+        let temp = TempFile::new(store.clone());
+        assert!(matches!(
+            temp.link(&mut dir, "taken.txt").await,
+            Err(FsError::PathExists(_))
+        ));
+
+        let mut temp = TempFile::new(store.clone());
+        temp.set_content(Some(store.put_bytes(b"kept".as_slice()).await?));
+        temp.link(&mut dir, "kept.txt").await?;
+
+        let file = dir.get_file("kept.txt").await?.unwrap();
+        let mut content = String::new();
+        file.get_input_stream()
+            .await?
+            .read_to_string(&mut content)
+            .await?;
+        assert_eq!(content, "kept");
+
+        Ok(())
+    }
+}
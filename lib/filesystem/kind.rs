@@ -1,3 +1,5 @@
+use std::fmt::{self, Display};
+
 use serde::{Deserialize, Serialize};
 
 //--------------------------------------------------------------------------------------------------
@@ -22,3 +24,18 @@ pub enum EntityType {
     /// The entity is a symbolic path link.
     SymPathLink,
 }
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Display for EntityType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntityType::File => write!(f, "file"),
+            EntityType::Dir => write!(f, "dir"),
+            EntityType::SymCidLink => write!(f, "symcidlink"),
+            EntityType::SymPathLink => write!(f, "sympathlink"),
+        }
+    }
+}
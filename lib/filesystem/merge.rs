@@ -0,0 +1,359 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use async_recursion::async_recursion;
+use getset::Getters;
+use ipldstore::{ipld::cid::Cid, IpldStore, Storable};
+use typed_path::Utf8UnixPathBuf;
+
+use crate::FsResult;
+
+use super::{
+    diff::{get_content_key, ContentKey},
+    Dir, Entity, EntityCidLink,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The outcome of a three-way merge.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct MergeResult {
+    /// The CID of the merged root directory.
+    root: Cid,
+
+    /// The paths that were changed differently on both sides.
+    ///
+    /// The merged root holds our version of each conflicting path, or their version if we
+    /// removed it.
+    conflicts: Vec<MergeConflict>,
+}
+
+/// A path that was changed differently on both sides of a merge.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct MergeConflict {
+    /// The path of the conflicting entity relative to the root.
+    path: Utf8UnixPathBuf,
+
+    /// How the two sides conflict.
+    kind: ConflictKind,
+}
+
+/// How the two sides of a merge conflict at a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// Both sides changed the entity in different ways.
+    BothModified,
+
+    /// Both sides added different entities at the same path.
+    BothAdded,
+
+    /// We modified the entity and they removed it.
+    ModifiedRemoved,
+
+    /// We removed the entity and they modified it.
+    RemovedModified,
+}
+
+/// An entry of one of the merged directories.
+struct Side<'a, S>
+where
+    S: IpldStore,
+{
+    /// The link to the entity.
+    link: &'a EntityCidLink<S>,
+
+    /// The entity.
+    entity: &'a Entity<S>,
+
+    /// What the entity holds.
+    key: ContentKey,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Merges two directory trees that diverged from a common ancestor.
+///
+/// Changes made on only one side since `base` are taken automatically. When both sides changed a
+/// path, the changes are merged if both made the same change, or if both sides still have a
+/// directory there, in which case the directories are merged recursively. Anything else is a
+/// conflict: our version is kept in the merged root, or their version if we removed the path,
+/// and the path is listed in the result so the caller can resolve it.
+///
+/// Entities are compared by content, so an entity that was rewritten with the same content is
+/// not considered changed.
+///
+/// ## Examples
+///
+/// ```
+/// use monofs::filesystem::{self, Dir, File};
+/// use ipldstore::{MemoryStore, Storable};
+///
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let store = MemoryStore::default();
+/// let mut base = Dir::new(store.clone());
+/// let base_cid = base.checkpoint().await?;
+///
+/// let mut ours = base.clone();
+/// let file = File::with_content(store.clone(), b"ours".as_slice()).await?;
+/// ours.put_adapted_file("ours.txt", file).await?;
+///
+/// let mut theirs = base.clone();
+/// let file = File::with_content(store.clone(), b"theirs".as_slice()).await?;
+/// theirs.put_adapted_file("theirs.txt", file).await?;
+///
+/// let result = filesystem::merge(
+///     base_cid,
+///     ours.checkpoint().await?,
+///     theirs.checkpoint().await?,
+///     store.clone(),
+/// )
+/// .await?;
+/// assert!(result.get_conflicts().is_empty());
+///
+/// let merged = Dir::load(result.get_root(), store).await?;
+/// assert!(merged.has_entry("ours.txt")?);
+/// assert!(merged.has_entry("theirs.txt")?);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn merge<S>(base: Cid, ours: Cid, theirs: Cid, store: S) -> FsResult<MergeResult>
+where
+    S: IpldStore + Send + Sync,
+{
+    let mut conflicts = Vec::new();
+    let root = if ours == theirs || theirs == base {
+        ours
+    } else if ours == base {
+        theirs
+    } else {
+        let path = Utf8UnixPathBuf::new();
+        merge_dirs(&store, &path, Some(base), ours, theirs, &mut conflicts)
+            .await?
+            .checkpoint()
+            .await?
+    };
+
+    Ok(MergeResult { root, conflicts })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Merges the directories at `ours` and `theirs`, starting from ours.
+#[async_recursion]
+async fn merge_dirs<S>(
+    store: &S,
+    path: &Utf8UnixPathBuf,
+    base: Option<Cid>,
+    ours: Cid,
+    theirs: Cid,
+    conflicts: &mut Vec<MergeConflict>,
+) -> FsResult<Dir<S>>
+where
+    S: IpldStore + Send + Sync,
+{
+    let base_dir = match base {
+        Some(cid) => Some(Dir::load(&cid, store.clone()).await?),
+        None => None,
+    };
+    let ours_dir = Dir::load(&ours, store.clone()).await?;
+    let theirs_dir = Dir::load(&theirs, store.clone()).await?;
+
+    let base_entries = match &base_dir {
+        Some(dir) => get_sides(dir).await?,
+        None => BTreeMap::new(),
+    };
+    let ours_entries = get_sides(&ours_dir).await?;
+    let theirs_entries = get_sides(&theirs_dir).await?;
+
+    let names = base_entries
+        .keys()
+        .chain(ours_entries.keys())
+        .chain(theirs_entries.keys())
+        .cloned()
+        .collect::<BTreeSet<_>>();
+
+    let mut merged = ours_dir.clone();
+    for name in names {
+        let base = base_entries.get(&name);
+        let ours = ours_entries.get(&name);
+        let theirs = theirs_entries.get(&name);
+
+        let base_key = base.map(|side| &side.key);
+        let ours_key = ours.map(|side| &side.key);
+        let theirs_key = theirs.map(|side| &side.key);
+
+        // Same change on both sides, or only we changed it
+        if ours_key == theirs_key || theirs_key == base_key {
+            continue;
+        }
+
+        // Only they changed it
+        if ours_key == base_key {
+            match theirs {
+                Some(theirs) => {
+                    merged
+                        .put_adapted_entity(&name, theirs.entity.clone())
+                        .await?
+                }
+                None => {
+                    merged.remove_entry(&name)?;
+                }
+            }
+            continue;
+        }
+
+        // Both changed it, which only merges cleanly if both still have a directory
+        let entity_path = path.join(&name);
+        match (ours, theirs) {
+            (Some(ours), Some(theirs))
+                if matches!(
+                    (ours.entity, theirs.entity),
+                    (Entity::Dir(_), Entity::Dir(_))
+                ) =>
+            {
+                let base = match base {
+                    Some(base) if matches!(base.entity, Entity::Dir(_)) => {
+                        Some(base.link.resolve_cid::<S>().await?)
+                    }
+                    _ => None,
+                };
+                let ours = ours.link.resolve_cid::<S>().await?;
+                let theirs = theirs.link.resolve_cid::<S>().await?;
+                let dir = merge_dirs(store, &entity_path, base, ours, theirs, conflicts).await?;
+                merged.put_adapted_dir(&name, dir).await?;
+            }
+            (Some(_), Some(_)) => conflicts.push(MergeConflict {
+                path: entity_path,
+                kind: if base.is_some() {
+                    ConflictKind::BothModified
+                } else {
+                    ConflictKind::BothAdded
+                },
+            }),
+            (Some(_), None) => conflicts.push(MergeConflict {
+                path: entity_path,
+                kind: ConflictKind::ModifiedRemoved,
+            }),
+            (None, Some(theirs)) => {
+                merged
+                    .put_adapted_entity(&name, theirs.entity.clone())
+                    .await?;
+                conflicts.push(MergeConflict {
+                    path: entity_path,
+                    kind: ConflictKind::RemovedModified,
+                });
+            }
+            (None, None) => {}
+        }
+    }
+
+    Ok(merged)
+}
+
+/// Returns the entries of `dir` along with their entities and content keys.
+async fn get_sides<S>(dir: &Dir<S>) -> FsResult<BTreeMap<String, Side<'_, S>>>
+where
+    S: IpldStore + Send + Sync,
+{
+    let mut sides = BTreeMap::new();
+    for (name, link) in dir.get_entries() {
+        let entity = link.resolve_entity(dir.get_store().clone()).await?;
+        let key = get_content_key(entity).await?;
+        sides.insert(name.to_string(), Side { link, entity, key });
+    }
+
+    Ok(sides)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::MemoryStore;
+    use tokio::io::AsyncReadExt;
+
+    use crate::filesystem::File;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_merge_combines_changes_and_reports_conflicts() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut base = Dir::new(store.clone());
+        for (path, content) in [
+            ("shared/a.txt", "a"),
+            ("shared/b.txt", "b"),
+            ("conflict.txt", "base"),
+            ("removed.txt", "removed"),
+        ] {
+            put_file(&mut base, path, content).await?;
+        }
+        let base_cid = base.checkpoint().await?;
+
+        // Each side changes a different file in the same directory and both change one file
+        let mut ours = base.clone();
+        put_file(&mut ours, "shared/a.txt", "ours").await?;
+        put_file(&mut ours, "conflict.txt", "ours").await?;
+        ours.remove("removed.txt").await?;
+        let ours_cid = ours.checkpoint().await?;
+
+        let mut theirs = base.clone();
+        put_file(&mut theirs, "shared/b.txt", "theirs").await?;
+        put_file(&mut theirs, "conflict.txt", "theirs").await?;
+        put_file(&mut theirs, "added.txt", "theirs").await?;
+        let theirs_cid = theirs.checkpoint().await?;
+
+        let result = merge(base_cid, ours_cid, theirs_cid, store.clone()).await?;
+        assert_eq!(
+            result.get_conflicts(),
+            &vec![MergeConflict {
+                path: Utf8UnixPathBuf::from("conflict.txt"),
+                kind: ConflictKind::BothModified,
+            }]
+        );
+
+        let merged = Dir::load(result.get_root(), store.clone()).await?;
+        assert_eq!(read_file(&merged, "shared/a.txt").await?, "ours");
+        assert_eq!(read_file(&merged, "shared/b.txt").await?, "theirs");
+        assert_eq!(read_file(&merged, "conflict.txt").await?, "ours");
+        assert_eq!(read_file(&merged, "added.txt").await?, "theirs");
+        assert!(merged.find("removed.txt").await?.is_none());
+
+        Ok(())
+    }
+
+    async fn put_file(root: &mut Dir<MemoryStore>, path: &str, content: &str) -> FsResult<()> {
+        let file = File::with_content(root.get_store().clone(), content.as_bytes()).await?;
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        match parent {
+            "" => root.put_adapted_file(name, file).await,
+            parent => match root.find_or_create(parent, false).await? {
+                Entity::Dir(dir) => dir.put_adapted_file(name, file).await,
+                _ => unreachable!(),
+            },
+        }
+    }
+
+    async fn read_file(root: &Dir<MemoryStore>, path: &str) -> anyhow::Result<String> {
+        let Some(Entity::File(file)) = root.find(path).await? else {
+            anyhow::bail!("{} is not a file", path);
+        };
+
+        let mut content = String::new();
+        file.get_input_stream()
+            .await?
+            .read_to_string(&mut content)
+            .await?;
+        Ok(content)
+    }
+}
@@ -5,7 +5,7 @@ use std::{
     sync::Arc,
 };
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeZone, Utc};
 use getset::Getters;
 use ipldstore::{
     ipld::{cid::Cid, ipld::Ipld},
@@ -37,6 +37,11 @@ pub const UNIX_ATIME_KEY: &str = "unix.atime";
 /// Key for storing Unix modification time in extended attributes.
 pub const UNIX_MTIME_KEY: &str = "unix.mtime";
 
+/// Prefix of the extended attribute keys that store user-visible xattrs, e.g. `user.comment` is
+/// stored under `xattr.user.comment`. This keeps them apart from the attributes monofs itself
+/// uses, such as [`UNIX_MODE_KEY`].
+pub const XATTR_KEY_PREFIX: &str = "xattr.";
+
 //--------------------------------------------------------------------------------------------------
 // Types
 //--------------------------------------------------------------------------------------------------
@@ -72,9 +77,12 @@ where
     /// The time the entity was created.
     created_at: DateTime<Utc>,
 
-    /// The time of the last modification of the entity.
+    /// The time of the last modification of the entity's content.
     modified_at: DateTime<Utc>,
 
+    /// The time of the last change to the entity, its content or its metadata, like `ctime`.
+    changed_at: DateTime<Utc>,
+
     /// The sync type of the entity.
     sync_type: SyncType,
 
@@ -157,6 +165,8 @@ pub struct MetadataSerializable {
     entity_type: EntityType,
     created_at: DateTime<Utc>,
     modified_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    changed_at: Option<DateTime<Utc>>,
     sync_type: SyncType,
     extended_attrs: Option<Cid>,
 }
@@ -180,6 +190,7 @@ where
             entity_type,
             created_at: now,
             modified_at: now,
+            changed_at: now,
             sync_type: SyncType::default(),
             extended_attrs: None,
             store,
@@ -187,11 +198,15 @@ where
     }
 
     /// Creates a new metadata instance from a serializable representation.
+    ///
+    /// Metadata without a change time, which is only stored when it differs from the
+    /// modification time, was last changed when it was modified.
     pub fn from_serializable(serializable: MetadataSerializable, store: S) -> FsResult<Self> {
         Ok(Self {
             entity_type: serializable.entity_type,
             created_at: serializable.created_at,
             modified_at: serializable.modified_at,
+            changed_at: serializable.changed_at.unwrap_or(serializable.modified_at),
             sync_type: serializable.sync_type,
             extended_attrs: serializable
                 .extended_attrs
@@ -215,6 +230,7 @@ where
             entity_type: self.entity_type,
             created_at: self.created_at,
             modified_at: self.modified_at,
+            changed_at: (self.changed_at != self.modified_at).then_some(self.changed_at),
             sync_type: self.sync_type,
             extended_attrs,
         })
@@ -319,6 +335,233 @@ where
         Ok(())
     }
 
+    /// Removes an attribute.
+    ///
+    /// Returns the value the attribute had, or `None` if it was not set.
+    pub async fn remove_attribute(&mut self, key: impl AsRef<str>) -> FsResult<Option<Arc<Ipld>>>
+    where
+        S: Send + Sync,
+    {
+        match &mut self.extended_attrs {
+            Some(link) => {
+                let attrs = link.resolve_value_mut(self.store.clone()).await?;
+                let ipld = attrs.inner.write().await.map.remove(key.as_ref());
+                Ok(ipld)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Gets the Unix permission bits of the entity, including the setuid, setgid and sticky bits.
+    ///
+    /// Returns `None` if no mode has been recorded.
+    pub async fn get_mode(&self) -> FsResult<Option<u32>>
+    where
+        S: Send + Sync,
+    {
+        self.get_unix_attribute(UNIX_MODE_KEY).await
+    }
+
+    /// Gets the user ID of the owner of the entity, or `None` if no owner has been recorded.
+    pub async fn get_uid(&self) -> FsResult<Option<u32>>
+    where
+        S: Send + Sync,
+    {
+        self.get_unix_attribute(UNIX_UID_KEY).await
+    }
+
+    /// Gets the group ID of the entity, or `None` if no group has been recorded.
+    pub async fn get_gid(&self) -> FsResult<Option<u32>>
+    where
+        S: Send + Sync,
+    {
+        self.get_unix_attribute(UNIX_GID_KEY).await
+    }
+
+    /// Sets the Unix permission bits of the entity, like `chmod(2)`.
+    ///
+    /// Only the permission, setuid, setgid and sticky bits of `mode` are kept. The file type is
+    /// given by the entity type.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use monofs::filesystem::{EntityType, Metadata};
+    /// use ipldstore::MemoryStore;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let store = MemoryStore::default();
+    /// let mut metadata = Metadata::new(EntityType::File, store);
+    ///
+    /// metadata.set_permissions(0o100600).await?;
+    /// assert_eq!(metadata.get_mode().await?, Some(0o600));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn set_permissions(&mut self, mode: u32) -> FsResult<()>
+    where
+        S: Send + Sync,
+    {
+        self.set_attribute(UNIX_MODE_KEY, mode & 0o7777).await
+    }
+
+    /// Sets the owner and group of the entity, like `chown(2)`. A `None` leaves that ID unchanged.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use monofs::filesystem::{EntityType, Metadata};
+    /// use ipldstore::MemoryStore;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let store = MemoryStore::default();
+    /// let mut metadata = Metadata::new(EntityType::File, store);
+    ///
+    /// metadata.chown(Some(1000), Some(1000)).await?;
+    /// metadata.chown(None, Some(100)).await?;
+    /// assert_eq!(metadata.get_uid().await?, Some(1000));
+    /// assert_eq!(metadata.get_gid().await?, Some(100));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn chown(&mut self, uid: Option<u32>, gid: Option<u32>) -> FsResult<()>
+    where
+        S: Send + Sync,
+    {
+        if let Some(uid) = uid {
+            self.set_attribute(UNIX_UID_KEY, uid).await?;
+        }
+
+        if let Some(gid) = gid {
+            self.set_attribute(UNIX_GID_KEY, gid).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a numeric Unix attribute such as the mode.
+    ///
+    /// Older versions stored these attributes as decimal strings, which are still accepted.
+    async fn get_unix_attribute(&self, key: &str) -> FsResult<Option<u32>>
+    where
+        S: Send + Sync,
+    {
+        Ok(self
+            .get_attribute(key)
+            .await?
+            .and_then(|ipld| match &*ipld {
+                Ipld::String(s) => s.parse().ok(),
+                Ipld::Integer(i) => u32::try_from(*i).ok(),
+                _ => None,
+            }))
+    }
+
+    /// Gets the value of an xattr, e.g. `user.comment` or `security.selinux`.
+    ///
+    /// Xattrs are stored as raw bytes among the extended attributes, under their name prefixed
+    /// with [`XATTR_KEY_PREFIX`].
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use monofs::filesystem::{EntityType, Metadata};
+    /// use ipldstore::MemoryStore;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let store = MemoryStore::default();
+    /// let mut metadata = Metadata::new(EntityType::File, store);
+    ///
+    /// assert_eq!(metadata.get_xattr("user.comment").await?, None);
+    ///
+    /// metadata.set_xattr("user.comment", b"draft".to_vec()).await?;
+    /// assert_eq!(metadata.get_xattr("user.comment").await?, Some(b"draft".to_vec()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_xattr(&self, name: impl AsRef<str>) -> FsResult<Option<Vec<u8>>>
+    where
+        S: Send + Sync,
+    {
+        let key = format!("{}{}", XATTR_KEY_PREFIX, name.as_ref());
+        let value = self.get_attribute(key).await?;
+        Ok(value.and_then(|ipld| match &*ipld {
+            Ipld::Bytes(bytes) => Some(bytes.clone()),
+            _ => None,
+        }))
+    }
+
+    /// Sets the value of an xattr, replacing any previous value.
+    pub async fn set_xattr(
+        &mut self,
+        name: impl AsRef<str>,
+        value: impl Into<Vec<u8>>,
+    ) -> FsResult<()>
+    where
+        S: Send + Sync,
+    {
+        let key = format!("{}{}", XATTR_KEY_PREFIX, name.as_ref());
+        self.set_attribute(key, Ipld::Bytes(value.into())).await
+    }
+
+    /// Removes an xattr.
+    ///
+    /// Returns `false` if the xattr was not set.
+    pub async fn remove_xattr(&mut self, name: impl AsRef<str>) -> FsResult<bool>
+    where
+        S: Send + Sync,
+    {
+        let key = format!("{}{}", XATTR_KEY_PREFIX, name.as_ref());
+        Ok(self.remove_attribute(key).await?.is_some())
+    }
+
+    /// Lists the names of the xattrs that are set, in sorted order.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use monofs::filesystem::{EntityType, Metadata, UNIX_MODE_KEY};
+    /// use ipldstore::MemoryStore;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let store = MemoryStore::default();
+    /// let mut metadata = Metadata::new(EntityType::File, store);
+    ///
+    /// metadata.set_xattr("user.b", b"2".to_vec()).await?;
+    /// metadata.set_xattr("user.a", b"1".to_vec()).await?;
+    ///
+    /// // Attributes used by monofs itself are not xattrs
+    /// metadata.set_attribute(UNIX_MODE_KEY, 0o644).await?;
+    ///
+    /// assert_eq!(metadata.list_xattrs().await?, vec!["user.a", "user.b"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn list_xattrs(&self) -> FsResult<Vec<String>>
+    where
+        S: Send + Sync,
+    {
+        match &self.extended_attrs {
+            Some(link) => {
+                let attrs = link.resolve_value(self.store.clone()).await?;
+                let names = attrs
+                    .inner
+                    .read()
+                    .await
+                    .map
+                    .keys()
+                    .filter_map(|key| key.strip_prefix(XATTR_KEY_PREFIX))
+                    .map(str::to_string)
+                    .collect();
+                Ok(names)
+            }
+            None => Ok(vec![]),
+        }
+    }
+
     /// Sets the sync type.
     pub fn set_sync_type(&mut self, sync_type: SyncType) {
         self.sync_type = sync_type;
@@ -329,6 +572,63 @@ where
         self.modified_at = modified_at;
     }
 
+    /// Sets the changed timestamp.
+    pub fn set_changed_at(&mut self, changed_at: DateTime<Utc>) {
+        self.changed_at = changed_at;
+    }
+
+    /// Records that the content of the entity was modified at `at`, which changes the entity
+    /// too, so both the modified and the changed timestamps are set.
+    pub fn mark_modified(&mut self, at: DateTime<Utc>) {
+        self.modified_at = at;
+        self.changed_at = at;
+    }
+
+    /// Gets the time the content of the entity was last accessed, or `None` if no access has
+    /// been recorded. Access times are recorded with a resolution of a second.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use chrono::{TimeZone, Utc};
+    /// use monofs::filesystem::{EntityType, Metadata};
+    /// use ipldstore::MemoryStore;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let store = MemoryStore::default();
+    /// let mut metadata = Metadata::new(EntityType::File, store);
+    /// assert_eq!(metadata.get_accessed_at().await?, None);
+    ///
+    /// let at = Utc.timestamp_opt(1_000_000, 0).unwrap();
+    /// metadata.set_accessed_at(at).await?;
+    /// assert_eq!(metadata.get_accessed_at().await?, Some(at));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_accessed_at(&self) -> FsResult<Option<DateTime<Utc>>>
+    where
+        S: Send + Sync,
+    {
+        Ok(self
+            .get_attribute(UNIX_ATIME_KEY)
+            .await?
+            .and_then(|ipld| match &*ipld {
+                Ipld::String(s) => s.parse().ok(),
+                Ipld::Integer(i) => i64::try_from(*i).ok(),
+                _ => None,
+            })
+            .and_then(|seconds| Utc.timestamp_opt(seconds, 0).single()))
+    }
+
+    /// Records that the content of the entity was accessed at `at`.
+    pub async fn set_accessed_at(&mut self, at: DateTime<Utc>) -> FsResult<()>
+    where
+        S: Send + Sync,
+    {
+        self.set_attribute(UNIX_ATIME_KEY, at.timestamp()).await
+    }
+
     /// Sets the created timestamp.
     pub fn set_created_at(&mut self, created_at: DateTime<Utc>) {
         self.created_at = created_at;
@@ -407,6 +707,7 @@ where
             .field("entity_type", &self.entity_type)
             .field("created_at", &self.created_at)
             .field("modified_at", &self.modified_at)
+            .field("changed_at", &self.changed_at)
             .field("sync_type", &self.sync_type)
             .field(
                 "extended_attrs",
@@ -479,6 +780,32 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_metadata_timestamps() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut metadata = Metadata::new(EntityType::File, store.clone());
+        assert_eq!(metadata.get_changed_at(), metadata.get_modified_at());
+        assert_eq!(metadata.get_accessed_at().await?, None);
+
+        // The change time is only stored when it differs from the modification time
+        let modified_at = Utc::now() + chrono::TimeDelta::seconds(10);
+        metadata.mark_modified(modified_at);
+        assert!(metadata.get_serializable().await?.changed_at.is_none());
+
+        let changed_at = modified_at + chrono::TimeDelta::seconds(10);
+        metadata.set_changed_at(changed_at);
+        let accessed_at = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        metadata.set_accessed_at(accessed_at).await?;
+
+        let cid = metadata.store().await?;
+        let loaded = Metadata::load(&cid, store).await?;
+        assert_eq!(*loaded.get_modified_at(), modified_at);
+        assert_eq!(*loaded.get_changed_at(), changed_at);
+        assert_eq!(loaded.get_accessed_at().await?, Some(accessed_at));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_metadata_attributes() -> anyhow::Result<()> {
         let store = MemoryStore::default();
@@ -528,4 +855,73 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_metadata_permissions_and_ownership() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut metadata = Metadata::new(EntityType::File, store.clone());
+
+        assert_eq!(metadata.get_mode().await?, None);
+        assert_eq!(metadata.get_uid().await?, None);
+        assert_eq!(metadata.get_gid().await?, None);
+
+        // File type bits are dropped, special bits are kept
+        metadata.set_permissions(0o104755).await?;
+        metadata.chown(Some(1000), Some(1000)).await?;
+        metadata.chown(None, Some(100)).await?;
+
+        let cid = metadata.store().await?;
+        let loaded = Metadata::load(&cid, store).await?;
+        assert_eq!(loaded.get_mode().await?, Some(0o4755));
+        assert_eq!(loaded.get_uid().await?, Some(1000));
+        assert_eq!(loaded.get_gid().await?, Some(100));
+
+        // Modes stored as strings by older versions are still read
+        metadata.set_attribute(UNIX_MODE_KEY, "420").await?;
+        assert_eq!(metadata.get_mode().await?, Some(0o644));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_metadata_xattrs() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut metadata = Metadata::new(EntityType::File, store.clone());
+        metadata.set_attribute(UNIX_MODE_KEY, 0o644).await?;
+
+        assert!(metadata.list_xattrs().await?.is_empty());
+
+        metadata
+            .set_xattr("user.comment", b"draft".to_vec())
+            .await?;
+        metadata
+            .set_xattr("security.selinux", b"system_u:object_r:tmp_t:s0".to_vec())
+            .await?;
+        metadata
+            .set_xattr("user.comment", b"final".to_vec())
+            .await?;
+
+        // Xattrs survive a round trip through the store
+        let cid = metadata.store().await?;
+        let mut loaded = Metadata::load(&cid, store).await?;
+        assert_eq!(
+            loaded.list_xattrs().await?,
+            vec!["security.selinux", "user.comment"]
+        );
+        assert_eq!(
+            loaded.get_xattr("user.comment").await?,
+            Some(b"final".to_vec())
+        );
+
+        // Xattrs and the attributes monofs uses do not overlap
+        assert_eq!(loaded.get_xattr(UNIX_MODE_KEY).await?, None);
+        assert!(loaded.get_attribute(UNIX_MODE_KEY).await?.is_some());
+
+        assert!(loaded.remove_xattr("user.comment").await?);
+        assert!(!loaded.remove_xattr("user.comment").await?);
+        assert_eq!(loaded.get_xattr("user.comment").await?, None);
+        assert_eq!(loaded.list_xattrs().await?, vec!["security.selinux"]);
+
+        Ok(())
+    }
 }
@@ -1,11 +1,14 @@
 //! Filesystem implementation.
 
 mod cidlink;
+mod diff;
 mod dir;
 mod entity;
 mod eq;
+mod facade;
 mod file;
 mod kind;
+mod merge;
 mod metadata;
 mod symcidlink;
 mod sympathlink;
@@ -15,11 +18,14 @@ mod sympathlink;
 //--------------------------------------------------------------------------------------------------
 
 pub use cidlink::*;
+pub use diff::*;
 pub use dir::*;
 pub use entity::*;
 pub use eq::*;
+pub use facade::*;
 pub use file::*;
 pub use kind::*;
+pub use merge::*;
 pub use metadata::*;
 pub use symcidlink::*;
 pub use sympathlink::*;
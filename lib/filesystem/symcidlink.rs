@@ -262,7 +262,7 @@ where
     pub fn set_link(&mut self, link: EntityCidLink<S>) {
         let inner = Arc::make_mut(&mut self.inner);
         inner.link = link;
-        inner.metadata.set_modified_at(Utc::now());
+        inner.metadata.mark_modified(Utc::now());
     }
 
     /// Sets the CID of the target of the symlink.
@@ -397,19 +397,30 @@ where
             return Ok(CidFollowResult::MaxDepthReached);
         }
 
+        match self.follow_once().await {
+            Ok(Entity::SymCidLink(next_symlink)) => {
+                next_symlink.follow_recursive(remaining_depth - 1).await
+            }
+            Ok(entity) => Ok(CidFollowResult::Resolved(entity)),
+            Err(FsError::BrokenSymCidLink(cid)) => Ok(CidFollowResult::BrokenLink(cid)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Gets the [`Entity`] that the symlink points to, which may be another symlink, failing with
+    /// [`FsError::BrokenSymCidLink`] if it is not in the store.
+    pub(crate) async fn follow_once(&self) -> FsResult<&Entity<S>>
+    where
+        S: Send + Sync,
+    {
         match self.get_entity().await {
-            Ok(entity) => match entity {
-                Entity::SymCidLink(next_symlink) => {
-                    next_symlink.follow_recursive(remaining_depth - 1).await
-                }
-                _ => Ok(CidFollowResult::Resolved(entity)),
-            },
+            Ok(entity) => Ok(entity),
             Err(FsError::IpldStore(StoreError::BlockNotFound(cid))) => {
-                return Ok(CidFollowResult::BrokenLink(cid));
+                Err(FsError::BrokenSymCidLink(cid))
             }
             Err(FsError::IpldStore(StoreError::Custom(any_err))) => {
                 if let Some(FsError::UnableToLoadEntity(cid)) = any_err.downcast::<FsError>() {
-                    return Ok(CidFollowResult::BrokenLink(*cid));
+                    return Err(FsError::BrokenSymCidLink(*cid));
                 }
                 Err(StoreError::custom(any_err).into())
             }
@@ -467,6 +478,57 @@ where
         }
     }
 
+    /// Resolves the symlink to its target entity, returning a mutable reference.
+    ///
+    /// The chain of symlinks is followed like [`resolve`][Self::resolve], and fails the same way
+    /// without changing any of the symlinks in it. Since a CID always refers to the same entity,
+    /// changes made through the returned reference do not affect the entity at the original CID.
+    /// They are kept in the symlinks instead, which point to the changed entity once they are
+    /// stored again.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use monofs::filesystem::{SymCidLink, Dir, Entity};
+    /// use ipldstore::{MemoryStore, Storable};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let store = MemoryStore::default();
+    /// let dir_cid = Dir::new(store.clone()).store().await?;
+    /// let mut symlink = SymCidLink::with_cid(store.clone(), dir_cid);
+    ///
+    /// if let Entity::Dir(dir) = symlink.resolve_mut().await? {
+    ///     dir.find_or_create("file.txt", true).await?;
+    /// }
+    ///
+    /// // The symlink now points to the changed directory
+    /// assert_ne!(symlink.get_cid().await?, dir_cid);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn resolve_mut(&mut self) -> FsResult<&mut Entity<S>>
+    where
+        S: Send + Sync,
+    {
+        // Check the chain first so that a broken or too deep chain is left as it is
+        self.resolve().await?;
+        self.resolve_mut_recursive().await
+    }
+
+    #[async_recursion]
+    async fn resolve_mut_recursive(&mut self) -> FsResult<&'life_self mut Entity<S>>
+    where
+        S: Send + Sync,
+    {
+        let store = self.inner.store.clone();
+        let inner = Arc::make_mut(&mut self.inner);
+        match inner.link.resolve_entity_mut(store).await? {
+            Entity::SymCidLink(next_symlink) => next_symlink.resolve_mut_recursive().await,
+            entity => Ok(entity),
+        }
+    }
+
     /// Tries to create a new `SymCidLink` from a serializable representation.
     pub fn from_serializable(
         serializable: SymCidLinkSerializable,
@@ -179,7 +179,7 @@ where
     pub fn set_target_path(&mut self, target_path: impl AsRef<str>) {
         let inner = Arc::make_mut(&mut self.inner);
         inner.target_path = Utf8UnixPathBuf::from(target_path.as_ref());
-        inner.metadata.set_modified_at(Utc::now());
+        inner.metadata.mark_modified(Utc::now());
     }
 
     /// Tries to create a new `SymPathLink` from a serializable representation.
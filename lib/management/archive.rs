@@ -0,0 +1,686 @@
+use std::{
+    convert::Infallible,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use chrono::{TimeZone, Utc};
+use futures::StreamExt;
+use ipldstore::{ipld::cid::Cid, IpldStore, IpldStoreSeekable, Storable};
+use tokio::{
+    fs,
+    io::{self, AsyncRead, AsyncWrite},
+};
+use tokio_tar::{Archive, Builder, EntryType, Header};
+
+use crate::{
+    filesystem::{Dir, Entity, File, Metadata, SymPathLink},
+    management::{find, head, Job, JobKind, JobProgress, MfsPaths},
+    server::{DEFAULT_DIR_MODE, DEFAULT_FILE_MODE, DEFAULT_SYMLINK_MODE},
+    FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The directory tree to export as a tar archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportSource {
+    /// The directory stored at a CID, such as the root of a snapshot.
+    Cid(Cid),
+
+    /// A directory in the current root, relative to it. An empty path is the root itself.
+    Path(String),
+}
+
+/// The attributes of an archive entry, applied once every entry has been written.
+struct EntryAttributes {
+    /// The path of the entity relative to the root.
+    path: String,
+
+    /// The permission bits of the entity.
+    mode: u32,
+
+    /// The owner of the entity.
+    uid: u32,
+
+    /// The group of the entity.
+    gid: u32,
+
+    /// The modification time of the entity in seconds since the epoch.
+    mtime: i64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Import a tar archive into a monofs filesystem
+///
+/// The archive is streamed straight into monofs entities and blocks without going through the
+/// mount, which makes it the fast way to bulk-load content such as container root filesystems.
+/// Directories, regular files, symbolic links and hard links are imported with their modes,
+/// owners and modification times. Hard links become copies of their target since monofs does not
+/// support hard links, and other entry types like devices and FIFOs are skipped.
+///
+/// Entries are merged into the current root, replacing files at the same paths. If the filesystem
+/// is mounted, the new root is swapped into the running server. Changes made through the mount or
+/// by other writers while the import runs are merged with the imported entries rather than lost.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `tar_path` - Path to the tar archive to import
+///
+/// ## Returns
+/// The CID of the new root directory
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let root = management::import_tar(Some("mfstest".into()), "rootfs.tar").await?;
+/// println!("imported into {}", root);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn import_tar(mount_dir: Option<PathBuf>, tar_path: impl AsRef<Path>) -> FsResult<Cid> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let job = Job::start(
+        paths.fs_db_path(),
+        paths.get_mount_dir(),
+        JobKind::Import,
+        None,
+    )
+    .await?;
+    let result = import_tar_file(&paths, tar_path.as_ref(), job.get_progress()).await;
+    job.finish(result).await
+}
+
+/// Export a directory tree of a monofs filesystem as a tar archive
+///
+/// The archive is streamed straight from the block store, so the filesystem does not need to be
+/// mounted. Directories, files and symbolic path links are written with their modes, owners and
+/// modification times, with paths relative to the exported directory. Symbolic CID links have no
+/// tar equivalent and are skipped.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `source` - The directory to export
+/// * `writer` - Where to write the archive to
+///
+/// ## Returns
+/// The number of entries written to the archive
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management::{self, ExportSource};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let file = tokio::fs::File::create("results.tar").await?;
+/// let source = ExportSource::Path("sandbox/results".into());
+/// management::export_tar(Some("mfstest".into()), source, file).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn export_tar(
+    mount_dir: Option<PathBuf>,
+    source: ExportSource,
+    writer: impl AsyncWrite + Unpin + Send + Sync,
+) -> FsResult<u64> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let job = Job::start(
+        paths.fs_db_path(),
+        paths.get_mount_dir(),
+        JobKind::Export,
+        None,
+    )
+    .await?;
+    let result = export_tree(&paths, source, writer, job.get_progress()).await;
+    job.finish(result).await
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Imports the tar archive at `tar_path` into the filesystem at `paths`, reporting the entries
+/// imported to `progress`.
+async fn import_tar_file(
+    paths: &MfsPaths,
+    tar_path: &Path,
+    progress: &JobProgress,
+) -> FsResult<Cid> {
+    let store = paths.open_store().await?;
+
+    let head = head::checkpoint_head(paths).await?;
+    let mut root = match &head {
+        Some(cid) => Dir::load(cid, store.clone()).await?,
+        None => Dir::new(store.clone()),
+    };
+
+    let archive = fs::File::open(tar_path).await?;
+    let count = import_entries(&mut root, archive, progress).await?;
+
+    let cid = root.checkpoint().await?;
+    let operation = format!("import {}", tar_path.display());
+    head::set_head(paths, head.as_ref(), &cid, &operation).await?;
+    tracing::info!(
+        "imported {} entries from {} into {}",
+        count,
+        tar_path.display(),
+        cid
+    );
+
+    Ok(cid)
+}
+
+/// Exports the directory tree `source` of the filesystem at `paths` as a tar archive written to
+/// `writer`, reporting the entries written to `progress`.
+async fn export_tree(
+    paths: &MfsPaths,
+    source: ExportSource,
+    writer: impl AsyncWrite + Unpin + Send + Sync,
+    progress: &JobProgress,
+) -> FsResult<u64> {
+    let store = paths.open_store().await?;
+
+    let dir = match source {
+        ExportSource::Cid(cid) => Dir::load(&cid, store).await?,
+        ExportSource::Path(path) => {
+            let head = head::checkpoint_head(paths).await?.ok_or_else(|| {
+                FsError::InvalidOperation(format!(
+                    "filesystem at {} has no recorded state to export",
+                    paths.get_mount_dir().display()
+                ))
+            })?;
+
+            let root = Dir::load(&head, store).await?;
+            let path = path.trim_matches('/');
+            if path.is_empty() {
+                root
+            } else {
+                match root.find(path).await? {
+                    Some(Entity::Dir(dir)) => dir.clone(),
+                    Some(_) => return Err(FsError::NotADirectory(path.to_string())),
+                    None => return Err(FsError::PathNotFound(path.to_string())),
+                }
+            }
+        }
+    };
+
+    let mut builder = Builder::new(writer);
+    let count = export_entries(&dir, &mut builder, progress).await?;
+    builder.into_inner().await?;
+    tracing::info!("exported {} entries", count);
+
+    Ok(count)
+}
+
+/// Writes the entries of the tar archive read from `reader` into `root`.
+///
+/// Returns the number of entries imported.
+async fn import_entries<S>(
+    root: &mut Dir<S>,
+    reader: impl AsyncRead + Unpin + Send + Sync,
+    progress: &JobProgress,
+) -> FsResult<u64>
+where
+    S: IpldStore + Send + Sync,
+{
+    import_entries_with(root, reader, progress, |_| false).await
+}
+
+/// Writes the entries of the tar archive read from `reader` into `root`, except the entries with
+/// normalized paths `skip` returns `true` for.
+///
+/// Returns the number of entries imported.
+pub(super) async fn import_entries_with<S>(
+    root: &mut Dir<S>,
+    reader: impl AsyncRead + Unpin + Send + Sync,
+    progress: &JobProgress,
+    skip: impl Fn(&str) -> bool + Send,
+) -> FsResult<u64>
+where
+    S: IpldStore + Send + Sync,
+{
+    let mut archive = Archive::new(reader);
+    let mut entries = archive.entries()?;
+    let mut attributes = Vec::new();
+
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry?;
+        let path = normalize_entry_path(&entry.path()?)?;
+        let entry_type = entry.header().entry_type();
+
+        // The root itself has no name to put it under
+        if path.is_empty() || skip(&path) {
+            continue;
+        }
+
+        match entry_type {
+            EntryType::Directory => {
+                get_or_create_dir(root, &path).await?;
+            }
+            EntryType::Regular | EntryType::Continuous => {
+                let file = File::with_content(root.get_store().clone(), &mut entry).await?;
+                let (parent, name) = split_entry_path(&path);
+                get_or_create_dir(root, parent)
+                    .await?
+                    .put_adapted_file(name, file)
+                    .await?;
+            }
+            EntryType::Symlink => {
+                let target = get_link_name(&entry.link_name()?, &path)?;
+                let symlink = SymPathLink::with_path(root.get_store().clone(), target)?;
+                let (parent, name) = split_entry_path(&path);
+                get_or_create_dir(root, parent)
+                    .await?
+                    .put_adapted_entity(name, Entity::SymPathLink(symlink))
+                    .await?;
+            }
+            EntryType::Link => {
+                let target = get_link_name(&entry.link_name()?, &path)?;
+                let target = normalize_entry_path(Path::new(&target))?;
+                let entity = root
+                    .find(&target)
+                    .await?
+                    .cloned()
+                    .ok_or_else(|| FsError::PathNotFound(target.clone()))?;
+                let (parent, name) = split_entry_path(&path);
+                get_or_create_dir(root, parent)
+                    .await?
+                    .put_adapted_entity(name, entity)
+                    .await?;
+            }
+            entry_type => {
+                tracing::warn!("skipping {} with unsupported type {:?}", path, entry_type);
+                continue;
+            }
+        }
+
+        attributes.push(get_entry_attributes(path, entry.header())?);
+        progress.add_done(1);
+    }
+
+    // Adding entries touches the modification times of their parents, so restore the recorded
+    // attributes once everything is in place
+    let count = attributes.len() as u64;
+    for attrs in attributes {
+        if let Some(entity) = root.find_mut(&attrs.path).await? {
+            apply_entry_attributes(entity.get_metadata_mut(), &attrs).await?;
+        }
+    }
+
+    Ok(count)
+}
+
+/// Returns the directory at `path` in `root`, creating it and its parents if needed.
+pub(super) async fn get_or_create_dir<'a, S>(
+    root: &'a mut Dir<S>,
+    path: &str,
+) -> FsResult<&'a mut Dir<S>>
+where
+    S: IpldStore + Send + Sync,
+{
+    if path.is_empty() {
+        return Ok(root);
+    }
+
+    match root.find_or_create(path, false).await? {
+        Entity::Dir(dir) => Ok(dir),
+        _ => Err(FsError::NotADirectory(path.to_string())),
+    }
+}
+
+/// Turns the path of an archive entry into a path relative to the root.
+///
+/// Leading `/` and `.` components are dropped. Paths that escape the root are rejected.
+pub(super) fn normalize_entry_path(path: &Path) -> FsResult<String> {
+    let mut segments = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(segment) => segments.push(
+                segment
+                    .to_str()
+                    .ok_or_else(|| FsError::InvalidPathComponent(path.display().to_string()))?,
+            ),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => {
+                return Err(FsError::InvalidPathComponent(path.display().to_string()))
+            }
+        }
+    }
+
+    Ok(segments.join("/"))
+}
+
+/// Splits a normalized entry path into its parent path and name.
+pub(super) fn split_entry_path(path: &str) -> (&str, &str) {
+    path.rsplit_once('/').unwrap_or(("", path))
+}
+
+/// Returns the target of a link entry at `path`.
+fn get_link_name(link_name: &Option<std::borrow::Cow<'_, Path>>, path: &str) -> FsResult<String> {
+    link_name
+        .as_ref()
+        .and_then(|target| target.to_str())
+        .map(str::to_string)
+        .ok_or_else(|| FsError::InvalidPathComponent(path.to_string()))
+}
+
+/// Reads the attributes to preserve from the header of the entry at `path`.
+fn get_entry_attributes(path: String, header: &Header) -> FsResult<EntryAttributes> {
+    Ok(EntryAttributes {
+        path,
+        mode: header.mode()? & 0o7777,
+        uid: header.uid()? as u32,
+        gid: header.gid()? as u32,
+        mtime: header.mtime()? as i64,
+    })
+}
+
+/// Sets the preserved attributes of an archive entry on `metadata`.
+async fn apply_entry_attributes<S>(
+    metadata: &mut Metadata<S>,
+    attrs: &EntryAttributes,
+) -> FsResult<()>
+where
+    S: IpldStore + Send + Sync,
+{
+    metadata.set_permissions(attrs.mode).await?;
+    metadata.chown(Some(attrs.uid), Some(attrs.gid)).await?;
+    if let Some(mtime) = Utc.timestamp_opt(attrs.mtime, 0).single() {
+        metadata.set_modified_at(mtime);
+    }
+
+    Ok(())
+}
+
+/// Writes the entities below `dir` to `builder` as tar entries.
+///
+/// Returns the number of entries written.
+async fn export_entries<S, W>(
+    dir: &Dir<S>,
+    builder: &mut Builder<W>,
+    progress: &JobProgress,
+) -> FsResult<u64>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+    W: AsyncWrite + Unpin + Send + Sync,
+{
+    export_entries_under(dir, "", builder, progress).await
+}
+
+/// Writes the entities below `dir` to `builder` as tar entries, with their paths under `prefix`.
+///
+/// Returns the number of entries written.
+pub(super) async fn export_entries_under<S, W>(
+    dir: &Dir<S>,
+    prefix: &str,
+    builder: &mut Builder<W>,
+    progress: &JobProgress,
+) -> FsResult<u64>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+    W: AsyncWrite + Unpin + Send + Sync,
+{
+    let mut pending = Vec::new();
+    push_dir_entries(dir, prefix, &mut pending).await?;
+
+    let mut count = 0;
+    while let Some((path, entity)) = pending.pop() {
+        if !append_entity(builder, &path, &entity).await? {
+            continue;
+        }
+
+        if let Entity::Dir(dir) = &entity {
+            push_dir_entries(dir, &path, &mut pending).await?;
+        }
+
+        count += 1;
+        progress.add_done(1);
+    }
+
+    Ok(count)
+}
+
+/// Writes `entity` to `builder` as a tar entry at `path`, without the entities below it.
+///
+/// Returns `false` if the entity has no tar equivalent and was skipped.
+pub(super) async fn append_entity<S, W>(
+    builder: &mut Builder<W>,
+    path: &str,
+    entity: &Entity<S>,
+) -> FsResult<bool>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+    W: AsyncWrite + Unpin + Send + Sync,
+{
+    let metadata = entity.get_metadata();
+    let mut header = Header::new_gnu();
+    header.set_mtime(metadata.get_modified_at().timestamp().max(0) as u64);
+    header.set_uid(metadata.get_uid().await?.unwrap_or(0) as u64);
+    header.set_gid(metadata.get_gid().await?.unwrap_or(0) as u64);
+    let mode = metadata.get_mode().await?;
+
+    match entity {
+        Entity::Dir(_) => {
+            header.set_entry_type(EntryType::Directory);
+            header.set_mode(mode.unwrap_or(DEFAULT_DIR_MODE));
+            header.set_size(0);
+            builder
+                .append_data(&mut header, format!("{}/", path), io::empty())
+                .await?;
+        }
+        Entity::File(file) => {
+            header.set_entry_type(EntryType::Regular);
+            header.set_mode(mode.unwrap_or(DEFAULT_FILE_MODE));
+            header.set_size(file.get_size().await?);
+            builder
+                .append_data(&mut header, path, file.get_input_stream().await?)
+                .await?;
+        }
+        Entity::SymPathLink(link) => {
+            header.set_entry_type(EntryType::Symlink);
+            header.set_mode(mode.unwrap_or(DEFAULT_SYMLINK_MODE));
+            header.set_size(0);
+            builder
+                .append_link(&mut header, path, link.get_target_path().as_str())
+                .await?;
+        }
+        Entity::SymCidLink(_) => {
+            tracing::warn!("skipping symbolic CID link {}", path);
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Pushes the entities in `dir` onto `pending` so they are popped in name order.
+async fn push_dir_entries<S>(
+    dir: &Dir<S>,
+    prefix: &str,
+    pending: &mut Vec<(String, Entity<S>)>,
+) -> FsResult<()>
+where
+    S: IpldStore + Send + Sync,
+{
+    let entries = dir.get_entries().collect::<Vec<_>>();
+    for (name, link) in entries.into_iter().rev() {
+        let entity = link.resolve_entity(dir.get_store().clone()).await?.clone();
+        let path = if prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+        pending.push((path, entity));
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl FromStr for ExportSource {
+    type Err = Infallible;
+
+    /// Parses a CID, or otherwise a path in the current root.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match Cid::try_from(s) {
+            Ok(cid) => Self::Cid(cid),
+            Err(_) => Self::Path(s.to_string()),
+        })
+    }
+}
+
+impl From<Cid> for ExportSource {
+    fn from(cid: Cid) -> Self {
+        Self::Cid(cid)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::{ipld::ipld::Ipld, MemoryStore};
+    use tokio::io::AsyncReadExt;
+
+    use crate::filesystem::UNIX_MODE_KEY;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_import_entries_preserves_attributes() -> anyhow::Result<()> {
+        let content = b"hello world";
+        let archive = build_test_archive(content).await?;
+
+        let mut root = Dir::new(MemoryStore::default());
+        let count = import_entries(&mut root, archive.as_slice(), &JobProgress::default()).await?;
+        assert_eq!(count, 3);
+
+        let Some(Entity::Dir(etc)) = root.find("etc").await? else {
+            panic!("etc is not a directory");
+        };
+        assert_eq!(etc.get_metadata().get_modified_at().timestamp(), 1_000_000);
+        assert_eq!(
+            etc.get_metadata()
+                .get_attribute(UNIX_MODE_KEY)
+                .await?
+                .as_deref(),
+            Some(&Ipld::Integer(0o750))
+        );
+
+        let Some(Entity::File(hosts)) = root.find("etc/hosts").await? else {
+            panic!("etc/hosts is not a file");
+        };
+        assert_eq!(
+            hosts.get_metadata().get_modified_at().timestamp(),
+            2_000_000
+        );
+
+        let mut buf = Vec::new();
+        hosts
+            .get_input_stream()
+            .await?
+            .read_to_end(&mut buf)
+            .await?;
+        assert_eq!(buf, content);
+
+        let Some(Entity::SymPathLink(link)) = root.find("etc/hosts.link").await? else {
+            panic!("etc/hosts.link is not a symlink");
+        };
+        assert_eq!(link.get_target_path().as_str(), "hosts");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_export_entries_round_trip() -> anyhow::Result<()> {
+        let content = b"hello world";
+        let archive = build_test_archive(content).await?;
+        let mut root = Dir::new(MemoryStore::default());
+        import_entries(&mut root, archive.as_slice(), &JobProgress::default()).await?;
+
+        let mut builder = Builder::new(Vec::new());
+        let count = export_entries(&root, &mut builder, &JobProgress::default()).await?;
+        assert_eq!(count, 3);
+        let exported = builder.into_inner().await?;
+
+        let mut imported = Dir::new(MemoryStore::default());
+        import_entries(&mut imported, exported.as_slice(), &JobProgress::default()).await?;
+
+        let Some(Entity::File(hosts)) = imported.find("etc/hosts").await? else {
+            panic!("etc/hosts is not a file");
+        };
+        assert_eq!(
+            hosts.get_metadata().get_modified_at().timestamp(),
+            2_000_000
+        );
+        assert_eq!(hosts.get_metadata().get_mode().await?, Some(0o640));
+
+        let mut buf = Vec::new();
+        hosts
+            .get_input_stream()
+            .await?
+            .read_to_end(&mut buf)
+            .await?;
+        assert_eq!(buf, content);
+
+        assert!(matches!(
+            imported.find("etc/hosts.link").await?,
+            Some(Entity::SymPathLink(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalize_entry_path() -> anyhow::Result<()> {
+        assert_eq!(normalize_entry_path(Path::new("./etc/hosts"))?, "etc/hosts");
+        assert_eq!(normalize_entry_path(Path::new("/usr/bin/"))?, "usr/bin");
+        assert_eq!(normalize_entry_path(Path::new("."))?, "");
+        assert!(normalize_entry_path(Path::new("../etc/passwd")).is_err());
+
+        Ok(())
+    }
+
+    /// Builds an archive with a directory, a file and a symlink in it.
+    async fn build_test_archive(content: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut builder = Builder::new(Vec::new());
+
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Directory);
+        header.set_mode(0o750);
+        header.set_mtime(1_000_000);
+        header.set_size(0);
+        builder
+            .append_data(&mut header, "etc/", tokio::io::empty())
+            .await?;
+
+        let mut header = Header::new_gnu();
+        header.set_mode(0o640);
+        header.set_mtime(2_000_000);
+        header.set_size(content.len() as u64);
+        builder
+            .append_data(&mut header, "./etc/hosts", content)
+            .await?;
+
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Symlink);
+        header.set_mode(0o777);
+        header.set_size(0);
+        builder
+            .append_link(&mut header, "etc/hosts.link", "hosts")
+            .await?;
+
+        Ok(builder.into_inner().await?)
+    }
+}
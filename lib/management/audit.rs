@@ -0,0 +1,391 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chrono::{DateTime, Utc};
+use getset::Getters;
+use ipldstore::ipld::cid::Cid;
+use serde::Serialize;
+use sqlx::{sqlite::SqliteRow, Pool, Row, Sqlite};
+
+use crate::{
+    management::{db, find},
+    utils, FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// What a change recorded in the audit log of a filesystem did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOperation {
+    /// An entity was created.
+    Create,
+
+    /// The content of a file was written, or its size changed.
+    Write,
+
+    /// An entity was moved to a new path.
+    Rename,
+
+    /// An entity was removed.
+    Remove,
+}
+
+/// A change the server of a filesystem applied, as it is recorded in the audit log.
+///
+/// Paths are absolute within the filesystem, like those of
+/// [`ChangeEvent`](crate::server::ChangeEvent)s.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct AuditEvent {
+    /// When the change was applied.
+    occurred_at: DateTime<Utc>,
+
+    /// What the change did.
+    operation: AuditOperation,
+
+    /// The path of the entity changed, or the path it was moved from.
+    path: String,
+
+    /// The path the entity was moved to, for renames.
+    new_path: Option<String>,
+
+    /// The user the change was made as, if the mount passes it on.
+    uid: Option<u32>,
+
+    /// The group the change was made as, if the mount passes it on.
+    gid: Option<u32>,
+
+    /// The CID of the content of the file written. Changes that did not store anything yet, like
+    /// creating an entity or a write still buffered, have no CID.
+    #[serde(serialize_with = "utils::serialize_optional_display")]
+    cid: Option<Cid>,
+}
+
+/// A change in the audit log of a filesystem.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct AuditEntry {
+    /// The sequence number of the entry. Sequence numbers increase with every entry.
+    sequence: i64,
+
+    /// The change.
+    #[serde(flatten)]
+    event: AuditEvent,
+}
+
+/// The audit log of a filesystem, in its fs database.
+///
+/// Auditing is enabled when the filesystem is initialized. The server of an audited filesystem
+/// then records every entity created, written, renamed and removed in the log, which only ever
+/// grows: the database refuses to change or delete entries.
+#[derive(Debug, Clone)]
+pub struct FsAuditLog {
+    /// The filesystem database.
+    fs_db: Pool<Sqlite>,
+
+    /// The mount directory identifying the filesystem.
+    mount_dir: PathBuf,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl AuditEvent {
+    /// Creates an event for a change applied now to the entity at `path`, which is relative to
+    /// the root directory.
+    pub fn new(operation: AuditOperation, path: &str, cid: Option<&Cid>) -> Self {
+        Self {
+            occurred_at: Utc::now(),
+            operation,
+            path: get_absolute_path(path),
+            new_path: None,
+            uid: None,
+            gid: None,
+            cid: cid.cloned(),
+        }
+    }
+
+    /// Records the entity as moved to `new_path`, which is relative to the root directory.
+    pub fn with_new_path(mut self, new_path: &str) -> Self {
+        self.new_path = Some(get_absolute_path(new_path));
+        self
+    }
+
+    /// Records the change as made by the user `uid` and the group `gid`.
+    pub fn with_caller(mut self, uid: u32, gid: u32) -> Self {
+        self.uid = Some(uid);
+        self.gid = Some(gid);
+        self
+    }
+
+    /// Returns whether the change touched `path_prefix` or anything below it, at either of its
+    /// paths.
+    pub fn is_under(&self, path_prefix: &str) -> bool {
+        let prefix = get_absolute_path(path_prefix);
+        let under = |path: &str| {
+            prefix == "/"
+                || path
+                    .strip_prefix(&prefix)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        };
+
+        under(&self.path) || self.new_path.as_deref().is_some_and(under)
+    }
+}
+
+impl FsAuditLog {
+    /// Opens the audit log of the filesystem mounted at `mount_dir`.
+    pub async fn new(
+        fs_db_path: impl AsRef<Path>,
+        mount_dir: impl Into<PathBuf>,
+    ) -> FsResult<Self> {
+        Ok(Self {
+            fs_db: db::get_db_pool(fs_db_path.as_ref()).await?,
+            mount_dir: mount_dir.into(),
+        })
+    }
+
+    /// Returns whether the server of the filesystem records the changes it applies.
+    pub async fn is_enabled(&self) -> FsResult<bool> {
+        let record = sqlx::query("SELECT id FROM audited_filesystems WHERE mount_dir = ?")
+            .bind(self.mount_dir.to_string_lossy().to_string())
+            .fetch_optional(&self.fs_db)
+            .await?;
+
+        Ok(record.is_some())
+    }
+
+    /// Has the server of the filesystem record the changes it applies from now on.
+    pub async fn enable(&self) -> FsResult<()> {
+        sqlx::query("INSERT OR IGNORE INTO audited_filesystems (mount_dir) VALUES (?)")
+            .bind(self.mount_dir.to_string_lossy().to_string())
+            .execute(&self.fs_db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Appends `events` to the log, in order.
+    pub async fn record(&self, events: &[AuditEvent]) -> FsResult<()> {
+        let mount_dir = self.mount_dir.to_string_lossy().to_string();
+        let mut tx = self.fs_db.begin().await?;
+        for event in events {
+            sqlx::query(
+                r#"
+                INSERT INTO audit_log
+                    (mount_dir, operation, path, new_path, uid, gid, cid, occurred_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&mount_dir)
+            .bind(event.operation.to_string())
+            .bind(&event.path)
+            .bind(&event.new_path)
+            .bind(event.uid)
+            .bind(event.gid)
+            .bind(event.cid.map(|cid| cid.to_string()))
+            .bind(event.occurred_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Returns the entries of the log that occurred at or after `since`, if given, oldest first.
+    pub async fn get_entries(&self, since: Option<DateTime<Utc>>) -> FsResult<Vec<AuditEntry>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, operation, path, new_path, uid, gid, cid, occurred_at
+            FROM audit_log
+            WHERE mount_dir = ? AND (? IS NULL OR occurred_at >= ?)
+            ORDER BY id
+            "#,
+        )
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .bind(since)
+        .bind(since)
+        .fetch_all(&self.fs_db)
+        .await?;
+
+        rows.iter().map(audit_entry_from_row).collect()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// List the changes recorded in the audit log of a monofs filesystem, oldest first
+///
+/// Filesystems initialized with auditing enabled record every entity created, written, renamed
+/// and removed through their mount, along with when it happened, the user and group it was done
+/// as, and the CID of the content written. Mounts over FUSE pass on the user and group of every
+/// request. NFS does not pass the credentials of a request on to the filesystem, so changes made
+/// over NFS are recorded without them.
+///
+/// Writes buffered by write-back are recorded when they are acknowledged, without a CID, as their
+/// content is only stored once they are flushed. The server adds what it recorded to the log every few seconds and
+/// when it stops, so the most recent changes of a running filesystem may be missing.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `since` - Only list the changes made at or after this time, if given
+/// * `path` - Only list the changes to this path or anything below it, if given
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// for entry in management::audit_log(None, None, Some("/etc")).await? {
+///     let event = entry.get_event();
+///     println!("{} {} {}", event.get_occurred_at(), event.get_operation(), event.get_path());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn audit_log(
+    mount_dir: Option<PathBuf>,
+    since: Option<DateTime<Utc>>,
+    path: Option<&str>,
+) -> FsResult<Vec<AuditEntry>> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let audit = FsAuditLog::new(paths.fs_db_path(), paths.get_mount_dir()).await?;
+    if !audit.is_enabled().await? {
+        return Err(FsError::AuditNotEnabled(
+            paths.get_mount_dir().display().to_string(),
+        ));
+    }
+
+    let mut entries = audit.get_entries(since).await?;
+    if let Some(path) = path {
+        entries.retain(|entry| entry.event.is_under(path));
+    }
+
+    Ok(entries)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns `path`, relative to the root directory, as an absolute path.
+fn get_absolute_path(path: &str) -> String {
+    format!("/{}", path.trim_matches('/'))
+}
+
+/// Builds an audit log entry from a row of the `audit_log` table.
+fn audit_entry_from_row(row: &SqliteRow) -> FsResult<AuditEntry> {
+    Ok(AuditEntry {
+        sequence: row.get("id"),
+        event: AuditEvent {
+            occurred_at: row.get("occurred_at"),
+            operation: row.get::<String, _>("operation").parse()?,
+            path: row.get("path"),
+            new_path: row.get("new_path"),
+            uid: row.get("uid"),
+            gid: row.get("gid"),
+            cid: row
+                .get::<Option<String>, _>("cid")
+                .map(|cid| Cid::try_from(cid.as_str()))
+                .transpose()?,
+        },
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Display for AuditOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditOperation::Create => write!(f, "create"),
+            AuditOperation::Write => write!(f, "write"),
+            AuditOperation::Rename => write!(f, "rename"),
+            AuditOperation::Remove => write!(f, "remove"),
+        }
+    }
+}
+
+impl FromStr for AuditOperation {
+    type Err = FsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "create" => Ok(AuditOperation::Create),
+            "write" => Ok(AuditOperation::Write),
+            "rename" => Ok(AuditOperation::Rename),
+            "remove" => Ok(AuditOperation::Remove),
+            _ => Err(FsError::InvalidAuditOperation(s.to_string())),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::management::FS_DB_MIGRATOR;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fs_audit_log() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("fs.db");
+        db::init_db(&db_path, &FS_DB_MIGRATOR).await?;
+
+        let audit = FsAuditLog::new(&db_path, temp_dir.path().join("mnt")).await?;
+        assert!(!audit.is_enabled().await?);
+        audit.enable().await?;
+        audit.enable().await?;
+        assert!(audit.is_enabled().await?);
+
+        let cid = "bafkreigh2akiscaildcqabsyg3dfr6chu3fgpregiymsck7e7aqa4s52zy".parse::<Cid>()?;
+        let events = [
+            AuditEvent::new(AuditOperation::Create, "etc/passwd", None).with_caller(1000, 100),
+            AuditEvent::new(AuditOperation::Write, "etc/passwd", Some(&cid)),
+            AuditEvent::new(AuditOperation::Rename, "etc/passwd", None).with_new_path("tmp/x"),
+        ];
+        audit.record(&events).await?;
+
+        let entries = audit.get_entries(None).await?;
+        assert_eq!(
+            entries.iter().map(|entry| &entry.event).collect::<Vec<_>>(),
+            events.iter().collect::<Vec<_>>()
+        );
+        assert!(entries[0].sequence < entries[1].sequence);
+        assert!(events[2].is_under("/tmp") && events[2].is_under("etc/"));
+        assert!(!events[2].is_under("/et"));
+
+        let later = Utc::now() + chrono::Duration::seconds(1);
+        assert!(audit.get_entries(Some(later)).await?.is_empty());
+
+        // The log cannot be rewritten
+        assert!(sqlx::query("DELETE FROM audit_log")
+            .execute(&audit.fs_db)
+            .await
+            .is_err());
+        assert!(sqlx::query("UPDATE audit_log SET path = '/'")
+            .execute(&audit.fs_db)
+            .await
+            .is_err());
+        assert_eq!(audit.get_entries(None).await?.len(), 3);
+
+        Ok(())
+    }
+}
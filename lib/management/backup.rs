@@ -0,0 +1,432 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use getset::Getters;
+use ipldstore::ipld::cid::Cid;
+use serde::Serialize;
+use sqlx::{sqlite::SqliteRow, Pool, Row, Sqlite};
+use tokio::fs;
+
+use crate::{
+    config::{Durability, EncryptionKey},
+    management::{db, find, snapshot, FsHead, Job, JobKind, JobProgress, MfsPaths},
+    store::{DagWalker, FlatFsStore},
+    utils::{
+        self,
+        path::{BLOCKS_SUBDIR, FS_DB_FILENAME},
+    },
+    FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The file describing a backup, written to its directory once everything else is.
+pub const BACKUP_MANIFEST_FILENAME: &str = "backup.json";
+
+/// What the names of the snapshots taken by backups start with.
+const BACKUP_SNAPSHOT_PREFIX: &str = "backup-";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Tracks the backups taken of a filesystem in its fs database.
+#[derive(Debug, Clone)]
+pub struct FsBackups {
+    /// The filesystem database.
+    fs_db: Pool<Sqlite>,
+
+    /// The mount directory identifying the filesystem.
+    mount_dir: PathBuf,
+}
+
+/// A backup of a filesystem written by [`backup_mfs`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct Backup {
+    /// The directory the backup was written to.
+    dest: PathBuf,
+
+    /// The name of the snapshot the backup captures.
+    snapshot: String,
+
+    /// The root captured by the snapshot.
+    #[serde(serialize_with = "utils::serialize_display")]
+    root: Cid,
+
+    /// The number of blocks copied.
+    blocks: u64,
+
+    /// The size of the blocks copied.
+    bytes: u64,
+
+    /// When the backup was finished.
+    created_at: DateTime<Utc>,
+}
+
+/// The contents of the manifest of a backup.
+#[derive(Debug, Serialize)]
+struct BackupManifest<'a> {
+    /// The mount directory of the filesystem backed up.
+    mount_dir: &'a Path,
+
+    /// The backup.
+    #[serde(flatten)]
+    backup: &'a Backup,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FsBackups {
+    /// Opens the backup records of the filesystem mounted at `mount_dir`.
+    pub async fn new(
+        fs_db_path: impl AsRef<Path>,
+        mount_dir: impl Into<PathBuf>,
+    ) -> FsResult<Self> {
+        Ok(Self {
+            fs_db: db::get_db_pool(fs_db_path.as_ref()).await?,
+            mount_dir: mount_dir.into(),
+        })
+    }
+
+    /// Returns the backups taken of the filesystem, oldest first.
+    pub async fn list(&self) -> FsResult<Vec<Backup>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT dest, snapshot, root_cid, blocks, bytes, created_at
+            FROM backups
+            WHERE mount_dir = ?
+            ORDER BY created_at, id
+            "#,
+        )
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .fetch_all(&self.fs_db)
+        .await?;
+
+        rows.iter().map(backup_from_row).collect()
+    }
+
+    /// Records a backup of `blocks` blocks, of `bytes` bytes, of the snapshot named `snapshot`
+    /// at `root`, written to `dest`.
+    pub async fn record(
+        &self,
+        dest: &Path,
+        snapshot: &str,
+        root: &Cid,
+        blocks: u64,
+        bytes: u64,
+    ) -> FsResult<Backup> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO backups (mount_dir, dest, snapshot, root_cid, blocks, bytes)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .bind(dest.to_string_lossy().to_string())
+        .bind(snapshot)
+        .bind(root.to_string())
+        .bind(blocks as i64)
+        .bind(bytes as i64)
+        .execute(&self.fs_db)
+        .await?;
+
+        let row = sqlx::query(
+            "SELECT dest, snapshot, root_cid, blocks, bytes, created_at FROM backups WHERE id = ?",
+        )
+        .bind(result.last_insert_rowid())
+        .fetch_one(&self.fs_db)
+        .await?;
+
+        backup_from_row(&row)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Back up a monofs filesystem to a directory while it stays mounted
+///
+/// Copying the data directory of a mounted filesystem can catch its database and blocks halfway
+/// through a write. Instead, this takes a snapshot, which the server checkpoints if the
+/// filesystem is mounted, and which keeps the blocks of its root from being collected while they
+/// are copied. The fs database is then exported with SQLite's `VACUUM INTO`, which gives a
+/// consistent copy even while the server writes to it, and the head of the copy is pointed at
+/// the snapshot. Last, the blocks reachable from every snapshot, branch and pin in the copy are
+/// read from the store and written to the backup, each after the blocks it links to.
+///
+/// The backup directory holds the database as `fs.db`, the blocks as `blocks/`, and a
+/// `backup.json` manifest written once the rest is complete. Blocks are written encrypted with
+/// the encryption key in the environment, if any, like the filesystem's own. The backup is
+/// restored into a new mount with [`restore_mfs`](crate::management::restore_mfs). Every backup
+/// is recorded in the filesystem's database, see [`list_backups`].
+///
+/// A root deleted and collected while its blocks are being copied makes the backup fail, in
+/// which case it can be taken again.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `dest` - The directory to write the backup to, which must be empty or missing
+///
+/// ## Returns
+/// The backup, as recorded
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let backup = management::backup_mfs(Some("mfstest".into()), "/mnt/backup/mfstest").await?;
+/// println!("copied {} blocks of {}", backup.get_blocks(), backup.get_root());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn backup_mfs(mount_dir: Option<PathBuf>, dest: impl AsRef<Path>) -> FsResult<Backup> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let dest = dest.as_ref();
+    fs::create_dir_all(dest).await?;
+    if fs::read_dir(dest).await?.next_entry().await?.is_some() {
+        return Err(FsError::BackupDestinationNotEmpty(
+            dest.to_string_lossy().to_string(),
+        ));
+    }
+    let dest = fs::canonicalize(dest).await?;
+
+    let job = Job::start(
+        paths.fs_db_path(),
+        paths.get_mount_dir(),
+        JobKind::Backup,
+        None,
+    )
+    .await?;
+    let result = write_backup(&paths, &dest, job.get_progress()).await;
+    job.finish(result).await
+}
+
+/// List the backups taken of a monofs filesystem with [`backup_mfs`], oldest first
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// for backup in management::list_backups(None).await? {
+///     println!("{} {}", backup.get_dest().display(), backup.get_root());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn list_backups(mount_dir: Option<PathBuf>) -> FsResult<Vec<Backup>> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    FsBackups::new(paths.fs_db_path(), paths.get_mount_dir())
+        .await?
+        .list()
+        .await
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Writes a backup of the filesystem at `paths` to the empty directory `dest`, reporting the
+/// blocks copied to `progress`.
+async fn write_backup(paths: &MfsPaths, dest: &Path, progress: &JobProgress) -> FsResult<Backup> {
+    // The snapshot keeps the blocks of its root from being collected while they are copied
+    let name = format!(
+        "{}{}",
+        BACKUP_SNAPSHOT_PREFIX,
+        Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+    );
+    let snapshot = snapshot::snapshot_mfs(Some(paths.get_mount_dir().clone()), &name).await?;
+    let root = *snapshot.get_root();
+
+    let db_path = dest.join(FS_DB_FILENAME);
+    export_db(paths, &db_path, &root).await?;
+
+    // Copy the blocks of every root the exported database refers to
+    let roots = get_recorded_roots(&db_path).await?;
+    let store = paths.open_store().await?;
+    let backup_store = open_backup_store(dest)?;
+    let (blocks, bytes) = copy_blocks(&store, &backup_store, roots, progress).await?;
+
+    let backup = FsBackups::new(paths.fs_db_path(), paths.get_mount_dir())
+        .await?
+        .record(dest, &name, &root, blocks, bytes)
+        .await?;
+    let manifest = BackupManifest {
+        mount_dir: paths.get_mount_dir(),
+        backup: &backup,
+    };
+    let manifest_path = dest.join(BACKUP_MANIFEST_FILENAME);
+    fs::write(
+        &manifest_path,
+        serde_json::to_vec_pretty(&manifest).map_err(FsError::custom)?,
+    )
+    .await?;
+    fs::File::open(&manifest_path).await?.sync_all().await?;
+    tracing::info!(
+        "backed up {} blocks ({} bytes) of {} to {}",
+        blocks,
+        bytes,
+        root,
+        dest.display()
+    );
+
+    Ok(backup)
+}
+
+/// Exports the fs database of the filesystem at `paths` to `db_path`, with `root` as the head of
+/// the copy.
+///
+/// The intents of the server are left out of the copy, as the backup does not hold the roots
+/// they refer to.
+async fn export_db(paths: &MfsPaths, db_path: &Path, root: &Cid) -> FsResult<()> {
+    let pool = db::get_db_pool(paths.fs_db_path()).await?;
+    sqlx::query("VACUUM INTO ?")
+        .bind(db_path.to_string_lossy().to_string())
+        .execute(&pool)
+        .await?;
+
+    let exported = db::get_db_pool(db_path).await?;
+    sqlx::query("DELETE FROM intents")
+        .execute(&exported)
+        .await?;
+    FsHead::new(db_path, paths.get_mount_dir())
+        .await?
+        .set(root, "backup")
+        .await?;
+
+    Ok(())
+}
+
+/// Opens the store of the blocks of the backup in `dir`, which encrypts them with the encryption
+/// key in the environment, if any.
+pub(crate) fn open_backup_store(dir: &Path) -> FsResult<FlatFsStore> {
+    Ok(FlatFsStore::new(dir.join(BLOCKS_SUBDIR))
+        .with_encryption(EncryptionKey::from_env()?)
+        .with_durability(Durability::Block))
+}
+
+/// Copies the blocks reachable from `roots` from `source` to `target`, each after the blocks it
+/// links to, reporting the blocks copied to `progress`.
+///
+/// Returns the number of blocks copied and their size.
+pub(crate) async fn copy_blocks(
+    source: &FlatFsStore,
+    target: &FlatFsStore,
+    roots: Vec<Cid>,
+    progress: &JobProgress,
+) -> FsResult<(u64, u64)> {
+    let dag = DagWalker::new(source).walk(roots.iter().copied()).await?;
+    let order = dag.get_postorder(roots);
+    progress.set_total(order.len() as u64);
+
+    let (mut blocks, mut bytes) = (0, 0);
+    for cid in &order {
+        let data = source.get_block_data(cid).await?;
+        target.put_block_data(cid, &data).await?;
+        blocks += 1;
+        bytes += data.len() as u64;
+        progress.set_done(blocks);
+    }
+
+    Ok((blocks, bytes))
+}
+
+/// Returns the roots of the snapshots, branches and pins recorded in the fs database at
+/// `db_path`, along with the heads.
+pub(crate) async fn get_recorded_roots(db_path: &Path) -> FsResult<Vec<Cid>> {
+    let pool = db::get_db_pool(db_path).await?;
+    let rows = sqlx::query(
+        r#"
+        SELECT root_cid AS cid FROM snapshots
+        UNION SELECT root_cid FROM branches
+        UNION SELECT cid FROM pins
+        UNION SELECT head FROM filesystems WHERE head IS NOT NULL
+        "#,
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    rows.iter()
+        .map(|row| Ok(Cid::try_from(row.get::<String, _>("cid").as_str())?))
+        .collect()
+}
+
+/// Builds a [`Backup`] from a row of the `backups` table.
+fn backup_from_row(row: &SqliteRow) -> FsResult<Backup> {
+    let root_cid: String = row.get("root_cid");
+    Ok(Backup {
+        dest: PathBuf::from(row.get::<String, _>("dest")),
+        snapshot: row.get("snapshot"),
+        root: Cid::try_from(root_cid.as_str())?,
+        blocks: row.get::<i64, _>("blocks") as u64,
+        bytes: row.get::<i64, _>("bytes") as u64,
+        created_at: row.get("created_at"),
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::IpldStore;
+    use tempfile::TempDir;
+
+    use crate::filesystem::Dir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_backup_mfs() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let mount_dir = fs::canonicalize(temp.path()).await?.join("mnt");
+        let paths = find::helper::setup_test_mfs(&mount_dir).await?;
+
+        let store = paths.open_store().await?;
+        let mut root = Dir::new(store.clone());
+        root.find_or_create("docs/readme.txt", true).await?;
+        let cid = root.checkpoint().await?;
+        let head = FsHead::new(paths.fs_db_path(), &mount_dir).await?;
+        head.set(&cid, "checkpoint").await?;
+
+        // Backups go to empty directories only
+        let dest = temp.path().join("backup");
+        fs::create_dir_all(&dest).await?;
+        fs::write(dest.join("stray"), b"").await?;
+        let result = backup_mfs(Some(mount_dir.clone()), &dest).await;
+        assert!(matches!(result, Err(FsError::BackupDestinationNotEmpty(_))));
+        fs::remove_file(dest.join("stray")).await?;
+
+        let backup = backup_mfs(Some(mount_dir.clone()), &dest).await?;
+        assert_eq!(backup.get_root(), &cid);
+        assert!(backup.get_snapshot().starts_with(BACKUP_SNAPSHOT_PREFIX));
+        assert_eq!(
+            list_backups(Some(mount_dir.clone())).await?,
+            [backup.clone()]
+        );
+        assert!(fs::try_exists(dest.join(BACKUP_MANIFEST_FILENAME)).await?);
+
+        // The backup has the database and every block of its head
+        let exported = FsHead::new(dest.join(FS_DB_FILENAME), &mount_dir).await?;
+        assert_eq!(exported.get().await?, Some(cid));
+        let backup_store = FlatFsStore::new(dest.join(BLOCKS_SUBDIR));
+        assert!(backup_store.has(&cid).await);
+        let dag = DagWalker::new(&backup_store).walk([cid]).await?;
+        assert_eq!(*backup.get_blocks(), dag.len() as u64);
+        let restored = Dir::load(&cid, backup_store).await?;
+        assert!(restored.find("docs/readme.txt").await?.is_some());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,381 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use getset::Getters;
+use ipldstore::ipld::cid::Cid;
+use serde::Serialize;
+use sqlx::{sqlite::SqliteRow, Pool, Row, Sqlite};
+
+use crate::{
+    filesystem::Dir,
+    management::{db, find, head},
+    utils, FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A named line of a filesystem's history, tracking the latest root reached on it.
+///
+/// A filesystem is always on one branch, the one its head belongs to. Every new head is recorded
+/// as the latest root of that branch, so the other branches keep the root they were left at.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct Branch {
+    /// The name of the branch.
+    name: String,
+
+    /// The CID of the latest root directory on the branch.
+    #[serde(serialize_with = "utils::serialize_display")]
+    root: Cid,
+
+    /// Whether the filesystem is on the branch.
+    active: bool,
+
+    /// When the branch was created.
+    created_at: DateTime<Utc>,
+
+    /// When the branch last moved to a new root.
+    modified_at: DateTime<Utc>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Create a branch of a monofs filesystem at its current root
+///
+/// The filesystem stays on the branch it is on. If the filesystem is mounted, its server
+/// checkpoints the in-memory root first so the new branch starts from everything written so far.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `name` - The name of the branch. Must be unique within the filesystem
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let branch = management::create_branch(Some("mfstest".into()), "experiment").await?;
+/// println!("created {} at {}", branch.get_name(), branch.get_root());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn create_branch(
+    mount_dir: Option<PathBuf>,
+    name: impl Into<String>,
+) -> FsResult<Branch> {
+    let name = name.into();
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let pool = db::get_db_pool(paths.fs_db_path()).await?;
+
+    // Refuse to overwrite an existing branch
+    if get_branch(&pool, paths.get_mount_dir(), &name)
+        .await?
+        .is_some()
+    {
+        return Err(FsError::BranchExists(name));
+    }
+
+    // Start the branch from the current root, checkpointing it first if the filesystem is mounted
+    let root = head::checkpoint_head(&paths).await?.ok_or_else(|| {
+        FsError::InvalidOperation(format!(
+            "filesystem at {} has no recorded state to branch from",
+            paths.get_mount_dir().display()
+        ))
+    })?;
+
+    let fs_id = db::get_fs_id(&pool, paths.get_mount_dir()).await?;
+    sqlx::query("INSERT INTO branches (fs_id, name, root_cid) VALUES (?, ?, ?)")
+        .bind(fs_id)
+        .bind(&name)
+        .bind(root.to_string())
+        .execute(&pool)
+        .await?;
+    tracing::info!("created branch {} at {}", name, root);
+
+    get_branch(&pool, paths.get_mount_dir(), &name)
+        .await?
+        .ok_or(FsError::BranchNotFound(name))
+}
+
+/// List the branches of a monofs filesystem, oldest first
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// for branch in management::list_branches(None).await? {
+///     println!("{} {}", branch.get_name(), branch.get_root());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn list_branches(mount_dir: Option<PathBuf>) -> FsResult<Vec<Branch>> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let pool = db::get_db_pool(paths.fs_db_path()).await?;
+
+    let rows = sqlx::query(
+        r#"
+        SELECT b.name, b.root_cid, b.name = f.branch AS active, b.created_at, b.modified_at
+        FROM branches b
+        JOIN filesystems f ON b.fs_id = f.id
+        WHERE f.mount_dir = ?
+        ORDER BY b.created_at, b.id
+        "#,
+    )
+    .bind(paths.get_mount_dir().to_string_lossy().to_string())
+    .fetch_all(&pool)
+    .await?;
+
+    rows.iter().map(branch_from_row).collect()
+}
+
+/// Switch a monofs filesystem to another branch
+///
+/// The current root is left as the latest root of the branch the filesystem was on, and the root
+/// of the other branch becomes the filesystem's head. From then on, new roots are recorded on the
+/// other branch. If the filesystem is mounted, its server checkpoints the in-memory root first
+/// and then swaps in the root of the other branch, so clients see its contents immediately.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `name` - The name of the branch to switch to
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// management::switch_branch(Some("mfstest".into()), "experiment").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn switch_branch(mount_dir: Option<PathBuf>, name: impl AsRef<str>) -> FsResult<Branch> {
+    let name = name.as_ref();
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let pool = db::get_db_pool(paths.fs_db_path()).await?;
+
+    let branch = get_branch(&pool, paths.get_mount_dir(), name)
+        .await?
+        .ok_or_else(|| FsError::BranchNotFound(name.to_string()))?;
+    if branch.active {
+        return Ok(branch);
+    }
+
+    // Leave the current root on the current branch
+    head::checkpoint_head(&paths).await?;
+
+    // Make sure the root of the branch has not been garbage collected before switching to it
+    let store = paths.open_store().await?;
+    Dir::load(&branch.root, store).await?;
+
+    // Move onto the branch before swapping the root, so the root is recorded on it
+    let fs_id = db::get_fs_id(&pool, paths.get_mount_dir()).await?;
+    let previous = set_active_branch(&pool, fs_id, name).await?;
+    let operation = format!("switch to branch {}", name);
+    if let Err(e) = head::set_head(&paths, None, &branch.root, &operation).await {
+        set_active_branch(&pool, fs_id, &previous).await?;
+        return Err(e);
+    }
+    tracing::info!("switched from branch {} to {}", previous, name);
+
+    get_branch(&pool, paths.get_mount_dir(), name)
+        .await?
+        .ok_or_else(|| FsError::BranchNotFound(name.to_string()))
+}
+
+/// Delete a branch of a monofs filesystem
+///
+/// The branch the filesystem is on cannot be deleted. Roots only on the deleted branch are no
+/// longer kept by garbage collection.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `name` - The name of the branch to delete
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// management::delete_branch(Some("mfstest".into()), "experiment").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn delete_branch(mount_dir: Option<PathBuf>, name: impl AsRef<str>) -> FsResult<Branch> {
+    let name = name.as_ref();
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let pool = db::get_db_pool(paths.fs_db_path()).await?;
+
+    let branch = get_branch(&pool, paths.get_mount_dir(), name)
+        .await?
+        .ok_or_else(|| FsError::BranchNotFound(name.to_string()))?;
+    if branch.active {
+        return Err(FsError::InvalidOperation(format!(
+            "cannot delete branch {}, the filesystem is on it",
+            name
+        )));
+    }
+
+    let fs_id = db::get_fs_id(&pool, paths.get_mount_dir()).await?;
+    sqlx::query("DELETE FROM branches WHERE fs_id = ? AND name = ?")
+        .bind(fs_id)
+        .bind(name)
+        .execute(&pool)
+        .await?;
+    tracing::info!("deleted branch {} at {}", name, branch.root);
+
+    Ok(branch)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Get a branch of the filesystem mounted at `mount_dir` by name.
+async fn get_branch(pool: &Pool<Sqlite>, mount_dir: &Path, name: &str) -> FsResult<Option<Branch>> {
+    let record = sqlx::query(
+        r#"
+        SELECT b.name, b.root_cid, b.name = f.branch AS active, b.created_at, b.modified_at
+        FROM branches b
+        JOIN filesystems f ON b.fs_id = f.id
+        WHERE f.mount_dir = ? AND b.name = ?
+        "#,
+    )
+    .bind(mount_dir.to_string_lossy().to_string())
+    .bind(name)
+    .fetch_optional(pool)
+    .await?;
+
+    record.as_ref().map(branch_from_row).transpose()
+}
+
+/// Puts the filesystem with the given ID on the branch `name`.
+///
+/// Returns the branch the filesystem was on.
+async fn set_active_branch(pool: &Pool<Sqlite>, fs_id: i64, name: &str) -> FsResult<String> {
+    let mut tx = pool.begin().await?;
+    let previous = sqlx::query("SELECT branch FROM filesystems WHERE id = ?")
+        .bind(fs_id)
+        .fetch_one(&mut *tx)
+        .await?
+        .get::<String, _>("branch");
+
+    sqlx::query(
+        r#"
+        UPDATE filesystems
+        SET branch = ?, modified_at = CURRENT_TIMESTAMP
+        WHERE id = ?
+        "#,
+    )
+    .bind(name)
+    .bind(fs_id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(previous)
+}
+
+/// Construct a branch from a row of the branches table.
+fn branch_from_row(row: &SqliteRow) -> FsResult<Branch> {
+    let root_cid: String = row.get("root_cid");
+    Ok(Branch {
+        name: row.get("name"),
+        root: Cid::try_from(root_cid.as_str())?,
+        active: row.get("active"),
+        created_at: row.get("created_at"),
+        modified_at: row.get("modified_at"),
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::Storable;
+    use tempfile::TempDir;
+    use tokio::fs;
+
+    use crate::{
+        config::DEFAULT_BRANCH_NAME,
+        filesystem::File,
+        management::{list_history, FsHead},
+        store::FlatFsStore,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_branches_track_their_own_roots() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let mount_dir = fs::canonicalize(temp.path()).await?.join("mnt");
+        let paths = find::helper::setup_test_mfs(&mount_dir).await?;
+
+        // The filesystem starts out on the default branch
+        let store = FlatFsStore::new(paths.blocks_dir());
+        let mut root = Dir::new(store.clone());
+        let base = root.checkpoint().await?;
+        let head = FsHead::new(paths.fs_db_path(), &mount_dir).await?;
+        head.set(&base, "checkpoint").await?;
+
+        let branches = list_branches(Some(mount_dir.clone())).await?;
+        assert_eq!(branches.len(), 1);
+        assert_eq!(branches[0].get_name(), DEFAULT_BRANCH_NAME);
+        assert!(*branches[0].get_active());
+
+        // A new branch starts at the current root, and moves on once switched to
+        let branch = create_branch(Some(mount_dir.clone()), "experiment").await?;
+        assert_eq!(branch.get_root(), &base);
+        assert!(!*branch.get_active());
+        let result = create_branch(Some(mount_dir.clone()), "experiment").await;
+        assert!(matches!(result, Err(FsError::BranchExists(_))));
+
+        switch_branch(Some(mount_dir.clone()), "experiment").await?;
+        let file = File::with_content(store.clone(), b"hello".as_slice()).await?;
+        root.put_adapted_file("hello.txt", file).await?;
+        let changed = root.checkpoint().await?;
+        head.set(&changed, "checkpoint").await?;
+
+        let history = list_history(Some(mount_dir.clone())).await?;
+        let last = history.last().unwrap();
+        assert_eq!(last.get_root(), &changed);
+        assert_eq!(last.get_branch(), "experiment");
+
+        // Switching back restores the root the default branch was left at
+        let branch = switch_branch(Some(mount_dir.clone()), DEFAULT_BRANCH_NAME).await?;
+        assert_eq!(branch.get_root(), &base);
+        assert_eq!(head.get().await?, Some(base));
+
+        let roots = list_branches(Some(mount_dir.clone()))
+            .await?
+            .into_iter()
+            .map(|branch| (branch.name, branch.root))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            roots,
+            vec![
+                (DEFAULT_BRANCH_NAME.to_string(), base),
+                ("experiment".to_string(), changed)
+            ]
+        );
+
+        // Only branches the filesystem is not on can be deleted
+        let result = delete_branch(Some(mount_dir.clone()), DEFAULT_BRANCH_NAME).await;
+        assert!(matches!(result, Err(FsError::InvalidOperation(_))));
+        delete_branch(Some(mount_dir.clone()), "experiment").await?;
+        let result = switch_branch(Some(mount_dir), "experiment").await;
+        assert!(matches!(result, Err(FsError::BranchNotFound(_))));
+
+        Ok(())
+    }
+}
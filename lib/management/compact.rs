@@ -0,0 +1,229 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use getset::Getters;
+use ipldstore::ipld::cid::Cid;
+use serde::Serialize;
+use tokio::fs;
+
+use crate::{
+    management::{find, head},
+    store::{self, DagWalker, FlatFsStore, PACKS_SUBDIR},
+    FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How many bytes of blocks a packfile holds before compaction starts a new one.
+const MAX_PACK_SIZE: u64 = 64 * 1024 * 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The outcome of compacting a filesystem's blocks into packfiles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct CompactReport {
+    /// The number of block files that were moved into packfiles.
+    packed_blocks: u64,
+
+    /// The number of packfiles written.
+    packs_created: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Move the blocks of a monofs filesystem from their own files into packfiles
+///
+/// Every block is stored in a file of its own as it is written, so a large filesystem ends up
+/// with millions of small files, which is slow to list, back up and read back on most host
+/// filesystems. Compaction appends those blocks to a few large packfiles in the `packs`
+/// subdirectory of the blocks directory, each with an index of where its blocks are, and removes
+/// their files. Blocks reachable from the current root are packed in the order they are walked,
+/// so that reading a directory tree reads packfiles mostly sequentially.
+///
+/// Compaction is safe while the filesystem is mounted: its server finds packed blocks through
+/// the pack indexes as they are written, and blocks written after the compaction started are left
+/// in their own files until the next one. Packfiles are never rewritten, so packed blocks are not
+/// removed by garbage collection. Collecting garbage before compacting keeps unreachable blocks
+/// out of the packs.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+///
+/// ## Returns
+/// How many blocks were packed, into how many packfiles
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// management::gc_mfs(Some("mfstest".into())).await?;
+/// let report = management::compact_mfs(Some("mfstest".into())).await?;
+/// println!("packed {} blocks", report.get_packed_blocks());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn compact_mfs(mount_dir: Option<PathBuf>) -> FsResult<CompactReport> {
+    let started_at = SystemTime::now();
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let store = paths.open_store().await?;
+
+    // Pack the blocks of the current tree together, in the order they are walked
+    let order = match head::checkpoint_head(&paths).await? {
+        Some(head) => get_walk_order(&store, head).await?,
+        None => HashMap::new(),
+    };
+
+    let mut block_paths = get_block_files(&paths.blocks_dir(), started_at).await?;
+    block_paths.sort_by_key(|(name, _)| order.get(name).copied().unwrap_or(usize::MAX));
+    let block_paths = block_paths
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect::<Vec<_>>();
+
+    let (packs_created, packed_blocks) =
+        store.pack_block_files(&block_paths, MAX_PACK_SIZE).await?;
+    tracing::info!(
+        "packed {} blocks into {} packfiles",
+        packed_blocks,
+        packs_created
+    );
+
+    Ok(CompactReport {
+        packed_blocks,
+        packs_created,
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the position of every block reachable from `root` in a depth-first walk, keyed by
+/// every name its file can have.
+async fn get_walk_order(store: &FlatFsStore, root: Cid) -> FsResult<HashMap<String, usize>> {
+    let dag = DagWalker::new(store).walk([root]).await?;
+    let order = dag
+        .get_preorder([root])
+        .iter()
+        .enumerate()
+        .flat_map(|(position, cid)| {
+            store
+                .get_block_names(cid)
+                .into_iter()
+                .map(move |name| (name, position))
+        })
+        .collect();
+
+    Ok(order)
+}
+
+/// Returns the name and path of every block file under `dir` that was last modified before
+/// `started_at`.
+///
+/// Block files are named after the hex digest of their CID, or a keyed hash of it if encrypted,
+/// with an extension if compressed or encrypted, and may be nested in subdirectories. Packfiles
+/// are skipped.
+async fn get_block_files(dir: &Path, started_at: SystemTime) -> FsResult<Vec<(String, PathBuf)>> {
+    let mut block_files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                if entry.file_name() != PACKS_SUBDIR {
+                    pending.push(entry.path());
+                }
+                continue;
+            }
+
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(block_name) = store::get_block_name(&name).filter(|_| file_type.is_file())
+            else {
+                continue;
+            };
+
+            if entry.metadata().await?.modified()? < started_at {
+                block_files.push((block_name.to_string(), entry.path()));
+            }
+        }
+    }
+
+    Ok(block_files)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::Storable;
+    use tempfile::TempDir;
+    use tokio::io::AsyncReadExt;
+
+    use crate::{
+        filesystem::{Dir, File},
+        management::{verify_mfs, FsHead},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_compact_packs_blocks() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let mount_dir = fs::canonicalize(temp.path()).await?.join("mnt");
+        let paths = find::helper::setup_test_mfs(&mount_dir).await?;
+
+        let store = FlatFsStore::new(paths.blocks_dir());
+        let mut root = Dir::new(store.clone());
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            let file = File::with_content(store.clone(), name.as_bytes()).await?;
+            root.put_adapted_file(name, file).await?;
+        }
+        let cid = root.checkpoint().await?;
+        FsHead::new(paths.fs_db_path(), &mount_dir)
+            .await?
+            .set(&cid, "checkpoint")
+            .await?;
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+        let loose = get_block_files(&paths.blocks_dir(), SystemTime::now()).await?;
+        let report = compact_mfs(Some(mount_dir.clone())).await?;
+        assert_eq!(*report.get_packed_blocks(), loose.len() as u64);
+        assert_eq!(*report.get_packs_created(), 1);
+        assert!(get_block_files(&paths.blocks_dir(), SystemTime::now())
+            .await?
+            .is_empty());
+
+        // Packed blocks are read by new stores and by the store that packed them alike
+        let root = Dir::load(&cid, FlatFsStore::new(paths.blocks_dir())).await?;
+        let mut content = String::new();
+        let file = root.get_file("b.txt").await?.unwrap();
+        file.get_input_stream()
+            .await?
+            .read_to_string(&mut content)
+            .await?;
+        assert_eq!(content, "b.txt");
+        assert!(Dir::load(&cid, store.clone()).await.is_ok());
+        assert!(verify_mfs(Some(mount_dir.clone()), false).await?.is_ok());
+
+        // Nothing is left to pack
+        let report = compact_mfs(Some(mount_dir)).await?;
+        assert_eq!(*report.get_packed_blocks(), 0);
+        assert_eq!(*report.get_packs_created(), 0);
+
+        Ok(())
+    }
+}
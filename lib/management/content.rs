@@ -0,0 +1,229 @@
+use std::path::PathBuf;
+
+use ipldstore::{ipld::cid::Cid, IpldStore, IpldStoreSeekable, Storable};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    filesystem::{Dir, Entity, File},
+    management::{find, head, tree, TreeSource},
+    FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Write the content of a file straight from the block store to `writer`, like `cat`
+///
+/// Nothing needs to be mounted, so this also reads files in snapshots and in stores no server is
+/// running for. Symbolic links are not followed.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `source` - The file to read
+/// * `writer` - Where to write the content of the file
+///
+/// ## Returns
+/// The number of bytes written
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management::{self, TreeSource};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let source = TreeSource::Path("sandbox/results/summary.txt".into());
+/// management::cat_file(Some("mfstest".into()), source, tokio::io::stdout()).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn cat_file(
+    mount_dir: Option<PathBuf>,
+    source: TreeSource,
+    writer: impl AsyncWrite + Unpin + Send,
+) -> FsResult<u64> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let store = paths.open_store().await?;
+
+    let (root, path) = match source {
+        TreeSource::Cid { cid, path } => (cid, path),
+        TreeSource::Path(path) => {
+            let head = head::checkpoint_head(&paths).await?.ok_or_else(|| {
+                FsError::InvalidOperation(format!(
+                    "filesystem at {} has no recorded state to read",
+                    paths.get_mount_dir().display()
+                ))
+            })?;
+            (head, path)
+        }
+    };
+
+    read_file_content(&store, &root, &path, writer).await
+}
+
+/// Write the content read from `reader` to a file, like redirecting into it
+///
+/// The file is replaced if it exists and created otherwise, along with any missing parent
+/// directories. A path in the current root changes the filesystem the same way as changing the
+/// file through a mount would, and is visible immediately if it is mounted. A path below a CID
+/// leaves the filesystem alone and only stores the new root, which can be restored or sent like
+/// any other.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `target` - The file to write
+/// * `reader` - Where to read the content of the file from
+///
+/// ## Returns
+/// The CID of the new root holding the file
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management::{self, TreeSource};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let target = TreeSource::Path("config/settings.json".into());
+/// let root = management::write_file(Some("mfstest".into()), target, tokio::io::stdin()).await?;
+/// println!("new root: {}", root);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn write_file(
+    mount_dir: Option<PathBuf>,
+    target: TreeSource,
+    reader: impl AsyncRead + Send + Sync,
+) -> FsResult<Cid> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let store = paths.open_store().await?;
+
+    match target {
+        TreeSource::Cid { cid, path } => {
+            let mut root = Dir::load(&cid, store).await?;
+            write_file_content(&mut root, &path, reader).await?;
+
+            let cid = root.checkpoint().await?;
+            tracing::info!("wrote {} into new root {}", path, cid);
+            Ok(cid)
+        }
+        TreeSource::Path(path) => {
+            let head = head::checkpoint_head(&paths).await?;
+            let mut root = match &head {
+                Some(cid) => Dir::load(cid, store.clone()).await?,
+                None => Dir::new(store.clone()),
+            };
+            write_file_content(&mut root, &path, reader).await?;
+
+            let cid = root.checkpoint().await?;
+            let operation = format!("write {}", path.trim_matches('/'));
+            head::set_head(&paths, head.as_ref(), &cid, &operation).await?;
+            tracing::info!("wrote {} into {}", path, cid);
+            Ok(cid)
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Copies the content of the file at `path` below the entity at `root` to `writer`.
+async fn read_file_content<S>(
+    store: &S,
+    root: &Cid,
+    path: &str,
+    mut writer: impl AsyncWrite + Unpin + Send,
+) -> FsResult<u64>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    let cid = tree::get_path_cid(store, root, path).await?;
+    let Entity::File(file) = Entity::load(&cid, store.clone()).await? else {
+        return Err(FsError::NotAFile(path.to_string()));
+    };
+
+    let mut input = file.get_input_stream().await?;
+    let written = tokio::io::copy(&mut input, &mut writer).await?;
+    writer.flush().await?;
+
+    Ok(written)
+}
+
+/// Puts a file with the content read from `reader` at `path` in `root`, replacing the file there
+/// and creating missing parent directories.
+async fn write_file_content<S>(
+    root: &mut Dir<S>,
+    path: &str,
+    reader: impl AsyncRead + Send + Sync,
+) -> FsResult<()>
+where
+    S: IpldStore + Send + Sync,
+{
+    let path = path.trim_matches('/');
+    let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+    if name.is_empty() {
+        return Err(FsError::InvalidOperation(
+            "cannot write file content to the root".to_string(),
+        ));
+    }
+
+    let store = root.get_store().clone();
+    let dir = if parent.is_empty() {
+        root
+    } else {
+        match root.find_or_create(parent, false).await? {
+            Entity::Dir(dir) => dir,
+            _ => return Err(FsError::NotADirectory(parent.to_string())),
+        }
+    };
+
+    if let Some(entity) = dir.get_entity(name).await? {
+        if !matches!(entity, Entity::File(_)) {
+            return Err(FsError::NotAFile(path.to_string()));
+        }
+    }
+
+    let file = File::with_content(store, reader).await?;
+    dir.put_adapted_file(name, file).await
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::MemoryStore;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_content() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut root = Dir::new(store.clone());
+
+        // Missing parents are created and existing files are replaced
+        write_file_content(&mut root, "/dir/sub/file.txt", b"first".as_slice()).await?;
+        write_file_content(&mut root, "dir/sub/file.txt", b"second".as_slice()).await?;
+        let cid = root.checkpoint().await?;
+
+        let mut content = Vec::new();
+        let written = read_file_content(&store, &cid, "dir/sub/file.txt", &mut content).await?;
+        assert_eq!(written, 6);
+        assert_eq!(content, b"second");
+
+        // Only files can be read and replaced
+        assert!(matches!(
+            read_file_content(&store, &cid, "dir/sub", Vec::new()).await,
+            Err(FsError::NotAFile(_))
+        ));
+        assert!(matches!(
+            write_file_content(&mut root, "dir/sub", b"content".as_slice()).await,
+            Err(FsError::NotAFile(_))
+        ));
+        assert!(matches!(
+            write_file_content(&mut root, "/", b"content".as_slice()).await,
+            Err(FsError::InvalidOperation(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -1,8 +1,12 @@
-use sqlx::{migrate::Migrator, sqlite::SqlitePoolOptions, Pool, Sqlite};
-use std::path::Path;
+use sqlx::{
+    migrate::{Migration, Migrator},
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    Pool, Row, Sqlite,
+};
+use std::{borrow::Cow, path::Path, str::FromStr};
 use tokio::fs;
 
-use crate::FsResult;
+use crate::{config::EncryptionKey, FsError, FsResult};
 
 //--------------------------------------------------------------------------------------------------
 // Constants
@@ -37,30 +41,234 @@ pub async fn init_db(db_path: impl AsRef<Path>, migrator: &Migrator) -> FsResult
     // Create database connection pool
     let pool = SqlitePoolOptions::new()
         .max_connections(5)
-        .connect(&format!("sqlite://{}?mode=rwc", db_path.display()))
+        .connect_with(get_connect_options(db_path)?)
         .await?;
 
-    // Run migrations
+    // Refuse to touch a database written by a newer version, then run migrations
+    get_pending_migrations(&pool, migrator, db_path).await?;
     migrator.run(&pool).await?;
 
     Ok(())
 }
 
+/// Brings the schema of the database at `db_path` up to date while it may be in use.
+///
+/// Migrations that only add tables, indexes, triggers, columns and rows leave everything that
+/// was there before as it was, so a server of an older version keeps working against the
+/// database while they run. They are applied as soon as the database is opened, which lets a
+/// newer version manage a filesystem served by an older one. Other migrations are only applied
+/// when `live` is false, and otherwise fail with [`FsError::DbMigrationRequired`], as they need
+/// the filesystem detached.
+///
+/// A database that does not exist yet is left alone. Fails with [`FsError::DbVersionTooNew`]
+/// if the database was migrated by a newer version.
+pub(crate) async fn upgrade_db(
+    db_path: impl AsRef<Path>,
+    migrator: &Migrator,
+    live: bool,
+) -> FsResult<()> {
+    let db_path = db_path.as_ref();
+    if !fs::try_exists(db_path).await? {
+        return Ok(());
+    }
+
+    let pool = get_db_pool(db_path).await?;
+    let pending = get_pending_migrations(&pool, migrator, db_path).await?;
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    if live && !pending.iter().all(|migration| is_additive(&migration.sql)) {
+        let versions = pending
+            .iter()
+            .map(|migration| migration.version.to_string())
+            .collect::<Vec<_>>();
+        return Err(FsError::DbMigrationRequired(format!(
+            "{} needs migrations {} that change existing data, detach and attach the filesystem \
+            to apply them",
+            db_path.display(),
+            versions.join(", ")
+        )));
+    }
+
+    migrator.run(&pool).await?;
+    tracing::info!(
+        "applied {} migrations to {}",
+        pending.len(),
+        db_path.display()
+    );
+
+    Ok(())
+}
+
 /// Creates and returns a connection pool for SQLite database operations.
 ///
 /// This function initializes a new SQLite connection pool with specified configuration parameters
 /// for managing database connections efficiently. The pool is configured with a maximum of 5
 /// concurrent connections.
+///
+/// If an encryption key is given in the environment (see [`EncryptionKey::from_env`]), the
+/// database is encrypted with it, which both this function and [`init_db`] apply.
 pub async fn get_db_pool(db_path: impl AsRef<Path>) -> FsResult<Pool<Sqlite>> {
     let db_path = db_path.as_ref();
     let pool = SqlitePoolOptions::new()
         .max_connections(5)
-        .connect(&format!("sqlite://{}?mode=rwc", db_path.display()))
+        .connect_with(get_connect_options(db_path)?)
         .await?;
 
     Ok(pool)
 }
 
+/// Flushes the SQLite database at `db_path` to disk, along with its write-ahead log or rollback
+/// journal if it has one and the directory it is in.
+///
+/// SQLite flushes its files on every commit unless told otherwise, so this only matters for
+/// connections that skip it, like those of a database kept in a filesystem mounted without
+/// synchronous writes.
+pub(crate) async fn sync_db(db_path: impl AsRef<Path>) -> FsResult<()> {
+    let db_path = db_path.as_ref();
+    let mut paths = vec![db_path.to_path_buf()];
+    for suffix in ["-wal", "-journal"] {
+        let mut path = db_path.as_os_str().to_owned();
+        path.push(suffix);
+        paths.push(path.into());
+    }
+    if let Some(parent) = db_path.parent() {
+        paths.push(parent.to_path_buf());
+    }
+
+    for path in paths {
+        match fs::File::open(&path).await {
+            Ok(file) => file.sync_all().await?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the id of the filesystem mounted at `mount_dir` from the filesystem database.
+pub(crate) async fn get_fs_id(pool: &Pool<Sqlite>, mount_dir: &Path) -> FsResult<i64> {
+    let mount_dir = mount_dir.to_string_lossy().to_string();
+    let record = sqlx::query("SELECT id FROM filesystems WHERE mount_dir = ?")
+        .bind(&mount_dir)
+        .fetch_optional(pool)
+        .await?;
+
+    record
+        .map(|row| row.get::<i64, _>("id"))
+        .ok_or(FsError::FilesystemNotRegistered(mount_dir))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the migrations of `migrator` that have yet to be applied to the database at
+/// `db_path`, oldest first.
+///
+/// Fails with [`FsError::DbVersionTooNew`] if the database has migrations applied that
+/// `migrator` does not know of, newer than all it does, as written by a newer version.
+async fn get_pending_migrations<'a>(
+    pool: &Pool<Sqlite>,
+    migrator: &'a Migrator,
+    db_path: &Path,
+) -> FsResult<Vec<&'a Migration>> {
+    let migrations = migrator
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .collect::<Vec<_>>();
+
+    // Databases that were never migrated have no record of migrations
+    let recorded = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'",
+    )
+    .fetch_one(pool)
+    .await?;
+    let applied = match recorded {
+        0 => Vec::new(),
+        _ => {
+            sqlx::query_scalar::<_, i64>(
+                "SELECT version FROM _sqlx_migrations WHERE success = TRUE ORDER BY version",
+            )
+            .fetch_all(pool)
+            .await?
+        }
+    };
+
+    let supported = migrations.iter().map(|migration| migration.version).max();
+    if let Some(&version) = applied.last() {
+        if supported.map_or(true, |supported| version > supported) {
+            return Err(FsError::DbVersionTooNew(format!(
+                "{} is at schema version {}, but this version of monofs supports up to {}, \
+                upgrade monofs to use it",
+                db_path.display(),
+                version,
+                supported.unwrap_or_default()
+            )));
+        }
+    }
+
+    Ok(migrations
+        .into_iter()
+        .filter(|migration| applied.binary_search(&migration.version).is_err())
+        .collect())
+}
+
+/// Returns whether the migration script `sql` only adds to the schema and data, without
+/// changing or removing what is there.
+fn is_additive(sql: &str) -> bool {
+    let sql = sql
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("--"))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .to_uppercase();
+
+    // Triggers hold statements of their own, up to their END
+    let mut statements = Vec::new();
+    let mut statement = String::new();
+    for part in sql.split(';') {
+        statement.push_str(part);
+        let trimmed = statement.trim();
+        if trimmed.starts_with("CREATE TRIGGER") && !trimmed.ends_with("END") {
+            statement.push(';');
+            continue;
+        }
+
+        if !trimmed.is_empty() {
+            statements.push(trimmed.split_whitespace().collect::<Vec<_>>().join(" "));
+        }
+        statement.clear();
+    }
+
+    statements.iter().all(|statement| {
+        [
+            "CREATE TABLE",
+            "CREATE INDEX",
+            "CREATE UNIQUE INDEX",
+            "CREATE TRIGGER",
+            "INSERT INTO",
+        ]
+        .iter()
+        .any(|prefix| statement.starts_with(prefix))
+            || (statement.starts_with("ALTER TABLE") && statement.contains(" ADD COLUMN "))
+    })
+}
+
+/// Returns the options for connecting to the database at `db_path`, creating it if missing and
+/// keying it with the encryption key in the environment, if any.
+fn get_connect_options(db_path: &Path) -> FsResult<SqliteConnectOptions> {
+    let options =
+        SqliteConnectOptions::from_str(&format!("sqlite://{}?mode=rwc", db_path.display()))?;
+    match EncryptionKey::from_env()? {
+        // SQLCipher takes a raw key as a blob literal in quotes
+        Some(key) => Ok(options.pragma("key", Cow::Owned(format!("\"x'{}'\"", key.to_hex())))),
+        None => Ok(options),
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Tests
 //--------------------------------------------------------------------------------------------------
@@ -108,4 +316,77 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_db_versions() -> FsResult<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("test.db");
+        init_db(&db_path, &FS_DB_MIGRATOR).await?;
+        let pool = get_db_pool(&db_path).await?;
+
+        // A migration that went missing, e.g. on a filesystem served by an older version, is
+        // applied while the filesystem is live. The latest migration is undone with its own down
+        // migration, so it has to be additive for this to hold.
+        let latest = FS_DB_MIGRATOR
+            .iter()
+            .map(|migration| migration.version)
+            .max()
+            .unwrap();
+        let down = FS_DB_MIGRATOR
+            .iter()
+            .find(|migration| {
+                migration.version == latest && migration.migration_type.is_down_migration()
+            })
+            .unwrap();
+        sqlx::query("DELETE FROM _sqlx_migrations WHERE version = ?")
+            .bind(latest)
+            .execute(&pool)
+            .await?;
+        sqlx::raw_sql(&down.sql).execute(&pool).await?;
+        upgrade_db(&db_path, &FS_DB_MIGRATOR, true).await?;
+        let applied = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM _sqlx_migrations WHERE version = ? AND success = TRUE",
+        )
+        .bind(latest)
+        .fetch_one(&pool)
+        .await?;
+        assert_eq!(applied, 1);
+
+        // Databases migrated by a newer version are refused
+        sqlx::query(
+            r#"
+            INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+            VALUES (?, 'from the future', TRUE, x'00', 0)
+            "#,
+        )
+        .bind(latest + 1)
+        .execute(&pool)
+        .await?;
+        let result = init_db(&db_path, &FS_DB_MIGRATOR).await;
+        assert!(matches!(result, Err(FsError::DbVersionTooNew(_))));
+        let result = upgrade_db(&db_path, &FS_DB_MIGRATOR, false).await;
+        assert!(matches!(result, Err(FsError::DbVersionTooNew(_))));
+
+        // Databases that do not exist are left alone
+        let missing = temp_dir.path().join("missing.db");
+        upgrade_db(&missing, &FS_DB_MIGRATOR, true).await?;
+        assert!(!missing.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_additive() {
+        for migration in FS_DB_MIGRATOR.iter() {
+            if !migration.migration_type.is_down_migration() {
+                assert!(is_additive(&migration.sql), "{}", migration.description);
+            }
+        }
+
+        assert!(!is_additive("DROP TABLE tags;"));
+        assert!(!is_additive("ALTER TABLE tags RENAME TO labels;"));
+        assert!(!is_additive(
+            "CREATE TABLE tags_new (id INTEGER);\nUPDATE tags SET name = lower(name);"
+        ));
+    }
 }
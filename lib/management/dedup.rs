@@ -0,0 +1,403 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use getset::Getters;
+use ipldstore::{ipld::cid::Cid, Storable};
+use serde::Serialize;
+use tokio::fs;
+
+use crate::{
+    filesystem::{Dir, Entity},
+    management::{find, head, snapshot},
+    store::{self, DagWalker, FlatFsStore},
+    utils, FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How many levels of directories below the root get their own entry in a dedup report.
+const MAX_DEDUP_REPORT_DEPTH: usize = 2;
+
+/// How many of the most duplicated blocks a dedup report lists.
+const MAX_DEDUP_REPORT_DUPLICATES: usize = 10;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// How much space content addressing saves in a monofs filesystem.
+///
+/// Sizes are those of blocks as they are stored. The logical size counts a block once for every
+/// place it is referenced from, which is the space the filesystem would take up without
+/// deduplication. The physical size counts every reachable block once.
+#[derive(Debug, Clone, PartialEq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct DedupReport {
+    /// The size of everything reachable from the current root and snapshots, counting
+    /// duplicates.
+    logical_size: u64,
+
+    /// The size of the unique blocks reachable from the current root and snapshots.
+    physical_size: u64,
+
+    /// The size of every block in the blocks directory, including unreachable ones.
+    store_size: u64,
+
+    /// Deduplication within the directories of the current root, down to a fixed depth.
+    directories: Vec<DirDedup>,
+
+    /// The blocks that are referenced the most, ordered by the space their sharing saves.
+    top_duplicates: Vec<DuplicateBlock>,
+}
+
+/// How much space content addressing saves within a directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct DirDedup {
+    /// The path of the directory relative to the root.
+    path: String,
+
+    /// The size of the directory's contents, counting duplicates.
+    logical_size: u64,
+
+    /// The size of the unique blocks in the directory's contents.
+    physical_size: u64,
+}
+
+/// A block that is referenced from more than one place.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct DuplicateBlock {
+    /// The CID of the block.
+    #[serde(serialize_with = "utils::serialize_display")]
+    cid: Cid,
+
+    /// The size of the block.
+    size: u64,
+
+    /// The number of places the block is referenced from.
+    references: u64,
+}
+
+/// The block graph reachable from a set of roots.
+pub(crate) struct BlockGraph {
+    /// The links of every reachable block.
+    links: HashMap<Cid, Vec<Cid>>,
+
+    /// Reachable blocks ordered so that every block comes after the blocks that link to it.
+    order: Vec<Cid>,
+
+    /// The size of each reachable block in the blocks directory.
+    sizes: HashMap<Cid, u64>,
+
+    /// The logical size of the DAG below each reachable block, memoized.
+    logical_sizes: HashMap<Cid, u64>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl DedupReport {
+    /// Returns the logical size divided by the physical size.
+    pub fn get_ratio(&self) -> f64 {
+        dedup_ratio(self.logical_size, self.physical_size)
+    }
+}
+
+impl DirDedup {
+    /// Returns the logical size divided by the physical size.
+    pub fn get_ratio(&self) -> f64 {
+        dedup_ratio(self.logical_size, self.physical_size)
+    }
+}
+
+impl BlockGraph {
+    /// Walks the blocks reachable from `roots`, whose sizes are looked up in `sizes`, as returned
+    /// by [`get_block_sizes`].
+    pub(crate) async fn build(
+        store: &FlatFsStore,
+        roots: &[Cid],
+        sizes: HashMap<String, u64>,
+    ) -> FsResult<Self> {
+        let dag = DagWalker::new(store).walk(roots.iter().copied()).await?;
+        let postorder = dag.get_postorder(roots.iter().copied());
+        let links = dag.get_links().clone();
+        let sizes = links
+            .keys()
+            .map(|cid| {
+                let names = store.get_block_names(cid);
+                let size = names.iter().find_map(|name| sizes.get(name)).copied();
+                (*cid, size.unwrap_or_default())
+            })
+            .collect();
+
+        let mut graph = Self {
+            links,
+            order: postorder.iter().rev().copied().collect(),
+            sizes,
+            logical_sizes: HashMap::new(),
+        };
+
+        for cid in postorder {
+            let logical_size = graph.size(&cid)
+                + graph.links[&cid]
+                    .iter()
+                    .map(|child| graph.logical_sizes[child])
+                    .sum::<u64>();
+            graph.logical_sizes.insert(cid, logical_size);
+        }
+
+        Ok(graph)
+    }
+
+    /// Returns the size of the block at `cid`, or 0 if it is missing.
+    pub(crate) fn size(&self, cid: &Cid) -> u64 {
+        self.sizes.get(cid).copied().unwrap_or_default()
+    }
+
+    /// Returns the reachable blocks, ordered so that every block comes after the blocks that link
+    /// to it.
+    pub(crate) fn get_order(&self) -> &[Cid] {
+        &self.order
+    }
+
+    /// Returns the CIDs the block at `cid` links to, with a CID repeated for every link to it.
+    pub(crate) fn get_links(&self, cid: &Cid) -> &[Cid] {
+        self.links.get(cid).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Returns the logical size of the DAG below `cid`.
+    fn logical_size(&self, cid: &Cid) -> u64 {
+        self.logical_sizes.get(cid).copied().unwrap_or_default()
+    }
+
+    /// Returns the size of the unique blocks reachable from `roots`.
+    pub(crate) fn physical_size(&self, roots: impl IntoIterator<Item = Cid>) -> u64 {
+        let mut seen = HashSet::new();
+        let mut pending = roots.into_iter().collect::<Vec<_>>();
+        while let Some(cid) = pending.pop() {
+            if seen.insert(cid) {
+                pending.extend(self.links.get(&cid).into_iter().flatten());
+            }
+        }
+
+        seen.iter().map(|cid| self.size(cid)).sum()
+    }
+
+    /// Returns the number of places each block is referenced from, starting from `roots`.
+    pub(crate) fn reference_counts(&self, roots: &[Cid]) -> HashMap<Cid, u64> {
+        let mut counts = HashMap::new();
+        for root in roots {
+            *counts.entry(*root).or_default() += 1;
+        }
+
+        // Parents come before their children, so their counts are final when propagated
+        for cid in &self.order {
+            let count = counts.get(cid).copied().unwrap_or_default();
+            for child in &self.links[cid] {
+                *counts.entry(*child).or_default() += count;
+            }
+        }
+
+        counts
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Report how much space block-level deduplication saves in a monofs filesystem
+///
+/// Every DAG reachable from the current root and the filesystem's snapshots is walked. If the
+/// filesystem is mounted, its server checkpoints the in-memory root first.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+///
+/// ## Returns
+/// The overall logical and physical sizes, a breakdown for the directories near the root, and the
+/// most duplicated blocks
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let report = management::dedup_report(Some("mfstest".into())).await?;
+/// println!("dedup ratio: {:.2}", report.get_ratio());
+/// for dir in report.get_directories() {
+///     println!("{}: {:.2}", dir.get_path(), dir.get_ratio());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn dedup_report(mount_dir: Option<PathBuf>) -> FsResult<DedupReport> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+
+    // Gather the roots, with the current root first
+    let head = head::checkpoint_head(&paths).await?;
+    let mut roots = head.into_iter().collect::<Vec<_>>();
+    roots.extend(
+        snapshot::list_snapshots(Some(paths.get_mount_dir().clone()))
+            .await?
+            .iter()
+            .map(|snapshot| *snapshot.get_root()),
+    );
+
+    if roots.is_empty() {
+        return Err(FsError::InvalidOperation(format!(
+            "filesystem at {} has no recorded state to report on",
+            paths.get_mount_dir().display()
+        )));
+    }
+
+    let blocks_dir = paths.blocks_dir();
+    let sizes = get_block_sizes(&blocks_dir).await?;
+    let store_size = sizes.values().sum();
+
+    let store = paths.open_store().await?;
+    let graph = BlockGraph::build(&store, &roots, sizes).await?;
+
+    // Break the current root down by directory
+    let mut directories = Vec::new();
+    if let Some(head) = head {
+        let mut pending = vec![(String::from("/"), head, 0)];
+        while let Some((path, cid, depth)) = pending.pop() {
+            directories.push(DirDedup {
+                path: path.clone(),
+                logical_size: graph.logical_size(&cid),
+                physical_size: graph.physical_size([cid]),
+            });
+
+            if depth == MAX_DEDUP_REPORT_DEPTH {
+                continue;
+            }
+
+            let dir = Dir::load(&cid, store.clone()).await?;
+            for (name, link) in dir.get_entries() {
+                let (Some(child), Entity::Dir(_)) =
+                    (link.get_cid(), link.resolve_entity(store.clone()).await?)
+                else {
+                    continue;
+                };
+
+                let child_path = format!("{}{}/", path, name);
+                pending.push((child_path, *child, depth + 1));
+            }
+        }
+        directories.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+
+    // Rank shared blocks by how much space sharing them saves
+    let mut top_duplicates = graph
+        .reference_counts(&roots)
+        .into_iter()
+        .filter(|(_, references)| *references > 1)
+        .map(|(cid, references)| DuplicateBlock {
+            cid,
+            size: graph.size(&cid),
+            references,
+        })
+        .collect::<Vec<_>>();
+    top_duplicates.sort_by_key(|block| std::cmp::Reverse(block.size * (block.references - 1)));
+    top_duplicates.truncate(MAX_DEDUP_REPORT_DUPLICATES);
+
+    Ok(DedupReport {
+        logical_size: roots.iter().map(|root| graph.logical_size(root)).sum(),
+        physical_size: graph.physical_size(roots.iter().copied()),
+        store_size,
+        directories,
+        top_duplicates,
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the size of every block file under `dir`, keyed by the name of the block, or by file
+/// name for files that do not hold a block.
+pub(crate) async fn get_block_sizes(dir: &Path) -> FsResult<HashMap<String, u64>> {
+    let mut sizes = HashMap::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else if metadata.is_file() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let key = store::get_block_name(&name).unwrap_or(&name).to_string();
+                sizes.insert(key, metadata.len());
+            }
+        }
+    }
+
+    Ok(sizes)
+}
+
+/// Returns `logical / physical`, or 1 when nothing is stored.
+fn dedup_ratio(logical: u64, physical: u64) -> f64 {
+    if physical == 0 {
+        1.0
+    } else {
+        logical as f64 / physical as f64
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use crate::{filesystem::File, management::FsHead};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dedup_report_counts_shared_content() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let mount_dir = fs::canonicalize(temp.path()).await?.join("mnt");
+        let paths = find::helper::setup_test_mfs(&mount_dir).await?;
+
+        // The same content stored under two directories
+        let store = FlatFsStore::new(paths.blocks_dir());
+        let mut root = Dir::new(store.clone());
+        for name in ["a", "b"] {
+            let mut dir = Dir::new(store.clone());
+            let file = File::with_content(store.clone(), b"shared".as_slice()).await?;
+            dir.put_adapted_file("file.txt", file).await?;
+            root.put_adapted_dir(name, dir).await?;
+        }
+        let cid = root.checkpoint().await?;
+        FsHead::new(paths.fs_db_path(), &mount_dir)
+            .await?
+            .set(&cid, "checkpoint")
+            .await?;
+
+        let report = dedup_report(Some(mount_dir)).await?;
+        assert!(report.get_logical_size() > report.get_physical_size());
+        assert!(report.get_ratio() > 1.0);
+        assert!(report.get_store_size() >= report.get_physical_size());
+        assert!(!report.get_top_duplicates().is_empty());
+
+        let dirs = report
+            .get_directories()
+            .iter()
+            .map(|dir| dir.get_path().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(dirs, vec!["/", "/a/", "/b/"]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,308 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use getset::Getters;
+use ipldstore::ipld::cid::Cid;
+use serde::Serialize;
+
+use crate::{
+    filesystem::Entity,
+    management::{
+        dedup::{self, BlockGraph},
+        find, gc, head, tree, TreeSource,
+    },
+    store, utils, FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// How much space a directory of a monofs filesystem and everything below it takes up.
+///
+/// Sizes are those of blocks as they are stored, except for the logical size. The physical size
+/// counts every block reachable from the directory once. Of that, the unique size is taken up by
+/// blocks that nothing outside the directory refers to, which is what removing it would free once
+/// garbage is collected. The shared size is taken up by blocks that are also referenced from
+/// elsewhere: other directories, snapshots, branches, pins or other filesystems sharing the store.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct DirUsage {
+    /// The path of the directory relative to where the report started, empty for the directory
+    /// the report started at.
+    path: String,
+
+    /// How many directories below where the report started the directory is.
+    depth: usize,
+
+    /// The CID of the directory.
+    #[serde(serialize_with = "utils::serialize_display")]
+    cid: Cid,
+
+    /// The size of the content of the files below the directory.
+    logical_size: u64,
+
+    /// The size of the blocks reachable from the directory.
+    physical_size: u64,
+
+    /// The size of the blocks only reachable through the directory.
+    unique_size: u64,
+
+    /// The size of the blocks reachable from the directory that are also referenced from
+    /// elsewhere.
+    shared_size: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Report the space taken up by a directory of a monofs filesystem and the directories below it,
+/// like `du`
+///
+/// Since identical blocks are stored once, the size of the files below a directory says little
+/// about the space it occupies. Each directory is reported with its logical size, and the
+/// physical size of its blocks split into what only it uses and what it shares with the rest of
+/// the store. Every root that keeps blocks from being collected as garbage is walked to tell the
+/// two apart. If the filesystem is mounted, its server checkpoints the in-memory root first.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `source` - The directory to report on
+/// * `max_depth` - How many directories down to report on at most, or `None` to report on all of
+///   them. Everything below still counts towards the sizes of the directories reported on
+///
+/// ## Returns
+/// The directory and the directories below it, each followed by the directories below it in name
+/// order
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management::{self, TreeSource};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let source = TreeSource::Path("sandboxes".into());
+/// for dir in management::disk_usage(Some("mfstest".into()), source, Some(1)).await? {
+///     println!("{}\t{}", dir.get_unique_size(), dir.get_path());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn disk_usage(
+    mount_dir: Option<PathBuf>,
+    source: TreeSource,
+    max_depth: Option<usize>,
+) -> FsResult<Vec<DirUsage>> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let store = paths.open_store().await?;
+
+    // The active branch of a filesystem is at its head, which already stands for it
+    let mut roots = gc::get_gc_roots(&paths, false).await?;
+    let (root, path) = match source {
+        TreeSource::Cid { cid, path } => {
+            if !roots.contains(&cid) {
+                roots.push(cid);
+            }
+            (cid, path)
+        }
+        TreeSource::Path(path) => {
+            let head = head::checkpoint_head(&paths).await?.ok_or_else(|| {
+                FsError::InvalidOperation(format!(
+                    "filesystem at {} has no recorded state to report on",
+                    paths.get_mount_dir().display()
+                ))
+            })?;
+            (head, path)
+        }
+    };
+
+    let cid = tree::get_path_cid(&store, &root, &path).await?;
+    let sizes = dedup::get_block_sizes(&paths.blocks_dir()).await?;
+    let graph = BlockGraph::build(&store, &roots, sizes).await?;
+    let unique_sizes = get_unique_sizes(&graph, &roots);
+
+    let walked = store::walk_entities(&store, &cid, None).await?;
+    if !matches!(walked[0].get_entity(), Entity::Dir(_)) {
+        return Err(FsError::NotADirectory(path));
+    }
+
+    // Every directory an entity is below is on the stack, whether it is reported on or not
+    let mut usages = Vec::<DirUsage>::new();
+    let mut ancestors = Vec::<Option<usize>>::new();
+    for walked in walked {
+        let depth = *walked.get_depth();
+        ancestors.truncate(depth);
+
+        let entity = walked.get_entity();
+        let size = entity.get_size().await?;
+        for index in ancestors.iter().flatten() {
+            usages[*index].logical_size += size;
+        }
+
+        if !matches!(entity, Entity::Dir(_)) {
+            continue;
+        }
+
+        if matches!(max_depth, Some(max_depth) if depth > max_depth) {
+            ancestors.push(None);
+            continue;
+        }
+
+        let cid = *walked.get_cid();
+        let physical_size = graph.physical_size([cid]);
+        let unique_size = unique_sizes.get(&cid).copied().unwrap_or_default();
+        ancestors.push(Some(usages.len()));
+        usages.push(DirUsage {
+            path: walked.get_path().clone(),
+            depth,
+            cid,
+            logical_size: 0,
+            physical_size,
+            unique_size,
+            shared_size: physical_size - unique_size,
+        });
+    }
+
+    Ok(usages)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the size of the blocks that are only reachable from `roots` through each block of
+/// `graph`, including the block itself.
+///
+/// A block is only reachable through another if that block dominates it: every chain of links
+/// from the roots to it passes through the other block. Nothing is only reachable through a block
+/// that can itself be reached along more than one chain, since removing it from one place leaves
+/// it in the others.
+fn get_unique_sizes(graph: &BlockGraph, roots: &[Cid]) -> HashMap<Cid, u64> {
+    // Blocks are numbered in an order where every block comes after those linking to it, after a
+    // virtual block linking to the roots
+    let order = graph.get_order();
+    let index = order
+        .iter()
+        .enumerate()
+        .map(|(i, cid)| (*cid, i + 1))
+        .collect::<HashMap<_, _>>();
+
+    let mut parents = vec![Vec::new(); order.len() + 1];
+    for root in roots {
+        parents[index[root]].push(0);
+    }
+    for (i, cid) in order.iter().enumerate() {
+        for child in graph.get_links(cid) {
+            parents[index[child]].push(i + 1);
+        }
+    }
+
+    // The immediate dominator of a block is the closest block on every chain leading to it. In a
+    // DAG visited in this order, the dominators of its parents are already known
+    let mut dominators = vec![0; order.len() + 1];
+    for i in 1..=order.len() {
+        let mut dominator = parents[i][0];
+        for parent in &parents[i][1..] {
+            let mut other = *parent;
+            while dominator != other {
+                if dominator > other {
+                    dominator = dominators[dominator];
+                } else {
+                    other = dominators[other];
+                }
+            }
+        }
+        dominators[i] = dominator;
+    }
+
+    // Blocks come after their dominators, so the size of a block is complete by the time it is
+    // added to its dominator's
+    let mut exclusive_sizes = vec![0; order.len() + 1];
+    for i in (1..=order.len()).rev() {
+        exclusive_sizes[i] += graph.size(&order[i - 1]);
+        exclusive_sizes[dominators[i]] += exclusive_sizes[i];
+    }
+
+    let references = graph.reference_counts(roots);
+    order
+        .iter()
+        .enumerate()
+        .map(|(i, cid)| {
+            let size = if references[cid] > 1 {
+                0
+            } else {
+                exclusive_sizes[i + 1]
+            };
+            (*cid, size)
+        })
+        .collect()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tokio::fs;
+
+    use tempfile::TempDir;
+
+    use crate::{
+        filesystem::{Dir, File},
+        management::FsHead,
+        store::FlatFsStore,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_disk_usage_splits_unique_and_shared() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let mount_dir = fs::canonicalize(temp.path()).await?.join("mnt");
+        let paths = find::helper::setup_test_mfs(&mount_dir).await?;
+
+        // The same content under two directories, and content only one of them has
+        let store = FlatFsStore::new(paths.blocks_dir());
+        let mut root = Dir::new(store.clone());
+        for name in ["a", "b"] {
+            let mut dir = Dir::new(store.clone());
+            let file = File::with_content(store.clone(), b"shared".as_slice()).await?;
+            dir.put_adapted_file("shared.txt", file).await?;
+            if name == "a" {
+                let file = File::with_content(store.clone(), b"unique".as_slice()).await?;
+                dir.put_adapted_file("unique.txt", file).await?;
+            }
+            root.put_adapted_dir(name, dir).await?;
+        }
+        let cid = root.checkpoint().await?;
+        FsHead::new(paths.fs_db_path(), &mount_dir)
+            .await?
+            .set(&cid, "checkpoint")
+            .await?;
+
+        let usages = disk_usage(Some(mount_dir), TreeSource::Path(String::new()), None).await?;
+        let dirs = usages
+            .iter()
+            .map(|usage| usage.get_path().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(dirs, vec!["", "a", "b"]);
+
+        // Nothing outside the root refers to its blocks
+        let (root, a, b) = (&usages[0], &usages[1], &usages[2]);
+        assert_eq!(root.get_logical_size(), &18);
+        assert_eq!(root.get_unique_size(), root.get_physical_size());
+        assert_eq!(root.get_shared_size(), &0);
+
+        assert_eq!(a.get_logical_size(), &12);
+        assert_eq!(b.get_logical_size(), &6);
+        assert!(a.get_unique_size() > b.get_unique_size());
+        assert!(*a.get_shared_size() > 0);
+        assert_eq!(a.get_shared_size(), b.get_shared_size());
+        assert_eq!(
+            a.get_unique_size() + a.get_shared_size(),
+            *a.get_physical_size()
+        );
+
+        Ok(())
+    }
+}
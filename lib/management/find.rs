@@ -1,7 +1,25 @@
+use getset::Getters;
+use globset::GlobBuilder;
+use ipldstore::{ipld::cid::Cid, IpldStore};
+use serde::Serialize;
+use sqlx::Row;
 use std::path::{Path, PathBuf};
 use tokio::{fs, net::TcpListener};
 
-use crate::{utils::path::MFS_LINK_FILENAME, FsError, FsResult};
+use crate::{
+    config::{EncryptionKey, FindType, PortRange, DEFAULT_NFS_PORT_RANGE_SIZE},
+    filesystem::EntityType,
+    management::{
+        db, head, tree, FsMirrors, FsRemote, FsReplicas, FsStoreHash, TreeSource, FS_DB_MIGRATOR,
+    },
+    store::{self, open_remote_store, FlatFsStore},
+    utils,
+    utils::path::{
+        BLOCKS_SUBDIR, CONTROL_SOCKET_FILENAME, FS_DB_FILENAME, LOG_SUBDIR, MFS_DIR_SUFFIX,
+        MFS_LINK_FILENAME, QUARANTINE_SUBDIR,
+    },
+    FsError, FsResult,
+};
 
 //--------------------------------------------------------------------------------------------------
 // Constants
@@ -10,10 +28,219 @@ use crate::{utils::path::MFS_LINK_FILENAME, FsError, FsResult};
 /// Maximum depth to search for MFS root
 const MAX_MFS_ROOT_SEARCH_DEPTH: u32 = 10;
 
+/// The characters that make a segment of a glob match more than the literal segment.
+const GLOB_META_CHARS: [char; 5] = ['*', '?', '[', '{', '\\'];
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The locations associated with a monofs filesystem.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct MfsPaths {
+    /// The directory where the filesystem is mounted.
+    mount_dir: PathBuf,
+
+    /// The directory where the filesystem's data is stored.
+    data_dir: PathBuf,
+}
+
+/// An entity found by [`find_entries`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct FoundEntry {
+    /// The path of the entity relative to where finding started.
+    path: String,
+
+    /// The CID of the entity.
+    #[serde(serialize_with = "utils::serialize_display")]
+    cid: Cid,
+
+    /// The type of the entity.
+    #[serde(rename = "type")]
+    entity_type: EntityType,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl MfsPaths {
+    /// Returns the path to the filesystem database.
+    pub fn fs_db_path(&self) -> PathBuf {
+        self.data_dir.join(FS_DB_FILENAME)
+    }
+
+    /// Returns the directory where the filesystem's blocks are stored.
+    pub fn blocks_dir(&self) -> PathBuf {
+        self.data_dir.join(BLOCKS_SUBDIR)
+    }
+
+    /// Returns the directory where the corrupt blocks found by the scrubber are moved to.
+    pub fn quarantine_dir(&self) -> PathBuf {
+        self.data_dir.join(QUARANTINE_SUBDIR)
+    }
+
+    /// Opens the store of the filesystem's blocks, which decrypts them with the encryption key in
+    /// the environment, if any, and fetches them from the filesystem's remote store if it has one.
+    /// Block files are copied to the filesystem's mirrors, and corrupt and missing blocks are
+    /// repaired from its mirrors and replicas, if it has any. New blocks are made with the
+    /// filesystem's hash function.
+    pub async fn open_store(&self) -> FsResult<FlatFsStore> {
+        let hash_function = FsStoreHash::new(self.fs_db_path(), &self.mount_dir)
+            .await?
+            .get()
+            .await?;
+        let mut store = FlatFsStore::new(self.blocks_dir())
+            .with_encryption(EncryptionKey::from_env()?)
+            .with_hash_function(hash_function);
+        if let Some(remote) = FsRemote::new(self.fs_db_path(), &self.mount_dir)
+            .await?
+            .get()
+            .await?
+        {
+            let remote_store = open_remote_store(&remote.get_location()?)?;
+            store = store.with_remote(remote_store, *remote.get_cache_max_bytes());
+        }
+
+        let mirrors = FsMirrors::new(self.fs_db_path(), &self.mount_dir)
+            .await?
+            .list()
+            .await?;
+        if !mirrors.is_empty() {
+            store = store.with_mirrors(mirrors);
+        }
+
+        let replicas = FsReplicas::new(self.fs_db_path(), &self.mount_dir)
+            .await?
+            .open()
+            .await?;
+        if !replicas.is_empty() {
+            store = store.with_replicas(replicas);
+        }
+
+        Ok(store)
+    }
+
+    /// Returns the directory where the filesystem's logs are stored.
+    pub fn log_dir(&self) -> PathBuf {
+        self.data_dir.join(LOG_SUBDIR)
+    }
+
+    /// Returns the path to the control socket of the filesystem's server.
+    pub fn control_socket_path(&self) -> PathBuf {
+        self.data_dir.join(CONTROL_SOCKET_FILENAME)
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
 
+/// Find the mount and data directories of a monofs filesystem.
+///
+/// While the filesystem is mounted, the MFS root is found with [`find_mfs_root`] and the data
+/// directory is read from its `.mfs_link`. When it is not mounted the link is not visible, so
+/// `start_path` itself is treated as the mount directory if a sibling data directory
+/// (`<mount_dir>.mfs`) exists.
+pub async fn find_mfs_paths(start_path: impl AsRef<Path>) -> FsResult<MfsPaths> {
+    let start_path = start_path.as_ref();
+    match find_mfs_root(start_path).await {
+        Ok(mount_dir) => {
+            let data_dir = fs::read_link(mount_dir.join(MFS_LINK_FILENAME)).await?;
+            Ok(MfsPaths {
+                mount_dir,
+                data_dir,
+            })
+        }
+        Err(FsError::NoMfsRootFound(path)) => {
+            let mount_dir = fs::canonicalize(start_path).await?;
+            let data_dir = PathBuf::from(format!("{}.{}", mount_dir.display(), MFS_DIR_SUFFIX));
+            if !fs::try_exists(&data_dir).await? {
+                return Err(FsError::NoMfsRootFound(path));
+            }
+
+            Ok(MfsPaths {
+                mount_dir,
+                data_dir,
+            })
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Find the paths of the filesystem containing `mount_dir`, defaulting to the current directory.
+///
+/// The schema of the filesystem's database is brought up to date on the way, so management
+/// functions can rely on it even while an older version serves the filesystem. See
+/// [`db::upgrade_db`].
+pub(crate) async fn resolve_mfs_paths(mount_dir: Option<PathBuf>) -> FsResult<MfsPaths> {
+    let start_path = mount_dir.unwrap_or_else(|| PathBuf::from("."));
+    let paths = find_mfs_paths(&start_path).await?;
+    let live = fs::try_exists(paths.mount_dir.join(MFS_LINK_FILENAME)).await?;
+    db::upgrade_db(paths.fs_db_path(), &FS_DB_MIGRATOR, live).await?;
+
+    Ok(paths)
+}
+
+/// Find the paths of the filesystem that owns the blocks directory used by the filesystem at
+/// `paths`.
+///
+/// A clone links its blocks directory to the one of the filesystem it was cloned from, so the
+/// owner is the filesystem whose data directory holds the blocks.
+pub(crate) async fn find_blocks_owner(paths: &MfsPaths) -> FsResult<MfsPaths> {
+    let blocks_dir = paths.blocks_dir();
+    if !fs::symlink_metadata(&blocks_dir).await?.is_symlink() {
+        return Ok(paths.clone());
+    }
+
+    let owner_blocks_dir = fs::read_link(&blocks_dir).await?;
+    let data_dir = owner_blocks_dir
+        .parent()
+        .map(Path::to_path_buf)
+        .ok_or_else(|| FsError::MfsDataDirNotFound(owner_blocks_dir.display().to_string()))?;
+    let mount_dir = data_dir
+        .to_string_lossy()
+        .strip_suffix(&format!(".{}", MFS_DIR_SUFFIX))
+        .map(PathBuf::from)
+        .ok_or_else(|| FsError::MfsDataDirNotFound(data_dir.display().to_string()))?;
+
+    Ok(MfsPaths {
+        mount_dir,
+        data_dir,
+    })
+}
+
+/// Find every filesystem that shares a blocks directory with the filesystem at `paths`,
+/// including the owner of the blocks and the filesystem itself.
+pub(crate) async fn find_block_sharers(paths: &MfsPaths) -> FsResult<Vec<MfsPaths>> {
+    let owner = find_blocks_owner(paths).await?;
+
+    // Filesystems created before clones existed have no table to record them in yet
+    db::init_db(owner.fs_db_path(), &FS_DB_MIGRATOR).await?;
+    let pool = db::get_db_pool(owner.fs_db_path()).await?;
+    let records = sqlx::query("SELECT mount_dir FROM block_sharers ORDER BY id")
+        .fetch_all(&pool)
+        .await?;
+
+    let mut sharers = vec![owner];
+    for record in records {
+        let mount_dir = PathBuf::from(record.get::<String, _>("mount_dir"));
+        let data_dir = PathBuf::from(format!("{}.{}", mount_dir.display(), MFS_DIR_SUFFIX));
+
+        // Clones that have been deleted no longer hold on to any blocks
+        if fs::try_exists(&data_dir).await? {
+            sharers.push(MfsPaths {
+                mount_dir,
+                data_dir,
+            });
+        }
+    }
+
+    Ok(sharers)
+}
+
 /// Find the MFS root directory by searching up the directory tree for the MFS link file.
 ///
 /// This function starts from the given path and traverses up the directory hierarchy
@@ -50,10 +277,13 @@ pub async fn find_mfs_root(start_path: impl AsRef<Path>) -> FsResult<PathBuf> {
 
 /// Find the next available port starting from the provided port number
 pub async fn find_available_port(host: &str, start_port: u32) -> FsResult<u32> {
-    const MAX_PORT_ATTEMPTS: u32 = 100;
-    let end_port = start_port + MAX_PORT_ATTEMPTS - 1;
+    let range = PortRange::new(start_port, start_port + DEFAULT_NFS_PORT_RANGE_SIZE - 1);
+    find_available_port_in_range(host, range).await
+}
 
-    for port in start_port..=end_port {
+/// Find the first available port in the provided range
+pub async fn find_available_port_in_range(host: &str, range: PortRange) -> FsResult<u32> {
+    for port in range.ports() {
         match TcpListener::bind((host, port as u16)).await {
             Ok(_) => return Ok(port),
             Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => continue,
@@ -63,11 +293,146 @@ pub async fn find_available_port(host: &str, start_port: u32) -> FsResult<u32> {
 
     Err(FsError::NoAvailablePorts {
         host: host.to_string(),
-        start: start_port,
-        end: end_port,
+        start: range.start(),
+        end: range.end(),
     })
 }
 
+/// Find the entities below a path whose paths match a glob, straight from the block store, like
+/// `find -path`
+///
+/// The glob is matched against the path of every entity relative to `source`. `*`, `?` and
+/// character classes like `[a-z]` match within a single path segment, `**` matches any number of
+/// segments and `{a,b}` matches either alternative. Only the directories the glob can match below
+/// are walked, so a glob like `src/*.rs` does not load anything outside `src`. Symbolic links are
+/// not followed.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `source` - The directory to find entities below
+/// * `glob` - The glob the paths of the entities must match
+/// * `find_type` - Optional type the entities must be of
+///
+/// ## Returns
+/// The matching entities, every directory followed by the matching entities below it in name order
+///
+/// ## Example
+/// ```no_run
+/// use monofs::{config::FindType, management::{self, TreeSource}};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let source = TreeSource::Path(String::new());
+/// let entries = management::find_entries(Some("mfstest".into()), source, "**/*.rs", Some(FindType::File)).await?;
+/// for entry in entries {
+///     println!("{}\t{}", entry.get_path(), entry.get_cid());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn find_entries(
+    mount_dir: Option<PathBuf>,
+    source: TreeSource,
+    glob: &str,
+    find_type: Option<FindType>,
+) -> FsResult<Vec<FoundEntry>> {
+    let paths = resolve_mfs_paths(mount_dir).await?;
+    let store = paths.open_store().await?;
+
+    let (root, path) = match source {
+        TreeSource::Cid { cid, path } => (cid, path),
+        TreeSource::Path(path) => {
+            let head = head::checkpoint_head(&paths).await?.ok_or_else(|| {
+                FsError::InvalidOperation(format!(
+                    "filesystem at {} has no recorded state to search",
+                    paths.get_mount_dir().display()
+                ))
+            })?;
+            (head, path)
+        }
+    };
+
+    get_found_entries(&store, &root, &path, glob, find_type).await
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the entities below the entity at `path` below `root` whose paths match `glob` and
+/// that are of `find_type`, as described by [`find_entries`].
+async fn get_found_entries<S>(
+    store: &S,
+    root: &Cid,
+    path: &str,
+    glob: &str,
+    find_type: Option<FindType>,
+) -> FsResult<Vec<FoundEntry>>
+where
+    S: IpldStore + Send + Sync,
+{
+    let glob = glob.trim_matches('/');
+    let matcher = GlobBuilder::new(glob)
+        .literal_separator(true)
+        .build()
+        .map_err(|e| FsError::InvalidSearchPattern(format!("{}: {}", glob, e)))?
+        .compile_matcher();
+
+    // Nothing matches below a literal prefix that does not exist
+    let (prefix, max_depth) = get_glob_walk(glob);
+    let source = tree::get_path_cid(store, root, path).await?;
+    let cid = match tree::get_path_cid(store, &source, &prefix).await {
+        Ok(cid) => cid,
+        Err(FsError::PathNotFound(_) | FsError::NotADirectory(_)) => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut entries = Vec::new();
+    for walked in store::walk_entities(store, &cid, max_depth).await? {
+        let path = match (prefix.is_empty(), walked.get_path().is_empty()) {
+            (true, true) => continue,
+            (true, false) => walked.get_path().clone(),
+            (false, true) => prefix.clone(),
+            (false, false) => format!("{}/{}", prefix, walked.get_path()),
+        };
+
+        let entity_type = *walked.get_entity().get_metadata().get_entity_type();
+        if matcher.is_match(&path) && find_type.is_none_or(|t| t.matches(entity_type)) {
+            entries.push(FoundEntry {
+                path,
+                cid: *walked.get_cid(),
+                entity_type,
+            });
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Returns the directory below which every path matching `glob` is, and how many directories
+/// below it the matching paths are at most, or `None` if there is no limit.
+///
+/// A `{a,b}` alternative can hold separators, but only one alternative is matched at a time, so
+/// the separators in the glob still bound the depth of the paths it matches.
+fn get_glob_walk(glob: &str) -> (String, Option<usize>) {
+    let segments = glob
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    let literal = segments[..segments.len().saturating_sub(1)]
+        .iter()
+        .take_while(|segment| !segment.contains(GLOB_META_CHARS))
+        .count();
+
+    let rest = &segments[literal..];
+    let max_depth = if rest.iter().any(|segment| segment.contains("**")) {
+        None
+    } else {
+        Some(rest.len())
+    };
+
+    (segments[..literal].join("/"), max_depth)
+}
+
 //--------------------------------------------------------------------------------------------------
 // Tests
 //--------------------------------------------------------------------------------------------------
@@ -75,6 +440,8 @@ pub async fn find_available_port(host: &str, start_port: u32) -> FsResult<u32> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::filesystem::{Dir, SymPathLink};
+    use ipldstore::MemoryStore;
     use std::fs::File;
     use tempfile::TempDir;
     use tokio::test;
@@ -132,6 +499,34 @@ mod tests {
         assert!(matches!(result, Err(FsError::IoError(_))));
     }
 
+    #[test]
+    async fn test_find_mfs_paths_unmounted() {
+        let (temp, path) = helper::setup_test_dir(1).await;
+
+        // Without a data directory there is nothing to find
+        let result = find_mfs_paths(&path).await;
+        assert!(matches!(result, Err(FsError::NoMfsRootFound(_))));
+
+        // A sibling data directory identifies an unmounted filesystem
+        let data_dir = PathBuf::from(format!("{}.{}", path.display(), MFS_DIR_SUFFIX));
+        fs::create_dir(&data_dir).await.unwrap();
+
+        let paths = find_mfs_paths(&path).await.unwrap();
+        assert_eq!(
+            paths.get_mount_dir(),
+            &fs::canonicalize(&path).await.unwrap()
+        );
+        assert_eq!(
+            paths.fs_db_path(),
+            fs::canonicalize(&data_dir)
+                .await
+                .unwrap()
+                .join(FS_DB_FILENAME)
+        );
+
+        temp.close().unwrap();
+    }
+
     #[test]
     async fn test_find_mfs_root_in_current_dir() {
         let temp = TempDir::new().unwrap();
@@ -147,10 +542,62 @@ mod tests {
 
         temp.close().unwrap();
     }
+
+    #[test]
+    async fn test_find_entries() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut dir = Dir::new(store.clone());
+        for path in [
+            "src/main.rs",
+            "src/lib.rs",
+            "src/bin/tool.rs",
+            "docs/guide.md",
+        ] {
+            dir.find_or_create(path, true).await?;
+        }
+        let symlink = SymPathLink::with_path(store.clone(), "docs")?;
+        dir.put_adapted_sympathlink("latest", symlink).await?;
+        let root = dir.checkpoint().await?;
+
+        let find = |glob: &'static str, find_type: Option<FindType>| {
+            let store = store.clone();
+            async move {
+                let entries = get_found_entries(&store, &root, "", glob, find_type).await?;
+                Ok::<_, FsError>(
+                    entries
+                        .into_iter()
+                        .map(|entry| entry.path)
+                        .collect::<Vec<_>>(),
+                )
+            }
+        };
+
+        // `*` stays within a segment, while `**` crosses them
+        assert_eq!(
+            find("**/*.rs", None).await?,
+            ["src/bin/tool.rs", "src/lib.rs", "src/main.rs"]
+        );
+        assert_eq!(find("src/*.rs", None).await?, ["src/lib.rs", "src/main.rs"]);
+        assert_eq!(find("src/[a-l]*", None).await?, ["src/bin", "src/lib.rs"]);
+
+        // Types narrow the entities found, and missing prefixes find nothing
+        assert_eq!(find("*", Some(FindType::Dir)).await?, ["docs", "src"]);
+        assert_eq!(find("*", Some(FindType::Symlink)).await?, ["latest"]);
+        assert!(find("missing/**", None).await?.is_empty());
+
+        let entries = get_found_entries(&store, &root, "src", "*.rs", None).await?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].get_entity_type(), &EntityType::File);
+
+        let result = find("src/[", None).await;
+        assert!(matches!(result, Err(FsError::InvalidSearchPattern(_))));
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
-mod helper {
+pub(crate) mod helper {
     use tempfile::TempDir;
 
     use super::*;
@@ -168,4 +615,16 @@ mod helper {
 
         (temp, current)
     }
+
+    /// Helper function to create an unmounted filesystem with an initialized fs database at
+    /// `mount_dir` for testing
+    pub(crate) async fn setup_test_mfs(mount_dir: &Path) -> FsResult<MfsPaths> {
+        let data_dir = PathBuf::from(format!("{}.{}", mount_dir.display(), MFS_DIR_SUFFIX));
+        fs::create_dir_all(mount_dir).await?;
+        fs::create_dir_all(data_dir.join(BLOCKS_SUBDIR)).await?;
+
+        let paths = find_mfs_paths(mount_dir).await?;
+        db::init_db(paths.fs_db_path(), &FS_DB_MIGRATOR).await?;
+        Ok(paths)
+    }
 }
@@ -0,0 +1,499 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use getset::Getters;
+use ipldstore::ipld::cid::Cid;
+use serde::Serialize;
+use tokio::fs;
+
+use crate::{
+    management::{
+        branch, find, head, pin, snapshot, BlockRefs, Job, JobKind, JobProgress, MfsPaths,
+    },
+    store::{self, DagWalker},
+    FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The suffix a block file is renamed with while a collection decides whether to remove it.
+const REMOVING_SUFFIX: &str = "removing";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The outcome of a garbage collection run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct GcReport {
+    /// The number of blocks that are still reachable.
+    reachable_blocks: u64,
+
+    /// The number of unreachable blocks that were removed.
+    removed_blocks: u64,
+
+    /// The number of bytes freed by removing blocks.
+    freed_bytes: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl GcReport {
+    /// Returns the report of this collection followed by `later`, which removed more blocks and
+    /// knows best how many remain.
+    pub(crate) fn followed_by(self, later: GcReport) -> GcReport {
+        GcReport {
+            reachable_blocks: later.reachable_blocks,
+            removed_blocks: self.removed_blocks + later.removed_blocks,
+            freed_bytes: self.freed_bytes + later.freed_bytes,
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Remove blocks that are no longer reachable from a monofs filesystem's blocks directory
+///
+/// A block is kept if it is reachable from the current root of the filesystem, from the root of
+/// any of its snapshots or branches or from any of its pinned CIDs. If the filesystem is mounted,
+/// its server checkpoints the in-memory root first so nothing written so far is lost. Blocks
+/// created after the collection started are never removed, nor are existing blocks written again
+/// since, which keeps writes that happen during the collection safe.
+///
+/// Clones share the blocks of the filesystem they were cloned from, so the roots, snapshots and
+/// pins of every filesystem sharing the blocks are kept, no matter which of them is collected.
+///
+/// Previous versions of files and directories are not kept unless a snapshot or pin refers to
+/// them. Blocks moved into packfiles by [`compact_mfs`](crate::management::compact_mfs) are kept
+/// too, as packfiles are never rewritten.
+///
+/// Every root is walked on every collection. [`gc_mfs_incremental`] only walks what changed, but
+/// misses blocks that never were part of a root.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+///
+/// ## Returns
+/// A report of how many blocks were kept and removed
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let report = management::gc_mfs(Some("mfstest".into())).await?;
+/// println!("freed {} bytes", report.get_freed_bytes());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn gc_mfs(mount_dir: Option<PathBuf>) -> FsResult<GcReport> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let job = Job::start(paths.fs_db_path(), paths.get_mount_dir(), JobKind::Gc, None).await?;
+    let result = collect_unreachable(&paths, job.get_progress()).await;
+    job.finish(result).await
+}
+
+/// Remove the blocks of a monofs filesystem that roots no longer refer to, walking only what
+/// changed since the last collection
+///
+/// Unlike [`gc_mfs`], which walks every root, this keeps reference counts for the blocks in the
+/// database of the filesystem owning them, and only visits the blocks of the roots that were
+/// added or dropped since the counts were last brought up to date. The roots are the same ones
+/// [`gc_mfs`] keeps. The first collection counts every root, so it takes as long as [`gc_mfs`].
+///
+/// Blocks that were never part of a root the counts saw, like those written and replaced
+/// between two checkpoints, are only removed by [`gc_mfs`], which is worth running every so
+/// often. Blocks created or written again after the collection started, or moved into
+/// packfiles, are never removed.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+///
+/// ## Returns
+/// A report of how many blocks are counted and how many were removed
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let report = management::gc_mfs_incremental(Some("mfstest".into())).await?;
+/// println!("freed {} bytes", report.get_freed_bytes());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn gc_mfs_incremental(mount_dir: Option<PathBuf>) -> FsResult<GcReport> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let job = Job::start(paths.fs_db_path(), paths.get_mount_dir(), JobKind::Gc, None).await?;
+    let result = collect_released(&paths).await;
+    job.finish(result).await
+}
+
+/// Brings the block reference counts of the filesystem at `paths` up to date with its roots, and
+/// removes the blocks they released.
+pub(crate) async fn collect_released(paths: &MfsPaths) -> FsResult<GcReport> {
+    let started_at = SystemTime::now();
+    let roots = get_gc_roots(paths, true).await?;
+    if roots.is_empty() {
+        return Err(FsError::InvalidOperation(format!(
+            "filesystem at {} has no recorded state to collect garbage for",
+            paths.get_mount_dir().display()
+        )));
+    }
+
+    let owner = find::find_blocks_owner(paths).await?;
+    let refs = BlockRefs::new(owner.fs_db_path()).await?;
+    let store = paths.open_store().await?;
+    let released = refs.update(&store, &roots).await?;
+
+    let mut report = GcReport {
+        reachable_blocks: refs.count().await?,
+        ..Default::default()
+    };
+    for cid in released {
+        let Some(block_path) = store.get_block_file(&cid) else {
+            continue;
+        };
+
+        let Some(size) = remove_block_file(&block_path, started_at).await? else {
+            continue;
+        };
+
+        store.remove_mirror_copies(&block_path).await;
+        report.removed_blocks += 1;
+        report.freed_bytes += size;
+    }
+    tracing::info!(
+        "removed {} released blocks ({} bytes), {} blocks remain counted",
+        report.removed_blocks,
+        report.freed_bytes,
+        report.reachable_blocks
+    );
+
+    Ok(report)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the roots that keep blocks of the filesystem at `paths` alive: the current roots,
+/// snapshots, branches and pins of every filesystem sharing its blocks.
+///
+/// The branch a filesystem is on was last moved to its recorded head, which a running server may
+/// have checkpointed past since. It is only included if `active_branches` is set.
+pub(crate) async fn get_gc_roots(paths: &MfsPaths, active_branches: bool) -> FsResult<Vec<Cid>> {
+    let mut roots = Vec::new();
+    for sharer in find::find_block_sharers(paths).await? {
+        roots.extend(
+            snapshot::list_snapshots(Some(sharer.get_mount_dir().clone()))
+                .await?
+                .into_iter()
+                .map(|snapshot| *snapshot.get_root()),
+        );
+        roots.extend(
+            branch::list_branches(Some(sharer.get_mount_dir().clone()))
+                .await?
+                .into_iter()
+                .filter(|branch| active_branches || !branch.get_active())
+                .map(|branch| *branch.get_root()),
+        );
+        roots.extend(
+            pin::list_pins(Some(sharer.get_mount_dir().clone()))
+                .await?
+                .into_iter()
+                .map(|pin| *pin.get_cid()),
+        );
+        roots.extend(head::checkpoint_head(&sharer).await?);
+    }
+
+    Ok(roots)
+}
+
+/// Removes the blocks of the filesystem at `paths` that no root reaches, reporting the blocks
+/// walked to `progress`.
+async fn collect_unreachable(paths: &MfsPaths, progress: &JobProgress) -> FsResult<GcReport> {
+    let started_at = SystemTime::now();
+    // Gather the roots to keep from every filesystem using the blocks
+    let roots = get_gc_roots(paths, true).await?;
+    if roots.is_empty() {
+        return Err(FsError::InvalidOperation(format!(
+            "filesystem at {} has no recorded state to collect garbage for",
+            paths.get_mount_dir().display()
+        )));
+    }
+
+    // Mark every block reachable from the roots
+    let blocks_dir = paths.blocks_dir();
+    let store = paths.open_store().await?;
+    let reachable = DagWalker::new(&store)
+        .walk_with(
+            roots,
+            |_| false,
+            |walked| progress.set_done(*walked.get_visited()),
+        )
+        .await?
+        .get_links()
+        .keys()
+        .flat_map(|cid| store.get_block_names(cid))
+        .collect::<HashSet<_>>();
+
+    // Sweep the rest
+    let mut report = GcReport::default();
+    sweep_blocks(&blocks_dir, &reachable, started_at, &mut report).await?;
+
+    // The mirrors hold copies of the same block files, which are not counted twice
+    for mirror in store.get_mirrors() {
+        if !fs::try_exists(mirror).await? {
+            tracing::warn!("skipping missing mirror {}", mirror.display());
+            continue;
+        }
+
+        sweep_blocks(mirror, &reachable, started_at, &mut GcReport::default()).await?;
+    }
+    tracing::info!(
+        "removed {} unreachable blocks ({} bytes), {} blocks remain reachable",
+        report.removed_blocks,
+        report.freed_bytes,
+        report.reachable_blocks
+    );
+
+    Ok(report)
+}
+
+/// Removes the block files under `dir` whose names are not in `reachable`.
+///
+/// Block files are named after the hex digest of their CID, or a keyed hash of it if encrypted,
+/// with an extension if compressed or encrypted, and may be nested in subdirectories. Files that
+/// are not named like a block or that were modified after `started_at` are left alone, see
+/// [`remove_block_file`].
+async fn sweep_blocks(
+    dir: &Path,
+    reachable: &HashSet<String>,
+    started_at: SystemTime,
+    report: &mut GcReport,
+) -> FsResult<()> {
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                pending.push(entry.path());
+                continue;
+            }
+
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(block_name) = store::get_block_name(&name).filter(|_| file_type.is_file())
+            else {
+                continue;
+            };
+
+            if reachable.contains(block_name) {
+                report.reachable_blocks += 1;
+                continue;
+            }
+
+            if let Some(size) = remove_block_file(&entry.path(), started_at).await? {
+                report.removed_blocks += 1;
+                report.freed_bytes += size;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the block file at `block_path` unless it was modified after `started_at`, as stores
+/// do to the file of a block they are putting again.
+///
+/// The file is moved aside before it is checked, so a store putting the block concurrently
+/// either marks it before the move, which keeps it, or finds it gone and writes it anew.
+///
+/// Returns the size of the removed file, or `None` if it was kept or is gone.
+async fn remove_block_file(block_path: &Path, started_at: SystemTime) -> FsResult<Option<u64>> {
+    if fs::metadata(block_path).await?.modified()? >= started_at {
+        return Ok(None);
+    }
+
+    let mut removing_path = block_path.as_os_str().to_owned();
+    removing_path.push(format!(".{}", REMOVING_SUFFIX));
+    let removing_path = PathBuf::from(removing_path);
+    match fs::rename(block_path, &removing_path).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+
+    // The block was put again before it was moved, and is kept unless it was written anew
+    let metadata = fs::metadata(&removing_path).await?;
+    if metadata.modified()? >= started_at {
+        match fs::try_exists(block_path).await? {
+            true => fs::remove_file(&removing_path).await?,
+            false => fs::rename(&removing_path, block_path).await?,
+        }
+        return Ok(None);
+    }
+
+    fs::remove_file(&removing_path).await?;
+    Ok(Some(metadata.len()))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::{IpldStore, Storable};
+    use tempfile::TempDir;
+
+    use crate::{
+        filesystem::{Dir, File},
+        management::{db, list_jobs, FsHead, JobState, FS_DB_MIGRATOR},
+        store::FlatFsStore,
+        utils::path::{BLOCKS_SUBDIR, MFS_DIR_SUFFIX},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_gc_removes_unreachable_blocks() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let mount_dir = fs::canonicalize(temp.path()).await?.join("mnt");
+        let paths = find::helper::setup_test_mfs(&mount_dir).await?;
+
+        // Write two versions of a file, snapshotting the first
+        let store = FlatFsStore::new(paths.blocks_dir());
+        let mut root = Dir::new(store.clone());
+        let file = File::with_content(store.clone(), b"first".as_slice()).await?;
+        root.put_adapted_file("file.txt", file).await?;
+        let first = root.checkpoint().await?;
+
+        let head = FsHead::new(paths.fs_db_path(), &mount_dir).await?;
+        head.set(&first, "checkpoint").await?;
+        snapshot::snapshot_mfs(Some(mount_dir.clone()), "v1").await?;
+
+        let file = File::with_content(store.clone(), b"second".as_slice()).await?;
+        root.put_adapted_file("file.txt", file).await?;
+        let second = root.checkpoint().await?;
+        head.set(&second, "checkpoint").await?;
+
+        // Only the orphan is garbage, the first version is kept by the snapshot
+        let orphan = store.put_bytes(b"orphan".as_slice()).await?;
+        let pinned = store.put_bytes(b"pinned".as_slice()).await?;
+        pin::pin(Some(mount_dir.clone()), pinned).await?;
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+        let report = gc_mfs(Some(mount_dir.clone())).await?;
+        assert!(*report.get_removed_blocks() >= 1);
+        assert!(*report.get_freed_bytes() > 0);
+        assert!(!store.has(&orphan).await);
+        assert!(store.has(&pinned).await);
+        assert!(Dir::load(&first, store.clone()).await.is_ok());
+        assert!(Dir::load(&second, store.clone()).await.is_ok());
+
+        // The collection is recorded as a job
+        let jobs = list_jobs(Some(mount_dir.clone())).await?;
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].get_kind(), &JobKind::Gc);
+        assert_eq!(jobs[0].get_state(), &JobState::Completed);
+
+        // Nothing is left to collect
+        let report = gc_mfs(Some(mount_dir)).await?;
+        assert_eq!(*report.get_removed_blocks(), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_gc_keeps_blocks_put_again() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let store = FlatFsStore::new(temp.path());
+        let cid = store.put_raw_block(b"orphan".to_vec()).await?;
+        let block_path = store.get_block_file(&cid).unwrap();
+        let age_block = || -> anyhow::Result<()> {
+            let modified_at = SystemTime::now() - std::time::Duration::from_secs(60);
+            std::fs::File::options()
+                .write(true)
+                .open(&block_path)?
+                .set_modified(modified_at)?;
+            Ok(())
+        };
+
+        // A block put again after the collection started is kept, though nothing reaches it
+        age_block()?;
+        let started_at = SystemTime::now();
+        store.put_raw_block(b"orphan".to_vec()).await?;
+        let mut report = GcReport::default();
+        sweep_blocks(temp.path(), &HashSet::new(), started_at, &mut report).await?;
+        assert_eq!(report.removed_blocks, 0);
+        assert!(store.has(&cid).await);
+
+        age_block()?;
+        sweep_blocks(temp.path(), &HashSet::new(), SystemTime::now(), &mut report).await?;
+        assert_eq!(report.removed_blocks, 1);
+        assert!(!store.has(&cid).await);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_gc_keeps_blocks_of_clones() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let temp_dir = fs::canonicalize(temp.path()).await?;
+        let mount_dir = temp_dir.join("mnt");
+        let paths = find::helper::setup_test_mfs(&mount_dir).await?;
+
+        // A clone links to the blocks of the original and is registered with it
+        let clone_dir = temp_dir.join("clone");
+        let clone_data_dir = PathBuf::from(format!("{}.{}", clone_dir.display(), MFS_DIR_SUFFIX));
+        fs::create_dir_all(&clone_dir).await?;
+        fs::create_dir_all(&clone_data_dir).await?;
+        fs::symlink(paths.blocks_dir(), clone_data_dir.join(BLOCKS_SUBDIR)).await?;
+
+        let clone_paths = find::find_mfs_paths(&clone_dir).await?;
+        db::init_db(clone_paths.fs_db_path(), &FS_DB_MIGRATOR).await?;
+        sqlx::query("INSERT INTO block_sharers (mount_dir) VALUES (?)")
+            .bind(clone_dir.to_string_lossy().to_string())
+            .execute(&db::get_db_pool(paths.fs_db_path()).await?)
+            .await?;
+
+        // Each filesystem has content the other one does not
+        let store = FlatFsStore::new(paths.blocks_dir());
+        let mut root = Dir::new(store.clone());
+        let original = root.checkpoint().await?;
+        FsHead::new(paths.fs_db_path(), &mount_dir)
+            .await?
+            .set(&original, "checkpoint")
+            .await?;
+
+        let file = File::with_content(store.clone(), b"cloned".as_slice()).await?;
+        root.put_adapted_file("file.txt", file).await?;
+        let cloned = root.checkpoint().await?;
+        FsHead::new(clone_paths.fs_db_path(), &clone_dir)
+            .await?
+            .set(&cloned, "checkpoint")
+            .await?;
+
+        // Collecting either side keeps the content of both
+        gc_mfs(Some(mount_dir)).await?;
+        assert!(Dir::load(&cloned, store.clone()).await.is_ok());
+
+        gc_mfs(Some(clone_dir)).await?;
+        assert!(Dir::load(&original, store.clone()).await.is_ok());
+        assert!(Dir::load(&cloned, store.clone()).await.is_ok());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,288 @@
+use std::path::PathBuf;
+
+use futures::stream::{self, BoxStream, StreamExt};
+use getset::Getters;
+use globset::{Glob, GlobMatcher};
+use ipldstore::{ipld::cid::Cid, IpldStoreSeekable};
+use regex::Regex;
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+use crate::{
+    filesystem::{Entity, File},
+    management::{find, head, tree, TreeSource},
+    store, FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The number of files searched at once, each reading and decompressing its own blocks.
+const GREP_CONCURRENCY: usize = 8;
+
+/// How many bytes from the start of a file are checked for a NUL byte to tell whether the file is
+/// binary, like git and grep do.
+const BINARY_CHECK_LEN: u64 = 8 * 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A line of a file matching the pattern of a [`grep`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct GrepMatch {
+    /// The path of the file, relative to the root the path being searched is in.
+    path: String,
+
+    /// The number of the line in the file, starting at 1.
+    line_number: u64,
+
+    /// The line, without its line ending. Bytes that are not valid UTF-8 are replaced.
+    line: String,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Search the content of the files below a path for lines matching a regular expression, like
+/// `grep -rn`
+///
+/// The files are read straight from the block store, so snapshots and other roots can be searched
+/// without mounting them. Several files are read at once, and files that look binary, holding a
+/// NUL byte near their start, are skipped. Symbolic links are not followed.
+///
+/// Matches are streamed in path order, and in line order within a file.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `source` - The directory or file to search
+/// * `pattern` - The regular expression lines are matched against
+/// * `path_glob` - Optional glob, like `**/*.rs`, the paths of the searched files must match
+///
+/// ## Returns
+/// A stream of the matching lines
+///
+/// ## Example
+/// ```no_run
+/// use futures::StreamExt;
+/// use monofs::management::{self, TreeSource};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let source = TreeSource::Path("sandbox".into());
+/// let mut matches = management::grep(Some("mfstest".into()), source, "TODO", Some("**/*.rs")).await?;
+/// while let Some(found) = matches.next().await {
+///     let found = found?;
+///     println!("{}:{}:{}", found.get_path(), found.get_line_number(), found.get_line());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn grep(
+    mount_dir: Option<PathBuf>,
+    source: TreeSource,
+    pattern: &str,
+    path_glob: Option<&str>,
+) -> FsResult<BoxStream<'static, FsResult<GrepMatch>>> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let store = paths.open_store().await?;
+
+    let (root, path) = match source {
+        TreeSource::Cid { cid, path } => (cid, path),
+        TreeSource::Path(path) => {
+            let head = head::checkpoint_head(&paths).await?.ok_or_else(|| {
+                FsError::InvalidOperation(format!(
+                    "filesystem at {} has no recorded state to search",
+                    paths.get_mount_dir().display()
+                ))
+            })?;
+            (head, path)
+        }
+    };
+
+    search_tree(&store, &root, &path, pattern, path_glob).await
+}
+
+/// Search the files below `path` in `root` for lines matching `pattern`, as described by [`grep`].
+pub(crate) async fn search_tree<S>(
+    store: &S,
+    root: &Cid,
+    path: &str,
+    pattern: &str,
+    path_glob: Option<&str>,
+) -> FsResult<BoxStream<'static, FsResult<GrepMatch>>>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    let regex = Regex::new(pattern)
+        .map_err(|e| FsError::InvalidSearchPattern(format!("{}: {}", pattern, e)))?;
+    let glob = path_glob
+        .map(|glob| {
+            Glob::new(glob)
+                .map(|glob| glob.compile_matcher())
+                .map_err(|e| FsError::InvalidSearchPattern(format!("{}: {}", glob, e)))
+        })
+        .transpose()?;
+
+    let base = path.trim_matches('/').to_string();
+    let cid = tree::get_path_cid(store, root, &base).await?;
+    let files = store::walk_entities(store, &cid, None)
+        .await?
+        .into_iter()
+        .filter_map(|walked| {
+            let file_path = join_path(&base, walked.get_path());
+            match walked.get_entity() {
+                Entity::File(file) if is_glob_match(glob.as_ref(), &file_path) => {
+                    Some((file_path, file.clone()))
+                }
+                _ => None,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let matches = stream::iter(files)
+        .map(move |(path, file)| search_file(path, file, regex.clone()))
+        .buffered(GREP_CONCURRENCY)
+        .flat_map(|result| match result {
+            Ok(matches) => stream::iter(matches.into_iter().map(Ok)).boxed(),
+            Err(e) => stream::once(async move { Err(e) }).boxed(),
+        });
+
+    Ok(matches.boxed())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the lines of `file`, found at `path`, that match `regex`, or none if the file looks
+/// binary.
+async fn search_file<S>(path: String, file: File<S>, regex: Regex) -> FsResult<Vec<GrepMatch>>
+where
+    S: IpldStoreSeekable + Send + Sync,
+{
+    let mut input = file.get_input_stream().await?;
+    let mut head = Vec::new();
+    (&mut input)
+        .take(BINARY_CHECK_LEN)
+        .read_to_end(&mut head)
+        .await?;
+    if head.contains(&0) {
+        return Ok(Vec::new());
+    }
+
+    let mut reader = BufReader::new(head.as_slice().chain(input));
+    let mut matches = Vec::new();
+    let mut buf = Vec::new();
+    let mut line_number = 0;
+    while reader.read_until(b'\n', &mut buf).await? > 0 {
+        line_number += 1;
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.trim_end_matches(['\n', '\r']);
+        if regex.is_match(line) {
+            matches.push(GrepMatch {
+                path: path.clone(),
+                line_number,
+                line: line.to_string(),
+            });
+        }
+
+        buf.clear();
+    }
+
+    Ok(matches)
+}
+
+/// Returns `path`, relative to the directory at `base`, relative to the root `base` is in.
+fn join_path(base: &str, path: &str) -> String {
+    match (base.is_empty(), path.is_empty()) {
+        (true, _) => path.to_string(),
+        (_, true) => base.to_string(),
+        _ => format!("{}/{}", base, path),
+    }
+}
+
+/// Returns whether `path` matches `glob`, which every path does if there is none.
+fn is_glob_match(glob: Option<&GlobMatcher>, path: &str) -> bool {
+    glob.is_none_or(|glob| glob.is_match(path))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+    use ipldstore::MemoryStore;
+
+    use crate::filesystem::Dir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_search_tree() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut dir = Dir::new(store.clone());
+        for (path, content) in [
+            (
+                "src/main.rs",
+                b"fn main() {\r\n    // TODO: run\r\n}\n".as_slice(),
+            ),
+            ("src/lib.rs", b"// TODO: test\n".as_slice()),
+            ("README.md", b"TODO: write\n".as_slice()),
+            ("blob.bin", b"TODO\0binary".as_slice()),
+        ] {
+            let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+            let file = File::with_content(store.clone(), content).await?;
+            if parent.is_empty() {
+                dir.put_adapted_file(name, file).await?;
+            } else {
+                let Entity::Dir(parent) = dir.find_or_create(parent, false).await? else {
+                    unreachable!()
+                };
+                parent.put_adapted_file(name, file).await?;
+            }
+        }
+        let root = dir.checkpoint().await?;
+
+        // Binary files are skipped, and line endings are not part of the lines
+        let matches = search_tree(&store, &root, "", "TODO", None)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        let found = matches
+            .iter()
+            .map(|m| {
+                (
+                    m.get_path().as_str(),
+                    *m.get_line_number(),
+                    m.get_line().as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                ("README.md", 1, "TODO: write"),
+                ("src/lib.rs", 1, "// TODO: test"),
+                ("src/main.rs", 2, "    // TODO: run"),
+            ]
+        );
+
+        // Globs and base paths narrow the files searched
+        let matches = search_tree(&store, &root, "src", "TODO", Some("**/main.rs"))
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].get_path(), "src/main.rs");
+
+        let result = search_tree(&store, &root, "", "(", None).await;
+        assert!(matches!(result, Err(FsError::InvalidSearchPattern(_))));
+
+        Ok(())
+    }
+}
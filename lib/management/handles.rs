@@ -0,0 +1,123 @@
+use std::path::{Path, PathBuf};
+
+use sqlx::{Pool, Row, Sqlite};
+
+use crate::{management::db, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Tracks the path behind each file ID the server of a filesystem handed out, in its fs database.
+///
+/// NFS clients hold on to file handles across restarts of the server, and a restarted server
+/// only knows the paths it has looked up since it started. Recording the paths lets it resolve
+/// the handles clients still hold instead of reporting them as stale.
+#[derive(Debug, Clone)]
+pub struct FsFileHandles {
+    /// The filesystem database.
+    fs_db: Pool<Sqlite>,
+
+    /// The mount directory identifying the filesystem.
+    mount_dir: PathBuf,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FsFileHandles {
+    /// Opens the file handle records of the filesystem mounted at `mount_dir`.
+    pub async fn new(
+        fs_db_path: impl AsRef<Path>,
+        mount_dir: impl Into<PathBuf>,
+    ) -> FsResult<Self> {
+        Ok(Self {
+            fs_db: db::get_db_pool(fs_db_path.as_ref()).await?,
+            mount_dir: mount_dir.into(),
+        })
+    }
+
+    /// Returns every recorded file ID along with its path.
+    pub async fn get_all(&self) -> FsResult<Vec<(u64, String)>> {
+        let records = sqlx::query("SELECT file_id, path FROM file_handles WHERE mount_dir = ?")
+            .bind(self.mount_dir.to_string_lossy().to_string())
+            .fetch_all(&self.fs_db)
+            .await?;
+
+        Ok(records
+            .iter()
+            .map(|row| (row.get::<i64, _>("file_id") as u64, row.get("path")))
+            .collect())
+    }
+
+    /// Records `file_ids`, each along with its path. IDs that are already recorded keep their
+    /// path.
+    pub async fn record(&self, file_ids: &[(u64, String)]) -> FsResult<()> {
+        let mount_dir = self.mount_dir.to_string_lossy().to_string();
+        let mut tx = self.fs_db.begin().await?;
+        for (file_id, path) in file_ids {
+            sqlx::query(
+                "INSERT OR IGNORE INTO file_handles (mount_dir, file_id, path) VALUES (?, ?, ?)",
+            )
+            .bind(&mount_dir)
+            .bind(*file_id as i64)
+            .bind(path)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::management::FS_DB_MIGRATOR;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fs_file_handles_record() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("fs.db");
+        db::init_db(&db_path, &FS_DB_MIGRATOR).await?;
+
+        let handles = FsFileHandles::new(&db_path, temp_dir.path().join("mnt")).await?;
+        assert!(handles.get_all().await?.is_empty());
+
+        handles
+            .record(&[(1, "dir".to_string()), (2, "dir/file.txt".to_string())])
+            .await?;
+        handles
+            .record(&[
+                (2, "other.txt".to_string()),
+                (u64::MAX >> 1, "x".to_string()),
+            ])
+            .await?;
+
+        let mut file_ids = handles.get_all().await?;
+        file_ids.sort();
+        assert_eq!(
+            file_ids,
+            vec![
+                (1, "dir".to_string()),
+                (2, "dir/file.txt".to_string()),
+                (u64::MAX >> 1, "x".to_string()),
+            ]
+        );
+
+        // Other filesystems sharing the database have records of their own
+        let other = FsFileHandles::new(&db_path, temp_dir.path().join("other")).await?;
+        assert!(other.get_all().await?.is_empty());
+
+        Ok(())
+    }
+}
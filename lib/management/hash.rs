@@ -0,0 +1,111 @@
+use std::path::{Path, PathBuf};
+
+use sqlx::{Pool, Row, Sqlite};
+
+use crate::{config::HashFunction, management::db, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Tracks the hash function a filesystem makes the CIDs of new blocks with, in its fs database.
+///
+/// The hash function is recorded when the filesystem is initialized or cloned, and used by its
+/// server and by the management functions that write blocks. Filesystems created before it could
+/// be chosen have no record, and use [`HashFunction::Blake3`] like they always did.
+#[derive(Debug, Clone)]
+pub struct FsStoreHash {
+    /// The filesystem database.
+    fs_db: Pool<Sqlite>,
+
+    /// The mount directory identifying the filesystem.
+    mount_dir: PathBuf,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FsStoreHash {
+    /// Opens the hash function record of the filesystem mounted at `mount_dir`.
+    pub async fn new(
+        fs_db_path: impl AsRef<Path>,
+        mount_dir: impl Into<PathBuf>,
+    ) -> FsResult<Self> {
+        Ok(Self {
+            fs_db: db::get_db_pool(fs_db_path.as_ref()).await?,
+            mount_dir: mount_dir.into(),
+        })
+    }
+
+    /// Returns the hash function of the filesystem.
+    ///
+    /// Fails with [`FsError::UnsupportedHashFunction`](crate::FsError::UnsupportedHashFunction)
+    /// if the recorded one is not supported, such as one recorded by a newer version of monofs.
+    pub async fn get(&self) -> FsResult<HashFunction> {
+        let record = sqlx::query("SELECT hash_function FROM store_hashes WHERE mount_dir = ?")
+            .bind(self.mount_dir.to_string_lossy().to_string())
+            .fetch_optional(&self.fs_db)
+            .await?;
+
+        match record {
+            Some(row) => row.get::<String, _>("hash_function").parse(),
+            None => Ok(HashFunction::default()),
+        }
+    }
+
+    /// Records that the filesystem makes the CIDs of new blocks with `hash`.
+    pub async fn set(&self, hash: HashFunction) -> FsResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO store_hashes (mount_dir, hash_function)
+            VALUES (?, ?)
+            ON CONFLICT (mount_dir) DO UPDATE
+            SET hash_function = excluded.hash_function,
+                modified_at = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .bind(hash.to_string())
+        .execute(&self.fs_db)
+        .await?;
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::{management::FS_DB_MIGRATOR, FsError};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fs_store_hash_get_set() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("fs.db");
+        db::init_db(&db_path, &FS_DB_MIGRATOR).await?;
+
+        let mount_dir = temp_dir.path().join("mnt");
+        let hash = FsStoreHash::new(&db_path, &mount_dir).await?;
+        assert_eq!(hash.get().await?, HashFunction::Blake3);
+
+        hash.set(HashFunction::Sha2_256).await?;
+        assert_eq!(hash.get().await?, HashFunction::Sha2_256);
+
+        // A hash function this version does not know of is refused
+        sqlx::query("UPDATE store_hashes SET hash_function = 'sha3-512'")
+            .execute(&db::get_db_pool(&db_path).await?)
+            .await?;
+        let result = hash.get().await;
+        assert!(matches!(result, Err(FsError::UnsupportedHashFunction(_))));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,674 @@
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use ipldstore::{ipld::cid::Cid, IpldStore, Storable};
+use sqlx::{Pool, Row, Sqlite};
+use tokio::sync::Mutex;
+
+use crate::{
+    config::{RootSigningKey, DEFAULT_BRANCH_NAME},
+    filesystem::{self, Dir},
+    management::{db, MfsPaths},
+    server::{self, ControlRequest, ControlResponse},
+    store::DagWalker,
+    FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How many of the latest intents are kept for a filesystem. Older ones are only needed if none
+/// of the newer ones can be replayed.
+const MAX_INTENTS: i64 = 16;
+
+/// How many times a root is merged with the heads other writers recorded before giving up on
+/// recording it.
+const MAX_HEAD_UPDATE_ATTEMPTS: usize = 8;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Tracks the head, i.e. the latest checkpointed root CID, of a filesystem in its fs database.
+///
+/// The head is what a filesystem server loads on start so the contents of a mount survive
+/// restarts, and what snapshot restores rewrite. It is also the latest root of the branch the
+/// filesystem is on, see [`switch_branch`](crate::management::switch_branch).
+///
+/// A running server only records its head when asked to or when it stops, so it also logs an
+/// intent for every root it checkpoints in between. Recording a head commits the intents logged
+/// before it. Intents left behind by a server that crashed are replayed by
+/// [`recover`](Self::recover) before the next server starts from the head.
+///
+/// More than one writer may record heads for the same filesystem, e.g. its server and a
+/// management command running while it is not mounted. Each handle, shared by its clones,
+/// remembers the head it last loaded or recorded, and [`update`](Self::update) only records a
+/// root over that head. A root recorded by another writer in the meantime is merged in rather than
+/// overwritten.
+///
+/// If a [`RootSigningKey`] is given in the environment, every root appended to the history is
+/// signed with it, see [`verify_head`](crate::management::verify_head).
+#[derive(Debug, Clone)]
+pub struct FsHead {
+    /// The filesystem database.
+    fs_db: Pool<Sqlite>,
+
+    /// The mount directory identifying the filesystem.
+    mount_dir: PathBuf,
+
+    /// The head this handle last loaded or recorded, which the roots it records derive from.
+    base: Arc<Mutex<Option<Cid>>>,
+
+    /// The key signing the roots this handle appends to the history, if any.
+    signing_key: Option<RootSigningKey>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FsHead {
+    /// Opens the head of the filesystem mounted at `mount_dir`.
+    pub async fn new(
+        fs_db_path: impl AsRef<Path>,
+        mount_dir: impl Into<PathBuf>,
+    ) -> FsResult<Self> {
+        Ok(Self {
+            fs_db: db::get_db_pool(fs_db_path.as_ref()).await?,
+            mount_dir: mount_dir.into(),
+            base: Arc::new(Mutex::new(None)),
+            signing_key: RootSigningKey::from_env()?,
+        })
+    }
+
+    /// Signs the roots this handle appends to the history with `key` instead of the key given in
+    /// the environment, if any.
+    pub fn with_signing_key(mut self, key: Option<RootSigningKey>) -> Self {
+        self.signing_key = key;
+        self
+    }
+
+    /// Returns the current head of the filesystem like [`get`](Self::get), remembering it as the
+    /// head the roots recorded with [`update`](Self::update) derive from.
+    pub async fn load(&self) -> FsResult<Option<Cid>> {
+        let head = self.get().await?;
+        *self.base.lock().await = head;
+        Ok(head)
+    }
+
+    /// Returns the current head of the filesystem, if one has been recorded.
+    pub async fn get(&self) -> FsResult<Option<Cid>> {
+        let record = sqlx::query("SELECT head FROM filesystems WHERE mount_dir = ?")
+            .bind(self.mount_dir.to_string_lossy().to_string())
+            .fetch_optional(&self.fs_db)
+            .await?;
+
+        match record.and_then(|row| row.get::<Option<String>, _>("head")) {
+            Some(head) => Ok(Some(Cid::try_from(head.as_str())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Records `cid` as the new head of the filesystem.
+    ///
+    /// If the head changes, the transition is appended to the filesystem's history along with
+    /// `operation`, a short summary of what produced the new root. The head is recorded as the
+    /// latest root of the branch the filesystem is on too.
+    ///
+    /// The head is replaced whatever it is now, so roots recorded by other writers since this
+    /// handle last saw the head are lost. Use [`update`](Self::update) to keep them.
+    pub async fn set(&self, cid: &Cid, operation: &str) -> FsResult<()> {
+        self.record(cid, operation, None).await?;
+        Ok(())
+    }
+
+    /// Records `cid` as the new head of the filesystem like [`set`](Self::set), but only if the
+    /// head is still `expected`.
+    ///
+    /// The head is compared and swapped in a single statement, so of two writers racing to
+    /// replace the same head, only one succeeds.
+    ///
+    /// ## Returns
+    /// Whether the head was recorded
+    pub async fn compare_and_set(
+        &self,
+        expected: Option<&Cid>,
+        cid: &Cid,
+        operation: &str,
+    ) -> FsResult<bool> {
+        self.record(cid, operation, Some(expected)).await
+    }
+
+    /// Records `cid` as the new head of the filesystem, merging in the roots other writers
+    /// recorded since this handle last loaded or recorded the head.
+    ///
+    /// `merge` is called with that head, the root about to be recorded and the head another
+    /// writer recorded, and returns the root to record instead. Recording is retried against the
+    /// new head until no other writer got in between, or gives up with
+    /// [`FsError::HeadUpdateConflict`] after a few attempts.
+    ///
+    /// ## Returns
+    /// The root that was recorded, which is `cid` unless it had to be merged
+    pub async fn update<F, Fut>(&self, cid: &Cid, operation: &str, merge: F) -> FsResult<Cid>
+    where
+        F: Fn(Option<Cid>, Cid, Cid) -> Fut,
+        Fut: Future<Output = FsResult<Cid>>,
+    {
+        let base = *self.base.lock().await;
+        update_head(self, base, cid, operation, merge).await
+    }
+
+    /// Records `cid` as the new head of the filesystem if the head is `expected`, or whatever it
+    /// is if `expected` is `None`.
+    ///
+    /// Returns whether the head was recorded.
+    async fn record(
+        &self,
+        cid: &Cid,
+        operation: &str,
+        expected: Option<Option<&Cid>>,
+    ) -> FsResult<bool> {
+        let mount_dir = self.mount_dir.to_string_lossy().to_string();
+        let new_head = *cid;
+        let cid = cid.to_string();
+        let mut tx = self.fs_db.begin().await?;
+
+        let record = sqlx::query("SELECT id, head, branch FROM filesystems WHERE mount_dir = ?")
+            .bind(&mount_dir)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+        let (fs_id, previous, branch) = match record {
+            Some(row) => {
+                let fs_id = row.get::<i64, _>("id");
+                let result = match expected {
+                    Some(expected) => {
+                        sqlx::query(
+                            r#"
+                            UPDATE filesystems
+                            SET head = ?, modified_at = CURRENT_TIMESTAMP
+                            WHERE id = ? AND head IS ?
+                            "#,
+                        )
+                        .bind(&cid)
+                        .bind(fs_id)
+                        .bind(expected.map(|cid| cid.to_string()))
+                        .execute(&mut *tx)
+                        .await?
+                    }
+                    None => {
+                        sqlx::query(
+                            r#"
+                            UPDATE filesystems
+                            SET head = ?, modified_at = CURRENT_TIMESTAMP
+                            WHERE id = ?
+                            "#,
+                        )
+                        .bind(&cid)
+                        .bind(fs_id)
+                        .execute(&mut *tx)
+                        .await?
+                    }
+                };
+
+                // Another writer recorded a head since it was expected
+                if result.rows_affected() == 0 {
+                    return Ok(false);
+                }
+
+                (
+                    fs_id,
+                    row.get::<Option<String>, _>("head"),
+                    row.get::<String, _>("branch"),
+                )
+            }
+            // The server may be running without a supervisor, in which case there is no entry yet
+            None if matches!(expected, Some(Some(_))) => return Ok(false),
+            None => {
+                let name = self
+                    .mount_dir
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| mount_dir.clone());
+
+                let result =
+                    sqlx::query("INSERT INTO filesystems (name, mount_dir, head) VALUES (?, ?, ?)")
+                        .bind(name)
+                        .bind(&mount_dir)
+                        .bind(&cid)
+                        .execute(&mut *tx)
+                        .await?;
+
+                (
+                    result.last_insert_rowid(),
+                    None,
+                    DEFAULT_BRANCH_NAME.to_string(),
+                )
+            }
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO branches (fs_id, name, root_cid)
+            VALUES (?, ?, ?)
+            ON CONFLICT (fs_id, name) DO UPDATE
+            SET root_cid = excluded.root_cid,
+                modified_at = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(fs_id)
+        .bind(&branch)
+        .bind(&cid)
+        .execute(&mut *tx)
+        .await?;
+
+        // The new head supersedes every root checkpointed before it
+        sqlx::query("DELETE FROM intents WHERE mount_dir = ?")
+            .bind(&mount_dir)
+            .execute(&mut *tx)
+            .await?;
+
+        if previous.as_deref() != Some(cid.as_str()) {
+            let signing_key = self.signing_key.as_ref();
+            sqlx::query(
+                r#"
+                INSERT INTO history (fs_id, root_cid, operation, branch, signature, public_key)
+                VALUES (?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(fs_id)
+            .bind(&cid)
+            .bind(operation)
+            .bind(&branch)
+            .bind(signing_key.map(|key| key.sign(&new_head).to_string()))
+            .bind(signing_key.map(|key| key.get_public_key().to_string()))
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        *self.base.lock().await = Some(new_head);
+        Ok(true)
+    }
+
+    /// Logs the intent to make the root at `cid`, produced by `operation`, the head of the
+    /// filesystem.
+    ///
+    /// The intent is committed by the next call to [`set`](Self::set), and replayed by
+    /// [`recover`](Self::recover) if that never happens.
+    pub async fn log_intent(&self, cid: &Cid, operation: &str) -> FsResult<()> {
+        let mount_dir = self.mount_dir.to_string_lossy().to_string();
+        let mut tx = self.fs_db.begin().await?;
+
+        sqlx::query("INSERT INTO intents (mount_dir, root_cid, operation) VALUES (?, ?, ?)")
+            .bind(&mount_dir)
+            .bind(cid.to_string())
+            .bind(operation)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            r#"
+            DELETE FROM intents
+            WHERE mount_dir = ? AND id NOT IN (
+                SELECT id FROM intents WHERE mount_dir = ? ORDER BY id DESC LIMIT ?
+            )
+            "#,
+        )
+        .bind(&mount_dir)
+        .bind(&mount_dir)
+        .bind(MAX_INTENTS)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Replays the intents left behind by a server that stopped without recording its head.
+    ///
+    /// The latest intent whose blocks all made it to `store` is recorded as the head. Intents
+    /// with missing blocks, e.g. because the machine went down before they were written out, are
+    /// rolled back to the intent before them, and eventually to the recorded head.
+    ///
+    /// ## Returns
+    /// The recovered root, or `None` if the head did not change
+    pub async fn recover<S>(&self, store: &S) -> FsResult<Option<Cid>>
+    where
+        S: IpldStore + Sync,
+    {
+        let rows = sqlx::query(
+            "SELECT root_cid, operation FROM intents WHERE mount_dir = ? ORDER BY id DESC",
+        )
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .fetch_all(&self.fs_db)
+        .await?;
+
+        if rows.is_empty() {
+            return Ok(None);
+        }
+
+        let previous = self.get().await?;
+        for row in rows {
+            let cid = Cid::try_from(row.get::<String, _>("root_cid").as_str())?;
+            if !is_complete(store, &cid).await? {
+                tracing::warn!(
+                    "rolling back intent {}, some of its blocks are missing",
+                    cid
+                );
+                continue;
+            }
+
+            let operation = format!("recover {}", row.get::<String, _>("operation"));
+            self.set(&cid, &operation).await?;
+            return Ok((previous != Some(cid)).then_some(cid));
+        }
+
+        // Nothing could be replayed, so the recorded head stays and the intents are dropped
+        sqlx::query("DELETE FROM intents WHERE mount_dir = ?")
+            .bind(self.mount_dir.to_string_lossy().to_string())
+            .execute(&self.fs_db)
+            .await?;
+
+        Ok(None)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the up-to-date head of the filesystem at `paths`.
+///
+/// If the filesystem is mounted, its server checkpoints the in-memory root first so that every
+/// change made so far is captured. Otherwise the last recorded head is returned.
+pub(crate) async fn checkpoint_head(paths: &MfsPaths) -> FsResult<Option<Cid>> {
+    let response =
+        server::try_send_control_request(paths.control_socket_path(), &ControlRequest::Checkpoint)
+            .await?;
+
+    match response {
+        Some(ControlResponse::Root { cid }) => Ok(Some(Cid::try_from(cid.as_str())?)),
+        Some(response) => Err(FsError::ControlRequestFailed(format!(
+            "unexpected response: {:?}",
+            response
+        ))),
+        None => {
+            FsHead::new(paths.fs_db_path(), paths.get_mount_dir())
+                .await?
+                .get()
+                .await
+        }
+    }
+}
+
+/// Asks the running server of the filesystem at `paths` to record its final root before it is
+/// stopped.
+///
+/// Returns the final root, or `None` if the filesystem is not mounted.
+pub(crate) async fn flush_head(paths: &MfsPaths) -> FsResult<Option<Cid>> {
+    let response =
+        server::try_send_control_request(paths.control_socket_path(), &ControlRequest::Shutdown)
+            .await?;
+
+    match response {
+        Some(ControlResponse::Root { cid }) => Ok(Some(Cid::try_from(cid.as_str())?)),
+        Some(response) => Err(FsError::ControlRequestFailed(format!(
+            "unexpected response: {:?}",
+            response
+        ))),
+        None => Ok(None),
+    }
+}
+
+/// Makes `cid` the root of the filesystem at `paths`, recording `operation` in its history.
+///
+/// If the filesystem is mounted, its server swaps its in-memory root and records the new head, so
+/// the change is visible immediately. Otherwise the head is recorded for the next mount.
+///
+/// `base` is the root `cid` was derived from, if any. Changes made to the filesystem since, by
+/// its server or by another writer, are then merged into `cid` rather than lost. Without a base,
+/// `cid` replaces the root as it is.
+///
+/// Returns whether the root was swapped on a running server.
+pub(crate) async fn set_head(
+    paths: &MfsPaths,
+    base: Option<&Cid>,
+    cid: &Cid,
+    operation: &str,
+) -> FsResult<bool> {
+    let request = ControlRequest::SetRoot {
+        cid: cid.to_string(),
+        operation: operation.to_string(),
+        base: base.map(|cid| cid.to_string()),
+    };
+
+    if server::try_send_control_request(paths.control_socket_path(), &request)
+        .await?
+        .is_some()
+    {
+        return Ok(true);
+    }
+
+    let head = FsHead::new(paths.fs_db_path(), paths.get_mount_dir()).await?;
+    match base {
+        Some(base) => {
+            let store = paths.open_store().await?;
+            let merge = |base, ours, theirs| merge_roots(store.clone(), base, ours, theirs);
+            update_head(&head, Some(*base), cid, operation, merge).await?;
+        }
+        None => head.set(cid, operation).await?,
+    }
+
+    Ok(false)
+}
+
+/// Records `cid`, derived from the head `base`, as the head, merging in the heads other writers
+/// recorded since with `merge`.
+///
+/// Returns the root that was recorded.
+pub(crate) async fn update_head<F, Fut>(
+    head: &FsHead,
+    mut base: Option<Cid>,
+    cid: &Cid,
+    operation: &str,
+    merge: F,
+) -> FsResult<Cid>
+where
+    F: Fn(Option<Cid>, Cid, Cid) -> Fut,
+    Fut: Future<Output = FsResult<Cid>>,
+{
+    let mut cid = *cid;
+    for _ in 0..MAX_HEAD_UPDATE_ATTEMPTS {
+        if head.compare_and_set(base.as_ref(), &cid, operation).await? {
+            return Ok(cid);
+        }
+
+        // Another writer recorded a head since, which the root is merged with before retrying
+        let Some(theirs) = head.get().await? else {
+            base = None;
+            continue;
+        };
+
+        tracing::warn!(
+            "head moved from {:?} to {} while recording {}, merging",
+            base.map(|cid| cid.to_string()),
+            theirs,
+            cid
+        );
+        cid = merge(base, cid, theirs).await?;
+        base = Some(theirs);
+    }
+
+    Err(FsError::HeadUpdateConflict(format!(
+        "other writers kept recording heads while recording {} ({})",
+        cid, operation
+    )))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Merges the roots `ours` and `theirs`, both derived from `base`, in `store`.
+///
+/// Paths changed differently on both sides keep our version. Without a base, both sides are
+/// taken to derive from an empty root.
+pub(crate) async fn merge_roots<S>(
+    store: S,
+    base: Option<Cid>,
+    ours: Cid,
+    theirs: Cid,
+) -> FsResult<Cid>
+where
+    S: IpldStore + Clone + Send + Sync,
+{
+    let base = match base {
+        Some(base) => base,
+        None => Dir::new(store.clone()).checkpoint().await?,
+    };
+
+    let result = filesystem::merge(base, ours, theirs, store).await?;
+    for conflict in result.get_conflicts() {
+        tracing::warn!(
+            "kept our version of {} after it was changed on both sides ({:?})",
+            conflict.get_path(),
+            conflict.get_kind()
+        );
+    }
+
+    Ok(*result.get_root())
+}
+
+/// Returns whether every block reachable from `cid` is in `store`.
+pub(crate) async fn is_complete<S>(store: &S, cid: &Cid) -> FsResult<bool>
+where
+    S: IpldStore + Sync,
+{
+    let dag = DagWalker::new(store).with_raw_checks().walk([*cid]).await?;
+    Ok(dag.get_missing().is_empty())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::{IpldStore, MemoryStore};
+    use tempfile::tempdir;
+
+    use crate::{filesystem::File, management::FS_DB_MIGRATOR};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fs_head_get_set() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("fs.db");
+        db::init_db(&db_path, &FS_DB_MIGRATOR).await?;
+
+        let head = FsHead::new(&db_path, temp_dir.path().join("mnt")).await?;
+        assert_eq!(head.get().await?, None);
+
+        // Setting the head creates the entry when it doesn't exist
+        let store = MemoryStore::default();
+        let first = store.put_bytes(b"first".as_slice()).await?;
+        head.set(&first, "checkpoint").await?;
+        assert_eq!(head.get().await?, Some(first));
+
+        // Subsequent updates overwrite the head
+        let second = store.put_bytes(b"second".as_slice()).await?;
+        head.set(&second, "checkpoint").await?;
+        assert_eq!(head.get().await?, Some(second));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fs_head_recover() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("fs.db");
+        db::init_db(&db_path, &FS_DB_MIGRATOR).await?;
+
+        let head = FsHead::new(&db_path, temp_dir.path().join("mnt")).await?;
+        let store = MemoryStore::default();
+        let first = store.put_bytes(b"first".as_slice()).await?;
+        head.set(&first, "checkpoint").await?;
+
+        // Nothing to recover after a clean stop
+        head.log_intent(&store.put_bytes(b"logged".as_slice()).await?, "checkpoint")
+            .await?;
+        head.set(&first, "unmount").await?;
+        assert_eq!(head.recover(&store).await?, None);
+        assert_eq!(head.get().await?, Some(first));
+
+        // The latest intent with all of its blocks is replayed
+        let second = store.put_bytes(b"second".as_slice()).await?;
+        let lost = MemoryStore::default().put_bytes(b"lost".as_slice()).await?;
+        head.log_intent(&second, "checkpoint").await?;
+        head.log_intent(&lost, "checkpoint").await?;
+        assert_eq!(head.recover(&store).await?, Some(second));
+        assert_eq!(head.get().await?, Some(second));
+
+        // Replayed intents are committed
+        assert_eq!(head.recover(&store).await?, None);
+
+        // Intents that cannot be replayed are rolled back to the recorded head
+        head.log_intent(&lost, "checkpoint").await?;
+        assert_eq!(head.recover(&store).await?, None);
+        assert_eq!(head.get().await?, Some(second));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fs_head_update_merges_other_writers() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("fs.db");
+        db::init_db(&db_path, &FS_DB_MIGRATOR).await?;
+
+        let store = MemoryStore::default();
+        let mut base = Dir::new(store.clone());
+        let base_cid = base.checkpoint().await?;
+        let ours_head = FsHead::new(&db_path, temp_dir.path().join("mnt")).await?;
+        ours_head.set(&base_cid, "checkpoint").await?;
+
+        // Another writer records a head over the base
+        let theirs_head = FsHead::new(&db_path, temp_dir.path().join("mnt")).await?;
+        assert_eq!(theirs_head.load().await?, Some(base_cid));
+        let mut theirs = base.clone();
+        let file = File::with_content(store.clone(), b"theirs".as_slice()).await?;
+        theirs.put_adapted_file("theirs.txt", file).await?;
+        let theirs_cid = theirs.checkpoint().await?;
+        let merge = |base, ours, theirs| merge_roots(store.clone(), base, ours, theirs);
+        assert_eq!(
+            theirs_head.update(&theirs_cid, "checkpoint", merge).await?,
+            theirs_cid
+        );
+
+        // A root derived from the old base is not swapped in over it
+        let mut ours = base.clone();
+        let file = File::with_content(store.clone(), b"ours".as_slice()).await?;
+        ours.put_adapted_file("ours.txt", file).await?;
+        let ours_cid = ours.checkpoint().await?;
+        assert!(
+            !ours_head
+                .compare_and_set(Some(&base_cid), &ours_cid, "checkpoint")
+                .await?
+        );
+
+        // But merged with the other writer's head
+        let merged = ours_head.update(&ours_cid, "checkpoint", merge).await?;
+        assert_eq!(ours_head.get().await?, Some(merged));
+        let merged = Dir::load(&merged, store.clone()).await?;
+        assert!(merged.has_entry("ours.txt")?);
+        assert!(merged.has_entry("theirs.txt")?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,440 @@
+use std::{fmt, path::PathBuf, str::FromStr};
+
+use chrono::{DateTime, Utc};
+use getset::Getters;
+use ipldstore::{ipld::cid::Cid, Storable};
+use serde::Serialize;
+use sqlx::{sqlite::SqliteRow, Row};
+
+use crate::{
+    config::{RootPublicKey, RootSignature},
+    filesystem::Dir,
+    management::{db, find, head, FsHead},
+    utils, FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A root a filesystem has had, as recorded in its history.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct HistoryEntry {
+    /// The version number of the entry. Versions increase with every transition.
+    version: i64,
+
+    /// The CID of the root directory.
+    #[serde(serialize_with = "utils::serialize_display")]
+    root: Cid,
+
+    /// A short summary of what produced the root.
+    operation: String,
+
+    /// The branch the filesystem was on.
+    branch: String,
+
+    /// When the filesystem transitioned to the root.
+    created_at: DateTime<Utc>,
+
+    /// The signature of the root, if a signing key was given when it was recorded.
+    #[serde(serialize_with = "utils::serialize_optional_display")]
+    signature: Option<RootSignature>,
+
+    /// The public key of the key that signed the root, if any.
+    #[serde(serialize_with = "utils::serialize_optional_display")]
+    signed_by: Option<RootPublicKey>,
+}
+
+/// The point in a filesystem's history to check out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckoutTarget {
+    /// The root recorded under a version number.
+    Version(i64),
+
+    /// The root the filesystem had at a point in time.
+    Timestamp(DateTime<Utc>),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl HistoryEntry {
+    /// Checks that the root of the entry was signed by the key `public_key` is the public key of.
+    pub fn verify(&self, public_key: &RootPublicKey) -> FsResult<()> {
+        match (&self.signature, &self.signed_by) {
+            (Some(signature), Some(signed_by)) if signed_by == public_key => {
+                public_key.verify(&self.root, signature)
+            }
+            _ => Err(FsError::RootSignatureInvalid(format!(
+                "version {} is not signed by {}",
+                self.version, public_key
+            ))),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// List the roots a monofs filesystem has had, oldest first
+///
+/// Every change of the filesystem's head is recorded, whether it comes from a checkpoint of the
+/// mounted filesystem, a snapshot restore, an import, a checkout or a branch switch, along with
+/// the branch the filesystem was on.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// for entry in management::list_history(None).await? {
+///     println!("{} {} {}", entry.get_version(), entry.get_root(), entry.get_operation());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn list_history(mount_dir: Option<PathBuf>) -> FsResult<Vec<HistoryEntry>> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let pool = db::get_db_pool(paths.fs_db_path()).await?;
+
+    let rows = sqlx::query(
+        r#"
+        SELECT h.id, h.root_cid, h.operation, h.branch, h.created_at, h.signature, h.public_key
+        FROM history h
+        JOIN filesystems f ON h.fs_id = f.id
+        WHERE f.mount_dir = ?
+        ORDER BY h.id
+        "#,
+    )
+    .bind(paths.get_mount_dir().to_string_lossy().to_string())
+    .fetch_all(&pool)
+    .await?;
+
+    rows.iter().map(history_entry_from_row).collect()
+}
+
+/// Roll a monofs filesystem back to a root from its history
+///
+/// If the filesystem is mounted, the root of the running server is swapped so the mount shows the
+/// old contents immediately. Changes made since the last checkpoint are discarded. The checkout
+/// is itself recorded in the history, so it can be undone by checking out the version before it.
+///
+/// Roots are only guaranteed to survive garbage collection while they are the current root or
+/// referenced by a snapshot, so older versions may no longer be available.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `target` - The version to check out, or a point in time to check out the root of
+///
+/// ## Returns
+/// The history entry that was checked out
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management::{self, CheckoutTarget};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// management::checkout(Some("mfstest".into()), CheckoutTarget::Version(3)).await?;
+///
+/// let target = "2025-02-17T10:00:00Z".parse::<CheckoutTarget>()?;
+/// management::checkout(Some("mfstest".into()), target).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn checkout(
+    mount_dir: Option<PathBuf>,
+    target: CheckoutTarget,
+) -> FsResult<HistoryEntry> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let pool = db::get_db_pool(paths.fs_db_path()).await?;
+    let mount_dir = paths.get_mount_dir().to_string_lossy().to_string();
+
+    let query = match target {
+        CheckoutTarget::Version(version) => sqlx::query(
+            r#"
+            SELECT h.id, h.root_cid, h.operation, h.branch, h.created_at, h.signature, h.public_key
+            FROM history h
+            JOIN filesystems f ON h.fs_id = f.id
+            WHERE f.mount_dir = ? AND h.id = ?
+            "#,
+        )
+        .bind(mount_dir)
+        .bind(version),
+        CheckoutTarget::Timestamp(timestamp) => sqlx::query(
+            r#"
+            SELECT h.id, h.root_cid, h.operation, h.branch, h.created_at, h.signature, h.public_key
+            FROM history h
+            JOIN filesystems f ON h.fs_id = f.id
+            WHERE f.mount_dir = ? AND h.created_at <= ?
+            ORDER BY h.created_at DESC, h.id DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(mount_dir)
+        // Stored in the format of SQLite's `CURRENT_TIMESTAMP` so they compare as text
+        .bind(timestamp.format("%Y-%m-%d %H:%M:%S").to_string()),
+    };
+
+    let entry = query
+        .fetch_optional(&pool)
+        .await?
+        .as_ref()
+        .map(history_entry_from_row)
+        .transpose()?
+        .ok_or_else(|| FsError::HistoryEntryNotFound(target.to_string()))?;
+
+    // Make sure the old root has not been garbage collected before switching to it
+    let store = paths.open_store().await?;
+    Dir::load(&entry.root, store).await?;
+
+    let operation = format!("checkout {}", target);
+    head::set_head(&paths, None, &entry.root, &operation).await?;
+    tracing::info!("checked out {} ({})", entry.root, target);
+
+    Ok(entry)
+}
+
+/// Prove that the current root of a monofs filesystem was produced by the owner of a signing key
+///
+/// The head recorded in the fs database is only trusted if its history records the same root
+/// with a signature by the key, so pointing the head at a root the key never signed is detected.
+/// A head pointed back at an earlier root the key did sign passes, as the owner of the key
+/// produced that root too. See [`RootSigningKey`](crate::config::RootSigningKey).
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `public_key` - The public key of the key the head has to be signed with
+///
+/// ## Returns
+/// The latest history entry recording the head with a valid signature
+///
+/// ## Example
+/// ```no_run
+/// use monofs::{config::RootPublicKey, management};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let public_key = "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c"
+///     .parse::<RootPublicKey>()?;
+/// let entry = management::verify_head(Some("mfstest".into()), &public_key).await?;
+/// println!("{} is signed", entry.get_root());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn verify_head(
+    mount_dir: Option<PathBuf>,
+    public_key: &RootPublicKey,
+) -> FsResult<HistoryEntry> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let pool = db::get_db_pool(paths.fs_db_path()).await?;
+
+    let head = FsHead::new(paths.fs_db_path(), paths.get_mount_dir())
+        .await?
+        .get()
+        .await?
+        .ok_or_else(|| {
+            FsError::RootSignatureInvalid(format!(
+                "filesystem at {} has no recorded root",
+                paths.get_mount_dir().display()
+            ))
+        })?;
+
+    let rows = sqlx::query(
+        r#"
+        SELECT h.id, h.root_cid, h.operation, h.branch, h.created_at, h.signature, h.public_key
+        FROM history h
+        JOIN filesystems f ON h.fs_id = f.id
+        WHERE f.mount_dir = ? AND h.root_cid = ?
+        ORDER BY h.id DESC
+        "#,
+    )
+    .bind(paths.get_mount_dir().to_string_lossy().to_string())
+    .bind(head.to_string())
+    .fetch_all(&pool)
+    .await?;
+
+    for row in &rows {
+        let entry = history_entry_from_row(row)?;
+        if entry.verify(public_key).is_ok() {
+            return Ok(entry);
+        }
+    }
+
+    Err(FsError::RootSignatureInvalid(format!(
+        "head {} of {} is not signed by {}",
+        head,
+        paths.get_mount_dir().display(),
+        public_key
+    )))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Construct a history entry from a row of the history table.
+fn history_entry_from_row(row: &SqliteRow) -> FsResult<HistoryEntry> {
+    let root_cid: String = row.get("root_cid");
+    Ok(HistoryEntry {
+        version: row.get("id"),
+        root: Cid::try_from(root_cid.as_str())?,
+        operation: row.get("operation"),
+        branch: row.get("branch"),
+        created_at: row.get("created_at"),
+        signature: row
+            .get::<Option<String>, _>("signature")
+            .map(|signature| signature.parse())
+            .transpose()?,
+        signed_by: row
+            .get::<Option<String>, _>("public_key")
+            .map(|public_key| public_key.parse())
+            .transpose()?,
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Display for CheckoutTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckoutTarget::Version(version) => write!(f, "version {}", version),
+            CheckoutTarget::Timestamp(timestamp) => write!(f, "{}", timestamp.to_rfc3339()),
+        }
+    }
+}
+
+impl FromStr for CheckoutTarget {
+    type Err = FsError;
+
+    /// Parses a version number, or an RFC 3339 timestamp.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(version) = s.parse::<i64>() {
+            return Ok(Self::Version(version));
+        }
+
+        DateTime::parse_from_rfc3339(s)
+            .map(|timestamp| Self::Timestamp(timestamp.with_timezone(&Utc)))
+            .map_err(|_| FsError::InvalidCheckoutTarget(s.to_string()))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use tokio::fs;
+
+    use crate::{config::RootSigningKey, filesystem::File, store::FlatFsStore};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_history_and_checkout() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let mount_dir = fs::canonicalize(temp.path()).await?.join("mnt");
+        let paths = find::helper::setup_test_mfs(&mount_dir).await?;
+
+        // Record two roots, plus a repeat of the second that is not a transition
+        let store = FlatFsStore::new(paths.blocks_dir());
+        let mut root = Dir::new(store.clone());
+        let first = root.checkpoint().await?;
+        let file = File::with_content(store.clone(), b"hello".as_slice()).await?;
+        root.put_adapted_file("hello.txt", file).await?;
+        let second = root.checkpoint().await?;
+
+        let head = FsHead::new(paths.fs_db_path(), &mount_dir).await?;
+        head.set(&first, "checkpoint").await?;
+        head.set(&second, "checkpoint").await?;
+        head.set(&second, "checkpoint").await?;
+
+        let history = list_history(Some(mount_dir.clone())).await?;
+        let roots = history.iter().map(|entry| entry.root).collect::<Vec<_>>();
+        assert_eq!(roots, vec![first, second]);
+
+        // Checking out the first version makes it the head again and is recorded
+        let version = *history[0].get_version();
+        let entry = checkout(Some(mount_dir.clone()), CheckoutTarget::Version(version)).await?;
+        assert_eq!(entry.get_root(), &first);
+        assert_eq!(head.get().await?, Some(first));
+
+        let history = list_history(Some(mount_dir.clone())).await?;
+        assert_eq!(history.len(), 3);
+        assert_eq!(
+            history[2].get_operation(),
+            &format!("checkout version {}", version)
+        );
+
+        // Nothing was recorded before the epoch
+        let result = checkout(
+            Some(mount_dir),
+            CheckoutTarget::Timestamp(DateTime::<Utc>::UNIX_EPOCH),
+        )
+        .await;
+        assert!(matches!(result, Err(FsError::HistoryEntryNotFound(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verify_head() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let mount_dir = fs::canonicalize(temp.path()).await?.join("mnt");
+        let paths = find::helper::setup_test_mfs(&mount_dir).await?;
+
+        let store = FlatFsStore::new(paths.blocks_dir());
+        let mut root = Dir::new(store.clone());
+        let signed = root.checkpoint().await?;
+        let file = File::with_content(store.clone(), b"hello".as_slice()).await?;
+        root.put_adapted_file("hello.txt", file).await?;
+        let unsigned = root.checkpoint().await?;
+
+        let key = RootSigningKey::generate();
+        let public_key = key.get_public_key();
+        let head = FsHead::new(paths.fs_db_path(), &mount_dir).await?;
+        head.clone()
+            .with_signing_key(Some(key))
+            .set(&signed, "checkpoint")
+            .await?;
+
+        let entry = verify_head(Some(mount_dir.clone()), &public_key).await?;
+        assert_eq!(entry.get_root(), &signed);
+        assert_eq!(entry.get_signed_by(), &Some(public_key));
+
+        // A head the key never signed is rejected, as is any other key
+        head.with_signing_key(None)
+            .set(&unsigned, "checkpoint")
+            .await?;
+        let result = verify_head(Some(mount_dir.clone()), &public_key).await;
+        assert!(matches!(result, Err(FsError::RootSignatureInvalid(_))));
+
+        let other_key = RootSigningKey::generate().get_public_key();
+        let history = list_history(Some(mount_dir)).await?;
+        assert!(history[0].verify(&public_key).is_ok());
+        assert!(history[0].verify(&other_key).is_err());
+        assert!(history[1].verify(&public_key).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkout_target_from_str() -> anyhow::Result<()> {
+        assert_eq!("3".parse::<CheckoutTarget>()?, CheckoutTarget::Version(3));
+        assert!(matches!(
+            "2025-02-17T10:00:00Z".parse::<CheckoutTarget>()?,
+            CheckoutTarget::Timestamp(_)
+        ));
+        assert!("yesterday".parse::<CheckoutTarget>().is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,652 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use getset::Getters;
+use serde::{de::DeserializeOwned, Serialize};
+use sqlx::{sqlite::SqliteRow, Pool, Row, Sqlite};
+use tokio::task::JoinHandle;
+
+use crate::{
+    management::{db, find},
+    utils, FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How often the progress of a running job is written to the fs database.
+const JOB_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The long-running operations that are recorded as jobs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    /// Garbage collection, see [`gc_mfs`](crate::management::gc_mfs).
+    Gc,
+
+    /// Verification of the blocks, see [`verify_mfs`](crate::management::verify_mfs).
+    Verify,
+
+    /// An import of a tar archive, see [`import_tar`](crate::management::import_tar).
+    Import,
+
+    /// An export of a tar archive, see [`export_tar`](crate::management::export_tar).
+    Export,
+
+    /// A send stream being written, see [`send`](crate::management::send) and
+    /// [`send_file`](crate::management::send_file).
+    Send,
+
+    /// A send stream being applied, see [`receive`](crate::management::receive).
+    Receive,
+
+    /// A sync with a host directory, see [`sync_dir`](crate::management::sync_dir).
+    Sync,
+
+    /// A resilver of the mirrors of the blocks, see
+    /// [`resilver_mfs`](crate::management::resilver_mfs).
+    Resilver,
+
+    /// A backup of the filesystem, see [`backup_mfs`](crate::management::backup_mfs).
+    Backup,
+
+    /// A restore of a backup or CAR file into a new filesystem, see
+    /// [`restore_mfs`](crate::management::restore_mfs).
+    Restore,
+}
+
+/// Where a job is at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// The job is running.
+    Running,
+
+    /// The job finished.
+    Completed,
+
+    /// The job stopped with an error.
+    Failed,
+
+    /// The process running the job exited before the job finished, such as when it was killed.
+    Interrupted,
+}
+
+/// A job run on a filesystem, as it is recorded in its fs database.
+///
+/// Jobs record their progress as they run, so it can be followed with [`job_status`] from
+/// another process. Jobs that work on a target, such as the file a stream is sent to, also
+/// record checkpoints, and pick up from the last one when they are run again on the same target
+/// after being interrupted or failing, keeping their ID.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct JobStatus {
+    /// The ID of the job.
+    id: i64,
+
+    /// The operation the job runs.
+    kind: JobKind,
+
+    /// What the job works on, if it can be resumed.
+    target: Option<String>,
+
+    /// Where the job is at.
+    state: JobState,
+
+    /// The units of work done, such as blocks or entries.
+    done: u64,
+
+    /// The units of work the job has in total, if known.
+    total: Option<u64>,
+
+    /// The error the job failed with.
+    error: Option<String>,
+
+    /// When the job was first started.
+    created_at: DateTime<Utc>,
+
+    /// When the job last recorded its progress.
+    modified_at: DateTime<Utc>,
+}
+
+/// The progress of a running job, which its operation updates as it goes.
+#[derive(Debug, Default)]
+pub(crate) struct JobProgress {
+    /// The units of work done.
+    done: AtomicU64,
+
+    /// The units of work in total, or 0 if not known.
+    total: AtomicU64,
+}
+
+/// A job run by this process, recorded in the fs database of a filesystem.
+///
+/// Its progress is written to the database every [`JOB_PROGRESS_INTERVAL`] while it runs.
+pub(crate) struct Job {
+    /// The filesystem database.
+    fs_db: Pool<Sqlite>,
+
+    /// The ID of the job.
+    id: i64,
+
+    /// The progress of the job.
+    progress: Arc<JobProgress>,
+
+    /// The last checkpoint of the job, if it was resumed from one.
+    checkpoint: Option<String>,
+
+    /// The task writing the progress of the job to the database.
+    reporter: JoinHandle<()>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl Job {
+    /// Records the start of a job of `kind` on the filesystem mounted at `mount_dir`.
+    ///
+    /// A job with a `target` resumes the last job of the same kind and target, if it was
+    /// interrupted or failed, along with its progress and last checkpoint. Fails if that job is
+    /// still running.
+    pub(crate) async fn start(
+        fs_db_path: impl AsRef<Path>,
+        mount_dir: impl AsRef<Path>,
+        kind: JobKind,
+        target: Option<&str>,
+    ) -> FsResult<Self> {
+        let fs_db = db::get_db_pool(fs_db_path.as_ref()).await?;
+        let mount_dir = mount_dir.as_ref().to_string_lossy().to_string();
+        let pid = std::process::id();
+
+        let mut resumed = None;
+        if let Some(target) = target {
+            let record = sqlx::query(
+                r#"
+                SELECT * FROM jobs
+                WHERE mount_dir = ? AND kind = ? AND target = ?
+                ORDER BY id DESC
+                LIMIT 1
+                "#,
+            )
+            .bind(&mount_dir)
+            .bind(kind.to_string())
+            .bind(target)
+            .fetch_optional(&fs_db)
+            .await?;
+
+            if let Some(row) = record {
+                let status = job_status_from_row(&row)?;
+                match status.state {
+                    JobState::Running => {
+                        return Err(FsError::InvalidOperation(format!(
+                            "{} job {} on {} is already running",
+                            kind, status.id, target
+                        )));
+                    }
+                    JobState::Failed | JobState::Interrupted => {
+                        resumed = Some((status, row.get::<Option<String>, _>("checkpoint")));
+                    }
+                    JobState::Completed => {}
+                }
+            }
+        }
+
+        let progress = Arc::new(JobProgress::default());
+        let (id, checkpoint) = match resumed {
+            Some((status, checkpoint)) => {
+                sqlx::query(
+                    r#"
+                    UPDATE jobs
+                    SET state = ?, pid = ?, error = NULL, modified_at = CURRENT_TIMESTAMP
+                    WHERE id = ?
+                    "#,
+                )
+                .bind(JobState::Running.to_string())
+                .bind(pid)
+                .bind(status.id)
+                .execute(&fs_db)
+                .await?;
+
+                progress.set_done(status.done);
+                progress.set_total(status.total.unwrap_or_default());
+                tracing::info!("resuming {} job {} at {}", kind, status.id, status.done);
+                (status.id, checkpoint)
+            }
+            None => {
+                let result = sqlx::query(
+                    "INSERT INTO jobs (mount_dir, kind, target, state, pid) VALUES (?, ?, ?, ?, ?)",
+                )
+                .bind(&mount_dir)
+                .bind(kind.to_string())
+                .bind(target)
+                .bind(JobState::Running.to_string())
+                .bind(pid)
+                .execute(&fs_db)
+                .await?;
+
+                let id = result.last_insert_rowid();
+                tracing::info!("started {} job {}", kind, id);
+                (id, None)
+            }
+        };
+
+        let reporter = tokio::spawn(report_progress(fs_db.clone(), id, progress.clone()));
+        Ok(Self {
+            fs_db,
+            id,
+            progress,
+            checkpoint,
+            reporter,
+        })
+    }
+
+    /// Returns the ID of the job.
+    pub(crate) fn get_id(&self) -> i64 {
+        self.id
+    }
+
+    /// Returns the progress of the job, for its operation to update.
+    pub(crate) fn get_progress(&self) -> &JobProgress {
+        &self.progress
+    }
+
+    /// Returns the checkpoint the job was resumed from, or `None` if it starts from scratch or
+    /// the checkpoint cannot be read.
+    pub(crate) fn get_checkpoint<T>(&self) -> Option<T>
+    where
+        T: DeserializeOwned,
+    {
+        let checkpoint = self.checkpoint.as_ref()?;
+        match serde_json::from_str(checkpoint) {
+            Ok(checkpoint) => Some(checkpoint),
+            Err(e) => {
+                tracing::warn!("ignoring unreadable checkpoint of job {}: {}", self.id, e);
+                None
+            }
+        }
+    }
+
+    /// Records `checkpoint`, which the job picks up from if it is run again after being
+    /// interrupted, along with its progress.
+    ///
+    /// Everything the checkpoint refers to has to be durable by the time it is recorded.
+    pub(crate) async fn checkpoint<T>(&self, checkpoint: &T) -> FsResult<()>
+    where
+        T: Serialize,
+    {
+        let checkpoint = serde_json::to_string(checkpoint).map_err(FsError::custom)?;
+        sqlx::query(
+            r#"
+            UPDATE jobs
+            SET done = ?, total = ?, checkpoint = ?, modified_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            "#,
+        )
+        .bind(self.progress.get_done() as i64)
+        .bind(self.progress.get_total().map(|total| total as i64))
+        .bind(checkpoint)
+        .bind(self.id)
+        .execute(&self.fs_db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records the job as completed or failed, depending on `result`, and passes it on.
+    ///
+    /// A completed job drops its checkpoint, while a failed one keeps it to be resumed from.
+    pub(crate) async fn finish<T>(self, result: FsResult<T>) -> FsResult<T> {
+        self.reporter.abort();
+        let (state, error) = match &result {
+            Ok(_) => (JobState::Completed, None),
+            Err(e) => (JobState::Failed, Some(e.to_string())),
+        };
+
+        let recorded = sqlx::query(
+            r#"
+            UPDATE jobs
+            SET state = ?, done = ?, total = ?, error = ?,
+                checkpoint = CASE WHEN ? THEN NULL ELSE checkpoint END,
+                modified_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            "#,
+        )
+        .bind(state.to_string())
+        .bind(self.progress.get_done() as i64)
+        .bind(self.progress.get_total().map(|total| total as i64))
+        .bind(error)
+        .bind(state == JobState::Completed)
+        .bind(self.id)
+        .execute(&self.fs_db)
+        .await;
+
+        if let Err(e) = recorded {
+            tracing::warn!("failed to record the end of job {}: {}", self.id, e);
+        }
+
+        result
+    }
+}
+
+impl JobProgress {
+    /// Returns the units of work done.
+    pub(crate) fn get_done(&self) -> u64 {
+        self.done.load(Ordering::Relaxed)
+    }
+
+    /// Returns the units of work in total, if known.
+    pub(crate) fn get_total(&self) -> Option<u64> {
+        Some(self.total.load(Ordering::Relaxed)).filter(|total| *total > 0)
+    }
+
+    /// Sets the units of work done.
+    pub(crate) fn set_done(&self, done: u64) {
+        self.done.store(done, Ordering::Relaxed);
+    }
+
+    /// Adds to the units of work done.
+    pub(crate) fn add_done(&self, done: u64) {
+        self.done.fetch_add(done, Ordering::Relaxed);
+    }
+
+    /// Sets the units of work in total.
+    pub(crate) fn set_total(&self, total: u64) {
+        self.total.store(total, Ordering::Relaxed);
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Get the status of a job run on a monofs filesystem
+///
+/// Garbage collection, verification, tar imports and exports, and sending and receiving streams
+/// are recorded as jobs, whose IDs are logged as they start. Their progress is updated every
+/// second while they run, so this can follow a job run by another process.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `id` - The ID of the job
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let status = management::job_status(Some("mfstest".into()), 1).await?;
+/// println!("{:?}: {}/{:?}", status.get_state(), status.get_done(), status.get_total());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn job_status(mount_dir: Option<PathBuf>, id: i64) -> FsResult<JobStatus> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let pool = db::get_db_pool(&paths.fs_db_path()).await?;
+    let record = sqlx::query("SELECT * FROM jobs WHERE mount_dir = ? AND id = ?")
+        .bind(paths.get_mount_dir().to_string_lossy().to_string())
+        .bind(id)
+        .fetch_optional(&pool)
+        .await?;
+
+    match record {
+        Some(row) => job_status_from_row(&row),
+        None => Err(FsError::JobNotFound(id)),
+    }
+}
+
+/// List the jobs run on a monofs filesystem, oldest first
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// for job in management::list_jobs(None).await? {
+///     println!("{}\t{:?}\t{:?}", job.get_id(), job.get_kind(), job.get_state());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn list_jobs(mount_dir: Option<PathBuf>) -> FsResult<Vec<JobStatus>> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let pool = db::get_db_pool(&paths.fs_db_path()).await?;
+    let records = sqlx::query("SELECT * FROM jobs WHERE mount_dir = ? ORDER BY id")
+        .bind(paths.get_mount_dir().to_string_lossy().to_string())
+        .fetch_all(&pool)
+        .await?;
+
+    records.iter().map(job_status_from_row).collect()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Builds the status of a job from a row of the `jobs` table. A job recorded as running whose
+/// process is gone was interrupted.
+fn job_status_from_row(row: &SqliteRow) -> FsResult<JobStatus> {
+    let mut state = row.get::<String, _>("state").parse()?;
+    if state == JobState::Running && !utils::is_process_running(row.get::<u32, _>("pid")) {
+        state = JobState::Interrupted;
+    }
+
+    Ok(JobStatus {
+        id: row.get("id"),
+        kind: row.get::<String, _>("kind").parse()?,
+        target: row.get("target"),
+        state,
+        done: row.get::<i64, _>("done") as u64,
+        total: row.get::<Option<i64>, _>("total").map(|total| total as u64),
+        error: row.get("error"),
+        created_at: row.get("created_at"),
+        modified_at: row.get("modified_at"),
+    })
+}
+
+/// Writes the progress of job `id` to the fs database whenever it changed, until aborted.
+async fn report_progress(fs_db: Pool<Sqlite>, id: i64, progress: Arc<JobProgress>) {
+    let mut recorded = (progress.get_done(), progress.get_total());
+    let mut interval = tokio::time::interval(JOB_PROGRESS_INTERVAL);
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let current = (progress.get_done(), progress.get_total());
+        if current == recorded {
+            continue;
+        }
+
+        let result = sqlx::query(
+            "UPDATE jobs SET done = ?, total = ?, modified_at = CURRENT_TIMESTAMP WHERE id = ?",
+        )
+        .bind(current.0 as i64)
+        .bind(current.1.map(|total| total as i64))
+        .bind(id)
+        .execute(&fs_db)
+        .await;
+
+        match result {
+            Ok(_) => recorded = current,
+            Err(e) => tracing::warn!("failed to record progress of job {}: {}", id, e),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.reporter.abort();
+    }
+}
+
+impl fmt::Display for JobKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobKind::Gc => write!(f, "gc"),
+            JobKind::Verify => write!(f, "verify"),
+            JobKind::Import => write!(f, "import"),
+            JobKind::Export => write!(f, "export"),
+            JobKind::Send => write!(f, "send"),
+            JobKind::Receive => write!(f, "receive"),
+            JobKind::Sync => write!(f, "sync"),
+            JobKind::Resilver => write!(f, "resilver"),
+            JobKind::Backup => write!(f, "backup"),
+            JobKind::Restore => write!(f, "restore"),
+        }
+    }
+}
+
+impl FromStr for JobKind {
+    type Err = FsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gc" => Ok(JobKind::Gc),
+            "verify" => Ok(JobKind::Verify),
+            "import" => Ok(JobKind::Import),
+            "export" => Ok(JobKind::Export),
+            "send" => Ok(JobKind::Send),
+            "receive" => Ok(JobKind::Receive),
+            "sync" => Ok(JobKind::Sync),
+            "resilver" => Ok(JobKind::Resilver),
+            "backup" => Ok(JobKind::Backup),
+            "restore" => Ok(JobKind::Restore),
+            _ => Err(FsError::InvalidJobRecord(format!("unknown job kind {}", s))),
+        }
+    }
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobState::Running => write!(f, "running"),
+            JobState::Completed => write!(f, "completed"),
+            JobState::Failed => write!(f, "failed"),
+            JobState::Interrupted => write!(f, "interrupted"),
+        }
+    }
+}
+
+impl FromStr for JobState {
+    type Err = FsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "running" => Ok(JobState::Running),
+            "completed" => Ok(JobState::Completed),
+            "failed" => Ok(JobState::Failed),
+            "interrupted" => Ok(JobState::Interrupted),
+            _ => Err(FsError::InvalidJobRecord(format!(
+                "unknown job state {}",
+                s
+            ))),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use tempfile::tempdir;
+
+    use crate::management::FS_DB_MIGRATOR;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TestCheckpoint {
+        position: u64,
+    }
+
+    #[tokio::test]
+    async fn test_job_resumes_from_checkpoint() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("fs.db");
+        db::init_db(&db_path, &FS_DB_MIGRATOR).await?;
+        let pool = db::get_db_pool(&db_path).await?;
+        let mount_dir = temp_dir.path().join("mnt");
+        let get_status = |id: i64| {
+            let pool = pool.clone();
+            async move {
+                let row = sqlx::query("SELECT * FROM jobs WHERE id = ?")
+                    .bind(id)
+                    .fetch_one(&pool)
+                    .await?;
+                job_status_from_row(&row)
+            }
+        };
+
+        // A running job cannot be started twice on the same target
+        let job = Job::start(&db_path, &mount_dir, JobKind::Send, Some("out")).await?;
+        let id = job.get_id();
+        let result = Job::start(&db_path, &mount_dir, JobKind::Send, Some("out")).await;
+        assert!(matches!(result, Err(FsError::InvalidOperation(_))));
+
+        job.get_progress().set_total(10);
+        job.get_progress().add_done(4);
+        job.checkpoint(&TestCheckpoint { position: 4 }).await?;
+        let status = get_status(id).await?;
+        assert_eq!(status.state, JobState::Running);
+        assert_eq!((status.done, status.total), (4, Some(10)));
+
+        // A failed job is resumed with its progress and checkpoint
+        let result = job
+            .finish::<()>(Err(FsError::InvalidOperation("boom".to_string())))
+            .await;
+        assert!(result.is_err());
+        assert_eq!(get_status(id).await?.state, JobState::Failed);
+
+        let job = Job::start(&db_path, &mount_dir, JobKind::Send, Some("out")).await?;
+        assert_eq!(job.get_id(), id);
+        assert_eq!(job.get_progress().get_done(), 4);
+        assert_eq!(
+            job.get_checkpoint::<TestCheckpoint>(),
+            Some(TestCheckpoint { position: 4 })
+        );
+        job.finish(Ok(())).await?;
+        let status = get_status(id).await?;
+        assert_eq!(status.state, JobState::Completed);
+        assert_eq!(status.error, None);
+
+        // Completed jobs and jobs without a target start afresh
+        let job = Job::start(&db_path, &mount_dir, JobKind::Send, Some("out")).await?;
+        assert_ne!(job.get_id(), id);
+        assert_eq!(job.get_checkpoint::<TestCheckpoint>(), None);
+        let other = Job::start(&db_path, &mount_dir, JobKind::Gc, None).await?;
+        assert_ne!(other.get_id(), job.get_id());
+
+        // A job whose process is gone was interrupted
+        sqlx::query("UPDATE jobs SET pid = ? WHERE id = ?")
+            .bind(i32::MAX)
+            .bind(job.get_id())
+            .execute(&pool)
+            .await?;
+        assert_eq!(get_status(job.get_id()).await?.state, JobState::Interrupted);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use sqlx::{Pool, Row, Sqlite};
+
+use crate::{management::db, utils, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How long to wait before trying again to take a lock someone else holds.
+const ENTITY_LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Tracks the advisory locks on the entities of a filesystem, in its fs database.
+///
+/// Processes that use the library on the same store see each other's locks, so they can take
+/// turns updating an entity instead of overwriting each other's changes. Locks are advisory:
+/// nothing stops a process that does not take them from changing the entity. Locks are taken on
+/// paths, so locking a directory does not lock the entities in it.
+///
+/// Locks held by a process that is no longer running are dropped when they get in the way.
+///
+/// ## Examples
+///
+/// ```no_run
+/// use monofs::management::{FsLocks, LockKind};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let locks = FsLocks::new("fs.db", "mnt").await?;
+///
+/// let lock = locks.lock("dir/file.txt", LockKind::Exclusive).await?;
+/// assert!(locks.try_lock("dir/file.txt", LockKind::Shared).await?.is_none());
+///
+/// lock.unlock().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FsLocks {
+    /// The filesystem database.
+    fs_db: Pool<Sqlite>,
+
+    /// The mount directory identifying the filesystem.
+    mount_dir: PathBuf,
+}
+
+/// The kind of an advisory lock on an entity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockKind {
+    /// Any number of shared locks can be held on an entity at once, as long as no exclusive lock
+    /// is.
+    Shared,
+
+    /// An exclusive lock is the only lock held on an entity.
+    Exclusive,
+}
+
+/// An advisory lock held on an entity, taken with [`FsLocks::lock`].
+///
+/// The lock is released by [`unlock`](Self::unlock), or in the background when it is dropped.
+#[derive(Debug)]
+pub struct EntityLock {
+    /// The locks the lock was taken from.
+    locks: FsLocks,
+
+    /// The ID of the lock's record.
+    id: i64,
+
+    /// Whether the lock was released already.
+    released: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FsLocks {
+    /// Opens the entity locks of the filesystem mounted at `mount_dir`.
+    pub async fn new(
+        fs_db_path: impl AsRef<Path>,
+        mount_dir: impl Into<PathBuf>,
+    ) -> FsResult<Self> {
+        Ok(Self {
+            fs_db: db::get_db_pool(fs_db_path.as_ref()).await?,
+            mount_dir: mount_dir.into(),
+        })
+    }
+
+    /// Takes a lock of `kind` on the entity at `path`, waiting for as long as another lock is in
+    /// the way.
+    pub async fn lock(&self, path: impl AsRef<str>, kind: LockKind) -> FsResult<EntityLock> {
+        loop {
+            if let Some(lock) = self.try_lock(path.as_ref(), kind).await? {
+                return Ok(lock);
+            }
+
+            tokio::time::sleep(ENTITY_LOCK_RETRY_INTERVAL).await;
+        }
+    }
+
+    /// Takes a lock of `kind` on the entity at `path`, or returns `None` if another lock is in the
+    /// way.
+    pub async fn try_lock(
+        &self,
+        path: impl AsRef<str>,
+        kind: LockKind,
+    ) -> FsResult<Option<EntityLock>> {
+        let path = path.as_ref().trim_matches('/');
+        let exclusive = kind == LockKind::Exclusive;
+
+        // Locks of processes that are gone are only looked for when they are in the way
+        let mut id = self.insert(path, exclusive).await?;
+        if id.is_none() && self.drop_stale(path).await? > 0 {
+            id = self.insert(path, exclusive).await?;
+        }
+
+        Ok(id.map(|id| EntityLock {
+            locks: self.clone(),
+            id,
+            released: false,
+        }))
+    }
+
+    /// Records a lock on the entity at `path` and returns the ID of its record, unless another
+    /// lock is in the way.
+    async fn insert(&self, path: &str, exclusive: bool) -> FsResult<Option<i64>> {
+        // Checking for other locks and recording this one is a single statement, so no other
+        // process can take a lock in between
+        let result = sqlx::query(
+            r#"
+            INSERT INTO entity_locks (mount_dir, path, exclusive, pid)
+            SELECT ?1, ?2, ?3, ?4
+            WHERE NOT EXISTS (
+                SELECT 1 FROM entity_locks
+                WHERE mount_dir = ?1 AND path = ?2 AND (exclusive OR ?3)
+            )
+            "#,
+        )
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .bind(path)
+        .bind(exclusive)
+        .bind(std::process::id() as i64)
+        .execute(&self.fs_db)
+        .await?;
+
+        Ok((result.rows_affected() == 1).then(|| result.last_insert_rowid()))
+    }
+
+    /// Drops the locks on the entity at `path` held by processes that are no longer running, and
+    /// returns how many there were.
+    async fn drop_stale(&self, path: &str) -> FsResult<u64> {
+        let mount_dir = self.mount_dir.to_string_lossy().to_string();
+        let records =
+            sqlx::query("SELECT DISTINCT pid FROM entity_locks WHERE mount_dir = ? AND path = ?")
+                .bind(&mount_dir)
+                .bind(path)
+                .fetch_all(&self.fs_db)
+                .await?;
+
+        let mut dropped = 0;
+        for row in records {
+            let pid = row.get::<i64, _>("pid");
+            if utils::is_process_running(pid as u32) {
+                continue;
+            }
+
+            tracing::info!("dropping stale lock on {} held by {}", path, pid);
+            dropped += sqlx::query(
+                "DELETE FROM entity_locks WHERE mount_dir = ? AND path = ? AND pid = ?",
+            )
+            .bind(&mount_dir)
+            .bind(path)
+            .bind(pid)
+            .execute(&self.fs_db)
+            .await?
+            .rows_affected();
+        }
+
+        Ok(dropped)
+    }
+
+    /// Deletes the record of the lock with ID `id`.
+    async fn release(&self, id: i64) -> FsResult<()> {
+        sqlx::query("DELETE FROM entity_locks WHERE id = ?")
+            .bind(id)
+            .execute(&self.fs_db)
+            .await?;
+
+        Ok(())
+    }
+}
+
+impl EntityLock {
+    /// Releases the lock.
+    pub async fn unlock(mut self) -> FsResult<()> {
+        self.released = true;
+        self.locks.release(self.id).await
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Drop for EntityLock {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+
+        // Without a runtime, the lock is left to be dropped as stale once the process exits
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let locks = self.locks.clone();
+        let id = self.id;
+        handle.spawn(async move {
+            if let Err(e) = locks.release(id).await {
+                tracing::warn!("failed to release entity lock {}: {}", id, e);
+            }
+        });
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::management::FS_DB_MIGRATOR;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fs_locks() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("fs.db");
+        db::init_db(&db_path, &FS_DB_MIGRATOR).await?;
+
+        let mount_dir = temp_dir.path().join("mnt");
+        let locks = FsLocks::new(&db_path, &mount_dir).await?;
+        let other = FsLocks::new(&db_path, &mount_dir).await?;
+
+        // Shared locks go together, but keep exclusive ones out
+        let first = locks.lock("dir/file.txt", LockKind::Shared).await?;
+        let second = other.lock("/dir/file.txt", LockKind::Shared).await?;
+        assert!(other
+            .try_lock("dir/file.txt", LockKind::Exclusive)
+            .await?
+            .is_none());
+
+        // Other paths are not locked
+        other
+            .lock("dir", LockKind::Exclusive)
+            .await?
+            .unlock()
+            .await?;
+
+        // Dropped locks are released too
+        first.unlock().await?;
+        drop(second);
+        let exclusive = other.lock("dir/file.txt", LockKind::Exclusive).await?;
+        assert!(locks
+            .try_lock("dir/file.txt", LockKind::Shared)
+            .await?
+            .is_none());
+        exclusive.unlock().await?;
+
+        // Locks of processes that are gone are dropped
+        sqlx::query(
+            "INSERT INTO entity_locks (mount_dir, path, exclusive, pid) VALUES (?, ?, TRUE, ?)",
+        )
+        .bind(mount_dir.to_string_lossy().to_string())
+        .bind("file.txt")
+        .bind(i32::MAX as i64)
+        .execute(&locks.fs_db)
+        .await?;
+        assert!(locks
+            .try_lock("file.txt", LockKind::Exclusive)
+            .await?
+            .is_some());
+
+        Ok(())
+    }
+}
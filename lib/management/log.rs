@@ -0,0 +1,423 @@
+use std::{
+    collections::VecDeque,
+    io::{self, SeekFrom},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use futures::stream::{self, BoxStream, StreamExt};
+use getset::Getters;
+use microsandbox_utils::LOG_SUFFIX;
+use serde::Serialize;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+
+use crate::{
+    config::{LogSource, TailOptions},
+    management::find,
+    utils::MFSRUN_LOG_PREFIX,
+    FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How often a followed log is checked for new lines.
+const LOG_FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A line read from the logs of a filesystem.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct LogLine {
+    /// The process that wrote the line, either [`LogSource::Server`] or
+    /// [`LogSource::Supervisor`].
+    source: LogSource,
+
+    /// The log file the line was read from.
+    path: PathBuf,
+
+    /// The line, without its line break.
+    line: String,
+}
+
+/// Reads the lines of the current log of one process, moving on to newer logs as they appear.
+struct LogTail {
+    /// The directory holding the logs.
+    log_dir: PathBuf,
+
+    /// The process whose logs are read.
+    source: LogSource,
+
+    /// The prefix of the names of the server's logs.
+    server_prefix: String,
+
+    /// Whether to wait for more lines once the log is read to its end.
+    follow: bool,
+
+    /// Only lines containing this text are read.
+    filter: Option<String>,
+
+    /// The log being read, if any was found yet.
+    current: Option<OpenLog>,
+
+    /// Lines read from the log that have yet to be returned.
+    pending: VecDeque<String>,
+
+    /// The end of the log, if it does not end with a line break yet.
+    partial: String,
+}
+
+/// The log a [`LogTail`] reads from.
+struct OpenLog {
+    /// The path of the log.
+    path: PathBuf,
+
+    /// The inode of the log, which changes when the log is rotated.
+    inode: u64,
+
+    /// How far the log has been read.
+    position: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl LogTail {
+    /// Creates a tail of the logs of `source` in `log_dir`, with the last `lines` lines of its
+    /// current log pending.
+    async fn new(
+        log_dir: PathBuf,
+        source: LogSource,
+        server_prefix: String,
+        options: &TailOptions,
+    ) -> FsResult<Self> {
+        let mut tail = Self {
+            log_dir,
+            source,
+            server_prefix,
+            follow: *options.get_follow(),
+            filter: options.get_filter().clone(),
+            current: None,
+            pending: VecDeque::new(),
+            partial: String::new(),
+        };
+
+        tail.poll().await?;
+        while tail.pending.len() > *options.get_lines() {
+            tail.pending.pop_front();
+        }
+
+        // Without following, an unterminated last line is not going to be completed
+        if !tail.follow && !tail.partial.is_empty() {
+            let partial = std::mem::take(&mut tail.partial);
+            tail.push_line(partial);
+            if tail.pending.len() > *options.get_lines() {
+                tail.pending.pop_front();
+            }
+        }
+
+        Ok(tail)
+    }
+
+    /// Returns the next line, waiting for one if the log is followed.
+    ///
+    /// Returns `None` once every line is read and the log is not followed.
+    async fn next_line(&mut self) -> FsResult<Option<LogLine>> {
+        loop {
+            if let Some(line) = self.pending.pop_front() {
+                let path = self
+                    .current
+                    .as_ref()
+                    .map(|log| log.path.clone())
+                    .unwrap_or_default();
+                return Ok(Some(LogLine {
+                    source: self.source,
+                    path,
+                    line,
+                }));
+            }
+
+            if !self.follow {
+                return Ok(None);
+            }
+
+            if !self.poll().await? {
+                tokio::time::sleep(LOG_FOLLOW_INTERVAL).await;
+            }
+        }
+    }
+
+    /// Reads what was added to the current log since it was last read, switching to a newer log
+    /// first if the log was rotated or the server restarted.
+    ///
+    /// Returns whether anything was read.
+    async fn poll(&mut self) -> FsResult<bool> {
+        let Some(path) = self.find_current_log().await? else {
+            return Ok(false);
+        };
+
+        let metadata = fs::metadata(&path).await?;
+        let switched = match &self.current {
+            Some(log) => {
+                log.path != path || log.inode != metadata.ino() || metadata.len() < log.position
+            }
+            None => true,
+        };
+
+        if switched {
+            self.partial.clear();
+            self.current = Some(OpenLog {
+                path,
+                inode: metadata.ino(),
+                position: 0,
+            });
+        }
+
+        let Some(log) = self.current.as_mut() else {
+            return Ok(false);
+        };
+
+        let mut file = fs::File::open(&log.path).await?;
+        file.seek(SeekFrom::Start(log.position)).await?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).await?;
+        if bytes.is_empty() {
+            return Ok(false);
+        }
+
+        log.position += bytes.len() as u64;
+        self.partial.push_str(&String::from_utf8_lossy(&bytes));
+        while let Some(end) = self.partial.find('\n') {
+            let line = self.partial[..end].trim_end_matches('\r').to_string();
+            self.partial.drain(..=end);
+            self.push_line(line);
+        }
+
+        Ok(true)
+    }
+
+    /// Queues `line` to be returned, unless it is filtered out.
+    fn push_line(&mut self, line: String) {
+        let keep = self
+            .filter
+            .as_ref()
+            .is_none_or(|filter| line.contains(filter.as_str()));
+        if keep {
+            self.pending.push_back(line);
+        }
+    }
+
+    /// Returns the most recently modified log of the process, if there is any.
+    ///
+    /// Rotated logs are renamed away from the log suffix, so only the current logs of the server
+    /// and of each of its restarts are considered.
+    async fn find_current_log(&self) -> FsResult<Option<PathBuf>> {
+        let mut entries = match fs::read_dir(&self.log_dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let suffix = format!(".{}", LOG_SUFFIX);
+        let mut newest = None;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.ends_with(&suffix) || !entry.file_type().await?.is_file() {
+                continue;
+            }
+
+            let source = if name.starts_with(&self.server_prefix) {
+                LogSource::Server
+            } else {
+                LogSource::Supervisor
+            };
+            if source != self.source {
+                continue;
+            }
+
+            let modified = entry.metadata().await?.modified()?;
+            if newest.as_ref().is_none_or(|(time, _)| modified > *time) {
+                newest = Some((modified, entry.path()));
+            }
+        }
+
+        Ok(newest.map(|(_, path)| path))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Read the logs of the server and the supervisor of a filesystem
+///
+/// The logs are kept in the `log` directory of the filesystem's data directory. Each start of the
+/// server writes a new log, so only the most recent log of each process is read. When following,
+/// newer logs are picked up as the server restarts and the logs are rotated, and the stream only
+/// ends when it is dropped.
+///
+/// Without following, the lines of the server come before those of the supervisor. When
+/// following, the lines of both are returned as they are written.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `options` - Which logs to read, how much of them, and whether to follow them
+///
+/// ## Example
+/// ```no_run
+/// use futures::StreamExt;
+/// use monofs::{config::TailOptions, management};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let options = TailOptions::builder().follow(true).build();
+/// let mut lines = management::tail_logs(Some("mfstest".into()), options).await?;
+/// while let Some(line) = lines.next().await {
+///     println!("{}", line?.get_line());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn tail_logs(
+    mount_dir: Option<PathBuf>,
+    options: TailOptions,
+) -> FsResult<BoxStream<'static, FsResult<LogLine>>> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let child_name = paths
+        .get_mount_dir()
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    tail_log_dir(&paths.log_dir(), &child_name, options).await
+}
+
+/// Read the logs in `log_dir` of the server named `child_name` and its supervisor, as described
+/// by `options`.
+pub(crate) async fn tail_log_dir(
+    log_dir: &Path,
+    child_name: &str,
+    options: TailOptions,
+) -> FsResult<BoxStream<'static, FsResult<LogLine>>> {
+    let server_prefix = format!("{}-{}-", MFSRUN_LOG_PREFIX, child_name);
+    let sources = match options.get_source() {
+        LogSource::All => vec![LogSource::Server, LogSource::Supervisor],
+        source => vec![*source],
+    };
+
+    let mut streams = Vec::new();
+    for source in sources {
+        let tail = LogTail::new(
+            log_dir.to_path_buf(),
+            source,
+            server_prefix.clone(),
+            &options,
+        )
+        .await?;
+        let lines = stream::try_unfold(tail, |mut tail| async move {
+            Ok::<_, FsError>(tail.next_line().await?.map(|line| (line, tail)))
+        });
+        streams.push(lines.boxed());
+    }
+
+    if *options.get_follow() {
+        Ok(stream::select_all(streams).boxed())
+    } else {
+        Ok(stream::iter(streams).flatten().boxed())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+    use tempfile::tempdir;
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tail_log_dir() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let log_dir = temp_dir.path();
+        let server_log = log_dir.join(format!("mfsrun-mnt-1-100.{}", LOG_SUFFIX));
+        let supervisor_log = log_dir.join(format!("supervisor.{}", LOG_SUFFIX));
+        fs::write(&server_log, "one\ntwo error\nthree\nfour error").await?;
+        fs::write(&supervisor_log, "started\n").await?;
+        fs::write(log_dir.join("mfsrun-mnt-0-99.log.old"), "rotated\n").await?;
+
+        // The last lines of each log are read, server first
+        let options = TailOptions::builder().lines(2).build();
+        let lines = tail_log_dir(log_dir, "mnt", options)
+            .await?
+            .map_ok(|line| line.get_line().clone())
+            .try_collect::<Vec<_>>()
+            .await?;
+        assert_eq!(lines, ["three", "four error", "started"]);
+
+        // Lines can be filtered
+        let options = TailOptions::builder()
+            .source(LogSource::Server)
+            .filter(Some("error".to_string()))
+            .build();
+        let lines = tail_log_dir(log_dir, "mnt", options)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].get_line(), "two error");
+        assert_eq!(lines[0].get_source(), &LogSource::Server);
+        assert_eq!(lines[0].get_path(), &server_log);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tail_log_dir_follow() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let log_dir = temp_dir.path();
+        let server_log = log_dir.join(format!("mfsrun-mnt-1-100.{}", LOG_SUFFIX));
+        fs::write(&server_log, "old\n").await?;
+
+        let options = TailOptions::builder()
+            .source(LogSource::Server)
+            .lines(0)
+            .follow(true)
+            .build();
+        let mut lines = tail_log_dir(log_dir, "mnt", options).await?;
+
+        // Lines are read as they are completed
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(&server_log)
+            .await?;
+        file.write_all(b"new").await?;
+        file.flush().await?;
+        tokio::time::sleep(LOG_FOLLOW_INTERVAL * 2).await;
+        file.write_all(b" line\n").await?;
+        file.flush().await?;
+        let line = lines.next().await.unwrap()?;
+        assert_eq!(line.get_line(), "new line");
+
+        // A restarted server writes a new log, which is followed from its start
+        let restarted_log = log_dir.join(format!("mfsrun-mnt-2-200.{}", LOG_SUFFIX));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        fs::write(&restarted_log, "restarted\n").await?;
+        let line = lines.next().await.unwrap()?;
+        assert_eq!(line.get_line(), "restarted");
+        assert_eq!(line.get_path(), &restarted_log);
+
+        Ok(())
+    }
+}
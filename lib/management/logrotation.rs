@@ -0,0 +1,392 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_compression::tokio::write::GzipEncoder;
+use getset::Getters;
+use microsandbox_utils::LOG_SUFFIX;
+use serde::Serialize;
+use sqlx::{Pool, Row, Sqlite};
+use tokio::{
+    fs,
+    io::{self, AsyncWriteExt},
+};
+
+use crate::{
+    config::LogRotationConfig,
+    management::{db, find},
+    FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The suffix of compressed rotated logs.
+const GZIP_SUFFIX: &str = ".gz";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The log rotation settings of a filesystem, in its fs database.
+///
+/// A filesystem without a record uses the default settings. The supervisor of the filesystem
+/// reads the settings as it runs, so changes apply without restarting it.
+#[derive(Debug, Clone)]
+pub struct FsLogRotation {
+    /// The filesystem database.
+    fs_db: Pool<Sqlite>,
+
+    /// The mount directory identifying the filesystem.
+    mount_dir: PathBuf,
+}
+
+/// The outcome of compressing and expiring the rotated logs of a filesystem.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct LogSweep {
+    /// The number of rotated logs compressed.
+    compressed_files: u64,
+
+    /// The number of rotated logs deleted.
+    removed_files: u64,
+
+    /// The number of bytes of the rotated logs deleted.
+    removed_bytes: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FsLogRotation {
+    /// Opens the log rotation record of the filesystem mounted at `mount_dir`.
+    pub async fn new(
+        fs_db_path: impl AsRef<Path>,
+        mount_dir: impl Into<PathBuf>,
+    ) -> FsResult<Self> {
+        Ok(Self {
+            fs_db: db::get_db_pool(fs_db_path.as_ref()).await?,
+            mount_dir: mount_dir.into(),
+        })
+    }
+
+    /// Returns the log rotation settings of the filesystem, or `None` if it uses the defaults.
+    pub async fn get(&self) -> FsResult<Option<LogRotationConfig>> {
+        let record = sqlx::query(
+            r#"
+            SELECT max_bytes, max_age_secs, compress, max_files, retention_secs
+            FROM log_rotations
+            WHERE mount_dir = ?
+            "#,
+        )
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .fetch_optional(&self.fs_db)
+        .await?;
+
+        Ok(record.map(|row| {
+            LogRotationConfig::builder()
+                .max_bytes(row.get::<i64, _>("max_bytes") as u64)
+                .max_age_secs(
+                    row.get::<Option<i64>, _>("max_age_secs")
+                        .map(|secs| secs as u64),
+                )
+                .compress(row.get("compress"))
+                .max_files(row.get::<i64, _>("max_files") as u64)
+                .retention_secs(
+                    row.get::<Option<i64>, _>("retention_secs")
+                        .map(|secs| secs as u64),
+                )
+                .build()
+        }))
+    }
+
+    /// Records `config` as the log rotation settings of the filesystem.
+    pub async fn set(&self, config: &LogRotationConfig) -> FsResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO log_rotations
+                (mount_dir, max_bytes, max_age_secs, compress, max_files, retention_secs)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT (mount_dir) DO UPDATE
+            SET max_bytes = excluded.max_bytes,
+                max_age_secs = excluded.max_age_secs,
+                compress = excluded.compress,
+                max_files = excluded.max_files,
+                retention_secs = excluded.retention_secs,
+                modified_at = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .bind(*config.get_max_bytes() as i64)
+        .bind(config.get_max_age_secs().map(|secs| secs as i64))
+        .bind(*config.get_compress())
+        .bind(*config.get_max_files() as i64)
+        .bind(config.get_retention_secs().map(|secs| secs as i64))
+        .execute(&self.fs_db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Forgets the log rotation settings of the filesystem, so that it uses the defaults.
+    pub async fn clear(&self) -> FsResult<()> {
+        sqlx::query("DELETE FROM log_rotations WHERE mount_dir = ?")
+            .bind(self.mount_dir.to_string_lossy().to_string())
+            .execute(&self.fs_db)
+            .await?;
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Set how the logs of a monofs filesystem are rotated, compressed and expired
+///
+/// The logs of the server and the supervisor are kept in the `log` directory of the filesystem's
+/// data directory. The current log of the server is rotated once it grows past the size or the
+/// age set, and the supervisor compresses the rotated logs and deletes the oldest ones past the
+/// number and the age kept. Filesystems use the default settings until they are set.
+///
+/// The settings are recorded in the filesystem's database, and the supervisor picks them up
+/// within a minute.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `config` - The log rotation settings, or `None` to go back to the defaults
+///
+/// ## Example
+/// ```no_run
+/// use monofs::{config::LogRotationConfig, management};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let config = LogRotationConfig::builder().max_files(30).build();
+/// management::set_log_rotation(Some("mfstest".into()), Some(config)).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn set_log_rotation(
+    mount_dir: Option<PathBuf>,
+    config: Option<LogRotationConfig>,
+) -> FsResult<()> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let record = FsLogRotation::new(paths.fs_db_path(), paths.get_mount_dir()).await?;
+    match config {
+        Some(config) => {
+            config.validate()?;
+            record.set(&config).await?;
+            tracing::info!("rotating logs {:?}", config);
+        }
+        None => {
+            record.clear().await?;
+            tracing::info!("rotating logs with the default settings");
+        }
+    }
+
+    Ok(())
+}
+
+/// Get how the logs of a monofs filesystem are rotated, compressed and expired
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let config = management::get_log_rotation(None).await?;
+/// println!("keeping {} rotated logs", config.get_max_files());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn get_log_rotation(mount_dir: Option<PathBuf>) -> FsResult<LogRotationConfig> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let record = FsLogRotation::new(paths.fs_db_path(), paths.get_mount_dir()).await?;
+    Ok(record.get().await?.unwrap_or_default())
+}
+
+/// Returns the path a log at `path` is renamed to when it is rotated at `rotated_at`.
+///
+/// The time of the rotation is appended to the name, so rotated logs no longer end with the log
+/// suffix and sort in the order they were rotated.
+pub(crate) fn get_rotated_log_path(path: &Path, rotated_at: SystemTime) -> PathBuf {
+    let millis = rotated_at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+
+    let mut rotated = OsString::from(path.as_os_str());
+    rotated.push(format!(".{}", millis));
+    PathBuf::from(rotated)
+}
+
+/// Compresses and expires the rotated logs in `log_dir`, as `config` asks.
+///
+/// Every file of the directory that does not end with the log suffix is a rotated log. Those
+/// rotated by the supervisor's own log writer are first renamed with the time they were last
+/// written, so that its next rotation does not overwrite them.
+pub(crate) async fn sweep_logs(log_dir: &Path, config: &LogRotationConfig) -> FsResult<LogSweep> {
+    let mut sweep = LogSweep::default();
+    let mut entries = match fs::read_dir(log_dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(sweep),
+        Err(e) => return Err(e.into()),
+    };
+
+    let suffix = format!(".{}", LOG_SUFFIX);
+    let mut rotated = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.ends_with(&suffix) || !entry.file_type().await?.is_file() {
+            continue;
+        }
+
+        let mut path = entry.path();
+        let rotated_at = match get_rotated_at(&name) {
+            Some(rotated_at) => rotated_at,
+            None => {
+                let rotated_at = entry.metadata().await?.modified()?;
+                let stamped = get_rotated_log_path(&path, rotated_at);
+                fs::rename(&path, &stamped).await?;
+                path = stamped;
+                rotated_at
+            }
+        };
+
+        if *config.get_compress() && !name.ends_with(GZIP_SUFFIX) {
+            path = compress_log(&path).await?;
+            sweep.compressed_files += 1;
+        }
+
+        rotated.push((rotated_at, path));
+    }
+
+    // Keep the newest logs
+    rotated.sort_by(|a, b| b.0.cmp(&a.0));
+    let now = SystemTime::now();
+    for (index, (rotated_at, path)) in rotated.into_iter().enumerate() {
+        let age = now.duration_since(rotated_at).unwrap_or_default();
+        let expired = config
+            .get_retention()
+            .is_some_and(|retention| age > retention);
+        if (index as u64) < *config.get_max_files() && !expired {
+            continue;
+        }
+
+        let len = fs::metadata(&path).await?.len();
+        fs::remove_file(&path).await?;
+        sweep.removed_files += 1;
+        sweep.removed_bytes += len;
+    }
+
+    Ok(sweep)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns when the log named `name` was rotated, if its name records it.
+fn get_rotated_at(name: &str) -> Option<SystemTime> {
+    let name = name.strip_suffix(GZIP_SUFFIX).unwrap_or(name);
+    let (_, stamp) = name.rsplit_once('.')?;
+    if stamp.is_empty() || !stamp.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let millis = stamp.parse().ok()?;
+    Some(UNIX_EPOCH + Duration::from_millis(millis))
+}
+
+/// Compresses the rotated log at `path` with gzip, replacing it.
+///
+/// Returns the path of the compressed log.
+async fn compress_log(path: &Path) -> FsResult<PathBuf> {
+    let mut compressed_path = OsString::from(path.as_os_str());
+    compressed_path.push(GZIP_SUFFIX);
+    let compressed_path = PathBuf::from(compressed_path);
+
+    let mut encoder = GzipEncoder::new(fs::File::create(&compressed_path).await?);
+    io::copy(&mut fs::File::open(path).await?, &mut encoder).await?;
+    encoder.shutdown().await?;
+    fs::remove_file(path).await?;
+
+    Ok(compressed_path)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use async_compression::tokio::bufread::GzipDecoder;
+    use tempfile::tempdir;
+    use tokio::io::{AsyncReadExt, BufReader};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sweep_logs() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let log_dir = temp_dir.path();
+        let current_log = log_dir.join(format!("mfsrun-mnt-1-100.{}", LOG_SUFFIX));
+        fs::write(&current_log, "current\n").await?;
+        fs::write(log_dir.join("supervisor.log.old"), "supervisor\n").await?;
+
+        let now = SystemTime::now();
+        let mut server_logs = Vec::new();
+        for secs in [1, 2, 3, 2 * 60 * 60] {
+            let path = get_rotated_log_path(&current_log, now - Duration::from_secs(secs));
+            fs::write(&path, format!("rotated {}\n", secs)).await?;
+            server_logs.push(path);
+        }
+
+        // Rotated logs are compressed, and the oldest past the age and the number kept deleted
+        let config = LogRotationConfig::builder()
+            .max_files(3)
+            .retention_secs(Some(60 * 60))
+            .build();
+        let sweep = sweep_logs(log_dir, &config).await?;
+        assert_eq!(sweep.get_compressed_files(), &5);
+        assert_eq!(sweep.get_removed_files(), &2);
+
+        let mut names = Vec::new();
+        let mut entries = fs::read_dir(log_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            names.push(entry.file_name().to_string_lossy().to_string());
+        }
+        names.sort();
+        assert_eq!(names.len(), 4);
+        assert!(names.contains(&"mfsrun-mnt-1-100.log".to_string()));
+        assert!(names
+            .iter()
+            .any(|name| name.starts_with("supervisor.log.old.") && name.ends_with(".gz")));
+        for path in &server_logs[..2] {
+            let name = path.file_name().unwrap().to_string_lossy();
+            assert!(names.contains(&format!("{}.gz", name)));
+        }
+
+        // The current log is left alone and the compressed logs hold the rotated lines
+        assert_eq!(fs::read_to_string(&current_log).await?, "current\n");
+        let compressed = fs::File::open(format!("{}.gz", server_logs[0].display())).await?;
+        let mut decoder = GzipDecoder::new(BufReader::new(compressed));
+        let mut contents = String::new();
+        decoder.read_to_string(&mut contents).await?;
+        assert_eq!(contents, "rotated 1\n");
+
+        // Compressed logs are not compressed again
+        let sweep = sweep_logs(log_dir, &config).await?;
+        assert_eq!(sweep, LogSweep::default());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use std::path::{Path, PathBuf};
+
+use sqlx::{Pool, Row, Sqlite};
+
+use crate::{config::MemoryStoreConfig, management::db, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Tracks whether a filesystem keeps the blocks it writes in memory, in its fs database.
+///
+/// The setting is recorded when the filesystem is initialized or cloned, and used by its server
+/// every time it starts.
+#[derive(Debug, Clone)]
+pub struct FsMemoryStore {
+    /// The filesystem database.
+    fs_db: Pool<Sqlite>,
+
+    /// The mount directory identifying the filesystem.
+    mount_dir: PathBuf,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FsMemoryStore {
+    /// Opens the in-memory store record of the filesystem mounted at `mount_dir`.
+    pub async fn new(
+        fs_db_path: impl AsRef<Path>,
+        mount_dir: impl Into<PathBuf>,
+    ) -> FsResult<Self> {
+        Ok(Self {
+            fs_db: db::get_db_pool(fs_db_path.as_ref()).await?,
+            mount_dir: mount_dir.into(),
+        })
+    }
+
+    /// Returns how the filesystem keeps its blocks in memory, if it does.
+    pub async fn get(&self) -> FsResult<Option<MemoryStoreConfig>> {
+        let record =
+            sqlx::query("SELECT max_bytes, spill_on_detach FROM memory_stores WHERE mount_dir = ?")
+                .bind(self.mount_dir.to_string_lossy().to_string())
+                .fetch_optional(&self.fs_db)
+                .await?;
+
+        Ok(record.map(|row| {
+            MemoryStoreConfig::builder()
+                .max_bytes(row.get::<i64, _>("max_bytes") as u64)
+                .spill_on_detach(row.get::<bool, _>("spill_on_detach"))
+                .build()
+        }))
+    }
+
+    /// Records that the filesystem keeps its blocks in memory as described by `memory`.
+    pub async fn set(&self, memory: &MemoryStoreConfig) -> FsResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO memory_stores (mount_dir, max_bytes, spill_on_detach)
+            VALUES (?, ?, ?)
+            ON CONFLICT (mount_dir) DO UPDATE
+            SET max_bytes = excluded.max_bytes,
+                spill_on_detach = excluded.spill_on_detach,
+                modified_at = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .bind(*memory.get_max_bytes() as i64)
+        .bind(*memory.get_spill_on_detach())
+        .execute(&self.fs_db)
+        .await?;
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::management::FS_DB_MIGRATOR;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fs_memory_store_get_set() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("fs.db");
+        db::init_db(&db_path, &FS_DB_MIGRATOR).await?;
+
+        let memory = FsMemoryStore::new(&db_path, temp_dir.path().join("mnt")).await?;
+        assert_eq!(memory.get().await?, None);
+
+        let config = MemoryStoreConfig::default();
+        memory.set(&config).await?;
+        assert_eq!(memory.get().await?, Some(config));
+
+        let config = MemoryStoreConfig::builder()
+            .max_bytes(1024)
+            .spill_on_detach(true)
+            .build();
+        memory.set(&config).await?;
+        assert_eq!(memory.get().await?, Some(config));
+
+        Ok(())
+    }
+}
@@ -1,19 +1,129 @@
 use crate::{
-    config::{DEFAULT_HOST, DEFAULT_MFSRUN_EXE_PATH, DEFAULT_NFS_PORT},
-    management::{db, find, FS_DB_MIGRATOR},
+    cli::{ChunkerArgs, CompressionArgs, ExportArgs, ThrottleArgs, TransferArgs, WriteBackArgs},
+    config::{
+        self, EncryptionKey, InitOptions, MountBackend, PortRange, RootSubtree, TransferConfig,
+        UserConfig, DEFAULT_HOST, DEFAULT_MFSRUN_EXE_PATH, DEFAULT_MOUNT_TIMEOUT,
+    },
+    filesystem::Dir,
+    management::{
+        db, find, head, mirror, registry, status, FsAuditLog, FsHead, FsMemoryStore, FsMirrors,
+        FsQuota, FsRemote, FsStoreHash, FsSubtree, MfsPaths, MountHealth, FS_DB_MIGRATOR,
+    },
+    server::{self, ControlRequest, ControlResponse},
     utils::{
-        path::{BLOCKS_SUBDIR, FS_DB_FILENAME, LOG_SUBDIR, MFS_DIR_SUFFIX, MFS_LINK_FILENAME},
+        self,
+        path::{
+            BLOCKS_SUBDIR, CONTROL_SOCKET_FILENAME, FS_DB_FILENAME, LOG_SUBDIR, MFS_DIR_SUFFIX,
+            MFS_LINK_FILENAME,
+        },
         MFSRUN_EXE_ENV_VAR,
     },
     FsError, FsResult,
 };
-use nix::{
-    sys::signal::{self, Signal},
-    unistd::Pid,
+use getset::Getters;
+use ipldstore::{ipld::cid::Cid, Storable};
+use serde::Serialize;
+use sqlx::{sqlite::SqliteRow, Row};
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
 };
-use sqlx::Row;
-use std::path::{Path, PathBuf};
-use tokio::{fs, net::TcpStream, process::Command, time, time::Instant};
+use tokio::{
+    fs,
+    net::TcpStream,
+    process::{Child, Command},
+    time,
+    time::Instant,
+};
+use tokio_util::sync::CancellationToken;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The maximum number of times to check whether a FUSE mount has become active
+const MAX_FUSE_MOUNT_CHECKS: u32 = 200;
+
+/// How long detaching waits for the server to record its final root and for the supervisor to
+/// exit
+const SHUTDOWN_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+
+/// How long checking whether a directory is a mount point may take before the mount counts as
+/// hung
+const MOUNT_PROBE_TIMEOUT: time::Duration = time::Duration::from_secs(2);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A monofs filesystem that was mounted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct MfsMount {
+    /// The directory where the filesystem is mounted.
+    mount_dir: PathBuf,
+
+    /// The directory where the filesystem's data is stored.
+    data_dir: PathBuf,
+
+    /// The mechanism the filesystem is mounted with.
+    backend: MountBackend,
+
+    /// The port the filesystem is served on, if it is served over NFS.
+    port: Option<u32>,
+
+    /// The PID of the supervisor serving the filesystem, if it is known.
+    supervisor_pid: Option<u32>,
+}
+
+/// A monofs filesystem that was detached.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct DetachReport {
+    /// The directory where the filesystem was mounted.
+    mount_dir: PathBuf,
+
+    /// The final root recorded by the server before it stopped, if it recorded one.
+    #[serde(serialize_with = "utils::serialize_optional_display")]
+    root: Option<Cid>,
+}
+
+/// A monofs filesystem that was repaired with [`repair_mount`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct RepairReport {
+    /// The directory where the filesystem is mounted.
+    mount_dir: PathBuf,
+
+    /// The state the filesystem was found in.
+    health: MountHealth,
+
+    /// The PID of the supervisor that was stopped, if one was still running.
+    stopped_supervisor_pid: Option<u32>,
+
+    /// Whether the filesystem was still mounted when it was found.
+    unmounted: bool,
+
+    /// Where the filesystem was attached again, if it was.
+    mount: Option<MfsMount>,
+}
+
+/// A mount served by the server of another filesystem, as recorded in the fs database.
+struct SharedMountRecord {
+    /// The directory where the shared mount is mounted.
+    mount_dir: PathBuf,
+
+    /// The mount directory of the filesystem whose server serves the shared mount.
+    served_by: PathBuf,
+
+    /// The address the shared mount is served on.
+    host: String,
+
+    /// The port the shared mount is served on.
+    port: u32,
+}
 
 //--------------------------------------------------------------------------------------------------
 // Functions
@@ -23,57 +133,1111 @@ use tokio::{fs, net::TcpStream, process::Command, time, time::Instant};
 ///
 /// ## Arguments
 /// * `mount_dir` - The path where the filesystem will be initialized and mounted. If None, uses current directory
+/// * `options` - How to mount and serve the filesystem
 ///
 /// ## Returns
-/// The port number that was successfully used for mounting
+/// Where the filesystem was mounted, along with the port it is served on and the PID of its
+/// supervisor
+///
+/// ## Existing Mounts
+/// If a healthy monofs filesystem is already mounted at `mount_dir`, it is returned as is instead
+/// of starting another supervisor for it, so retrying initialization is safe. `options` are not
+/// applied to it. A filesystem that is mounted there but whose server does not answer is an
+/// error, as it has to be detached first.
+///
+/// ## Defaults
+/// The mount backend, host, port range, chunker, compression and quota that `options` leaves
+/// unset are taken from the user's configuration file and the environment variables overriding
+/// it, or else from the built-in defaults. See [`UserConfig`].
+///
+/// ## Privileges
+/// Root is not needed to mount with the FUSE backend, which goes through the `fusermount3` helper
+/// when the process cannot mount by itself, or with the NFS backend on macOS, where the owner of
+/// the mount directory may mount it. Only the NFS backend on Linux takes root, so the backend
+/// defaults to FUSE there for other users, and choosing NFS fails before anything is started.
+///
+/// ## Exports
+/// The NFS server trusts the user IDs its clients send, so a server bound to an address other
+/// than loopback lets every host that reaches it act as any user. The exports in `options`
+/// restrict the networks clients may connect from, squash the owners they set, and can have the
+/// server check the permissions of the users they act as, see
+/// [`ExportConfig`](crate::config::ExportConfig). They are taken from the user's configuration
+/// when the filesystem is attached again.
+///
+/// ## Throttling
+/// The throttle in `options` limits how many requests the NFS server serves, and how many bytes
+/// it moves, each second, across all clients and for each client connection, so that a runaway
+/// client cannot saturate the host disk. Requests past a limit are delayed rather than refused.
+/// Like the exports, it is taken from the user's configuration when the filesystem is attached
+/// again. See [`ThrottleConfig`](crate::config::ThrottleConfig).
+///
+/// ## Encryption
+/// If an encryption key is given in the environment, either in hex in `MONOFS_ENCRYPTION_KEY`
+/// or in a file named by `MONOFS_ENCRYPTION_KEY_FILE`, the filesystem's blocks and database are
+/// encrypted with it at rest. The same key must then be in the environment of everything that
+/// touches the filesystem afterwards, including [`attach_mfs`] and the other management
+/// functions. See [`EncryptionKey`].
+///
+/// ## Quota
+/// The quota in `options` is recorded in the filesystem's database and enforced by its server
+/// from then on, including after the filesystem is attached again. See
+/// [`QuotaConfig`](crate::config::QuotaConfig).
+///
+/// ## Remote Store
+/// If `options` has a remote store, every block is written to it as well, and the blocks
+/// directory only keeps a cache of the most recently used blocks. The remote store is recorded
+/// in the filesystem's database, so it is used from then on. See
+/// [`RemoteConfig`](crate::config::RemoteConfig).
+///
+/// ## In-Memory Store
+/// If `options` has an in-memory store, the server keeps the blocks the filesystem writes in
+/// memory, up to a size cap, instead of writing them to the blocks directory. What is written is
+/// discarded when the filesystem is detached, unless the store is set to spill the final root to
+/// the blocks directory. The setting is recorded in the filesystem's database, so it applies from
+/// then on. See [`MemoryStoreConfig`](crate::config::MemoryStoreConfig).
+///
+/// ## Mirrors
+/// If `options` has mirrors, every block file and packfile of the filesystem is also written to
+/// each of them, so its blocks survive losing the disk of the blocks directory or of any mirror.
+/// Corrupt and missing blocks are repaired from the mirrors as they are read, and
+/// [`resilver_mfs`](crate::management::resilver_mfs) brings a replaced disk back in line. The
+/// mirrors are recorded in the filesystem's database, so they are used from then on.
+///
+/// ## Subtree
+/// If `options` has a subtree, the mount serves that directory as its root instead of the whole
+/// root, like `chroot`. A path names a directory in the root, which is created if needed and
+/// recorded in the filesystem's database, so the same directory is served from then on while the
+/// rest of the root is kept. A CID names a directory the filesystem starts out from, whose blocks
+/// must already be in its store, e.g. in a remote store it shares with other filesystems. See
+/// [`RootSubtree`].
+///
+/// ## Audit
+/// If `options` enables auditing, the server records every entity created, written, renamed and
+/// removed through the mount in an append-only log in the filesystem's database, from then on.
+/// See [`audit_log`](crate::management::audit_log).
 ///
 /// ## Example
 /// ```no_run
-/// use monofs::management;
+/// use monofs::{config::InitOptions, management};
 ///
 /// # async fn example() -> anyhow::Result<()> {
-/// management::init_mfs(Some("mfstest".into())).await?;
+/// // Mount with the defaults
+/// management::init_mfs(Some("mfstest".into()), InitOptions::default()).await?;
+///
+/// // Serve NFS on a pinned port on all interfaces
+/// let options = InitOptions::builder()
+///     .host(Some("0.0.0.0".into()))
+///     .fixed_port(Some(3049))
+///     .build();
+/// management::init_mfs(Some("mfsshared".into()), options).await?;
 /// # Ok(())
 /// # }
 /// ```
-pub async fn init_mfs(mount_dir: Option<PathBuf>) -> FsResult<u32> {
+pub async fn init_mfs(mount_dir: Option<PathBuf>, options: InitOptions) -> FsResult<MfsMount> {
+    init_mfs_with_cancel(mount_dir, options, CancellationToken::new()).await
+}
+
+/// Initialize a new monofs filesystem like [`init_mfs`], giving up when `cancel` is cancelled
+///
+/// Cancelling stops the supervisor if it was started, unmounts the filesystem if it was mounted
+/// and removes the directories initialization created, the same as when mounting fails or does
+/// not finish within the mount timeout in `options`.
+///
+/// ## Arguments
+/// * `mount_dir` - The path where the filesystem will be initialized and mounted. If None, uses current directory
+/// * `options` - How to mount and serve the filesystem
+/// * `cancel` - Cancels initialization when it is cancelled
+///
+/// ## Example
+/// ```no_run
+/// use monofs::{config::InitOptions, management};
+/// use tokio_util::sync::CancellationToken;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let cancel = CancellationToken::new();
+/// tokio::spawn({
+///     let cancel = cancel.clone();
+///     async move {
+///         tokio::signal::ctrl_c().await.ok();
+///         cancel.cancel();
+///     }
+/// });
+///
+/// management::init_mfs_with_cancel(Some("mfstest".into()), InitOptions::default(), cancel)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn init_mfs_with_cancel(
+    mount_dir: Option<PathBuf>,
+    options: InitOptions,
+    cancel: CancellationToken,
+) -> FsResult<MfsMount> {
+    // Fill in what the options leave unset from the user's configuration
+    let options = options.with_defaults(&UserConfig::load()?);
+
     // Default to current directory if no path specified
     let mount_dir = mount_dir.unwrap_or_else(|| PathBuf::from("."));
+    let created_mount_dir = !fs::try_exists(&mount_dir).await?;
     fs::create_dir_all(&mount_dir).await?;
 
     // Ensure the mount directory is absolute
     let mount_dir = fs::canonicalize(&mount_dir).await?;
     tracing::info!("mount point available at {}", mount_dir.display());
 
+    // Reuse a filesystem that is already served here, e.g. by an earlier attempt
+    if let Some(mount) = get_healthy_mount(&mount_dir).await? {
+        tracing::info!(
+            "filesystem already mounted at {}, reusing it",
+            mount_dir.display()
+        );
+        return Ok(mount);
+    }
+
     // Create the .mfs directory adjacent to the mount point
     let mfs_data_dir = PathBuf::from(format!("{}.{}", mount_dir.display(), MFS_DIR_SUFFIX));
+    let created_data_dir = !fs::try_exists(&mfs_data_dir).await?;
     fs::create_dir_all(&mfs_data_dir).await?;
     tracing::info!(".mfs directory available at {}", mfs_data_dir.display());
 
-    // Find an available port
-    let port = super::find_available_port(DEFAULT_HOST, DEFAULT_NFS_PORT).await?;
-    tracing::info!("found available port: {}", port);
+    let result = async {
+        prepare_mfs(&mount_dir, &mfs_data_dir, &options).await?;
+        start_mfs(&mount_dir, &mfs_data_dir, &options, &cancel).await
+    }
+    .await;
+
+    // Leave nothing behind of a filesystem that could not be mounted
+    if result.is_err() {
+        if created_data_dir {
+            if let Err(e) = fs::remove_dir_all(&mfs_data_dir).await {
+                tracing::warn!("failed to remove {}: {}", mfs_data_dir.display(), e);
+            }
+        }
+
+        if created_mount_dir {
+            if let Err(e) = fs::remove_dir(&mount_dir).await {
+                tracing::warn!("failed to remove {}: {}", mount_dir.display(), e);
+            }
+        }
+    }
+
+    result
+}
+
+/// Set up the data directory and the fs database of a new filesystem for `options`
+pub(crate) async fn prepare_mfs(
+    mount_dir: &Path,
+    mfs_data_dir: &Path,
+    options: &InitOptions,
+) -> FsResult<()> {
+    // Check if mount point is empty before starting any server
+    let mut entries = fs::read_dir(mount_dir).await?;
+    if entries.next_entry().await?.is_some() {
+        return Err(FsError::MountPointNotEmpty(
+            mount_dir.to_string_lossy().to_string(),
+        ));
+    }
+
+    // Create required directories
+    let log_dir = mfs_data_dir.join(LOG_SUBDIR);
+    fs::create_dir_all(&log_dir).await?;
+    tracing::info!("log directory available at {}", log_dir.display());
+
+    // Create the fs database file
+    let fs_db_path = mfs_data_dir.join(FS_DB_FILENAME);
+    if !fs_db_path.exists() {
+        fs::File::create(&fs_db_path).await?;
+        tracing::info!("created fs database at {}", fs_db_path.display());
+    }
+
+    // Initialize the filesystem database schema
+    db::init_db(&fs_db_path, &FS_DB_MIGRATOR).await?;
+    tracing::info!("initialized fs database schema");
+
+    // Record the hash function, which the server and management functions load every time they
+    // open the blocks
+    FsStoreHash::new(&fs_db_path, mount_dir)
+        .await?
+        .set(*options.get_hash_function())
+        .await?;
+
+    // Record the quota, which the server loads every time it starts
+    FsQuota::new(&fs_db_path, mount_dir)
+        .await?
+        .set(&options.get_quota().unwrap_or_default())
+        .await?;
+
+    // Enable the audit log, which the server checks every time it starts
+    if *options.get_audit() {
+        FsAuditLog::new(&fs_db_path, mount_dir)
+            .await?
+            .enable()
+            .await?;
+    }
+
+    // Record the remote store, which the server and management functions load every time they
+    // open the blocks
+    if let Some(remote) = options.get_remote() {
+        remote.validate()?;
+        FsRemote::new(&fs_db_path, mount_dir)
+            .await?
+            .set(remote)
+            .await?;
+    }
+
+    // Record the in-memory store, which the server loads every time it starts
+    if let Some(memory) = options.get_memory() {
+        memory.validate()?;
+        FsMemoryStore::new(&fs_db_path, mount_dir)
+            .await?
+            .set(memory)
+            .await?;
+    }
+
+    // Record the mirrors, which the server and management functions load every time they open
+    // the blocks
+    let mirrors = options.get_mirrors();
+    mirror::prepare_mirrors(mirrors, mfs_data_dir).await?;
+    let fs_mirrors = FsMirrors::new(&fs_db_path, mount_dir).await?;
+    for path in mirrors {
+        fs_mirrors.add(path).await?;
+    }
+
+    // Create the blocks directory
+    let blocks_dir = mfs_data_dir.join(BLOCKS_SUBDIR);
+    fs::create_dir_all(&blocks_dir).await?;
+    tracing::info!("blocks directory available at {}", blocks_dir.display());
+
+    // Record the subtree to serve, which the server loads every time it starts, or start out from
+    // the directory to serve, making sure its blocks are there
+    match options.get_subtree() {
+        Some(subtree @ RootSubtree::Path(path)) => {
+            subtree.validate()?;
+            FsSubtree::new(&fs_db_path, mount_dir)
+                .await?
+                .set(path.trim_matches('/'))
+                .await?;
+        }
+        Some(RootSubtree::Cid(cid)) => {
+            let store = find::find_mfs_paths(mount_dir).await?.open_store().await?;
+            Dir::load(cid, store).await?;
+            FsHead::new(&fs_db_path, mount_dir)
+                .await?
+                .set(cid, "init")
+                .await?;
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// Attach an existing monofs filesystem back to its mount directory
+///
+/// This brings a filesystem whose server is no longer running, e.g. after a host reboot, back
+/// online. Its data directory (`<mount_dir>.mfs`) must already exist. The existing database and
+/// blocks are reused, the supervisor is restarted with the backend the filesystem was last
+/// mounted with, and the filesystem is remounted at the same path with the contents of its last
+/// recorded head.
+///
+/// If the server crashed before recording its head, the roots it checkpointed since are
+/// replayed from its intent log, so directory updates made before the crash are kept. See
+/// [`FsHead::recover`](crate::management::FsHead::recover).
+///
+/// The schema of the database is migrated to the one of this version first. A database
+/// migrated by a newer version is refused with [`FsError::DbVersionTooNew`] instead, as this
+/// version would not know what to make of it.
+///
+/// ## Arguments
+/// * `mount_dir` - The path where the filesystem was previously mounted. If None, uses current directory
+///
+/// ## Returns
+/// Where the filesystem was mounted, along with the port it is served on and the PID of its
+/// supervisor
+///
+/// An encrypted filesystem is attached with the encryption key it was initialized with, taken
+/// from the environment like in [`init_mfs`].
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// management::attach_mfs(Some("mfstest".into())).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn attach_mfs(mount_dir: Option<PathBuf>) -> FsResult<MfsMount> {
+    // Default to current directory if no path specified
+    let mount_dir = mount_dir.unwrap_or_else(|| PathBuf::from("."));
+    fs::create_dir_all(&mount_dir).await?;
+
+    // Ensure the mount directory is absolute
+    let mount_dir = fs::canonicalize(&mount_dir).await?;
+
+    // Find the existing .mfs directory adjacent to the mount point
+    let mfs_data_dir = PathBuf::from(format!("{}.{}", mount_dir.display(), MFS_DIR_SUFFIX));
+    if !fs::try_exists(&mfs_data_dir).await? {
+        return Err(FsError::MfsDataDirNotFound(
+            mfs_data_dir.to_string_lossy().to_string(),
+        ));
+    }
+    tracing::info!("found .mfs directory at {}", mfs_data_dir.display());
+
+    // Refuse to mount over a filesystem that is still attached
+    if fs::try_exists(mount_dir.join(MFS_LINK_FILENAME)).await?
+        || is_mount_point(&mount_dir).await?
+    {
+        return Err(FsError::AlreadyMounted(
+            mount_dir.to_string_lossy().to_string(),
+        ));
+    }
+
+    let mut entries = fs::read_dir(&mount_dir).await?;
+    if entries.next_entry().await?.is_some() {
+        return Err(FsError::MountPointNotEmpty(
+            mount_dir.to_string_lossy().to_string(),
+        ));
+    }
+
+    // Recreate any directories that went missing
+    fs::create_dir_all(mfs_data_dir.join(LOG_SUBDIR)).await?;
+    fs::create_dir_all(mfs_data_dir.join(BLOCKS_SUBDIR)).await?;
+
+    // Bring the database schema up to date
+    let fs_db_path = mfs_data_dir.join(FS_DB_FILENAME);
+    db::init_db(&fs_db_path, &FS_DB_MIGRATOR).await?;
+
+    // Refuse to serve blocks made with a hash function this version does not support
+    let hash_function = FsStoreHash::new(&fs_db_path, &mount_dir)
+        .await?
+        .get()
+        .await?;
+    tracing::info!("filesystem makes block CIDs with {}", hash_function);
+
+    // Shared mounts come back with the filesystem serving them
+    if let Some(record) = get_shared_mount(&fs_db_path, &mount_dir).await? {
+        return Err(FsError::InvalidOperation(format!(
+            "{} is served by {}, attach that filesystem instead",
+            mount_dir.display(),
+            record.served_by.display()
+        )));
+    }
+
+    // Remount with the backend the filesystem was last served by
+    let backend = get_mount_backend(&fs_db_path, &mount_dir).await?;
+    tracing::info!("attaching filesystem with the {} backend", backend);
+
+    // Serve metrics where they were served before
+    let metrics_addr = get_metrics_addr(&fs_db_path, &mount_dir).await?;
+
+    let options = InitOptions::builder()
+        .backend(Some(backend))
+        .metrics_addr(metrics_addr)
+        .build()
+        .with_defaults(&UserConfig::load()?);
+    let mount = start_mfs(
+        &mount_dir,
+        &mfs_data_dir,
+        &options,
+        &CancellationToken::new(),
+    )
+    .await?;
+
+    // The server serves the mounts sharing it again, which only need to be mounted
+    for record in list_shared_mounts(&fs_db_path, &mount_dir).await? {
+        let mfs_data_dir =
+            PathBuf::from(format!("{}.{}", record.mount_dir.display(), MFS_DIR_SUFFIX));
+        let result = async {
+            mount_fs(
+                &record.mount_dir,
+                &get_mount_host(&record.host),
+                record.port,
+                options.get_transfer(),
+                options.get_extra_mount_options(),
+                Instant::now() + *options.get_mount_timeout(),
+            )
+            .await?;
+            link_data_dir(&record.mount_dir, &mfs_data_dir).await
+        };
+        match result.await {
+            Ok(()) => registry::register_mount(&record.mount_dir).await,
+            Err(e) => tracing::warn!("failed to mount {}: {}", record.mount_dir.display(), e),
+        }
+    }
+
+    Ok(mount)
+}
+
+/// Clone a monofs filesystem into a new mount
+///
+/// The clone starts out with the current contents of the source and changes independently from
+/// then on. It shares the blocks directory of the source instead of copying it, so cloning takes
+/// the same time and space no matter how large the source is. Garbage collection on either side
+/// keeps the blocks that any filesystem sharing them still needs.
+///
+/// If the source is mounted, its server checkpoints the in-memory root first so the clone sees
+/// everything written so far. A source that keeps its blocks in an in-memory store is cloned
+/// from the state it last recorded on disk instead, and the clone keeps its blocks in memory
+/// too. The clone is mounted with the same backend as the source, and serves the same subtree
+/// of its root if the source serves one.
+///
+/// ## Arguments
+/// * `source_mount_dir` - The path where the filesystem to clone is mounted
+/// * `target_mount_dir` - The path where the clone will be mounted. It must be empty
+///
+/// ## Returns
+/// Where the clone was mounted, along with the port it is served on and the PID of its supervisor
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// management::clone_mfs("sandbox-image".into(), "sandbox-1".into()).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn clone_mfs(source_mount_dir: PathBuf, target_mount_dir: PathBuf) -> FsResult<MfsMount> {
+    let source = find::find_mfs_paths(&source_mount_dir).await?;
+
+    // The root of a source that keeps its blocks in memory only has its blocks on disk once its
+    // server spills them, so it is cloned from the state it last recorded
+    let memory = FsMemoryStore::new(source.fs_db_path(), source.get_mount_dir())
+        .await?
+        .get()
+        .await?;
+    let head = match memory {
+        Some(_) => {
+            FsHead::new(source.fs_db_path(), source.get_mount_dir())
+                .await?
+                .get()
+                .await?
+        }
+        None => head::checkpoint_head(&source).await?,
+    };
+
+    // Set up an empty mount directory without any data
+    fs::create_dir_all(&target_mount_dir).await?;
+    let target_mount_dir = fs::canonicalize(&target_mount_dir).await?;
+    let mfs_data_dir = PathBuf::from(format!("{}.{}", target_mount_dir.display(), MFS_DIR_SUFFIX));
+    if fs::try_exists(&mfs_data_dir).await? {
+        return Err(FsError::PathExists(
+            mfs_data_dir.to_string_lossy().to_string(),
+        ));
+    }
+
+    let mut entries = fs::read_dir(&target_mount_dir).await?;
+    if entries.next_entry().await?.is_some() {
+        return Err(FsError::MountPointNotEmpty(
+            target_mount_dir.to_string_lossy().to_string(),
+        ));
+    }
+
+    // Link to the blocks of the filesystem that owns them, so clones of clones share them too
+    let owner = find::find_blocks_owner(&source).await?;
+    fs::create_dir_all(mfs_data_dir.join(LOG_SUBDIR)).await?;
+    fs::symlink(owner.blocks_dir(), mfs_data_dir.join(BLOCKS_SUBDIR)).await?;
+    register_block_sharer(&owner, &target_mount_dir).await?;
+    tracing::info!(
+        "sharing blocks of {} with {}",
+        owner.get_mount_dir().display(),
+        target_mount_dir.display()
+    );
+
+    // Start the clone from the current root of the source
+    let fs_db_path = mfs_data_dir.join(FS_DB_FILENAME);
+    db::init_db(&fs_db_path, &FS_DB_MIGRATOR).await?;
+
+    // The shared blocks are cached from the remote store of the source, if it has one
+    if let Some(remote) = FsRemote::new(source.fs_db_path(), source.get_mount_dir())
+        .await?
+        .get()
+        .await?
+    {
+        FsRemote::new(&fs_db_path, &target_mount_dir)
+            .await?
+            .set(&remote)
+            .await?;
+    }
+
+    // Clones make the CIDs of their blocks like the source does
+    let hash_function = FsStoreHash::new(source.fs_db_path(), source.get_mount_dir())
+        .await?
+        .get()
+        .await?;
+    FsStoreHash::new(&fs_db_path, &target_mount_dir)
+        .await?
+        .set(hash_function)
+        .await?;
+
+    // Clones of a throwaway filesystem are throwaway too
+    if let Some(memory) = memory {
+        FsMemoryStore::new(&fs_db_path, &target_mount_dir)
+            .await?
+            .set(&memory)
+            .await?;
+    }
+
+    // Clones of a filesystem serving a subtree serve the same subtree
+    if let Some(path) = FsSubtree::new(source.fs_db_path(), source.get_mount_dir())
+        .await?
+        .get()
+        .await?
+    {
+        FsSubtree::new(&fs_db_path, &target_mount_dir)
+            .await?
+            .set(&path)
+            .await?;
+    }
+    if let Some(head) = head {
+        let operation = format!("clone {}", source.get_mount_dir().display());
+        FsHead::new(&fs_db_path, &target_mount_dir)
+            .await?
+            .set(&head, &operation)
+            .await?;
+    }
+
+    let backend = get_mount_backend(source.fs_db_path(), source.get_mount_dir()).await?;
+    let options = InitOptions::builder()
+        .backend(Some(backend))
+        .build()
+        .with_defaults(&UserConfig::load()?);
+    start_mfs(
+        &target_mount_dir,
+        &mfs_data_dir,
+        &options,
+        &CancellationToken::new(),
+    )
+    .await
+}
+
+/// Mount another view of a monofs filesystem's data at a new mount directory
+///
+/// The new mount is served by the server of the source filesystem and uses the same blocks and
+/// fs database, so no data is copied and no extra supervisor is started. It starts out at `root`,
+/// e.g. the root of a snapshot or of an entry in the history, or at the current root of the
+/// source, and changes independently from then on. Its head, history and snapshots are kept
+/// under its own mount directory.
+///
+/// Shared mounts are only supported by the NFS backend. They are unmounted and served again
+/// along with the source filesystem when it is detached and attached, and are removed with
+/// [`detach_mfs`].
+///
+/// ## Arguments
+/// * `source_mount_dir` - The path where the filesystem whose data to share is mounted
+/// * `target_mount_dir` - The path where the new view will be mounted. It must be empty
+/// * `root` - The root to start the new view from. If None, uses the current root of the source
+///
+/// ## Returns
+/// Where the new view was mounted, along with the port it is served on and the PID of the
+/// supervisor serving it
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let snapshot = management::list_snapshots(Some("mfstest".into())).await?.remove(0);
+/// management::share_mfs(
+///     "mfstest".into(),
+///     "mfstest-v1".into(),
+///     Some(*snapshot.get_root()),
+/// )
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn share_mfs(
+    source_mount_dir: PathBuf,
+    target_mount_dir: PathBuf,
+    root: Option<Cid>,
+) -> FsResult<MfsMount> {
+    let source = find::find_mfs_paths(&source_mount_dir).await?;
+
+    // Views of a shared mount are served by the same server
+    let server = match get_shared_mount(source.fs_db_path(), source.get_mount_dir()).await? {
+        Some(record) => find::find_mfs_paths(&record.served_by).await?,
+        None => source.clone(),
+    };
+
+    // Start from the requested root, making sure its blocks are still there
+    let root = match root {
+        Some(root) => root,
+        None => head::checkpoint_head(&source).await?.ok_or_else(|| {
+            FsError::InvalidOperation(format!(
+                "filesystem at {} has no recorded state to share",
+                source.get_mount_dir().display()
+            ))
+        })?,
+    };
+    Dir::load(&root, source.open_store().await?).await?;
+    check_mount_permitted(&target_mount_dir, MountBackend::Nfs)?;
+
+    // Set up an empty mount directory
+    fs::create_dir_all(&target_mount_dir).await?;
+    let target_mount_dir = fs::canonicalize(&target_mount_dir).await?;
+    let mfs_data_dir = PathBuf::from(format!("{}.{}", target_mount_dir.display(), MFS_DIR_SUFFIX));
+    if fs::try_exists(&mfs_data_dir).await? {
+        return Err(FsError::PathExists(
+            mfs_data_dir.to_string_lossy().to_string(),
+        ));
+    }
+
+    let mut entries = fs::read_dir(&target_mount_dir).await?;
+    if entries.next_entry().await?.is_some() {
+        return Err(FsError::MountPointNotEmpty(
+            target_mount_dir.to_string_lossy().to_string(),
+        ));
+    }
+
+    // The data directory of the view only holds its control socket and links to the shared data
+    let fs_db_path = fs::canonicalize(server.fs_db_path()).await?;
+    let owner = find::find_blocks_owner(&server).await?;
+    fs::create_dir_all(mfs_data_dir.join(LOG_SUBDIR)).await?;
+    fs::symlink(&fs_db_path, mfs_data_dir.join(FS_DB_FILENAME)).await?;
+    fs::symlink(owner.blocks_dir(), mfs_data_dir.join(BLOCKS_SUBDIR)).await?;
+    register_block_sharer(&owner, &target_mount_dir).await?;
+
+    let operation = format!("share {}", source.get_mount_dir().display());
+    FsHead::new(&fs_db_path, &target_mount_dir)
+        .await?
+        .set(&root, &operation)
+        .await?;
+
+    // Have the server of the source serve the view
+    let request = ControlRequest::AddMount {
+        mount_dir: target_mount_dir.to_string_lossy().to_string(),
+    };
+    let (host, port) =
+        match server::try_send_control_request(server.control_socket_path(), &request).await? {
+            Some(ControlResponse::Listening { host, port }) => (host, port),
+            Some(response) => {
+                return Err(FsError::ControlRequestFailed(format!(
+                    "unexpected response: {:?}",
+                    response
+                )))
+            }
+            None => {
+                return Err(FsError::InvalidOperation(format!(
+                    "filesystem at {} is not mounted",
+                    server.get_mount_dir().display()
+                )))
+            }
+        };
+
+    mount_fs(
+        &target_mount_dir,
+        &get_mount_host(&host),
+        port,
+        &TransferConfig::default(),
+        UserConfig::load()?
+            .get_mount_options()
+            .as_deref()
+            .unwrap_or_default(),
+        Instant::now() + DEFAULT_MOUNT_TIMEOUT,
+    )
+    .await?;
+    link_data_dir(&target_mount_dir, &mfs_data_dir).await?;
+    registry::register_mount(&target_mount_dir).await;
+    tracing::info!("mounted {} at {}", root, target_mount_dir.display());
+
+    let supervisor_pid = get_supervisor_pid(server.fs_db_path(), server.get_mount_dir()).await?;
+    Ok(MfsMount {
+        mount_dir: target_mount_dir,
+        data_dir: mfs_data_dir,
+        backend: MountBackend::Nfs,
+        port: Some(port),
+        supervisor_pid: supervisor_pid.map(|pid| pid as u32),
+    })
+}
+
+/// Detach a monofs filesystem by finding its root and unmounting it
+///
+/// NFS mounts are unmounted with the system's `umount` command before the supervisor is stopped.
+/// FUSE mounts are unmounted by the FUSE server itself when the supervisor stops it.
+///
+/// Before the supervisor is stopped, the server is asked over its control socket to checkpoint
+/// everything written so far and record it as the filesystem's head. Detaching then waits for the
+/// supervisor to exit, and fails if either step does not finish within a few seconds.
+///
+/// Mounts shared with [`share_mfs`] are unmounted along with the filesystem serving them and
+/// mounted again when it is attached. Detaching a shared mount itself only stops serving it.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching from. If None, uses current directory
+/// * `force` - Whether to force unmount even if the filesystem is busy
+///
+/// ## Returns
+/// The final root the server recorded before it was stopped, if it was running
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// // Detach from current directory
+/// management::detach_mfs(None, false).await?;
+///
+/// // Detach from specific directory with force option
+/// management::detach_mfs(Some("mfstest".into()), true).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn detach_mfs(mount_dir: Option<PathBuf>, force: bool) -> FsResult<DetachReport> {
+    // Default to current directory if no path specified
+    let start_path = mount_dir.unwrap_or_else(|| PathBuf::from("."));
+
+    // Find the MFS root directory
+    let mfs_root = find::find_mfs_root(&start_path).await?;
+    tracing::info!("found MFS root at {}", mfs_root.display());
+
+    // Get the filesystem database path
+    let db_path = get_fs_db_path(&mfs_root).await?;
+
+    // A shared mount is served by another filesystem's server, which keeps running
+    if let Some(record) = get_shared_mount(&db_path, &mfs_root).await? {
+        return detach_shared_mount(record, force).await;
+    }
+
+    // Unmount the filesystem and the mounts sharing its server
+    for record in list_shared_mounts(&db_path, &mfs_root).await? {
+        match unmount_fs(&record.mount_dir, force).await {
+            Ok(()) => registry::unregister_mount(&record.mount_dir).await,
+            Err(e) => tracing::warn!("failed to unmount {}: {}", record.mount_dir.display(), e),
+        }
+    }
+
+    let backend = get_mount_backend(&db_path, &mfs_root).await?;
+    if backend == MountBackend::Nfs {
+        unmount_fs(&mfs_root, force).await?;
+    }
+
+    // Have the server record its final root before it is stopped
+    let paths = find::find_mfs_paths(&mfs_root).await?;
+    let root = match time::timeout(SHUTDOWN_TIMEOUT, head::flush_head(&paths)).await {
+        Ok(Ok(Some(cid))) => {
+            tracing::info!("server recorded final root {}", cid);
+            Some(cid)
+        }
+        Ok(Ok(None)) => {
+            tracing::warn!("no server is running to record the final root");
+            None
+        }
+        Ok(Err(e)) => {
+            tracing::error!("failed to record the final root: {}", e);
+            None
+        }
+        Err(_) => {
+            return Err(FsError::ShutdownTimedOut(format!(
+                "server did not record the final root of {} in time",
+                mfs_root.display()
+            )))
+        }
+    };
+
+    // Get and terminate the supervisor process
+    let mut stopping_pid = None;
+    match get_supervisor_pid(&db_path, &mfs_root).await {
+        Ok(Some(supervisor_pid)) => {
+            tracing::info!("found supervisor process with PID: {}", supervisor_pid);
+
+            let pid = supervisor_pid as u32;
+            if !utils::is_process_running(pid) {
+                tracing::info!("supervisor process {} no longer exists", pid);
+            } else if !status::is_supervisor_running(pid, &mfs_root).await {
+                // The PID may have been reused since the supervisor recorded it
+                tracing::warn!(
+                    "process {} is not the supervisor of {}, leaving it alone",
+                    pid,
+                    mfs_root.display()
+                );
+            } else {
+                match utils::terminate_process(pid) {
+                    Ok(true) => {
+                        tracing::info!("asked supervisor process {} to exit", pid);
+                        stopping_pid = Some(pid);
+                    }
+                    Ok(false) => {
+                        tracing::info!("supervisor process {} no longer exists", pid);
+                    }
+                    Err(e) => {
+                        tracing::warn!("failed to stop supervisor process {}: {}", pid, e);
+                    }
+                }
+            }
+        }
+        Ok(None) => {
+            tracing::warn!(
+                "no supervisor PID found in database for mount point {}. \
+                the supervisor may have already exited.",
+                mfs_root.display()
+            );
+        }
+        Err(e) => {
+            tracing::error!("Failed to query supervisor PID from database: {}.", e);
+        }
+    }
+
+    // Wait for the supervisor to stop the server and exit
+    if let Some(pid) = stopping_pid {
+        wait_for_process_exit(pid).await?;
+    }
+
+    // Wait for the FUSE server to release the mount point
+    if backend == MountBackend::Fuse {
+        wait_for_fuse_unmount(&mfs_root).await?;
+    }
+    registry::unregister_mount(&mfs_root).await;
+
+    Ok(DetachReport {
+        mount_dir: mfs_root,
+        root,
+    })
+}
+
+/// Force everything written to a monofs filesystem so far onto disk
+///
+/// Writes still buffered by the mount are flushed to the server first. If the filesystem is
+/// mounted, its server then checkpoints the in-memory root and records it as the filesystem's
+/// head, after which it flushes the filesystem's blocks and database to disk. Nothing else on the
+/// machine is flushed, except where the platform cannot flush a single mount. Once this returns,
+/// the filesystem's data directory can be backed up and restored to the returned root.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+///
+/// ## Returns
+/// The root that was made durable, or `None` if the filesystem has no recorded state yet
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// if let Some(root) = management::sync_mfs(Some("mfstest".into())).await? {
+///     println!("synced {}", root);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn sync_mfs(mount_dir: Option<PathBuf>) -> FsResult<Option<Cid>> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+
+    // Push writes buffered by the kernel's NFS or FUSE client to the server
+    sync_mount(paths.get_mount_dir()).await?;
+
+    // Checkpoint the root, which the server flushes to disk along with the blocks it is stored in
+    let root = head::checkpoint_head(&paths).await?;
+
+    match &root {
+        Some(root) => tracing::info!("synced filesystem root {}", root),
+        None => tracing::info!("filesystem has no recorded state to sync"),
+    }
+
+    Ok(root)
+}
+
+/// Repair a monofs filesystem left in a broken state, e.g. by a crashed supervisor
+///
+/// A filesystem whose supervisor died while it was mounted, or whose server stopped answering,
+/// leaves its mount directory hung, so that anything touching it blocks or fails. Repairing stops
+/// whatever is left of the supervisor and its server, force-unmounts the directory along with the
+/// mounts sharing its server, and clears the processes recorded for it in the fs database. A
+/// supervisor that runs without the filesystem being mounted is stopped too. A healthy filesystem
+/// is left alone.
+///
+/// A server that is gone cannot record its final root, so writes it had not checkpointed yet are
+/// lost. Those it had are replayed from its intent log when the filesystem is attached again.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path where the filesystem is mounted. If None, uses current directory
+/// * `reattach` - Whether to attach the filesystem again once it is repaired
+///
+/// ## Returns
+/// The state the filesystem was found in and what was done about it
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management::{self, MountHealth};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let report = management::repair_mount(Some("mfstest".into()), true).await?;
+/// if *report.get_health() != MountHealth::Healthy {
+///     println!("repaired filesystem that was {}", report.get_health());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn repair_mount(mount_dir: Option<PathBuf>, reattach: bool) -> FsResult<RepairReport> {
+    // The mount directory itself is not looked into, as that hangs if its server is gone
+    let mount_dir = resolve_mount_dir(mount_dir.unwrap_or_else(|| PathBuf::from("."))).await?;
+    let mfs_data_dir = PathBuf::from(format!("{}.{}", mount_dir.display(), MFS_DIR_SUFFIX));
+    if !fs::try_exists(&mfs_data_dir).await? {
+        return Err(FsError::MfsDataDirNotFound(
+            mfs_data_dir.to_string_lossy().to_string(),
+        ));
+    }
+
+    let fs_db_path = mfs_data_dir.join(FS_DB_FILENAME);
+    if let Some(record) = get_shared_mount(&fs_db_path, &mount_dir).await? {
+        return Err(FsError::InvalidOperation(format!(
+            "{} is served by {}, repair that filesystem instead",
+            mount_dir.display(),
+            record.served_by.display()
+        )));
+    }
+
+    // Find out what state the filesystem is in
+    let pool = db::get_db_pool(&fs_db_path).await?;
+    let record =
+        sqlx::query("SELECT supervisor_pid, nfsserver_pid FROM filesystems WHERE mount_dir = ?")
+            .bind(mount_dir.to_string_lossy().to_string())
+            .fetch_optional(&pool)
+            .await?;
+    let (supervisor_pid, server_pid) = match record {
+        Some(row) => (
+            row.get::<Option<i64>, _>("supervisor_pid"),
+            row.get::<Option<i64>, _>("nfsserver_pid"),
+        ),
+        None => (None, None),
+    };
+    let supervisor_pid = match supervisor_pid.map(|pid| pid as u32) {
+        Some(pid) if status::is_supervisor_running(pid, &mount_dir).await => Some(pid),
+        _ => None,
+    };
+
+    let mounted = probe_mount_point(&mount_dir).await;
+    let health = match (supervisor_pid, mounted) {
+        (None, _) => MountHealth::Stopped,
+        (Some(_), false) => MountHealth::Unmounted,
+        (Some(_), true) if registry::is_server_responsive(&mount_dir).await => MountHealth::Healthy,
+        (Some(_), true) => MountHealth::Unresponsive,
+    };
+    tracing::info!("filesystem at {} is {}", mount_dir.display(), health);
+
+    if health == MountHealth::Healthy {
+        return Ok(RepairReport {
+            mount_dir,
+            health,
+            stopped_supervisor_pid: None,
+            unmounted: false,
+            mount: None,
+        });
+    }
+
+    // Stop what is left of the supervisor. The server is only known to be its child while the
+    // supervisor still runs, as its PID may have been reused otherwise
+    if let Some(pid) = supervisor_pid {
+        stop_supervisor(pid, server_pid.map(|pid| pid as u32)).await?;
+    }
+
+    // Unmount the mounts sharing the server, then the filesystem itself
+    for record in list_shared_mounts(&fs_db_path, &mount_dir).await? {
+        if !probe_mount_point(&record.mount_dir).await {
+            continue;
+        }
+
+        match force_unmount(&record.mount_dir).await {
+            Ok(()) => registry::unregister_mount(&record.mount_dir).await,
+            Err(e) => tracing::warn!("failed to unmount {}: {}", record.mount_dir.display(), e),
+        }
+    }
+
+    if mounted && probe_mount_point(&mount_dir).await {
+        force_unmount(&mount_dir).await?;
+    }
 
-    // Create required directories
-    let log_dir = mfs_data_dir.join(LOG_SUBDIR);
-    fs::create_dir_all(&log_dir).await?;
-    tracing::info!("log directory available at {}", log_dir.display());
+    // Clear what the supervisor and the server left behind
+    sqlx::query(
+        r#"
+        UPDATE filesystems
+        SET supervisor_pid = NULL, nfsserver_pid = NULL, port = NULL,
+            modified_at = CURRENT_TIMESTAMP
+        WHERE mount_dir = ?
+        "#,
+    )
+    .bind(mount_dir.to_string_lossy().to_string())
+    .execute(&pool)
+    .await?;
 
-    // Create the fs database file
-    let fs_db_path = mfs_data_dir.join(FS_DB_FILENAME);
-    if !fs_db_path.exists() {
-        fs::File::create(&fs_db_path).await?;
-        tracing::info!("created fs database at {}", fs_db_path.display());
+    match fs::remove_file(mfs_data_dir.join(CONTROL_SOCKET_FILENAME)).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
     }
 
-    // Initialize the filesystem database schema
-    db::init_db(&fs_db_path, &FS_DB_MIGRATOR).await?;
-    tracing::info!("initialized fs database schema");
+    let mount = if reattach {
+        Some(attach_mfs(Some(mount_dir.clone())).await?)
+    } else {
+        registry::unregister_mount(&mount_dir).await;
+        None
+    };
 
-    // Create the blocks directory
+    Ok(RepairReport {
+        mount_dir,
+        health,
+        stopped_supervisor_pid: supervisor_pid,
+        unmounted: mounted,
+        mount,
+    })
+}
+
+/// Start the supervisor for the filesystem in `mfs_data_dir` and mount it at `mount_dir`
+///
+/// If the supervisor exits, the mount timeout in `options` passes or `cancel` is cancelled before
+/// the filesystem is mounted, the supervisor is stopped and the filesystem unmounted again.
+async fn start_mfs(
+    mount_dir: &Path,
+    mfs_data_dir: &Path,
+    options: &InitOptions,
+    cancel: &CancellationToken,
+) -> FsResult<MfsMount> {
+    let backend = options
+        .get_backend()
+        .unwrap_or_else(MountBackend::get_preferred);
+    let host = options.get_host().as_deref().unwrap_or(DEFAULT_HOST);
+    let chunker = options.get_chunker().unwrap_or_default();
+    let compression = options.get_compression().unwrap_or_default();
+    let exports = options.get_exports().clone().unwrap_or_default();
+    let throttle = options.get_throttle().unwrap_or_default();
+    chunker.validate()?;
+    compression.validate()?;
+    options.get_transfer().validate()?;
+    exports.validate()?;
+    throttle.validate()?;
+    config::validate_mount_options(options.get_extra_mount_options())?;
+    check_mount_permitted(mount_dir, backend)?;
+
+    // Any host that can reach the server may use it as any user unless the exports say otherwise
+    let loopback = matches!(host.parse::<IpAddr>(), Ok(addr) if addr.is_loopback());
+    if backend == MountBackend::Nfs && !loopback && !exports.is_restricted() {
+        tracing::warn!(
+            "serving NFS on {} to any client acting as any user, restrict the exports to limit it",
+            host
+        );
+    }
+
+    // Fail before anything is started if the encryption or signing key in the environment is
+    // unusable. The supervisor inherits the environment and passes the keys on to the server.
+    EncryptionKey::from_env()?;
+    RootSigningKey::from_env()?;
+    let log_dir = mfs_data_dir.join(LOG_SUBDIR);
+    let fs_db_path = mfs_data_dir.join(FS_DB_FILENAME);
     let blocks_dir = mfs_data_dir.join(BLOCKS_SUBDIR);
-    fs::create_dir_all(&blocks_dir).await?;
-    tracing::info!("blocks directory available at {}", blocks_dir.display());
+
+    // Find an available port
+    let port = match backend {
+        MountBackend::Nfs => {
+            let range = match options.get_fixed_port() {
+                Some(port) => PortRange::single(*port),
+                None => options.get_port_range().unwrap_or_default(),
+            };
+            let port = super::find_available_port_in_range(host, range).await?;
+            tracing::info!("found available port: {}", port);
+            port
+        }
+        MountBackend::Fuse => 0,
+    };
 
     // Start the supervisor process
     let child_name = mount_dir
@@ -85,14 +1249,15 @@ pub async fn init_mfs(mount_dir: Option<PathBuf>) -> FsResult<u32> {
         microsandbox_utils::path::resolve_env_path(MFSRUN_EXE_ENV_VAR, &*DEFAULT_MFSRUN_EXE_PATH)?;
 
     tracing::info!("mounting the filesystem...");
-    let status = Command::new(mfsrun_path)
+    let mut command = Command::new(mfsrun_path);
+    command
         .arg("supervisor")
         .arg("--log-dir")
         .arg(&log_dir)
         .arg("--child-name")
         .arg(child_name)
         .arg("--host")
-        .arg(DEFAULT_HOST)
+        .arg(host)
         .arg("--port")
         .arg(port.to_string())
         .arg("--store-dir")
@@ -100,106 +1265,123 @@ pub async fn init_mfs(mount_dir: Option<PathBuf>) -> FsResult<u32> {
         .arg("--fs-db-path")
         .arg(&fs_db_path)
         .arg("--mount-dir")
-        .arg(&mount_dir)
-        .spawn()?;
+        .arg(mount_dir)
+        .arg("--backend")
+        .arg(backend.to_string())
+        .args(ChunkerArgs::to_args(&chunker))
+        .args(CompressionArgs::to_args(&compression))
+        .args(WriteBackArgs::to_args(options.get_write_back()))
+        .args(TransferArgs::to_args(options.get_transfer()))
+        .args(ExportArgs::to_args(&exports))
+        .args(ThrottleArgs::to_args(&throttle))
+        .arg("--atime")
+        .arg(options.get_atime().to_string())
+        .arg("--durability")
+        .arg(options.get_durability().to_string());
+    if let Some(metrics_addr) = options.get_metrics_addr() {
+        command.arg("--metrics-addr").arg(metrics_addr);
+    }
 
+    if cancel.is_cancelled() {
+        return Err(FsError::MountCancelled(
+            mount_dir.to_string_lossy().to_string(),
+        ));
+    }
+    let mut supervisor = command.spawn()?;
+
+    let supervisor_pid = supervisor.id();
     tracing::info!(
         "started supervisor process with PID: {}",
-        status.id().unwrap_or(0)
+        supervisor_pid.unwrap_or(0)
     );
 
-    // Mount the filesystem
-    mount_fs(&mount_dir, DEFAULT_HOST, port).await?;
-    tracing::info!("mounted filesystem at {}", mount_dir.display());
+    // Mount the filesystem and create symbolic link to mfs_data_dir in mount directory, unless
+    // the supervisor exits, the deadline passes or mounting is cancelled first
+    let deadline = Instant::now() + *options.get_mount_timeout();
+    let mounted = async {
+        match backend {
+            MountBackend::Nfs => {
+                mount_fs(
+                    mount_dir,
+                    &get_mount_host(host),
+                    port,
+                    options.get_transfer(),
+                    options.get_extra_mount_options(),
+                    deadline,
+                )
+                .await?
+            }
+            MountBackend::Fuse => wait_for_fuse_mount(mount_dir).await?,
+        }
+        tracing::info!("mounted filesystem at {}", mount_dir.display());
 
-    // Create symbolic link to mfs_data_dir in mount directory
-    let link_path = mount_dir.join(MFS_LINK_FILENAME);
-    if !link_path.exists() {
-        fs::symlink(&mfs_data_dir, &link_path).await?;
-        tracing::info!("created symbolic link at {}", link_path.display());
+        link_data_dir(mount_dir, mfs_data_dir).await
+    };
+
+    let result = tokio::select! {
+        result = mounted => result,
+        status = supervisor.wait() => Err(match status {
+            Ok(status) => FsError::MountFailed(format!(
+                "supervisor exited with {} before the filesystem was mounted",
+                status
+            )),
+            Err(e) => e.into(),
+        }),
+        _ = time::sleep_until(deadline) => Err(FsError::MountTimedOut(format!(
+            "{} was not mounted within {:?}",
+            mount_dir.display(),
+            options.get_mount_timeout()
+        ))),
+        _ = cancel.cancelled() => Err(FsError::MountCancelled(
+            mount_dir.to_string_lossy().to_string(),
+        )),
+    };
+
+    if let Err(e) = result {
+        tracing::error!("failed to mount {}: {}", mount_dir.display(), e);
+        abort_start(mount_dir, supervisor).await;
+        return Err(e);
     }
+    registry::register_mount(mount_dir).await;
 
-    Ok(port)
+    Ok(MfsMount {
+        mount_dir: mount_dir.to_path_buf(),
+        data_dir: mfs_data_dir.to_path_buf(),
+        backend,
+        port: (backend == MountBackend::Nfs).then_some(port),
+        supervisor_pid,
+    })
 }
 
-/// Detach a monofs filesystem by finding its root and unmounting it
-///
-/// ## Arguments
-/// * `mount_dir` - Optional path to start searching from. If None, uses current directory
-/// * `force` - Whether to force unmount even if the filesystem is busy
+/// Get the filesystem mounted at `mount_dir`, if one is mounted there and its server answers
 ///
-/// ## Example
-/// ```no_run
-/// use monofs::management;
-///
-/// # async fn example() -> anyhow::Result<()> {
-/// // Detach from current directory
-/// management::detach_mfs(None, false).await?;
-///
-/// // Detach from specific directory with force option
-/// management::detach_mfs(Some("mfstest".into()), true).await?;
-/// # Ok(())
-/// # }
-/// ```
-pub async fn detach_mfs(mount_dir: Option<PathBuf>, force: bool) -> FsResult<()> {
-    // Default to current directory if no path specified
-    let start_path = mount_dir.unwrap_or_else(|| PathBuf::from("."));
-
-    // Find the MFS root directory
-    let mfs_root = find::find_mfs_root(&start_path).await?;
-    tracing::info!("found MFS root at {}", mfs_root.display());
-
-    // Get the filesystem database path
-    let db_path = get_fs_db_path(&mfs_root).await?;
-
-    // Unmount the filesystem
-    unmount_fs(&mfs_root, force).await?;
-
-    // Get and terminate the supervisor process
-    match get_supervisor_pid(&db_path, &mfs_root).await {
-        Ok(Some(supervisor_pid)) => {
-            tracing::info!("found supervisor process with PID: {}", supervisor_pid);
+/// A filesystem that is mounted there but whose server does not answer is an error, since nothing
+/// can be mounted over it.
+async fn get_healthy_mount(mount_dir: &Path) -> FsResult<Option<MfsMount>> {
+    // The link inside the mount directory is only visible while a filesystem is mounted
+    let Ok(data_dir) = fs::read_link(mount_dir.join(MFS_LINK_FILENAME)).await else {
+        return Ok(None);
+    };
 
-            // Check if process is still running
-            let pid = Pid::from_raw(supervisor_pid);
-            match nix::unistd::getpgid(Some(pid)) {
-                Ok(_) => {
-                    // Process exists, send SIGTERM
-                    if let Err(e) = signal::kill(pid, Signal::SIGTERM) {
-                        tracing::warn!(
-                            "failed to send SIGTERM to supervisor process {}: {}",
-                            supervisor_pid,
-                            e
-                        );
-                    } else {
-                        tracing::info!("sent SIGTERM to supervisor process {}", supervisor_pid);
-                    }
-                }
-                Err(nix::errno::Errno::ESRCH) => {
-                    tracing::info!("supervisor process {} no longer exists", supervisor_pid);
-                }
-                Err(e) => {
-                    tracing::warn!(
-                        "failed to check if supervisor process {} exists: {}",
-                        supervisor_pid,
-                        e
-                    );
-                }
-            }
-        }
-        Ok(None) => {
-            tracing::warn!(
-                "no supervisor PID found in database for mount point {}. \
-                the supervisor may have already exited.",
-                mfs_root.display()
-            );
-        }
-        Err(e) => {
-            tracing::error!("Failed to query supervisor PID from database: {}.", e);
-        }
+    let info = registry::get_mount_info(mount_dir).await?;
+    if *info.get_health() != MountHealth::Healthy {
+        return Err(FsError::AlreadyMounted(format!(
+            "{} ({})",
+            mount_dir.display(),
+            info.get_health()
+        )));
     }
 
-    Ok(())
+    // Mounts made before the registry existed are registered when they are found
+    registry::register_mount(mount_dir).await;
+
+    Ok(Some(MfsMount {
+        mount_dir: mount_dir.to_path_buf(),
+        data_dir,
+        backend: *info.get_backend(),
+        port: *info.get_port(),
+        supervisor_pid: *info.get_supervisor_pid(),
+    }))
 }
 
 /// Get the filesystem database path from the MFS root directory
@@ -240,6 +1422,157 @@ async fn get_supervisor_pid(
     Ok(record.and_then(|row| row.get::<Option<i32>, _>("supervisor_pid")))
 }
 
+/// Get the mount backend for a mount directory from the filesystem database
+async fn get_mount_backend(
+    fs_db_path: impl AsRef<Path>,
+    mount_dir: impl AsRef<Path>,
+) -> FsResult<MountBackend> {
+    let pool = db::get_db_pool(fs_db_path.as_ref()).await?;
+
+    let mount_dir = mount_dir.as_ref().to_string_lossy().to_string();
+
+    // Query the database for the backend
+    let record = sqlx::query("SELECT backend FROM filesystems WHERE mount_dir = ?")
+        .bind(mount_dir)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|e| FsError::custom(e))?;
+
+    let backend = match record.map(|row| row.get::<String, _>("backend")).as_deref() {
+        Some("fuse") => MountBackend::Fuse,
+        _ => MountBackend::Nfs,
+    };
+
+    Ok(backend)
+}
+
+/// Get the address the supervisor of a mount directory last served metrics on from the
+/// filesystem database
+async fn get_metrics_addr(
+    fs_db_path: impl AsRef<Path>,
+    mount_dir: impl AsRef<Path>,
+) -> FsResult<Option<String>> {
+    let pool = db::get_db_pool(fs_db_path.as_ref()).await?;
+
+    let mount_dir = mount_dir.as_ref().to_string_lossy().to_string();
+
+    let record = sqlx::query("SELECT metrics_addr FROM filesystems WHERE mount_dir = ?")
+        .bind(mount_dir)
+        .fetch_optional(&pool)
+        .await?;
+
+    Ok(record.and_then(|row| row.get::<Option<String>, _>("metrics_addr")))
+}
+
+/// Record in the fs database of `owner` that the filesystem at `mount_dir` shares its blocks
+async fn register_block_sharer(owner: &MfsPaths, mount_dir: &Path) -> FsResult<()> {
+    db::init_db(owner.fs_db_path(), &FS_DB_MIGRATOR).await?;
+    let pool = db::get_db_pool(owner.fs_db_path()).await?;
+
+    sqlx::query("INSERT OR IGNORE INTO block_sharers (mount_dir) VALUES (?)")
+        .bind(mount_dir.to_string_lossy().to_string())
+        .execute(&pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Get the record of the shared mount at `mount_dir`, if it is one
+async fn get_shared_mount(
+    fs_db_path: impl AsRef<Path>,
+    mount_dir: impl AsRef<Path>,
+) -> FsResult<Option<SharedMountRecord>> {
+    let pool = db::get_db_pool(fs_db_path.as_ref()).await?;
+    let record = sqlx::query(
+        "SELECT mount_dir, served_by, host, port FROM shared_mounts WHERE mount_dir = ?",
+    )
+    .bind(mount_dir.as_ref().to_string_lossy().to_string())
+    .fetch_optional(&pool)
+    .await?;
+
+    Ok(record.as_ref().map(shared_mount_from_row))
+}
+
+/// List the shared mounts served by the server of the filesystem at `mount_dir`
+async fn list_shared_mounts(
+    fs_db_path: impl AsRef<Path>,
+    mount_dir: impl AsRef<Path>,
+) -> FsResult<Vec<SharedMountRecord>> {
+    let pool = db::get_db_pool(fs_db_path.as_ref()).await?;
+    let records = sqlx::query(
+        "SELECT mount_dir, served_by, host, port FROM shared_mounts WHERE served_by = ? ORDER BY id",
+    )
+    .bind(mount_dir.as_ref().to_string_lossy().to_string())
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(records.iter().map(shared_mount_from_row).collect())
+}
+
+/// Construct a shared mount record from a row of the shared_mounts table
+fn shared_mount_from_row(row: &SqliteRow) -> SharedMountRecord {
+    SharedMountRecord {
+        mount_dir: PathBuf::from(row.get::<String, _>("mount_dir")),
+        served_by: PathBuf::from(row.get::<String, _>("served_by")),
+        host: row.get("host"),
+        port: row.get("port"),
+    }
+}
+
+/// Unmount a shared mount and have the server serving it record its final root and stop
+async fn detach_shared_mount(record: SharedMountRecord, force: bool) -> FsResult<DetachReport> {
+    unmount_fs(&record.mount_dir, force).await?;
+
+    let server = find::find_mfs_paths(&record.served_by).await?;
+    let request = ControlRequest::RemoveMount {
+        mount_dir: record.mount_dir.to_string_lossy().to_string(),
+    };
+    let response = time::timeout(
+        SHUTDOWN_TIMEOUT,
+        server::send_control_request(server.control_socket_path(), &request),
+    )
+    .await
+    .map_err(|_| {
+        FsError::ShutdownTimedOut(format!(
+            "server did not record the final root of {} in time",
+            record.mount_dir.display()
+        ))
+    })??;
+
+    registry::unregister_mount(&record.mount_dir).await;
+
+    let root = match response {
+        ControlResponse::Root { cid } => {
+            tracing::info!("server recorded final root {}", cid);
+            Some(Cid::try_from(cid.as_str())?)
+        }
+        _ => None,
+    };
+
+    Ok(DetachReport {
+        mount_dir: record.mount_dir,
+        root,
+    })
+}
+
+/// Point the `.mfs_link` in the root of the filesystem mounted at `mount_dir` to `mfs_data_dir`
+///
+/// The link is stored in the filesystem itself, so a root that came from another filesystem, e.g.
+/// through cloning or sharing, still links to that filesystem's data and has to be replaced.
+async fn link_data_dir(mount_dir: &Path, mfs_data_dir: &Path) -> FsResult<()> {
+    let link_path = mount_dir.join(MFS_LINK_FILENAME);
+    match fs::read_link(&link_path).await {
+        Ok(target) if target == mfs_data_dir => return Ok(()),
+        Ok(_) => fs::remove_file(&link_path).await?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    fs::symlink(mfs_data_dir, &link_path).await?;
+    tracing::info!("created symbolic link at {}", link_path.display());
+    Ok(())
+}
+
 /// Unmount a filesystem at the specified mount point
 async fn unmount_fs(mount_dir: impl AsRef<Path>, force: bool) -> FsResult<()> {
     let mount_dir = mount_dir.as_ref();
@@ -277,46 +1610,42 @@ async fn unmount_fs(mount_dir: impl AsRef<Path>, force: bool) -> FsResult<()> {
     Ok(())
 }
 
-/// Mount a remote NFS filesystem at the specified mount point
-async fn mount_fs(mount_dir: impl AsRef<Path>, host: &str, port: u32) -> FsResult<()> {
+/// Mount a remote NFS filesystem at the specified mount point, moving as much data in a single
+/// request as `transfer` allows, with `extra_options` overriding the platform's mount options
+async fn mount_fs(
+    mount_dir: impl AsRef<Path>,
+    host: &str,
+    port: u32,
+    transfer: &TransferConfig,
+    extra_options: &[String],
+    deadline: Instant,
+) -> FsResult<()> {
     let mount_dir = mount_dir.as_ref();
 
     // Create mount point if it doesn't exist
     fs::create_dir_all(&mount_dir).await?;
     tracing::info!("mount point available at {}", mount_dir.display());
 
-    // Check if mount point is empty
-    let mut entries = fs::read_dir(&mount_dir).await?;
-    if entries.next_entry().await?.is_some() {
-        return Err(FsError::MountPointNotEmpty(
-            mount_dir.to_string_lossy().to_string(),
-        ));
-    }
     tracing::info!("mounting NFS share at {}", mount_dir.display());
 
     // Wait for the port to be ready. If we don't do this, the mount command will retry every
     // 5+ seconds on macos.
-    wait_for_port(host, port).await;
-
-    // Construct the mount command
-    // Using standard NFS mount options:
-    // - nolocks: disable NFS file locking
-    // - vers=3: use NFSv3
-    // - tcp: use TCP transport
-    // - soft: return errors rather than hang on timeouts
-    // - mountport=port: use same port for mount protocol
+    wait_for_port(host, port, deadline).await?;
+
+    // Construct the mount command from the platform's NFS mount options, the port and transfer
+    // sizes, and the options overriding them
     let source = format!("{}:/", host);
+    let options = config::get_nfs_mount_options(port, transfer, extra_options)?;
+    tracing::info!("mounting with options {}", options);
     let start = Instant::now();
     let status = Command::new("mount")
         .arg("-t")
         .arg("nfs")
         .arg("-o")
-        .arg(format!(
-            "nolocks,vers=3,tcp,port={port},mountport={port},soft",
-            port = port
-        ))
+        .arg(options)
         .arg(source)
         .arg(&mount_dir)
+        .kill_on_drop(true)
         .status()
         .await?;
 
@@ -337,17 +1666,293 @@ async fn mount_fs(mount_dir: impl AsRef<Path>, host: &str, port: u32) -> FsResul
 // Functions: Helpers
 //--------------------------------------------------------------------------------------------------
 
-/// Wait for the given host and port to become available.
+/// Returns the address to mount an NFS server bound to `host` from.
+///
+/// A server bound to all interfaces is mounted through the loopback address.
+pub(crate) fn get_mount_host(host: &str) -> String {
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(addr)) if addr.is_unspecified() => Ipv4Addr::LOCALHOST.to_string(),
+        Ok(IpAddr::V6(addr)) if addr.is_unspecified() => Ipv6Addr::LOCALHOST.to_string(),
+        _ => host.to_string(),
+    }
+}
+
+/// Fail if the current process may not mount a filesystem at `mount_dir` with `backend`.
+fn check_mount_permitted(mount_dir: &Path, backend: MountBackend) -> FsResult<()> {
+    if backend.is_permitted() {
+        return Ok(());
+    }
+
+    Err(FsError::MountNotPermitted(format!(
+        "only root may mount {} with the {} backend on this platform, use the {} backend instead",
+        mount_dir.display(),
+        backend,
+        MountBackend::Fuse
+    )))
+}
+
+/// Check whether a directory is a mount point by comparing its device with its parent's.
+async fn is_mount_point(dir: &Path) -> FsResult<bool> {
+    let Some(parent) = dir.parent() else {
+        return Ok(true);
+    };
+
+    let dir_dev = fs::metadata(dir).await?.dev();
+    let parent_dev = fs::metadata(parent).await?.dev();
+
+    Ok(dir_dev != parent_dev)
+}
+
+/// Wait for the FUSE server to mount the filesystem at the given directory.
+async fn wait_for_fuse_mount(mount_dir: &Path) -> FsResult<()> {
+    for _ in 0..MAX_FUSE_MOUNT_CHECKS {
+        if is_mount_point(mount_dir).await? {
+            tracing::info!("FUSE mount at {} is ready!", mount_dir.display());
+            return Ok(());
+        }
+
+        time::sleep(time::Duration::from_millis(50)).await;
+    }
+
+    Err(FsError::MountFailed(format!(
+        "FUSE server did not mount {} in time",
+        mount_dir.display()
+    )))
+}
+
+/// Wait for the FUSE server to unmount the filesystem at the given directory.
+async fn wait_for_fuse_unmount(mount_dir: &Path) -> FsResult<()> {
+    for _ in 0..MAX_FUSE_MOUNT_CHECKS {
+        if !is_mount_point(mount_dir).await? {
+            tracing::info!(
+                "successfully unmounted filesystem at {}",
+                mount_dir.display()
+            );
+            return Ok(());
+        }
+
+        time::sleep(time::Duration::from_millis(50)).await;
+    }
+
+    Err(FsError::UnmountFailed(format!(
+        "FUSE server did not unmount {} in time",
+        mount_dir.display()
+    )))
+}
+
+/// Flush the writes buffered by the kernel for the filesystem mounted at `mount_dir`, and only
+/// that filesystem.
+///
+/// Over NFS, the client sends the writes to the server and commits them, which makes the server
+/// flush its blocks and fs database to disk.
+#[cfg(target_os = "linux")]
+async fn sync_mount(mount_dir: &Path) -> FsResult<()> {
+    use std::os::fd::AsRawFd;
+
+    let mount_dir = mount_dir.to_path_buf();
+    tokio::task::spawn_blocking(move || -> FsResult<()> {
+        let dir = std::fs::File::open(&mount_dir)?;
+
+        // SAFETY: the descriptor stays open until syncfs returns
+        if unsafe { nix::libc::syncfs(dir.as_raw_fd()) } == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        Ok(())
+    })
+    .await
+    .map_err(FsError::custom)?
+}
+
+/// Flush the writes buffered by the kernel for the filesystem mounted at `mount_dir`.
+///
+/// There is no way to flush a single filesystem here, so every filesystem's buffered writes are
+/// flushed.
+#[cfg(not(target_os = "linux"))]
+async fn sync_mount(_mount_dir: &Path) -> FsResult<()> {
+    // SAFETY: sync takes no arguments and cannot fail
+    tokio::task::spawn_blocking(|| unsafe { nix::libc::sync() })
+        .await
+        .map_err(FsError::custom)
+}
+
+/// Wait for the process `pid` to exit, for at most [`SHUTDOWN_TIMEOUT`].
+async fn wait_for_process_exit(pid: u32) -> FsResult<()> {
+    let start = Instant::now();
+    while start.elapsed() < SHUTDOWN_TIMEOUT {
+        if !utils::is_process_running(pid) {
+            tracing::info!("supervisor process {} exited", pid);
+            return Ok(());
+        }
+
+        time::sleep(time::Duration::from_millis(50)).await;
+    }
+
+    Err(FsError::ShutdownTimedOut(format!(
+        "supervisor process {} did not exit in time",
+        pid
+    )))
+}
+
+/// Stop the `supervisor` of a filesystem that could not be mounted at `mount_dir`, and unmount
+/// whatever part of it was mounted
+async fn abort_start(mount_dir: &Path, mut supervisor: Child) {
+    // A supervisor that already exited has no PID anymore
+    if let Some(pid) = supervisor.id() {
+        if let Err(e) = utils::terminate_process(pid) {
+            tracing::warn!("failed to stop supervisor process {}: {}", pid, e);
+        }
+
+        if time::timeout(SHUTDOWN_TIMEOUT, supervisor.wait())
+            .await
+            .is_err()
+        {
+            tracing::warn!(
+                "supervisor process {} did not exit in time, killing it",
+                pid
+            );
+            if let Err(e) = supervisor.kill().await {
+                tracing::warn!("failed to kill supervisor process {}: {}", pid, e);
+            }
+        }
+    }
+
+    if probe_mount_point(mount_dir).await {
+        if let Err(e) = force_unmount(mount_dir).await {
+            tracing::warn!("failed to unmount {}: {}", mount_dir.display(), e);
+        }
+    }
+}
+
+/// Stop the supervisor process `pid`, killing it along with its server `server_pid` if it does not
+/// exit in time
+async fn stop_supervisor(pid: u32, server_pid: Option<u32>) -> FsResult<()> {
+    if !utils::terminate_process(pid)? {
+        return Ok(());
+    }
+    tracing::info!("asked supervisor process {} to exit", pid);
+
+    if wait_for_process_exit(pid).await.is_ok() {
+        return Ok(());
+    }
+
+    tracing::warn!(
+        "supervisor process {} did not exit in time, killing it",
+        pid
+    );
+    for pid in std::iter::once(pid).chain(server_pid) {
+        utils::kill_process(pid)?;
+    }
+
+    wait_for_process_exit(pid).await
+}
+
+/// Make `dir` absolute without looking into it, which hangs if it is a mount whose server is gone
+async fn resolve_mount_dir(dir: PathBuf) -> FsResult<PathBuf> {
+    match (dir.parent(), dir.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            Ok(fs::canonicalize(parent).await?.join(name))
+        }
+        _ => Ok(fs::canonicalize(&dir).await?),
+    }
+}
+
+/// Check whether `dir` is a mount point, counting a mount that fails to answer, or does not answer
+/// within [`MOUNT_PROBE_TIMEOUT`], as one
+async fn probe_mount_point(dir: &Path) -> bool {
+    match time::timeout(MOUNT_PROBE_TIMEOUT, is_mount_point(dir)).await {
+        Ok(Ok(mounted)) => mounted,
+        Ok(Err(FsError::IoError(e))) if e.kind() == io::ErrorKind::NotFound => false,
+        _ => true,
+    }
+}
+
+/// Forcibly unmount a mount whose server is gone, detaching it lazily on Linux if that fails
+///
+/// Unlike [`unmount_fs`], this does not look into the mount directory, which hangs if its server
+/// is gone.
+async fn force_unmount(mount_dir: &Path) -> FsResult<()> {
+    tracing::info!("forcibly unmounting filesystem at {}", mount_dir.display());
+    let status = Command::new("umount")
+        .arg("-f")
+        .arg(mount_dir)
+        .status()
+        .await?;
+    if status.success() {
+        return Ok(());
+    }
+
+    #[cfg(target_os = "linux")]
+    let status = {
+        tracing::warn!(
+            "forced unmount of {} failed, detaching it lazily",
+            mount_dir.display()
+        );
+        let status = Command::new("umount")
+            .arg("-l")
+            .arg(mount_dir)
+            .status()
+            .await?;
+        if status.success() {
+            return Ok(());
+        }
+
+        // Only root may unmount, except for FUSE mounts through the helper that mounted them
+        if utils::is_privileged() {
+            status
+        } else {
+            let mut status = status;
+            for helper in ["fusermount3", "fusermount"] {
+                match Command::new(helper)
+                    .args(["-u", "-z"])
+                    .arg(mount_dir)
+                    .status()
+                    .await
+                {
+                    Ok(helper_status) if helper_status.success() => return Ok(()),
+                    Ok(helper_status) => status = helper_status,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            status
+        }
+    };
+
+    Err(FsError::UnmountFailed(format!(
+        "unmount command exited with status: {}",
+        status
+    )))
+}
+
+/// Wait for the given host and port to become available, until `deadline`.
 ///
 /// This function tries to open a TCP connection to the address. If it fails,
 /// it waits 50ms and tries again.
-async fn wait_for_port(host: &str, port: u32) {
+async fn wait_for_port(host: &str, port: u32, deadline: Instant) -> FsResult<()> {
     let addr = format!("{}:{}", host, port);
     loop {
-        match TcpStream::connect(&addr).await {
+        let result = time::timeout_at(deadline, TcpStream::connect(&addr))
+            .await
+            .map_err(|_| {
+                FsError::MountTimedOut(format!("port {} on {} did not become ready", port, host))
+            })?;
+
+        match result {
             Ok(_) => {
                 tracing::info!("port {} on {} is ready!", port, host);
-                break;
+                return Ok(());
+            }
+            Err(e) if Instant::now() >= deadline => {
+                return Err(FsError::MountTimedOut(format!(
+                    "port {} on {} did not become ready: {}",
+                    port, host, e
+                )))
             }
             Err(e) => {
                 let retry_delay = 50;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+
+-- Record which mount backend serves each filesystem
+ALTER TABLE filesystems ADD COLUMN backend TEXT NOT NULL DEFAULT 'nfs';
@@ -0,0 +1,7 @@
+-- Add down migration script here
+
+-- Drop index
+DROP INDEX IF EXISTS idx_snapshots_fs_id;
+
+-- Drop table
+DROP TABLE IF EXISTS snapshots;
@@ -0,0 +1,15 @@
+-- Add up migration script here
+
+-- Create snapshots table
+CREATE TABLE IF NOT EXISTS snapshots (
+    id INTEGER PRIMARY KEY,
+    fs_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    root_cid TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (fs_id) REFERENCES filesystems(id) ON DELETE CASCADE,
+    UNIQUE (fs_id, name)
+);
+
+-- Create index for filesystem lookups
+CREATE INDEX idx_snapshots_fs_id ON snapshots(fs_id);
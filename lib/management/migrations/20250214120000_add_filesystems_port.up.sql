@@ -0,0 +1,4 @@
+-- Add up migration script here
+
+-- Record the port each filesystem's NFS server listens on
+ALTER TABLE filesystems ADD COLUMN port INTEGER;
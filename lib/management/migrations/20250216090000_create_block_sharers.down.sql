@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+-- Drop table
+DROP TABLE IF EXISTS block_sharers;
@@ -0,0 +1,8 @@
+-- Add up migration script here
+
+-- Create block sharers table, listing the clones that use this filesystem's blocks directory
+CREATE TABLE IF NOT EXISTS block_sharers (
+    id INTEGER PRIMARY KEY,
+    mount_dir TEXT NOT NULL UNIQUE,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
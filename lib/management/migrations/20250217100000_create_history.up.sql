@@ -0,0 +1,14 @@
+-- Add up migration script here
+
+-- Create history table, an append-only log of every root a filesystem has had
+CREATE TABLE IF NOT EXISTS history (
+    id INTEGER PRIMARY KEY,
+    fs_id INTEGER NOT NULL,
+    root_cid TEXT NOT NULL,
+    operation TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (fs_id) REFERENCES filesystems(id) ON DELETE CASCADE
+);
+
+-- Create index for filesystem lookups
+CREATE INDEX idx_history_fs_id ON history(fs_id);
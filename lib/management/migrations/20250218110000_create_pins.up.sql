@@ -0,0 +1,14 @@
+-- Add up migration script here
+
+-- Create pins table, CIDs whose blocks are kept by garbage collection
+CREATE TABLE IF NOT EXISTS pins (
+    id INTEGER PRIMARY KEY,
+    fs_id INTEGER NOT NULL,
+    cid TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (fs_id) REFERENCES filesystems(id) ON DELETE CASCADE,
+    UNIQUE (fs_id, cid)
+);
+
+-- Create index for filesystem lookups
+CREATE INDEX idx_pins_fs_id ON pins(fs_id);
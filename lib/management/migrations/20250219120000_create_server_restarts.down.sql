@@ -0,0 +1,7 @@
+-- Add down migration script here
+
+-- Drop index
+DROP INDEX IF EXISTS idx_server_restarts_fs_id;
+
+-- Drop table
+DROP TABLE IF EXISTS server_restarts;
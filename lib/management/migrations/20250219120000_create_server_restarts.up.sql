@@ -0,0 +1,14 @@
+-- Add up migration script here
+
+-- Create server_restarts table, a log of the supervisor restarting a dead or hung server
+CREATE TABLE IF NOT EXISTS server_restarts (
+    id INTEGER PRIMARY KEY,
+    fs_id INTEGER NOT NULL,
+    uptime_ms INTEGER NOT NULL,
+    backoff_ms INTEGER NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (fs_id) REFERENCES filesystems(id) ON DELETE CASCADE
+);
+
+-- Create index for filesystem lookups
+CREATE INDEX idx_server_restarts_fs_id ON server_restarts(fs_id);
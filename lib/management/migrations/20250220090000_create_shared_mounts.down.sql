@@ -0,0 +1,7 @@
+-- Add down migration script here
+
+-- Drop index
+DROP INDEX IF EXISTS idx_shared_mounts_served_by;
+
+-- Drop table
+DROP TABLE IF EXISTS shared_mounts;
@@ -0,0 +1,14 @@
+-- Add up migration script here
+
+-- Create shared_mounts table, the extra mounts a filesystem's server serves from the same data
+CREATE TABLE IF NOT EXISTS shared_mounts (
+    id INTEGER PRIMARY KEY,
+    mount_dir TEXT NOT NULL UNIQUE,
+    served_by TEXT NOT NULL,
+    host TEXT NOT NULL,
+    port INTEGER NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create index for server lookups
+CREATE INDEX idx_shared_mounts_served_by ON shared_mounts(served_by);
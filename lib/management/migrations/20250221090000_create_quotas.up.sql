@@ -0,0 +1,13 @@
+-- Add up migration script here
+
+-- Create quotas table, how much each filesystem may hold and how much it held when last recorded
+CREATE TABLE IF NOT EXISTS quotas (
+    id INTEGER PRIMARY KEY,
+    mount_dir TEXT NOT NULL UNIQUE,
+    max_logical_bytes INTEGER,
+    max_store_bytes INTEGER,
+    logical_bytes INTEGER,
+    store_bytes INTEGER,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    modified_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
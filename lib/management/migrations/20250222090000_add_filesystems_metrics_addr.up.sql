@@ -0,0 +1,4 @@
+-- Add up migration script here
+
+-- Record the address each filesystem's supervisor serves its metrics on
+ALTER TABLE filesystems ADD COLUMN metrics_addr TEXT;
@@ -0,0 +1,14 @@
+-- Add up migration script here
+
+-- Create intents table, the roots a server checkpointed since it last recorded the head of a
+-- filesystem, which are replayed if the server crashes before recording one
+CREATE TABLE IF NOT EXISTS intents (
+    id INTEGER PRIMARY KEY,
+    mount_dir TEXT NOT NULL,
+    root_cid TEXT NOT NULL,
+    operation TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create index for filesystem lookups
+CREATE INDEX idx_intents_mount_dir ON intents(mount_dir);
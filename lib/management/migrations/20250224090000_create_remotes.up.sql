@@ -0,0 +1,11 @@
+-- Add up migration script here
+
+-- Create remotes table, the remote store each filesystem keeps its blocks in, if any
+CREATE TABLE IF NOT EXISTS remotes (
+    id INTEGER PRIMARY KEY,
+    mount_dir TEXT NOT NULL UNIQUE,
+    url TEXT NOT NULL,
+    cache_max_bytes INTEGER NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    modified_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
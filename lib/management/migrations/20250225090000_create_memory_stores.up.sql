@@ -0,0 +1,11 @@
+-- Add up migration script here
+
+-- Create memory_stores table, how each filesystem keeps its blocks in memory, if it does
+CREATE TABLE IF NOT EXISTS memory_stores (
+    id INTEGER PRIMARY KEY,
+    mount_dir TEXT NOT NULL UNIQUE,
+    max_bytes INTEGER NOT NULL,
+    spill_on_detach BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    modified_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,7 @@
+-- Add down migration script here
+
+-- Drop table
+DROP TABLE IF EXISTS branches;
+
+ALTER TABLE history DROP COLUMN branch;
+ALTER TABLE filesystems DROP COLUMN branch;
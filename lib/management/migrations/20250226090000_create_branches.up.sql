@@ -0,0 +1,20 @@
+-- Add up migration script here
+
+-- Record the branch each filesystem is on, and the branch each root in its history was on
+ALTER TABLE filesystems ADD COLUMN branch TEXT NOT NULL DEFAULT 'main';
+ALTER TABLE history ADD COLUMN branch TEXT NOT NULL DEFAULT 'main';
+
+-- Create branches table, the latest root of each named line of a filesystem's history
+CREATE TABLE IF NOT EXISTS branches (
+    id INTEGER PRIMARY KEY,
+    fs_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    root_cid TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    modified_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (fs_id) REFERENCES filesystems(id) ON DELETE CASCADE,
+    UNIQUE (fs_id, name)
+);
+
+-- Create index for filesystem lookups
+CREATE INDEX idx_branches_fs_id ON branches(fs_id);
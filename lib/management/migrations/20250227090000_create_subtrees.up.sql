@@ -0,0 +1,10 @@
+-- Add up migration script here
+
+-- Create subtrees table, the directory each filesystem serves as its root, if not the whole root
+CREATE TABLE IF NOT EXISTS subtrees (
+    id INTEGER PRIMARY KEY,
+    mount_dir TEXT NOT NULL UNIQUE,
+    path TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    modified_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,11 @@
+-- Add up migration script here
+
+-- Create file_handles table, the path behind each file ID the server of a filesystem handed out
+CREATE TABLE IF NOT EXISTS file_handles (
+    id INTEGER PRIMARY KEY,
+    mount_dir TEXT NOT NULL,
+    file_id INTEGER NOT NULL,
+    path TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (mount_dir, file_id)
+);
@@ -0,0 +1,7 @@
+-- Add down migration script here
+
+-- Drop index
+DROP INDEX IF EXISTS idx_entity_locks_mount_dir_path;
+
+-- Drop table
+DROP TABLE IF EXISTS entity_locks;
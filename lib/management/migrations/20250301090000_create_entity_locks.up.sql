@@ -0,0 +1,14 @@
+-- Add up migration script here
+
+-- Create entity_locks table, the advisory locks processes using a filesystem hold on its entities
+CREATE TABLE IF NOT EXISTS entity_locks (
+    id INTEGER PRIMARY KEY,
+    mount_dir TEXT NOT NULL,
+    path TEXT NOT NULL,
+    exclusive BOOLEAN NOT NULL,
+    pid INTEGER NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create index for entity lookups
+CREATE INDEX idx_entity_locks_mount_dir_path ON entity_locks(mount_dir, path);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+ALTER TABLE history DROP COLUMN public_key;
+ALTER TABLE history DROP COLUMN signature;
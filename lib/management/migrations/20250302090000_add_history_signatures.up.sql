@@ -0,0 +1,5 @@
+-- Add up migration script here
+
+-- Record the signature of each root and the public key of the key that signed it
+ALTER TABLE history ADD COLUMN signature TEXT;
+ALTER TABLE history ADD COLUMN public_key TEXT;
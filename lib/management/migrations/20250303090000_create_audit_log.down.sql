@@ -0,0 +1,8 @@
+-- Add down migration script here
+
+-- Drop triggers, index and tables
+DROP TRIGGER IF EXISTS audit_log_no_delete;
+DROP TRIGGER IF EXISTS audit_log_no_update;
+DROP INDEX IF EXISTS audit_log_mount_dir;
+DROP TABLE IF EXISTS audit_log;
+DROP TABLE IF EXISTS audited_filesystems;
@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+ALTER TABLE snapshots DROP COLUMN scheduled;
+
+-- Drop table
+DROP TABLE IF EXISTS snapshot_schedules;
@@ -0,0 +1,18 @@
+-- Add up migration script here
+
+-- Create snapshot_schedules table, when the supervisor of each filesystem takes snapshots on its
+-- own and how many of them it keeps
+CREATE TABLE IF NOT EXISTS snapshot_schedules (
+    id INTEGER PRIMARY KEY,
+    mount_dir TEXT NOT NULL UNIQUE,
+    interval_secs INTEGER,
+    every_changes INTEGER,
+    keep_last INTEGER,
+    keep_daily INTEGER,
+    keep_weekly INTEGER,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    modified_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Mark the snapshots taken by the schedule, which are the only ones it deletes
+ALTER TABLE snapshots ADD COLUMN scheduled BOOLEAN NOT NULL DEFAULT FALSE;
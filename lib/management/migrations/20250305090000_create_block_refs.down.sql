@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+-- Drop tables
+DROP TABLE IF EXISTS block_ref_state;
+DROP TABLE IF EXISTS block_ref_roots;
+DROP TABLE IF EXISTS block_refs;
@@ -0,0 +1,24 @@
+-- Add up migration script here
+
+-- Create block_refs table, how many counted roots and nodes link to each block of the blocks
+-- directory this filesystem owns. Blocks no longer linked to have no row.
+CREATE TABLE IF NOT EXISTS block_refs (
+    cid TEXT PRIMARY KEY,
+    refs INTEGER NOT NULL
+);
+
+-- Create block_ref_roots table, the roots the counts in block_refs were last brought up to date
+-- with, and how many times each of them was counted
+CREATE TABLE IF NOT EXISTS block_ref_roots (
+    cid TEXT PRIMARY KEY,
+    refs INTEGER NOT NULL
+);
+
+-- Create block_ref_state table, a single row bumped on every update of the counts so that
+-- concurrent updates can tell they raced
+CREATE TABLE IF NOT EXISTS block_ref_state (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    generation INTEGER NOT NULL
+);
+
+INSERT INTO block_ref_state (id, generation) VALUES (1, 0);
@@ -0,0 +1,10 @@
+-- Add up migration script here
+
+-- Create store_hashes table, the hash function each filesystem makes the CIDs of new blocks with
+CREATE TABLE IF NOT EXISTS store_hashes (
+    id INTEGER PRIMARY KEY,
+    mount_dir TEXT NOT NULL UNIQUE,
+    hash_function TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    modified_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
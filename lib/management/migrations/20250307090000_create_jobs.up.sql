@@ -0,0 +1,21 @@
+-- Add up migration script here
+
+-- Create jobs table, the long-running operations run on each filesystem, how far they got and
+-- where resumable ones pick up from if they are interrupted
+CREATE TABLE IF NOT EXISTS jobs (
+    id INTEGER PRIMARY KEY,
+    mount_dir TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    target TEXT,
+    state TEXT NOT NULL,
+    pid INTEGER NOT NULL,
+    done INTEGER NOT NULL DEFAULT 0,
+    total INTEGER,
+    checkpoint TEXT,
+    error TEXT,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    modified_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create index for filesystem lookups
+CREATE INDEX idx_jobs_mount_dir ON jobs(mount_dir);
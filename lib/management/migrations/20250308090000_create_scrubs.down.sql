@@ -0,0 +1,8 @@
+-- Add down migration script here
+
+-- Drop index
+DROP INDEX IF EXISTS idx_quarantined_blocks_mount_dir;
+
+-- Drop tables
+DROP TABLE IF EXISTS quarantined_blocks;
+DROP TABLE IF EXISTS scrubs;
@@ -0,0 +1,30 @@
+-- Add up migration script here
+
+-- Create scrubs table, how the supervisor of each filesystem scrubs its blocks in the background
+-- and how far it got
+CREATE TABLE IF NOT EXISTS scrubs (
+    id INTEGER PRIMARY KEY,
+    mount_dir TEXT NOT NULL UNIQUE,
+    interval_secs INTEGER NOT NULL,
+    batch_blocks INTEGER NOT NULL,
+    cursor TEXT,
+    checked_blocks INTEGER NOT NULL DEFAULT 0,
+    passes INTEGER NOT NULL DEFAULT 0,
+    last_scrubbed_at DATETIME,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    modified_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create quarantined_blocks table, the corrupt blocks the scrubber moved out of each filesystem's
+-- store
+CREATE TABLE IF NOT EXISTS quarantined_blocks (
+    id INTEGER PRIMARY KEY,
+    mount_dir TEXT NOT NULL,
+    digest TEXT NOT NULL,
+    path TEXT NOT NULL,
+    quarantine_path TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create index for filesystem lookups
+CREATE INDEX idx_quarantined_blocks_mount_dir ON quarantined_blocks(mount_dir);
@@ -0,0 +1,9 @@
+-- Add down migration script here
+
+ALTER TABLE scrubs DROP COLUMN repaired_blocks;
+
+-- Drop index
+DROP INDEX IF EXISTS idx_replicas_mount_dir;
+
+-- Drop table
+DROP TABLE IF EXISTS replicas;
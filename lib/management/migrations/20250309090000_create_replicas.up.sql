@@ -0,0 +1,16 @@
+-- Add up migration script here
+
+-- Create replicas table, the stores each filesystem repairs its corrupt and missing blocks from
+CREATE TABLE IF NOT EXISTS replicas (
+    id INTEGER PRIMARY KEY,
+    mount_dir TEXT NOT NULL,
+    url TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (mount_dir, url)
+);
+
+-- Create index for filesystem lookups
+CREATE INDEX idx_replicas_mount_dir ON replicas(mount_dir);
+
+-- Count the corrupt blocks the scrubber repaired from replicas
+ALTER TABLE scrubs ADD COLUMN repaired_blocks INTEGER NOT NULL DEFAULT 0;
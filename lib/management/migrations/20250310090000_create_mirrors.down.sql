@@ -0,0 +1,7 @@
+-- Add down migration script here
+
+-- Drop index
+DROP INDEX IF EXISTS idx_mirrors_mount_dir;
+
+-- Drop table
+DROP TABLE IF EXISTS mirrors;
@@ -0,0 +1,13 @@
+-- Add up migration script here
+
+-- Create mirrors table, the directories each filesystem keeps copies of its block files in
+CREATE TABLE IF NOT EXISTS mirrors (
+    id INTEGER PRIMARY KEY,
+    mount_dir TEXT NOT NULL,
+    path TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (mount_dir, path)
+);
+
+-- Create index for filesystem lookups
+CREATE INDEX idx_mirrors_mount_dir ON mirrors(mount_dir);
@@ -0,0 +1,7 @@
+-- Add down migration script here
+
+-- Drop index
+DROP INDEX IF EXISTS idx_backups_mount_dir;
+
+-- Drop table
+DROP TABLE IF EXISTS backups;
@@ -0,0 +1,16 @@
+-- Add up migration script here
+
+-- Create backups table, the backups taken of each filesystem and where they were written
+CREATE TABLE IF NOT EXISTS backups (
+    id INTEGER PRIMARY KEY,
+    mount_dir TEXT NOT NULL,
+    dest TEXT NOT NULL,
+    snapshot TEXT NOT NULL,
+    root_cid TEXT NOT NULL,
+    blocks INTEGER NOT NULL,
+    bytes INTEGER NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create index for filesystem lookups
+CREATE INDEX idx_backups_mount_dir ON backups(mount_dir);
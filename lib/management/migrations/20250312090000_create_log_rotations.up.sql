@@ -0,0 +1,15 @@
+-- Add up migration script here
+
+-- Create log_rotations table, how the logs of each filesystem are rotated and expired, for the
+-- filesystems that do not use the default settings
+CREATE TABLE IF NOT EXISTS log_rotations (
+    id INTEGER PRIMARY KEY,
+    mount_dir TEXT NOT NULL UNIQUE,
+    max_bytes INTEGER NOT NULL,
+    max_age_secs INTEGER,
+    compress BOOLEAN NOT NULL,
+    max_files INTEGER NOT NULL,
+    retention_secs INTEGER,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    modified_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
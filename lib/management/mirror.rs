@@ -0,0 +1,413 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use getset::Getters;
+use serde::Serialize;
+use sqlx::{Pool, Row, Sqlite};
+use tokio::fs;
+
+use crate::{
+    management::{db, find, Job, JobKind, JobProgress},
+    store::{self, FlatFsStore, PACKS_SUBDIR, PACK_EXTENSION, PACK_INDEX_EXTENSION},
+    FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Tracks the mirrors of the blocks directory of a filesystem in its fs database.
+///
+/// The mirrors are set when the filesystem is initialized, and read every time its blocks are
+/// opened, by its server and by the management functions.
+#[derive(Debug, Clone)]
+pub struct FsMirrors {
+    /// The filesystem database.
+    fs_db: Pool<Sqlite>,
+
+    /// The mount directory identifying the filesystem.
+    mount_dir: PathBuf,
+}
+
+/// The outcome of a [`resilver_mfs`] run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct ResilverReport {
+    /// The number of block files and packfiles checked across the blocks directory and its
+    /// mirrors.
+    checked_files: u64,
+
+    /// The number of copies that were missing or corrupt and were written again from a good one.
+    copied_files: u64,
+
+    /// The digests of the blocks no directory holds a good copy of.
+    lost_blocks: Vec<String>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FsMirrors {
+    /// Opens the mirror records of the filesystem mounted at `mount_dir`.
+    pub async fn new(
+        fs_db_path: impl AsRef<Path>,
+        mount_dir: impl Into<PathBuf>,
+    ) -> FsResult<Self> {
+        Ok(Self {
+            fs_db: db::get_db_pool(fs_db_path.as_ref()).await?,
+            mount_dir: mount_dir.into(),
+        })
+    }
+
+    /// Returns the mirrors of the filesystem, in the order they are repaired from.
+    pub async fn list(&self) -> FsResult<Vec<PathBuf>> {
+        let rows =
+            sqlx::query("SELECT path FROM mirrors WHERE mount_dir = ? ORDER BY created_at, id")
+                .bind(self.mount_dir.to_string_lossy().to_string())
+                .fetch_all(&self.fs_db)
+                .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| PathBuf::from(row.get::<String, _>("path")))
+            .collect())
+    }
+
+    /// Records `path` as a mirror of the filesystem, after the others. Adding a mirror that is
+    /// already recorded has no effect.
+    pub async fn add(&self, path: &Path) -> FsResult<()> {
+        sqlx::query("INSERT OR IGNORE INTO mirrors (mount_dir, path) VALUES (?, ?)")
+            .bind(self.mount_dir.to_string_lossy().to_string())
+            .bind(path.to_string_lossy().to_string())
+            .execute(&self.fs_db)
+            .await?;
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Bring every mirror of the blocks of a monofs filesystem back in line with the others
+///
+/// The mirrors of a filesystem, set when it is initialized, each keep a copy of every block file
+/// and packfile in its blocks directory. A copy goes missing when a disk is replaced or a write
+/// to it fails, and goes bad when a disk corrupts it. This checks every block file found in the
+/// blocks directory or any mirror against its digest, and writes it again from a good copy
+/// wherever it is missing or corrupt. Packfiles missing from a directory are copied from another
+/// holding them; their contents are checked by [`verify_mfs`](crate::management::verify_mfs).
+///
+/// Blocks that have no good copy left are reported and left as they are.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+///
+/// ## Returns
+/// A report of how many files were checked and copied, and which blocks were lost
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let report = management::resilver_mfs(Some("mfstest".into())).await?;
+/// println!("copied {} files", report.get_copied_files());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn resilver_mfs(mount_dir: Option<PathBuf>) -> FsResult<ResilverReport> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let job = Job::start(
+        paths.fs_db_path(),
+        paths.get_mount_dir(),
+        JobKind::Resilver,
+        None,
+    )
+    .await?;
+    let result = async {
+        let store = paths.open_store().await?;
+        if store.get_mirrors().is_empty() {
+            return Err(FsError::InvalidOperation(format!(
+                "filesystem at {} has no mirrors to resilver",
+                paths.get_mount_dir().display()
+            )));
+        }
+
+        resilver_blocks(&store, job.get_progress()).await
+    }
+    .await;
+    job.finish(result).await
+}
+
+/// Checks that `mirrors` can each hold copies of the blocks of the filesystem whose data
+/// directory is `mfs_data_dir`, and creates the ones that do not exist yet.
+///
+/// A mirror must be an absolute path outside the data directory, and empty if it exists.
+pub(crate) async fn prepare_mirrors(mirrors: &[PathBuf], mfs_data_dir: &Path) -> FsResult<()> {
+    for mirror in mirrors {
+        if !mirror.is_absolute() {
+            return Err(FsError::InvalidMirror(format!(
+                "{} is not an absolute path",
+                mirror.display()
+            )));
+        }
+        if mirror.starts_with(mfs_data_dir) {
+            return Err(FsError::InvalidMirror(format!(
+                "{} is inside the data directory {}",
+                mirror.display(),
+                mfs_data_dir.display()
+            )));
+        }
+
+        fs::create_dir_all(mirror).await?;
+        if fs::read_dir(mirror).await?.next_entry().await?.is_some() {
+            return Err(FsError::InvalidMirror(format!(
+                "{} is not empty",
+                mirror.display()
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Copies the good copy of every block file and packfile of `store` over the copies missing or
+/// corrupt in its directory and its mirrors, reporting the files checked to `progress`.
+async fn resilver_blocks(store: &FlatFsStore, progress: &JobProgress) -> FsResult<ResilverReport> {
+    let dirs = std::iter::once(store.get_path())
+        .chain(store.get_mirrors())
+        .collect::<Vec<_>>();
+
+    // Gather what any of the directories holds
+    let (mut blocks, mut packs) = (BTreeSet::new(), BTreeSet::new());
+    for dir in &dirs {
+        if !fs::try_exists(dir).await? {
+            tracing::warn!("{} is missing, recreating it", dir.display());
+            continue;
+        }
+
+        for path in list_files(dir).await? {
+            let relative = path.strip_prefix(dir).unwrap_or(&path).to_path_buf();
+            let name = relative
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            if relative.starts_with(PACKS_SUBDIR) {
+                if relative
+                    .extension()
+                    .is_some_and(|ext| ext == PACK_EXTENSION)
+                {
+                    packs.insert(relative);
+                }
+            } else if store::get_block_name(&name).is_some() {
+                blocks.insert(relative);
+            }
+        }
+    }
+    progress.set_total((blocks.len() + packs.len()) as u64);
+
+    let mut report = ResilverReport::default();
+    for relative in &blocks {
+        let (mut good, mut unverified, mut stale) = (None, None, Vec::new());
+        for dir in &dirs {
+            let path = dir.join(relative);
+            if !fs::try_exists(&path).await? {
+                stale.push(path);
+                continue;
+            }
+
+            match store.verify_block_file(&path).await {
+                Ok(true) => {
+                    good.get_or_insert(path);
+                }
+                Ok(false) => {
+                    tracing::warn!("{} is corrupt", path.display());
+                    stale.push(path);
+                }
+                // Encrypted blocks cannot be checked without the key
+                Err(e) => {
+                    tracing::warn!(error = %e, "failed to check {}", path.display());
+                    unverified.get_or_insert(path);
+                }
+            }
+        }
+
+        report.checked_files += 1;
+        progress.add_done(1);
+        let Some(source) = good.or(unverified) else {
+            let name = relative.file_name().unwrap_or_default().to_string_lossy();
+            let digest = store::get_block_name(&name).unwrap_or(&name).to_string();
+            tracing::error!("no good copy of block {} is left", digest);
+            report.lost_blocks.push(digest);
+            continue;
+        };
+
+        for target in stale {
+            copy_file(&source, &target).await?;
+            report.copied_files += 1;
+        }
+    }
+
+    // Packfiles are copied before their indexes, as stores only read packs that have one
+    for relative in &packs {
+        let index = relative.with_extension(PACK_INDEX_EXTENSION);
+        for relative in [relative, &index] {
+            let mut source = None;
+            let mut missing = Vec::new();
+            for dir in &dirs {
+                let path = dir.join(relative);
+                match fs::try_exists(&path).await? {
+                    true => source = source.or(Some(path)),
+                    false => missing.push(path),
+                }
+            }
+
+            let Some(source) = source else {
+                tracing::error!("{} is missing everywhere", relative.display());
+                continue;
+            };
+            for target in missing {
+                copy_file(&source, &target).await?;
+                report.copied_files += 1;
+            }
+        }
+
+        report.checked_files += 1;
+        progress.add_done(1);
+    }
+
+    tracing::info!(
+        "resilvered {} files across {} directories, copied {}, lost {} blocks",
+        report.checked_files,
+        dirs.len(),
+        report.copied_files,
+        report.lost_blocks.len()
+    );
+
+    Ok(report)
+}
+
+/// Lists the paths of the files below `dir`.
+async fn list_files(dir: &Path) -> FsResult<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Copies the file at `source` to `target`, in place of any file there, and flushes the copy to
+/// disk.
+async fn copy_file(source: &Path, target: &Path) -> FsResult<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    // Copy the file under another name first, as the store may read it any time
+    let temp_path = target.with_extension(format!("resilver-{}", std::process::id()));
+    fs::copy(source, &temp_path).await?;
+    fs::File::open(&temp_path).await?.sync_all().await?;
+    fs::rename(&temp_path, target).await?;
+    tracing::info!("copied {} to {}", source.display(), target.display());
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::IpldStore;
+    use tempfile::TempDir;
+
+    use crate::management::FS_DB_MIGRATOR;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resilver_blocks() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let data_dir = temp.path().join("mnt.mfs");
+        let (blocks_dir, mirror) = (data_dir.join("blocks"), temp.path().join("mirror"));
+
+        // Mirrors are absolute, outside the data directory, and start out empty
+        prepare_mirrors(&[mirror.clone()], &data_dir).await?;
+        for invalid in [
+            PathBuf::from("mirror"),
+            data_dir.join("mirror"),
+            temp.path().to_path_buf(),
+        ] {
+            let result = prepare_mirrors(&[invalid], &data_dir).await;
+            assert!(matches!(result, Err(FsError::InvalidMirror(_))));
+        }
+
+        let db_path = temp.path().join("fs.db");
+        db::init_db(&db_path, &FS_DB_MIGRATOR).await?;
+        let mirrors = FsMirrors::new(&db_path, temp.path().join("mnt")).await?;
+        mirrors.add(&mirror).await?;
+        mirrors.add(&mirror).await?;
+        assert_eq!(mirrors.list().await?, [mirror.clone()]);
+
+        let store = FlatFsStore::new(&blocks_dir).with_mirrors(vec![mirror.clone()]);
+        let mut cids = Vec::new();
+        for data in [b"first".as_slice(), b"second", b"third"] {
+            cids.push(store.put_bytes(data).await?);
+        }
+        let paths = cids
+            .iter()
+            .map(|cid| store.get_block_file(cid).unwrap())
+            .collect::<Vec<_>>();
+        let mirror_paths = paths
+            .iter()
+            .map(|path| mirror.join(path.strip_prefix(&blocks_dir).unwrap()))
+            .collect::<Vec<_>>();
+
+        // One copy is lost, one corrupt, and both copies of the last block are corrupt
+        fs::remove_file(&paths[0]).await?;
+        fs::write(&mirror_paths[1], b"garbage").await?;
+        fs::write(&paths[2], b"garbage").await?;
+        fs::write(&mirror_paths[2], b"garbage").await?;
+
+        let progress = JobProgress::default();
+        let report = resilver_blocks(&store, &progress).await?;
+        assert_eq!(report.checked_files, 3);
+        assert_eq!(report.copied_files, 2);
+        assert_eq!(report.lost_blocks.len(), 1);
+        assert_eq!(
+            fs::read(&paths[0]).await?,
+            fs::read(&mirror_paths[0]).await?
+        );
+        assert_eq!(
+            fs::read(&paths[1]).await?,
+            fs::read(&mirror_paths[1]).await?
+        );
+        assert!(store.verify_block_file(&mirror_paths[1]).await?);
+
+        // Everything left to copy was copied
+        let report = resilver_blocks(&store, &progress).await?;
+        assert_eq!(report.copied_files, 0);
+
+        Ok(())
+    }
+}
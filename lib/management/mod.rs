@@ -1,13 +1,93 @@
 //! Management functions.
 
+mod archive;
+mod audit;
+mod backup;
+mod branch;
+mod compact;
+mod content;
 mod db;
+mod dedup;
+mod du;
 mod find;
+mod gc;
+mod grep;
+mod handles;
+mod hash;
+mod head;
+mod history;
+mod job;
+mod lock;
+mod log;
+mod logrotation;
+mod memory;
 mod mfs;
+mod mirror;
+mod oci;
+mod overlay;
+mod pin;
+mod quota;
+mod refs;
+mod registry;
+mod remote;
+mod replica;
+mod restore;
+mod retention;
+mod schedule;
+mod scrub;
+mod send;
+mod snapshot;
+mod status;
+mod subtree;
+mod sync;
+mod tree;
+mod verify;
+mod watch;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
 //--------------------------------------------------------------------------------------------------
 
+pub use archive::*;
+pub use audit::*;
+pub use backup::*;
+pub use branch::*;
+pub use compact::*;
+pub use content::*;
 pub use db::*;
+pub use dedup::*;
+pub use du::*;
 pub use find::*;
+pub use gc::*;
+pub use grep::*;
+pub use handles::*;
+pub use hash::*;
+pub use head::*;
+pub use history::*;
+pub use job::*;
+pub use lock::*;
+pub use log::*;
+pub use logrotation::*;
+pub use memory::*;
 pub use mfs::*;
+pub use mirror::*;
+pub use oci::*;
+pub use overlay::*;
+pub use pin::*;
+pub use quota::*;
+pub use refs::*;
+pub use registry::*;
+pub use remote::*;
+pub use replica::*;
+pub use restore::*;
+pub use retention::*;
+pub use schedule::*;
+pub use scrub::*;
+pub use send::*;
+pub use snapshot::*;
+pub use status::*;
+pub use subtree::*;
+pub use sync::*;
+pub use tree::*;
+pub use verify::*;
+pub use watch::*;
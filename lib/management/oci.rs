@@ -0,0 +1,987 @@
+use std::{
+    collections::HashSet,
+    path::{Component, Path, PathBuf},
+};
+
+use async_compression::tokio::{
+    bufread::{GzipDecoder, ZstdDecoder},
+    write::GzipEncoder,
+};
+use chrono::Utc;
+use futures::{StreamExt, TryStreamExt};
+use getset::Getters;
+use ipldstore::{ipld::cid::Cid, IpldStore, IpldStoreSeekable};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs,
+    io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+};
+use tokio_tar::{Archive, Builder, EntryType, Header};
+
+use crate::{
+    filesystem::{self, DiffKind, Dir, Entity},
+    management::{
+        archive, db, find, head, snapshot, tree, ExportSource, Job, JobKind, JobProgress, MfsPaths,
+        Snapshot,
+    },
+    FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The prefix of the name of a whiteout file, which hides the entry named by the rest of its name
+/// in the layers below.
+const WHITEOUT_PREFIX: &str = ".wh.";
+
+/// The prefix of the names of whiteout files with a special meaning, rather than naming an entry.
+const WHITEOUT_META_PREFIX: &str = ".wh..wh.";
+
+/// The name of an opaque whiteout file, which hides every entry of its directory in the layers
+/// below.
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+/// The magic bytes gzip-compressed layers start with.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The magic bytes zstd-compressed layers start with.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// How many image indexes deep the manifest of an image is looked for.
+const MAX_OCI_INDEX_DEPTH: usize = 8;
+
+/// The media type of an OCI image index.
+const OCI_INDEX_MEDIA_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+
+/// The media type of an OCI image manifest.
+const OCI_MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+/// The media type of an OCI image configuration.
+const OCI_CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+
+/// The media type of the gzip-compressed layers written by [`export_oci`].
+const OCI_LAYER_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar+gzip";
+
+/// The annotation holding the tag of a manifest in an image index.
+const OCI_REF_NAME_ANNOTATION: &str = "org.opencontainers.image.ref.name";
+
+/// The size of the chunks files are read in to compute their digests.
+const DIGEST_CHUNK_LEN: usize = 64 * 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An image written by [`export_oci`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct OciImage {
+    /// The digest of the manifest of the image, like `sha256:<hex>`.
+    digest: String,
+
+    /// The tag of the image.
+    tag: String,
+
+    /// The digests of the compressed layers of the image, lowest first.
+    layers: Vec<String>,
+}
+
+/// A blob written to an OCI image layout.
+#[derive(Debug, Clone)]
+struct OciBlob {
+    /// The digest of the blob, like `sha256:<hex>`.
+    digest: String,
+
+    /// The size of the blob in bytes.
+    size: u64,
+}
+
+/// An image in the `manifest.json` of an archive written by `docker save`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DockerManifest {
+    /// The paths of the layers in the archive, lowest first.
+    layers: Vec<String>,
+}
+
+/// A reference to a blob of an OCI image layout.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OciDescriptor {
+    /// The digest of the blob, like `sha256:<hex>`.
+    digest: String,
+
+    /// The platform the manifest the blob holds is for.
+    #[serde(default)]
+    platform: Option<OciPlatform>,
+}
+
+/// The platform of a manifest in an OCI image index.
+#[derive(Debug, Deserialize)]
+struct OciPlatform {
+    /// The CPU architecture, like `amd64`.
+    architecture: String,
+
+    /// The operating system, like `linux`.
+    os: String,
+}
+
+/// An OCI image index or image manifest, told apart by which of their fields are set.
+#[derive(Debug, Deserialize)]
+struct OciManifest {
+    /// The manifests of an index, one per platform.
+    #[serde(default)]
+    manifests: Option<Vec<OciDescriptor>>,
+
+    /// The layers of an image, lowest first.
+    #[serde(default)]
+    layers: Vec<OciDescriptor>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Import an OCI or Docker image archive into a monofs filesystem as a stack of overlays, and
+/// record its root as a snapshot
+///
+/// Every layer of the image becomes an overlay of the layer below it, with the whiteouts of the
+/// layer hiding the entries of the layers below, so images sharing layers share their blocks too.
+/// The root of the top layer is recorded as a snapshot named `name`, which a sandbox can boot
+/// from with [`restore_snapshot`](super::restore_snapshot), or layer its own changes over with
+/// [`overlay_mfs`](super::overlay_mfs). The current root of the filesystem is left alone.
+///
+/// Both archives in the OCI image layout, like those written by `skopeo copy ... oci-archive:`,
+/// and archives written by `docker save` are supported, with gzip, zstd or uncompressed layers.
+/// For an image index, the manifest for Linux on the current architecture is imported, or the
+/// first one if there is none. Images are not pulled from registries, so pull and save them
+/// first.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `image_path` - Path to the image archive to import
+/// * `name` - The name of the snapshot to record the image as. Must be unique within the filesystem
+///
+/// ## Returns
+/// The snapshot of the image
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let snapshot = management::import_oci(Some("mfstest".into()), "alpine.tar", "alpine").await?;
+/// println!("imported alpine as {}", snapshot.get_root());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn import_oci(
+    mount_dir: Option<PathBuf>,
+    image_path: impl AsRef<Path>,
+    name: impl Into<String>,
+) -> FsResult<Snapshot> {
+    let name = name.into();
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let job = Job::start(
+        paths.fs_db_path(),
+        paths.get_mount_dir(),
+        JobKind::Import,
+        None,
+    )
+    .await?;
+    let result = import_image_file(&paths, image_path.as_ref(), &name, job.get_progress()).await;
+    job.finish(result).await
+}
+
+/// Export a directory tree of a monofs filesystem as an OCI image archive
+///
+/// The archive is in the OCI image layout, with a `manifest.json` like those written by
+/// `docker save` as well, so it can be loaded with `docker load`, or copied to a registry with
+/// tools like `skopeo` to publish it. It can also be imported back with [`import_oci`].
+///
+/// Without a `base`, the image has a single layer holding the whole tree. With one, such as the
+/// root of the snapshot a sandbox started from, the image has two layers: one holding the `base`
+/// tree, and one holding only the changes from it to the exported tree, with whiteouts for the
+/// removed entries. Symbolic CID links have no tar equivalent and are skipped.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `source` - The directory to export as the root of the image
+/// * `base` - Optional root of a directory tree to export as a layer of its own, below the changes from it
+/// * `tag` - The tag to record the image under, like `sandbox:latest`
+/// * `writer` - Where to write the archive to
+///
+/// ## Returns
+/// The digests of the image and its layers
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management::{self, ExportSource};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let snapshots = management::list_snapshots(Some("mfstest".into())).await?;
+/// let base = snapshots.iter().find(|s| s.get_name() == "alpine").map(|s| *s.get_root());
+/// let file = tokio::fs::File::create("sandbox.tar").await?;
+/// let source = ExportSource::Path(String::new());
+/// let image = management::export_oci(Some("mfstest".into()), source, base, "sandbox:latest", file).await?;
+/// println!("exported {}", image.get_digest());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn export_oci(
+    mount_dir: Option<PathBuf>,
+    source: ExportSource,
+    base: Option<Cid>,
+    tag: &str,
+    writer: impl AsyncWrite + Unpin + Send + Sync,
+) -> FsResult<OciImage> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let job = Job::start(
+        paths.fs_db_path(),
+        paths.get_mount_dir(),
+        JobKind::Export,
+        None,
+    )
+    .await?;
+    let result = export_image(&paths, source, base, tag, writer, job.get_progress()).await;
+    job.finish(result).await
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Imports the image archive at `image_path` into the filesystem at `paths` as the snapshot
+/// `name`, reporting the entries imported to `progress`.
+async fn import_image_file(
+    paths: &MfsPaths,
+    image_path: &Path,
+    name: &str,
+    progress: &JobProgress,
+) -> FsResult<Snapshot> {
+    let pool = db::get_db_pool(paths.fs_db_path()).await?;
+    if snapshot::get_snapshot(&pool, paths.get_mount_dir(), name)
+        .await?
+        .is_some()
+    {
+        return Err(FsError::SnapshotExists(name.to_string()));
+    }
+
+    // The manifest can come after the layers in the archive, so unpack it to find the layers
+    let image_dir = tempfile::tempdir()?;
+    let mut archive = Archive::new(fs::File::open(image_path).await?);
+    archive.unpack(image_dir.path()).await?;
+
+    let store = paths.open_store().await?;
+    let layers = import_image_dir(&store, image_dir.path(), progress).await?;
+    let root = layers
+        .last()
+        .ok_or_else(|| FsError::InvalidOciImage("the image has no layers".to_string()))?;
+    tracing::info!(
+        "imported {} with {} layers as {}",
+        image_path.display(),
+        layers.len(),
+        root
+    );
+
+    snapshot::record_snapshot(&pool, paths.get_mount_dir(), name, root, false).await
+}
+
+/// Imports the layers of the image unpacked in `image_dir` into `store`, each as an overlay of
+/// the one below it.
+///
+/// Returns the roots of the layers, lowest first.
+async fn import_image_dir<S>(
+    store: &S,
+    image_dir: &Path,
+    progress: &JobProgress,
+) -> FsResult<Vec<Cid>>
+where
+    S: IpldStore + Send + Sync,
+{
+    let layer_paths = get_layer_paths(image_dir).await?;
+    let mut layers = Vec::new();
+    for (index, layer_path) in layer_paths.iter().enumerate() {
+        let tar_path = image_dir.join(format!("layer-{}.tar", index));
+        let tar_path = decompress_layer(layer_path, &tar_path).await?;
+
+        let mut root = match layers.last() {
+            Some(base) => Dir::overlay(base, store.clone()).await?,
+            None => Dir::new(store.clone()),
+        };
+
+        // Whiteouts only hide entries of the layers below, so apply them before the entries
+        apply_whiteouts(&mut root, &tar_path).await?;
+        let reader = fs::File::open(&tar_path).await?;
+        let count = archive::import_entries_with(&mut root, reader, progress, is_whiteout).await?;
+
+        let cid = root.checkpoint().await?;
+        tracing::debug!("imported layer {} with {} entries as {}", index, count, cid);
+        layers.push(cid);
+
+        if tar_path != *layer_path {
+            fs::remove_file(&tar_path).await?;
+        }
+    }
+
+    Ok(layers)
+}
+
+/// Returns the paths of the layers of the image unpacked in `image_dir`, lowest first.
+async fn get_layer_paths(image_dir: &Path) -> FsResult<Vec<PathBuf>> {
+    // Archives written by `docker save` list their layers in `manifest.json`
+    let manifest_path = image_dir.join("manifest.json");
+    if fs::try_exists(&manifest_path).await? {
+        let manifests: Vec<DockerManifest> = read_json(&manifest_path).await?;
+        let manifest = manifests
+            .into_iter()
+            .next()
+            .ok_or_else(|| FsError::InvalidOciImage("manifest.json lists no images".to_string()))?;
+
+        return manifest
+            .layers
+            .iter()
+            .map(|layer| get_image_path(image_dir, layer))
+            .collect();
+    }
+
+    // The OCI image layout finds them through `index.json`
+    let index_path = image_dir.join("index.json");
+    if !fs::try_exists(&index_path).await? {
+        return Err(FsError::InvalidOciImage(
+            "the archive has neither a manifest.json nor an index.json".to_string(),
+        ));
+    }
+
+    let mut manifest: OciManifest = read_json(&index_path).await?;
+    for _ in 0..MAX_OCI_INDEX_DEPTH {
+        let Some(manifests) = manifest.manifests else {
+            return manifest
+                .layers
+                .iter()
+                .map(|layer| get_blob_path(image_dir, &layer.digest))
+                .collect();
+        };
+
+        let descriptor = pick_manifest(manifests)?;
+        manifest = read_json(&get_blob_path(image_dir, &descriptor.digest)?).await?;
+    }
+
+    Err(FsError::InvalidOciImage(format!(
+        "image indexes are nested more than {} deep",
+        MAX_OCI_INDEX_DEPTH
+    )))
+}
+
+/// Returns the manifest of an image index for Linux on the current architecture, or the first
+/// one if there is none.
+fn pick_manifest(manifests: Vec<OciDescriptor>) -> FsResult<OciDescriptor> {
+    let architecture = get_oci_architecture();
+
+    let position = manifests
+        .iter()
+        .position(|manifest| {
+            manifest.platform.as_ref().is_some_and(|platform| {
+                platform.os == "linux" && platform.architecture == architecture
+            })
+        })
+        .unwrap_or(0);
+
+    manifests
+        .into_iter()
+        .nth(position)
+        .ok_or_else(|| FsError::InvalidOciImage("an image index lists no manifests".to_string()))
+}
+
+/// Returns the path of the blob with `digest` in the image unpacked in `image_dir`.
+fn get_blob_path(image_dir: &Path, digest: &str) -> FsResult<PathBuf> {
+    let (algorithm, hex) = digest
+        .split_once(':')
+        .filter(|(algorithm, hex)| {
+            [algorithm, hex]
+                .iter()
+                .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric()))
+        })
+        .ok_or_else(|| FsError::InvalidOciImage(format!("invalid digest {}", digest)))?;
+
+    Ok(image_dir.join("blobs").join(algorithm).join(hex))
+}
+
+/// Returns the path of the file at `path` relative to the image unpacked in `image_dir`,
+/// refusing paths outside of it.
+fn get_image_path(image_dir: &Path, path: &str) -> FsResult<PathBuf> {
+    let relative = Path::new(path);
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(FsError::InvalidOciImage(format!(
+            "invalid layer path {}",
+            path
+        )));
+    }
+
+    Ok(image_dir.join(relative))
+}
+
+/// Reads and parses the JSON file at `path`.
+async fn read_json<T>(path: &Path) -> FsResult<T>
+where
+    T: DeserializeOwned,
+{
+    let bytes = fs::read(path).await?;
+    serde_json::from_slice(&bytes)
+        .map_err(|e| FsError::InvalidOciImage(format!("{}: {}", path.display(), e)))
+}
+
+/// Returns the path of the uncompressed tar archive of the layer at `layer_path`, decompressing
+/// it to `tar_path` if it is compressed.
+async fn decompress_layer(layer_path: &Path, tar_path: &Path) -> FsResult<PathBuf> {
+    let mut magic = Vec::new();
+    fs::File::open(layer_path)
+        .await?
+        .take(ZSTD_MAGIC.len() as u64)
+        .read_to_end(&mut magic)
+        .await?;
+
+    let reader = BufReader::new(fs::File::open(layer_path).await?);
+    let mut decoder: Box<dyn AsyncRead + Unpin + Send> = if magic.starts_with(&GZIP_MAGIC) {
+        let mut decoder = GzipDecoder::new(reader);
+        decoder.multiple_members(true);
+        Box::new(decoder)
+    } else if magic.starts_with(&ZSTD_MAGIC) {
+        Box::new(ZstdDecoder::new(reader))
+    } else {
+        return Ok(layer_path.to_path_buf());
+    };
+
+    let mut file = fs::File::create(tar_path).await?;
+    io::copy(&mut decoder, &mut file).await?;
+
+    Ok(tar_path.to_path_buf())
+}
+
+/// Applies the whiteouts of the layer in the tar archive at `tar_path` to `root`, removing the
+/// entries they hide.
+async fn apply_whiteouts<S>(root: &mut Dir<S>, tar_path: &Path) -> FsResult<()>
+where
+    S: IpldStore + Send + Sync,
+{
+    let mut archive = Archive::new(fs::File::open(tar_path).await?);
+    let mut entries = archive.entries()?;
+    let mut whiteouts = Vec::new();
+    while let Some(entry) = entries.next().await {
+        let path = archive::normalize_entry_path(&entry?.path()?)?;
+        let (parent, name) = archive::split_entry_path(&path);
+        if name == OPAQUE_WHITEOUT {
+            whiteouts.push((parent.to_string(), None));
+        } else if is_whiteout(&path) && !name.starts_with(WHITEOUT_META_PREFIX) {
+            let hidden = &name[WHITEOUT_PREFIX.len()..];
+            whiteouts.push((parent.to_string(), Some(hidden.to_string())));
+        }
+    }
+
+    for (parent, name) in whiteouts {
+        let dir = if parent.is_empty() {
+            &mut *root
+        } else {
+            match root.find_mut(&parent).await? {
+                Some(Entity::Dir(dir)) => dir,
+                _ => continue,
+            }
+        };
+
+        let names = match name {
+            Some(name) if dir.has_entry(&name)? => vec![name],
+            Some(_) => continue,
+            None => dir.get_entry_names().map(|name| name.to_string()).collect(),
+        };
+        for name in names {
+            dir.remove_entry(&name)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns whether the entry at `path` is a whiteout rather than content of its layer.
+fn is_whiteout(path: &str) -> bool {
+    archive::split_entry_path(path)
+        .1
+        .starts_with(WHITEOUT_PREFIX)
+}
+
+/// Returns the OCI name of the architecture of the current platform.
+fn get_oci_architecture() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        architecture => architecture,
+    }
+}
+
+/// Exports the directory tree `source` of the filesystem at `paths` as an OCI image archive
+/// written to `writer`, reporting the entries written to `progress`.
+async fn export_image(
+    paths: &MfsPaths,
+    source: ExportSource,
+    base: Option<Cid>,
+    tag: &str,
+    writer: impl AsyncWrite + Unpin + Send + Sync,
+    progress: &JobProgress,
+) -> FsResult<OciImage> {
+    let store = paths.open_store().await?;
+    let root = match source {
+        ExportSource::Cid(cid) => cid,
+        ExportSource::Path(path) => {
+            let head = head::checkpoint_head(paths).await?.ok_or_else(|| {
+                FsError::InvalidOperation(format!(
+                    "filesystem at {} has no recorded state to export",
+                    paths.get_mount_dir().display()
+                ))
+            })?;
+            tree::get_path_cid(&store, &head, path.trim_matches('/')).await?
+        }
+    };
+
+    let image_dir = tempfile::tempdir()?;
+    let image = build_image_dir(
+        &store,
+        &root,
+        base.as_ref(),
+        tag,
+        image_dir.path(),
+        progress,
+    )
+    .await?;
+
+    let mut builder = Builder::new(writer);
+    for name in ["oci-layout", "index.json", "manifest.json"] {
+        builder
+            .append_path_with_name(image_dir.path().join(name), name)
+            .await?;
+    }
+
+    let blobs_dir = image_dir.path().join("blobs").join("sha256");
+    let mut blobs = fs::read_dir(&blobs_dir).await?;
+    let mut names = Vec::new();
+    while let Some(blob) = blobs.next_entry().await? {
+        names.push(blob.file_name().to_string_lossy().to_string());
+    }
+    names.sort();
+    for name in names {
+        builder
+            .append_path_with_name(blobs_dir.join(&name), format!("blobs/sha256/{}", name))
+            .await?;
+    }
+    builder.into_inner().await?;
+
+    tracing::info!(
+        "exported {} as {} with {} layers",
+        root,
+        image.digest,
+        image.layers.len()
+    );
+
+    Ok(image)
+}
+
+/// Writes the image of the directory tree at `root` to `image_dir` in the OCI image layout, with
+/// the tree at `base` as a layer of its own if there is one.
+async fn build_image_dir<S>(
+    store: &S,
+    root: &Cid,
+    base: Option<&Cid>,
+    tag: &str,
+    image_dir: &Path,
+    progress: &JobProgress,
+) -> FsResult<OciImage>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    fs::create_dir_all(image_dir.join("blobs").join("sha256")).await?;
+
+    let tar_path = image_dir.join("layer.tar");
+    let mut layers = Vec::new();
+    let mut diff_ids = Vec::new();
+    let trees = match base {
+        Some(base) => vec![(None, base), (Some(base), root)],
+        None => vec![(None, root)],
+    };
+    for (lower, upper) in trees {
+        let mut builder = Builder::new(fs::File::create(&tar_path).await?);
+        let count = match lower {
+            Some(lower) => write_diff_layer(store, lower, upper, &mut builder, progress).await?,
+            None => {
+                let dir = Dir::load(upper, store.clone()).await?;
+                archive::export_entries_under(&dir, "", &mut builder, progress).await?
+            }
+        };
+        builder.into_inner().await?.shutdown().await?;
+
+        let (diff_id, layer) = write_layer_blob(image_dir, &tar_path).await?;
+        tracing::debug!("wrote layer {} with {} entries", layer.digest, count);
+        diff_ids.push(diff_id);
+        layers.push(layer);
+    }
+    fs::remove_file(&tar_path).await?;
+
+    let config = json!({
+        "created": Utc::now().to_rfc3339(),
+        "architecture": get_oci_architecture(),
+        "os": "linux",
+        "config": {},
+        "rootfs": { "type": "layers", "diff_ids": diff_ids },
+    });
+    let config = write_json_blob(image_dir, &config).await?;
+
+    let manifest = json!({
+        "schemaVersion": 2,
+        "mediaType": OCI_MANIFEST_MEDIA_TYPE,
+        "config": get_descriptor(OCI_CONFIG_MEDIA_TYPE, &config),
+        "layers": layers
+            .iter()
+            .map(|layer| get_descriptor(OCI_LAYER_MEDIA_TYPE, layer))
+            .collect::<Vec<_>>(),
+    });
+    let manifest = write_json_blob(image_dir, &manifest).await?;
+
+    let mut descriptor = get_descriptor(OCI_MANIFEST_MEDIA_TYPE, &manifest);
+    descriptor["annotations"] = json!({ OCI_REF_NAME_ANNOTATION: tag });
+    let index = json!({
+        "schemaVersion": 2,
+        "mediaType": OCI_INDEX_MEDIA_TYPE,
+        "manifests": [descriptor],
+    });
+    write_json(&image_dir.join("index.json"), &index).await?;
+    write_json(
+        &image_dir.join("oci-layout"),
+        &json!({ "imageLayoutVersion": "1.0.0" }),
+    )
+    .await?;
+
+    // What `docker load` reads, with blob paths as in the OCI image layout
+    let docker_manifest = json!([{
+        "Config": get_blob_name(&config.digest),
+        "RepoTags": [tag],
+        "Layers": layers
+            .iter()
+            .map(|layer| get_blob_name(&layer.digest))
+            .collect::<Vec<_>>(),
+    }]);
+    write_json(&image_dir.join("manifest.json"), &docker_manifest).await?;
+
+    Ok(OciImage {
+        digest: manifest.digest,
+        tag: tag.to_string(),
+        layers: layers.into_iter().map(|layer| layer.digest).collect(),
+    })
+}
+
+/// Writes the changes from the directory tree at `lower` to the one at `upper` to `builder` as a
+/// layer, with whiteouts for the removed entries.
+///
+/// Returns the number of entries written.
+async fn write_diff_layer<S, W>(
+    store: &S,
+    lower: &Cid,
+    upper: &Cid,
+    builder: &mut Builder<W>,
+    progress: &JobProgress,
+) -> FsResult<u64>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+    W: AsyncWrite + Unpin + Send + Sync,
+{
+    let mut changes = filesystem::diff(*lower, *upper, store.clone())
+        .try_collect::<Vec<_>>()
+        .await?;
+    changes.sort_by(|a, b| a.get_path().cmp(b.get_path()));
+
+    let root = Dir::load(upper, store.clone()).await?;
+    let mut written = HashSet::new();
+    let mut count = 0;
+    for change in changes {
+        let path = change.get_path().as_str();
+        let (removed, added) = match change.get_kind() {
+            DiffKind::Removed => (Some(path), None),
+            DiffKind::Renamed { from } => (Some(from.as_str()), Some(path)),
+            DiffKind::Added | DiffKind::Modified => (None, Some(path)),
+        };
+
+        if let Some(removed) = removed {
+            let (parent, name) = archive::split_entry_path(removed);
+            count += write_parent_dirs(&root, removed, builder, &mut written, progress).await?;
+            let whiteout = match parent.is_empty() {
+                true => format!("{}{}", WHITEOUT_PREFIX, name),
+                false => format!("{}/{}{}", parent, WHITEOUT_PREFIX, name),
+            };
+
+            let mut header = Header::new_gnu();
+            header.set_entry_type(EntryType::Regular);
+            header.set_mode(0o644);
+            header.set_size(0);
+            builder
+                .append_data(&mut header, whiteout, io::empty())
+                .await?;
+            count += 1;
+            progress.add_done(1);
+        }
+
+        if let Some(added) = added {
+            count += write_parent_dirs(&root, added, builder, &mut written, progress).await?;
+            let Some(entity) = root.find(added).await? else {
+                continue;
+            };
+
+            if archive::append_entity(builder, added, entity).await? {
+                count += 1;
+                progress.add_done(1);
+            }
+
+            if let Entity::Dir(dir) = entity {
+                count += archive::export_entries_under(dir, added, builder, progress).await?;
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+/// Writes the directories `path` is in to `builder`, as they are in `root`, unless they are in
+/// `written` already.
+///
+/// Returns the number of entries written.
+async fn write_parent_dirs<S, W>(
+    root: &Dir<S>,
+    path: &str,
+    builder: &mut Builder<W>,
+    written: &mut HashSet<String>,
+    progress: &JobProgress,
+) -> FsResult<u64>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+    W: AsyncWrite + Unpin + Send + Sync,
+{
+    let mut count = 0;
+    let segments = path.split('/').collect::<Vec<_>>();
+    for depth in 1..segments.len() {
+        let parent = segments[..depth].join("/");
+        if written.contains(&parent) {
+            continue;
+        }
+
+        if let Some(entity) = root.find(&parent).await? {
+            archive::append_entity(builder, &parent, entity).await?;
+            count += 1;
+            progress.add_done(1);
+        }
+        written.insert(parent);
+    }
+
+    Ok(count)
+}
+
+/// Compresses the layer in the tar archive at `tar_path` into a blob of the image in
+/// `image_dir`.
+///
+/// Returns the digest of the uncompressed layer and the blob.
+async fn write_layer_blob(image_dir: &Path, tar_path: &Path) -> FsResult<(String, OciBlob)> {
+    let diff_id = get_file_blob(tar_path).await?.digest;
+
+    let compressed_path = image_dir.join("layer.tar.gz");
+    let mut encoder = GzipEncoder::new(fs::File::create(&compressed_path).await?);
+    io::copy(&mut fs::File::open(tar_path).await?, &mut encoder).await?;
+    encoder.shutdown().await?;
+
+    let blob = get_file_blob(&compressed_path).await?;
+    fs::rename(&compressed_path, get_blob_path(image_dir, &blob.digest)?).await?;
+
+    Ok((diff_id, blob))
+}
+
+/// Writes `value` as a JSON blob of the image in `image_dir`.
+async fn write_json_blob(image_dir: &Path, value: &serde_json::Value) -> FsResult<OciBlob> {
+    let bytes = serde_json::to_vec(value).map_err(FsError::custom)?;
+    let blob = OciBlob {
+        digest: format!("sha256:{}", hex::encode(Sha256::digest(&bytes))),
+        size: bytes.len() as u64,
+    };
+    fs::write(get_blob_path(image_dir, &blob.digest)?, bytes).await?;
+
+    Ok(blob)
+}
+
+/// Writes `value` as JSON to the file at `path`.
+async fn write_json(path: &Path, value: &serde_json::Value) -> FsResult<()> {
+    let bytes = serde_json::to_vec(value).map_err(FsError::custom)?;
+    fs::write(path, bytes).await?;
+    Ok(())
+}
+
+/// Returns the digest and size of the file at `path` as a blob.
+async fn get_file_blob(path: &Path) -> FsResult<OciBlob> {
+    let mut file = fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; DIGEST_CHUNK_LEN];
+    let mut size = 0;
+    loop {
+        let read = file.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+
+        hasher.update(&buf[..read]);
+        size += read as u64;
+    }
+
+    Ok(OciBlob {
+        digest: format!("sha256:{}", hex::encode(hasher.finalize())),
+        size,
+    })
+}
+
+/// Returns the descriptor of `blob` with `media_type`.
+fn get_descriptor(media_type: &str, blob: &OciBlob) -> serde_json::Value {
+    json!({
+        "mediaType": media_type,
+        "digest": blob.digest,
+        "size": blob.size,
+    })
+}
+
+/// Returns the path of the blob with `digest` relative to the root of the image.
+fn get_blob_name(digest: &str) -> String {
+    format!("blobs/{}", digest.replacen(':', "/", 1))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::MemoryStore;
+
+    use crate::filesystem::File;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_import_image_dir() -> anyhow::Result<()> {
+        let image_dir = tempfile::tempdir()?;
+        let lower =
+            build_test_layer(&["etc/hosts", "etc/passwd", "var/cache/a", "var/cache/b"]).await?;
+        fs::write(image_dir.path().join("lower.tar"), lower).await?;
+
+        // Whiteouts hide single entries, or every entry of their directory below the layer
+        let upper = build_test_layer(&[
+            "etc/.wh.passwd",
+            "var/cache/.wh..wh..opq",
+            "var/cache/c",
+            "tmp/.wh.missing",
+        ])
+        .await?;
+        let mut encoder = GzipEncoder::new(Vec::new());
+        encoder.write_all(&upper).await?;
+        encoder.shutdown().await?;
+        fs::write(image_dir.path().join("upper.tar.gz"), encoder.into_inner()).await?;
+
+        let manifest =
+            r#"[{"Config":"config.json","RepoTags":[],"Layers":["lower.tar","upper.tar.gz"]}]"#;
+        fs::write(image_dir.path().join("manifest.json"), manifest).await?;
+
+        let store = MemoryStore::default();
+        let layers = import_image_dir(&store, image_dir.path(), &JobProgress::default()).await?;
+        assert_eq!(layers.len(), 2);
+
+        let root = Dir::load(&layers[1], store.clone()).await?;
+        assert_eq!(root.get_base(), Some(&layers[0]));
+        assert!(root.find("etc/hosts").await?.is_some());
+        assert!(root.find("etc/passwd").await?.is_none());
+        assert!(root.find("etc/.wh.passwd").await?.is_none());
+        assert!(root.find("var/cache/a").await?.is_none());
+        assert!(root.find("var/cache/c").await?.is_some());
+
+        // The lower layer is left as it was
+        let lower = Dir::load(&layers[0], store.clone()).await?;
+        assert!(lower.find("etc/passwd").await?.is_some());
+        assert!(lower.find("var/cache/a").await?.is_some());
+
+        let escaping = r#"[{"Layers":["../lower.tar"]}]"#;
+        fs::write(image_dir.path().join("manifest.json"), escaping).await?;
+        let result = import_image_dir(&store, image_dir.path(), &JobProgress::default()).await;
+        assert!(matches!(result, Err(FsError::InvalidOciImage(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_build_image_dir() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut root = Dir::new(store.clone());
+        for path in ["etc/hosts", "etc/passwd", "var/cache/a"] {
+            root.find_or_create(path, true).await?;
+        }
+        let base = root.checkpoint().await?;
+
+        let hosts = File::with_content(store.clone(), b"127.0.0.1 sandbox".as_slice()).await?;
+        let Some(Entity::Dir(etc)) = root.find_mut("etc").await? else {
+            unreachable!()
+        };
+        etc.put_adapted_file("hosts", hosts).await?;
+        root.remove("etc/passwd").await?;
+        root.find_or_create("tmp/out/log", true).await?;
+        let cid = root.checkpoint().await?;
+
+        let image_dir = tempfile::tempdir()?;
+        let progress = JobProgress::default();
+        let image = build_image_dir(
+            &store,
+            &cid,
+            Some(&base),
+            "sandbox:latest",
+            image_dir.path(),
+            &progress,
+        )
+        .await?;
+        assert_eq!(image.get_layers().len(), 2);
+        assert!(fs::try_exists(image_dir.path().join("index.json")).await?);
+        assert!(fs::try_exists(get_blob_path(image_dir.path(), image.get_digest())?).await?);
+
+        // The image imports back as the exported tree, with the removed entries whited out
+        let imported = MemoryStore::default();
+        let layers = import_image_dir(&imported, image_dir.path(), &progress).await?;
+        assert_eq!(layers.len(), 2);
+        let lower = Dir::load(&layers[0], imported.clone()).await?;
+        assert!(lower.find("etc/passwd").await?.is_some());
+        let upper = Dir::load(&layers[1], imported.clone()).await?;
+        assert!(upper.find("etc/passwd").await?.is_none());
+        assert!(upper.find("var/cache/a").await?.is_some());
+        assert!(upper.find("tmp/out/log").await?.is_some());
+        let Some(Entity::File(hosts)) = upper.find("etc/hosts").await? else {
+            unreachable!()
+        };
+        assert_eq!(hosts.get_size().await?, 17);
+
+        Ok(())
+    }
+
+    /// Builds an uncompressed layer with an empty file at each of `paths`.
+    async fn build_test_layer(paths: &[&str]) -> anyhow::Result<Vec<u8>> {
+        let mut builder = Builder::new(Vec::new());
+        for path in paths {
+            let mut header = Header::new_gnu();
+            header.set_mode(0o644);
+            header.set_size(0);
+            builder
+                .append_data(&mut header, path, tokio::io::empty())
+                .await?;
+        }
+
+        Ok(builder.into_inner().await?)
+    }
+}
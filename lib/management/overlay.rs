@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+
+use ipldstore::ipld::cid::Cid;
+
+use crate::{
+    filesystem::Dir,
+    management::{find, head},
+    FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Replace the root of a monofs filesystem with a writable overlay of a directory in its store
+///
+/// The filesystem shows the directory at `base`, like the root of a shared image, and stores only
+/// what is changed on top of it: files it writes shadow the files of the base, and files it
+/// deletes are hidden without touching the base. Any number of filesystems sharing a blocks
+/// directory can be layered over the same base this way, each with its own thin layer. The base
+/// is kept from garbage collection for as long as an overlay of it is.
+///
+/// If the filesystem is mounted, its root is swapped in place. Changes made since the last
+/// checkpoint are discarded, so take a snapshot first if they should be kept.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `base` - The CID of the directory to layer the filesystem over, which has to be in its store
+///
+/// ## Returns
+/// The CID of the new root
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let base = "bafyreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku".parse()?;
+/// let root = management::overlay_mfs(Some("mfstest".into()), base).await?;
+/// println!("new root: {}", root);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn overlay_mfs(mount_dir: Option<PathBuf>, base: Cid) -> FsResult<Cid> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let store = paths.open_store().await?;
+
+    let mut root = Dir::overlay(&base, store).await?;
+    let cid = root.checkpoint().await?;
+
+    let operation = format!("overlay {}", base);
+    head::set_head(&paths, None, &cid, &operation).await?;
+    tracing::info!("layered {} over {}", paths.get_mount_dir().display(), base);
+
+    Ok(cid)
+}
@@ -0,0 +1,224 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use getset::Getters;
+use ipldstore::{ipld::cid::Cid, IpldStore};
+use serde::Serialize;
+use sqlx::{sqlite::SqliteRow, Row};
+
+use crate::{
+    management::{db, find},
+    utils, FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A CID whose blocks are kept by garbage collection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct Pin {
+    /// The pinned CID.
+    #[serde(serialize_with = "utils::serialize_display")]
+    cid: Cid,
+
+    /// When the CID was pinned.
+    created_at: DateTime<Utc>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Pin a CID so that garbage collection keeps it and every block reachable from it
+///
+/// Pinning a CID that is already pinned has no effect. The CID must refer to a block in the
+/// filesystem's blocks directory.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `cid` - The CID to pin
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let cid = "bafyreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku".parse()?;
+/// management::pin(Some("mfstest".into()), cid).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn pin(mount_dir: Option<PathBuf>, cid: Cid) -> FsResult<Pin> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let pool = db::get_db_pool(paths.fs_db_path()).await?;
+
+    // A pin can only protect blocks that are still there
+    let store = paths.open_store().await?;
+    if !store.has(&cid).await {
+        return Err(FsError::InvalidOperation(format!(
+            "block {} is not in the store",
+            cid
+        )));
+    }
+
+    let fs_id = db::get_fs_id(&pool, paths.get_mount_dir()).await?;
+    sqlx::query("INSERT OR IGNORE INTO pins (fs_id, cid) VALUES (?, ?)")
+        .bind(fs_id)
+        .bind(cid.to_string())
+        .execute(&pool)
+        .await?;
+    tracing::info!("pinned {}", cid);
+
+    let record = sqlx::query("SELECT cid, created_at FROM pins WHERE fs_id = ? AND cid = ?")
+        .bind(fs_id)
+        .bind(cid.to_string())
+        .fetch_one(&pool)
+        .await?;
+
+    pin_from_row(&record)
+}
+
+/// Unpin a CID so that garbage collection may remove its blocks again
+///
+/// The blocks are still kept if they are reachable from the filesystem's root, a snapshot or
+/// another pin.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `cid` - The CID to unpin
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let cid = "bafyreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku".parse()?;
+/// management::unpin(Some("mfstest".into()), cid).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn unpin(mount_dir: Option<PathBuf>, cid: Cid) -> FsResult<()> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let pool = db::get_db_pool(paths.fs_db_path()).await?;
+
+    let fs_id = db::get_fs_id(&pool, paths.get_mount_dir()).await?;
+    let result = sqlx::query("DELETE FROM pins WHERE fs_id = ? AND cid = ?")
+        .bind(fs_id)
+        .bind(cid.to_string())
+        .execute(&pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(FsError::PinNotFound(cid.to_string()));
+    }
+
+    tracing::info!("unpinned {}", cid);
+    Ok(())
+}
+
+/// List the pinned CIDs of a monofs filesystem, oldest first
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// for pin in management::list_pins(None).await? {
+///     println!("{}", pin.get_cid());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn list_pins(mount_dir: Option<PathBuf>) -> FsResult<Vec<Pin>> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let pool = db::get_db_pool(paths.fs_db_path()).await?;
+
+    let rows = sqlx::query(
+        r#"
+        SELECT p.cid, p.created_at
+        FROM pins p
+        JOIN filesystems f ON p.fs_id = f.id
+        WHERE f.mount_dir = ?
+        ORDER BY p.created_at, p.id
+        "#,
+    )
+    .bind(paths.get_mount_dir().to_string_lossy().to_string())
+    .fetch_all(&pool)
+    .await?;
+
+    rows.iter().map(pin_from_row).collect()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Construct a pin from a row of the pins table.
+fn pin_from_row(row: &SqliteRow) -> FsResult<Pin> {
+    let cid: String = row.get("cid");
+    Ok(Pin {
+        cid: Cid::try_from(cid.as_str())?,
+        created_at: row.get("created_at"),
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::MemoryStore;
+    use tempfile::TempDir;
+    use tokio::fs;
+
+    use crate::{management::FsHead, store::FlatFsStore};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pin_unpin_and_list() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let mount_dir = fs::canonicalize(temp.path()).await?.join("mnt");
+        let paths = find::helper::setup_test_mfs(&mount_dir).await?;
+
+        let store = FlatFsStore::new(paths.blocks_dir());
+        let first = store.put_bytes(b"first".as_slice()).await?;
+        let second = store.put_bytes(b"second".as_slice()).await?;
+        FsHead::new(paths.fs_db_path(), &mount_dir)
+            .await?
+            .set(&first, "checkpoint")
+            .await?;
+
+        // Pinning is idempotent
+        pin(Some(mount_dir.clone()), first).await?;
+        pin(Some(mount_dir.clone()), second).await?;
+        pin(Some(mount_dir.clone()), first).await?;
+
+        let pins = list_pins(Some(mount_dir.clone())).await?;
+        let cids = pins.iter().map(|pin| *pin.get_cid()).collect::<Vec<_>>();
+        assert_eq!(cids, vec![first, second]);
+
+        // Blocks that are not in the store cannot be pinned
+        let missing = MemoryStore::default()
+            .put_bytes(b"missing".as_slice())
+            .await?;
+        let result = pin(Some(mount_dir.clone()), missing).await;
+        assert!(matches!(result, Err(FsError::InvalidOperation(_))));
+
+        unpin(Some(mount_dir.clone()), first).await?;
+        let pins = list_pins(Some(mount_dir.clone())).await?;
+        assert_eq!(pins.len(), 1);
+        assert_eq!(pins[0].get_cid(), &second);
+
+        let result = unpin(Some(mount_dir), first).await;
+        assert!(matches!(result, Err(FsError::PinNotFound(_))));
+
+        Ok(())
+    }
+}
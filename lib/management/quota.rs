@@ -0,0 +1,244 @@
+use std::path::{Path, PathBuf};
+
+use getset::Getters;
+use serde::Serialize;
+use sqlx::{Pool, Row, Sqlite};
+
+use crate::{
+    config::QuotaConfig,
+    management::{db, find},
+    server::{self, ControlRequest},
+    FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Tracks the quota of a filesystem, and how much of it is used, in its fs database.
+///
+/// The quota is recorded when the filesystem is initialized and loaded by its server on every
+/// start. The server records its usage whenever it records a new head.
+#[derive(Debug, Clone)]
+pub struct FsQuota {
+    /// The filesystem database.
+    fs_db: Pool<Sqlite>,
+
+    /// The mount directory identifying the filesystem.
+    mount_dir: PathBuf,
+}
+
+/// How much a filesystem holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct QuotaUsage {
+    /// The total size of the files, as clients see them.
+    logical_bytes: u64,
+
+    /// The bytes the blocks take up on disk.
+    store_bytes: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FsQuota {
+    /// Opens the quota of the filesystem mounted at `mount_dir`.
+    pub async fn new(
+        fs_db_path: impl AsRef<Path>,
+        mount_dir: impl Into<PathBuf>,
+    ) -> FsResult<Self> {
+        Ok(Self {
+            fs_db: db::get_db_pool(fs_db_path.as_ref()).await?,
+            mount_dir: mount_dir.into(),
+        })
+    }
+
+    /// Returns the quota of the filesystem, which is unlimited if none was recorded.
+    pub async fn get(&self) -> FsResult<QuotaConfig> {
+        let record = sqlx::query(
+            "SELECT max_logical_bytes, max_store_bytes FROM quotas WHERE mount_dir = ?",
+        )
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .fetch_optional(&self.fs_db)
+        .await?;
+
+        let Some(row) = record else {
+            return Ok(QuotaConfig::unlimited());
+        };
+
+        Ok(QuotaConfig::builder()
+            .max_logical_bytes(
+                row.get::<Option<i64>, _>("max_logical_bytes")
+                    .map(|bytes| bytes as u64),
+            )
+            .max_store_bytes(
+                row.get::<Option<i64>, _>("max_store_bytes")
+                    .map(|bytes| bytes as u64),
+            )
+            .build())
+    }
+
+    /// Records `quota` as the quota of the filesystem.
+    pub async fn set(&self, quota: &QuotaConfig) -> FsResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO quotas (mount_dir, max_logical_bytes, max_store_bytes)
+            VALUES (?, ?, ?)
+            ON CONFLICT (mount_dir) DO UPDATE
+            SET max_logical_bytes = excluded.max_logical_bytes,
+                max_store_bytes = excluded.max_store_bytes,
+                modified_at = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .bind(quota.get_max_logical_bytes().map(|bytes| bytes as i64))
+        .bind(quota.get_max_store_bytes().map(|bytes| bytes as i64))
+        .execute(&self.fs_db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns the usage last recorded for the filesystem, if any.
+    pub async fn get_usage(&self) -> FsResult<Option<QuotaUsage>> {
+        let record =
+            sqlx::query("SELECT logical_bytes, store_bytes FROM quotas WHERE mount_dir = ?")
+                .bind(self.mount_dir.to_string_lossy().to_string())
+                .fetch_optional(&self.fs_db)
+                .await?;
+
+        let usage = record.and_then(|row| {
+            let logical_bytes = row.get::<Option<i64>, _>("logical_bytes")?;
+            let store_bytes = row.get::<Option<i64>, _>("store_bytes")?;
+            Some(QuotaUsage::new(logical_bytes as u64, store_bytes as u64))
+        });
+
+        Ok(usage)
+    }
+
+    /// Records `usage` as how much the filesystem holds.
+    pub async fn set_usage(&self, usage: &QuotaUsage) -> FsResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO quotas (mount_dir, logical_bytes, store_bytes)
+            VALUES (?, ?, ?)
+            ON CONFLICT (mount_dir) DO UPDATE
+            SET logical_bytes = excluded.logical_bytes,
+                store_bytes = excluded.store_bytes,
+                modified_at = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .bind(usage.logical_bytes as i64)
+        .bind(usage.store_bytes as i64)
+        .execute(&self.fs_db)
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl QuotaUsage {
+    /// Creates a usage of `logical_bytes` in files taking up `store_bytes` on disk.
+    pub fn new(logical_bytes: u64, store_bytes: u64) -> Self {
+        Self {
+            logical_bytes,
+            store_bytes,
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Change how much a monofs filesystem may hold
+///
+/// The quota is recorded for every later start of the filesystem's server. If the filesystem is
+/// mounted, its server enforces the new quota right away, provided it was started with a quota.
+/// A server started without one does not count the size of its store, so it only enforces the
+/// new quota once the filesystem is attached again.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `quota` - The limits to enforce
+///
+/// ## Returns
+/// Whether the running server enforces the new quota already
+///
+/// ## Example
+/// ```no_run
+/// use monofs::{config::QuotaConfig, management};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let quota = QuotaConfig::builder().max_logical_bytes(Some(1 << 30)).build();
+/// management::set_quota(Some("mfstest".into()), quota).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn set_quota(mount_dir: Option<PathBuf>, quota: QuotaConfig) -> FsResult<bool> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    FsQuota::new(paths.fs_db_path(), paths.get_mount_dir())
+        .await?
+        .set(&quota)
+        .await?;
+
+    let request = ControlRequest::SetQuota { quota };
+    match server::try_send_control_request(paths.control_socket_path(), &request).await {
+        Ok(Some(_)) => {
+            tracing::info!("server enforces quota {:?}", quota);
+            Ok(true)
+        }
+        Ok(None) => Ok(false),
+        Err(FsError::ControlRequestFailed(message)) => {
+            tracing::warn!(
+                "quota recorded for the next start of the server: {}",
+                message
+            );
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::management::FS_DB_MIGRATOR;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fs_quota_get_set() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("fs.db");
+        db::init_db(&db_path, &FS_DB_MIGRATOR).await?;
+
+        let quota = FsQuota::new(&db_path, temp_dir.path().join("mnt")).await?;
+        assert_eq!(quota.get().await?, QuotaConfig::unlimited());
+        assert_eq!(quota.get_usage().await?, None);
+
+        // Usage can be recorded before or after the quota, without either overwriting the other
+        quota.set_usage(&QuotaUsage::new(10, 20)).await?;
+        let config = QuotaConfig::builder()
+            .max_logical_bytes(Some(100))
+            .max_store_bytes(Some(200))
+            .build();
+        quota.set(&config).await?;
+        assert_eq!(quota.get().await?, config);
+        assert_eq!(quota.get_usage().await?, Some(QuotaUsage::new(10, 20)));
+
+        quota.set_usage(&QuotaUsage::new(30, 40)).await?;
+        assert_eq!(quota.get().await?, config);
+        assert_eq!(quota.get_usage().await?, Some(QuotaUsage::new(30, 40)));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,289 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    path::Path,
+};
+
+use ipldstore::{ipld::cid::Cid, Codec, IpldStore};
+use sqlx::{Pool, Row, Sqlite};
+
+use crate::{management::db, store, FsError, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The reference counts of the blocks of a blocks directory, in the fs database of the
+/// filesystem that owns it.
+///
+/// A block is counted once for every counted root it is, and once for every counted node that
+/// links to it, leaving out the `previous` links of entities like
+/// [`collect_reachable`](crate::store::collect_reachable) does. The counts are brought up to date
+/// with the current roots by [`update`](Self::update), which only visits the nodes that were
+/// added or released since the last update, as a node is counted or released along with
+/// everything below it at once.
+///
+/// Nodes that are missing from the store when they would be counted are left out, so the counts
+/// never drop to zero for a block something still links to, at the cost of keeping some blocks
+/// that are released while missing.
+#[derive(Debug, Clone)]
+pub struct BlockRefs {
+    /// The fs database of the owner of the blocks.
+    fs_db: Pool<Sqlite>,
+}
+
+/// Reference counts being brought up to date, which are written back all at once.
+struct RefCounts<'a> {
+    /// The fs database the counts are read from.
+    fs_db: &'a Pool<Sqlite>,
+
+    /// The counts read so far, with the changes made to them.
+    counts: HashMap<Cid, u64>,
+
+    /// The blocks whose counts dropped to zero.
+    released: Vec<Cid>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl BlockRefs {
+    /// Opens the reference counts kept in the fs database at `fs_db_path`.
+    pub async fn new(fs_db_path: impl AsRef<Path>) -> FsResult<Self> {
+        Ok(Self {
+            fs_db: db::get_db_pool(fs_db_path.as_ref()).await?,
+        })
+    }
+
+    /// Returns how many blocks are counted, i.e. reachable from the roots of the last update.
+    pub async fn count(&self) -> FsResult<u64> {
+        let count: i64 = sqlx::query("SELECT COUNT(*) AS count FROM block_refs")
+            .fetch_one(&self.fs_db)
+            .await?
+            .get("count");
+
+        Ok(count as u64)
+    }
+
+    /// Brings the counts up to date with `roots`, which may list a root more than once.
+    ///
+    /// The roots that were not counted yet are counted before the ones no longer given are
+    /// released, so blocks that moved from one root to another are never released.
+    ///
+    /// Fails with [`FsError::BlockRefsConflict`] if the counts were updated by someone else in
+    /// the meantime.
+    ///
+    /// ## Returns
+    /// The blocks no root links to anymore, which may be removed from the store
+    pub async fn update<S>(&self, store: &S, roots: &[Cid]) -> FsResult<Vec<Cid>>
+    where
+        S: IpldStore,
+    {
+        let generation: i64 = sqlx::query("SELECT generation FROM block_ref_state WHERE id = 1")
+            .fetch_one(&self.fs_db)
+            .await?
+            .get("generation");
+
+        let mut counted = HashMap::new();
+        for row in sqlx::query("SELECT cid, refs FROM block_ref_roots")
+            .fetch_all(&self.fs_db)
+            .await?
+        {
+            let cid = Cid::try_from(row.get::<String, _>("cid").as_str())?;
+            counted.insert(cid, row.get::<i64, _>("refs") as u64);
+        }
+
+        let mut wanted = HashMap::<Cid, u64>::new();
+        for root in roots {
+            *wanted.entry(*root).or_default() += 1;
+        }
+
+        let mut counts = RefCounts {
+            fs_db: &self.fs_db,
+            counts: HashMap::new(),
+            released: Vec::new(),
+        };
+        for (root, refs) in &wanted {
+            let before = counted.get(root).copied().unwrap_or(0);
+            for _ in before..*refs {
+                counts.acquire(store, *root).await?;
+            }
+        }
+        for (root, refs) in &counted {
+            let after = wanted.get(root).copied().unwrap_or(0);
+            for _ in after..*refs {
+                counts.release(store, *root).await?;
+            }
+        }
+
+        // Write the counts back, unless another update got in between
+        let mut tx = self.fs_db.begin().await?;
+        let result = sqlx::query(
+            "UPDATE block_ref_state SET generation = generation + 1 WHERE id = 1 AND generation = ?",
+        )
+        .bind(generation)
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(FsError::BlockRefsConflict(
+                "block reference counts were updated by another collection".to_string(),
+            ));
+        }
+
+        for (cid, refs) in &counts.counts {
+            if *refs == 0 {
+                sqlx::query("DELETE FROM block_refs WHERE cid = ?")
+                    .bind(cid.to_string())
+                    .execute(&mut *tx)
+                    .await?;
+            } else {
+                sqlx::query(
+                    r#"
+                    INSERT INTO block_refs (cid, refs) VALUES (?, ?)
+                    ON CONFLICT (cid) DO UPDATE SET refs = excluded.refs
+                    "#,
+                )
+                .bind(cid.to_string())
+                .bind(*refs as i64)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        sqlx::query("DELETE FROM block_ref_roots")
+            .execute(&mut *tx)
+            .await?;
+        for (root, refs) in &wanted {
+            sqlx::query("INSERT INTO block_ref_roots (cid, refs) VALUES (?, ?)")
+                .bind(root.to_string())
+                .bind(*refs as i64)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(counts.released)
+    }
+}
+
+impl RefCounts<'_> {
+    /// Returns the count of `cid`, reading it from the database the first time.
+    async fn get(&mut self, cid: &Cid) -> FsResult<&mut u64> {
+        match self.counts.entry(*cid) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let refs = sqlx::query("SELECT refs FROM block_refs WHERE cid = ?")
+                    .bind(cid.to_string())
+                    .fetch_optional(self.fs_db)
+                    .await?
+                    .map_or(0, |row| row.get::<i64, _>("refs") as u64);
+                Ok(entry.insert(refs))
+            }
+        }
+    }
+
+    /// Counts a link to `cid`, and to everything below it if it was not counted yet.
+    async fn acquire<S>(&mut self, store: &S, cid: Cid) -> FsResult<()>
+    where
+        S: IpldStore,
+    {
+        let mut pending = vec![cid];
+        while let Some(cid) = pending.pop() {
+            // A missing node's links are unknown, so it is left for a later update to count
+            let codec: Codec = cid.codec().try_into()?;
+            if codec == Codec::DagCbor && !store.has(&cid).await {
+                continue;
+            }
+
+            let refs = self.get(&cid).await?;
+            *refs += 1;
+            if *refs == 1 {
+                pending.extend(store::get_links(store, &cid).await?);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Drops a link to `cid`, and releases everything below it if nothing else links to it.
+    async fn release<S>(&mut self, store: &S, cid: Cid) -> FsResult<()>
+    where
+        S: IpldStore,
+    {
+        let mut pending = vec![cid];
+        while let Some(cid) = pending.pop() {
+            let refs = self.get(&cid).await?;
+            if *refs == 0 {
+                continue;
+            }
+
+            *refs -= 1;
+            if *refs == 0 {
+                pending.extend(store::get_links(store, &cid).await?);
+                self.released.push(cid);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use ipldstore::MemoryStore;
+    use tempfile::TempDir;
+
+    use crate::{
+        filesystem::{Dir, File},
+        management::FS_DB_MIGRATOR,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_block_refs_update() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let fs_db_path = temp.path().join("fs.db");
+        db::init_db(&fs_db_path, &FS_DB_MIGRATOR).await?;
+        let refs = BlockRefs::new(&fs_db_path).await?;
+
+        // Two roots sharing a file
+        let store = MemoryStore::default();
+        let shared = File::with_content(store.clone(), b"shared".as_slice()).await?;
+        let only_first = File::with_content(store.clone(), b"first".as_slice()).await?;
+        let mut root = Dir::new(store.clone());
+        root.put_adapted_file("shared.txt", shared).await?;
+        root.put_adapted_file("first.txt", only_first).await?;
+        let first = root.checkpoint().await?;
+
+        root.remove_entry("first.txt")?;
+        let second = root.checkpoint().await?;
+
+        // Counting both roots releases nothing
+        assert!(refs.update(&store, &[first, second]).await?.is_empty());
+        let reachable = store::collect_reachable(&store, [first, second]).await?;
+        assert_eq!(refs.count().await?, reachable.len() as u64);
+
+        // Releasing the first root releases only what the second does not link to
+        let released = refs.update(&store, &[second]).await?;
+        let kept = store::collect_reachable(&store, [second]).await?;
+        let expected = reachable.difference(&kept).copied().collect::<HashSet<_>>();
+        assert_eq!(released.into_iter().collect::<HashSet<_>>(), expected);
+        assert!(refs.update(&store, &[second]).await?.is_empty());
+        assert_eq!(refs.count().await?, kept.len() as u64);
+
+        // A root given twice has to be released twice
+        refs.update(&store, &[second, second]).await?;
+        assert!(refs.update(&store, &[second]).await?.is_empty());
+        assert_eq!(refs.update(&store, &[]).await?.len(), kept.len());
+        assert_eq!(refs.count().await?, 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,408 @@
+use std::{
+    env,
+    fmt::{self, Display},
+    io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use getset::Getters;
+use ipldstore::codetable::{Code, MultihashDigest};
+use serde::Serialize;
+use sqlx::Row;
+use tokio::{fs, time};
+
+use crate::{
+    config::MountBackend,
+    management::{db, status},
+    server::{self, ControlRequest, ControlResponse},
+    utils::{
+        path::{
+            CONTROL_SOCKET_FILENAME, FS_DB_FILENAME, MFS_DIR_SUFFIX, MFS_LINK_FILENAME,
+            MOUNT_REGISTRY_SUBDIR, USER_STATE_SUBDIR,
+        },
+        MOUNT_REGISTRY_ENV_VAR,
+    },
+    FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How long a server may take to answer before its mount counts as unresponsive.
+const MOUNT_HEALTH_TIMEOUT: Duration = Duration::from_secs(2);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Keeps track of the monofs filesystems mounted on the host by a user.
+///
+/// Every mount is registered as a symbolic link to its mount directory, in
+/// `$XDG_STATE_HOME/monofs/mounts`, or `~/.local/state/monofs/mounts` if `XDG_STATE_HOME` is not
+/// set, unless the `MONOFS_REGISTRY_DIR` environment variable names another directory. Mounting
+/// registers a filesystem and detaching it unregisters it. Entries of filesystems whose data
+/// directory was removed without detaching them are dropped when the registry is listed.
+///
+/// ## Examples
+///
+/// ```no_run
+/// use monofs::management::MountRegistry;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let registry = MountRegistry::open()?;
+/// for mount_dir in registry.list().await? {
+///     println!("{}", mount_dir.display());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct MountRegistry {
+    /// The directory holding the entries of the registry.
+    dir: PathBuf,
+}
+
+/// A monofs filesystem mounted on the host, listed by [`list_mounts`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct MountInfo {
+    /// The directory where the filesystem is mounted.
+    mount_dir: PathBuf,
+
+    /// The mechanism the filesystem is mounted with.
+    backend: MountBackend,
+
+    /// The port the filesystem is served on, if it is served over NFS.
+    port: Option<u32>,
+
+    /// The PID of the supervisor serving the filesystem, if it is running.
+    supervisor_pid: Option<u32>,
+
+    /// How the filesystem is doing.
+    health: MountHealth,
+}
+
+/// How a mounted monofs filesystem is doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MountHealth {
+    /// The filesystem is mounted and its server answers requests.
+    Healthy,
+
+    /// The filesystem is mounted, but its server did not answer in time.
+    Unresponsive,
+
+    /// The supervisor is running, but the filesystem is not mounted.
+    Unmounted,
+
+    /// No supervisor is serving the filesystem.
+    Stopped,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl MountRegistry {
+    /// Opens the registry in `dir`, which is created when the first mount is registered.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Opens the user's registry, in the directory named by `MONOFS_REGISTRY_DIR` or otherwise
+    /// their state directory.
+    pub fn open() -> FsResult<Self> {
+        if let Some(dir) = env::var_os(MOUNT_REGISTRY_ENV_VAR).filter(|dir| !dir.is_empty()) {
+            return Ok(Self::new(dir));
+        }
+
+        let state_dir = match env::var_os("XDG_STATE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => {
+                let home = env::var_os("HOME").ok_or_else(|| {
+                    FsError::InvalidOperation(format!(
+                        "cannot find the mount registry without HOME or {} set",
+                        MOUNT_REGISTRY_ENV_VAR
+                    ))
+                })?;
+                PathBuf::from(home).join(".local").join("state")
+            }
+        };
+
+        Ok(Self::new(
+            state_dir
+                .join(USER_STATE_SUBDIR)
+                .join(MOUNT_REGISTRY_SUBDIR),
+        ))
+    }
+
+    /// Registers the filesystem mounted at `mount_dir`, unless it is registered already.
+    pub async fn register(&self, mount_dir: impl AsRef<Path>) -> FsResult<()> {
+        let mount_dir = mount_dir.as_ref();
+        fs::create_dir_all(&self.dir).await?;
+
+        let entry = self.entry_path(mount_dir);
+        match fs::symlink(mount_dir, &entry).await {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(()),
+            result => Ok(result?),
+        }
+    }
+
+    /// Unregisters the filesystem mounted at `mount_dir`, if it is registered.
+    pub async fn unregister(&self, mount_dir: impl AsRef<Path>) -> FsResult<()> {
+        match fs::remove_file(self.entry_path(mount_dir.as_ref())).await {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => Ok(result?),
+        }
+    }
+
+    /// Returns the mount directories of the registered filesystems in path order, dropping the
+    /// entries of those whose data directory is gone.
+    pub async fn list(&self) -> FsResult<Vec<PathBuf>> {
+        let mut mount_dirs = Vec::new();
+        if !fs::try_exists(&self.dir).await? {
+            return Ok(mount_dirs);
+        }
+
+        let mut entries = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let Ok(mount_dir) = fs::read_link(entry.path()).await else {
+                continue;
+            };
+
+            if !fs::try_exists(get_data_dir(&mount_dir)).await? {
+                tracing::info!("dropping stale mount entry for {}", mount_dir.display());
+                fs::remove_file(entry.path()).await?;
+                continue;
+            }
+
+            mount_dirs.push(mount_dir);
+        }
+
+        mount_dirs.sort();
+        Ok(mount_dirs)
+    }
+
+    /// Returns the path of the entry registering the filesystem mounted at `mount_dir`.
+    fn entry_path(&self, mount_dir: &Path) -> PathBuf {
+        let digest = Code::Blake3_256.digest(mount_dir.as_os_str().as_bytes());
+        self.dir.join(hex::encode(digest.digest()))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// List the monofs filesystems mounted on the host by the user
+///
+/// Every filesystem in the [`MountRegistry`] is listed, along with how it is doing. A filesystem
+/// is healthy if it is mounted and its server answers a request over its control socket within a
+/// few seconds. Mounts shared with [`share_mfs`](crate::management::share_mfs) are listed with the
+/// supervisor of the filesystem serving them.
+///
+/// ## Returns
+/// The registered filesystems, in the order of their mount directories
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// for mount in management::list_mounts().await? {
+///     println!("{}\t{}", mount.get_mount_dir().display(), mount.get_health());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn list_mounts() -> FsResult<Vec<MountInfo>> {
+    let registry = MountRegistry::open()?;
+
+    let mut mounts = Vec::new();
+    for mount_dir in registry.list().await? {
+        match get_mount_info(&mount_dir).await {
+            Ok(mount) => mounts.push(mount),
+            Err(e) => tracing::warn!("failed to inspect {}: {}", mount_dir.display(), e),
+        }
+    }
+
+    Ok(mounts)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Registers the filesystem mounted at `mount_dir` in the user's registry, only warning if it
+/// cannot be, since the filesystem is mounted either way.
+pub(crate) async fn register_mount(mount_dir: &Path) {
+    let result = async { MountRegistry::open()?.register(mount_dir).await };
+    if let Err(e) = result.await {
+        tracing::warn!("failed to register mount {}: {}", mount_dir.display(), e);
+    }
+}
+
+/// Unregisters the filesystem mounted at `mount_dir` from the user's registry, only warning if
+/// it cannot be.
+pub(crate) async fn unregister_mount(mount_dir: &Path) {
+    let result = async { MountRegistry::open()?.unregister(mount_dir).await };
+    if let Err(e) = result.await {
+        tracing::warn!("failed to unregister mount {}: {}", mount_dir.display(), e);
+    }
+}
+
+/// Returns what the fs database records about the filesystem mounted at `mount_dir` and how it is
+/// doing.
+pub(crate) async fn get_mount_info(mount_dir: &Path) -> FsResult<MountInfo> {
+    // The data directory is found next to the mount directory rather than through it, so that a
+    // hung mount does not hang the listing
+    let pool = db::get_db_pool(get_data_dir(mount_dir).join(FS_DB_FILENAME)).await?;
+
+    // Shared mounts are served by the server of another filesystem, on a port of their own
+    let shared = sqlx::query("SELECT served_by, port FROM shared_mounts WHERE mount_dir = ?")
+        .bind(mount_dir.to_string_lossy().to_string())
+        .fetch_optional(&pool)
+        .await?;
+    let (server_dir, shared_port) = match shared {
+        Some(row) => (
+            PathBuf::from(row.get::<String, _>("served_by")),
+            Some(row.get::<i64, _>("port") as u32),
+        ),
+        None => (mount_dir.to_path_buf(), None),
+    };
+
+    let record =
+        sqlx::query("SELECT supervisor_pid, port, backend FROM filesystems WHERE mount_dir = ?")
+            .bind(server_dir.to_string_lossy().to_string())
+            .fetch_optional(&pool)
+            .await?;
+    let (supervisor_pid, port, backend) = match record {
+        Some(row) => (
+            row.get::<Option<i64>, _>("supervisor_pid")
+                .map(|pid| pid as u32),
+            row.get::<Option<i64>, _>("port").map(|port| port as u32),
+            row.get::<String, _>("backend"),
+        ),
+        None => (None, None, String::new()),
+    };
+
+    let backend = match (shared_port, backend.as_str()) {
+        (None, "fuse") => MountBackend::Fuse,
+        _ => MountBackend::Nfs,
+    };
+
+    // A supervisor that died without cleaning up leaves its PID behind, which may have been reused
+    let supervisor_pid = match supervisor_pid {
+        Some(pid) if status::is_supervisor_running(pid, &server_dir).await => Some(pid),
+        _ => None,
+    };
+    let port = match backend {
+        MountBackend::Nfs => shared_port.or(port).filter(|_| supervisor_pid.is_some()),
+        MountBackend::Fuse => None,
+    };
+
+    let health = if supervisor_pid.is_none() {
+        MountHealth::Stopped
+    } else if !fs::try_exists(mount_dir.join(MFS_LINK_FILENAME))
+        .await
+        .unwrap_or(false)
+    {
+        MountHealth::Unmounted
+    } else if is_server_responsive(&server_dir).await {
+        MountHealth::Healthy
+    } else {
+        MountHealth::Unresponsive
+    };
+
+    Ok(MountInfo {
+        mount_dir: mount_dir.to_path_buf(),
+        backend,
+        port,
+        supervisor_pid,
+        health,
+    })
+}
+
+/// Returns whether the server of the filesystem mounted at `mount_dir` answers a request over its
+/// control socket within [`MOUNT_HEALTH_TIMEOUT`].
+pub(crate) async fn is_server_responsive(mount_dir: &Path) -> bool {
+    let socket_path = get_data_dir(mount_dir).join(CONTROL_SOCKET_FILENAME);
+    let request = server::try_send_control_request(socket_path, &ControlRequest::Metrics);
+    matches!(
+        time::timeout(MOUNT_HEALTH_TIMEOUT, request).await,
+        Ok(Ok(Some(ControlResponse::Metrics { .. })))
+    )
+}
+
+/// Returns the data directory of the filesystem mounted at `mount_dir`.
+fn get_data_dir(mount_dir: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.{}", mount_dir.display(), MFS_DIR_SUFFIX))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Display for MountHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Healthy => write!(f, "healthy"),
+            Self::Unresponsive => write!(f, "unresponsive"),
+            Self::Unmounted => write!(f, "unmounted"),
+            Self::Stopped => write!(f, "stopped"),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mount_registry() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let root = fs::canonicalize(temp_dir.path()).await?;
+        let registry = MountRegistry::new(root.join("registry"));
+        assert!(registry.list().await?.is_empty());
+
+        let mut mount_dirs = Vec::new();
+        for name in ["b", "a"] {
+            let mount_dir = root.join(name);
+            fs::create_dir_all(&mount_dir).await?;
+            fs::create_dir_all(get_data_dir(&mount_dir)).await?;
+            mount_dirs.push(mount_dir);
+        }
+
+        // Registering twice is the same as registering once
+        for mount_dir in &mount_dirs {
+            registry.register(mount_dir).await?;
+        }
+        registry.register(&mount_dirs[0]).await?;
+        assert_eq!(registry.list().await?, vec![root.join("a"), root.join("b")]);
+
+        registry.unregister(root.join("a")).await?;
+        registry.unregister(root.join("a")).await?;
+        assert_eq!(registry.list().await?, vec![root.join("b")]);
+
+        // Filesystems whose data is gone are dropped
+        fs::remove_dir_all(get_data_dir(&root.join("b"))).await?;
+        assert!(registry.list().await?.is_empty());
+        assert!(fs::read_dir(registry.get_dir())
+            .await?
+            .next_entry()
+            .await?
+            .is_none());
+
+        Ok(())
+    }
+}
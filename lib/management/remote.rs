@@ -0,0 +1,111 @@
+use std::path::{Path, PathBuf};
+
+use sqlx::{Pool, Row, Sqlite};
+
+use crate::{config::RemoteConfig, management::db, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Tracks the remote store of a filesystem in its fs database.
+///
+/// The remote store is recorded when the filesystem is initialized or cloned, and used by its
+/// server and by the management functions every time they open the filesystem's blocks.
+#[derive(Debug, Clone)]
+pub struct FsRemote {
+    /// The filesystem database.
+    fs_db: Pool<Sqlite>,
+
+    /// The mount directory identifying the filesystem.
+    mount_dir: PathBuf,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FsRemote {
+    /// Opens the remote store record of the filesystem mounted at `mount_dir`.
+    pub async fn new(
+        fs_db_path: impl AsRef<Path>,
+        mount_dir: impl Into<PathBuf>,
+    ) -> FsResult<Self> {
+        Ok(Self {
+            fs_db: db::get_db_pool(fs_db_path.as_ref()).await?,
+            mount_dir: mount_dir.into(),
+        })
+    }
+
+    /// Returns the remote store of the filesystem, if it has one.
+    pub async fn get(&self) -> FsResult<Option<RemoteConfig>> {
+        let record = sqlx::query("SELECT url, cache_max_bytes FROM remotes WHERE mount_dir = ?")
+            .bind(self.mount_dir.to_string_lossy().to_string())
+            .fetch_optional(&self.fs_db)
+            .await?;
+
+        Ok(record.map(|row| {
+            RemoteConfig::builder()
+                .url(row.get::<String, _>("url"))
+                .cache_max_bytes(row.get::<i64, _>("cache_max_bytes") as u64)
+                .build()
+        }))
+    }
+
+    /// Records `remote` as the remote store of the filesystem.
+    pub async fn set(&self, remote: &RemoteConfig) -> FsResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO remotes (mount_dir, url, cache_max_bytes)
+            VALUES (?, ?, ?)
+            ON CONFLICT (mount_dir) DO UPDATE
+            SET url = excluded.url,
+                cache_max_bytes = excluded.cache_max_bytes,
+                modified_at = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .bind(remote.get_url())
+        .bind(*remote.get_cache_max_bytes() as i64)
+        .execute(&self.fs_db)
+        .await?;
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::management::FS_DB_MIGRATOR;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fs_remote_get_set() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("fs.db");
+        db::init_db(&db_path, &FS_DB_MIGRATOR).await?;
+
+        let remote = FsRemote::new(&db_path, temp_dir.path().join("mnt")).await?;
+        assert_eq!(remote.get().await?, None);
+
+        let config = RemoteConfig::builder().url("s3://images/base").build();
+        remote.set(&config).await?;
+        assert_eq!(remote.get().await?, Some(config));
+
+        let config = RemoteConfig::builder()
+            .url("file:///mnt/share")
+            .cache_max_bytes(1024)
+            .build();
+        remote.set(&config).await?;
+        assert_eq!(remote.get().await?, Some(config));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use getset::Getters;
+use serde::Serialize;
+use sqlx::{sqlite::SqliteRow, Pool, Row, Sqlite};
+
+use crate::{
+    config::RemoteLocation,
+    management::{db, find},
+    store::{self, RemoteStore},
+    FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Tracks the replica stores of a filesystem in its fs database.
+///
+/// The replicas are read every time the filesystem's blocks are opened, by its server and by the
+/// management functions, so replicas that are added or removed apply the next time the server
+/// starts.
+#[derive(Debug, Clone)]
+pub struct FsReplicas {
+    /// The filesystem database.
+    fs_db: Pool<Sqlite>,
+
+    /// The mount directory identifying the filesystem.
+    mount_dir: PathBuf,
+}
+
+/// A store holding copies of the blocks of a filesystem, which corrupt and missing blocks are
+/// repaired from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct Replica {
+    /// The URL of the replica, like that of a remote store.
+    url: String,
+
+    /// When the replica was added.
+    created_at: DateTime<Utc>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FsReplicas {
+    /// Opens the replica records of the filesystem mounted at `mount_dir`.
+    pub async fn new(
+        fs_db_path: impl AsRef<Path>,
+        mount_dir: impl Into<PathBuf>,
+    ) -> FsResult<Self> {
+        Ok(Self {
+            fs_db: db::get_db_pool(fs_db_path.as_ref()).await?,
+            mount_dir: mount_dir.into(),
+        })
+    }
+
+    /// Returns the replicas of the filesystem, in the order they are tried.
+    pub async fn list(&self) -> FsResult<Vec<Replica>> {
+        let rows = sqlx::query(
+            "SELECT url, created_at FROM replicas WHERE mount_dir = ? ORDER BY created_at, id",
+        )
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .fetch_all(&self.fs_db)
+        .await?;
+
+        Ok(rows.iter().map(replica_from_row).collect())
+    }
+
+    /// Records the store at `location` as a replica of the filesystem, after the others. Adding
+    /// a replica that is already recorded has no effect.
+    pub async fn add(&self, location: &RemoteLocation) -> FsResult<Replica> {
+        let url = location.to_string();
+        sqlx::query("INSERT OR IGNORE INTO replicas (mount_dir, url) VALUES (?, ?)")
+            .bind(self.mount_dir.to_string_lossy().to_string())
+            .bind(&url)
+            .execute(&self.fs_db)
+            .await?;
+
+        let row =
+            sqlx::query("SELECT url, created_at FROM replicas WHERE mount_dir = ? AND url = ?")
+                .bind(self.mount_dir.to_string_lossy().to_string())
+                .bind(&url)
+                .fetch_one(&self.fs_db)
+                .await?;
+
+        Ok(replica_from_row(&row))
+    }
+
+    /// Forgets the replica at `location`. Returns whether it was recorded.
+    pub async fn remove(&self, location: &RemoteLocation) -> FsResult<bool> {
+        let result = sqlx::query("DELETE FROM replicas WHERE mount_dir = ? AND url = ?")
+            .bind(self.mount_dir.to_string_lossy().to_string())
+            .bind(location.to_string())
+            .execute(&self.fs_db)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Opens the replicas of the filesystem, in the order they are tried.
+    pub async fn open(&self) -> FsResult<Vec<Arc<dyn RemoteStore>>> {
+        self.list()
+            .await?
+            .iter()
+            .map(|replica| store::open_remote_store(&replica.url.parse()?))
+            .collect()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Add a replica store that corrupt and missing blocks of a monofs filesystem are repaired from
+///
+/// Once a filesystem has replicas, the data of every block read is checked against its CID. A
+/// block whose data does not match, or that is missing locally and from the remote store, is
+/// fetched from the first replica holding a good copy and written back in place, and so is every
+/// corrupt block the background scrubber finds. Replicas are tried in the order they were added.
+///
+/// A replica is given as a URL, like a remote store, and holds blocks the way a remote store
+/// does. The remote store of another filesystem with the same blocks makes a good replica. The
+/// server picks up new replicas when it restarts.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `url` - The URL of the replica, `file:///path` or `s3://bucket/prefix`
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// management::add_replica(Some("mfstest".into()), "file:///mnt/backup/mfstest").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn add_replica(mount_dir: Option<PathBuf>, url: &str) -> FsResult<Replica> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let location = url.parse::<RemoteLocation>()?;
+    let replica = FsReplicas::new(paths.fs_db_path(), paths.get_mount_dir())
+        .await?
+        .add(&location)
+        .await?;
+    tracing::info!("added replica {}", location);
+
+    Ok(replica)
+}
+
+/// Remove a replica store of a monofs filesystem, so its blocks are no longer repaired from it
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `url` - The URL of the replica
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// management::remove_replica(Some("mfstest".into()), "file:///mnt/backup/mfstest").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn remove_replica(mount_dir: Option<PathBuf>, url: &str) -> FsResult<()> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let location = url.parse::<RemoteLocation>()?;
+    let removed = FsReplicas::new(paths.fs_db_path(), paths.get_mount_dir())
+        .await?
+        .remove(&location)
+        .await?;
+    if !removed {
+        return Err(FsError::ReplicaNotFound(location.to_string()));
+    }
+
+    tracing::info!("removed replica {}", location);
+    Ok(())
+}
+
+/// List the replica stores of a monofs filesystem, in the order they are tried
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// for replica in management::list_replicas(None).await? {
+///     println!("{}", replica.get_url());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn list_replicas(mount_dir: Option<PathBuf>) -> FsResult<Vec<Replica>> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    FsReplicas::new(paths.fs_db_path(), paths.get_mount_dir())
+        .await?
+        .list()
+        .await
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Builds a [`Replica`] from a row of the `replicas` table.
+fn replica_from_row(row: &SqliteRow) -> Replica {
+    Replica {
+        url: row.get("url"),
+        created_at: row.get("created_at"),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::management::FS_DB_MIGRATOR;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fs_replicas() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("fs.db");
+        db::init_db(&db_path, &FS_DB_MIGRATOR).await?;
+
+        let replicas = FsReplicas::new(&db_path, temp_dir.path().join("mnt")).await?;
+        assert!(replicas.list().await?.is_empty());
+
+        // Replicas are kept in the order they were added, once each
+        let first = "file:///mnt/backup".parse::<RemoteLocation>()?;
+        let second = "s3://images/backup/".parse::<RemoteLocation>()?;
+        replicas.add(&first).await?;
+        replicas.add(&second).await?;
+        replicas.add(&first).await?;
+        let urls = replicas
+            .list()
+            .await?
+            .iter()
+            .map(|replica| replica.get_url().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(urls, ["file:///mnt/backup", "s3://images/backup"]);
+
+        assert!(replicas.remove(&second).await?);
+        assert!(!replicas.remove(&second).await?);
+        assert_eq!(replicas.list().await?.len(), 1);
+        assert_eq!(replicas.open().await?.len(), 1);
+
+        Ok(())
+    }
+}
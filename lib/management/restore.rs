@@ -0,0 +1,551 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use ipldstore::{
+    ipld::{cid::Cid, codec::Links},
+    Codec,
+};
+use serde::{Deserialize, Serialize};
+use serde_ipld_dagcbor::codec::DagCborCodec;
+use tokio::{
+    fs::{self, File},
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, BufReader},
+};
+
+use crate::{
+    config::{InitOptions, UserConfig},
+    filesystem::Dir,
+    management::{
+        backup, db, find, mfs, FsHead, Job, JobKind, JobProgress, MfsMount,
+        BACKUP_MANIFEST_FILENAME, FS_DB_MIGRATOR,
+    },
+    store::{DagWalker, FlatFsStore},
+    utils::path::{BLOCKS_SUBDIR, FS_DB_FILENAME, MFS_DIR_SUFFIX},
+    FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The version of the CAR format that can be restored from.
+const CAR_VERSION: u64 = 1;
+
+/// The largest section a CAR file may have.
+const MAX_CAR_SECTION_SIZE: u64 = 64 * 1024 * 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The header a CAR file starts with.
+#[derive(Debug, Serialize, Deserialize)]
+struct CarHeader {
+    /// The version of the format.
+    version: u64,
+
+    /// The roots of the DAG in the file.
+    roots: Vec<Cid>,
+}
+
+/// A block found in a CAR file.
+#[derive(Debug)]
+struct CarBlock {
+    /// Where the data of the block starts in the file.
+    offset: u64,
+
+    /// The size of the data of the block.
+    len: usize,
+
+    /// The CIDs the block links to.
+    links: Vec<Cid>,
+}
+
+/// The part of the manifest of a backup that a restore reads.
+#[derive(Debug, Deserialize)]
+struct BackupSource {
+    /// The mount directory of the filesystem backed up.
+    mount_dir: PathBuf,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Restore a monofs filesystem from a backup or a CAR file into a new mount, and mount it
+///
+/// A backup is a directory written by [`backup_mfs`](crate::management::backup_mfs). The new
+/// filesystem gets its database, with the head, history, snapshots, branches and pins of the
+/// backed up filesystem moved to the new mount directory, and the blocks of all their roots. It
+/// is mounted with the same backend and settings, except for the mirrors, which stay with the
+/// filesystem that was backed up, and the mounts that filesystem shared.
+///
+/// A CAR file (version 1) is restored into a filesystem set up with the defaults, like
+/// [`init_mfs`](crate::management::init_mfs) would, whose root is the first root of the file.
+/// Every block reachable from that root must be in the file, and the root must be a directory.
+/// Blocks of other roots are left out.
+///
+/// Blocks are encrypted with the encryption key in the environment, if any, which must also be
+/// the key the backup was taken with. A restore that fails leaves nothing behind. Once the data
+/// is in place, the filesystem is attached like with
+/// [`attach_mfs`](crate::management::attach_mfs), so a restore that fails to mount can be
+/// attached again later.
+///
+/// ## Arguments
+/// * `backup_path` - The backup directory or CAR file to restore from
+/// * `mount_dir` - The path where the filesystem will be mounted. It must be empty. If None, uses current directory
+///
+/// ## Returns
+/// Where the filesystem was mounted, along with the port it is served on and the PID of its
+/// supervisor
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// management::restore_mfs("/mnt/backup/mfstest", Some("mfstest-restored".into())).await?;
+/// management::restore_mfs("rootfs.car", Some("rootfs".into())).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn restore_mfs(
+    backup_path: impl AsRef<Path>,
+    mount_dir: Option<PathBuf>,
+) -> FsResult<MfsMount> {
+    let backup_path = backup_path.as_ref();
+    let from_backup = fs::metadata(backup_path).await?.is_dir();
+
+    // Set up an empty mount directory without any data
+    let mount_dir = mount_dir.unwrap_or_else(|| PathBuf::from("."));
+    let created_mount_dir = !fs::try_exists(&mount_dir).await?;
+    fs::create_dir_all(&mount_dir).await?;
+    let mount_dir = fs::canonicalize(&mount_dir).await?;
+    let mfs_data_dir = PathBuf::from(format!("{}.{}", mount_dir.display(), MFS_DIR_SUFFIX));
+    if fs::try_exists(&mfs_data_dir).await? {
+        return Err(FsError::PathExists(
+            mfs_data_dir.to_string_lossy().to_string(),
+        ));
+    }
+
+    let mut entries = fs::read_dir(&mount_dir).await?;
+    if entries.next_entry().await?.is_some() {
+        return Err(FsError::MountPointNotEmpty(
+            mount_dir.to_string_lossy().to_string(),
+        ));
+    }
+
+    fs::create_dir_all(mfs_data_dir.join(BLOCKS_SUBDIR)).await?;
+    let result = async {
+        if from_backup {
+            restore_backup_db(backup_path, &mount_dir, &mfs_data_dir).await?;
+        } else {
+            let options = InitOptions::default().with_defaults(&UserConfig::load()?);
+            mfs::prepare_mfs(&mount_dir, &mfs_data_dir, &options).await?;
+        }
+
+        let paths = find::find_mfs_paths(&mount_dir).await?;
+        let job = Job::start(paths.fs_db_path(), &mount_dir, JobKind::Restore, None).await?;
+        let result = async {
+            let store = paths.open_store().await?;
+            let head = FsHead::new(paths.fs_db_path(), &mount_dir).await?;
+            if from_backup {
+                let roots = backup::get_recorded_roots(&paths.fs_db_path()).await?;
+                let backup_store = backup::open_backup_store(backup_path)?;
+                backup::copy_blocks(&backup_store, &store, roots, job.get_progress()).await?;
+                head.get().await?.ok_or_else(|| {
+                    FsError::InvalidBackup(format!(
+                        "{} has no recorded state to restore",
+                        backup_path.display()
+                    ))
+                })
+            } else {
+                let root = restore_car_blocks(&store, backup_path, job.get_progress()).await?;
+                let operation = format!("restore {}", backup_path.display());
+                head.set(&root, &operation).await?;
+                Ok(root)
+            }
+        }
+        .await;
+        job.finish(result).await
+    }
+    .await;
+
+    // Leave nothing behind of a filesystem that could not be restored
+    let root = match result {
+        Ok(root) => root,
+        Err(e) => {
+            if let Err(e) = fs::remove_dir_all(&mfs_data_dir).await {
+                tracing::warn!("failed to remove {}: {}", mfs_data_dir.display(), e);
+            }
+
+            if created_mount_dir {
+                if let Err(e) = fs::remove_dir(&mount_dir).await {
+                    tracing::warn!("failed to remove {}: {}", mount_dir.display(), e);
+                }
+            }
+
+            return Err(e);
+        }
+    };
+
+    tracing::info!(
+        "restored {} from {} into {}",
+        root,
+        backup_path.display(),
+        mount_dir.display()
+    );
+    mfs::attach_mfs(Some(mount_dir)).await
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Copies the fs database of the backup in `backup_dir` into `mfs_data_dir`, for the filesystem
+/// to be mounted at `mount_dir`.
+async fn restore_backup_db(
+    backup_dir: &Path,
+    mount_dir: &Path,
+    mfs_data_dir: &Path,
+) -> FsResult<()> {
+    // The manifest is written last, so a backup without one is incomplete
+    let manifest_path = backup_dir.join(BACKUP_MANIFEST_FILENAME);
+    if !fs::try_exists(&manifest_path).await? {
+        return Err(FsError::InvalidBackup(format!(
+            "{} has no {}, the backup did not finish",
+            backup_dir.display(),
+            BACKUP_MANIFEST_FILENAME
+        )));
+    }
+
+    let source: BackupSource =
+        serde_json::from_slice(&fs::read(&manifest_path).await?).map_err(FsError::custom)?;
+    let fs_db_path = mfs_data_dir.join(FS_DB_FILENAME);
+    fs::copy(backup_dir.join(FS_DB_FILENAME), &fs_db_path).await?;
+    db::init_db(&fs_db_path, &FS_DB_MIGRATOR).await?;
+
+    move_db(&fs_db_path, &source.mount_dir, mount_dir).await
+}
+
+/// Moves the records of the filesystem mounted at `from` in the fs database at `fs_db_path` to
+/// the filesystem mounted at `to`, which is not running and owns its blocks.
+async fn move_db(fs_db_path: &Path, from: &Path, to: &Path) -> FsResult<()> {
+    let pool = db::get_db_pool(fs_db_path).await?;
+    let from = from.to_string_lossy().to_string();
+    let to = to.to_string_lossy().to_string();
+
+    // The mirrors, the mounts the filesystem served and the filesystems sharing its blocks stay
+    // with the filesystem the records came from
+    sqlx::query("DELETE FROM mirrors WHERE mount_dir = ?")
+        .bind(&from)
+        .execute(&pool)
+        .await?;
+    sqlx::query("DELETE FROM shared_mounts")
+        .execute(&pool)
+        .await?;
+    sqlx::query("DELETE FROM block_sharers")
+        .execute(&pool)
+        .await?;
+    sqlx::query(
+        r#"
+        UPDATE filesystems
+        SET supervisor_pid = NULL, nfsserver_pid = NULL, port = NULL
+        WHERE mount_dir = ?
+        "#,
+    )
+    .bind(&from)
+    .execute(&pool)
+    .await?;
+
+    // Every record of the filesystem is keyed by its mount directory, or by its id
+    let tables = sqlx::query_scalar::<_, String>(
+        r#"
+        SELECT t.name
+        FROM sqlite_master t
+        JOIN pragma_table_info(t.name) c
+        WHERE t.type = 'table' AND c.name = 'mount_dir'
+        "#,
+    )
+    .fetch_all(&pool)
+    .await?;
+    for table in tables {
+        sqlx::query(&format!(
+            "UPDATE \"{}\" SET mount_dir = ? WHERE mount_dir = ?",
+            table
+        ))
+        .bind(&to)
+        .bind(&from)
+        .execute(&pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Stores the blocks reachable from the first root of the CAR file at `car_path` in `store`,
+/// each after the blocks it links to, reporting the blocks stored to `progress`.
+///
+/// Returns the root.
+async fn restore_car_blocks(
+    store: &FlatFsStore,
+    car_path: &Path,
+    progress: &JobProgress,
+) -> FsResult<Cid> {
+    let (root, blocks) = index_car(car_path).await?;
+    let order = get_car_postorder(&root, &blocks);
+    progress.set_total(order.len() as u64);
+
+    let mut file = File::open(car_path).await?;
+    for cid in &order {
+        let block = &blocks[cid];
+        file.seek(SeekFrom::Start(block.offset)).await?;
+        let mut data = vec![0; block.len];
+        file.read_exact(&mut data).await?;
+        store.put_block_data(cid, &data).await?;
+        progress.add_done(1);
+    }
+
+    // Everything below the root must have been in the file
+    let dag = DagWalker::new(store).with_raw_checks().walk([root]).await?;
+    if !dag.get_missing().is_empty() {
+        return Err(FsError::InvalidBackup(format!(
+            "{} is missing {} blocks reachable from {}",
+            car_path.display(),
+            dag.get_missing().len(),
+            root
+        )));
+    }
+
+    Dir::load(&root, store.clone()).await?;
+    Ok(root)
+}
+
+/// Reads the CAR file at `car_path`, returning its first root and where each of its blocks is.
+async fn index_car(car_path: &Path) -> FsResult<(Cid, HashMap<Cid, CarBlock>)> {
+    let invalid = |reason: String| {
+        FsError::InvalidBackup(format!(
+            "{} is not a CAR file: {}",
+            car_path.display(),
+            reason
+        ))
+    };
+
+    let mut reader = BufReader::new(File::open(car_path).await?);
+    let (header_len, mut offset) = read_varint(&mut reader)
+        .await?
+        .ok_or_else(|| invalid("file is empty".to_string()))?;
+    if header_len > MAX_CAR_SECTION_SIZE {
+        return Err(invalid(format!("header is {} bytes", header_len)));
+    }
+
+    let mut header = vec![0; header_len as usize];
+    reader.read_exact(&mut header).await?;
+    offset += header_len;
+    let header: CarHeader = serde_ipld_dagcbor::from_slice(&header)
+        .map_err(|e| invalid(format!("invalid header: {}", e)))?;
+    if header.version != CAR_VERSION {
+        return Err(invalid(format!("unsupported version {}", header.version)));
+    }
+
+    let root = *header
+        .roots
+        .first()
+        .ok_or_else(|| invalid("it has no roots".to_string()))?;
+
+    let mut blocks = HashMap::new();
+    while let Some((len, varint_len)) = read_varint(&mut reader).await? {
+        offset += varint_len;
+        if len > MAX_CAR_SECTION_SIZE {
+            return Err(invalid(format!("section at {} is {} bytes", offset, len)));
+        }
+
+        let mut section = vec![0; len as usize];
+        reader.read_exact(&mut section).await?;
+        let mut cursor = io::Cursor::new(section.as_slice());
+        let cid = Cid::read_bytes(&mut cursor)
+            .map_err(|e| invalid(format!("invalid CID at {}: {}", offset, e)))?;
+        let cid_len = cursor.position();
+        let data = &section[cid_len as usize..];
+
+        let codec: Codec = cid.codec().try_into()?;
+        let links = match codec {
+            Codec::DagCbor => DagCborCodec::links(data)
+                .map_err(|e| invalid(format!("invalid block {}: {}", cid, e)))?
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        blocks.insert(
+            cid,
+            CarBlock {
+                offset: offset + cid_len,
+                len: data.len(),
+                links,
+            },
+        );
+        offset += len;
+    }
+
+    Ok((root, blocks))
+}
+
+/// Returns the blocks of `blocks` reachable from `root`, each after the blocks it links to.
+fn get_car_postorder(root: &Cid, blocks: &HashMap<Cid, CarBlock>) -> Vec<Cid> {
+    let mut order = Vec::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![(*root, false)];
+    while let Some((cid, expanded)) = pending.pop() {
+        if expanded {
+            order.push(cid);
+            continue;
+        }
+
+        let Some(block) = blocks.get(&cid) else {
+            continue;
+        };
+        if !visited.insert(cid) {
+            continue;
+        }
+
+        // The block comes back off the stack once the blocks it links to are done
+        pending.push((cid, true));
+        pending.extend(
+            block
+                .links
+                .iter()
+                .filter(|link| !visited.contains(*link))
+                .map(|link| (*link, false)),
+        );
+    }
+
+    order
+}
+
+/// Reads an unsigned LEB128 varint, returning it along with its size, or `None` at the end of
+/// the stream.
+async fn read_varint(reader: &mut (impl AsyncRead + Unpin)) -> FsResult<Option<(u64, u64)>> {
+    let mut value = 0u64;
+    for i in 0..10 {
+        let byte = match reader.read_u8().await {
+            Ok(byte) => byte,
+            Err(e) if i == 0 && e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(Some((value, i + 1)));
+        }
+    }
+
+    Err(FsError::InvalidBackup("varint is too long".to_string()))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use crate::management::FsMirrors;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_restore_car_blocks() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let source = FlatFsStore::new(temp.path().join("source"));
+        let mut dir = Dir::new(source.clone());
+        dir.find_or_create("etc/hosts", true).await?;
+        dir.find_or_create("usr/bin", false).await?;
+        let root = dir.checkpoint().await?;
+
+        // Write the blocks parents first, the way CAR files usually have them
+        let dag = DagWalker::new(&source).walk([root]).await?;
+        let mut car = Vec::new();
+        let header = serde_ipld_dagcbor::to_vec(&CarHeader {
+            version: CAR_VERSION,
+            roots: vec![root],
+        })?;
+        write_varint(&mut car, header.len() as u64);
+        car.extend_from_slice(&header);
+        let mut sections = Vec::new();
+        for cid in dag.get_preorder([root]) {
+            let mut section = cid.to_bytes();
+            section.extend_from_slice(&source.get_block_data(&cid).await?);
+            sections.push(section);
+        }
+        let mut partial_len = 0;
+        for section in &sections {
+            partial_len = car.len();
+            write_varint(&mut car, section.len() as u64);
+            car.extend_from_slice(section);
+        }
+        let car_path = temp.path().join("root.car");
+        fs::write(&car_path, &car).await?;
+
+        let store = FlatFsStore::new(temp.path().join("blocks"));
+        let progress = JobProgress::default();
+        assert_eq!(
+            restore_car_blocks(&store, &car_path, &progress).await?,
+            root
+        );
+        assert_eq!(progress.get_done(), dag.len() as u64);
+        let restored = Dir::load(&root, store).await?;
+        assert!(restored.find("etc/hosts").await?.is_some());
+
+        // A file missing blocks below its root is refused
+        fs::write(&car_path, &car[..partial_len]).await?;
+        let store = FlatFsStore::new(temp.path().join("partial"));
+        let result = restore_car_blocks(&store, &car_path, &progress).await;
+        assert!(matches!(result, Err(FsError::InvalidBackup(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_move_db() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let db_path = temp.path().join("fs.db");
+        db::init_db(&db_path, &FS_DB_MIGRATOR).await?;
+
+        let (from, to) = (temp.path().join("old"), temp.path().join("new"));
+        let root = Dir::new(FlatFsStore::new(temp.path().join("blocks")))
+            .checkpoint()
+            .await?;
+        FsHead::new(&db_path, &from)
+            .await?
+            .set(&root, "checkpoint")
+            .await?;
+        FsMirrors::new(&db_path, &from)
+            .await?
+            .add(&temp.path().join("mirror"))
+            .await?;
+
+        // The head moves along, the mirrors stay behind
+        move_db(&db_path, &from, &to).await?;
+        assert_eq!(FsHead::new(&db_path, &to).await?.get().await?, Some(root));
+        assert_eq!(FsHead::new(&db_path, &from).await?.get().await?, None);
+        assert!(FsMirrors::new(&db_path, &to)
+            .await?
+            .list()
+            .await?
+            .is_empty());
+
+        Ok(())
+    }
+
+    fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        buf.push(value as u8);
+    }
+}
@@ -0,0 +1,184 @@
+use std::path::PathBuf;
+
+use getset::Getters;
+use serde::Serialize;
+
+use crate::{
+    config::SnapshotRetention,
+    management::{db, find, gc, snapshot, GcReport, MfsPaths, Snapshot},
+    FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The outcome of pruning the snapshots of a filesystem.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct SnapshotPruneReport {
+    /// The snapshots that were deleted, newest first.
+    pruned: Vec<Snapshot>,
+
+    /// The blocks that were removed because no root refers to them anymore.
+    reclaimed: GcReport,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Delete the snapshots of a monofs filesystem that a retention policy does not keep, and the
+/// blocks only they referred to
+///
+/// Deleting a snapshot on its own frees no space, so the blocks only the deleted snapshots
+/// referred to are collected right away, like
+/// [`gc_mfs_incremental`](crate::management::gc_mfs_incremental) does. Blocks still reachable
+/// from the current root of the filesystem, or from any snapshot, branch or pin of the
+/// filesystems sharing its blocks, are kept. Blocks other roots released since the last
+/// collection are removed along the way.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `policy` - Which snapshots to keep
+///
+/// ## Returns
+/// The deleted snapshots and how much space was reclaimed
+///
+/// ## Example
+/// ```no_run
+/// use monofs::{config::SnapshotRetention, management};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let policy = SnapshotRetention::builder()
+///     .keep_last(Some(10))
+///     .keep_daily(Some(7))
+///     .build();
+/// let report = management::prune_snapshots(Some("mfstest".into()), policy).await?;
+/// println!(
+///     "pruned {} snapshots, freed {} bytes",
+///     report.get_pruned().len(),
+///     report.get_reclaimed().get_freed_bytes()
+/// );
+/// # Ok(())
+/// # }
+/// ```
+pub async fn prune_snapshots(
+    mount_dir: Option<PathBuf>,
+    policy: SnapshotRetention,
+) -> FsResult<SnapshotPruneReport> {
+    policy.validate()?;
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    apply_retention(&paths, &policy).await
+}
+
+/// Deletes the snapshots of the filesystem at `paths` that `policy` does not keep, and then the
+/// blocks only they referred to.
+pub(crate) async fn apply_retention(
+    paths: &MfsPaths,
+    policy: &SnapshotRetention,
+) -> FsResult<SnapshotPruneReport> {
+    let pool = db::get_db_pool(paths.fs_db_path()).await?;
+    let mount_dir = paths.get_mount_dir();
+
+    // The snapshots about to be deleted have to be counted before they can be released
+    let counted = gc::collect_released(paths).await?;
+
+    let mut snapshots = snapshot::list_snapshots(Some(mount_dir.clone())).await?;
+    snapshots.reverse();
+    snapshots.retain(|snapshot| !policy.get_scheduled_only() || *snapshot.get_scheduled());
+
+    let taken_at = snapshots
+        .iter()
+        .map(|snapshot| *snapshot.get_created_at())
+        .collect::<Vec<_>>();
+    let mut pruned = Vec::new();
+    for (snapshot, retained) in snapshots.into_iter().zip(policy.select(&taken_at)) {
+        if !retained {
+            snapshot::delete_snapshot(&pool, mount_dir, snapshot.get_name()).await?;
+            pruned.push(snapshot);
+        }
+    }
+
+    let reclaimed = counted.followed_by(gc::collect_released(paths).await?);
+    tracing::info!(
+        "pruned {} snapshots, freeing {} bytes",
+        pruned.len(),
+        reclaimed.get_freed_bytes()
+    );
+
+    Ok(SnapshotPruneReport { pruned, reclaimed })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::{IpldStore, Storable};
+    use tempfile::TempDir;
+    use tokio::fs;
+
+    use crate::{
+        filesystem::{Dir, File},
+        management::{self, FsHead},
+        store::FlatFsStore,
+        FsError,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_prune_snapshots_reclaims_blocks() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let mount_dir = fs::canonicalize(temp.path()).await?.join("mnt");
+        let paths = find::helper::setup_test_mfs(&mount_dir).await?;
+
+        // Snapshot three versions of a file
+        let store = FlatFsStore::new(paths.blocks_dir());
+        let head = FsHead::new(paths.fs_db_path(), &mount_dir).await?;
+        let mut root = Dir::new(store.clone());
+        let mut roots = Vec::new();
+        for (name, content) in [("v1", "first"), ("v2", "second"), ("v3", "third")] {
+            let file = File::with_content(store.clone(), content.as_bytes()).await?;
+            root.put_adapted_file("file.txt", file).await?;
+            let cid = root.checkpoint().await?;
+            head.set(&cid, "checkpoint").await?;
+            management::snapshot_mfs(Some(mount_dir.clone()), name).await?;
+            roots.push(cid);
+        }
+
+        // A policy has to keep something
+        let result = prune_snapshots(Some(mount_dir.clone()), SnapshotRetention::default()).await;
+        assert!(matches!(result, Err(FsError::InvalidSnapshotRetention(_))));
+
+        // Only the snapshot no longer kept is deleted, along with its blocks
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        let policy = SnapshotRetention::builder().keep_last(Some(2)).build();
+        let report = prune_snapshots(Some(mount_dir.clone()), policy).await?;
+        let pruned = report
+            .get_pruned()
+            .iter()
+            .map(|snapshot| snapshot.get_name().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(pruned, vec!["v1"]);
+        assert!(*report.get_reclaimed().get_removed_blocks() >= 1);
+        assert!(*report.get_reclaimed().get_freed_bytes() > 0);
+        assert!(!store.has(&roots[0]).await);
+        assert!(Dir::load(&roots[1], store.clone()).await.is_ok());
+        assert!(Dir::load(&roots[2], store.clone()).await.is_ok());
+
+        // Snapshots taken by hand are left alone when only scheduled ones are pruned
+        let policy = SnapshotRetention::builder()
+            .keep_last(Some(1))
+            .scheduled_only(true)
+            .build();
+        let report = prune_snapshots(Some(mount_dir.clone()), policy).await?;
+        assert!(report.get_pruned().is_empty());
+        assert_eq!(*report.get_reclaimed().get_removed_blocks(), 0);
+        assert_eq!(management::list_snapshots(Some(mount_dir)).await?.len(), 2);
+
+        Ok(())
+    }
+}
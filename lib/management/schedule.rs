@@ -0,0 +1,366 @@
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use sqlx::{Pool, Row, Sqlite};
+
+use crate::{
+    config::SnapshotScheduleConfig,
+    management::{db, find, head, retention, snapshot, MfsPaths, Snapshot},
+    FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The prefix of the names of scheduled snapshots, which are followed by the time they were taken.
+pub const SCHEDULED_SNAPSHOT_PREFIX: &str = "auto-";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The snapshot schedule of a filesystem, in its fs database.
+///
+/// The supervisor of the filesystem reads the schedule as it runs, so a schedule that is set or
+/// cleared applies without restarting it.
+#[derive(Debug, Clone)]
+pub struct FsSnapshotSchedule {
+    /// The filesystem database.
+    fs_db: Pool<Sqlite>,
+
+    /// The mount directory identifying the filesystem.
+    mount_dir: PathBuf,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FsSnapshotSchedule {
+    /// Opens the snapshot schedule of the filesystem mounted at `mount_dir`.
+    pub async fn new(
+        fs_db_path: impl AsRef<Path>,
+        mount_dir: impl Into<PathBuf>,
+    ) -> FsResult<Self> {
+        Ok(Self {
+            fs_db: db::get_db_pool(fs_db_path.as_ref()).await?,
+            mount_dir: mount_dir.into(),
+        })
+    }
+
+    /// Returns the schedule of the filesystem, or `None` if it takes no scheduled snapshots.
+    pub async fn get(&self) -> FsResult<Option<SnapshotScheduleConfig>> {
+        let record = sqlx::query(
+            r#"
+            SELECT interval_secs, every_changes, keep_last, keep_daily, keep_weekly
+            FROM snapshot_schedules
+            WHERE mount_dir = ?
+            "#,
+        )
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .fetch_optional(&self.fs_db)
+        .await?;
+
+        Ok(record.map(|row| {
+            SnapshotScheduleConfig::builder()
+                .interval_secs(
+                    row.get::<Option<i64>, _>("interval_secs")
+                        .map(|secs| secs as u64),
+                )
+                .every_changes(
+                    row.get::<Option<i64>, _>("every_changes")
+                        .map(|changes| changes as u64),
+                )
+                .keep_last(row.get("keep_last"))
+                .keep_daily(row.get("keep_daily"))
+                .keep_weekly(row.get("keep_weekly"))
+                .build()
+        }))
+    }
+
+    /// Records `schedule` as the schedule of the filesystem.
+    pub async fn set(&self, schedule: &SnapshotScheduleConfig) -> FsResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO snapshot_schedules
+                (mount_dir, interval_secs, every_changes, keep_last, keep_daily, keep_weekly)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT (mount_dir) DO UPDATE
+            SET interval_secs = excluded.interval_secs,
+                every_changes = excluded.every_changes,
+                keep_last = excluded.keep_last,
+                keep_daily = excluded.keep_daily,
+                keep_weekly = excluded.keep_weekly,
+                modified_at = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .bind(schedule.get_interval_secs().map(|secs| secs as i64))
+        .bind(schedule.get_every_changes().map(|changes| changes as i64))
+        .bind(schedule.get_keep_last())
+        .bind(schedule.get_keep_daily())
+        .bind(schedule.get_keep_weekly())
+        .execute(&self.fs_db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stops the filesystem from taking scheduled snapshots. The ones already taken are kept.
+    pub async fn clear(&self) -> FsResult<()> {
+        sqlx::query("DELETE FROM snapshot_schedules WHERE mount_dir = ?")
+            .bind(self.mount_dir.to_string_lossy().to_string())
+            .execute(&self.fs_db)
+            .await?;
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Set when the supervisor of a monofs filesystem takes snapshots on its own, and how many of
+/// them it keeps
+///
+/// The schedule is recorded in the filesystem's database, and the supervisor picks it up within
+/// a few seconds, including after the filesystem is attached again. Scheduled snapshots are named
+/// after the time they were taken, prefixed with [`SCHEDULED_SNAPSHOT_PREFIX`]. See
+/// [`SnapshotScheduleConfig`].
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `schedule` - The schedule to follow, or `None` to stop taking scheduled snapshots
+///
+/// ## Example
+/// ```no_run
+/// use monofs::{config::SnapshotScheduleConfig, management};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let schedule = SnapshotScheduleConfig::builder()
+///     .interval_secs(Some(3600))
+///     .keep_daily(Some(7))
+///     .build();
+/// management::set_snapshot_schedule(Some("mfstest".into()), Some(schedule)).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn set_snapshot_schedule(
+    mount_dir: Option<PathBuf>,
+    schedule: Option<SnapshotScheduleConfig>,
+) -> FsResult<()> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let record = FsSnapshotSchedule::new(paths.fs_db_path(), paths.get_mount_dir()).await?;
+    match schedule {
+        Some(schedule) => {
+            schedule.validate()?;
+            record.set(&schedule).await?;
+            tracing::info!("scheduled snapshots {:?}", schedule);
+        }
+        None => {
+            record.clear().await?;
+            tracing::info!("cleared snapshot schedule");
+        }
+    }
+
+    Ok(())
+}
+
+/// Get when the supervisor of a monofs filesystem takes snapshots on its own, if it does
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// if let Some(schedule) = management::get_snapshot_schedule(None).await? {
+///     println!("{:?}", schedule);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn get_snapshot_schedule(
+    mount_dir: Option<PathBuf>,
+) -> FsResult<Option<SnapshotScheduleConfig>> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    FsSnapshotSchedule::new(paths.fs_db_path(), paths.get_mount_dir())
+        .await?
+        .get()
+        .await
+}
+
+/// Takes a scheduled snapshot of the filesystem at `paths`, and deletes the scheduled snapshots
+/// `schedule` no longer keeps, along with the blocks only they referred to.
+///
+/// Returns the snapshot, or `None` if the filesystem has no root yet or its root is the same as
+/// in the last scheduled snapshot.
+pub(crate) async fn take_scheduled_snapshot(
+    paths: &MfsPaths,
+    schedule: &SnapshotScheduleConfig,
+) -> FsResult<Option<Snapshot>> {
+    let pool = db::get_db_pool(paths.fs_db_path()).await?;
+    let mount_dir = paths.get_mount_dir();
+
+    let Some(root) = head::checkpoint_head(paths).await? else {
+        return Ok(None);
+    };
+
+    let scheduled = list_scheduled_snapshots(&pool, mount_dir).await?;
+    if scheduled.first().map(Snapshot::get_root) == Some(&root) {
+        return Ok(None);
+    }
+
+    let name = format!(
+        "{}{}",
+        SCHEDULED_SNAPSHOT_PREFIX,
+        Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+    );
+    let snapshot = snapshot::record_snapshot(&pool, mount_dir, &name, &root, true).await?;
+
+    if let Some(retention) = schedule.get_retention() {
+        retention::apply_retention(paths, &retention).await?;
+    }
+
+    Ok(Some(snapshot))
+}
+
+/// Returns the last scheduled snapshot of the filesystem at `paths`, if any.
+pub(crate) async fn get_last_scheduled_snapshot(paths: &MfsPaths) -> FsResult<Option<Snapshot>> {
+    let pool = db::get_db_pool(paths.fs_db_path()).await?;
+    let scheduled = list_scheduled_snapshots(&pool, paths.get_mount_dir()).await?;
+    Ok(scheduled.into_iter().next())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Lists the scheduled snapshots of the filesystem mounted at `mount_dir`, newest first.
+async fn list_scheduled_snapshots(
+    pool: &Pool<Sqlite>,
+    mount_dir: &Path,
+) -> FsResult<Vec<Snapshot>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT s.name, s.root_cid, s.created_at, s.scheduled
+        FROM snapshots s
+        JOIN filesystems f ON s.fs_id = f.id
+        WHERE f.mount_dir = ? AND s.scheduled
+        ORDER BY s.created_at DESC, s.id DESC
+        "#,
+    )
+    .bind(mount_dir.to_string_lossy().to_string())
+    .fetch_all(pool)
+    .await?;
+
+    rows.iter().map(snapshot::snapshot_from_row).collect()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use ipldstore::{IpldStore, MemoryStore};
+    use tempfile::TempDir;
+    use tokio::fs;
+
+    use crate::{
+        management::{self, FsHead},
+        FsError,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_snapshot_schedule() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let mount_dir = fs::canonicalize(temp.path()).await?.join("mnt");
+        let paths = find::helper::setup_test_mfs(&mount_dir).await?;
+
+        // Schedules are recorded, replaced and cleared
+        assert_eq!(get_snapshot_schedule(Some(mount_dir.clone())).await?, None);
+        let invalid = SnapshotScheduleConfig::builder().keep_last(Some(1)).build();
+        let result = set_snapshot_schedule(Some(mount_dir.clone()), Some(invalid)).await;
+        assert!(matches!(result, Err(FsError::InvalidSnapshotSchedule(_))));
+
+        let schedule = SnapshotScheduleConfig::builder()
+            .every_changes(Some(10))
+            .keep_last(Some(1))
+            .build();
+        set_snapshot_schedule(Some(mount_dir.clone()), Some(schedule)).await?;
+        assert_eq!(
+            get_snapshot_schedule(Some(mount_dir.clone())).await?,
+            Some(schedule)
+        );
+
+        // Nothing to snapshot before the filesystem has a head
+        assert_eq!(take_scheduled_snapshot(&paths, &schedule).await?, None);
+
+        let store = MemoryStore::default();
+        let first = store.put_bytes(b"first".as_slice()).await?;
+        let head = FsHead::new(paths.fs_db_path(), &mount_dir).await?;
+        head.set(&first, "checkpoint").await?;
+        management::snapshot_mfs(Some(mount_dir.clone()), "manual").await?;
+
+        let snapshot = take_scheduled_snapshot(&paths, &schedule).await?.unwrap();
+        assert!(snapshot.get_name().starts_with(SCHEDULED_SNAPSHOT_PREFIX));
+        assert_eq!(snapshot.get_root(), &first);
+        assert!(*snapshot.get_scheduled());
+
+        // An unchanged root is not snapshotted again
+        assert_eq!(take_scheduled_snapshot(&paths, &schedule).await?, None);
+
+        // Only the newest scheduled snapshot is kept, along with the one taken by hand
+        let second = store.put_bytes(b"second".as_slice()).await?;
+        head.set(&second, "checkpoint").await?;
+        let latest = take_scheduled_snapshot(&paths, &schedule).await?.unwrap();
+        assert_eq!(
+            get_last_scheduled_snapshot(&paths).await?,
+            Some(latest.clone())
+        );
+
+        let snapshots = management::list_snapshots(Some(mount_dir.clone())).await?;
+        let names = snapshots
+            .iter()
+            .map(|s| s.get_name().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["manual", latest.get_name().as_str()]);
+
+        set_snapshot_schedule(Some(mount_dir.clone()), None).await?;
+        assert_eq!(get_snapshot_schedule(Some(mount_dir)).await?, None);
+
+        // The newest snapshot of each day and week is kept as far back as asked
+        let taken_at = [
+            Utc.with_ymd_and_hms(2025, 3, 12, 18, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2025, 3, 12, 6, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2025, 3, 11, 18, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2025, 3, 10, 18, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2025, 3, 5, 18, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2025, 2, 26, 18, 0, 0).unwrap(),
+        ];
+        let schedule = SnapshotScheduleConfig::builder()
+            .interval_secs(Some(3600))
+            .keep_daily(Some(2))
+            .keep_weekly(Some(2))
+            .build();
+        assert_eq!(
+            schedule.get_retention().unwrap().select(&taken_at),
+            vec![true, false, true, false, true, false]
+        );
+
+        let schedule = SnapshotScheduleConfig::builder()
+            .interval_secs(Some(3600))
+            .build();
+        assert_eq!(schedule.get_retention(), None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,516 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use getset::Getters;
+use serde::Serialize;
+use sqlx::{sqlite::SqliteRow, Pool, Row, Sqlite};
+use tokio::fs;
+
+use crate::{
+    config::ScrubConfig,
+    management::{db, find, MfsPaths},
+    store, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The scrub settings of a filesystem, how far its scrubber got and the blocks it quarantined, in
+/// its fs database.
+///
+/// The supervisor of the filesystem reads the settings as it runs, so scrubbing that is started,
+/// changed or stopped applies without restarting it.
+#[derive(Debug, Clone)]
+pub struct FsScrub {
+    /// The filesystem database.
+    fs_db: Pool<Sqlite>,
+
+    /// The mount directory identifying the filesystem.
+    mount_dir: PathBuf,
+}
+
+/// How a filesystem is scrubbed, how far the scrubber got and what it found.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct ScrubStatus {
+    /// The scrub settings, or `None` if the filesystem is not scrubbed.
+    config: Option<ScrubConfig>,
+
+    /// The number of block files checked since scrubbing started.
+    checked_blocks: u64,
+
+    /// The number of corrupt block files replaced with good copies from replicas since scrubbing
+    /// started.
+    repaired_blocks: u64,
+
+    /// The number of times every block file was checked.
+    passes: u64,
+
+    /// When the last batch of blocks was checked.
+    last_scrubbed_at: Option<DateTime<Utc>>,
+
+    /// The corrupt blocks moved out of the store, oldest first.
+    quarantined_blocks: Vec<QuarantinedBlock>,
+}
+
+/// A corrupt block the scrubber moved out of the store.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct QuarantinedBlock {
+    /// The hex digest of the CID the block was stored under, or the keyed hash of it an encrypted
+    /// block is named after.
+    digest: String,
+
+    /// The path the block file had in the store.
+    path: PathBuf,
+
+    /// The path the block file was moved to.
+    quarantine_path: PathBuf,
+
+    /// When the block was found to be corrupt.
+    created_at: DateTime<Utc>,
+}
+
+/// The outcome of scrubbing one batch of blocks.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct ScrubBatch {
+    /// The number of block files checked.
+    checked_blocks: u64,
+
+    /// The number of corrupt block files replaced with good copies from replicas.
+    repaired_blocks: u64,
+
+    /// The corrupt blocks moved out of the store.
+    quarantined_blocks: Vec<QuarantinedBlock>,
+
+    /// Whether the batch reached the last block file, so the next one starts over.
+    finished_pass: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FsScrub {
+    /// Opens the scrub record of the filesystem mounted at `mount_dir`.
+    pub async fn new(
+        fs_db_path: impl AsRef<Path>,
+        mount_dir: impl Into<PathBuf>,
+    ) -> FsResult<Self> {
+        Ok(Self {
+            fs_db: db::get_db_pool(fs_db_path.as_ref()).await?,
+            mount_dir: mount_dir.into(),
+        })
+    }
+
+    /// Returns the scrub settings of the filesystem, or `None` if it is not scrubbed.
+    pub async fn get(&self) -> FsResult<Option<ScrubConfig>> {
+        let record =
+            sqlx::query("SELECT interval_secs, batch_blocks FROM scrubs WHERE mount_dir = ?")
+                .bind(self.mount_dir.to_string_lossy().to_string())
+                .fetch_optional(&self.fs_db)
+                .await?;
+
+        Ok(record.map(|row| {
+            ScrubConfig::builder()
+                .interval_secs(row.get::<i64, _>("interval_secs") as u64)
+                .batch_blocks(row.get::<i64, _>("batch_blocks") as u64)
+                .build()
+        }))
+    }
+
+    /// Records `config` as the scrub settings of the filesystem, keeping how far the scrubber got.
+    pub async fn set(&self, config: &ScrubConfig) -> FsResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO scrubs (mount_dir, interval_secs, batch_blocks)
+            VALUES (?, ?, ?)
+            ON CONFLICT (mount_dir) DO UPDATE
+            SET interval_secs = excluded.interval_secs,
+                batch_blocks = excluded.batch_blocks,
+                modified_at = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .bind(*config.get_interval_secs() as i64)
+        .bind(*config.get_batch_blocks() as i64)
+        .execute(&self.fs_db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stops scrubbing the filesystem, forgetting how far the scrubber got. The blocks already
+    /// quarantined stay recorded.
+    pub async fn clear(&self) -> FsResult<()> {
+        sqlx::query("DELETE FROM scrubs WHERE mount_dir = ?")
+            .bind(self.mount_dir.to_string_lossy().to_string())
+            .execute(&self.fs_db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Returns how the filesystem is scrubbed, how far the scrubber got and what it found.
+    pub async fn get_status(&self) -> FsResult<ScrubStatus> {
+        let mount_dir = self.mount_dir.to_string_lossy().to_string();
+        let record = sqlx::query(
+            r#"
+            SELECT interval_secs, batch_blocks, checked_blocks, repaired_blocks, passes,
+                last_scrubbed_at
+            FROM scrubs
+            WHERE mount_dir = ?
+            "#,
+        )
+        .bind(&mount_dir)
+        .fetch_optional(&self.fs_db)
+        .await?;
+
+        let rows = sqlx::query(
+            r#"
+            SELECT digest, path, quarantine_path, created_at
+            FROM quarantined_blocks
+            WHERE mount_dir = ?
+            ORDER BY id
+            "#,
+        )
+        .bind(&mount_dir)
+        .fetch_all(&self.fs_db)
+        .await?;
+
+        let mut status = ScrubStatus {
+            quarantined_blocks: rows.iter().map(quarantined_block_from_row).collect(),
+            ..Default::default()
+        };
+        if let Some(row) = record {
+            status.config = Some(
+                ScrubConfig::builder()
+                    .interval_secs(row.get::<i64, _>("interval_secs") as u64)
+                    .batch_blocks(row.get::<i64, _>("batch_blocks") as u64)
+                    .build(),
+            );
+            status.checked_blocks = row.get::<i64, _>("checked_blocks") as u64;
+            status.repaired_blocks = row.get::<i64, _>("repaired_blocks") as u64;
+            status.passes = row.get::<i64, _>("passes") as u64;
+            status.last_scrubbed_at = row.get("last_scrubbed_at");
+        }
+
+        Ok(status)
+    }
+
+    /// Returns the file name of the last block file checked in the current pass, if any.
+    async fn get_cursor(&self) -> FsResult<Option<String>> {
+        let cursor: Option<Option<String>> =
+            sqlx::query_scalar("SELECT cursor FROM scrubs WHERE mount_dir = ?")
+                .bind(self.mount_dir.to_string_lossy().to_string())
+                .fetch_optional(&self.fs_db)
+                .await?;
+
+        Ok(cursor.flatten())
+    }
+
+    /// Records that `batch` was checked, up to the block file named `cursor`, or to the last one
+    /// if `cursor` is `None`.
+    async fn advance(&self, cursor: Option<&str>, batch: &ScrubBatch) -> FsResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE scrubs
+            SET cursor = ?,
+                checked_blocks = checked_blocks + ?,
+                repaired_blocks = repaired_blocks + ?,
+                passes = passes + ?,
+                last_scrubbed_at = CURRENT_TIMESTAMP,
+                modified_at = CURRENT_TIMESTAMP
+            WHERE mount_dir = ?
+            "#,
+        )
+        .bind(cursor)
+        .bind(batch.checked_blocks as i64)
+        .bind(batch.repaired_blocks as i64)
+        .bind(batch.finished_pass as i64)
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .execute(&self.fs_db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records that the corrupt block file at `path` was moved to `quarantine_path`.
+    async fn record_quarantined(
+        &self,
+        digest: &str,
+        path: &Path,
+        quarantine_path: &Path,
+    ) -> FsResult<QuarantinedBlock> {
+        let row = sqlx::query(
+            r#"
+            INSERT INTO quarantined_blocks (mount_dir, digest, path, quarantine_path)
+            VALUES (?, ?, ?, ?)
+            RETURNING digest, path, quarantine_path, created_at
+            "#,
+        )
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .bind(digest)
+        .bind(path.to_string_lossy().to_string())
+        .bind(quarantine_path.to_string_lossy().to_string())
+        .fetch_one(&self.fs_db)
+        .await?;
+
+        Ok(quarantined_block_from_row(&row))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Set how the supervisor of a monofs filesystem scrubs its blocks in the background
+///
+/// A scrubbed filesystem has its block files re-hashed a batch at a time, in a rolling pass over
+/// the store. Block files whose data does not match their CID are replaced with a good copy from
+/// the filesystem's replicas, if one holds it. Otherwise they are moved out of the store into the
+/// `quarantine` directory of the filesystem, recorded and logged as errors. A quarantined block
+/// reads as missing, so a filesystem with a remote store fetches it again from there.
+///
+/// The settings are recorded in the filesystem's database, and the supervisor picks them up
+/// within a few seconds. Blocks held by packfiles are not scrubbed; `monofs verify` checks those.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `config` - The scrub settings, or `None` to stop scrubbing
+///
+/// ## Example
+/// ```no_run
+/// use monofs::{config::ScrubConfig, management};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let config = ScrubConfig::builder().interval_secs(30).build();
+/// management::set_scrub_config(Some("mfstest".into()), Some(config)).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn set_scrub_config(
+    mount_dir: Option<PathBuf>,
+    config: Option<ScrubConfig>,
+) -> FsResult<()> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let record = FsScrub::new(paths.fs_db_path(), paths.get_mount_dir()).await?;
+    match config {
+        Some(config) => {
+            config.validate()?;
+            record.set(&config).await?;
+            tracing::info!("scrubbing blocks {:?}", config);
+        }
+        None => {
+            record.clear().await?;
+            tracing::info!("stopped scrubbing blocks");
+        }
+    }
+
+    Ok(())
+}
+
+/// Get how a monofs filesystem is scrubbed, how far its scrubber got and the corrupt blocks it
+/// quarantined
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let status = management::get_scrub_status(None).await?;
+/// for block in status.get_quarantined_blocks() {
+///     println!("{} -> {}", block.get_digest(), block.get_quarantine_path().display());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn get_scrub_status(mount_dir: Option<PathBuf>) -> FsResult<ScrubStatus> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    FsScrub::new(paths.fs_db_path(), paths.get_mount_dir())
+        .await?
+        .get_status()
+        .await
+}
+
+/// Checks the next batch of block files of the filesystem at `paths`, in the order of their
+/// names, after the last one checked, and quarantines the corrupt ones.
+///
+/// Block files removed while the batch runs, by a garbage collection for one, are skipped.
+pub(crate) async fn scrub_blocks(paths: &MfsPaths, config: &ScrubConfig) -> FsResult<ScrubBatch> {
+    let record = FsScrub::new(paths.fs_db_path(), paths.get_mount_dir()).await?;
+    let cursor = record.get_cursor().await?;
+    let batch_blocks = *config.get_batch_blocks() as usize;
+
+    let mut blocks = list_block_files(&paths.blocks_dir())
+        .await?
+        .into_iter()
+        .filter(|(name, _)| cursor.as_ref().is_none_or(|cursor| name > cursor))
+        .collect::<Vec<_>>();
+    blocks.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    blocks.truncate(batch_blocks);
+
+    let store = paths.open_store().await?;
+    let quarantine_dir = paths.quarantine_dir();
+    let mut batch = ScrubBatch {
+        finished_pass: blocks.len() < batch_blocks && (cursor.is_some() || !blocks.is_empty()),
+        ..Default::default()
+    };
+    for (name, path) in &blocks {
+        match store.verify_block_file(path).await {
+            Ok(true) => {}
+            Ok(false) if store.repair_block_file(path).await? => {
+                tracing::warn!(
+                    "block {} was corrupt, repaired it from a mirror or replica",
+                    path.display()
+                );
+                batch.repaired_blocks += 1;
+            }
+            Ok(false) => {
+                let quarantine_path = store.quarantine_block(path, &quarantine_dir).await?;
+                let digest = store::get_block_name(name).unwrap_or(name);
+                tracing::error!(
+                    "block {} is corrupt, moved it to {}",
+                    path.display(),
+                    quarantine_path.display()
+                );
+                batch.quarantined_blocks.push(
+                    record
+                        .record_quarantined(digest, path, &quarantine_path)
+                        .await?,
+                );
+            }
+            Err(e) => {
+                if fs::try_exists(path).await.unwrap_or(true) {
+                    return Err(e.into());
+                }
+                continue;
+            }
+        }
+        batch.checked_blocks += 1;
+    }
+
+    let cursor = match batch.finished_pass {
+        true => None,
+        false => blocks.last().map(|(name, _)| name.as_str()),
+    };
+    record.advance(cursor, &batch).await?;
+
+    Ok(batch)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Lists the names and paths of the block files below `dir`.
+async fn list_block_files(dir: &Path) -> FsResult<Vec<(String, PathBuf)>> {
+    let mut blocks = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                pending.push(entry.path());
+                continue;
+            }
+
+            let name = entry.file_name().to_string_lossy().to_string();
+            if file_type.is_file() && store::get_block_name(&name).is_some() {
+                blocks.push((name, entry.path()));
+            }
+        }
+    }
+
+    Ok(blocks)
+}
+
+/// Builds a [`QuarantinedBlock`] from a row of the `quarantined_blocks` table.
+fn quarantined_block_from_row(row: &SqliteRow) -> QuarantinedBlock {
+    QuarantinedBlock {
+        digest: row.get("digest"),
+        path: PathBuf::from(row.get::<String, _>("path")),
+        quarantine_path: PathBuf::from(row.get::<String, _>("quarantine_path")),
+        created_at: row.get("created_at"),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::IpldStore;
+    use tempfile::TempDir;
+
+    use crate::FsError;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scrub_blocks() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let mount_dir = fs::canonicalize(temp.path()).await?.join("mnt");
+        let paths = find::helper::setup_test_mfs(&mount_dir).await?;
+
+        // Settings are validated, recorded and cleared
+        let invalid = ScrubConfig::builder().batch_blocks(0).build();
+        let result = set_scrub_config(Some(mount_dir.clone()), Some(invalid)).await;
+        assert!(matches!(result, Err(FsError::InvalidScrubConfig(_))));
+
+        let config = ScrubConfig::builder().batch_blocks(2).build();
+        set_scrub_config(Some(mount_dir.clone()), Some(config)).await?;
+        let status = get_scrub_status(Some(mount_dir.clone())).await?;
+        assert_eq!(status.get_config(), &Some(config));
+
+        // One of three blocks is corrupt
+        let store = paths.open_store().await?;
+        let mut cids = Vec::new();
+        for data in [b"first".as_slice(), b"second", b"third"] {
+            cids.push(store.put_bytes(data).await?);
+        }
+        let corrupt = store.get_block_file(&cids[1]).unwrap();
+        fs::write(&corrupt, b"garbage").await?;
+
+        // The blocks are checked two at a time, and the pass starts over after the last one
+        let first = scrub_blocks(&paths, &config).await?;
+        let second = scrub_blocks(&paths, &config).await?;
+        assert_eq!(first.get_checked_blocks() + second.get_checked_blocks(), 3);
+        assert!(!first.get_finished_pass());
+        assert!(second.get_finished_pass());
+
+        let quarantined = [
+            first.get_quarantined_blocks().as_slice(),
+            second.get_quarantined_blocks().as_slice(),
+        ]
+        .concat();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].get_path(), &corrupt);
+        assert!(!fs::try_exists(&corrupt).await?);
+        assert!(fs::try_exists(quarantined[0].get_quarantine_path()).await?);
+
+        let third = scrub_blocks(&paths, &config).await?;
+        assert_eq!(*third.get_checked_blocks(), 2);
+        assert!(third.get_quarantined_blocks().is_empty());
+
+        let status = get_scrub_status(Some(mount_dir.clone())).await?;
+        assert_eq!(*status.get_checked_blocks(), 5);
+        assert_eq!(*status.get_passes(), 1);
+        assert!(status.get_last_scrubbed_at().is_some());
+        assert_eq!(status.get_quarantined_blocks(), &quarantined);
+
+        set_scrub_config(Some(mount_dir.clone()), None).await?;
+        let status = get_scrub_status(Some(mount_dir)).await?;
+        assert_eq!(status.get_config(), &None);
+        assert_eq!(status.get_quarantined_blocks().len(), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,670 @@
+use std::{
+    collections::HashSet,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+};
+
+use getset::Getters;
+use ipldstore::ipld::cid::Cid;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs,
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt},
+};
+
+use crate::{
+    filesystem::Dir,
+    management::{find, head, Job, JobKind, JobProgress, MfsPaths},
+    store::{self, DagWalker, FlatFsStore},
+    utils, FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The bytes every send stream starts with.
+const SEND_STREAM_MAGIC: &[u8; 8] = b"MFSSEND1";
+
+/// The largest block a send stream may carry.
+const MAX_SEND_BLOCK_SIZE: u32 = 64 * 1024 * 1024;
+
+/// How many blocks [`send_file`] writes between the checkpoints it resumes from.
+const SEND_CHECKPOINT_BLOCKS: u64 = 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// What a call to [`send`] or [`send_file`] wrote to its stream.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct SendReport {
+    /// The root the stream brings its receiver to.
+    #[serde(serialize_with = "utils::serialize_display")]
+    root: Cid,
+
+    /// The root whose blocks were left out of the stream, if any.
+    #[serde(serialize_with = "utils::serialize_optional_display")]
+    since: Option<Cid>,
+
+    /// The number of blocks written.
+    blocks: u64,
+
+    /// The size of the blocks written.
+    bytes: u64,
+}
+
+/// What a call to [`receive`] read from its stream.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct ReceiveReport {
+    /// The root the filesystem was moved to.
+    #[serde(serialize_with = "utils::serialize_display")]
+    root: Cid,
+
+    /// The number of blocks that were new to the filesystem.
+    received_blocks: u64,
+
+    /// The number of blocks the filesystem already had.
+    skipped_blocks: u64,
+
+    /// The size of the blocks read.
+    bytes: u64,
+}
+
+/// How far an interrupted [`send_file`] got, recorded in its job.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct SendCheckpoint {
+    /// The root being sent.
+    root: String,
+
+    /// The root whose blocks are left out, if any.
+    since: Option<String>,
+
+    /// The number of blocks written.
+    blocks: u64,
+
+    /// The size of the blocks written.
+    bytes: u64,
+
+    /// The length of the stream file once they were written.
+    offset: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Write the current root of a monofs filesystem and its blocks to a stream
+///
+/// The stream can be applied to another filesystem with [`receive`], similar to `zfs send`.
+/// When `since` is given, blocks reachable from it are left out, so the stream only carries what
+/// changed after that root. Such an incremental stream can only be received by a filesystem that
+/// already has every block of `since`, e.g. because it received it before.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `since` - A root the receiver already has, whose blocks are not sent
+/// * `writer` - Where to write the stream to
+///
+/// ## Returns
+/// What was written to the stream
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let file = tokio::fs::File::create("mfstest.send").await?;
+/// let report = management::send(Some("mfstest".into()), None, file).await?;
+/// println!("sent {} blocks", report.get_blocks());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn send(
+    mount_dir: Option<PathBuf>,
+    since: Option<Cid>,
+    writer: impl AsyncWrite + Unpin + Send,
+) -> FsResult<SendReport> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let job = Job::start(
+        paths.fs_db_path(),
+        paths.get_mount_dir(),
+        JobKind::Send,
+        None,
+    )
+    .await?;
+    let result = send_stream(&paths, since, writer, job.get_progress()).await;
+    job.finish(result).await
+}
+
+/// Write the current root of a monofs filesystem and its blocks to a stream file, picking up
+/// where an earlier call left off
+///
+/// The stream is the one [`send`] writes. It is written as a job that records a checkpoint every
+/// thousand or so blocks, so if the call is interrupted or fails, calling it again with the same
+/// `path` and `since` resumes the stream from its last checkpoint instead of starting over. A
+/// resumed stream still brings its receiver to the root it started with, even if the filesystem
+/// has moved on since. See [`job_status`](crate::management::job_status) to follow its progress.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `since` - A root the receiver already has, whose blocks are not sent
+/// * `path` - The file to write the stream to
+///
+/// ## Returns
+/// What the stream holds, including the blocks written before it was resumed
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let report = management::send_file(Some("mfstest".into()), None, "mfstest.send").await?;
+/// println!("sent {} blocks", report.get_blocks());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn send_file(
+    mount_dir: Option<PathBuf>,
+    since: Option<Cid>,
+    path: impl AsRef<Path>,
+) -> FsResult<SendReport> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let path = std::path::absolute(path.as_ref())?;
+    let target = path.to_string_lossy().to_string();
+    let job = Job::start(
+        paths.fs_db_path(),
+        paths.get_mount_dir(),
+        JobKind::Send,
+        Some(&target),
+    )
+    .await?;
+
+    let result = write_stream_file(&paths, since, &path, &job).await;
+    job.finish(result).await
+}
+
+/// Apply a stream written by [`send`] to a monofs filesystem
+///
+/// Blocks the filesystem already has are skipped. Once every block is stored, the filesystem is
+/// moved to the root of the stream, which is recorded in its history like any other root. If
+/// the filesystem is mounted, the new root is swapped into the running server, and changes made
+/// through the mount since its last checkpoint are lost.
+///
+/// An incremental stream fails to apply if the filesystem does not have the blocks it was sent
+/// without. The blocks it carried are kept, and a full stream or one sent since a root the
+/// filesystem has will complete them.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `reader` - Where to read the stream from
+///
+/// ## Returns
+/// What was read from the stream
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let file = tokio::fs::File::open("mfstest.send").await?;
+/// let report = management::receive(Some("replica".into()), file).await?;
+/// println!("received root {}", report.get_root());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn receive(
+    mount_dir: Option<PathBuf>,
+    reader: impl AsyncRead + Unpin + Send,
+) -> FsResult<ReceiveReport> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let job = Job::start(
+        paths.fs_db_path(),
+        paths.get_mount_dir(),
+        JobKind::Receive,
+        None,
+    )
+    .await?;
+    let result = apply_stream(&paths, reader, job.get_progress()).await;
+    job.finish(result).await
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Applies the stream read from `reader` to the filesystem at `paths`, reporting the blocks read
+/// to `progress`.
+async fn apply_stream(
+    paths: &MfsPaths,
+    reader: impl AsyncRead + Unpin + Send,
+    progress: &JobProgress,
+) -> FsResult<ReceiveReport> {
+    let store = paths.open_store().await?;
+
+    let report = read_stream(&store, reader, progress).await?;
+    if !head::is_complete(&store, &report.root).await? {
+        return Err(FsError::InvalidSendStream(format!(
+            "blocks of root {} are missing, send since a root this filesystem has",
+            report.root
+        )));
+    }
+
+    Dir::load(&report.root, store).await?;
+    head::set_head(paths, None, &report.root, "receive").await?;
+    tracing::info!(
+        "received root {} ({} new blocks, {} skipped)",
+        report.root,
+        report.received_blocks,
+        report.skipped_blocks
+    );
+
+    Ok(report)
+}
+
+/// Writes the current root of the filesystem at `paths` and its blocks to `writer`, reporting
+/// the blocks written to `progress`.
+async fn send_stream(
+    paths: &MfsPaths,
+    since: Option<Cid>,
+    writer: impl AsyncWrite + Unpin + Send,
+    progress: &JobProgress,
+) -> FsResult<SendReport> {
+    let store = paths.open_store().await?;
+    let root = get_send_root(paths).await?;
+    let (blocks, bytes) = write_stream(&store, &root, since.as_ref(), writer, progress).await?;
+    tracing::info!("sent {} blocks ({} bytes) of root {}", blocks, bytes, root);
+
+    Ok(SendReport {
+        root,
+        since,
+        blocks,
+        bytes,
+    })
+}
+
+/// Returns the current root of the filesystem at `paths`, which is the root it sends.
+async fn get_send_root(paths: &MfsPaths) -> FsResult<Cid> {
+    head::checkpoint_head(paths).await?.ok_or_else(|| {
+        FsError::InvalidOperation(format!(
+            "filesystem at {} has no recorded state to send",
+            paths.get_mount_dir().display()
+        ))
+    })
+}
+
+/// Writes the blocks reachable from `root` but not from `since` to `writer`.
+///
+/// Blocks are written after the blocks they link to, so a receiver can count references as it
+/// goes. Returns the number and size of the blocks written.
+async fn write_stream(
+    store: &FlatFsStore,
+    root: &Cid,
+    since: Option<&Cid>,
+    mut writer: impl AsyncWrite + Unpin + Send,
+    progress: &JobProgress,
+) -> FsResult<(u64, u64)> {
+    let order = get_stream_blocks(store, root, since).await?;
+    progress.set_total(order.len() as u64);
+    write_stream_header(&mut writer, root).await?;
+
+    let (mut blocks, mut bytes) = (0, 0);
+    for cid in &order {
+        bytes += write_stream_block(store, &mut writer, cid).await?;
+        blocks += 1;
+        progress.set_done(blocks);
+    }
+
+    // A zero-length CID marks the end of the stream
+    writer.write_u16(0).await?;
+    writer.flush().await?;
+
+    Ok((blocks, bytes))
+}
+
+/// Writes the stream of [`send_file`] to the file at `path`, resuming from the last checkpoint
+/// of `job` if the file still holds what it says was written.
+async fn write_stream_file(
+    paths: &MfsPaths,
+    since: Option<Cid>,
+    path: &Path,
+    job: &Job,
+) -> FsResult<SendReport> {
+    let store = paths.open_store().await?;
+    let since_cid = since.map(|cid| cid.to_string());
+    let checkpoint = match job.get_checkpoint::<SendCheckpoint>() {
+        Some(checkpoint) if checkpoint.since == since_cid => match fs::metadata(path).await {
+            Ok(metadata) if metadata.len() >= checkpoint.offset => Some(checkpoint),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    let root = match &checkpoint {
+        Some(checkpoint) => Cid::try_from(checkpoint.root.as_str())?,
+        None => get_send_root(paths).await?,
+    };
+
+    // The blocks come in the same order every time, so the ones already written can be skipped
+    let order = get_stream_blocks(&store, &root, since.as_ref()).await?;
+    let progress = job.get_progress();
+    progress.set_total(order.len() as u64);
+
+    let (mut file, mut blocks, mut bytes) = match checkpoint {
+        Some(checkpoint) => {
+            let mut file = fs::OpenOptions::new().write(true).open(path).await?;
+            file.set_len(checkpoint.offset).await?;
+            file.seek(SeekFrom::Start(checkpoint.offset)).await?;
+            tracing::info!(
+                "resuming stream to {} after {} blocks",
+                path.display(),
+                checkpoint.blocks
+            );
+            (file, checkpoint.blocks, checkpoint.bytes)
+        }
+        None => {
+            let mut file = fs::File::create(path).await?;
+            write_stream_header(&mut file, &root).await?;
+            (file, 0, 0)
+        }
+    };
+    progress.set_done(blocks);
+
+    for cid in order.iter().skip(blocks as usize) {
+        bytes += write_stream_block(&store, &mut file, cid).await?;
+        blocks += 1;
+        progress.set_done(blocks);
+
+        // The blocks have to be on disk before a checkpoint says they were written
+        if blocks % SEND_CHECKPOINT_BLOCKS == 0 {
+            file.flush().await?;
+            file.sync_data().await?;
+            let checkpoint = SendCheckpoint {
+                root: root.to_string(),
+                since: since_cid.clone(),
+                blocks,
+                bytes,
+                offset: file.stream_position().await?,
+            };
+            job.checkpoint(&checkpoint).await?;
+        }
+    }
+
+    // A zero-length CID marks the end of the stream
+    file.write_u16(0).await?;
+    file.flush().await?;
+    file.sync_all().await?;
+    tracing::info!(
+        "sent {} blocks ({} bytes) of root {} to {}",
+        blocks,
+        bytes,
+        root,
+        path.display()
+    );
+
+    Ok(SendReport {
+        root,
+        since,
+        blocks,
+        bytes,
+    })
+}
+
+/// Returns the blocks reachable from `root` but not from `since`, every block after the blocks
+/// it links to, so a receiver can count references as it goes.
+///
+/// The order only depends on the blocks, so it is the same every time a stream is written.
+async fn get_stream_blocks(
+    store: &FlatFsStore,
+    root: &Cid,
+    since: Option<&Cid>,
+) -> FsResult<Vec<Cid>> {
+    let known = match since {
+        Some(since) => store::collect_reachable(store, [*since]).await?,
+        None => HashSet::new(),
+    };
+
+    let dag = DagWalker::new(store)
+        .walk_with([*root], |cid| known.contains(cid), |_| {})
+        .await?;
+
+    Ok(dag
+        .get_postorder([*root])
+        .into_iter()
+        .filter(|cid| !dag.get_skipped().contains(cid))
+        .collect())
+}
+
+/// Writes the start of a stream bringing its receiver to `root`.
+async fn write_stream_header(writer: &mut (impl AsyncWrite + Unpin), root: &Cid) -> FsResult<()> {
+    writer.write_all(SEND_STREAM_MAGIC).await?;
+    write_cid(writer, root).await
+}
+
+/// Writes the block at `cid` to a stream, returning its size.
+async fn write_stream_block(
+    store: &FlatFsStore,
+    writer: &mut (impl AsyncWrite + Unpin),
+    cid: &Cid,
+) -> FsResult<u64> {
+    let data = store.get_block_data(cid).await?;
+    write_cid(writer, cid).await?;
+    writer.write_u32(data.len() as u32).await?;
+    writer.write_all(&data).await?;
+
+    Ok(data.len() as u64)
+}
+
+/// Stores the blocks of the stream read from `reader` in `store`.
+async fn read_stream(
+    store: &FlatFsStore,
+    mut reader: impl AsyncRead + Unpin + Send,
+    progress: &JobProgress,
+) -> FsResult<ReceiveReport> {
+    let mut magic = [0; SEND_STREAM_MAGIC.len()];
+    reader.read_exact(&mut magic).await?;
+    if &magic != SEND_STREAM_MAGIC {
+        return Err(FsError::InvalidSendStream(
+            "stream does not start with a send header".to_string(),
+        ));
+    }
+
+    let root = read_cid(&mut reader)
+        .await?
+        .ok_or_else(|| FsError::InvalidSendStream("stream has no root".to_string()))?;
+
+    let mut report = ReceiveReport {
+        root,
+        received_blocks: 0,
+        skipped_blocks: 0,
+        bytes: 0,
+    };
+
+    while let Some(cid) = read_cid(&mut reader).await? {
+        let len = reader.read_u32().await?;
+        if len > MAX_SEND_BLOCK_SIZE {
+            return Err(FsError::InvalidSendStream(format!(
+                "block {} is {} bytes, more than the maximum of {}",
+                cid, len, MAX_SEND_BLOCK_SIZE
+            )));
+        }
+
+        let mut data = vec![0; len as usize];
+        reader.read_exact(&mut data).await?;
+        if store.put_block_data(&cid, &data).await? {
+            report.received_blocks += 1;
+        } else {
+            report.skipped_blocks += 1;
+        }
+        report.bytes += len as u64;
+        progress.add_done(1);
+    }
+
+    Ok(report)
+}
+
+/// Writes `cid` prefixed with its length.
+async fn write_cid(writer: &mut (impl AsyncWrite + Unpin), cid: &Cid) -> FsResult<()> {
+    let bytes = cid.to_bytes();
+    writer.write_u16(bytes.len() as u16).await?;
+    writer.write_all(&bytes).await?;
+
+    Ok(())
+}
+
+/// Reads a CID prefixed with its length, or `None` at the end of the stream.
+async fn read_cid(reader: &mut (impl AsyncRead + Unpin)) -> FsResult<Option<Cid>> {
+    let len = reader.read_u16().await?;
+    if len == 0 {
+        return Ok(None);
+    }
+
+    let mut bytes = vec![0; len as usize];
+    reader.read_exact(&mut bytes).await?;
+    let cid = Cid::try_from(bytes.as_slice())
+        .map_err(|e| FsError::InvalidSendStream(format!("invalid CID in stream: {}", e)))?;
+
+    Ok(Some(cid))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::Storable;
+    use tempfile::TempDir;
+
+    use crate::{
+        filesystem::File,
+        management::{self, FsHead, JobState},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_send_stream_round_trip() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let source = FlatFsStore::new(temp.path().join("source"));
+        let full_dest = FlatFsStore::new(temp.path().join("full"));
+        let partial_dest = FlatFsStore::new(temp.path().join("partial"));
+        let progress = JobProgress::default();
+
+        let mut root = Dir::new(source.clone());
+        let file = File::with_content(source.clone(), b"first".as_slice()).await?;
+        root.put_adapted_file("first.txt", file).await?;
+        let first = root.checkpoint().await?;
+
+        // A full stream carries every block
+        let mut stream = Vec::new();
+        let (blocks, _) = write_stream(&source, &first, None, &mut stream, &progress).await?;
+        let report = read_stream(&full_dest, stream.as_slice(), &progress).await?;
+        assert_eq!(report.root, first);
+        assert_eq!(report.received_blocks, blocks);
+        assert!(head::is_complete(&full_dest, &first).await?);
+
+        // An incremental stream only carries what changed since the root the receiver has
+        let file = File::with_content(source.clone(), b"second".as_slice()).await?;
+        root.put_adapted_file("second.txt", file).await?;
+        let second = root.checkpoint().await?;
+
+        let mut stream = Vec::new();
+        let (incremental, _) =
+            write_stream(&source, &second, Some(&first), &mut stream, &progress).await?;
+        let (full, _) = write_stream(&source, &second, None, tokio::io::sink(), &progress).await?;
+        assert!(incremental < full);
+        let report = read_stream(&full_dest, stream.as_slice(), &progress).await?;
+        assert_eq!(report.skipped_blocks, 0);
+        assert!(head::is_complete(&full_dest, &second).await?);
+
+        let root = Dir::load(&second, full_dest.clone()).await?;
+        assert!(root.get_file("second.txt").await?.is_some());
+
+        // A receiver without the earlier root is left incomplete
+        read_stream(&partial_dest, stream.as_slice(), &progress).await?;
+        assert!(!head::is_complete(&partial_dest, &second).await?);
+
+        // Anything else is rejected
+        let result = read_stream(&partial_dest, b"not a stream".as_slice(), &progress).await;
+        assert!(matches!(result, Err(FsError::InvalidSendStream(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_file_resumes_from_checkpoint() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let mount_dir = fs::canonicalize(temp.path()).await?.join("mnt");
+        let paths = find::helper::setup_test_mfs(&mount_dir).await?;
+
+        let store = FlatFsStore::new(paths.blocks_dir());
+        let mut root = Dir::new(store.clone());
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            let file = File::with_content(store.clone(), name.as_bytes()).await?;
+            root.put_adapted_file(name, file).await?;
+        }
+        let cid = root.checkpoint().await?;
+        FsHead::new(paths.fs_db_path(), &mount_dir)
+            .await?
+            .set(&cid, "checkpoint")
+            .await?;
+
+        let mut expected = Vec::new();
+        let (blocks, _) =
+            write_stream(&store, &cid, None, &mut expected, &JobProgress::default()).await?;
+
+        // A send that died after a checkpoint, having written more than it recorded
+        let path = temp.path().join("stream");
+        let order = get_stream_blocks(&store, &cid, None).await?;
+        let mut partial = Vec::new();
+        write_stream_header(&mut partial, &cid).await?;
+        let bytes = write_stream_block(&store, &mut partial, &order[0]).await?;
+        let offset = partial.len() as u64;
+        write_stream_block(&store, &mut partial, &order[1]).await?;
+        partial.extend_from_slice(b"torn");
+        fs::write(&path, &partial).await?;
+
+        let target = path.to_string_lossy().to_string();
+        let job = Job::start(paths.fs_db_path(), &mount_dir, JobKind::Send, Some(&target)).await?;
+        let id = job.get_id();
+        job.get_progress().set_done(1);
+        let checkpoint = SendCheckpoint {
+            root: cid.to_string(),
+            since: None,
+            blocks: 1,
+            bytes,
+            offset,
+        };
+        job.checkpoint(&checkpoint).await?;
+        job.finish::<()>(Err(FsError::InvalidOperation("killed".to_string())))
+            .await
+            .ok();
+
+        // Sending again picks up from the checkpoint, even though the root has moved on
+        let file = File::with_content(store.clone(), b"later".as_slice()).await?;
+        root.put_adapted_file("later.txt", file).await?;
+        let later = root.checkpoint().await?;
+        FsHead::new(paths.fs_db_path(), &mount_dir)
+            .await?
+            .set(&later, "checkpoint")
+            .await?;
+
+        let report = send_file(Some(mount_dir.clone()), None, &path).await?;
+        assert_eq!(report.root, cid);
+        assert_eq!(report.blocks, blocks);
+        assert_eq!(fs::read(&path).await?, expected);
+
+        let status = management::job_status(Some(mount_dir.clone()), id).await?;
+        assert_eq!(status.get_state(), &JobState::Completed);
+        assert_eq!(status.get_done(), &blocks);
+
+        // Once completed, the next send starts over with the current root
+        let report = send_file(Some(mount_dir), None, &path).await?;
+        assert_eq!(report.root, later);
+
+        Ok(())
+    }
+}
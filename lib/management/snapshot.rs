@@ -0,0 +1,303 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use getset::Getters;
+use ipldstore::ipld::cid::Cid;
+use serde::Serialize;
+use sqlx::{sqlite::SqliteRow, Pool, Row, Sqlite};
+
+use crate::{
+    management::{db, find, head},
+    utils, FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A named, immutable checkpoint of a filesystem's root directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct Snapshot {
+    /// The name of the snapshot.
+    name: String,
+
+    /// The CID of the root directory captured by the snapshot.
+    #[serde(serialize_with = "utils::serialize_display")]
+    root: Cid,
+
+    /// When the snapshot was taken.
+    created_at: DateTime<Utc>,
+
+    /// Whether the snapshot was taken by the filesystem's snapshot schedule, which deletes it
+    /// again once it is no longer kept. See [`set_snapshot_schedule`](super::set_snapshot_schedule)
+    /// and [`prune_snapshots`](super::prune_snapshots).
+    scheduled: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Capture the current state of a monofs filesystem as a named snapshot
+///
+/// If the filesystem is mounted, its server checkpoints the in-memory root first so that every
+/// change made so far is captured. Otherwise the last recorded head is used.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `name` - The name of the snapshot. Must be unique within the filesystem
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let snapshot = management::snapshot_mfs(Some("mfstest".into()), "before-upgrade").await?;
+/// println!("captured {}", snapshot.get_root());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn snapshot_mfs(
+    mount_dir: Option<PathBuf>,
+    name: impl Into<String>,
+) -> FsResult<Snapshot> {
+    let name = name.into();
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let pool = db::get_db_pool(paths.fs_db_path()).await?;
+
+    // Refuse to overwrite an existing snapshot
+    if get_snapshot(&pool, paths.get_mount_dir(), &name)
+        .await?
+        .is_some()
+    {
+        return Err(FsError::SnapshotExists(name));
+    }
+
+    // Capture the root, checkpointing it first if the filesystem is mounted
+    let root = head::checkpoint_head(&paths).await?.ok_or_else(|| {
+        FsError::InvalidOperation(format!(
+            "filesystem at {} has no recorded state to snapshot",
+            paths.get_mount_dir().display()
+        ))
+    })?;
+
+    record_snapshot(&pool, paths.get_mount_dir(), &name, &root, false).await
+}
+
+/// List the snapshots of a monofs filesystem, oldest first
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// for snapshot in management::list_snapshots(None).await? {
+///     println!("{} {}", snapshot.get_name(), snapshot.get_root());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn list_snapshots(mount_dir: Option<PathBuf>) -> FsResult<Vec<Snapshot>> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let pool = db::get_db_pool(paths.fs_db_path()).await?;
+
+    let rows = sqlx::query(
+        r#"
+        SELECT s.name, s.root_cid, s.created_at, s.scheduled
+        FROM snapshots s
+        JOIN filesystems f ON s.fs_id = f.id
+        WHERE f.mount_dir = ?
+        ORDER BY s.created_at, s.id
+        "#,
+    )
+    .bind(paths.get_mount_dir().to_string_lossy().to_string())
+    .fetch_all(&pool)
+    .await?;
+
+    rows.iter().map(snapshot_from_row).collect()
+}
+
+/// Restore a monofs filesystem to a named snapshot
+///
+/// If the filesystem is mounted, its root is swapped in place and clients see the snapshot's
+/// contents immediately. Changes made since the last snapshot or checkpoint are discarded, so
+/// take a snapshot first if they should be kept. If the filesystem is not mounted, the snapshot
+/// becomes its head and is served the next time it is mounted.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `name` - The name of the snapshot to restore
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// management::restore_snapshot(Some("mfstest".into()), "before-upgrade").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn restore_snapshot(
+    mount_dir: Option<PathBuf>,
+    name: impl AsRef<str>,
+) -> FsResult<Snapshot> {
+    let name = name.as_ref();
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let pool = db::get_db_pool(paths.fs_db_path()).await?;
+
+    let snapshot = get_snapshot(&pool, paths.get_mount_dir(), name)
+        .await?
+        .ok_or_else(|| FsError::SnapshotNotFound(name.to_string()))?;
+
+    // Swap the root of the running server, or record the snapshot as the head for the next mount
+    let operation = format!("restore snapshot {}", name);
+    if head::set_head(&paths, None, &snapshot.root, &operation).await? {
+        tracing::info!("restored snapshot {} on the running filesystem", name);
+    } else {
+        tracing::info!("restored snapshot {} as the filesystem head", name);
+    }
+
+    Ok(snapshot)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Records `root` as a snapshot named `name` of the filesystem mounted at `mount_dir`, marked as
+/// taken by its schedule if `scheduled`.
+pub(super) async fn record_snapshot(
+    pool: &Pool<Sqlite>,
+    mount_dir: &Path,
+    name: &str,
+    root: &Cid,
+    scheduled: bool,
+) -> FsResult<Snapshot> {
+    let fs_id = db::get_fs_id(pool, mount_dir).await?;
+    sqlx::query("INSERT INTO snapshots (fs_id, name, root_cid, scheduled) VALUES (?, ?, ?, ?)")
+        .bind(fs_id)
+        .bind(name)
+        .bind(root.to_string())
+        .bind(scheduled)
+        .execute(pool)
+        .await?;
+    tracing::info!("created snapshot {} at {}", name, root);
+
+    get_snapshot(pool, mount_dir, name)
+        .await?
+        .ok_or_else(|| FsError::SnapshotNotFound(name.to_string()))
+}
+
+/// Get a snapshot of the filesystem mounted at `mount_dir` by name.
+pub(super) async fn get_snapshot(
+    pool: &Pool<Sqlite>,
+    mount_dir: &Path,
+    name: &str,
+) -> FsResult<Option<Snapshot>> {
+    let record = sqlx::query(
+        r#"
+        SELECT s.name, s.root_cid, s.created_at, s.scheduled
+        FROM snapshots s
+        JOIN filesystems f ON s.fs_id = f.id
+        WHERE f.mount_dir = ? AND s.name = ?
+        "#,
+    )
+    .bind(mount_dir.to_string_lossy().to_string())
+    .bind(name)
+    .fetch_optional(pool)
+    .await?;
+
+    record.as_ref().map(snapshot_from_row).transpose()
+}
+
+/// Delete the snapshot named `name` of the filesystem mounted at `mount_dir`.
+pub(super) async fn delete_snapshot(
+    pool: &Pool<Sqlite>,
+    mount_dir: &Path,
+    name: &str,
+) -> FsResult<()> {
+    let fs_id = db::get_fs_id(pool, mount_dir).await?;
+    sqlx::query("DELETE FROM snapshots WHERE fs_id = ? AND name = ?")
+        .bind(fs_id)
+        .bind(name)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Construct a snapshot from a row of the snapshots table.
+pub(super) fn snapshot_from_row(row: &SqliteRow) -> FsResult<Snapshot> {
+    let root_cid: String = row.get("root_cid");
+    Ok(Snapshot {
+        name: row.get("name"),
+        root: Cid::try_from(root_cid.as_str())?,
+        created_at: row.get("created_at"),
+        scheduled: row.get("scheduled"),
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::{IpldStore, MemoryStore};
+    use tempfile::TempDir;
+    use tokio::fs;
+
+    use crate::management::FsHead;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_snapshot_unmounted_filesystem() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let mount_dir = fs::canonicalize(temp.path()).await?.join("mnt");
+        let paths = find::helper::setup_test_mfs(&mount_dir).await?;
+
+        // Nothing to snapshot before the filesystem has a head
+        let result = snapshot_mfs(Some(mount_dir.clone()), "empty").await;
+        assert!(matches!(result, Err(FsError::InvalidOperation(_))));
+
+        // Snapshot the recorded head
+        let store = MemoryStore::default();
+        let first = store.put_bytes(b"first".as_slice()).await?;
+        let head = FsHead::new(paths.fs_db_path(), &mount_dir).await?;
+        head.set(&first, "checkpoint").await?;
+
+        let snapshot = snapshot_mfs(Some(mount_dir.clone()), "v1").await?;
+        assert_eq!(snapshot.get_name(), "v1");
+        assert_eq!(snapshot.get_root(), &first);
+
+        // Snapshot names are unique
+        let result = snapshot_mfs(Some(mount_dir.clone()), "v1").await;
+        assert!(matches!(result, Err(FsError::SnapshotExists(_))));
+
+        // Move the head on and take another snapshot
+        let second = store.put_bytes(b"second".as_slice()).await?;
+        head.set(&second, "checkpoint").await?;
+        snapshot_mfs(Some(mount_dir.clone()), "v2").await?;
+
+        let snapshots = list_snapshots(Some(mount_dir.clone())).await?;
+        let names = snapshots
+            .iter()
+            .map(|s| s.get_name().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["v1", "v2"]);
+
+        // Restoring makes the snapshot the head again
+        restore_snapshot(Some(mount_dir.clone()), "v1").await?;
+        assert_eq!(head.get().await?, Some(first));
+
+        let result = restore_snapshot(Some(mount_dir), "missing").await;
+        assert!(matches!(result, Err(FsError::SnapshotNotFound(_))));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,294 @@
+use std::path::{Path, PathBuf};
+
+use getset::Getters;
+use ipldstore::ipld::cid::Cid;
+use serde::Serialize;
+use sqlx::Row;
+use tokio::fs;
+#[cfg(not(target_os = "linux"))]
+use tokio::process::Command;
+
+use crate::{
+    config::{QuotaConfig, DEFAULT_BRANCH_NAME},
+    management::{db, find, FsHead, FsQuota, QuotaUsage},
+    utils::{self, path::MFS_LINK_FILENAME},
+    FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The state of a monofs filesystem.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct MfsStatus {
+    /// The directory where the filesystem is mounted.
+    mount_dir: PathBuf,
+
+    /// Whether the filesystem is currently mounted.
+    mounted: bool,
+
+    /// The port the filesystem's NFS server listens on, if it is running one.
+    port: Option<u32>,
+
+    /// The PID of the supervisor serving the filesystem, if it is running.
+    supervisor_pid: Option<u32>,
+
+    /// The last recorded root CID of the filesystem.
+    #[serde(serialize_with = "utils::serialize_optional_display")]
+    root_cid: Option<Cid>,
+
+    /// The branch the filesystem is on.
+    branch: String,
+
+    /// The number of blocks in the filesystem's blocks directory.
+    block_count: u64,
+
+    /// The total size in bytes of the filesystem's blocks.
+    store_size: u64,
+
+    /// How much the filesystem may hold.
+    quota: QuotaConfig,
+
+    /// How much the filesystem held when its server last recorded it. Only filesystems with a
+    /// quota have their usage recorded.
+    usage: Option<QuotaUsage>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Get the status of a monofs filesystem
+///
+/// The status works whether or not the filesystem is mounted. The reported root is the last one
+/// recorded in the fs database, so changes made to a mounted filesystem since its last checkpoint
+/// are not reflected in it.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let status = management::status_mfs(Some("mfstest".into())).await?;
+/// if *status.get_mounted() {
+///     println!("mounted on port {:?}", status.get_port());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn status_mfs(mount_dir: Option<PathBuf>) -> FsResult<MfsStatus> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let mount_dir = paths.get_mount_dir().clone();
+
+    // The link inside the mount directory is only visible while the filesystem is mounted
+    let mounted = fs::try_exists(mount_dir.join(MFS_LINK_FILENAME))
+        .await
+        .unwrap_or(false);
+
+    // Read the process information recorded by the supervisor
+    let pool = db::get_db_pool(paths.fs_db_path()).await?;
+    let record =
+        sqlx::query("SELECT supervisor_pid, port, branch FROM filesystems WHERE mount_dir = ?")
+            .bind(mount_dir.to_string_lossy().to_string())
+            .fetch_optional(&pool)
+            .await?;
+
+    let (supervisor_pid, port, branch) = match record {
+        Some(row) => (
+            row.get::<Option<i64>, _>("supervisor_pid")
+                .map(|pid| pid as u32),
+            row.get::<Option<i64>, _>("port").map(|port| port as u32),
+            row.get::<String, _>("branch"),
+        ),
+        None => (None, None, DEFAULT_BRANCH_NAME.to_string()),
+    };
+
+    // A supervisor that died without cleaning up leaves its PID behind, which may have been reused
+    let supervisor_pid = match supervisor_pid {
+        Some(pid) if is_supervisor_running(pid, &mount_dir).await => Some(pid),
+        _ => None,
+    };
+    let port = port.filter(|_| supervisor_pid.is_some());
+
+    let root_cid = FsHead::new(paths.fs_db_path(), &mount_dir)
+        .await?
+        .get()
+        .await?;
+
+    let (block_count, store_size) = get_blocks_usage(&paths.blocks_dir()).await?;
+
+    let quota = FsQuota::new(paths.fs_db_path(), &mount_dir).await?;
+    let usage = quota.get_usage().await?;
+    let quota = quota.get().await?;
+
+    Ok(MfsStatus {
+        mount_dir,
+        mounted,
+        port,
+        supervisor_pid,
+        root_cid,
+        branch,
+        block_count,
+        store_size,
+        quota,
+        usage,
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns whether the process with the given PID is the supervisor serving the filesystem
+/// mounted at `mount_dir`.
+///
+/// The PID recorded by a supervisor that exited may have been reused by an unrelated process, so
+/// the command line of the process has to be that of a supervisor for the same mount directory.
+pub(crate) async fn is_supervisor_running(pid: u32, mount_dir: &Path) -> bool {
+    if !utils::is_process_running(pid) {
+        return false;
+    }
+
+    match get_process_command(pid).await {
+        Some(command) => is_supervisor_command(&command, mount_dir),
+        None => {
+            tracing::warn!("failed to read the command line of process {}", pid);
+            false
+        }
+    }
+}
+
+/// Returns the command line of the process with the given PID, with its arguments separated by
+/// spaces, if it can be read.
+#[cfg(target_os = "linux")]
+async fn get_process_command(pid: u32) -> Option<String> {
+    let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).await.ok()?;
+    let args = cmdline
+        .split(|byte| *byte == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(String::from_utf8_lossy)
+        .collect::<Vec<_>>();
+
+    Some(args.join(" "))
+}
+
+/// Returns the command line of the process with the given PID, with its arguments separated by
+/// spaces, if it can be read.
+#[cfg(not(target_os = "linux"))]
+async fn get_process_command(pid: u32) -> Option<String> {
+    let output = Command::new("ps")
+        .args(["-ww", "-o", "args=", "-p"])
+        .arg(pid.to_string())
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Returns whether `command` runs the supervisor of the filesystem mounted at `mount_dir`.
+fn is_supervisor_command(command: &str, mount_dir: &Path) -> bool {
+    let mount_arg = format!("--mount-dir {}", mount_dir.display());
+    command.split(' ').any(|arg| arg == "supervisor")
+        && command.match_indices(&mount_arg).any(|(start, _)| {
+            let rest = &command[start + mount_arg.len()..];
+            rest.is_empty() || rest.starts_with(' ')
+        })
+}
+
+/// Returns the number of block files under `dir` and their total size in bytes.
+pub(crate) async fn get_blocks_usage(dir: &Path) -> FsResult<(u64, u64)> {
+    let mut count = 0;
+    let mut size = 0;
+
+    if !fs::try_exists(dir).await? {
+        return Ok((count, size));
+    }
+
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else if metadata.is_file() {
+                count += 1;
+                size += metadata.len();
+            }
+        }
+    }
+
+    Ok((count, size))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::IpldStore;
+    use tempfile::TempDir;
+
+    use crate::store::FlatFsStore;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_status_unmounted_filesystem() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let mount_dir = fs::canonicalize(temp.path()).await?.join("mnt");
+        let paths = find::helper::setup_test_mfs(&mount_dir).await?;
+
+        let status = status_mfs(Some(mount_dir.clone())).await?;
+        assert_eq!(status.get_mount_dir(), &mount_dir);
+        assert!(!status.get_mounted());
+        assert_eq!(status.get_port(), &None);
+        assert_eq!(status.get_supervisor_pid(), &None);
+        assert_eq!(status.get_root_cid(), &None);
+        assert_eq!(*status.get_block_count(), 0);
+
+        // Blocks and the head are picked up
+        let store = FlatFsStore::new(paths.blocks_dir());
+        let cid = store.put_bytes(b"hello".as_slice()).await?;
+        FsHead::new(paths.fs_db_path(), &mount_dir)
+            .await?
+            .set(&cid, "checkpoint")
+            .await?;
+
+        let status = status_mfs(Some(mount_dir)).await?;
+        assert_eq!(status.get_root_cid(), &Some(cid));
+        assert!(*status.get_block_count() > 0);
+        assert!(*status.get_store_size() > 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_is_supervisor_running() -> anyhow::Result<()> {
+        let mount_dir = Path::new("/tmp/mnt");
+        let command = "/usr/bin/mfsrun supervisor --log-dir /tmp/mnt.mfs/log --mount-dir /tmp/mnt --backend nfs";
+        assert!(is_supervisor_command(command, mount_dir));
+        assert!(!is_supervisor_command(command, Path::new("/tmp/mn")));
+        assert!(!is_supervisor_command(command, Path::new("/tmp")));
+        assert!(!is_supervisor_command(
+            "/usr/bin/vim --mount-dir /tmp/mnt",
+            mount_dir
+        ));
+
+        // This process is running, but is not a supervisor
+        assert!(!is_supervisor_running(std::process::id(), mount_dir).await);
+        assert!(!is_supervisor_running(i32::MAX as u32, mount_dir).await);
+
+        Ok(())
+    }
+}
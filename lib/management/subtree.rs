@@ -0,0 +1,100 @@
+use std::path::{Path, PathBuf};
+
+use sqlx::{Pool, Row, Sqlite};
+
+use crate::{management::db, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Tracks the directory a filesystem serves as the root of its mount, in its fs database.
+///
+/// The path is recorded when the filesystem is initialized to serve a subtree, and used by its
+/// server every time it starts.
+#[derive(Debug, Clone)]
+pub struct FsSubtree {
+    /// The filesystem database.
+    fs_db: Pool<Sqlite>,
+
+    /// The mount directory identifying the filesystem.
+    mount_dir: PathBuf,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FsSubtree {
+    /// Opens the subtree record of the filesystem mounted at `mount_dir`.
+    pub async fn new(
+        fs_db_path: impl AsRef<Path>,
+        mount_dir: impl Into<PathBuf>,
+    ) -> FsResult<Self> {
+        Ok(Self {
+            fs_db: db::get_db_pool(fs_db_path.as_ref()).await?,
+            mount_dir: mount_dir.into(),
+        })
+    }
+
+    /// Returns the path of the directory the filesystem serves as its root, if it does not serve
+    /// the whole root.
+    pub async fn get(&self) -> FsResult<Option<String>> {
+        let record = sqlx::query("SELECT path FROM subtrees WHERE mount_dir = ?")
+            .bind(self.mount_dir.to_string_lossy().to_string())
+            .fetch_optional(&self.fs_db)
+            .await?;
+
+        Ok(record.map(|row| row.get::<String, _>("path")))
+    }
+
+    /// Records that the filesystem serves the directory at `path` as its root.
+    pub async fn set(&self, path: &str) -> FsResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO subtrees (mount_dir, path)
+            VALUES (?, ?)
+            ON CONFLICT (mount_dir) DO UPDATE
+            SET path = excluded.path,
+                modified_at = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(self.mount_dir.to_string_lossy().to_string())
+        .bind(path)
+        .execute(&self.fs_db)
+        .await?;
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::management::FS_DB_MIGRATOR;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fs_subtree_get_set() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("fs.db");
+        db::init_db(&db_path, &FS_DB_MIGRATOR).await?;
+
+        let subtree = FsSubtree::new(&db_path, temp_dir.path().join("mnt")).await?;
+        assert_eq!(subtree.get().await?, None);
+
+        subtree.set("home/alice").await?;
+        assert_eq!(subtree.get().await?, Some("home/alice".to_string()));
+
+        subtree.set("home/bob").await?;
+        assert_eq!(subtree.get().await?, Some("home/bob".to_string()));
+
+        Ok(())
+    }
+}
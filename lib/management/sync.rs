@@ -0,0 +1,593 @@
+use std::{
+    collections::HashSet,
+    fs::{Metadata as HostMetadata, Permissions},
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use chrono::{TimeZone, Utc};
+use getset::Getters;
+use ipldstore::{ipld::cid::Cid, IpldStore, IpldStoreSeekable};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs,
+    io::{self, AsyncRead, AsyncReadExt, AsyncWriteExt},
+};
+
+use crate::{
+    config::SyncDirection,
+    filesystem::{Dir, Entity, File, Metadata, SymPathLink},
+    management::{archive, find, head, tree, Job, JobKind, JobProgress, MfsPaths, TreeSource},
+    store, utils, FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The number of bytes read at a time when hashing the content of a file.
+const HASH_CHUNK_LEN: usize = 64 * 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// What a [`sync_dir`] changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct SyncSummary {
+    /// The number of entries copied because they were missing or changed at the destination.
+    copied: u64,
+
+    /// The number of entries left alone because they were unchanged.
+    unchanged: u64,
+
+    /// The number of entries removed from the destination because the source has no such entry.
+    removed: u64,
+
+    /// The number of bytes of file content copied.
+    bytes: u64,
+
+    /// The root of the filesystem after a push that changed it.
+    #[serde(serialize_with = "utils::serialize_optional_display")]
+    root: Option<Cid>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Sync a directory of the host with a directory of a monofs filesystem, like `rsync -rlpt`
+///
+/// Only files whose content differs from the destination are copied, which files of the same size
+/// are compared for by a hash of their content, and the block store is read and written directly,
+/// so nothing needs to be mounted. Content pushed into
+/// the filesystem is deduplicated against what the store already holds, unlike copying it through
+/// the mount.
+///
+/// A push copies the host directory into the directory at a path of the current root, creating
+/// it if needed, and records the new root if anything changed. A pull copies a directory of the
+/// current root, or of any root in the store such as that of a snapshot, into the host
+/// directory. Directories, files and symbolic links are copied with their permissions, and files
+/// with their modification times. Symbolic CID links have no host equivalent and are skipped.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `host_dir` - The directory of the host to sync
+/// * `source` - The directory of the filesystem to sync. Pushes only support paths of the current root
+/// * `direction` - Which way to copy changes
+/// * `delete` - Whether to remove entries of the destination the source has no entry for
+///
+/// ## Returns
+/// What the sync changed
+///
+/// ## Example
+/// ```no_run
+/// use monofs::{config::SyncDirection, management::{self, TreeSource}};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let source = TreeSource::Path("sandbox/src".into());
+/// let summary = management::sync_dir(Some("mfstest".into()), "src", source, SyncDirection::Push, true).await?;
+/// println!("copied {} entries", summary.get_copied());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn sync_dir(
+    mount_dir: Option<PathBuf>,
+    host_dir: impl AsRef<Path>,
+    source: TreeSource,
+    direction: SyncDirection,
+    delete: bool,
+) -> FsResult<SyncSummary> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let host_dir = host_dir.as_ref();
+    let job = Job::start(
+        paths.fs_db_path(),
+        paths.get_mount_dir(),
+        JobKind::Sync,
+        None,
+    )
+    .await?;
+    let result = match direction {
+        SyncDirection::Push => push_dir(&paths, host_dir, source, delete, job.get_progress()).await,
+        SyncDirection::Pull => pull_dir(&paths, host_dir, source, delete, job.get_progress()).await,
+    };
+    job.finish(result).await
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Pushes `host_dir` into the directory at `source` in the current root of the filesystem at
+/// `paths`, recording the new root if anything changed.
+async fn push_dir(
+    paths: &MfsPaths,
+    host_dir: &Path,
+    source: TreeSource,
+    delete: bool,
+    progress: &JobProgress,
+) -> FsResult<SyncSummary> {
+    let TreeSource::Path(path) = source else {
+        return Err(FsError::InvalidOperation(
+            "only directories of the current root can be pushed to".to_string(),
+        ));
+    };
+
+    let store = paths.open_store().await?;
+    let head = head::checkpoint_head(paths).await?;
+    let mut root = match &head {
+        Some(cid) => Dir::load(cid, store.clone()).await?,
+        None => Dir::new(store.clone()),
+    };
+
+    let target = archive::get_or_create_dir(&mut root, path.trim_matches('/')).await?;
+    let mut summary = push_tree(target, host_dir, delete, progress).await?;
+    if summary.copied == 0 && summary.removed == 0 {
+        return Ok(summary);
+    }
+
+    let cid = root.checkpoint().await?;
+    let operation = format!("sync {}", host_dir.display());
+    head::set_head(paths, head.as_ref(), &cid, &operation).await?;
+    tracing::info!(
+        "pushed {} entries from {} into {}",
+        summary.copied,
+        host_dir.display(),
+        cid
+    );
+    summary.root = Some(cid);
+
+    Ok(summary)
+}
+
+/// Pulls the directory at `source` of the filesystem at `paths` into `host_dir`.
+async fn pull_dir(
+    paths: &MfsPaths,
+    host_dir: &Path,
+    source: TreeSource,
+    delete: bool,
+    progress: &JobProgress,
+) -> FsResult<SyncSummary> {
+    let store = paths.open_store().await?;
+    let (root, path) = match source {
+        TreeSource::Cid { cid, path } => (cid, path),
+        TreeSource::Path(path) => {
+            let head = head::checkpoint_head(paths).await?.ok_or_else(|| {
+                FsError::InvalidOperation(format!(
+                    "filesystem at {} has no recorded state to sync",
+                    paths.get_mount_dir().display()
+                ))
+            })?;
+            (head, path)
+        }
+    };
+
+    let cid = tree::get_path_cid(&store, &root, path.trim_matches('/')).await?;
+    let summary = pull_tree(&store, &cid, host_dir, delete, progress).await?;
+    tracing::info!(
+        "pulled {} entries from {} into {}",
+        summary.copied,
+        cid,
+        host_dir.display()
+    );
+
+    Ok(summary)
+}
+
+/// Copies the entries of `host_dir` that differ from those of `dir` into it.
+async fn push_tree<S>(
+    dir: &mut Dir<S>,
+    host_dir: &Path,
+    delete: bool,
+    progress: &JobProgress,
+) -> FsResult<SyncSummary>
+where
+    S: IpldStoreSeekable + Send + Sync,
+{
+    let mut summary = SyncSummary::default();
+    let mut pending = vec![(host_dir.to_path_buf(), String::new())];
+    while let Some((host_path, path)) = pending.pop() {
+        let mut children = Vec::new();
+        let mut entries = fs::read_dir(&host_path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            children.push(entry);
+        }
+        children.sort_by_key(|entry| entry.file_name());
+
+        let parent = archive::get_or_create_dir(dir, &path).await?;
+        let mut names = HashSet::new();
+        for child in children {
+            let Some(name) = child.file_name().to_str().map(str::to_string) else {
+                tracing::warn!("skipping {} with a non UTF-8 name", child.path().display());
+                continue;
+            };
+
+            let host_metadata = fs::symlink_metadata(child.path()).await?;
+            let existing = parent.find(&name).await?.cloned();
+            names.insert(name.clone());
+
+            if host_metadata.is_dir() {
+                if !matches!(existing, Some(Entity::Dir(_))) {
+                    if existing.is_some() {
+                        parent.remove_entry(&name)?;
+                    }
+
+                    let created = parent.find_or_create(&name, false).await?;
+                    set_permissions(created.get_metadata_mut(), &host_metadata).await?;
+                    summary.copied += 1;
+                } else {
+                    summary.unchanged += 1;
+                }
+
+                pending.push((child.path(), join_path(&path, &name)));
+            } else if host_metadata.is_file() {
+                if is_unchanged_file(existing.as_ref(), &child.path(), &host_metadata).await? {
+                    summary.unchanged += 1;
+                    continue;
+                }
+
+                let reader = fs::File::open(child.path()).await?;
+                let file = File::with_content(parent.get_store().clone(), reader).await?;
+                parent.put_adapted_file(&name, file).await?;
+                if let Some(entity) = parent.find_mut(&name).await? {
+                    let metadata = entity.get_metadata_mut();
+                    set_permissions(metadata, &host_metadata).await?;
+                    if let Some(mtime) = Utc
+                        .timestamp_opt(host_metadata.mtime(), host_metadata.mtime_nsec() as u32)
+                        .single()
+                    {
+                        metadata.set_modified_at(mtime);
+                    }
+                }
+                summary.copied += 1;
+                summary.bytes += host_metadata.len();
+            } else if host_metadata.is_symlink() {
+                let target = fs::read_link(child.path()).await?;
+                let target = target
+                    .to_str()
+                    .ok_or_else(|| FsError::InvalidPathComponent(target.display().to_string()))?;
+                let unchanged = match &existing {
+                    Some(Entity::SymPathLink(link)) => link.get_target_path().as_str() == target,
+                    _ => false,
+                };
+                if unchanged {
+                    summary.unchanged += 1;
+                    continue;
+                }
+
+                let symlink = SymPathLink::with_path(parent.get_store().clone(), target)?;
+                parent
+                    .put_adapted_entity(&name, Entity::SymPathLink(symlink))
+                    .await?;
+                summary.copied += 1;
+            } else {
+                tracing::warn!("skipping {} of an unsupported type", child.path().display());
+                continue;
+            }
+
+            progress.add_done(1);
+        }
+
+        if delete {
+            let stale = parent
+                .get_entry_names()
+                .map(|name| name.to_string())
+                .filter(|name| !names.contains(name))
+                .collect::<Vec<_>>();
+            for name in stale {
+                parent.remove_entry(&name)?;
+                summary.removed += 1;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Copies the entities below the directory at `cid` that differ from those in `host_dir` into
+/// it.
+async fn pull_tree<S>(
+    store: &S,
+    cid: &Cid,
+    host_dir: &Path,
+    delete: bool,
+    progress: &JobProgress,
+) -> FsResult<SyncSummary>
+where
+    S: IpldStoreSeekable + Send + Sync,
+{
+    let walked = store::walk_entities(store, cid, None).await?;
+    if !matches!(walked.first().map(|w| w.get_entity()), Some(Entity::Dir(_))) {
+        return Err(FsError::NotADirectory(cid.to_string()));
+    }
+
+    fs::create_dir_all(host_dir).await?;
+    let mut summary = SyncSummary::default();
+    for walked in walked {
+        let host_path = match walked.get_path().is_empty() {
+            true => host_dir.to_path_buf(),
+            false => host_dir.join(walked.get_path()),
+        };
+        let host_metadata = fs::symlink_metadata(&host_path).await.ok();
+
+        match walked.get_entity() {
+            Entity::Dir(dir) => {
+                if !host_metadata.as_ref().is_some_and(|m| m.is_dir()) {
+                    remove_host_entry(&host_path, host_metadata.as_ref()).await?;
+                    fs::create_dir(&host_path).await?;
+                    if let Some(mode) = dir.get_metadata().get_mode().await? {
+                        fs::set_permissions(&host_path, Permissions::from_mode(mode)).await?;
+                    }
+                    summary.copied += 1;
+                } else if !walked.get_path().is_empty() {
+                    summary.unchanged += 1;
+                }
+
+                if delete {
+                    summary.removed += remove_stale_entries(dir, &host_path).await?;
+                }
+            }
+            Entity::File(file) => {
+                let metadata = file.get_metadata();
+                let size = file.get_size().await?;
+                if let Some(host_metadata) = &host_metadata {
+                    if is_unchanged_file(Some(walked.get_entity()), &host_path, host_metadata)
+                        .await?
+                    {
+                        summary.unchanged += 1;
+                        continue;
+                    }
+                }
+
+                if !host_metadata.as_ref().is_some_and(|m| m.is_file()) {
+                    remove_host_entry(&host_path, host_metadata.as_ref()).await?;
+                }
+
+                let mut output = fs::File::create(&host_path).await?;
+                io::copy(&mut file.get_input_stream().await?, &mut output).await?;
+                output.flush().await?;
+                if let Some(mode) = metadata.get_mode().await? {
+                    output.set_permissions(Permissions::from_mode(mode)).await?;
+                }
+                let modified_at = SystemTime::from(*metadata.get_modified_at());
+                output.into_std().await.set_modified(modified_at)?;
+                summary.copied += 1;
+                summary.bytes += size;
+            }
+            Entity::SymPathLink(link) => {
+                let target = link.get_target_path().as_str();
+                if host_metadata.as_ref().is_some_and(|m| m.is_symlink())
+                    && fs::read_link(&host_path).await? == Path::new(target)
+                {
+                    summary.unchanged += 1;
+                    continue;
+                }
+
+                remove_host_entry(&host_path, host_metadata.as_ref()).await?;
+                fs::symlink(target, &host_path).await?;
+                summary.copied += 1;
+            }
+            Entity::SymCidLink(_) => {
+                tracing::warn!("skipping symbolic CID link {}", walked.get_path());
+                continue;
+            }
+        }
+
+        progress.add_done(1);
+    }
+
+    Ok(summary)
+}
+
+/// Removes the entries of `host_dir` that `dir` has no entry for.
+///
+/// Returns the number of entries removed.
+async fn remove_stale_entries<S>(dir: &Dir<S>, host_dir: &Path) -> FsResult<u64>
+where
+    S: IpldStore,
+{
+    let names = dir
+        .get_entry_names()
+        .map(|name| name.to_string())
+        .collect::<HashSet<_>>();
+
+    let mut removed = 0;
+    let mut entries = fs::read_dir(host_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        if name.to_str().is_some_and(|name| names.contains(name)) {
+            continue;
+        }
+
+        let metadata = fs::symlink_metadata(entry.path()).await?;
+        remove_host_entry(&entry.path(), Some(&metadata)).await?;
+        removed += 1;
+    }
+
+    Ok(removed)
+}
+
+/// Removes the entry of the host at `path`, if there is one.
+async fn remove_host_entry(path: &Path, metadata: Option<&HostMetadata>) -> FsResult<()> {
+    match metadata {
+        Some(metadata) if metadata.is_dir() => fs::remove_dir_all(path).await?,
+        Some(_) => fs::remove_file(path).await?,
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// Returns whether `existing` is a file with the same content as the file of the host at
+/// `host_path` with `host_metadata`.
+///
+/// Files of different sizes differ without being read. Otherwise the content of both is hashed,
+/// as files changed within the same second, or copied with their modification time, may differ in
+/// nothing else.
+async fn is_unchanged_file<S>(
+    existing: Option<&Entity<S>>,
+    host_path: &Path,
+    host_metadata: &HostMetadata,
+) -> FsResult<bool>
+where
+    S: IpldStoreSeekable + Send + Sync,
+{
+    let Some(Entity::File(file)) = existing else {
+        return Ok(false);
+    };
+    if !host_metadata.is_file() || file.get_size().await? != host_metadata.len() {
+        return Ok(false);
+    }
+
+    let stored = hash_content(file.get_input_stream().await?).await?;
+    let host = hash_content(fs::File::open(host_path).await?).await?;
+    Ok(stored == host)
+}
+
+/// Returns the SHA-256 digest of everything `reader` reads.
+async fn hash_content(mut reader: impl AsyncRead + Unpin) -> FsResult<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; HASH_CHUNK_LEN];
+    loop {
+        let read = reader.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+
+        hasher.update(&buf[..read]);
+    }
+
+    Ok(hasher.finalize().into())
+}
+
+/// Sets the permissions of the entry of the host with `host_metadata` on `metadata`.
+async fn set_permissions<S>(
+    metadata: &mut Metadata<S>,
+    host_metadata: &HostMetadata,
+) -> FsResult<()>
+where
+    S: IpldStore + Send + Sync,
+{
+    metadata
+        .set_permissions(host_metadata.mode() & 0o7777)
+        .await
+}
+
+/// Returns the path of `name` in the directory at `path`.
+fn join_path(path: &str, name: &str) -> String {
+    match path.is_empty() {
+        true => name.to_string(),
+        false => format!("{}/{}", path, name),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::MemoryStore;
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_push_and_pull_tree() -> anyhow::Result<()> {
+        let host = tempdir()?;
+        fs::create_dir_all(host.path().join("src/nested")).await?;
+        fs::write(host.path().join("src/main.rs"), "fn main() {}").await?;
+        fs::write(host.path().join("src/nested/lib.rs"), "").await?;
+        fs::symlink("src/main.rs", host.path().join("main.rs")).await?;
+
+        let store = MemoryStore::default();
+        let mut root = Dir::new(store.clone());
+        root.find_or_create("stale.txt", true).await?;
+        let progress = JobProgress::default();
+
+        // Everything is copied once, and unchanged entries are skipped after that
+        let summary = push_tree(&mut root, host.path(), true, &progress).await?;
+        assert_eq!(summary.copied, 5);
+        assert_eq!(summary.removed, 1);
+        assert_eq!(summary.bytes, 12);
+        assert!(root.find("stale.txt").await?.is_none());
+        let Some(Entity::SymPathLink(link)) = root.find("main.rs").await? else {
+            unreachable!()
+        };
+        assert_eq!(link.get_target_path().as_str(), "src/main.rs");
+
+        let summary = push_tree(&mut root, host.path(), true, &progress).await?;
+        assert_eq!(summary.copied, 0);
+        assert_eq!(summary.unchanged, 5);
+
+        fs::write(host.path().join("src/main.rs"), "fn main() { run() }").await?;
+        let summary = push_tree(&mut root, host.path(), true, &progress).await?;
+        assert_eq!(summary.copied, 1);
+        assert_eq!(summary.bytes, 19);
+
+        // Changes that keep the size and modification time of a file are copied too
+        let main_path = host.path().join("src/main.rs");
+        let modified_at = fs::metadata(&main_path).await?.modified()?;
+        fs::write(&main_path, "fn main() { jmp() }").await?;
+        std::fs::File::options()
+            .write(true)
+            .open(&main_path)?
+            .set_modified(modified_at)?;
+        let summary = push_tree(&mut root, host.path(), true, &progress).await?;
+        assert_eq!(summary.copied, 1);
+
+        // Pulling into another directory recreates the tree, with modification times
+        let cid = root.checkpoint().await?;
+        let pulled = tempdir()?;
+        fs::write(pulled.path().join("extra.txt"), "extra").await?;
+        let summary = pull_tree(&store, &cid, pulled.path(), true, &progress).await?;
+        assert_eq!(summary.copied, 5);
+        assert_eq!(summary.removed, 1);
+        assert_eq!(
+            fs::read_to_string(pulled.path().join("src/main.rs")).await?,
+            "fn main() { jmp() }"
+        );
+        assert_eq!(
+            fs::read_link(pulled.path().join("main.rs")).await?,
+            Path::new("src/main.rs")
+        );
+        assert!(!fs::try_exists(pulled.path().join("extra.txt")).await?);
+
+        let summary = pull_tree(&store, &cid, pulled.path(), true, &progress).await?;
+        assert_eq!(summary.copied, 0);
+        assert_eq!(summary.unchanged, 5);
+
+        fs::write(pulled.path().join("src/main.rs"), "fn main() { run() }").await?;
+        let summary = pull_tree(&store, &cid, pulled.path(), true, &progress).await?;
+        assert_eq!(summary.copied, 1);
+        assert_eq!(
+            fs::read_to_string(pulled.path().join("src/main.rs")).await?,
+            "fn main() { jmp() }"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,305 @@
+use std::{convert::Infallible, path::PathBuf, str::FromStr};
+
+use getset::Getters;
+use ipldstore::{ipld::cid::Cid, IpldStore};
+use serde::Serialize;
+
+use crate::{
+    filesystem::{Dir, EntityType},
+    management::{find, head},
+    store, utils, FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The entity to list with [`list_dir`] or [`list_tree`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeSource {
+    /// The entity stored at a CID, such as the root of a snapshot, or the entity at a path below
+    /// it. An empty path is the entity at the CID itself.
+    Cid {
+        /// The CID of the entity to start from.
+        cid: Cid,
+
+        /// The path below it.
+        path: String,
+    },
+
+    /// An entity in the current root, relative to it. An empty path is the root itself.
+    Path(String),
+}
+
+/// An entity listed by [`list_dir`] or [`list_tree`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct TreeEntry {
+    /// The name of the entity, or the path it was listed from for the entity listing started at.
+    name: String,
+
+    /// The path of the entity relative to where listing started, empty for the entity listing
+    /// started at.
+    path: String,
+
+    /// How many directories below where listing started the entity is.
+    depth: usize,
+
+    /// The CID of the entity.
+    #[serde(serialize_with = "utils::serialize_display")]
+    cid: Cid,
+
+    /// The type of the entity.
+    #[serde(rename = "type")]
+    entity_type: EntityType,
+
+    /// The size of the content of a file, and zero for other entities.
+    size: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// List the entries of a directory straight from the block store, like `ls`
+///
+/// Nothing needs to be mounted, so this also lists the roots of snapshots and the contents of
+/// stores no server is running for. Listing anything other than a directory lists the entity
+/// itself.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `source` - The entity to list
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management::{self, TreeSource};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let source = TreeSource::Path("sandbox/results".into());
+/// for entry in management::list_dir(Some("mfstest".into()), source).await? {
+///     println!("{}\t{}", entry.get_name(), entry.get_cid());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn list_dir(mount_dir: Option<PathBuf>, source: TreeSource) -> FsResult<Vec<TreeEntry>> {
+    let entries = list_tree(mount_dir, source, Some(1)).await?;
+    if entries.len() == 1 && entries[0].entity_type != EntityType::Dir {
+        return Ok(entries);
+    }
+
+    Ok(entries.into_iter().skip(1).collect())
+}
+
+/// List an entity and every entity below it straight from the block store, like `tree`
+///
+/// Nothing needs to be mounted, so this also lists the roots of snapshots and the contents of
+/// stores no server is running for. The entity comes first, and every directory is followed by
+/// its entries in name order. Symbolic links are not followed.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `source` - The entity to list
+/// * `max_depth` - How many directories down to list at most, or `None` to list everything
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management::{self, TreeSource};
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let source = "bafyreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku".parse()?;
+/// for entry in management::list_tree(Some("mfstest".into()), source, Some(2)).await? {
+///     println!("{}\t{}", entry.get_path(), entry.get_size());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn list_tree(
+    mount_dir: Option<PathBuf>,
+    source: TreeSource,
+    max_depth: Option<usize>,
+) -> FsResult<Vec<TreeEntry>> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let store = paths.open_store().await?;
+
+    let (root, path, name) = match source {
+        TreeSource::Cid { cid, path } => {
+            let name = if path.is_empty() {
+                cid.to_string()
+            } else {
+                format!("{}/{}", cid, path)
+            };
+            (cid, path, name)
+        }
+        TreeSource::Path(path) => {
+            let head = head::checkpoint_head(&paths).await?.ok_or_else(|| {
+                FsError::InvalidOperation(format!(
+                    "filesystem at {} has no recorded state to list",
+                    paths.get_mount_dir().display()
+                ))
+            })?;
+            let name = format!("/{}", path.trim_matches('/'));
+            (head, path, name)
+        }
+    };
+
+    let mut entries = get_tree_entries(&store, &root, &path, max_depth).await?;
+    entries[0].name = name;
+    Ok(entries)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the entries of the entity at `path` below the entity at `root`, and of the entities
+/// below it, down to `max_depth` directories.
+async fn get_tree_entries<S>(
+    store: &S,
+    root: &Cid,
+    path: &str,
+    max_depth: Option<usize>,
+) -> FsResult<Vec<TreeEntry>>
+where
+    S: IpldStore + Send + Sync,
+{
+    let cid = get_path_cid(store, root, path).await?;
+
+    let mut entries = Vec::new();
+    for walked in store::walk_entities(store, &cid, max_depth).await? {
+        let entity = walked.get_entity();
+        let path = walked.get_path().clone();
+        entries.push(TreeEntry {
+            name: path.rsplit('/').next().unwrap_or_default().to_string(),
+            path,
+            depth: *walked.get_depth(),
+            cid: *walked.get_cid(),
+            entity_type: *entity.get_metadata().get_entity_type(),
+            size: entity.get_size().await?,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Returns the CID of the entity at `path` below the entity at `root`, without following symbolic
+/// links.
+///
+/// Only the entries on the way are read from the directories along `path`.
+pub(crate) async fn get_path_cid<S>(store: &S, root: &Cid, path: &str) -> FsResult<Cid>
+where
+    S: IpldStore + Send + Sync,
+{
+    let mut cid = *root;
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        cid = match Dir::load_entry_cid(&cid, store, segment).await {
+            Ok(entry) => entry.ok_or_else(|| FsError::PathNotFound(path.to_string()))?,
+            Err(FsError::NotADirectory(_)) => return Err(FsError::NotADirectory(path.to_string())),
+            Err(e) => return Err(e),
+        };
+    }
+
+    Ok(cid)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl FromStr for TreeSource {
+    type Err = Infallible;
+
+    /// Parses a CID, optionally followed by a path below it, or otherwise a path in the current
+    /// root.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (first, rest) = s.split_once('/').unwrap_or((s, ""));
+        Ok(match Cid::try_from(first) {
+            Ok(cid) => Self::Cid {
+                cid,
+                path: rest.to_string(),
+            },
+            Err(_) => Self::Path(s.to_string()),
+        })
+    }
+}
+
+impl From<Cid> for TreeSource {
+    fn from(cid: Cid) -> Self {
+        Self::Cid {
+            cid,
+            path: String::new(),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::MemoryStore;
+
+    use crate::filesystem::File;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_get_tree_entries() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut root = Dir::new(store.clone());
+        root.find_or_create("dir/sub", false).await?;
+        let file = File::with_content(store.clone(), b"Hello".as_slice()).await?;
+        root.get_dir_mut("dir")
+            .await?
+            .unwrap()
+            .put_adapted_file("file.txt", file)
+            .await?;
+        let cid = root.checkpoint().await?;
+
+        let entries = get_tree_entries(&store, &cid, "/dir", None).await?;
+        let listed = entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.get_path().as_str(),
+                    *entry.get_entity_type(),
+                    *entry.get_size(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            listed,
+            vec![
+                ("", EntityType::Dir, 0),
+                ("file.txt", EntityType::File, 5),
+                ("sub", EntityType::Dir, 0),
+            ]
+        );
+
+        // Paths below a CID are resolved too
+        let source = format!("{}/dir/file.txt", cid).parse::<TreeSource>()?;
+        let TreeSource::Cid {
+            cid: root_cid,
+            path,
+        } = source
+        else {
+            panic!("expected a CID source");
+        };
+        let file_entries = get_tree_entries(&store, &root_cid, &path, None).await?;
+        assert_eq!(file_entries.len(), 1);
+        assert_eq!(file_entries[0].get_cid(), entries[1].get_cid());
+
+        assert!(matches!(
+            get_tree_entries(&store, &cid, "dir/missing", None).await,
+            Err(FsError::PathNotFound(_))
+        ));
+        assert_eq!(
+            "dir/file.txt".parse::<TreeSource>()?,
+            TreeSource::Path("dir/file.txt".to_string())
+        );
+
+        Ok(())
+    }
+}
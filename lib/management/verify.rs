@@ -0,0 +1,461 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use async_recursion::async_recursion;
+use getset::Getters;
+use ipldstore::{ipld::cid::Cid, IpldStore, Storable};
+use serde::Serialize;
+use tokio::fs;
+
+use crate::{
+    filesystem::{Dir, Entity},
+    management::{branch, find, head, pin, snapshot, Job, JobKind, JobProgress, MfsPaths},
+    store::{self, DagWalker, FlatFsStore},
+    utils, FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The outcome of verifying a filesystem's blocks and the DAGs built from them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct VerifyReport {
+    /// The number of block files that were re-hashed.
+    checked_blocks: u64,
+
+    /// The number of blocks reachable from the current root, snapshots and pins.
+    reachable_blocks: u64,
+
+    /// The block files whose data does not match their CID.
+    corrupt_blocks: Vec<CorruptBlock>,
+
+    /// The blocks that are linked to but missing from the blocks directory.
+    missing_blocks: Vec<MissingBlock>,
+
+    /// The paths of the entries removed from the current root by a repair, which were the ones
+    /// leading to damaged blocks.
+    dropped_entries: Vec<String>,
+
+    /// The root recorded by a repair, if the current root had to be repaired.
+    #[serde(serialize_with = "utils::serialize_optional_display")]
+    repaired_root: Option<Cid>,
+}
+
+/// A block file whose data does not hash to the CID it is stored under.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct CorruptBlock {
+    /// The hex digest of the CID the block is stored under, or the keyed hash of it an encrypted
+    /// block is named after.
+    digest: String,
+
+    /// The path of the block file, or of the packfile holding the block.
+    path: PathBuf,
+
+    /// Whether the block is held by a packfile rather than its own file.
+    packed: bool,
+
+    /// Whether the block is reachable, and so damages the filesystem. Unreachable blocks are
+    /// removed by garbage collection.
+    reachable: bool,
+}
+
+/// A block that is linked to but missing from the blocks directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct MissingBlock {
+    /// The CID of the missing block.
+    #[serde(serialize_with = "utils::serialize_display")]
+    cid: Cid,
+
+    /// The block that links to it, or `None` if it is a root.
+    #[serde(serialize_with = "utils::serialize_optional_display")]
+    parent: Option<Cid>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl VerifyReport {
+    /// Returns whether the filesystem is intact, with no reachable block corrupt or missing.
+    pub fn is_ok(&self) -> bool {
+        self.missing_blocks.is_empty() && !self.corrupt_blocks.iter().any(|block| block.reachable)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Verify the blocks of a monofs filesystem and the DAGs built from them, like `fsck`
+///
+/// Every block file in the blocks directory is re-hashed and compared against the CID it is
+/// stored under. Then the DAGs of the current root, the snapshots, the branches and the pins are
+/// walked to find the blocks they link to that are missing. If the filesystem is mounted, its
+/// server checkpoints the in-memory root first. Encrypted blocks that cannot be decrypted are
+/// reported as corrupt, so the encryption key has to be set in the environment.
+///
+/// With `repair`, the entries of the current root that lead to corrupt or missing blocks are
+/// dropped, and the repaired root is recorded as the new head, swapping the root of the running
+/// server if the filesystem is mounted. The reachable corrupt block files are removed so the
+/// same content can be stored again, while packfiles are left as they are. Snapshots, other
+/// branches and pins are never modified. A current root that is itself corrupt or missing cannot be repaired.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `repair` - Whether to drop the references to corrupt and missing blocks from the current root
+///
+/// ## Returns
+/// A summary of the corrupt and missing blocks found, and of what the repair changed
+///
+/// ## Example
+/// ```no_run
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let report = management::verify_mfs(Some("mfstest".into()), false).await?;
+/// if !report.is_ok() {
+///     management::verify_mfs(Some("mfstest".into()), true).await?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn verify_mfs(mount_dir: Option<PathBuf>, repair: bool) -> FsResult<VerifyReport> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    let job = Job::start(
+        paths.fs_db_path(),
+        paths.get_mount_dir(),
+        JobKind::Verify,
+        None,
+    )
+    .await?;
+    let result = verify_blocks(&paths, repair, job.get_progress()).await;
+    job.finish(result).await
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Verifies the blocks of the filesystem at `paths` and the DAGs built from them, repairing its
+/// current root if `repair` is set, and reports the blocks checked and walked to `progress`.
+async fn verify_blocks(
+    paths: &MfsPaths,
+    repair: bool,
+    progress: &JobProgress,
+) -> FsResult<VerifyReport> {
+    // Gather the roots, with the current root first
+    let head = head::checkpoint_head(paths).await?;
+    let mut roots = head.into_iter().collect::<Vec<_>>();
+    roots.extend(
+        snapshot::list_snapshots(Some(paths.get_mount_dir().clone()))
+            .await?
+            .iter()
+            .map(|snapshot| *snapshot.get_root()),
+    );
+    roots.extend(
+        branch::list_branches(Some(paths.get_mount_dir().clone()))
+            .await?
+            .iter()
+            .map(|branch| *branch.get_root()),
+    );
+    roots.extend(
+        pin::list_pins(Some(paths.get_mount_dir().clone()))
+            .await?
+            .iter()
+            .map(|pin| *pin.get_cid()),
+    );
+
+    if roots.is_empty() {
+        return Err(FsError::InvalidOperation(format!(
+            "filesystem at {} has no recorded state to verify",
+            paths.get_mount_dir().display()
+        )));
+    }
+
+    // Re-hash every block file
+    let store = paths.open_store().await?;
+    let mut report = VerifyReport::default();
+    check_blocks(&store, &paths.blocks_dir(), &mut report, progress).await?;
+    let corrupt = report
+        .corrupt_blocks
+        .iter()
+        .map(|block| block.digest.clone())
+        .collect::<HashSet<_>>();
+
+    // Walk the DAGs, without descending into damaged blocks
+    let checked = report.checked_blocks;
+    let dag = DagWalker::new(&store)
+        .with_raw_checks()
+        .walk_with(
+            roots.iter().copied(),
+            |cid| {
+                let names = store.get_block_names(cid);
+                names.iter().any(|name| corrupt.contains(name))
+            },
+            |walked| progress.set_done(checked + walked.get_visited()),
+        )
+        .await?;
+    let parents = dag.get_parents();
+    for cid in dag.get_preorder(roots.iter().copied()) {
+        if dag.get_missing().contains(&cid) {
+            let parent = parents
+                .get(&cid)
+                .and_then(|parents| parents.first())
+                .copied()
+                .filter(|_| !roots.contains(&cid));
+            report.missing_blocks.push(MissingBlock { cid, parent });
+        }
+    }
+
+    let damaged = dag
+        .get_missing()
+        .union(dag.get_skipped())
+        .copied()
+        .collect::<HashSet<_>>();
+    let reachable_corrupt = damaged
+        .iter()
+        .flat_map(|cid| store.get_block_names(cid))
+        .collect::<HashSet<_>>();
+    for block in &mut report.corrupt_blocks {
+        block.reachable = reachable_corrupt.contains(&block.digest);
+    }
+    report.reachable_blocks = dag.len() as u64;
+
+    tracing::info!(
+        "verified {} blocks: {} corrupt, {} missing",
+        report.checked_blocks,
+        report.corrupt_blocks.len(),
+        report.missing_blocks.len()
+    );
+
+    let Some(head) = head.filter(|_| repair && !damaged.is_empty()) else {
+        return Ok(report);
+    };
+
+    if damaged.contains(&head) {
+        return Err(FsError::InvalidOperation(format!(
+            "root {} of filesystem at {} is damaged and cannot be repaired",
+            head,
+            paths.get_mount_dir().display()
+        )));
+    }
+
+    // Every block that links to a damaged block, directly or not, is damaged along with it
+    let mut tainted = HashSet::new();
+    let mut pending = damaged.iter().copied().collect::<Vec<_>>();
+    while let Some(cid) = pending.pop() {
+        if tainted.insert(cid) {
+            pending.extend(parents.get(&cid).into_iter().flatten());
+        }
+    }
+
+    let mut root = Dir::load(&head, store.clone()).await?;
+    drop_damaged_entries(&mut root, "/", &tainted, &mut report.dropped_entries).await?;
+    let repaired_root = root.checkpoint().await?;
+    head::set_head(paths, Some(&head), &repaired_root, "repair").await?;
+    report.repaired_root = Some(repaired_root);
+
+    // Packfiles hold other blocks too, so corrupt packed blocks stay where they are
+    for block in &report.corrupt_blocks {
+        if block.reachable && !block.packed {
+            store.remove_block(&block.path).await?;
+        }
+    }
+
+    tracing::info!(
+        "repaired root of {}: dropped {} entries, new root {}",
+        paths.get_mount_dir().display(),
+        report.dropped_entries.len(),
+        repaired_root
+    );
+
+    Ok(report)
+}
+
+/// Re-hashes the block files under `dir` and the blocks in the store's packfiles, recording
+/// those that are corrupt in `report`.
+///
+/// Block files are named after the hex digest of their CID, or a keyed hash of it if encrypted,
+/// with an extension if compressed or encrypted, and may be nested in subdirectories. Files that
+/// are not named like a block are left alone.
+async fn check_blocks(
+    store: &FlatFsStore,
+    dir: &Path,
+    report: &mut VerifyReport,
+    progress: &JobProgress,
+) -> FsResult<()> {
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                pending.push(entry.path());
+                continue;
+            }
+
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(block_name) = store::get_block_name(&name).filter(|_| file_type.is_file())
+            else {
+                continue;
+            };
+
+            report.checked_blocks += 1;
+            progress.set_done(report.checked_blocks);
+            if !store.verify_block_file(&entry.path()).await? {
+                tracing::warn!("block {} is corrupt", entry.path().display());
+                report.corrupt_blocks.push(CorruptBlock {
+                    digest: block_name.to_string(),
+                    path: entry.path(),
+                    packed: false,
+                    reachable: false,
+                });
+            }
+        }
+    }
+
+    let (corrupt, checked) = store.verify_packed_blocks().await?;
+    report.checked_blocks += checked;
+    progress.set_done(report.checked_blocks);
+    for (digest, pack) in corrupt {
+        tracing::warn!("block {} in {} is corrupt", digest, pack.display());
+        report.corrupt_blocks.push(CorruptBlock {
+            digest,
+            path: pack,
+            packed: true,
+            reachable: false,
+        });
+    }
+
+    Ok(())
+}
+
+/// Removes the entries of `dir` that lead to damaged blocks, recording their paths in `dropped`.
+///
+/// Directories are repaired in place when only some of their entries are damaged. Any other
+/// tainted entity, and directories that are damaged themselves, are dropped whole.
+#[async_recursion]
+async fn drop_damaged_entries(
+    dir: &mut Dir<FlatFsStore>,
+    path: &str,
+    tainted: &HashSet<Cid>,
+    dropped: &mut Vec<String>,
+) -> FsResult<()> {
+    let damaged = dir
+        .get_entries()
+        .filter_map(|(name, link)| {
+            let cid = link.get_cid()?;
+            tainted.contains(cid).then(|| (name.to_string(), *cid))
+        })
+        .collect::<Vec<_>>();
+
+    for (name, cid) in damaged {
+        let entry_path = format!("{}{}", path, name);
+
+        // A directory is only worth keeping if the damage is below its entries
+        if dir.get_store().has(&cid).await {
+            if let Ok(Some(Entity::Dir(subdir))) = dir.get_entity_mut(&name).await {
+                let has_damaged_entries = subdir
+                    .get_entry_links()
+                    .filter_map(|link| link.get_cid())
+                    .any(|cid| tainted.contains(cid));
+                if has_damaged_entries {
+                    let subdir_path = format!("{}/", entry_path);
+                    drop_damaged_entries(subdir, &subdir_path, tainted, dropped).await?;
+                    continue;
+                }
+            }
+        }
+
+        dir.remove_entry(&name)?;
+        tracing::warn!("dropped damaged entry {}", entry_path);
+        dropped.push(entry_path);
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use crate::{filesystem::File, management::FsHead};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_verify_finds_and_repairs_damage() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let mount_dir = fs::canonicalize(temp.path()).await?.join("mnt");
+        let paths = find::helper::setup_test_mfs(&mount_dir).await?;
+
+        // One file to corrupt, one to lose and one to keep, in a subdirectory
+        let store = FlatFsStore::new(paths.blocks_dir());
+        let mut dir = Dir::new(store.clone());
+        let mut contents = Vec::new();
+        for (name, content) in [("corrupt.txt", "corrupt"), ("missing.txt", "missing")] {
+            let file = File::with_content(store.clone(), content.as_bytes()).await?;
+            contents.push(*file.get_content().unwrap());
+            dir.put_adapted_file(name, file).await?;
+        }
+        let file = File::with_content(store.clone(), b"intact".as_slice()).await?;
+        dir.put_adapted_file("intact.txt", file).await?;
+
+        let mut root = Dir::new(store.clone());
+        root.put_adapted_dir("dir", dir).await?;
+        let cid = root.checkpoint().await?;
+        FsHead::new(paths.fs_db_path(), &mount_dir)
+            .await?
+            .set(&cid, "checkpoint")
+            .await?;
+
+        let report = verify_mfs(Some(mount_dir.clone()), false).await?;
+        assert!(report.is_ok());
+        assert!(*report.get_checked_blocks() > 0);
+
+        // Damage the blocks holding the contents of two files
+        let block_path = |cid: &Cid| {
+            let digest = hex::encode(cid.hash().digest());
+            paths.blocks_dir().join(&digest[0..2]).join(digest)
+        };
+        let corrupt_path = block_path(&contents[0]);
+        let mut data = fs::read(&corrupt_path).await?;
+        *data.last_mut().unwrap() ^= 0xff;
+        fs::write(&corrupt_path, data).await?;
+        fs::remove_file(block_path(&contents[1])).await?;
+
+        let report = verify_mfs(Some(mount_dir.clone()), false).await?;
+        assert_eq!(report.get_corrupt_blocks().len(), 1);
+        assert!(*report.get_corrupt_blocks()[0].get_reachable());
+        assert_eq!(report.get_missing_blocks().len(), 1);
+        assert_eq!(report.get_missing_blocks()[0].get_cid(), &contents[1]);
+        assert!(report.get_repaired_root().is_none());
+
+        // Repairing drops the damaged files and keeps the rest
+        let report = verify_mfs(Some(mount_dir.clone()), true).await?;
+        let mut dropped = report.get_dropped_entries().clone();
+        dropped.sort();
+        assert_eq!(dropped, vec!["/dir/corrupt.txt", "/dir/missing.txt"]);
+        assert!(!corrupt_path.exists());
+
+        let repaired_root = report.get_repaired_root().unwrap();
+        let root = Dir::load(&repaired_root, store.clone()).await?;
+        assert!(root.find("dir/intact.txt").await?.is_some());
+        assert!(root.find("dir/corrupt.txt").await?.is_none());
+
+        let report = verify_mfs(Some(mount_dir), false).await?;
+        assert!(report.is_ok());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+use std::{io, path::PathBuf};
+
+use futures::stream::BoxStream;
+
+use crate::{
+    management::find,
+    server::{self, ChangeEvent},
+    FsError, FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Watch a mounted filesystem for changes to entities at or below `path_prefix`
+///
+/// The changes are reported by the filesystem's server as clients make them, from the moment
+/// this returns until the server stops, which ends the stream. Paths are absolute within the
+/// filesystem, so a `path_prefix` of `/` covers every change.
+///
+/// ## Arguments
+/// * `mount_dir` - Optional path to start searching for the filesystem from. If None, uses current directory
+/// * `path_prefix` - The path the changes are reported for
+///
+/// ## Example
+/// ```no_run
+/// use futures::StreamExt;
+/// use monofs::management;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let mut changes = management::watch(Some("mfstest".into()), "/out").await?;
+/// while let Some(event) = changes.next().await {
+///     let event = event?;
+///     println!("{:?} {}", event.get_kind(), event.get_path());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn watch(
+    mount_dir: Option<PathBuf>,
+    path_prefix: impl Into<String>,
+) -> FsResult<BoxStream<'static, FsResult<ChangeEvent>>> {
+    let paths = find::resolve_mfs_paths(mount_dir).await?;
+    match server::watch_changes(paths.control_socket_path(), path_prefix).await {
+        Err(FsError::IoError(e))
+            if matches!(
+                e.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+            ) =>
+        {
+            Err(FsError::NotMounted(
+                paths.get_mount_dir().display().to_string(),
+            ))
+        }
+        result => result,
+    }
+}
@@ -0,0 +1,252 @@
+use std::path::{Path, PathBuf};
+
+use tokio::{fs, net::UnixListener};
+use tokio_stream::wrappers::UnixListenerStream;
+use tonic::{transport::Server, Request, Response, Status};
+
+use crate::{
+    config::QuotaConfig,
+    management::{self, MfsStatus, QuotaUsage},
+    utils::path::GRPC_SOCKET_FILENAME,
+    FsError, FsResult,
+};
+
+use super::proto::{
+    monofs_control_server::{MonofsControl, MonofsControlServer},
+    Quota, SetQuotaRequest, SetQuotaResponse, ShutdownRequest, ShutdownResponse, SnapshotRequest,
+    SnapshotResponse, StatusRequest, StatusResponse, SyncRequest, SyncResponse, Usage,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Serves the `monofs.control.v1.MonofsControl` gRPC service of a supervised filesystem over a
+/// unix socket.
+///
+/// This lets orchestrators control a mount without shelling out to the `monofs` CLI. Every call
+/// is handled by the management function the CLI would call, so calls behave the same whether or
+/// not the server is running at the time.
+#[derive(Debug)]
+pub struct GrpcControlServer {
+    /// The path of the unix socket to listen on.
+    socket_path: PathBuf,
+
+    /// The mount directory identifying the filesystem.
+    mount_dir: PathBuf,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl GrpcControlServer {
+    /// Creates a gRPC control server for the filesystem mounted at `mount_dir`, listening on a
+    /// socket next to the fs database at `fs_db_path`.
+    pub fn new(fs_db_path: impl AsRef<Path>, mount_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            socket_path: grpc_socket_path(fs_db_path.as_ref()),
+            mount_dir: mount_dir.into(),
+        }
+    }
+
+    /// Listens on the socket and serves calls until an error occurs.
+    ///
+    /// A stale socket file left behind by a previous supervisor is replaced.
+    pub async fn serve(self) -> FsResult<()> {
+        if fs::try_exists(&self.socket_path).await? {
+            fs::remove_file(&self.socket_path).await?;
+        }
+
+        let listener = UnixListener::bind(&self.socket_path)?;
+        tracing::info!(
+            "gRPC control API listening at {}",
+            self.socket_path.display()
+        );
+
+        Server::builder()
+            .add_service(MonofsControlServer::new(self))
+            .serve_with_incoming(UnixListenerStream::new(listener))
+            .await
+            .map_err(FsError::custom)
+    }
+
+    /// Returns the mount directory to hand to the management functions.
+    fn mount_dir(&self) -> Option<PathBuf> {
+        Some(self.mount_dir.clone())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the path of the gRPC control socket, which lives next to the fs database.
+pub fn grpc_socket_path(fs_db_path: &Path) -> PathBuf {
+    fs_db_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(GRPC_SOCKET_FILENAME)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Converts a failed management call into the gRPC status reported to the caller.
+fn to_status(error: FsError) -> Status {
+    let message = error.to_string();
+    match error {
+        FsError::SnapshotExists(_) | FsError::AlreadyMounted(_) => Status::already_exists(message),
+        FsError::SnapshotNotFound(_)
+        | FsError::PathNotFound(_)
+        | FsError::FilesystemNotRegistered(_)
+        | FsError::NoMfsRootFound(_)
+        | FsError::MfsDataDirNotFound(_) => Status::not_found(message),
+        FsError::InvalidOperation(_) | FsError::NotMounted(_) => {
+            Status::failed_precondition(message)
+        }
+        FsError::ShutdownTimedOut(_) => Status::deadline_exceeded(message),
+        _ => Status::internal(message),
+    }
+}
+
+/// Converts the status of a filesystem into its gRPC message.
+fn status_to_proto(status: MfsStatus) -> StatusResponse {
+    StatusResponse {
+        mount_dir: status.get_mount_dir().to_string_lossy().to_string(),
+        mounted: *status.get_mounted(),
+        port: *status.get_port(),
+        supervisor_pid: *status.get_supervisor_pid(),
+        root_cid: status.get_root_cid().map(|cid| cid.to_string()),
+        branch: status.get_branch().clone(),
+        block_count: *status.get_block_count(),
+        store_size: *status.get_store_size(),
+        quota: Some(quota_to_proto(status.get_quota())),
+        usage: status.get_usage().as_ref().map(usage_to_proto),
+    }
+}
+
+/// Converts a quota into its gRPC message.
+fn quota_to_proto(quota: &QuotaConfig) -> Quota {
+    Quota {
+        max_logical_bytes: *quota.get_max_logical_bytes(),
+        max_store_bytes: *quota.get_max_store_bytes(),
+    }
+}
+
+/// Converts a quota's gRPC message back into a quota. A missing message does not limit anything.
+fn quota_from_proto(quota: Option<Quota>) -> QuotaConfig {
+    let quota = quota.unwrap_or_default();
+    QuotaConfig::builder()
+        .max_logical_bytes(quota.max_logical_bytes)
+        .max_store_bytes(quota.max_store_bytes)
+        .build()
+}
+
+/// Converts a filesystem's usage into its gRPC message.
+fn usage_to_proto(usage: &QuotaUsage) -> Usage {
+    Usage {
+        logical_bytes: *usage.get_logical_bytes(),
+        store_bytes: *usage.get_store_bytes(),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+#[tonic::async_trait]
+impl MonofsControl for GrpcControlServer {
+    async fn status(
+        &self,
+        _request: Request<StatusRequest>,
+    ) -> Result<Response<StatusResponse>, Status> {
+        let status = management::status_mfs(self.mount_dir())
+            .await
+            .map_err(to_status)?;
+
+        Ok(Response::new(status_to_proto(status)))
+    }
+
+    async fn sync(&self, _request: Request<SyncRequest>) -> Result<Response<SyncResponse>, Status> {
+        let root = management::sync_mfs(self.mount_dir())
+            .await
+            .map_err(to_status)?;
+
+        Ok(Response::new(SyncResponse {
+            root_cid: root.map(|cid| cid.to_string()),
+        }))
+    }
+
+    async fn snapshot(
+        &self,
+        request: Request<SnapshotRequest>,
+    ) -> Result<Response<SnapshotResponse>, Status> {
+        let name = request.into_inner().name;
+        let snapshot = management::snapshot_mfs(self.mount_dir(), name)
+            .await
+            .map_err(to_status)?;
+
+        Ok(Response::new(SnapshotResponse {
+            name: snapshot.get_name().clone(),
+            root_cid: snapshot.get_root().to_string(),
+            created_at: snapshot.get_created_at().to_rfc3339(),
+        }))
+    }
+
+    async fn set_quota(
+        &self,
+        request: Request<SetQuotaRequest>,
+    ) -> Result<Response<SetQuotaResponse>, Status> {
+        let quota = quota_from_proto(request.into_inner().quota);
+        let applied = management::set_quota(self.mount_dir(), quota)
+            .await
+            .map_err(to_status)?;
+
+        Ok(Response::new(SetQuotaResponse { applied }))
+    }
+
+    async fn shutdown(
+        &self,
+        request: Request<ShutdownRequest>,
+    ) -> Result<Response<ShutdownResponse>, Status> {
+        // Detaching signals this very supervisor to stop, so it runs on its own and the call
+        // returns before the supervisor exits
+        let force = request.into_inner().force;
+        let mount_dir = self.mount_dir();
+        tokio::spawn(async move {
+            if let Err(e) = management::detach_mfs(mount_dir, force).await {
+                tracing::error!(error = %e, "failed to detach the filesystem");
+            }
+        });
+
+        Ok(Response::new(ShutdownResponse {}))
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grpc_quota_conversion() {
+        let quota = QuotaConfig::builder().max_logical_bytes(Some(100)).build();
+        let message = quota_to_proto(&quota);
+        assert_eq!(message.max_logical_bytes, Some(100));
+        assert_eq!(message.max_store_bytes, None);
+        assert_eq!(quota_from_proto(Some(message)), quota);
+
+        // A call without a quota lifts the limits
+        assert_eq!(quota_from_proto(None), QuotaConfig::unlimited());
+
+        assert_eq!(
+            to_status(FsError::SnapshotExists("nightly".to_string())).code(),
+            tonic::Code::AlreadyExists
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use std::{path::Path, time::Duration};
+
+use nix::{
+    sys::signal::{self, Signal},
+    unistd::Pid,
+};
+use sqlx::{Pool, Row, Sqlite};
+use tokio::{net::TcpStream, task::JoinHandle, time};
+
+use crate::FsResult;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How often the supervisor probes the server's port.
+pub const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a probe may take before it counts as failed.
+pub const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How many probes in a row may fail before the server is considered hung and killed.
+pub const MAX_FAILED_HEALTH_CHECKS: u32 = 3;
+
+/// How long the supervisor waits before restarting a server for the first time.
+pub const INITIAL_RESTART_BACKOFF: Duration = Duration::from_millis(500);
+
+/// The longest the supervisor waits before restarting a server.
+pub const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How long a server must run before its next restart starts over at the initial backoff.
+pub const STABLE_RUN_DURATION: Duration = Duration::from_secs(120);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Exponential backoff between restarts of a supervised server.
+///
+/// The delay doubles with every restart of a server that keeps dying quickly, up to
+/// [`MAX_RESTART_BACKOFF`], and starts over once a server has run for [`STABLE_RUN_DURATION`].
+#[derive(Debug, Clone)]
+pub struct RestartBackoff {
+    /// The delay before the next restart.
+    next: Duration,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl RestartBackoff {
+    /// Creates a backoff that starts at [`INITIAL_RESTART_BACKOFF`].
+    pub fn new() -> Self {
+        Self {
+            next: INITIAL_RESTART_BACKOFF,
+        }
+    }
+
+    /// Returns how long to wait before restarting a server that ran for `uptime`.
+    pub fn next_delay(&mut self, uptime: Duration) -> Duration {
+        if uptime >= STABLE_RUN_DURATION {
+            self.next = INITIAL_RESTART_BACKOFF;
+        }
+
+        let delay = self.next;
+        self.next = (self.next * 2).min(MAX_RESTART_BACKOFF);
+        delay
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Checks whether a server accepts connections on `host:port` within [`HEALTH_CHECK_TIMEOUT`].
+///
+/// ## Arguments
+/// * `host` - The address to connect to
+/// * `port` - The port to connect to
+pub async fn probe_server(host: &str, port: u32) -> bool {
+    let addr = format!("{}:{}", host, port);
+    matches!(
+        time::timeout(HEALTH_CHECK_TIMEOUT, TcpStream::connect(&addr)).await,
+        Ok(Ok(_))
+    )
+}
+
+/// Records that the supervisor restarted the server of the filesystem mounted at `mount_dir`.
+///
+/// ## Arguments
+/// * `fs_db` - The filesystem database
+/// * `mount_dir` - The mount directory identifying the filesystem
+/// * `uptime` - How long the server ran before it stopped
+/// * `backoff` - How long the supervisor waited before restarting it
+pub async fn record_restart(
+    fs_db: &Pool<Sqlite>,
+    mount_dir: &Path,
+    uptime: Duration,
+    backoff: Duration,
+) -> FsResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO server_restarts (fs_id, uptime_ms, backoff_ms)
+        SELECT id, ?, ? FROM filesystems WHERE mount_dir = ?
+        "#,
+    )
+    .bind(uptime.as_millis() as i64)
+    .bind(backoff.as_millis() as i64)
+    .bind(mount_dir.to_string_lossy().to_string())
+    .execute(fs_db)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns how many times the supervisor restarted the server of the filesystem mounted at
+/// `mount_dir`.
+///
+/// ## Arguments
+/// * `fs_db` - The filesystem database
+/// * `mount_dir` - The mount directory identifying the filesystem
+pub async fn count_restarts(fs_db: &Pool<Sqlite>, mount_dir: &Path) -> FsResult<u64> {
+    let row = sqlx::query(
+        r#"
+        SELECT COUNT(*) AS restarts FROM server_restarts
+        JOIN filesystems ON filesystems.id = server_restarts.fs_id
+        WHERE filesystems.mount_dir = ?
+        "#,
+    )
+    .bind(mount_dir.to_string_lossy().to_string())
+    .fetch_one(fs_db)
+    .await?;
+
+    Ok(row.get::<i64, _>("restarts") as u64)
+}
+
+/// Spawns a task that probes the server with process id `pid` every [`HEALTH_CHECK_INTERVAL`].
+///
+/// A server that fails [`MAX_FAILED_HEALTH_CHECKS`] probes in a row is killed, so that the
+/// supervisor notices it is gone and restarts it. The task ends when the server is killed, and
+/// should be aborted when the server stops on its own.
+pub(crate) fn spawn_health_check(pid: u32, host: String, port: u32) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut failures = 0;
+        let mut interval = time::interval(HEALTH_CHECK_INTERVAL);
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+
+        // Give the server one interval to start listening
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if probe_server(&host, port).await {
+                failures = 0;
+                continue;
+            }
+
+            failures += 1;
+            tracing::warn!(
+                pid = pid,
+                "server is not accepting connections on {}:{} ({}/{} failed checks)",
+                host,
+                port,
+                failures,
+                MAX_FAILED_HEALTH_CHECKS
+            );
+
+            if failures >= MAX_FAILED_HEALTH_CHECKS {
+                tracing::error!(pid = pid, "server is unresponsive, killing it");
+                if let Err(e) = signal::kill(Pid::from_raw(pid as i32), Signal::SIGKILL) {
+                    tracing::error!(pid = pid, error = %e, "failed to kill unresponsive server");
+                }
+                break;
+            }
+        }
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Default for RestartBackoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn test_restart_backoff_doubles_and_resets() {
+        let mut backoff = RestartBackoff::new();
+        let quick = Duration::from_secs(1);
+
+        assert_eq!(backoff.next_delay(quick), INITIAL_RESTART_BACKOFF);
+        assert_eq!(backoff.next_delay(quick), INITIAL_RESTART_BACKOFF * 2);
+        assert_eq!(backoff.next_delay(quick), INITIAL_RESTART_BACKOFF * 4);
+
+        for _ in 0..20 {
+            backoff.next_delay(quick);
+        }
+        assert_eq!(backoff.next_delay(quick), MAX_RESTART_BACKOFF);
+
+        // A server that ran for a while starts over
+        assert_eq!(
+            backoff.next_delay(STABLE_RUN_DURATION),
+            INITIAL_RESTART_BACKOFF
+        );
+    }
+
+    #[tokio::test]
+    async fn test_probe_server() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port() as u32;
+        assert!(probe_server("127.0.0.1", port).await);
+
+        drop(listener);
+        assert!(!probe_server("127.0.0.1", port).await);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use tokio::{sync::watch, time};
+
+use crate::{
+    config::LogRotationConfig,
+    management::{self, FsLogRotation},
+    FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How often the log rotator reads its settings again and sweeps the rotated logs.
+const LOG_ROTATION_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Compresses and expires the rotated logs of a supervised filesystem in the background, as its
+/// log rotation settings ask.
+///
+/// The settings are read from the fs database every minute and passed on to the
+/// [`RotatingLogFile`]s subscribed to the rotator, so changes apply without restarting the
+/// supervisor.
+#[derive(Debug)]
+pub struct LogRotator {
+    /// The directory holding the logs.
+    log_dir: PathBuf,
+
+    /// The log rotation record of the filesystem.
+    record: FsLogRotation,
+
+    /// The current settings, shared with the log files.
+    config: watch::Sender<LogRotationConfig>,
+}
+
+/// A log file that is rotated once it grows past the size or the age its settings allow.
+///
+/// The log is rotated by renaming it with the time of the rotation and creating a new one at its
+/// path, so readers following the log move on to the new one. Clones write to the same file.
+#[derive(Debug, Clone)]
+pub struct RotatingLogFile {
+    /// The state of the log, shared by the clones.
+    state: Arc<Mutex<LogFileState>>,
+}
+
+/// The state of a [`RotatingLogFile`].
+#[derive(Debug)]
+struct LogFileState {
+    /// The path of the current log.
+    path: PathBuf,
+
+    /// The current log.
+    file: File,
+
+    /// The number of bytes in the current log.
+    size: u64,
+
+    /// When the current log was opened.
+    opened_at: Instant,
+
+    /// The log rotation settings.
+    config: watch::Receiver<LogRotationConfig>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl LogRotator {
+    /// Creates a log rotator for the logs in `log_dir` of the filesystem mounted at `mount_dir`,
+    /// whose log rotation settings are recorded in the database at `fs_db_path`.
+    pub async fn new(
+        fs_db_path: impl AsRef<Path>,
+        mount_dir: impl Into<PathBuf>,
+        log_dir: impl Into<PathBuf>,
+    ) -> FsResult<Self> {
+        let record = FsLogRotation::new(fs_db_path, mount_dir).await?;
+        let (config, _) = watch::channel(record.get().await?.unwrap_or_default());
+        Ok(Self {
+            log_dir: log_dir.into(),
+            record,
+            config,
+        })
+    }
+
+    /// Returns a receiver of the current log rotation settings, for a [`RotatingLogFile`].
+    pub fn subscribe(&self) -> watch::Receiver<LogRotationConfig> {
+        self.config.subscribe()
+    }
+
+    /// Sweeps the rotated logs as the settings ask, for as long as the supervisor runs.
+    pub async fn run(self) {
+        let mut interval = time::interval(LOG_ROTATION_CHECK_INTERVAL);
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            match self.record.get().await {
+                Ok(config) => {
+                    self.config.send_replace(config.unwrap_or_default());
+                }
+                Err(e) => tracing::error!(error = %e, "failed to read log rotation settings"),
+            }
+
+            let config = *self.config.borrow();
+            match management::sweep_logs(&self.log_dir, &config).await {
+                Ok(sweep) => tracing::debug!(
+                    "compressed {} and removed {} rotated logs",
+                    sweep.get_compressed_files(),
+                    sweep.get_removed_files()
+                ),
+                Err(e) => tracing::error!(error = %e, "failed to sweep rotated logs"),
+            }
+        }
+    }
+}
+
+impl RotatingLogFile {
+    /// Opens the log at `path` for appending, creating it if it does not exist, to be rotated as
+    /// `config` asks.
+    pub fn open(
+        path: impl Into<PathBuf>,
+        config: watch::Receiver<LogRotationConfig>,
+    ) -> io::Result<Self> {
+        let path = path.into();
+        let file = open_log(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            state: Arc::new(Mutex::new(LogFileState {
+                path,
+                file,
+                size,
+                opened_at: Instant::now(),
+                config,
+            })),
+        })
+    }
+}
+
+impl LogFileState {
+    /// Returns whether the current log holds anything and grew past the size or the age allowed.
+    fn is_rotation_due(&self) -> bool {
+        let config = *self.config.borrow();
+        self.size > 0
+            && (self.size >= *config.get_max_bytes()
+                || config
+                    .get_max_age()
+                    .is_some_and(|max_age| self.opened_at.elapsed() >= max_age))
+    }
+
+    /// Renames the current log away and starts a new one at its path.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let rotated_path = management::get_rotated_log_path(&self.path, SystemTime::now());
+        fs::rename(&self.path, rotated_path)?;
+
+        self.file = open_log(&self.path)?;
+        self.size = 0;
+        self.opened_at = Instant::now();
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Opens the log at `path` for appending, creating it if it does not exist.
+fn open_log(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Write for RotatingLogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        if state.is_rotation_due() {
+            state.rotate()?;
+        }
+
+        let written = state.file.write(buf)?;
+        state.size += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.state.lock().unwrap().file.flush()
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_rotating_log_file() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("mfsrun-mnt-1-100.log");
+        let config = LogRotationConfig::builder().max_bytes(8).build();
+        let (sender, receiver) = watch::channel(config);
+
+        // The log is rotated on the first write past its size
+        let mut log = RotatingLogFile::open(&path, receiver)?;
+        log.write_all(b"one two\n")?;
+        log.write_all(b"three\n")?;
+        log.flush()?;
+        assert_eq!(fs::read_to_string(&path)?, "three\n");
+
+        let rotated = fs::read_dir(temp_dir.path())?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|rotated| rotated != &path)
+            .collect::<Vec<_>>();
+        assert_eq!(rotated.len(), 1);
+        assert_eq!(fs::read_to_string(&rotated[0])?, "one two\n");
+
+        // Changed settings apply to the next write
+        sender.send_replace(LogRotationConfig::builder().max_bytes(1024).build());
+        log.write_all(b"four\n")?;
+        log.flush()?;
+        assert_eq!(fs::read_to_string(&path)?, "three\nfour\n");
+
+        Ok(())
+    }
+}
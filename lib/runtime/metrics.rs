@@ -0,0 +1,452 @@
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use getset::Getters;
+use sqlx::{Pool, Sqlite};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    config::MountBackend,
+    management,
+    runtime::health,
+    server::{self, ControlRequest, ControlResponse, MetricsSnapshot},
+    FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The path the metrics are served on.
+pub const METRICS_PATH: &str = "/metrics";
+
+/// The content type of the Prometheus text exposition format.
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Serves the metrics of a supervised filesystem over HTTP, in the Prometheus text format.
+///
+/// The metrics of the server are fetched over its control socket on every scrape, so they count
+/// up from when the server last started. The size of the store and the number of times the
+/// server was restarted are read from disk by the supervisor itself, so they are reported even
+/// while the server is down.
+#[derive(Debug)]
+pub struct MetricsServer {
+    /// The address to listen on, as `host:port`.
+    addr: String,
+
+    /// The filesystem database, holding the server's restarts.
+    fs_db: Pool<Sqlite>,
+
+    /// The control socket of the supervised server.
+    socket_path: PathBuf,
+
+    /// The mount directory identifying the filesystem.
+    mount_dir: PathBuf,
+
+    /// The directory holding the filesystem's blocks.
+    store_dir: PathBuf,
+
+    /// The mechanism the filesystem is mounted with.
+    backend: MountBackend,
+}
+
+/// The metrics of a supervised filesystem, as collected for a single scrape.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct FsMetrics {
+    /// The mount directory identifying the filesystem.
+    mount_dir: PathBuf,
+
+    /// The mechanism the filesystem is mounted with.
+    backend: MountBackend,
+
+    /// The metrics of the server, or `None` if it did not answer.
+    server: Option<MetricsSnapshot>,
+
+    /// The number of blocks in the store.
+    store_blocks: u64,
+
+    /// The bytes the blocks take up on disk.
+    store_bytes: u64,
+
+    /// How many times the supervisor restarted the server.
+    restarts: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl MetricsServer {
+    /// Creates a metrics server for the filesystem mounted at `mount_dir`, listening on `addr`.
+    ///
+    /// ## Arguments
+    /// * `addr` - The address to listen on, as `host:port`
+    /// * `fs_db_path` - The filesystem database, next to which the server's control socket lives
+    /// * `mount_dir` - The mount directory identifying the filesystem
+    /// * `store_dir` - The directory holding the filesystem's blocks
+    /// * `backend` - The mechanism the filesystem is mounted with
+    pub async fn new(
+        addr: impl Into<String>,
+        fs_db_path: impl AsRef<Path>,
+        mount_dir: impl Into<PathBuf>,
+        store_dir: impl Into<PathBuf>,
+        backend: MountBackend,
+    ) -> FsResult<Self> {
+        let fs_db_path = fs_db_path.as_ref();
+        Ok(Self {
+            addr: addr.into(),
+            fs_db: management::get_db_pool(fs_db_path).await?,
+            socket_path: server::control_socket_path(fs_db_path),
+            mount_dir: mount_dir.into(),
+            store_dir: store_dir.into(),
+            backend,
+        })
+    }
+
+    /// Listens on the configured address and answers scrapes until an error occurs.
+    pub async fn serve(self) -> FsResult<()> {
+        let listener = TcpListener::bind(&self.addr).await?;
+        tracing::info!("serving metrics at http://{}{}", self.addr, METRICS_PATH);
+
+        let server = Arc::new(self);
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = server.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.handle_connection(stream).await {
+                    tracing::warn!(error = %e, "metrics connection failed");
+                }
+            });
+        }
+    }
+
+    /// Collects the metrics of the filesystem.
+    ///
+    /// A server that is not running, or fails to answer, is reported as down rather than failing
+    /// the whole collection.
+    pub async fn collect(&self) -> FsResult<FsMetrics> {
+        let server =
+            match server::try_send_control_request(&self.socket_path, &ControlRequest::Metrics)
+                .await
+            {
+                Ok(Some(ControlResponse::Metrics { metrics })) => Some(metrics),
+                Ok(Some(response)) => {
+                    tracing::warn!("unexpected response to a metrics request: {:?}", response);
+                    None
+                }
+                Ok(None) => None,
+                Err(e) => {
+                    tracing::warn!(error = %e, "failed to fetch the server's metrics");
+                    None
+                }
+            };
+
+        let (store_blocks, store_bytes) = management::get_blocks_usage(&self.store_dir).await?;
+        let restarts = health::count_restarts(&self.fs_db, &self.mount_dir).await?;
+
+        Ok(FsMetrics {
+            mount_dir: self.mount_dir.clone(),
+            backend: self.backend,
+            server,
+            store_blocks,
+            store_bytes,
+            restarts,
+        })
+    }
+
+    /// Answers a single HTTP request, serving the metrics on [`METRICS_PATH`] and nothing else.
+    async fn handle_connection(&self, stream: TcpStream) -> FsResult<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        // Only the request line matters, the headers are read and ignored
+        let Some(request_line) = lines.next_line().await? else {
+            return Ok(());
+        };
+        while let Some(header) = lines.next_line().await? {
+            if header.is_empty() {
+                break;
+            }
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default();
+        let path = parts.next().unwrap_or_default();
+        let path = path.split_once('?').map_or(path, |(path, _)| path);
+
+        let (status, body) = match (method, path) {
+            ("GET", METRICS_PATH) => match self.collect().await {
+                Ok(metrics) => ("200 OK", metrics.to_prometheus()),
+                Err(e) => {
+                    tracing::error!(error = %e, "failed to collect metrics");
+                    ("500 Internal Server Error", format!("{}\n", e))
+                }
+            },
+            ("GET", _) => ("404 Not Found", "not found\n".to_string()),
+            _ => ("405 Method Not Allowed", "method not allowed\n".to_string()),
+        };
+
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            METRICS_CONTENT_TYPE,
+            body.len(),
+            body
+        );
+        writer.write_all(response.as_bytes()).await?;
+        writer.shutdown().await?;
+
+        Ok(())
+    }
+}
+
+impl FsMetrics {
+    /// Renders the metrics in the Prometheus text exposition format.
+    ///
+    /// The filesystem is identified by the `monofs_info` metric, which carries its mount
+    /// directory and backend as labels. The other metrics only carry the labels that tell their
+    /// series apart, so scraping many supervisors keeps them apart by the scrape target.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        write_header(
+            &mut out,
+            "monofs_info",
+            "gauge",
+            "The supervised filesystem.",
+        );
+        let _ = writeln!(
+            out,
+            "monofs_info{{mount_dir=\"{}\",backend=\"{}\"}} 1",
+            escape_label(&self.mount_dir.to_string_lossy()),
+            self.backend
+        );
+
+        write_header(
+            &mut out,
+            "monofs_server_up",
+            "gauge",
+            "Whether the server answered the supervisor.",
+        );
+        let _ = writeln!(out, "monofs_server_up {}", u8::from(self.server.is_some()));
+
+        write_header(
+            &mut out,
+            "monofs_server_restarts_total",
+            "counter",
+            "How many times the supervisor restarted the server.",
+        );
+        let _ = writeln!(out, "monofs_server_restarts_total {}", self.restarts);
+
+        write_header(
+            &mut out,
+            "monofs_store_blocks",
+            "gauge",
+            "The number of blocks in the store.",
+        );
+        let _ = writeln!(out, "monofs_store_blocks {}", self.store_blocks);
+
+        write_header(
+            &mut out,
+            "monofs_store_bytes",
+            "gauge",
+            "The bytes the blocks take up on disk.",
+        );
+        let _ = writeln!(out, "monofs_store_bytes {}", self.store_bytes);
+
+        let Some(server) = &self.server else {
+            return out;
+        };
+
+        write_header(
+            &mut out,
+            "monofs_nfs_ops_total",
+            "counter",
+            "How many operations the server served.",
+        );
+        for stats in server.get_ops() {
+            let _ = writeln!(
+                out,
+                "monofs_nfs_ops_total{{op=\"{}\"}} {}",
+                stats.get_op(),
+                stats.get_count()
+            );
+        }
+
+        write_header(
+            &mut out,
+            "monofs_nfs_op_duration_seconds",
+            "summary",
+            "How long the server took to serve operations.",
+        );
+        for stats in server.get_ops() {
+            let _ = writeln!(
+                out,
+                "monofs_nfs_op_duration_seconds_sum{{op=\"{}\"}} {:.6}",
+                stats.get_op(),
+                *stats.get_duration_micros() as f64 / 1_000_000.0
+            );
+            let _ = writeln!(
+                out,
+                "monofs_nfs_op_duration_seconds_count{{op=\"{}\"}} {}",
+                stats.get_op(),
+                stats.get_count()
+            );
+        }
+
+        write_header(
+            &mut out,
+            "monofs_cache_hits_total",
+            "counter",
+            "How many requests were answered without waiting on the block store.",
+        );
+        let _ = writeln!(
+            out,
+            "monofs_cache_hits_total{{cache=\"lookup\"}} {}",
+            server.get_lookup_cache_hits()
+        );
+        let _ = writeln!(
+            out,
+            "monofs_cache_hits_total{{cache=\"read_ahead\"}} {}",
+            server.get_read_ahead_hits()
+        );
+
+        write_header(
+            &mut out,
+            "monofs_cache_misses_total",
+            "counter",
+            "How many requests had to wait on the block store.",
+        );
+        let _ = writeln!(
+            out,
+            "monofs_cache_misses_total{{cache=\"lookup\"}} {}",
+            server.get_lookup_cache_misses()
+        );
+        let _ = writeln!(
+            out,
+            "monofs_cache_misses_total{{cache=\"read_ahead\"}} {}",
+            server.get_read_ahead_misses()
+        );
+
+        write_header(
+            &mut out,
+            "monofs_root_updates_total",
+            "counter",
+            "How many times the root was checkpointed or replaced by a stored one.",
+        );
+        let _ = writeln!(
+            out,
+            "monofs_root_updates_total{{kind=\"checkpoint\"}} {}",
+            server.get_checkpoints()
+        );
+        let _ = writeln!(
+            out,
+            "monofs_root_updates_total{{kind=\"restore\"}} {}",
+            server.get_root_restores()
+        );
+
+        out
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Writes the `HELP` and `TYPE` lines of the metric `name`.
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Escapes `value` for use as a label value.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+    use tokio::io::AsyncReadExt;
+
+    use crate::management::FS_DB_MIGRATOR;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_metrics_server() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let fs_db_path = temp_dir.path().join("fs.db");
+        management::init_db(&fs_db_path, &FS_DB_MIGRATOR).await?;
+
+        let store_dir = temp_dir.path().join("blocks");
+        tokio::fs::create_dir_all(&store_dir).await?;
+        tokio::fs::write(store_dir.join("block"), b"content").await?;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?.to_string();
+        drop(listener);
+
+        let server = MetricsServer::new(
+            &addr,
+            &fs_db_path,
+            temp_dir.path().join("mnt \"quoted\""),
+            &store_dir,
+            MountBackend::Nfs,
+        )
+        .await?;
+
+        // Without a running server, only what the supervisor reads from disk is reported
+        let metrics = server.collect().await?;
+        assert_eq!(metrics.server, None);
+        assert_eq!((metrics.store_blocks, metrics.store_bytes), (1, 7));
+        let text = metrics.to_prometheus();
+        assert!(text.contains("monofs_server_up 0\n"));
+        assert!(text.contains("monofs_store_bytes 7\n"));
+        assert!(text.contains("mnt \\\"quoted\\\""));
+        assert!(!text.contains("monofs_nfs_ops_total"));
+
+        // The metrics of a running server are reported per operation
+        let metrics = FsMetrics {
+            server: Some(MetricsSnapshot::default()),
+            ..metrics
+        };
+        assert!(metrics.to_prometheus().contains("monofs_server_up 1\n"));
+
+        // Scrapes are answered over HTTP
+        tokio::spawn(server.serve());
+        let mut stream = loop {
+            match TcpStream::connect(&addr).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        };
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("monofs_server_restarts_total 0\n"));
+
+        Ok(())
+    }
+}
@@ -1,9 +1,28 @@
 //! Runtime components for the Monofs filesystem.
 
+#[cfg(feature = "grpc")]
+mod grpc;
+mod health;
+mod logrotator;
+mod metrics;
 mod monitor;
+mod scheduler;
+mod scrubber;
+mod telemetry;
+
+#[cfg(feature = "grpc")]
+pub mod proto;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
 //--------------------------------------------------------------------------------------------------
 
+#[cfg(feature = "grpc")]
+pub use grpc::*;
+pub use health::*;
+pub use logrotator::*;
+pub use metrics::*;
 pub use monitor::*;
+pub use scheduler::*;
+pub use scrubber::*;
+pub use telemetry::*;
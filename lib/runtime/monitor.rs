@@ -6,13 +6,18 @@ use std::{
 
 use async_trait::async_trait;
 use microsandbox_utils::{
-    ChildIo, MicrosandboxUtilsError, MicrosandboxUtilsResult, ProcessMonitor, RotatingLog,
-    LOG_SUFFIX,
+    ChildIo, MicrosandboxUtilsError, MicrosandboxUtilsResult, ProcessMonitor, LOG_SUFFIX,
 };
 use sqlx::{Pool, Sqlite};
-use tokio::io::AsyncReadExt;
+use tokio::{io::AsyncReadExt, sync::watch, task::JoinHandle};
 
-use crate::{management, utils::MFSRUN_LOG_PREFIX, FsError, FsResult};
+use crate::{
+    config::{LogRotationConfig, MountBackend},
+    management,
+    runtime::{health, RotatingLogFile},
+    utils::MFSRUN_LOG_PREFIX,
+    FsError, FsResult,
+};
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -37,6 +42,24 @@ pub struct NfsServerMonitor {
 
     /// The log path
     log_path: Option<PathBuf>,
+
+    /// The settings the log is rotated with
+    log_rotation: watch::Receiver<LogRotationConfig>,
+
+    /// The mount backend served by the child process
+    backend: MountBackend,
+
+    /// The port the child process listens on, if it serves over the network
+    port: Option<u32>,
+
+    /// The address the supervisor serves metrics on, if any
+    metrics_addr: Option<String>,
+
+    /// The address to probe the child process on, if its health should be checked
+    health_check_host: Option<String>,
+
+    /// The task probing the child process
+    health_check: Option<JoinHandle<()>>,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -51,6 +74,8 @@ impl NfsServerMonitor {
         name: String,
         mount_dir: impl Into<PathBuf>,
         log_dir: impl Into<PathBuf>,
+        backend: MountBackend,
+        port: Option<u32>,
     ) -> FsResult<Self> {
         Ok(Self {
             fs_db: management::get_db_pool(fs_db_path.as_ref()).await?,
@@ -59,9 +84,38 @@ impl NfsServerMonitor {
             mount_dir: mount_dir.into(),
             log_dir: log_dir.into(),
             log_path: None,
+            log_rotation: watch::channel(LogRotationConfig::default()).1,
+            backend,
+            port,
+            metrics_addr: None,
+            health_check_host: None,
+            health_check: None,
         })
     }
 
+    /// Probe the child process on `host` while it runs and kill it if it stops accepting
+    /// connections, so that the supervisor restarts it.
+    ///
+    /// Only a child that listens on a port is probed.
+    pub fn with_health_check(mut self, host: impl Into<String>) -> Self {
+        self.health_check_host = Some(host.into());
+        self
+    }
+
+    /// Record `metrics_addr` as where the supervisor serves metrics, so that they are served
+    /// there again when the filesystem is attached.
+    pub fn with_metrics_addr(mut self, metrics_addr: Option<String>) -> Self {
+        self.metrics_addr = metrics_addr;
+        self
+    }
+
+    /// Rotate the log of the child process with the settings `log_rotation` receives, rather than
+    /// with the defaults.
+    pub fn with_log_rotation(mut self, log_rotation: watch::Receiver<LogRotationConfig>) -> Self {
+        self.log_rotation = log_rotation;
+        self
+    }
+
     /// Generates a unique log name using name, process ID, and current timestamp.
     ///
     /// The ID format is: "mfsrun-{name}-{timestamp}-{child_pid}.log"
@@ -93,27 +147,61 @@ impl ProcessMonitor for NfsServerMonitor {
         let log_name = self.generate_log_name(pid);
         let log_path = self.log_dir.join(&log_name);
 
-        let nfs_server_log = RotatingLog::new(&log_path).await?;
-        let mut stdout_writer = nfs_server_log.get_sync_writer();
-        let mut stderr_writer = nfs_server_log.get_sync_writer();
+        let nfs_server_log = RotatingLogFile::open(&log_path, self.log_rotation.clone())
+            .map_err(MicrosandboxUtilsError::custom)?;
+        let mut stdout_writer = nfs_server_log.clone();
+        let mut stderr_writer = nfs_server_log;
 
         self.log_path = Some(log_path);
 
-        // Insert filesystem entry into fs_db
-        sqlx::query(
+        // Record the running processes on the filesystem's entry, creating it on first start.
+        // The entry outlives the processes because it also holds the filesystem's head.
+        let mount_dir = self.mount_dir.to_string_lossy().to_string();
+        let result = sqlx::query(
             r#"
-            INSERT INTO filesystems (name, mount_dir, supervisor_pid, nfsserver_pid)
-            VALUES (?, ?, ?, ?)
+            UPDATE filesystems
+            SET name = ?, supervisor_pid = ?, nfsserver_pid = ?, backend = ?, port = ?,
+                metrics_addr = ?, modified_at = CURRENT_TIMESTAMP
+            WHERE mount_dir = ?
             "#,
         )
         .bind(&self.name)
-        .bind(self.mount_dir.to_string_lossy().to_string())
         .bind(self.supervisor_pid)
         .bind(pid)
+        .bind(self.backend.to_string())
+        .bind(self.port)
+        .bind(&self.metrics_addr)
+        .bind(&mount_dir)
         .execute(&self.fs_db)
         .await
         .map_err(MicrosandboxUtilsError::custom)?;
 
+        if result.rows_affected() == 0 {
+            sqlx::query(
+                r#"
+                INSERT INTO filesystems
+                    (name, mount_dir, supervisor_pid, nfsserver_pid, backend, port, metrics_addr)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&self.name)
+            .bind(&mount_dir)
+            .bind(self.supervisor_pid)
+            .bind(pid)
+            .bind(self.backend.to_string())
+            .bind(self.port)
+            .bind(&self.metrics_addr)
+            .execute(&self.fs_db)
+            .await
+            .map_err(MicrosandboxUtilsError::custom)?;
+        }
+
+        // Kill the child if it stops accepting connections so that the supervisor restarts it
+        if let (Some(host), Some(port)) = (&self.health_check_host, self.port) {
+            let host = management::get_mount_host(host);
+            self.health_check = Some(health::spawn_health_check(pid, host, port));
+        }
+
         // Spawn tasks to handle stdout/stderr
         if let Some(mut stdout) = stdout {
             tokio::spawn(async move {
@@ -155,10 +243,16 @@ impl ProcessMonitor for NfsServerMonitor {
     }
 
     async fn stop(&mut self) -> MicrosandboxUtilsResult<()> {
-        // Remove filesystem entry from fs_db
+        if let Some(health_check) = self.health_check.take() {
+            health_check.abort();
+        }
+
+        // Clear the processes from the filesystem's entry, keeping its head and snapshots
         sqlx::query(
             r#"
-            DELETE FROM filesystems
+            UPDATE filesystems
+            SET supervisor_pid = NULL, nfsserver_pid = NULL, port = NULL,
+                modified_at = CURRENT_TIMESTAMP
             WHERE mount_dir = ? AND supervisor_pid = ?
             "#,
         )
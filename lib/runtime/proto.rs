@@ -0,0 +1,3 @@
+//! The messages and services of the gRPC control API, generated from `proto/control.proto`.
+
+tonic::include_proto!("monofs.control.v1");
@@ -0,0 +1,139 @@
+use std::{future, path::Path, time::Duration};
+
+use chrono::Utc;
+use futures::{stream::BoxStream, StreamExt};
+use tokio::time;
+
+use crate::{
+    management::{self, FsSnapshotSchedule, MfsPaths},
+    server::{self, ChangeEvent},
+    FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How often the scheduler reads the schedule again and checks whether a snapshot is due.
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Takes the scheduled snapshots of a supervised filesystem, as its snapshot schedule asks.
+///
+/// The schedule is read from the fs database every few seconds, so it can be set, changed or
+/// cleared while the supervisor runs. Changes are counted by watching the server over its control
+/// socket, so those made while the server is down, or before the scheduler connects to it after
+/// a restart, are not counted.
+#[derive(Debug)]
+pub struct SnapshotScheduler {
+    /// The paths of the filesystem.
+    paths: MfsPaths,
+
+    /// The schedule of the filesystem.
+    schedule: FsSnapshotSchedule,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl SnapshotScheduler {
+    /// Creates a scheduler for the filesystem mounted at `mount_dir`, whose schedule is recorded
+    /// in the database at `fs_db_path`.
+    pub async fn new(fs_db_path: impl AsRef<Path>, mount_dir: impl AsRef<Path>) -> FsResult<Self> {
+        let mount_dir = mount_dir.as_ref();
+        Ok(Self {
+            paths: management::find_mfs_paths(mount_dir).await?,
+            schedule: FsSnapshotSchedule::new(fs_db_path, mount_dir).await?,
+        })
+    }
+
+    /// Takes snapshots as the schedule asks, for as long as the supervisor runs.
+    pub async fn run(self) {
+        let mut interval = time::interval(SCHEDULE_CHECK_INTERVAL);
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+
+        let mut schedule = None;
+        let mut changes = None;
+        let mut changed = 0;
+        let mut last_taken = match management::get_last_scheduled_snapshot(&self.paths).await {
+            Ok(Some(snapshot)) => *snapshot.get_created_at(),
+            Ok(None) => Utc::now(),
+            Err(e) => {
+                tracing::warn!(error = %e, "failed to find the last scheduled snapshot");
+                Utc::now()
+            }
+        };
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    match self.schedule.get().await {
+                        Ok(current) => schedule = current,
+                        Err(e) => tracing::error!(error = %e, "failed to read snapshot schedule"),
+                    }
+
+                    // Only schedules counting changes watch the server, which may be restarting
+                    let counts_changes = schedule
+                        .as_ref()
+                        .is_some_and(|schedule| schedule.get_every_changes().is_some());
+                    if !counts_changes {
+                        changes = None;
+                        changed = 0;
+                    } else if changes.is_none() {
+                        changes = server::watch_changes(self.paths.control_socket_path(), "/")
+                            .await
+                            .ok();
+                    }
+                }
+                event = next_change(&mut changes) => match event {
+                    Some(Ok(_)) => changed += 1,
+                    Some(Err(_)) | None => changes = None,
+                },
+            }
+
+            let Some(schedule) = &schedule else {
+                continue;
+            };
+
+            let elapsed = (Utc::now() - last_taken).to_std().unwrap_or_default();
+            let due = schedule
+                .get_interval()
+                .is_some_and(|interval| elapsed >= interval)
+                || schedule
+                    .get_every_changes()
+                    .is_some_and(|every| changed >= every);
+            if !due {
+                continue;
+            }
+
+            // An unchanged root is not snapshotted again, but still waits for the next interval
+            match management::take_scheduled_snapshot(&self.paths, schedule).await {
+                Ok(Some(snapshot)) => {
+                    tracing::info!("took scheduled snapshot {}", snapshot.get_name());
+                }
+                Ok(None) => {}
+                Err(e) => tracing::error!(error = %e, "failed to take scheduled snapshot"),
+            }
+            last_taken = Utc::now();
+            changed = 0;
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the next change reported by `changes`, or never returns if the server is not watched.
+async fn next_change(
+    changes: &mut Option<BoxStream<'static, FsResult<ChangeEvent>>>,
+) -> Option<FsResult<ChangeEvent>> {
+    match changes {
+        Some(changes) => changes.next().await,
+        None => future::pending().await,
+    }
+}
@@ -0,0 +1,84 @@
+use std::{path::Path, time::Duration};
+
+use tokio::time::{self, Instant};
+
+use crate::{
+    management::{self, FsScrub, MfsPaths},
+    FsResult,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How often the scrubber reads its settings again and checks whether a batch is due.
+const SCRUB_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Scrubs the blocks of a supervised filesystem in the background, as its scrub settings ask.
+///
+/// The settings are read from the fs database every few seconds, so scrubbing can be started,
+/// changed or stopped while the supervisor runs. How far the scrubber got is recorded after
+/// every batch, so a pass picks up where it left off after the supervisor restarts.
+#[derive(Debug)]
+pub struct BlockScrubber {
+    /// The paths of the filesystem.
+    paths: MfsPaths,
+
+    /// The scrub record of the filesystem.
+    scrub: FsScrub,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl BlockScrubber {
+    /// Creates a scrubber for the filesystem mounted at `mount_dir`, whose scrub settings are
+    /// recorded in the database at `fs_db_path`.
+    pub async fn new(fs_db_path: impl AsRef<Path>, mount_dir: impl AsRef<Path>) -> FsResult<Self> {
+        let mount_dir = mount_dir.as_ref();
+        Ok(Self {
+            paths: management::find_mfs_paths(mount_dir).await?,
+            scrub: FsScrub::new(fs_db_path, mount_dir).await?,
+        })
+    }
+
+    /// Scrubs batches of blocks as the settings ask, for as long as the supervisor runs.
+    pub async fn run(self) {
+        let mut interval = time::interval(SCRUB_CHECK_INTERVAL);
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+
+        let mut last_scrubbed = Instant::now();
+        loop {
+            interval.tick().await;
+
+            let config = match self.scrub.get().await {
+                Ok(Some(config)) => config,
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::error!(error = %e, "failed to read scrub settings");
+                    continue;
+                }
+            };
+
+            if last_scrubbed.elapsed() < config.get_interval() {
+                continue;
+            }
+
+            match management::scrub_blocks(&self.paths, &config).await {
+                Ok(batch) => {
+                    tracing::debug!("scrubbed {} blocks", batch.get_checked_blocks());
+                    if *batch.get_finished_pass() {
+                        tracing::info!("finished a scrub pass over the blocks");
+                    }
+                }
+                Err(e) => tracing::error!(error = %e, "failed to scrub blocks"),
+            }
+            last_scrubbed = Instant::now();
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use tracing_subscriber::{
+    fmt::format::FmtSpan, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter,
+};
+
+#[cfg(feature = "otlp")]
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+#[cfg(feature = "otlp")]
+use opentelemetry_sdk::{runtime, trace::TracerProvider, Resource};
+
+use crate::{utils::LOG_FILTER_ENV_VAR, FsError, FsResult};
+
+#[cfg(feature = "otlp")]
+use crate::utils::OTLP_ENDPOINT_ENV_VAR;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The filter used when [`LOG_FILTER_ENV_VAR`] is not set.
+pub const DEFAULT_LOG_FILTER: &str = "info";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The tracing subscriber of an `mfsrun` process.
+///
+/// Logs are written to stderr without colors, filtered by [`LOG_FILTER_ENV_VAR`]. Spans that pass
+/// the filter are logged when they close, along with how long they were busy, so enabling the
+/// `debug` spans of the NFS operations with `RUST_LOG=info,monofs=debug` logs the latency of
+/// every operation.
+///
+/// Built with the `otlp` feature, spans are also exported to the OpenTelemetry collector at
+/// [`OTLP_ENDPOINT_ENV_VAR`] when it is set, configured by the standard `OTEL_*` environment
+/// variables. The same filter applies to them.
+#[derive(Debug)]
+pub struct Telemetry {
+    /// Exports spans over OTLP, if enabled.
+    #[cfg(feature = "otlp")]
+    provider: Option<TracerProvider>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl Telemetry {
+    /// Installs the subscriber for the process, naming it `service_name` in exported spans.
+    ///
+    /// Fails if a subscriber is already installed.
+    pub fn init(service_name: &str) -> FsResult<Self> {
+        let filter = EnvFilter::try_from_env(LOG_FILTER_ENV_VAR)
+            .unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+        let logs = tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .with_span_events(FmtSpan::CLOSE);
+        let subscriber = tracing_subscriber::registry().with(filter).with(logs);
+
+        #[cfg(feature = "otlp")]
+        {
+            let provider = match std::env::var_os(OTLP_ENDPOINT_ENV_VAR) {
+                Some(_) => Some(init_otlp(service_name)?),
+                None => None,
+            };
+            let spans = provider.as_ref().map(|provider| {
+                tracing_opentelemetry::layer()
+                    .with_tracer(provider.tracer(service_name.to_string()))
+            });
+            subscriber.with(spans).try_init().map_err(FsError::custom)?;
+
+            Ok(Self { provider })
+        }
+
+        #[cfg(not(feature = "otlp"))]
+        {
+            let _ = service_name;
+            subscriber.try_init().map_err(FsError::custom)?;
+
+            Ok(Self {})
+        }
+    }
+
+    /// Exports the spans that are still buffered, before the process exits.
+    pub fn shutdown(self) {
+        #[cfg(feature = "otlp")]
+        if let Some(provider) = self.provider {
+            if let Err(e) = provider.shutdown() {
+                tracing::warn!(error = %e, "failed to export the remaining spans");
+            }
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Creates a provider that exports spans in batches to the collector configured in the
+/// environment.
+#[cfg(feature = "otlp")]
+fn init_otlp(service_name: &str) -> FsResult<TracerProvider> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .build()
+        .map_err(FsError::custom)?;
+
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            service_name.to_string(),
+        )]))
+        .build())
+}
@@ -0,0 +1,552 @@
+use nfsserve::{
+    nfs::{fattr3, ftype3, nfs_fh3},
+    vfs::NFSFileSystem,
+};
+
+use crate::config::ExportConfig;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The program number of NFS.
+const NFS_PROGRAM: u32 = 100003;
+
+/// The version of NFS checked.
+const NFS_VERSION: u32 = 3;
+
+/// The flavor of `AUTH_SYS` credentials.
+const AUTH_SYS: u32 = 1;
+
+/// The status of a request that failed on a hard error, `NFS3ERR_IO`.
+const NFS3ERR_IO: u32 = 5;
+
+/// The status of a request refused for lack of permission, `NFS3ERR_ACCES`.
+const NFS3ERR_ACCES: u32 = 13;
+
+/// The procedures checked, by number.
+const NFSPROC3_SETATTR: u32 = 2;
+const NFSPROC3_LOOKUP: u32 = 3;
+const NFSPROC3_ACCESS: u32 = 4;
+const NFSPROC3_READ: u32 = 6;
+const NFSPROC3_WRITE: u32 = 7;
+const NFSPROC3_CREATE: u32 = 8;
+const NFSPROC3_RMDIR: u32 = 13;
+const NFSPROC3_RENAME: u32 = 14;
+const NFSPROC3_LINK: u32 = 15;
+const NFSPROC3_READDIR: u32 = 16;
+const NFSPROC3_READDIRPLUS: u32 = 17;
+const NFSPROC3_COMMIT: u32 = 21;
+
+/// The bits of the access ACCESS calls ask about.
+const ACCESS3_READ: u32 = 0x01;
+const ACCESS3_LOOKUP: u32 = 0x02;
+const ACCESS3_MODIFY: u32 = 0x04;
+const ACCESS3_EXTEND: u32 = 0x08;
+const ACCESS3_DELETE: u32 = 0x10;
+const ACCESS3_EXECUTE: u32 = 0x20;
+
+/// The permission bits of a class in a mode.
+const MODE_READ: u32 = 0o4;
+const MODE_WRITE: u32 = 0o2;
+const MODE_EXECUTE: u32 = 0o1;
+
+/// The longest opaque value in a call, the credentials.
+const MAX_OPAQUE_LEN: usize = 400;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Checks the NFS calls of clients against the permissions of the entities they act on, as the
+/// users their `AUTH_SYS` credentials name.
+///
+/// `nfsserve` does not pass the credentials of a call on to the filesystem, so the gate looks at
+/// each call before it reaches the server. It maps the user and groups of the credentials to
+/// stored ones with the [`ExportConfig`], checks them against the mode and owner of the entity
+/// or directory the call acts on, and answers the calls they do not permit with
+/// `NFS3ERR_ACCES` itself. ACCESS calls are answered from the same checks, so that clients know
+/// what they may do before trying. Root may do anything.
+///
+/// Calls the gate cannot make sense of, of other programs, or with handles the filesystem does
+/// not know are passed on, for the server to answer as it would otherwise.
+pub(crate) struct PermissionGate<T>
+where
+    T: NFSFileSystem,
+{
+    /// The filesystem whose entities are checked.
+    fs: T,
+
+    /// How the credentials of clients map to stored users and groups.
+    exports: ExportConfig,
+}
+
+/// The stored user and groups a call is made as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Credentials {
+    /// The user.
+    uid: u32,
+
+    /// The primary group.
+    gid: u32,
+
+    /// The supplementary groups.
+    gids: Vec<u32>,
+}
+
+/// The header of an NFS call.
+struct Call {
+    /// The ID replies are matched to the call with.
+    xid: u32,
+
+    /// The procedure called.
+    procedure: u32,
+
+    /// Who the call is made as.
+    credentials: Credentials,
+}
+
+/// Reads XDR values from the start of a buffer.
+struct XdrReader<'a> {
+    /// What is left to read.
+    buf: &'a [u8],
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<T> PermissionGate<T>
+where
+    T: NFSFileSystem,
+{
+    /// Creates a gate checking calls against the entities of `fs`.
+    pub(crate) fn new(fs: T, exports: ExportConfig) -> Self {
+        Self { fs, exports }
+    }
+
+    /// Checks the call in `record`, returning the reply to send the client instead of passing the
+    /// call on, if there is one.
+    pub(crate) async fn check(&self, record: &[u8]) -> Option<Vec<u8>> {
+        let mut reader = XdrReader::new(record);
+        let call = self.read_call(&mut reader)?;
+        let credentials = &call.credentials;
+        if credentials.uid == 0 {
+            return None;
+        }
+
+        let permitted = match call.procedure {
+            NFSPROC3_ACCESS => {
+                let attr = self.get_attributes(reader.read_opaque()?).await?;
+                let requested = reader.read_u32()?;
+                let granted = credentials.get_access(&attr) & requested;
+                return Some(get_access_reply(call.xid, granted));
+            }
+            NFSPROC3_SETATTR => {
+                let attr = self.get_attributes(reader.read_opaque()?).await?;
+                self.may_set_attributes(credentials, &attr, &mut reader)?
+            }
+            NFSPROC3_LOOKUP => {
+                let attr = self.get_attributes(reader.read_opaque()?).await?;
+                credentials.permits(&attr, MODE_EXECUTE)
+            }
+            NFSPROC3_READ => {
+                // Files may be read to be executed
+                let attr = self.get_attributes(reader.read_opaque()?).await?;
+                credentials.permits(&attr, MODE_READ) || credentials.permits(&attr, MODE_EXECUTE)
+            }
+            NFSPROC3_WRITE | NFSPROC3_COMMIT => {
+                let attr = self.get_attributes(reader.read_opaque()?).await?;
+                credentials.permits(&attr, MODE_WRITE)
+            }
+            NFSPROC3_CREATE..=NFSPROC3_RMDIR => {
+                let attr = self.get_attributes(reader.read_opaque()?).await?;
+                credentials.permits(&attr, MODE_WRITE | MODE_EXECUTE)
+            }
+            NFSPROC3_RENAME => {
+                let from = self.get_attributes(reader.read_opaque()?).await?;
+                reader.read_opaque()?;
+                let to = self.get_attributes(reader.read_opaque()?).await?;
+                credentials.permits(&from, MODE_WRITE | MODE_EXECUTE)
+                    && credentials.permits(&to, MODE_WRITE | MODE_EXECUTE)
+            }
+            NFSPROC3_LINK => {
+                reader.read_opaque()?;
+                let dir = self.get_attributes(reader.read_opaque()?).await?;
+                credentials.permits(&dir, MODE_WRITE | MODE_EXECUTE)
+            }
+            NFSPROC3_READDIR | NFSPROC3_READDIRPLUS => {
+                let attr = self.get_attributes(reader.read_opaque()?).await?;
+                credentials.permits(&attr, MODE_READ)
+            }
+            _ => true,
+        };
+
+        if permitted {
+            return None;
+        }
+
+        tracing::debug!(
+            uid = credentials.uid,
+            procedure = call.procedure,
+            "refused NFS call for lack of permission"
+        );
+        Some(get_error_reply(call.xid, call.procedure, NFS3ERR_ACCES))
+    }
+
+    /// Reads the header of an NFS call, returning `None` if the record is not one.
+    fn read_call(&self, reader: &mut XdrReader<'_>) -> Option<Call> {
+        let xid = reader.read_u32()?;
+        let [msg_type, rpc_version, program, version, procedure] = reader.read_u32s()?;
+        if msg_type != 0 || rpc_version != 2 || program != NFS_PROGRAM || version != NFS_VERSION {
+            return None;
+        }
+
+        let flavor = reader.read_u32()?;
+        let body = reader.read_opaque()?;
+        reader.read_u32()?;
+        reader.read_opaque()?;
+
+        let credentials = if flavor == AUTH_SYS {
+            self.read_credentials(&mut XdrReader::new(body))?
+        } else {
+            Credentials {
+                uid: *self.exports.get_anon_uid(),
+                gid: *self.exports.get_anon_gid(),
+                gids: Vec::new(),
+            }
+        };
+
+        Some(Call {
+            xid,
+            procedure,
+            credentials,
+        })
+    }
+
+    /// Reads the body of `AUTH_SYS` credentials, mapping its user and groups to stored ones.
+    fn read_credentials(&self, reader: &mut XdrReader<'_>) -> Option<Credentials> {
+        reader.read_u32()?;
+        reader.read_opaque()?;
+        let [uid, gid] = reader.read_u32s()?;
+        let count = reader.read_u32()?;
+        let gids = (0..count)
+            .map(|_| reader.read_u32().map(|gid| self.exports.map_gid(gid)))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Credentials {
+            uid: self.exports.map_uid(uid),
+            gid: self.exports.map_gid(gid),
+            gids,
+        })
+    }
+
+    /// Returns whether `credentials` permit setting the attributes a SETATTR call reads next on
+    /// an entity with attributes `attr`.
+    ///
+    /// Only the owner may change the mode, the group or the times to ones of its choosing, and
+    /// only root the owner. Changing the size takes write permission, and setting the times to
+    /// the current time either.
+    fn may_set_attributes(
+        &self,
+        credentials: &Credentials,
+        attr: &fattr3,
+        reader: &mut XdrReader<'_>,
+    ) -> Option<bool> {
+        let owner = credentials.uid == attr.uid;
+        let writable = credentials.permits(attr, MODE_WRITE);
+        let mut permitted = true;
+
+        if reader.read_optional(|reader| reader.read_u32())?.is_some() {
+            permitted &= owner;
+        }
+        if let Some(uid) = reader.read_optional(|reader| reader.read_u32())? {
+            permitted &= self.exports.squash_uid(uid) == attr.uid;
+        }
+        if reader.read_optional(|reader| reader.read_u32())?.is_some() {
+            permitted &= owner;
+        }
+        if reader.read_optional(|reader| reader.read_u64())?.is_some() {
+            permitted &= writable;
+        }
+        for _ in 0..2 {
+            match reader.read_u32()? {
+                0 => {}
+                1 => permitted &= owner || writable,
+                _ => {
+                    reader.read_u64()?;
+                    permitted &= owner;
+                }
+            }
+        }
+
+        Some(permitted)
+    }
+
+    /// Returns the attributes of the entity with handle `fh`, if the filesystem knows it.
+    async fn get_attributes(&self, fh: &[u8]) -> Option<fattr3> {
+        let id = self.fs.fh_to_id(&nfs_fh3 { data: fh.to_vec() }).ok()?;
+        self.fs.getattr(id).await.ok()
+    }
+}
+
+impl Credentials {
+    /// Returns the permission bits of the class `attr` puts these credentials in.
+    fn get_mode(&self, attr: &fattr3) -> u32 {
+        let shift = if self.uid == attr.uid {
+            6
+        } else if self.gid == attr.gid || self.gids.contains(&attr.gid) {
+            3
+        } else {
+            0
+        };
+
+        (attr.mode >> shift) & 0o7
+    }
+
+    /// Returns whether these credentials have each of the permission `bits` on an entity with
+    /// attributes `attr`.
+    fn permits(&self, attr: &fattr3, bits: u32) -> bool {
+        self.uid == 0 || self.get_mode(attr) & bits == bits
+    }
+
+    /// Returns the ACCESS bits these credentials have on an entity with attributes `attr`.
+    fn get_access(&self, attr: &fattr3) -> u32 {
+        let mode = if self.uid == 0 {
+            0o7
+        } else {
+            self.get_mode(attr)
+        };
+        let dir = matches!(attr.ftype, ftype3::NF3DIR);
+        let mut access = 0;
+        if mode & MODE_READ != 0 {
+            access |= ACCESS3_READ;
+        }
+        if mode & MODE_WRITE != 0 {
+            access |= ACCESS3_MODIFY | ACCESS3_EXTEND;
+            if dir {
+                access |= ACCESS3_DELETE;
+            }
+        }
+        if mode & MODE_EXECUTE != 0 {
+            access |= if dir { ACCESS3_LOOKUP } else { ACCESS3_EXECUTE };
+        }
+
+        access
+    }
+}
+
+impl<'a> XdrReader<'a> {
+    /// Creates a reader of the values in `buf`.
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    /// Reads an unsigned 32-bit integer.
+    fn read_u32(&mut self) -> Option<u32> {
+        let (value, rest) = self.buf.split_first_chunk::<4>()?;
+        self.buf = rest;
+        Some(u32::from_be_bytes(*value))
+    }
+
+    /// Reads `N` unsigned 32-bit integers.
+    fn read_u32s<const N: usize>(&mut self) -> Option<[u32; N]> {
+        let mut values = [0; N];
+        for value in &mut values {
+            *value = self.read_u32()?;
+        }
+
+        Some(values)
+    }
+
+    /// Reads an unsigned 64-bit integer.
+    fn read_u64(&mut self) -> Option<u64> {
+        let [high, low] = self.read_u32s()?;
+        Some(((high as u64) << 32) | low as u64)
+    }
+
+    /// Reads a value that is only there if the boolean before it is set.
+    fn read_optional<V>(&mut self, read: impl FnOnce(&mut Self) -> Option<V>) -> Option<Option<V>> {
+        match self.read_u32()? {
+            0 => Some(None),
+            _ => read(self).map(Some),
+        }
+    }
+
+    /// Reads an opaque value or string of variable length.
+    fn read_opaque(&mut self) -> Option<&'a [u8]> {
+        let len = self.read_u32()? as usize;
+        let padded = len.checked_add(3)? & !3;
+        if len > MAX_OPAQUE_LEN || padded > self.buf.len() {
+            return None;
+        }
+
+        let value = &self.buf[..len];
+        self.buf = &self.buf[padded..];
+        Some(value)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns whether `record` holds an NFS COMMIT call.
+pub(crate) fn is_commit_call(record: &[u8]) -> bool {
+    let mut reader = XdrReader::new(record);
+    let header = reader.read_u32().and_then(|_| reader.read_u32s());
+    matches!(
+        header,
+        Some([0, 2, NFS_PROGRAM, NFS_VERSION, NFSPROC3_COMMIT])
+    )
+}
+
+/// Returns the reply failing the COMMIT call in `record` with `NFS3ERR_IO`, for when its writes
+/// could not be made durable.
+pub(crate) fn get_commit_failed_reply(record: &[u8]) -> Vec<u8> {
+    let xid = XdrReader::new(record).read_u32().unwrap_or_default();
+    get_error_reply(xid, NFSPROC3_COMMIT, NFS3ERR_IO)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the header of a successful reply to the call with ID `xid`.
+fn get_reply_header(xid: u32) -> Vec<u8> {
+    // Reply, accepted, with an empty verifier, and executed
+    [xid, 1, 0, 0, 0, 0]
+        .iter()
+        .flat_map(|word| word.to_be_bytes())
+        .collect()
+}
+
+/// Returns the reply to an ACCESS call with ID `xid` granting the ACCESS bits `access`.
+fn get_access_reply(xid: u32, access: u32) -> Vec<u8> {
+    let mut reply = get_reply_header(xid);
+
+    // Success, without the attributes of the entity
+    for word in [0, 0, access] {
+        reply.extend(word.to_be_bytes());
+    }
+
+    reply
+}
+
+/// Returns the reply failing the call to `procedure` with ID `xid` with `status`.
+///
+/// The reply leaves out every attribute the failure of the procedure may carry, so it has as many
+/// unset booleans as the procedure has optional attributes after the status.
+fn get_error_reply(xid: u32, procedure: u32, status: u32) -> Vec<u8> {
+    let attributes = match procedure {
+        NFSPROC3_RENAME => 4,
+        NFSPROC3_LINK => 3,
+        NFSPROC3_SETATTR | NFSPROC3_WRITE | NFSPROC3_COMMIT => 2,
+        NFSPROC3_CREATE..=NFSPROC3_RMDIR => 2,
+        _ => 1,
+    };
+
+    let mut reply = get_reply_header(xid);
+    reply.extend(status.to_be_bytes());
+    reply.extend(vec![0; attributes * 4]);
+    reply
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::MemoryStore;
+    use nfsserve::nfs::{filename3, sattr3, set_mode3, set_uid3};
+
+    use crate::{config::IdMapping, server::MemoryMonofsNFS};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_permission_gate() -> anyhow::Result<()> {
+        let fs = MemoryMonofsNFS::new(MemoryStore::default());
+        let mut attr = sattr3::default();
+        attr.mode = set_mode3::mode(0o640);
+        attr.uid = set_uid3::uid(1000);
+        let (fileid, _) = fs
+            .create(0, &filename3::from("secret.txt".as_bytes()), attr)
+            .await
+            .map_err(|e| anyhow::anyhow!("{:?}", e))?;
+        let fh = fs.id_to_fh(fileid).data;
+
+        let exports = ExportConfig::builder()
+            .check_permissions(true)
+            .uid_map(vec!["3000:1000".parse::<IdMapping>()?])
+            .build();
+        let gate = PermissionGate::new(fs, exports);
+
+        // Only the owner, under its own ID or a mapped one, may read the file
+        let read = |uid| encode_call(NFSPROC3_READ, uid, &[&encode_opaque(&fh)[..], &[0; 12]]);
+        assert!(gate.check(&read(1000)).await.is_none());
+        assert!(gate.check(&read(3000)).await.is_none());
+        assert!(gate.check(&read(0)).await.is_none());
+        let reply = gate.check(&read(2000)).await.unwrap();
+        assert_eq!(reply[..4], 7u32.to_be_bytes());
+        assert_eq!(reply[24..28], NFS3ERR_ACCES.to_be_bytes());
+        assert_eq!(reply.len(), 32);
+
+        // ACCESS calls are answered with what the mode grants
+        let access = |uid| {
+            encode_call(
+                NFSPROC3_ACCESS,
+                uid,
+                &[&encode_opaque(&fh)[..], &0x3fu32.to_be_bytes()],
+            )
+        };
+        let reply = gate.check(&access(1000)).await.unwrap();
+        assert_eq!(
+            reply[32..],
+            (ACCESS3_READ | ACCESS3_MODIFY | ACCESS3_EXTEND).to_be_bytes()
+        );
+        let reply = gate.check(&access(2000)).await.unwrap();
+        assert_eq!(reply[32..], 0u32.to_be_bytes());
+
+        // Calls that cannot be checked are passed on
+        assert!(gate.check(&[0; 8]).await.is_none());
+        let unknown = encode_call(NFSPROC3_READ, 2000, &[&encode_opaque(&[9; 8])]);
+        assert!(gate.check(&unknown).await.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_commit_call() {
+        let commit = encode_call(NFSPROC3_COMMIT, 1000, &[&encode_opaque(&[1; 8])]);
+        assert!(is_commit_call(&commit));
+        assert!(!is_commit_call(&encode_call(NFSPROC3_WRITE, 1000, &[])));
+        assert!(!is_commit_call(&commit[..16]));
+    }
+
+    /// Encodes a call to `procedure` made as the user `uid` with the arguments `args`.
+    fn encode_call(procedure: u32, uid: u32, args: &[&[u8]]) -> Vec<u8> {
+        let mut credentials = Vec::new();
+        for word in [0, 0, uid, uid, 0] {
+            credentials.extend(word.to_be_bytes());
+        }
+
+        let mut call = Vec::new();
+        for word in [7, 0, 2, NFS_PROGRAM, NFS_VERSION, procedure, AUTH_SYS] {
+            call.extend(word.to_be_bytes());
+        }
+        call.extend(encode_opaque(&credentials));
+        call.extend([0; 8]);
+        call.extend(args.concat());
+        call
+    }
+
+    /// Encodes an opaque value of variable length.
+    fn encode_opaque(value: &[u8]) -> Vec<u8> {
+        let mut encoded = (value.len() as u32).to_be_bytes().to_vec();
+        encoded.extend(value);
+        encoded.resize(encoded.len() + (4 - value.len() % 4) % 4, 0);
+        encoded
+    }
+}
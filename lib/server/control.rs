@@ -0,0 +1,632 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use futures::stream::{self, BoxStream, StreamExt};
+use ipldstore::{ipld::cid::Cid, IpldStoreSeekable};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs,
+    io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::broadcast::error::RecvError,
+};
+
+use crate::{
+    config::QuotaConfig,
+    management::{FsHead, FsQuota},
+    FsError, FsResult,
+};
+
+use super::{ChangeEvent, DiskSync, MetricsSnapshot, MonofsNFS, SharedMounts};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A request sent to a running filesystem server over its control socket.
+///
+/// Requests and responses are exchanged as newline-delimited JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ControlRequest {
+    /// Checkpoint the root directory and record it as the filesystem's head.
+    Checkpoint,
+
+    /// Record the final root before the server is stopped.
+    ///
+    /// The root is checkpointed and recorded as the filesystem's head like [`Self::Checkpoint`],
+    /// but marks the end of the mount in the filesystem's history. The server keeps running
+    /// until it is signalled to stop.
+    Shutdown,
+
+    /// Replace the root directory with the directory stored at `cid`.
+    SetRoot {
+        /// The CID of the new root directory.
+        cid: String,
+
+        /// A short summary of what produced the new root, recorded in the filesystem's history.
+        #[serde(default)]
+        operation: String,
+
+        /// The root the new root was derived from, if any. Changes made to the filesystem since
+        /// are merged into the new root rather than discarded.
+        #[serde(default)]
+        base: Option<String>,
+    },
+
+    /// Serve the filesystem tracked under `mount_dir` next to this one, sharing its blocks and
+    /// fs database.
+    AddMount {
+        /// The mount directory identifying the filesystem in the fs database.
+        mount_dir: String,
+    },
+
+    /// Stop serving a filesystem added with [`Self::AddMount`] and record its final root.
+    RemoveMount {
+        /// The mount directory identifying the filesystem in the fs database.
+        mount_dir: String,
+    },
+
+    /// Report the changes made to entities at or below `path_prefix` from now on.
+    ///
+    /// The server acknowledges the request with [`ControlResponse::Done`] and then sends a
+    /// [`ControlResponse::Change`] for every change, for as long as the connection is open. No
+    /// other requests are served over the connection after this one.
+    Watch {
+        /// The path the changes are reported for, with `/` covering the whole filesystem.
+        #[serde(default)]
+        path_prefix: String,
+    },
+
+    /// Report the operations the server has served and how its caches fared.
+    Metrics,
+
+    /// Enforce `quota` from now on instead of the quota the server was started with.
+    ///
+    /// Only a server started with a quota counts the size of its store, so others refuse the
+    /// request and pick the quota up when they next start.
+    SetQuota {
+        /// The limits to enforce.
+        quota: QuotaConfig,
+    },
+}
+
+/// A response returned by a filesystem server over its control socket.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ControlResponse {
+    /// The request succeeded and produced the given root CID.
+    Root {
+        /// The CID of the root directory.
+        cid: String,
+    },
+
+    /// The request succeeded and the server is listening for NFS clients on the given address.
+    Listening {
+        /// The address the server is bound to.
+        host: String,
+
+        /// The port the server is listening on.
+        port: u32,
+    },
+
+    /// The request succeeded.
+    Done,
+
+    /// An entity changed, reported to a [`ControlRequest::Watch`] request.
+    Change {
+        /// What changed.
+        event: ChangeEvent,
+    },
+
+    /// The metrics of the server, reported to a [`ControlRequest::Metrics`] request.
+    Metrics {
+        /// The metrics counted since the server started.
+        metrics: MetricsSnapshot,
+    },
+
+    /// The request failed.
+    Error {
+        /// A description of the failure.
+        message: String,
+    },
+}
+
+/// Serves [`ControlRequest`]s for a running filesystem over a unix socket.
+///
+/// This is how management commands such as snapshotting reach into a mounted filesystem whose
+/// state only lives in the server process.
+pub struct ControlServer<S>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    /// The filesystem being controlled.
+    fs: MonofsNFS<S>,
+
+    /// Where to record the filesystem's head after it changes.
+    head: Option<FsHead>,
+
+    /// The path of the unix socket to listen on.
+    socket_path: PathBuf,
+
+    /// The mounts served next to the filesystem, if the server supports them.
+    shared_mounts: Option<Arc<SharedMounts>>,
+
+    /// Where to record the filesystem's usage along with its head, if it has a quota.
+    quota: Option<FsQuota>,
+
+    /// Flushes the filesystem's writes to disk once its head is checkpointed, if it is on disk.
+    disk_sync: Option<DiskSync>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> ControlServer<S>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    /// Creates a new control server for `fs` listening on `socket_path`.
+    pub fn new(fs: MonofsNFS<S>, head: Option<FsHead>, socket_path: impl Into<PathBuf>) -> Self {
+        Self {
+            fs,
+            head,
+            socket_path: socket_path.into(),
+            shared_mounts: None,
+            quota: None,
+            disk_sync: None,
+        }
+    }
+
+    /// Accepts requests to serve more mounts of the same data through `shared_mounts`.
+    pub(crate) fn with_shared_mounts(mut self, shared_mounts: Arc<SharedMounts>) -> Self {
+        self.shared_mounts = Some(shared_mounts);
+        self
+    }
+
+    /// Records the filesystem's usage in `quota` whenever its head is recorded.
+    pub(crate) fn with_quota(mut self, quota: FsQuota) -> Self {
+        self.quota = Some(quota);
+        self
+    }
+
+    /// Flushes the filesystem's blocks and fs database to disk with `disk_sync` whenever its
+    /// head is checkpointed.
+    pub(crate) fn with_disk_sync(mut self, disk_sync: DiskSync) -> Self {
+        self.disk_sync = Some(disk_sync);
+        self
+    }
+
+    /// Listens on the control socket and serves requests until an error occurs.
+    ///
+    /// A stale socket file left behind by a previous server is replaced.
+    pub async fn serve(self) -> FsResult<()> {
+        if fs::try_exists(&self.socket_path).await? {
+            fs::remove_file(&self.socket_path).await?;
+        }
+
+        let listener = UnixListener::bind(&self.socket_path)?;
+        tracing::info!("control socket listening at {}", self.socket_path.display());
+
+        let server = Arc::new(self);
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = server.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.handle_connection(stream).await {
+                    tracing::warn!(error = %e, "control connection failed");
+                }
+            });
+        }
+    }
+
+    /// Handles every request sent over a single connection.
+    async fn handle_connection(&self, stream: UnixStream) -> FsResult<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        while let Some(line) = lines.next_line().await? {
+            let response = match serde_json::from_str::<ControlRequest>(&line) {
+                Ok(ControlRequest::Watch { path_prefix }) => {
+                    tracing::info!("control request: watch {}", path_prefix);
+                    return self.send_changes(&path_prefix, &mut writer).await;
+                }
+                Ok(request) => {
+                    tracing::info!("control request: {:?}", request);
+                    self.handle_request(request)
+                        .await
+                        .unwrap_or_else(|e| ControlResponse::Error {
+                            message: e.to_string(),
+                        })
+                }
+                Err(e) => ControlResponse::Error {
+                    message: format!("invalid control request: {}", e),
+                },
+            };
+
+            write_response(&mut writer, &response).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends the changes made to entities at or below `path_prefix` over `writer` until the
+    /// watcher goes away.
+    async fn send_changes(
+        &self,
+        path_prefix: &str,
+        writer: &mut (impl AsyncWrite + Unpin),
+    ) -> FsResult<()> {
+        let mut changes = self.fs.watch();
+        write_response(writer, &ControlResponse::Done).await?;
+
+        loop {
+            let response = match changes.recv().await {
+                Ok(event) if event.is_under(path_prefix) => ControlResponse::Change { event },
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => ControlResponse::Error {
+                    message: format!("watcher fell behind and missed {} changes", missed),
+                },
+                Err(RecvError::Closed) => return Ok(()),
+            };
+
+            if let Err(e) = write_response(writer, &response).await {
+                tracing::debug!(error = %e, "watcher went away");
+                return Ok(());
+            }
+        }
+    }
+
+    /// Applies a single request to the filesystem.
+    async fn handle_request(&self, request: ControlRequest) -> FsResult<ControlResponse> {
+        match request {
+            ControlRequest::Checkpoint => {
+                let cid = self.fs.checkpoint().await?;
+                let cid = self.save_head(&cid, "checkpoint").await?;
+                self.sync_to_disk().await?;
+                Ok(ControlResponse::Root {
+                    cid: cid.to_string(),
+                })
+            }
+            ControlRequest::Shutdown => {
+                let cid = self.fs.checkpoint().await?;
+                let cid = self.save_head(&cid, "unmount").await?;
+                self.sync_to_disk().await?;
+                tracing::info!("recorded final root {} ahead of shutdown", cid);
+                Ok(ControlResponse::Root {
+                    cid: cid.to_string(),
+                })
+            }
+            ControlRequest::SetRoot {
+                cid,
+                operation,
+                base,
+            } => {
+                let cid = Cid::try_from(cid.as_str())?;
+                let cid = match base {
+                    Some(base) => {
+                        let base = Cid::try_from(base.as_str())?;
+                        self.fs.merge_root(Some(base), cid).await?
+                    }
+                    None => {
+                        self.fs.set_root(&cid).await?;
+                        cid
+                    }
+                };
+                self.save_head(&cid, &operation).await?;
+                Ok(ControlResponse::Done)
+            }
+            ControlRequest::AddMount { mount_dir } => {
+                let port = self.get_shared_mounts()?.add(Path::new(&mount_dir)).await?;
+                Ok(ControlResponse::Listening {
+                    host: self.get_shared_mounts()?.get_host().clone(),
+                    port,
+                })
+            }
+            ControlRequest::RemoveMount { mount_dir } => {
+                let cid = self
+                    .get_shared_mounts()?
+                    .remove(Path::new(&mount_dir))
+                    .await?;
+                Ok(ControlResponse::Root {
+                    cid: cid.to_string(),
+                })
+            }
+            ControlRequest::Metrics => Ok(ControlResponse::Metrics {
+                metrics: self.fs.get_metrics().await,
+            }),
+            ControlRequest::SetQuota { quota } => {
+                if self.quota.is_none() {
+                    return Err(FsError::InvalidOperation(
+                        "this server was started without a quota and applies one when it next \
+                         starts"
+                            .to_string(),
+                    ));
+                }
+
+                self.fs.update_quota(quota).await?;
+                Ok(ControlResponse::Done)
+            }
+            ControlRequest::Watch { .. } => Err(FsError::InvalidOperation(
+                "changes are only watched over a connection of their own".to_string(),
+            )),
+        }
+    }
+
+    /// Returns the mounts served next to the filesystem, or an error if the server does not
+    /// support them.
+    fn get_shared_mounts(&self) -> FsResult<&SharedMounts> {
+        self.shared_mounts.as_deref().ok_or_else(|| {
+            FsError::InvalidOperation("this server does not serve shared mounts".to_string())
+        })
+    }
+
+    /// Records `cid` as the filesystem's head if a head is being tracked, along with the
+    /// filesystem's usage if it has a quota.
+    ///
+    /// Heads recorded by other writers since the server last recorded one are merged into the
+    /// root first, see [`FsHead::update`].
+    ///
+    /// Returns the root that was recorded.
+    async fn save_head(&self, cid: &Cid, operation: &str) -> FsResult<Cid> {
+        // The root about to be recorded is left out of merges, as the in-memory root it was
+        // checkpointed from holds every change it does, along with those made since
+        let cid = match &self.head {
+            Some(head) => {
+                let merge = |base, _ours, theirs| self.fs.merge_root(base, theirs);
+                head.update(cid, operation, merge).await?
+            }
+            None => *cid,
+        };
+
+        if let Some(quota) = &self.quota {
+            quota.set_usage(&self.fs.get_usage().await).await?;
+        }
+
+        Ok(cid)
+    }
+
+    /// Flushes the filesystem's blocks and fs database to disk, if it is on disk.
+    async fn sync_to_disk(&self) -> FsResult<()> {
+        match &self.disk_sync {
+            Some(disk_sync) => disk_sync.sync().await,
+            None => Ok(()),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Sends a request to the filesystem server listening on `socket_path` and waits for its response.
+///
+/// Error responses from the server are returned as [`FsError::ControlRequestFailed`].
+pub async fn send_control_request(
+    socket_path: impl AsRef<Path>,
+    request: &ControlRequest,
+) -> FsResult<ControlResponse> {
+    let stream = UnixStream::connect(socket_path.as_ref()).await?;
+    let (reader, mut writer) = stream.into_split();
+
+    let mut payload = serde_json::to_string(request).map_err(FsError::custom)?;
+    payload.push('\n');
+    writer.write_all(payload.as_bytes()).await?;
+
+    let mut lines = BufReader::new(reader).lines();
+    let line = lines
+        .next_line()
+        .await?
+        .ok_or_else(|| FsError::ControlRequestFailed("server closed the connection".to_string()))?;
+
+    match serde_json::from_str(&line).map_err(FsError::custom)? {
+        ControlResponse::Error { message } => Err(FsError::ControlRequestFailed(message)),
+        response => Ok(response),
+    }
+}
+
+/// Watches the filesystem server listening on `socket_path` for changes to entities at or below
+/// `path_prefix`.
+///
+/// The returned stream ends when the server stops. Changes the watcher fell behind on are
+/// reported as [`FsError::ControlRequestFailed`] items, after which the stream carries on.
+pub async fn watch_changes(
+    socket_path: impl AsRef<Path>,
+    path_prefix: impl Into<String>,
+) -> FsResult<BoxStream<'static, FsResult<ChangeEvent>>> {
+    let stream = UnixStream::connect(socket_path.as_ref()).await?;
+    let (reader, mut writer) = stream.into_split();
+
+    let request = ControlRequest::Watch {
+        path_prefix: path_prefix.into(),
+    };
+    let mut payload = serde_json::to_string(&request).map_err(FsError::custom)?;
+    payload.push('\n');
+    writer.write_all(payload.as_bytes()).await?;
+
+    // Changes are only reported once the server has acknowledged the request
+    let mut lines = BufReader::new(reader).lines();
+    let line = lines
+        .next_line()
+        .await?
+        .ok_or_else(|| FsError::ControlRequestFailed("server closed the connection".to_string()))?;
+    match serde_json::from_str(&line).map_err(FsError::custom)? {
+        ControlResponse::Done => {}
+        ControlResponse::Error { message } => return Err(FsError::ControlRequestFailed(message)),
+        response => {
+            return Err(FsError::ControlRequestFailed(format!(
+                "unexpected response: {:?}",
+                response
+            )))
+        }
+    }
+
+    // The writer is kept alive with the stream, as dropping it would close the connection
+    let changes = stream::try_unfold((lines, writer), |(mut lines, writer)| async move {
+        let Some(line) = lines.next_line().await? else {
+            return Ok(None);
+        };
+
+        let event = match serde_json::from_str(&line).map_err(FsError::custom)? {
+            ControlResponse::Change { event } => Ok(event),
+            ControlResponse::Error { message } => Err(FsError::ControlRequestFailed(message)),
+            response => Err(FsError::ControlRequestFailed(format!(
+                "unexpected response: {:?}",
+                response
+            ))),
+        };
+
+        Ok::<_, FsError>(Some((event, (lines, writer))))
+    });
+
+    Ok(changes.map(|item| item.and_then(|event| event)).boxed())
+}
+
+/// Like [`send_control_request`], but returns `None` when no server is listening on
+/// `socket_path`, e.g. because the filesystem is not mounted.
+pub(crate) async fn try_send_control_request(
+    socket_path: impl AsRef<Path>,
+    request: &ControlRequest,
+) -> FsResult<Option<ControlResponse>> {
+    match send_control_request(socket_path, request).await {
+        Ok(response) => Ok(Some(response)),
+        Err(FsError::IoError(e))
+            if matches!(
+                e.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+            ) =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Writes `response` to `writer` as a line of JSON.
+async fn write_response(
+    writer: &mut (impl AsyncWrite + Unpin),
+    response: &ControlResponse,
+) -> FsResult<()> {
+    let mut response = serde_json::to_string(response).map_err(FsError::custom)?;
+    response.push('\n');
+    writer.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::MemoryStore;
+    use tempfile::tempdir;
+
+    use nfsserve::{nfs::filename3, vfs::NFSFileSystem};
+
+    use crate::server::{ChangeKind, MemoryMonofsNFS};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_control_checkpoint_and_set_root() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let socket_path = temp_dir.path().join("control.sock");
+
+        let fs = MemoryMonofsNFS::new(MemoryStore::default());
+        let server = ControlServer::new(fs.clone(), None, &socket_path);
+        tokio::spawn(server.serve());
+
+        // Wait for the socket to come up
+        while !fs::try_exists(&socket_path).await? {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        let ControlResponse::Root { cid } =
+            send_control_request(&socket_path, &ControlRequest::Checkpoint).await?
+        else {
+            panic!("expected a root response");
+        };
+
+        let request = ControlRequest::SetRoot {
+            cid: cid.clone(),
+            operation: "test".to_string(),
+            base: None,
+        };
+        let response = send_control_request(&socket_path, &request).await?;
+        assert_eq!(response, ControlResponse::Done);
+
+        // Shutting down reports the final root
+        let response = send_control_request(&socket_path, &ControlRequest::Shutdown).await?;
+        assert_eq!(response, ControlResponse::Root { cid });
+
+        // Both checkpoints and the new root are counted
+        let ControlResponse::Metrics { metrics } =
+            send_control_request(&socket_path, &ControlRequest::Metrics).await?
+        else {
+            panic!("expected a metrics response");
+        };
+        assert_eq!(*metrics.get_checkpoints(), 2);
+        assert_eq!(*metrics.get_root_restores(), 1);
+
+        // Invalid CIDs are reported as errors
+        let result = send_control_request(
+            &socket_path,
+            &ControlRequest::SetRoot {
+                cid: "not-a-cid".to_string(),
+                operation: "test".to_string(),
+                base: None,
+            },
+        )
+        .await;
+        assert!(matches!(result, Err(FsError::ControlRequestFailed(_))));
+
+        // A server started without a quota does not take one on
+        let request = ControlRequest::SetQuota {
+            quota: QuotaConfig::unlimited(),
+        };
+        let result = send_control_request(&socket_path, &request).await;
+        assert!(matches!(result, Err(FsError::ControlRequestFailed(_))));
+
+        Ok(())
+    }
+    #[tokio::test]
+    async fn test_control_watch() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let socket_path = temp_dir.path().join("control.sock");
+
+        let fs = MemoryMonofsNFS::new(MemoryStore::default());
+        let server = ControlServer::new(fs.clone(), None, &socket_path);
+        tokio::spawn(server.serve());
+
+        // Wait for the socket to come up
+        while !fs::try_exists(&socket_path).await? {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        // Only changes below the prefix are reported
+        let mut changes = watch_changes(&socket_path, "/out").await?;
+        for name in ["in", "out"] {
+            fs.mkdir(0, &filename3::from(name.as_bytes()))
+                .await
+                .unwrap();
+        }
+
+        let event = changes.next().await.unwrap()?;
+        assert_eq!(event, ChangeEvent::new(ChangeKind::Created, "out", None));
+
+        // Other requests are still served over their own connections
+        let response = send_control_request(&socket_path, &ControlRequest::Checkpoint).await?;
+        assert!(matches!(response, ControlResponse::Root { .. }));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    management::{self, FsHead},
+    store::FlatFsStore,
+    FsResult,
+};
+
+use super::MonofsNFS;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Makes what a server wrote for one filesystem durable, without touching anything else on the
+/// machine.
+///
+/// Only the block files the store of the filesystem wrote without flushing and the filesystem's
+/// fs database are flushed, so a sync costs as much as the writes since the last one, not as much
+/// as everything every other process has buffered.
+#[derive(Clone)]
+pub(crate) struct DiskSync {
+    /// The filesystem whose writes are made durable.
+    fs: MonofsNFS<FlatFsStore>,
+
+    /// Where intents to make checkpointed roots the head are logged.
+    head: FsHead,
+
+    /// The path of the fs database the head is recorded in.
+    fs_db_path: PathBuf,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl DiskSync {
+    /// Creates a sync for the writes of `fs`, whose head is recorded by `head` in the fs database
+    /// at `fs_db_path`.
+    pub(crate) fn new(fs: MonofsNFS<FlatFsStore>, head: FsHead, fs_db_path: &Path) -> Self {
+        Self {
+            fs,
+            head,
+            fs_db_path: fs_db_path.to_path_buf(),
+        }
+    }
+
+    /// Flushes the blocks the store wrote since the last sync, then the fs database.
+    pub(crate) async fn sync(&self) -> FsResult<()> {
+        self.fs.get_store().await.sync().await?;
+        management::sync_db(&self.fs_db_path).await
+    }
+
+    /// Makes the writes the server acknowledged so far durable, as an NFS COMMIT asks.
+    ///
+    /// The root is checkpointed, which applies the writes that are still buffered, and logged as
+    /// an intent once its blocks are flushed, so that a server that crashes before recording its
+    /// head recovers it.
+    pub(crate) async fn commit(&self) -> FsResult<()> {
+        let cid = self.fs.checkpoint().await?;
+        self.fs.get_store().await.sync().await?;
+        self.head.log_intent(&cid, "commit").await?;
+        management::sync_db(&self.fs_db_path).await
+    }
+}
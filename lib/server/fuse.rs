@@ -0,0 +1,726 @@
+use std::{
+    ffi::OsStr,
+    future::Future,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use fuser::{
+    FileAttr, FileType, Filesystem, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory, ReplyEmpty,
+    ReplyEntry, ReplyStatfs, ReplyWrite, ReplyXattr, Request, TimeOrNow, FUSE_ROOT_ID,
+};
+use ipldstore::IpldStoreSeekable;
+use nfsserve::{
+    nfs::{
+        fattr3, fileid3, filename3, ftype3, nfspath3, nfsstat3, nfstime3, sattr3, set_atime,
+        set_gid3, set_mode3, set_mtime, set_size3, set_uid3,
+    },
+    vfs::NFSFileSystem,
+};
+use nix::libc;
+use tokio::runtime::Handle;
+
+use super::{call_as, FsStats, MonofsNFS};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How long the kernel may cache entries and attributes returned by the FUSE adapter.
+const FUSE_TTL: Duration = Duration::from_secs(1);
+
+/// The preferred block size reported to the kernel.
+const FUSE_BLOCK_SIZE: u32 = 4096;
+
+/// The most bytes a `copy_file_range` call copies through a read and a write.
+const FUSE_COPY_CHUNK_SIZE: u32 = 1024 * 1024;
+
+/// The errno reported for an xattr that is not set.
+#[cfg(target_os = "macos")]
+const NO_XATTR: i32 = libc::ENOATTR;
+
+/// The errno reported for an xattr that is not set.
+#[cfg(not(target_os = "macos"))]
+const NO_XATTR: i32 = libc::ENODATA;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A FUSE adapter that exposes a [`MonofsNFS`] filesystem through the kernel's FUSE interface.
+///
+/// The adapter delegates every operation to the same [`NFSFileSystem`] implementation used by
+/// the NFS server so both backends share identical semantics. FUSE inode numbers are the NFS
+/// file IDs shifted by one, since FUSE reserves inode `1` for the root directory while monofs
+/// uses file ID `0`.
+///
+/// Changes are made as the user and group of the FUSE request, which the audit log of the
+/// filesystem records, see [`MonofsNFS::enable_audit`].
+///
+/// FUSE callbacks are synchronous, so each one blocks on the provided tokio runtime handle.
+/// The adapter must therefore be driven from a thread that is not itself running async tasks,
+/// which is what [`fuser::spawn_mount2`] does.
+///
+/// The space reported to `statfs` is that of the host filesystem holding the directory given to
+/// [`with_stats_dir`](Self::with_stats_dir), narrowed down by the filesystem's quota.
+///
+/// Whole files copied with `copy_file_range`, which `cp` uses where the kernel supports it, are
+/// cloned with [`MonofsNFS::clone_file`] rather than read and written back. NFSv3 has no copy or
+/// clone operation, so over the NFS backend copies still go through the client.
+pub struct MonofsFuse<S>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    fs: MonofsNFS<S>,
+    runtime: Handle,
+    stats_dir: Option<PathBuf>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<S> MonofsFuse<S>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    /// Creates a new FUSE adapter around the given filesystem.
+    pub fn new(fs: MonofsNFS<S>, runtime: Handle) -> Self {
+        Self {
+            fs,
+            runtime,
+            stats_dir: None,
+        }
+    }
+
+    /// Reports the space of the host filesystem holding `dir`, usually the store directory, as
+    /// the space of the filesystem.
+    pub fn with_stats_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.stats_dir = Some(dir.into());
+        self
+    }
+
+    /// Blocks on `call` made as the user and group of `req`.
+    fn block_on_as<F>(&self, req: &Request<'_>, call: F) -> F::Output
+    where
+        F: Future,
+    {
+        self.runtime.block_on(call_as(req.uid(), req.gid(), call))
+    }
+
+    /// Looks up `name` in `parent` and returns its file ID and attributes.
+    fn lookup_entry(&self, parent: u64, name: &OsStr) -> Result<FileAttr, nfsstat3> {
+        let name = filename3::from(name.as_bytes());
+        self.runtime.block_on(async {
+            let id = self.fs.lookup(to_fileid(parent), &name).await?;
+            let attr = self.fs.getattr(id).await?;
+            Ok(to_file_attr(&attr))
+        })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<S> Filesystem for MonofsFuse<S>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        match self.lookup_entry(parent, name) {
+            Ok(attr) => reply.entry(&FUSE_TTL, &attr, 0),
+            Err(e) => reply.error(to_errno(e)),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        match self.runtime.block_on(self.fs.getattr(to_fileid(ino))) {
+            Ok(attr) => reply.attr(&FUSE_TTL, &to_file_attr(&attr)),
+            Err(e) => reply.error(to_errno(e)),
+        }
+    }
+
+    fn setattr(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        mode: Option<u32>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        size: Option<u64>,
+        atime: Option<TimeOrNow>,
+        mtime: Option<TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        _fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        let attr = sattr3 {
+            mode: mode.map_or(set_mode3::Void, |m| set_mode3::mode(m & 0o7777)),
+            uid: uid.map_or(set_uid3::Void, set_uid3::uid),
+            gid: gid.map_or(set_gid3::Void, set_gid3::gid),
+            size: size.map_or(set_size3::Void, set_size3::size),
+            atime: match atime {
+                None => set_atime::DONT_CHANGE,
+                Some(TimeOrNow::Now) => set_atime::SET_TO_SERVER_TIME,
+                Some(TimeOrNow::SpecificTime(t)) => set_atime::SET_TO_CLIENT_TIME(to_nfstime(t)),
+            },
+            mtime: match mtime {
+                None => set_mtime::DONT_CHANGE,
+                Some(TimeOrNow::Now) => set_mtime::SET_TO_SERVER_TIME,
+                Some(TimeOrNow::SpecificTime(t)) => set_mtime::SET_TO_CLIENT_TIME(to_nfstime(t)),
+            },
+        };
+
+        match self.block_on_as(req, self.fs.setattr(to_fileid(ino), attr)) {
+            Ok(attr) => reply.attr(&FUSE_TTL, &to_file_attr(&attr)),
+            Err(e) => reply.error(to_errno(e)),
+        }
+    }
+
+    fn readlink(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyData) {
+        match self.runtime.block_on(self.fs.readlink(to_fileid(ino))) {
+            Ok(target) => reply.data(&target),
+            Err(e) => reply.error(to_errno(e)),
+        }
+    }
+
+    fn mkdir(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        reply: ReplyEntry,
+    ) {
+        let name = filename3::from(name.as_bytes());
+        let result = self.block_on_as(req, async {
+            let (id, _) = self.fs.mkdir(to_fileid(parent), &name).await?;
+            let attr = sattr3 {
+                mode: set_mode3::mode(mode & !umask & 0o7777),
+                ..unchanged_attributes()
+            };
+            self.fs.setattr(id, attr).await
+        });
+
+        match result {
+            Ok(attr) => reply.entry(&FUSE_TTL, &to_file_attr(&attr), 0),
+            Err(e) => reply.error(to_errno(e)),
+        }
+    }
+
+    fn unlink(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let name = filename3::from(name.as_bytes());
+        match self.block_on_as(req, self.fs.remove(to_fileid(parent), &name)) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(to_errno(e)),
+        }
+    }
+
+    fn rmdir(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let name = filename3::from(name.as_bytes());
+        match self.block_on_as(req, self.fs.remove(to_fileid(parent), &name)) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(to_errno(e)),
+        }
+    }
+
+    fn symlink(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        link_name: &OsStr,
+        target: &Path,
+        reply: ReplyEntry,
+    ) {
+        let link_name = filename3::from(link_name.as_bytes());
+        let target = nfspath3::from(target.as_os_str().as_bytes());
+        let result = self.block_on_as(
+            req,
+            self.fs.symlink(
+                to_fileid(parent),
+                &link_name,
+                &target,
+                &unchanged_attributes(),
+            ),
+        );
+
+        match result {
+            Ok((_, attr)) => reply.entry(&FUSE_TTL, &to_file_attr(&attr), 0),
+            Err(e) => reply.error(to_errno(e)),
+        }
+    }
+
+    fn rename(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        _flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        let name = filename3::from(name.as_bytes());
+        let newname = filename3::from(newname.as_bytes());
+        let result = self.block_on_as(
+            req,
+            self.fs
+                .rename(to_fileid(parent), &name, to_fileid(newparent), &newname),
+        );
+
+        match result {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(to_errno(e)),
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        if offset < 0 {
+            return reply.error(libc::EINVAL);
+        }
+
+        match self
+            .runtime
+            .block_on(self.fs.read(to_fileid(ino), offset as u64, size))
+        {
+            Ok((data, _)) => reply.data(&data),
+            Err(e) => reply.error(to_errno(e)),
+        }
+    }
+
+    fn write(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        if offset < 0 {
+            return reply.error(libc::EINVAL);
+        }
+
+        match self.block_on_as(req, self.fs.write(to_fileid(ino), offset as u64, data)) {
+            Ok(_) => reply.written(data.len() as u32),
+            Err(e) => reply.error(to_errno(e)),
+        }
+    }
+
+    fn copy_file_range(
+        &mut self,
+        req: &Request<'_>,
+        ino_in: u64,
+        _fh_in: u64,
+        offset_in: i64,
+        ino_out: u64,
+        _fh_out: u64,
+        offset_out: i64,
+        len: u64,
+        _flags: u32,
+        reply: ReplyWrite,
+    ) {
+        if offset_in < 0 || offset_out < 0 {
+            return reply.error(libc::EINVAL);
+        }
+
+        let (src, dst) = (to_fileid(ino_in), to_fileid(ino_out));
+        let result: Result<u32, nfsstat3> = self.block_on_as(req, async {
+            // Copying a whole file over one that is no larger, as `cp` does, shares its content
+            if offset_in == 0 && offset_out == 0 && src != dst {
+                let size = self.fs.getattr(src).await?.size;
+                let dst_size = self.fs.getattr(dst).await?.size;
+                if len >= size && dst_size <= size && size <= u32::MAX as u64 {
+                    self.fs.clone_file(src, dst).await?;
+                    return Ok(size as u32);
+                }
+            }
+
+            // Anything else is copied a chunk at a time, and the caller asks for the rest
+            let len = len.min(FUSE_COPY_CHUNK_SIZE as u64) as u32;
+            let (data, _) = self.fs.read(src, offset_in as u64, len).await?;
+            if !data.is_empty() {
+                self.fs.write(dst, offset_out as u64, &data).await?;
+            }
+            Ok(data.len() as u32)
+        });
+
+        match result {
+            Ok(written) => reply.written(written),
+            Err(e) => reply.error(to_errno(e)),
+        }
+    }
+
+    fn fsync(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        _fh: u64,
+        _datasync: bool,
+        reply: ReplyEmpty,
+    ) {
+        // Buffered writes are applied to the tree; the tree itself is persisted by checkpoints
+        match self.runtime.block_on(self.fs.flush_writes()) {
+            Ok(()) => reply.ok(),
+            Err(e) => reply.error(to_errno(e.into())),
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let dirid = to_fileid(ino);
+        let result = self.runtime.block_on(self.fs.readdir(dirid, 0, usize::MAX));
+
+        let entries = match result {
+            Ok(result) => result.entries,
+            Err(e) => return reply.error(to_errno(e)),
+        };
+
+        // FUSE expects `.` and `..` to be listed by the filesystem itself. The parent inode is
+        // not tracked, so `..` points back at the directory; the kernel resolves it correctly.
+        let dot_entries = [
+            (ino, FileType::Directory, OsStr::new(".")),
+            (ino, FileType::Directory, OsStr::new("..")),
+        ];
+        let listing = dot_entries.into_iter().chain(entries.iter().map(|entry| {
+            (
+                to_ino(entry.fileid),
+                to_file_type(entry.attr.ftype),
+                OsStr::from_bytes(&entry.name),
+            )
+        }));
+
+        // The offset passed back to us is the index of the next entry to return.
+        for (index, (ino, kind, name)) in listing.enumerate().skip(offset as usize) {
+            if reply.add(ino, (index + 1) as i64, kind, name) {
+                break;
+            }
+        }
+
+        reply.ok();
+    }
+
+    fn create(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        _flags: i32,
+        reply: ReplyCreate,
+    ) {
+        let name = filename3::from(name.as_bytes());
+        let attr = sattr3 {
+            mode: set_mode3::mode(mode & !umask & 0o7777),
+            uid: set_uid3::uid(req.uid()),
+            gid: set_gid3::gid(req.gid()),
+            ..unchanged_attributes()
+        };
+
+        match self.block_on_as(req, self.fs.create(to_fileid(parent), &name, attr)) {
+            Ok((_, attr)) => reply.created(&FUSE_TTL, &to_file_attr(&attr), 0, 0, 0),
+            Err(e) => reply.error(to_errno(e)),
+        }
+    }
+
+    fn getxattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        size: u32,
+        reply: ReplyXattr,
+    ) {
+        let Some(name) = name.to_str() else {
+            return reply.error(libc::EINVAL);
+        };
+
+        match self
+            .runtime
+            .block_on(self.fs.get_xattr(to_fileid(ino), name))
+        {
+            Ok(Some(value)) => reply_xattr(reply, size, &value),
+            Ok(None) => reply.error(NO_XATTR),
+            Err(e) => reply.error(to_errno(e)),
+        }
+    }
+
+    fn setxattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        value: &[u8],
+        flags: i32,
+        position: u32,
+        reply: ReplyEmpty,
+    ) {
+        // Positions are only used for resource forks on macOS
+        if position != 0 {
+            return reply.error(libc::EINVAL);
+        }
+
+        let Some(name) = name.to_str() else {
+            return reply.error(libc::EINVAL);
+        };
+
+        let id = to_fileid(ino);
+        let result = self.runtime.block_on(async {
+            if flags & (libc::XATTR_CREATE | libc::XATTR_REPLACE) != 0 {
+                let exists = self.fs.get_xattr(id, name).await?.is_some();
+                if flags & libc::XATTR_CREATE != 0 && exists {
+                    return Ok(Err(libc::EEXIST));
+                }
+                if flags & libc::XATTR_REPLACE != 0 && !exists {
+                    return Ok(Err(NO_XATTR));
+                }
+            }
+
+            self.fs.set_xattr(id, name, value).await.map(Ok)
+        });
+
+        match result {
+            Ok(Ok(())) => reply.ok(),
+            Ok(Err(errno)) => reply.error(errno),
+            Err(e) => reply.error(to_errno(e)),
+        }
+    }
+
+    fn listxattr(&mut self, _req: &Request<'_>, ino: u64, size: u32, reply: ReplyXattr) {
+        match self.runtime.block_on(self.fs.list_xattrs(to_fileid(ino))) {
+            Ok(names) => reply_xattr(reply, size, &to_xattr_list(&names)),
+            Err(e) => reply.error(to_errno(e)),
+        }
+    }
+
+    fn removexattr(&mut self, _req: &Request<'_>, ino: u64, name: &OsStr, reply: ReplyEmpty) {
+        let Some(name) = name.to_str() else {
+            return reply.error(libc::EINVAL);
+        };
+
+        match self
+            .runtime
+            .block_on(self.fs.remove_xattr(to_fileid(ino), name))
+        {
+            Ok(true) => reply.ok(),
+            Ok(false) => reply.error(NO_XATTR),
+            Err(e) => reply.error(to_errno(e)),
+        }
+    }
+
+    fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyStatfs) {
+        let host = match &self.stats_dir {
+            Some(dir) => match FsStats::from_host(dir) {
+                Ok(host) => host,
+                Err(e) => {
+                    tracing::warn!("failed to get the space of {}: {}", dir.display(), e);
+                    return reply.error(libc::EIO);
+                }
+            },
+            None => FsStats::default(),
+        };
+
+        let stats = self.runtime.block_on(self.fs.get_fs_stats(host));
+        let block_size = u64::from(FUSE_BLOCK_SIZE);
+        reply.statfs(
+            stats.get_total_bytes() / block_size,
+            stats.get_free_bytes() / block_size,
+            stats.get_available_bytes() / block_size,
+            *stats.get_total_files(),
+            *stats.get_free_files(),
+            FUSE_BLOCK_SIZE,
+            u32::try_from(*stats.get_max_name_len()).unwrap_or(u32::MAX),
+            FUSE_BLOCK_SIZE,
+        );
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Converts a FUSE inode number to an NFS file ID.
+fn to_fileid(ino: u64) -> fileid3 {
+    ino - FUSE_ROOT_ID
+}
+
+/// Converts an NFS file ID to a FUSE inode number.
+fn to_ino(id: fileid3) -> u64 {
+    id + FUSE_ROOT_ID
+}
+
+/// Converts an NFS file type to a FUSE file type.
+fn to_file_type(ftype: ftype3) -> FileType {
+    match ftype {
+        ftype3::NF3REG => FileType::RegularFile,
+        ftype3::NF3DIR => FileType::Directory,
+        ftype3::NF3BLK => FileType::BlockDevice,
+        ftype3::NF3CHR => FileType::CharDevice,
+        ftype3::NF3LNK => FileType::Symlink,
+        ftype3::NF3SOCK => FileType::Socket,
+        ftype3::NF3FIFO => FileType::NamedPipe,
+    }
+}
+
+/// Converts an NFS timestamp to a system time.
+fn to_system_time(time: nfstime3) -> SystemTime {
+    UNIX_EPOCH + Duration::new(time.seconds as u64, time.nseconds)
+}
+
+/// Converts a system time to an NFS timestamp.
+fn to_nfstime(time: SystemTime) -> nfstime3 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    nfstime3 {
+        seconds: since_epoch.as_secs() as u32,
+        nseconds: since_epoch.subsec_nanos(),
+    }
+}
+
+/// Returns a set of NFS attributes that leaves every attribute unchanged.
+fn unchanged_attributes() -> sattr3 {
+    sattr3 {
+        mode: set_mode3::Void,
+        uid: set_uid3::Void,
+        gid: set_gid3::Void,
+        size: set_size3::Void,
+        atime: set_atime::DONT_CHANGE,
+        mtime: set_mtime::DONT_CHANGE,
+    }
+}
+
+/// Converts NFS attributes to FUSE attributes.
+fn to_file_attr(attr: &fattr3) -> FileAttr {
+    FileAttr {
+        ino: to_ino(attr.fileid),
+        size: attr.size,
+        blocks: attr.size.div_ceil(512),
+        atime: to_system_time(attr.atime),
+        mtime: to_system_time(attr.mtime),
+        ctime: to_system_time(attr.ctime),
+        crtime: to_system_time(attr.ctime),
+        kind: to_file_type(attr.ftype),
+        perm: (attr.mode & 0o7777) as u16,
+        nlink: attr.nlink,
+        uid: attr.uid,
+        gid: attr.gid,
+        rdev: 0,
+        blksize: FUSE_BLOCK_SIZE,
+        flags: 0,
+    }
+}
+
+/// Replies with an xattr value or list of names, following the `getxattr(2)` convention: a
+/// `size` of zero asks for the size of the data, and a buffer too small for it is an error.
+fn reply_xattr(reply: ReplyXattr, size: u32, data: &[u8]) {
+    if size == 0 {
+        reply.size(data.len() as u32);
+    } else if data.len() > size as usize {
+        reply.error(libc::ERANGE);
+    } else {
+        reply.data(data);
+    }
+}
+
+/// Encodes xattr names as the NUL-terminated list returned by `listxattr(2)`.
+fn to_xattr_list(names: &[String]) -> Vec<u8> {
+    names
+        .iter()
+        .flat_map(|name| name.bytes().chain([0]))
+        .collect()
+}
+
+/// Converts an NFS status code to the errno reported to the kernel.
+fn to_errno(status: nfsstat3) -> i32 {
+    match status {
+        nfsstat3::NFS3ERR_PERM => libc::EPERM,
+        nfsstat3::NFS3ERR_NOENT => libc::ENOENT,
+        nfsstat3::NFS3ERR_IO => libc::EIO,
+        nfsstat3::NFS3ERR_NXIO => libc::ENXIO,
+        nfsstat3::NFS3ERR_ACCES => libc::EACCES,
+        nfsstat3::NFS3ERR_EXIST => libc::EEXIST,
+        nfsstat3::NFS3ERR_XDEV => libc::EXDEV,
+        nfsstat3::NFS3ERR_NODEV => libc::ENODEV,
+        nfsstat3::NFS3ERR_NOTDIR => libc::ENOTDIR,
+        nfsstat3::NFS3ERR_ISDIR => libc::EISDIR,
+        nfsstat3::NFS3ERR_INVAL => libc::EINVAL,
+        nfsstat3::NFS3ERR_FBIG => libc::EFBIG,
+        nfsstat3::NFS3ERR_NOSPC => libc::ENOSPC,
+        nfsstat3::NFS3ERR_ROFS => libc::EROFS,
+        nfsstat3::NFS3ERR_MLINK => libc::EMLINK,
+        nfsstat3::NFS3ERR_NAMETOOLONG => libc::ENAMETOOLONG,
+        nfsstat3::NFS3ERR_NOTEMPTY => libc::ENOTEMPTY,
+        nfsstat3::NFS3ERR_DQUOT => libc::EDQUOT,
+        nfsstat3::NFS3ERR_STALE => libc::ESTALE,
+        nfsstat3::NFS3ERR_NOTSUPP => libc::ENOTSUP,
+        _ => libc::EIO,
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuse_inode_mapping() {
+        assert_eq!(to_ino(0), FUSE_ROOT_ID);
+        assert_eq!(to_fileid(FUSE_ROOT_ID), 0);
+        assert_eq!(to_fileid(to_ino(42)), 42);
+    }
+
+    #[test]
+    fn test_fuse_errno_mapping() {
+        assert_eq!(to_errno(nfsstat3::NFS3ERR_NOENT), libc::ENOENT);
+        assert_eq!(to_errno(nfsstat3::NFS3ERR_EXIST), libc::EEXIST);
+        assert_eq!(to_errno(nfsstat3::NFS3ERR_NOTDIR), libc::ENOTDIR);
+        assert_eq!(to_errno(nfsstat3::NFS3ERR_SERVERFAULT), libc::EIO);
+    }
+
+    #[test]
+    fn test_fuse_xattr_list() {
+        let names = vec!["user.a".to_string(), "security.selinux".to_string()];
+        assert_eq!(to_xattr_list(&names), b"user.a\0security.selinux\0");
+        assert!(to_xattr_list(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_fuse_time_roundtrip() {
+        let time = nfstime3 {
+            seconds: 1_700_000_000,
+            nseconds: 123,
+        };
+        let converted = to_nfstime(to_system_time(time));
+        assert_eq!(converted.seconds, time.seconds);
+        assert_eq!(converted.nseconds, time.nseconds);
+    }
+}
@@ -0,0 +1,410 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+};
+
+use nfsserve::{
+    tcp::{NFSTcp, NFSTcpListener},
+    vfs::NFSFileSystem,
+};
+use tokio::{
+    io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+};
+
+use crate::{config::ExportConfig, FsResult};
+
+use super::{
+    get_commit_failed_reply, is_commit_call, ConnectionThrottle, DiskSync, PermissionGate, Throttle,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The bit of a record marking header set on the last fragment of a record.
+const LAST_FRAGMENT: u32 = 0x8000_0000;
+
+/// The largest RPC record relayed a record at a time, well above the largest transfer size,
+/// beyond which the connection is closed.
+const MAX_RECORD_SIZE: usize = 4 * 1024 * 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Listens for NFS clients on an address, and serves the ones an [`ExportConfig`] allows as fast
+/// as a [`Throttle`] lets them.
+///
+/// `nfsserve` serves every connection it accepts, so a listener restricted to some clients, or
+/// limited in how fast it serves them, accepts connections itself, and relays the allowed ones
+/// to an `nfsserve` listener on the loopback interface. Connections from the host itself are allowed whatever the exports say,
+/// whether they come over the loopback interface or to the address listened on, as the
+/// filesystem's own mount is one of them.
+///
+/// If the exports check permissions, every call relayed goes through a [`PermissionGate`]
+/// first, which answers the calls the credentials they are made with do not permit. If the
+/// throttle limits anything, every call and reply relayed waits for the limits of the throttle,
+/// which `nfsserve` has no way to enforce itself, to allow it.
+///
+/// `nfsserve` also answers COMMIT calls without telling the filesystem, so a listener given a
+/// [`DiskSync`] relays every connection, and makes the writes of the filesystem durable before
+/// passing each COMMIT call on.
+pub(crate) enum NfsListener<T>
+where
+    T: NFSFileSystem + Clone + Send + Sync + 'static,
+{
+    /// Serves every client.
+    Open(NFSTcpListener<T>),
+
+    /// Serves the clients `exports` allows, checking their calls if it asks, throttling them, and
+    /// syncing the filesystem to disk on their commits.
+    Restricted {
+        /// Accepts the connections of clients.
+        listener: TcpListener,
+
+        /// Serves the connections relayed to it.
+        backend: NFSTcpListener<T>,
+
+        /// The address of the backend.
+        backend_addr: SocketAddr,
+
+        /// Which clients are allowed.
+        exports: ExportConfig,
+
+        /// Checks the calls of clients, if the exports check permissions.
+        gate: Option<Arc<PermissionGate<T>>>,
+
+        /// Limits how fast clients are served.
+        throttle: Throttle,
+
+        /// Makes the writes of the filesystem durable when clients commit them, if it is on disk.
+        disk_sync: Option<DiskSync>,
+    },
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<T> NfsListener<T>
+where
+    T: NFSFileSystem + Clone + Send + Sync + 'static,
+{
+    /// Listens on `addr` for the clients `exports` allows, serving `fs` to them as fast as
+    /// `throttle` lets them, and committing their writes with `disk_sync` if there is one.
+    pub(crate) async fn bind(
+        addr: &str,
+        fs: T,
+        exports: &ExportConfig,
+        throttle: &Throttle,
+        disk_sync: Option<DiskSync>,
+    ) -> FsResult<Self> {
+        if exports.get_allowed_clients().is_empty()
+            && !exports.get_check_permissions()
+            && !throttle.is_limited()
+            && disk_sync.is_none()
+        {
+            return Ok(Self::Open(NFSTcpListener::bind(addr, fs).await?));
+        }
+
+        let gate = exports
+            .get_check_permissions()
+            .then(|| Arc::new(PermissionGate::new(fs.clone(), exports.clone())));
+        let listener = TcpListener::bind(addr).await?;
+        let backend = NFSTcpListener::bind(&format!("{}:0", Ipv4Addr::LOCALHOST), fs).await?;
+        let backend_addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), backend.get_listen_port());
+
+        Ok(Self::Restricted {
+            listener,
+            backend,
+            backend_addr,
+            exports: exports.clone(),
+            gate,
+            throttle: throttle.clone(),
+            disk_sync,
+        })
+    }
+
+    /// Serves clients until an error stops the listener.
+    pub(crate) async fn handle_forever(&self) -> FsResult<()> {
+        match self {
+            Self::Open(listener) => Ok(listener.handle_forever().await?),
+            Self::Restricted {
+                listener,
+                backend,
+                backend_addr,
+                exports,
+                gate,
+                throttle,
+                disk_sync,
+            } => {
+                let relayed =
+                    relay_allowed(listener, *backend_addr, exports, gate, throttle, disk_sync);
+                tokio::select! {
+                    result = backend.handle_forever() => Ok(result?),
+                    result = relayed => result,
+                }
+            }
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Accepts connections on `listener`, relaying the ones `exports` allows to `backend_addr`, through
+/// `gate` if there is one, as fast as `throttle` lets them and committing with `disk_sync` if
+/// there is one, and closing the rest.
+async fn relay_allowed<T>(
+    listener: &TcpListener,
+    backend_addr: SocketAddr,
+    exports: &ExportConfig,
+    gate: &Option<Arc<PermissionGate<T>>>,
+    throttle: &Throttle,
+    disk_sync: &Option<DiskSync>,
+) -> FsResult<()>
+where
+    T: NFSFileSystem + Send + Sync + 'static,
+{
+    loop {
+        let (client, peer_addr) = listener.accept().await?;
+        let local_addr = client.local_addr()?;
+        if !is_local_client(peer_addr.ip(), local_addr.ip())
+            && !exports.is_client_allowed(peer_addr.ip())
+        {
+            tracing::warn!("refused NFS connection from {}", peer_addr);
+            continue;
+        }
+
+        let gate = gate.clone();
+        let throttle = throttle.is_limited().then(|| throttle.connection());
+        let disk_sync = disk_sync.clone();
+        tokio::spawn(async move {
+            let result = match (gate, throttle, disk_sync) {
+                (None, None, None) => relay(client, backend_addr).await,
+                (gate, throttle, disk_sync) => {
+                    relay_records(client, backend_addr, gate, throttle, disk_sync).await
+                }
+            };
+            if let Err(e) = result {
+                tracing::debug!(error = %e, "NFS connection from {} closed", peer_addr);
+            }
+        });
+    }
+}
+
+/// Relays the connection of a client to the server listening on `backend_addr` until either
+/// side closes it.
+async fn relay(mut client: TcpStream, backend_addr: SocketAddr) -> io::Result<()> {
+    let mut backend = TcpStream::connect(backend_addr).await?;
+    client.set_nodelay(true)?;
+    backend.set_nodelay(true)?;
+    io::copy_bidirectional(&mut client, &mut backend).await?;
+    Ok(())
+}
+
+/// Relays the connection of a client to the server listening on `backend_addr` a record at a
+/// time until either side closes it, passing each call through `gate` first if there is one, and
+/// waiting for `throttle` to allow each call and reply if there is one.
+///
+/// If there is a `disk_sync`, the writes of the filesystem are made durable before a COMMIT call
+/// is passed on, and the call is failed if they cannot be.
+///
+/// The gate answers some calls itself, so replies are relayed a whole record at a time, so that
+/// they never interleave with the ones it sends.
+async fn relay_records<T>(
+    client: TcpStream,
+    backend_addr: SocketAddr,
+    gate: Option<Arc<PermissionGate<T>>>,
+    throttle: Option<ConnectionThrottle>,
+    disk_sync: Option<DiskSync>,
+) -> io::Result<()>
+where
+    T: NFSFileSystem + Send + Sync + 'static,
+{
+    let backend = TcpStream::connect(backend_addr).await?;
+    client.set_nodelay(true)?;
+    backend.set_nodelay(true)?;
+    let (mut client_read, client_write) = client.into_split();
+    let (mut backend_read, mut backend_write) = backend.into_split();
+    let client_write = Mutex::new(client_write);
+
+    let calls = async {
+        while let Some(record) = read_record(&mut client_read).await? {
+            if let Some(throttle) = &throttle {
+                throttle.admit_call(record.len()).await;
+            }
+
+            let mut reply = match &gate {
+                Some(gate) => gate.check(&record).await,
+                None => None,
+            };
+            if let Some(disk_sync) = disk_sync.as_ref().filter(|_| reply.is_none()) {
+                if is_commit_call(&record) {
+                    if let Err(e) = disk_sync.commit().await {
+                        tracing::error!(error = %e, "failed to commit writes to disk");
+                        reply = Some(get_commit_failed_reply(&record));
+                    }
+                }
+            }
+            match reply {
+                Some(reply) => write_record(&mut *client_write.lock().await, &reply).await?,
+                None => write_record(&mut backend_write, &record).await?,
+            }
+        }
+        Ok::<_, io::Error>(())
+    };
+
+    let replies = async {
+        while let Some(record) = read_record(&mut backend_read).await? {
+            if let Some(throttle) = &throttle {
+                throttle.admit_reply(record.len()).await;
+            }
+
+            write_record(&mut *client_write.lock().await, &record).await?;
+        }
+        Ok::<_, io::Error>(())
+    };
+
+    tokio::select! {
+        result = calls => result,
+        result = replies => result,
+    }
+}
+
+/// Reads an RPC record made of one or more fragments, returning `None` if the stream ends before
+/// it starts.
+async fn read_record(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Option<Vec<u8>>> {
+    let mut record = Vec::new();
+    loop {
+        let header = match reader.read_u32().await {
+            Ok(header) => header,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && record.is_empty() => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e),
+        };
+
+        let start = record.len();
+        let len = (header & !LAST_FRAGMENT) as usize;
+        if start + len > MAX_RECORD_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("RPC record larger than {} bytes", MAX_RECORD_SIZE),
+            ));
+        }
+
+        record.resize(start + len, 0);
+        reader.read_exact(&mut record[start..]).await?;
+        if header & LAST_FRAGMENT != 0 {
+            return Ok(Some(record));
+        }
+    }
+}
+
+/// Writes `record` as an RPC record of a single fragment.
+async fn write_record(writer: &mut (impl AsyncWrite + Unpin), record: &[u8]) -> io::Result<()> {
+    let header = LAST_FRAGMENT | record.len() as u32;
+    writer
+        .write_all(&[&header.to_be_bytes()[..], record].concat())
+        .await
+}
+
+/// Returns whether a connection from `peer` to `local` comes from the host itself.
+fn is_local_client(peer: IpAddr, local: IpAddr) -> bool {
+    let peer = peer.to_canonical();
+    peer.is_loopback() || peer == local.to_canonical()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::MemoryStore;
+
+    use crate::{
+        config::{ClientNetwork, ThrottleConfig},
+        server::MemoryMonofsNFS,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_nfs_listener_restriction() -> anyhow::Result<()> {
+        let fs = MemoryMonofsNFS::new(MemoryStore::default());
+        let throttle = Throttle::default();
+        let listener = NfsListener::bind(
+            "127.0.0.1:0",
+            fs.clone(),
+            &ExportConfig::default(),
+            &throttle,
+            None,
+        )
+        .await?;
+        assert!(matches!(listener, NfsListener::Open(_)));
+
+        let exports = ExportConfig::builder()
+            .allowed_clients(vec!["203.0.113.0/24".parse::<ClientNetwork>()?])
+            .build();
+        let listener =
+            NfsListener::bind("127.0.0.1:0", fs.clone(), &exports, &throttle, None).await?;
+        assert!(matches!(
+            listener,
+            NfsListener::Restricted { gate: None, .. }
+        ));
+
+        // Permissions are checked on every connection, the host's own included
+        let exports = ExportConfig::builder().check_permissions(true).build();
+        let listener =
+            NfsListener::bind("127.0.0.1:0", fs.clone(), &exports, &throttle, None).await?;
+        assert!(matches!(
+            listener,
+            NfsListener::Restricted { gate: Some(_), .. }
+        ));
+
+        // Throttled listeners relay every connection, without checking permissions
+        let throttle = Throttle::new(ThrottleConfig::builder().max_ops_per_sec(Some(100)).build());
+        let listener =
+            NfsListener::bind("127.0.0.1:0", fs, &ExportConfig::default(), &throttle, None).await?;
+        assert!(matches!(
+            listener,
+            NfsListener::Restricted { gate: None, .. }
+        ));
+
+        // Records survive being split into fragments
+        let mut stream = Vec::new();
+        stream.extend(4u32.to_be_bytes());
+        stream.extend([1, 2, 3, 4]);
+        stream.extend((LAST_FRAGMENT | 2).to_be_bytes());
+        stream.extend([5, 6]);
+        let mut reader = stream.as_slice();
+        assert_eq!(
+            read_record(&mut reader).await?,
+            Some(vec![1, 2, 3, 4, 5, 6])
+        );
+        assert_eq!(read_record(&mut reader).await?, None);
+
+        let mut written = Vec::new();
+        write_record(&mut written, &[1, 2]).await?;
+        assert_eq!(
+            read_record(&mut written.as_slice()).await?,
+            Some(vec![1, 2])
+        );
+
+        // The host itself is allowed over any of its addresses
+        assert!(is_local_client("127.0.0.1".parse()?, "127.0.0.1".parse()?));
+        assert!(is_local_client("::1".parse()?, "192.0.2.1".parse()?));
+        assert!(is_local_client(
+            "::ffff:10.0.0.2".parse()?,
+            "10.0.0.2".parse()?
+        ));
+        assert!(!is_local_client("10.0.0.3".parse()?, "10.0.0.2".parse()?));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use ipldstore::ipld::cid::Cid;
+use nfsserve::nfs::fattr3;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How many lookups the NFS server remembers.
+pub(crate) const LOOKUP_CACHE_CAPACITY: usize = 64 * 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Remembers what names resolve to in directories, including names that do not exist.
+///
+/// Lookups are keyed by the CID of the directory plus the name. A directory's CID fully describes
+/// its entries and their attributes, so a cached lookup stays correct for as long as the CID
+/// describes the directory. That holds until the directory or anything below it changes, after
+/// which its CID is marked as dirty and ignored until the tree is reloaded from the store. The
+/// same key also serves identical directories found elsewhere in the tree, and lookups survive
+/// checkpoints for the directories they did not touch.
+///
+/// The least recently used lookups are evicted once the cache holds its capacity.
+#[derive(Debug)]
+pub(crate) struct LookupCache {
+    /// The most lookups the cache holds.
+    capacity: usize,
+
+    /// The attributes each name resolves to, or `None` if it does not exist, along with when the
+    /// lookup was last used.
+    entries: HashMap<(Cid, String), (u64, Option<fattr3>)>,
+
+    /// The cached lookups by when they were last used.
+    recency: BTreeMap<u64, (Cid, String)>,
+
+    /// Increases with every use of the cache.
+    tick: u64,
+
+    /// The CIDs of directories that changed since they were loaded.
+    dirty: HashSet<Cid>,
+
+    /// How many lookups were answered by the cache.
+    hits: u64,
+
+    /// How many lookups were not cached.
+    misses: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl LookupCache {
+    /// Creates an empty cache that holds up to `capacity` lookups.
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            dirty: HashSet::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Gets what `name` resolves to in the directory with CID `dir`.
+    ///
+    /// Returns `None` if the lookup is not cached, and `Some(None)` if the name is known not to
+    /// exist.
+    pub(crate) fn get(&mut self, dir: &Cid, name: &str) -> Option<Option<fattr3>> {
+        let key = (*dir, name.to_string());
+        let entry = match self.entries.get_mut(&key) {
+            Some(entry) if !self.dirty.contains(dir) => entry,
+            _ => {
+                self.misses += 1;
+                return None;
+            }
+        };
+        let (used, attrs) = entry;
+        let attrs = *attrs;
+        self.hits += 1;
+
+        self.tick += 1;
+        self.recency.remove(used);
+        self.recency.insert(self.tick, key);
+        *used = self.tick;
+
+        Some(attrs)
+    }
+
+    /// Remembers that `name` resolves to `attrs` in the directory with CID `dir`, or does not
+    /// exist if `attrs` is `None`.
+    pub(crate) fn insert(&mut self, dir: Cid, name: &str, attrs: Option<fattr3>) {
+        if self.capacity == 0 || self.dirty.contains(&dir) {
+            return;
+        }
+
+        self.tick += 1;
+        let key = (dir, name.to_string());
+        if let Some((used, _)) = self.entries.insert(key.clone(), (self.tick, attrs)) {
+            self.recency.remove(&used);
+        }
+        self.recency.insert(self.tick, key);
+
+        while self.entries.len() > self.capacity {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+
+    /// Stops answering lookups in the directory with CID `dir`, which has changed.
+    pub(crate) fn invalidate(&mut self, dir: &Cid) {
+        self.dirty.insert(*dir);
+    }
+
+    /// Forgets which directories changed, after the tree was reloaded from the store.
+    ///
+    /// Cached lookups are kept, as they were only cached while their CIDs described their
+    /// directories.
+    pub(crate) fn reset_dirty(&mut self) {
+        self.dirty.clear();
+    }
+
+    /// Returns how many lookups were answered by the cache, and how many were not.
+    pub(crate) fn get_hits_and_misses(&self) -> (u64, u64) {
+        (self.hits, self.misses)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::{IpldStore, MemoryStore};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lookup_cache() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let first = store.put_bytes(b"first".as_slice()).await?;
+        let second = store.put_bytes(b"second".as_slice()).await?;
+
+        let mut cache = LookupCache::new(2);
+        cache.insert(first, "missing", None);
+        cache.insert(first, "found", Some(fattr3::default()));
+        assert!(matches!(cache.get(&first, "missing"), Some(None)));
+        assert!(matches!(cache.get(&first, "found"), Some(Some(_))));
+        assert!(cache.get(&second, "found").is_none());
+
+        // The least recently used lookup is evicted
+        cache.get(&first, "missing");
+        cache.insert(second, "other", None);
+        assert!(cache.get(&first, "found").is_none());
+        assert!(matches!(cache.get(&first, "missing"), Some(None)));
+
+        // Lookups in changed directories are ignored until the tree is reloaded
+        cache.invalidate(&first);
+        assert!(cache.get(&first, "missing").is_none());
+        cache.insert(first, "new", None);
+        cache.reset_dirty();
+        assert!(matches!(cache.get(&first, "missing"), Some(None)));
+        assert!(cache.get(&first, "new").is_none());
+        assert_eq!(cache.get_hits_and_misses(), (5, 4));
+
+        Ok(())
+    }
+}
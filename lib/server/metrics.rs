@@ -0,0 +1,278 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An operation clients perform on a filesystem, counted and timed by the server.
+///
+/// FUSE requests are served by the same operations, so they are counted under the NFS operation
+/// they map to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NfsOp {
+    /// Resolving a name in a directory.
+    Lookup,
+
+    /// Reading the attributes of an entity.
+    Getattr,
+
+    /// Changing the attributes of an entity.
+    Setattr,
+
+    /// Reading from a file.
+    Read,
+
+    /// Writing to a file.
+    Write,
+
+    /// Creating a file.
+    Create,
+
+    /// Creating a file that must not exist yet.
+    CreateExclusive,
+
+    /// Creating a directory.
+    Mkdir,
+
+    /// Removing an entity.
+    Remove,
+
+    /// Moving an entity.
+    Rename,
+
+    /// Listing a directory.
+    Readdir,
+
+    /// Creating a symbolic link.
+    Symlink,
+
+    /// Reading the target of a symbolic link.
+    Readlink,
+}
+
+/// Counts and times the operations a filesystem serves, along with how often its root changes.
+///
+/// The counters are updated without locking, so the metrics can be read while the filesystem is
+/// busy.
+#[derive(Debug, Default)]
+pub(crate) struct ServerMetrics {
+    /// The counters of each operation, indexed by [`NfsOp`].
+    ops: [OpCounters; NfsOp::ALL.len()],
+
+    /// How many times the root was checkpointed to the store.
+    checkpoints: AtomicU64,
+
+    /// How many times the root was replaced by a stored one.
+    root_restores: AtomicU64,
+}
+
+/// How often an operation was served and how long it took.
+#[derive(Debug, Default)]
+struct OpCounters {
+    /// How many times the operation was served.
+    count: AtomicU64,
+
+    /// How long serving the operation took in total, in microseconds.
+    duration_micros: AtomicU64,
+}
+
+/// Times an operation and records it in [`ServerMetrics`] when dropped, however the operation
+/// ends.
+pub(crate) struct OpTimer<'a> {
+    /// The counters to record the operation in.
+    counters: &'a OpCounters,
+
+    /// When the operation started.
+    started_at: Instant,
+}
+
+/// How often an operation was served and how long it took, as reported by a server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct OpStats {
+    /// The operation.
+    op: NfsOp,
+
+    /// How many times the operation was served.
+    count: u64,
+
+    /// How long serving the operation took in total, in microseconds.
+    duration_micros: u64,
+}
+
+/// The metrics of a running filesystem, as reported over its control socket.
+///
+/// Every value counts up from when the server started.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct MetricsSnapshot {
+    /// The operations served, in the order of [`NfsOp::ALL`].
+    ops: Vec<OpStats>,
+
+    /// How many lookups were answered from the lookup cache.
+    lookup_cache_hits: u64,
+
+    /// How many lookups had to load directories from the store.
+    lookup_cache_misses: u64,
+
+    /// How many reads were served from data prefetched by read-ahead.
+    read_ahead_hits: u64,
+
+    /// How many reads had to wait on the store.
+    read_ahead_misses: u64,
+
+    /// How many times the root was checkpointed to the store.
+    checkpoints: u64,
+
+    /// How many times the root was replaced by a stored one, e.g. by restoring a snapshot.
+    root_restores: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl NfsOp {
+    /// Every operation.
+    pub const ALL: [NfsOp; 13] = [
+        NfsOp::Lookup,
+        NfsOp::Getattr,
+        NfsOp::Setattr,
+        NfsOp::Read,
+        NfsOp::Write,
+        NfsOp::Create,
+        NfsOp::CreateExclusive,
+        NfsOp::Mkdir,
+        NfsOp::Remove,
+        NfsOp::Rename,
+        NfsOp::Readdir,
+        NfsOp::Symlink,
+        NfsOp::Readlink,
+    ];
+}
+
+impl ServerMetrics {
+    /// Starts timing a single `op`, which is recorded once the returned timer is dropped.
+    pub(crate) fn time(&self, op: NfsOp) -> OpTimer<'_> {
+        OpTimer {
+            counters: &self.ops[op as usize],
+            started_at: Instant::now(),
+        }
+    }
+
+    /// Records that the root was checkpointed to the store.
+    pub(crate) fn record_checkpoint(&self) {
+        self.checkpoints.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that the root was replaced by a stored one.
+    pub(crate) fn record_root_restore(&self) {
+        self.root_restores.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the metrics counted so far, along with the hits and misses of the lookup cache and
+    /// read-ahead, which count those themselves.
+    pub(crate) fn snapshot(
+        &self,
+        (lookup_cache_hits, lookup_cache_misses): (u64, u64),
+        (read_ahead_hits, read_ahead_misses): (u64, u64),
+    ) -> MetricsSnapshot {
+        let ops = NfsOp::ALL
+            .iter()
+            .map(|op| {
+                let counters = &self.ops[*op as usize];
+                OpStats {
+                    op: *op,
+                    count: counters.count.load(Ordering::Relaxed),
+                    duration_micros: counters.duration_micros.load(Ordering::Relaxed),
+                }
+            })
+            .collect();
+
+        MetricsSnapshot {
+            ops,
+            lookup_cache_hits,
+            lookup_cache_misses,
+            read_ahead_hits,
+            read_ahead_misses,
+            checkpoints: self.checkpoints.load(Ordering::Relaxed),
+            root_restores: self.root_restores.load(Ordering::Relaxed),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Drop for OpTimer<'_> {
+    fn drop(&mut self) {
+        let elapsed = self.started_at.elapsed().as_micros() as u64;
+        self.counters.count.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .duration_micros
+            .fetch_add(elapsed, Ordering::Relaxed);
+    }
+}
+
+impl fmt::Display for NfsOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NfsOp::Lookup => write!(f, "lookup"),
+            NfsOp::Getattr => write!(f, "getattr"),
+            NfsOp::Setattr => write!(f, "setattr"),
+            NfsOp::Read => write!(f, "read"),
+            NfsOp::Write => write!(f, "write"),
+            NfsOp::Create => write!(f, "create"),
+            NfsOp::CreateExclusive => write!(f, "create_exclusive"),
+            NfsOp::Mkdir => write!(f, "mkdir"),
+            NfsOp::Remove => write!(f, "remove"),
+            NfsOp::Rename => write!(f, "rename"),
+            NfsOp::Readdir => write!(f, "readdir"),
+            NfsOp::Symlink => write!(f, "symlink"),
+            NfsOp::Readlink => write!(f, "readlink"),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_metrics_snapshot() {
+        let metrics = ServerMetrics::default();
+        drop(metrics.time(NfsOp::Read));
+        drop(metrics.time(NfsOp::Read));
+        drop(metrics.time(NfsOp::Readlink));
+        metrics.record_checkpoint();
+
+        let snapshot = metrics.snapshot((3, 1), (0, 2));
+        assert_eq!(snapshot.ops.len(), NfsOp::ALL.len());
+        for stats in &snapshot.ops {
+            let expected = match stats.op {
+                NfsOp::Read => 2,
+                NfsOp::Readlink => 1,
+                _ => 0,
+            };
+            assert_eq!(stats.count, expected, "{}", stats.op);
+        }
+
+        assert_eq!(snapshot.lookup_cache_hits, 3);
+        assert_eq!(snapshot.read_ahead_misses, 2);
+        assert_eq!(snapshot.checkpoints, 1);
+        assert_eq!(snapshot.root_restores, 0);
+    }
+}
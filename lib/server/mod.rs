@@ -16,6 +16,13 @@
 //! - [`DiskMonofsNFS`]: A convenience type alias for a MonofsServer using filesystem-based storage.
 //!   This is the recommended type for production use.
 //!
+//! - [`MonofsFuseServer`]: An alternative to the NFS server that mounts the filesystem directly
+//!   through FUSE using the [`MonofsFuse`] adapter. It avoids the loopback NFS mount and the
+//!   external `mount`/`umount` binaries.
+//!
+//! - [`ControlServer`]: Serves management requests, such as checkpointing the root for a
+//!   snapshot, over a unix socket next to the filesystem's data.
+//!
 //! # Features
 //!
 //! - Content-addressed storage for efficient deduplication and versioning
@@ -58,12 +65,40 @@
 //! All operations are implemented in a thread-safe manner, allowing concurrent access
 //! from multiple NFS clients.
 
+mod auth;
+mod control;
+mod disksync;
+mod fuse;
+mod listener;
+mod lookup;
+mod metrics;
 mod nfs;
+mod quota;
+mod readahead;
 mod server;
+mod shared;
+mod stats;
+mod throttle;
+mod watch;
+mod writeback;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
 //--------------------------------------------------------------------------------------------------
 
+pub(crate) use auth::*;
+pub use control::*;
+pub(crate) use disksync::*;
+pub use fuse::*;
+pub(crate) use listener::*;
+pub(crate) use lookup::*;
+pub use metrics::*;
 pub use nfs::*;
+pub(crate) use quota::*;
+pub(crate) use readahead::*;
 pub use server::*;
+pub(crate) use shared::*;
+pub use stats::*;
+pub(crate) use throttle::*;
+pub use watch::*;
+pub(crate) use writeback::*;
@@ -1,33 +1,54 @@
 use std::{
     collections::HashMap,
+    future::Future,
     str,
-    sync::{
-        atomic::{AtomicU64, Ordering},
-        Arc,
-    },
+    sync::{atomic::AtomicU64, Arc},
 };
 
+use async_recursion::async_recursion;
 use async_trait::async_trait;
-use chrono::{TimeZone, Utc};
+use chrono::{DateTime, TimeZone, Utc};
+use futures::{stream, StreamExt};
 use getset::Getters;
 use intaglio::{Symbol, SymbolTable};
-use ipldstore::{ipld::ipld::Ipld, IpldStore, IpldStoreSeekable, MemoryStore, Storable};
+use ipldstore::{
+    codetable::{Code, MultihashDigest},
+    ipld::cid::Cid,
+    IpldStore, IpldStoreSeekable, MemoryStore, Storable, StoreError,
+};
 use nfsserve::{
     nfs::{
-        fattr3, fileid3, filename3, ftype3, nfspath3, nfsstat3, nfstime3, sattr3, set_atime,
-        set_gid3, set_mode3, set_mtime, set_size3, set_uid3, specdata3,
+        fattr3, fileid3, filename3, fsinfo3, ftype3, nfs_fh3, nfspath3, nfsstat3, nfstime3,
+        post_op_attr, sattr3, set_atime, set_gid3, set_mode3, set_mtime, set_size3, set_uid3,
+        specdata3, FSF_CANSETTIME, FSF_HOMOGENEOUS, FSF_SYMLINK,
     },
     vfs::{DirEntry, NFSFileSystem, ReadDirResult, VFSCapabilities},
 };
-use tokio::sync::Mutex;
+use nix::unistd;
+use tokio::{
+    io::{AsyncSeekExt, AsyncWriteExt, SeekFrom},
+    sync::{broadcast, Mutex},
+};
+use typed_path::{Utf8UnixPath, Utf8UnixPathBuf};
 
 use crate::{
+    config::{
+        AtimePolicy, ExportConfig, QuotaConfig, SquashMode, TransferConfig, WriteBackConfig,
+        NFS_TRANSFER_SIZE_MULTIPLE,
+    },
     filesystem::{
-        Dir, Entity, EntityType, File, Metadata, SymPathLink, UNIX_ATIME_KEY, UNIX_GID_KEY,
-        UNIX_MODE_KEY, UNIX_UID_KEY,
+        Dir, DirCursor, Entity, EntityType, File, FollowPolicy, Metadata, OpenFlags, SymPathLink,
+        Utf8UnixPathSegment, DIR_LOAD_CONCURRENCY,
     },
+    management::{self, AuditEvent, AuditOperation, QuotaUsage},
     store::FlatFsStore,
-    FsError,
+    FsError, FsResult,
+};
+
+use super::{
+    ChangeEvent, ChangeKind, FsStats, LookupCache, MetricsSnapshot, NfsOp, PendingWrite,
+    QuotaTracker, ReadAhead, ServerMetrics, WriteBackCache, CHANGE_CHANNEL_CAPACITY,
+    LOOKUP_CACHE_CAPACITY, READ_AHEAD_CONCURRENCY,
 };
 
 //--------------------------------------------------------------------------------------------------
@@ -46,6 +67,28 @@ pub const DEFAULT_DIR_MODE: u32 = 0o755;
 /// Equivalent to 777 in octal (rwxrwxrwx).
 pub const DEFAULT_SYMLINK_MODE: u32 = 0o777;
 
+/// The xattr that reads as the CID of an entity as it currently stands. See [`Entity::get_cid`].
+pub const CID_XATTR_NAME: &str = "user.monofs.cid";
+
+/// The xattr that reads as the CID of the content of a file, which only empty files and other
+/// entities lack. See [`File::get_content`].
+pub const CONTENT_CID_XATTR_NAME: &str = "user.monofs.content_cid";
+
+/// The largest file ID derived from a path, which leaves room for FUSE to number inodes from 1.
+const MAX_FILEID: fileid3 = fileid3::MAX >> 1;
+
+/// The length of the file handles `nfsserve` gives out by default, which start with the time
+/// the server started.
+const LEGACY_FILE_HANDLE_LEN: usize = 16;
+
+/// The largest file size advertised to clients, which is what `nfsserve` advertises by default.
+const MAX_FILE_SIZE: u64 = 128 * 1024 * 1024 * 1024;
+
+tokio::task_local! {
+    /// The user and group the task calling the filesystem acts as, see [`call_as`].
+    static CALLER: (u32, u32);
+}
+
 //--------------------------------------------------------------------------------------------------
 // Types
 //--------------------------------------------------------------------------------------------------
@@ -58,6 +101,19 @@ pub type MemoryMonofsNFS = MonofsNFS<MemoryStore>;
 /// This is the recommended type for production use.
 pub type DiskMonofsNFS = MonofsNFS<FlatFsStore>;
 
+/// The whole tree of a server that serves a subtree of it, see [`MonofsNFS::set_subtree`].
+#[derive(Debug)]
+struct ServedSubtree<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    /// The path of the served directory in the tree.
+    path: Utf8UnixPathBuf,
+
+    /// The tree, which the served directory is written back into when it is checkpointed.
+    tree: Dir<S>,
+}
+
 /// An implementation of the NFSv3 server interface backed by a content-addressed store.
 ///
 /// MonofsNFS provides an NFSv3 server implementation that stores all file system
@@ -82,6 +138,134 @@ pub type DiskMonofsNFS = MonofsNFS<FlatFsStore>;
 /// - Sparse files are not supported. Attempts to write data beyond the current file size
 ///   will result in an INVAL error. All writes must be contiguous with existing data or
 ///   start at offset 0 for new files.
+/// - NFSv3 has no operations for extended attributes (xattrs), so they are only available
+///   through the FUSE backend, which uses [`get_xattr`](Self::get_xattr) and related methods.
+///   NFS clients on macOS fall back to storing them in `._` AppleDouble files, which monofs keeps
+///   like any other file.
+///
+/// ## Read-ahead
+///
+/// Reads that continue where the previous read of the same file ended are detected, and the next
+/// few ranges of the file are fetched from the store concurrently in the background, so reading a
+/// file from start to end is not bound by the store's latency for each range in turn.
+///
+/// ## Directory listings
+///
+/// READDIR and READDIRPLUS list the names of a directory without loading its entries, and then
+/// load the entries of the requested page from the store concurrently. The attributes they return
+/// are kept in the lookup cache, so the LOOKUP and GETATTR calls a client like `ls -l` makes for
+/// each listed entry afterwards are answered without going back to the store.
+///
+/// ## Transfer sizes
+///
+/// FSINFO advertises the sizes given to [`with_transfer`](Self::with_transfer) as the largest and
+/// preferred sizes of a READ, a WRITE and a READDIR, which is what clients size their requests
+/// by unless they are mounted with sizes of their own. Reads of more than the read size are served
+/// short, as clients are told not to make them.
+///
+/// ## Write-back
+///
+/// A server created with [`with_write_back`](Self::with_write_back) acknowledges sequential
+/// writes as soon as they are buffered, and applies them to the file in one go later on, which is
+/// what an NFS client expects from UNSTABLE writes followed by a COMMIT. `nfsserve` does not pass
+/// COMMIT on to the filesystem and reports every write as FILE_SYNC, so buffered writes are
+/// flushed by the server instead: when the buffer is full, when a write has waited for the flush
+/// interval (see [`flush_expired_writes`](Self::flush_expired_writes)), before any operation that
+/// needs the stored content, and on [`checkpoint`](Self::checkpoint). The root itself is only
+/// durable once checkpointed, so buffering does not widen what a crash of the server loses. The
+/// listener of a filesystem on disk checkpoints it on COMMIT, and flushes its blocks and fs
+/// database to disk before passing the COMMIT on.
+///
+/// ## Appends
+///
+/// Writes at or past the end of a file, like those of a log being written, only chunk the last
+/// chunk of the file again along with the written data, instead of streaming the whole file
+/// through the store. See [`File::append`].
+///
+/// ## Temporary files
+///
+/// Compilers and databases create files with CREATE EXCLUSIVE, write them and then rename them
+/// into place or remove them, much like files opened with `O_TMPFILE` that are linked or closed.
+/// With write-back, the buffered writes of a file created this way are held, and not flushed when
+/// the flush interval passes, until the file is renamed. Removing the file before then discards
+/// them, so its content never reaches the store. A file stops being held once it has not been
+/// written to for the flush interval, and only so many files are held at once, so files that are
+/// never renamed or removed are flushed like any other. Held writes are also flushed when the
+/// buffer is full and on [`checkpoint`](Self::checkpoint), which the listener of a filesystem on
+/// disk does on COMMIT. See [`TempFile`](crate::filesystem::TempFile) for the same in the entity
+/// API.
+///
+/// ## Quota
+///
+/// A server given a quota with [`set_quota`](Self::set_quota) tracks the total size of its files
+/// and the bytes its store takes up, and refuses writes that would take either past its limit
+/// with `NFS3ERR_DQUOT` and `NFS3ERR_NOSPC` respectively. Once the store is full, new files,
+/// directories and symlinks are refused too.
+///
+/// [`get_fs_stats`](Self::get_fs_stats) narrows the space of the host down to what the quota
+/// leaves of it, which is what `df` reports inside a FUSE mount. `nfsserve` answers FSSTAT itself
+/// without asking the filesystem, so NFS clients still see its fixed values.
+///
+/// ## Symbolic links
+///
+/// By default LOOKUP returns symbolic links as they are and leaves following them to the client,
+/// which may follow a [`SymPathLink`] anywhere on its own side. A server given a
+/// [`FollowPolicy`] that follows links with [`set_follow_policy`](Self::set_follow_policy)
+/// resolves the [`SymPathLink`]s it looks up itself instead, and returns what they lead to, so
+/// clients never see them. Links that lead outside of the filesystem are then refused with
+/// `NFS3ERR_ACCES`. NFS clients cannot go into a `SymCidLink`, so LOOKUP still returns it as a
+/// link.
+///
+/// ## Timestamps
+///
+/// Every change to an entity updates its change time, and changes to its content, or to the
+/// entries of a directory, update its modification time too. Both are reported with nanosecond
+/// precision, so build tools comparing them see every change. Buffered writes count as made when
+/// they are acknowledged, not when they are flushed. Moving an entity only updates its change
+/// time, as with `rename(2)`.
+///
+/// Reads record access times as the [`AtimePolicy`] given to
+/// [`set_atime_policy`](Self::set_atime_policy) asks, which is never by default. Recording one
+/// changes the file like any other update, so it is written to the store with the next
+/// checkpoint. Entities without a recorded access time report their creation time.
+///
+/// ## File handles
+///
+/// The file ID of a path is derived from a hash of the path, and the handle of a file is its ID,
+/// so a server hands out the same handle for the same path every time it starts. A restarted
+/// server can only map a handle back to its path once it has seen the path, so the paths behind
+/// the IDs it registers can be taken with [`take_new_file_ids`](Self::take_new_file_ids) and
+/// given back to it after a restart with [`restore_file_ids`](Self::restore_file_ids). Clients
+/// then carry on with the handles they hold instead of getting `NFS3ERR_STALE` for them.
+///
+/// ## Subtrees
+///
+/// A server given a path with [`set_subtree`](Self::set_subtree) serves the directory at that
+/// path as its root, like `chroot`, and nothing above it is visible to clients. Roots passed to
+/// [`set_root`](Self::set_root) and returned by [`checkpoint`](Self::checkpoint) are still those
+/// of the whole tree: the served directory is taken out of the tree when it is loaded, and written
+/// back into it when it is checkpointed, so the rest of the tree is kept as it is. Symbolic links
+/// are resolved within the served directory, so links leading above it are refused like links
+/// leading outside of the filesystem.
+///
+/// ## Ownership
+///
+/// Entities belong to the owner clients set, and those without one to the user running the
+/// server. A server given an [`ExportConfig`] with [`with_exports`](Self::with_exports) squashes
+/// the owners clients set: with [`SquashMode::Root`], an owner or group of 0 becomes the
+/// anonymous user or group of the config, and with [`SquashMode::All`], every owner does, and
+/// every entity clients create belongs to the anonymous user and group. Owners recorded before
+/// are kept until a client sets new ones.
+///
+/// ## Tracing
+///
+/// Every NFS operation runs in a `debug` span named after it, e.g. `nfs.read`, carrying the file
+/// IDs and names it acts on and the bytes it read or wrote. A subscriber records how long each
+/// operation took from its span, see [`Telemetry`](crate::runtime::Telemetry) for the one `mfsrun`
+/// installs. FUSE requests are served by the same operations, so they are traced the same way.
+///
+/// Cloning a `MonofsNFS` is cheap and the clones share the same root directory and file ID
+/// mappings, which lets the NFS listener and the control server operate on the same tree.
 ///
 /// ## Examples
 ///
@@ -101,10 +285,22 @@ where
     S: IpldStore + Send + Sync + 'static,
 {
     root: Arc<Mutex<Dir<S>>>,
-    next_fileid: AtomicU64,
     filenames: Arc<Mutex<SymbolTable>>,
     fileid_to_path_map: Arc<Mutex<HashMap<fileid3, Vec<Symbol>>>>,
     path_to_fileid_map: Arc<Mutex<HashMap<Vec<Symbol>, fileid3>>>,
+    new_file_ids: Arc<Mutex<Vec<(fileid3, String)>>>,
+    audit: Arc<Mutex<Option<Vec<AuditEvent>>>>,
+    write_back: Arc<Mutex<WriteBackCache>>,
+    lookups: Arc<Mutex<LookupCache>>,
+    read_ahead: Arc<Mutex<ReadAhead>>,
+    quota: Arc<Mutex<QuotaTracker>>,
+    follow_policy: Arc<Mutex<FollowPolicy>>,
+    atime_policy: Arc<Mutex<AtimePolicy>>,
+    subtree: Arc<Mutex<Option<ServedSubtree<S>>>>,
+    changes: broadcast::Sender<ChangeEvent>,
+    metrics: Arc<ServerMetrics>,
+    transfer: TransferConfig,
+    exports: ExportConfig,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -125,17 +321,415 @@ where
     /// let server = MemoryMonofsNFS::new(MemoryStore::default());
     /// ```
     pub fn new(store: S) -> Self {
+        Self::with_write_back(store, WriteBackConfig::disabled())
+    }
+
+    /// Creates a new MonofsNFS instance with the given store that buffers writes as described by
+    /// `config`.
+    ///
+    /// ## Example
+    /// ```rust
+    /// use monofs::{config::WriteBackConfig, server::MemoryMonofsNFS};
+    /// use ipldstore::MemoryStore;
+    ///
+    /// let config = WriteBackConfig::default();
+    /// let server = MemoryMonofsNFS::with_write_back(MemoryStore::default(), config);
+    /// ```
+    pub fn with_write_back(store: S, config: WriteBackConfig) -> Self {
         Self {
             root: Arc::new(Mutex::new(Dir::new(store))),
             filenames: Arc::new(Mutex::new(SymbolTable::new())),
-            next_fileid: AtomicU64::new(1),
             fileid_to_path_map: Arc::new(Mutex::new(HashMap::from([(0, vec![])]))),
             path_to_fileid_map: Arc::new(Mutex::new(HashMap::from([(vec![], 0)]))),
+            new_file_ids: Arc::new(Mutex::new(Vec::new())),
+            audit: Arc::new(Mutex::new(None)),
+            write_back: Arc::new(Mutex::new(WriteBackCache::new(config))),
+            lookups: Arc::new(Mutex::new(LookupCache::new(LOOKUP_CACHE_CAPACITY))),
+            read_ahead: Arc::new(Mutex::new(ReadAhead::new(READ_AHEAD_CONCURRENCY))),
+            quota: Arc::new(Mutex::new(QuotaTracker::unlimited())),
+            follow_policy: Arc::new(Mutex::new(FollowPolicy::Never)),
+            atime_policy: Arc::new(Mutex::new(AtimePolicy::default())),
+            subtree: Arc::new(Mutex::new(None)),
+            changes: broadcast::channel(CHANGE_CHANNEL_CAPACITY).0,
+            metrics: Arc::new(ServerMetrics::default()),
+            transfer: TransferConfig::default(),
+            exports: ExportConfig::default(),
+        }
+    }
+
+    /// Advertises the transfer sizes described by `config` to clients, and serves at most its
+    /// read size in a single READ.
+    ///
+    /// ## Example
+    /// ```rust
+    /// use monofs::{config::TransferConfig, server::MemoryMonofsNFS};
+    /// use ipldstore::MemoryStore;
+    ///
+    /// let config = TransferConfig::builder().rsize(256 * 1024).build();
+    /// let server = MemoryMonofsNFS::new(MemoryStore::default()).with_transfer(config);
+    /// ```
+    pub fn with_transfer(mut self, config: TransferConfig) -> Self {
+        self.transfer = config;
+        self
+    }
+
+    /// Squashes the owners clients set as `config` asks. See [Ownership](Self#ownership).
+    ///
+    /// ## Example
+    /// ```rust
+    /// use monofs::{
+    ///     config::{ExportConfig, SquashMode},
+    ///     server::MemoryMonofsNFS,
+    /// };
+    /// use ipldstore::MemoryStore;
+    ///
+    /// let config = ExportConfig::builder().squash(SquashMode::Root).build();
+    /// let server = MemoryMonofsNFS::new(MemoryStore::default()).with_exports(config);
+    /// ```
+    pub fn with_exports(mut self, config: ExportConfig) -> Self {
+        self.exports = config;
+        self
+    }
+
+    /// Replaces the root directory with the directory stored at `cid`.
+    ///
+    /// File IDs are derived from paths, so handles held by clients remain valid for paths that
+    /// also exist in the new tree and report an error for those that don't. Buffered writes are
+    /// discarded along with the rest of the old tree.
+    ///
+    /// If the server serves a subtree, `cid` is the root of the whole tree, and the served
+    /// directory is taken out of it.
+    pub async fn set_root(&self, cid: &Cid) -> FsResult<()> {
+        let mut root = self.root.lock().await;
+        let store = root.get_store().clone();
+        *root = self.load_subtree(cid, store).await?;
+        self.write_back.lock().await.clear();
+        self.lookups.lock().await.reset_dirty();
+        self.read_ahead.lock().await.clear();
+
+        let mut quota = self.quota.lock().await;
+        if quota.is_limited() {
+            quota.set_logical_bytes(get_logical_size(&root).await?);
         }
+
+        self.metrics.record_root_restore();
+        self.notify(ChangeKind::Modified, "", Some(cid));
+        Ok(())
     }
 
-    fn next_fileid(&self) -> fileid3 {
-        self.next_fileid.fetch_add(1, Ordering::SeqCst)
+    /// Limits how much the filesystem may hold as described by `quota`.
+    ///
+    /// `store_bytes` must hold the bytes the store takes up on disk and be kept up to date as the
+    /// store writes blocks, which [`FlatFsStore::with_usage`] does. The total size of the files is
+    /// measured now, which loads every directory in the tree, and tracked from then on.
+    pub async fn set_quota(&self, quota: QuotaConfig, store_bytes: Arc<AtomicU64>) -> FsResult<()> {
+        let root = self.root.lock().await;
+        let mut tracker = QuotaTracker::new(quota, store_bytes);
+        if tracker.is_limited() {
+            tracker.set_logical_bytes(get_logical_size(&root).await?);
+        }
+
+        *self.quota.lock().await = tracker;
+        Ok(())
+    }
+
+    /// Replaces the limits enforced on the filesystem with `quota`, keeping the store's size
+    /// counted as it was by [`set_quota`](Self::set_quota).
+    pub async fn update_quota(&self, quota: QuotaConfig) -> FsResult<()> {
+        let store_bytes = self.quota.lock().await.get_store_bytes_counter();
+        self.set_quota(quota, store_bytes).await
+    }
+
+    /// Sets which symbolic links LOOKUP follows, see [Symbolic links](Self#symbolic-links).
+    pub async fn set_follow_policy(&self, policy: FollowPolicy) {
+        *self.follow_policy.lock().await = policy;
+    }
+
+    /// Sets when reads record access times, see [Timestamps](Self#timestamps).
+    pub async fn set_atime_policy(&self, policy: AtimePolicy) {
+        *self.atime_policy.lock().await = policy;
+    }
+
+    /// Registers the file IDs of `file_ids`, each along with its path, e.g. the IDs recorded
+    /// before the server restarted, so that the file handles clients hold for them stay valid.
+    /// See [File handles](Self#file-handles).
+    ///
+    /// IDs and paths that are already registered keep the mapping they have, and paths that
+    /// cannot be registered are skipped.
+    pub async fn restore_file_ids(&self, file_ids: impl IntoIterator<Item = (fileid3, String)>) {
+        for (fileid, path) in file_ids {
+            let Ok(path_symbols) = self.path_to_symbols(&path).await else {
+                continue;
+            };
+
+            let mut fileid_to_path_map = self.fileid_to_path_map.lock().await;
+            let mut path_to_fileid_map = self.path_to_fileid_map.lock().await;
+            if fileid_to_path_map.contains_key(&fileid)
+                || path_to_fileid_map.contains_key(&path_symbols)
+            {
+                continue;
+            }
+
+            fileid_to_path_map.insert(fileid, path_symbols.clone());
+            path_to_fileid_map.insert(path_symbols, fileid);
+        }
+    }
+
+    /// Returns the file IDs registered since the last call, each along with its path, so they
+    /// can be recorded and restored with [`restore_file_ids`](Self::restore_file_ids).
+    pub async fn take_new_file_ids(&self) -> Vec<(fileid3, String)> {
+        std::mem::take(&mut *self.new_file_ids.lock().await)
+    }
+
+    /// Records every entity clients create, write, rename and remove from now on, until the
+    /// events are taken with [`take_audit_events`](Self::take_audit_events).
+    ///
+    /// Events are recorded along with the user and group of the caller if it is called through
+    /// [`call_as`], as the FUSE adapter does. NFS clients are not, as `nfsserve` does not pass the
+    /// credentials of a request on.
+    pub async fn enable_audit(&self) {
+        self.audit.lock().await.get_or_insert_with(Vec::new);
+    }
+
+    /// Returns the audit events recorded since the last call, oldest first, or nothing if
+    /// auditing is not enabled.
+    pub async fn take_audit_events(&self) -> Vec<AuditEvent> {
+        self.audit
+            .lock()
+            .await
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Returns how much the filesystem holds.
+    ///
+    /// Usage is only tracked for filesystems given a quota, and is zero for the others.
+    pub async fn get_usage(&self) -> QuotaUsage {
+        self.quota.lock().await.get_usage()
+    }
+
+    /// Returns the space left in the filesystem, given `host`, the space of the host filesystem
+    /// holding its store.
+    ///
+    /// Without a quota, this is the space of the host.
+    pub async fn get_fs_stats(&self, host: FsStats) -> FsStats {
+        let quota = self.quota.lock().await;
+        host.limit(quota.get_config(), &quota.get_usage())
+    }
+
+    /// Returns the operations served so far and how the caches in front of the store fared.
+    pub async fn get_metrics(&self) -> MetricsSnapshot {
+        let lookups = self.lookups.lock().await.get_hits_and_misses();
+        let read_ahead = self.read_ahead.lock().await.get_hits_and_misses();
+        self.metrics.snapshot(lookups, read_ahead)
+    }
+
+    /// Returns the store the filesystem is backed by.
+    pub(crate) async fn get_store(&self) -> S {
+        self.root.lock().await.get_store().clone()
+    }
+
+    /// Returns a receiver of the changes clients make to the filesystem from now on.
+    ///
+    /// Writes buffered by write-back are reported once they are applied to their file, along
+    /// with the CID of the file's new content. A receiver that falls more than
+    /// [`CHANGE_CHANNEL_CAPACITY`] events behind misses the oldest ones and is told how many.
+    pub fn watch(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.changes.subscribe()
+    }
+
+    /// Reports a change of `kind` to the entity at `path` to every receiver from
+    /// [`watch`](Self::watch).
+    fn notify(&self, kind: ChangeKind, path: &str, cid: Option<&Cid>) {
+        if self.changes.receiver_count() > 0 {
+            let _ = self.changes.send(ChangeEvent::new(kind, path, cid));
+        }
+    }
+
+    /// Records `event` as made by the caller, if auditing is enabled.
+    async fn audit(&self, event: AuditEvent) {
+        if let Some(events) = self.audit.lock().await.as_mut() {
+            events.push(match CALLER.try_with(|caller| *caller) {
+                Ok((uid, gid)) => event.with_caller(uid, gid),
+                Err(_) => event,
+            });
+        }
+    }
+
+    /// Gets the value of the xattr `name` of the entity with file ID `id`.
+    ///
+    /// Returns `None` if the xattr is not set. [`CID_XATTR_NAME`] and [`CONTENT_CID_XATTR_NAME`]
+    /// read as the CIDs of the entity and its content, after applying the writes buffered for
+    /// it. They are not listed, set or removed like other xattrs.
+    pub async fn get_xattr(&self, id: fileid3, name: &str) -> Result<Option<Vec<u8>>, nfsstat3> {
+        tracing::trace!("get_xattr: id: {}, name: {}", id, name);
+
+        if is_cid_xattr(name) {
+            return self.get_cid_xattr(id, name).await;
+        }
+
+        let path = self.fileid_to_path(id).await?;
+        let root = self.root.lock().await;
+        let metadata = if path.is_empty() {
+            root.get_metadata()
+        } else {
+            let entity = root.find(&path).await?.ok_or(nfsstat3::NFS3ERR_NOENT)?;
+            entity.get_metadata()
+        };
+
+        Ok(metadata.get_xattr(name).await?)
+    }
+
+    /// Gets the value of [`CID_XATTR_NAME`] or [`CONTENT_CID_XATTR_NAME`] for the entity with
+    /// file ID `id`.
+    async fn get_cid_xattr(&self, id: fileid3, name: &str) -> Result<Option<Vec<u8>>, nfsstat3> {
+        let path = self.fileid_to_path(id).await?;
+        let mut root = self.root.lock().await;
+
+        // Writes buffered below a directory change its CID as much as those to a file do
+        let is_file = !path.is_empty() && matches!(root.find(&path).await?, Some(Entity::File(_)));
+        if is_file {
+            self.flush_file(&mut root, id).await?;
+        } else {
+            let pending = self.write_back.lock().await.take_all();
+            self.apply_writes(&mut root, pending).await?;
+        }
+
+        let cid = if path.is_empty() {
+            match name {
+                CONTENT_CID_XATTR_NAME => None,
+                _ => Some(root.store().await.map_err(FsError::from)?),
+            }
+        } else {
+            let entity = root.find(&path).await?.ok_or(nfsstat3::NFS3ERR_NOENT)?;
+            match (name, entity) {
+                (CONTENT_CID_XATTR_NAME, Entity::File(file)) => file.get_content().copied(),
+                (CONTENT_CID_XATTR_NAME, _) => None,
+                _ => Some(entity.get_cid().await.map_err(FsError::from)?),
+            }
+        };
+
+        Ok(cid.map(|cid| cid.to_string().into_bytes()))
+    }
+
+    /// Lists the names of the xattrs of the entity with file ID `id`.
+    pub async fn list_xattrs(&self, id: fileid3) -> Result<Vec<String>, nfsstat3> {
+        tracing::trace!("list_xattrs: id: {}", id);
+
+        let path = self.fileid_to_path(id).await?;
+        let root = self.root.lock().await;
+        let metadata = if path.is_empty() {
+            root.get_metadata()
+        } else {
+            let entity = root.find(&path).await?.ok_or(nfsstat3::NFS3ERR_NOENT)?;
+            entity.get_metadata()
+        };
+
+        Ok(metadata.list_xattrs().await?)
+    }
+
+    /// Sets the xattr `name` of the entity with file ID `id` to `value`.
+    pub async fn set_xattr(&self, id: fileid3, name: &str, value: &[u8]) -> Result<(), nfsstat3> {
+        tracing::trace!("set_xattr: id: {}, name: {}", id, name);
+
+        if is_cid_xattr(name) {
+            return Err(nfsstat3::NFS3ERR_PERM);
+        }
+
+        let path = self.fileid_to_path(id).await?;
+        let mut root = self.root.lock().await;
+        self.invalidate_lookups(&root, &path).await?;
+        let metadata = if path.is_empty() {
+            root.get_metadata_mut()
+        } else {
+            let entity = root.find_mut(&path).await?.ok_or(nfsstat3::NFS3ERR_NOENT)?;
+            entity.get_metadata_mut()
+        };
+
+        metadata.set_xattr(name, value).await?;
+        metadata.set_changed_at(Utc::now());
+        Ok(())
+    }
+
+    /// Removes the xattr `name` of the entity with file ID `id`.
+    ///
+    /// Returns `false` if the xattr was not set.
+    pub async fn remove_xattr(&self, id: fileid3, name: &str) -> Result<bool, nfsstat3> {
+        tracing::trace!("remove_xattr: id: {}, name: {}", id, name);
+
+        if is_cid_xattr(name) {
+            return Err(nfsstat3::NFS3ERR_PERM);
+        }
+
+        let path = self.fileid_to_path(id).await?;
+        let mut root = self.root.lock().await;
+        self.invalidate_lookups(&root, &path).await?;
+        let metadata = if path.is_empty() {
+            root.get_metadata_mut()
+        } else {
+            let entity = root.find_mut(&path).await?.ok_or(nfsstat3::NFS3ERR_NOENT)?;
+            entity.get_metadata_mut()
+        };
+
+        let removed = metadata.remove_xattr(name).await?;
+        if removed {
+            metadata.set_changed_at(Utc::now());
+        }
+
+        Ok(removed)
+    }
+
+    /// Replaces the content of the file with file ID `dst_id` with that of the file with file ID
+    /// `src_id`, and returns the new attributes of the destination.
+    ///
+    /// The destination links to the same content as the source instead of getting a copy of its
+    /// bytes, so files of any size are cloned at the same cost. The metadata of the destination
+    /// is kept.
+    pub async fn clone_file(&self, src_id: fileid3, dst_id: fileid3) -> Result<fattr3, nfsstat3> {
+        tracing::trace!("clone_file: src_id: {}, dst_id: {}", src_id, dst_id);
+
+        let src_path = self.fileid_to_path(src_id).await?;
+        let dst_path = self.fileid_to_path(dst_id).await?;
+        if src_path.is_empty() || dst_path.is_empty() {
+            return Err(nfsstat3::NFS3ERR_INVAL);
+        }
+
+        // Both files have to be complete before one replaces the other
+        let mut root = self.root.lock().await;
+        self.invalidate_lookups(&root, &dst_path).await?;
+        self.flush_file(&mut root, src_id).await?;
+        self.flush_file(&mut root, dst_id).await?;
+
+        let (content, size) = match root.find(&src_path).await? {
+            Some(Entity::File(file)) => (file.get_content().cloned(), file.get_size().await?),
+            Some(_) => return Err(nfsstat3::NFS3ERR_INVAL),
+            None => return Err(nfsstat3::NFS3ERR_NOENT),
+        };
+
+        let file = match root.find_mut(&dst_path).await? {
+            Some(Entity::File(file)) => file,
+            Some(_) => return Err(nfsstat3::NFS3ERR_INVAL),
+            None => return Err(nfsstat3::NFS3ERR_NOENT),
+        };
+
+        // Shared content takes up no room in the store, but does count towards the logical quota
+        let mut quota = self.quota.lock().await;
+        let original_size = file.get_size().await?;
+        quota.check_write(size.saturating_sub(original_size), 0)?;
+
+        file.set_content(content);
+        quota.grow(size.saturating_sub(original_size));
+        quota.shrink(original_size.saturating_sub(size));
+        self.notify(ChangeKind::Modified, &dst_path, file.get_content());
+        self.audit(AuditEvent::new(
+            AuditOperation::Write,
+            &dst_path,
+            file.get_content(),
+        ))
+        .await;
+
+        Self::construct_attributes(file.get_metadata(), size, dst_id).await
     }
 
     /// Converts a file ID to its corresponding path by looking up the symbols in the mapping
@@ -181,16 +775,6 @@ where
         Ok(path_symbols)
     }
 
-    /// Gets the fileid for a registered path if it exists.
-    /// This is a convenience wrapper around `get_path_registered` that takes a string path.
-    async fn get_path_registered_str(
-        &self,
-        path: impl AsRef<str>,
-    ) -> Result<Option<fileid3>, nfsstat3> {
-        let path_symbols = self.path_to_symbols(path).await?;
-        self.get_path_registered(&path_symbols).await
-    }
-
     /// Gets the fileid for a registered path if it exists.
     /// This is the core path lookup function that works directly with symbols.
     async fn get_path_registered(
@@ -211,72 +795,233 @@ where
     /// Ensures a path is registered in the path-fileid mapping system and returns its fileid.
     /// This is the core path registration function that works directly with symbols.
     /// If the path is already registered, returns the existing fileid.
-    /// If not, creates a new fileid and registers the bidirectional mappings.
+    /// If not, derives a fileid from the path and registers the bidirectional mappings.
     async fn ensure_path_registered(&self, path_symbols: &[Symbol]) -> Result<fileid3, nfsstat3> {
         // First check if the path is already registered
         if let Some(existing_id) = self.get_path_registered(path_symbols).await? {
             return Ok(existing_id);
         }
 
-        // Create new mapping
-        let fileid = self.next_fileid();
+        let path = {
+            let filenames = self.filenames.lock().await;
+            path_symbols
+                .iter()
+                .map(|s| filenames.get(*s).ok_or(nfsstat3::NFS3ERR_STALE))
+                .collect::<Result<Vec<_>, _>>()?
+                .join("/")
+        };
+
+        // Create new mapping, unless the path was registered in the meantime. A file ID taken by
+        // another path is passed over for the next free one.
         let mut fileid_to_path_map = self.fileid_to_path_map.lock().await;
         let mut path_to_fileid_map = self.path_to_fileid_map.lock().await;
+        if let Some(existing_id) = path_to_fileid_map.get(path_symbols) {
+            return Ok(*existing_id);
+        }
+
+        let mut fileid = get_path_fileid(&path);
+        while fileid_to_path_map.contains_key(&fileid) {
+            fileid = fileid % MAX_FILEID + 1;
+        }
 
         fileid_to_path_map.insert(fileid, path_symbols.to_vec());
         path_to_fileid_map.insert(path_symbols.to_vec(), fileid);
+        self.new_file_ids.lock().await.push((fileid, path));
 
         Ok(fileid)
     }
 
-    /// Helper method to update attributes on an entity's metadata
-    async fn update_attributes(metadata: &mut Metadata<S>, attr: &sattr3) -> Result<(), nfsstat3> {
-        // Update mode
-        match attr.mode {
-            set_mode3::Void => {}
-            set_mode3::mode(mode) => {
-                metadata
-                    .set_attribute(UNIX_MODE_KEY, mode)
-                    .await
-                    .map_err(nfsstat3::from)?;
+    /// Gets the attributes of the entity `name` in the directory at `parent_path`, or `None` if
+    /// there is no such entity. The file ID of the returned attributes is not set.
+    async fn find_attributes(
+        &self,
+        root: &Dir<S>,
+        parent_path: &str,
+        name: &str,
+    ) -> Result<Option<fattr3>, nfsstat3> {
+        // Get parent directory - handle root directory case specially
+        let parent_dir = if parent_path.is_empty() {
+            root
+        } else {
+            match root.find(parent_path).await? {
+                Some(Entity::Dir(dir)) => dir,
+                Some(_) => return Err(nfsstat3::NFS3ERR_NOTDIR),
+                None => return Err(nfsstat3::NFS3ERR_NOENT),
             }
-        }
+        };
 
-        // Update uid
-        match attr.uid {
-            set_uid3::Void => {}
-            set_uid3::uid(uid) => {
-                metadata
-                    .set_attribute(UNIX_UID_KEY, uid)
-                    .await
-                    .map_err(nfsstat3::from)?;
+        self.get_entry_attributes(parent_dir, name).await
+    }
+
+    /// Gets the attributes of the entity `name` in `dir`, or `None` if there is no such entity.
+    /// The file ID of the returned attributes is not set.
+    ///
+    /// Lookups in directories that have not changed since they were loaded are answered from the
+    /// lookup cache when possible, and cached otherwise.
+    async fn get_entry_attributes(
+        &self,
+        dir: &Dir<S>,
+        name: &str,
+    ) -> Result<Option<fattr3>, nfsstat3> {
+        let cid = dir.get_initial_load_cid().copied();
+        if let Some(cid) = &cid {
+            if let Some(attrs) = self.lookups.lock().await.get(cid, name) {
+                return Ok(attrs);
             }
         }
 
-        // Update gid
-        match attr.gid {
-            set_gid3::Void => {}
-            set_gid3::gid(gid) => {
-                metadata
-                    .set_attribute(UNIX_GID_KEY, gid)
-                    .await
-                    .map_err(nfsstat3::from)?;
+        let attrs = match dir.get_entity(name).await? {
+            Some(entity) => Some(
+                Self::construct_attributes(entity.get_metadata(), entity.get_size().await?, 0)
+                    .await?,
+            ),
+            None => None,
+        };
+
+        if let Some(cid) = cid {
+            self.lookups.lock().await.insert(cid, name, attrs);
+        }
+
+        Ok(attrs)
+    }
+
+    /// Loads the tree stored at `cid` and returns the directory to serve as the root from it,
+    /// which is the tree itself unless the server serves a subtree of it.
+    async fn load_subtree(&self, cid: &Cid, store: S) -> FsResult<Dir<S>> {
+        let mut tree = Dir::load(cid, store).await?;
+        let mut subtree = self.subtree.lock().await;
+        let Some(subtree) = subtree.as_mut() else {
+            return Ok(tree);
+        };
+
+        let root = get_subtree_dir(&mut tree, &subtree.path).await?.clone();
+        subtree.tree = tree;
+        Ok(root)
+    }
+
+    /// Stops the lookup cache from answering for the directories on the way to the entity at
+    /// `path`, and for the entity itself if it is a directory, before any of them changes.
+    async fn invalidate_lookups(&self, root: &Dir<S>, path: &str) -> FsResult<()> {
+        let mut lookups = self.lookups.lock().await;
+        let mut dir = root;
+        if let Some(cid) = dir.get_initial_load_cid() {
+            lookups.invalidate(cid);
+        }
+
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            match dir.get_entity(name).await? {
+                Some(Entity::Dir(child)) => dir = child,
+                _ => break,
+            }
+
+            if let Some(cid) = dir.get_initial_load_cid() {
+                lookups.invalidate(cid);
             }
         }
 
+        Ok(())
+    }
+
+    /// Records that the entity at `path` was accessed just now, if the atime policy asks for it.
+    async fn record_access(&self, root: &mut Dir<S>, path: &str) -> FsResult<()> {
+        let policy = *self.atime_policy.lock().await;
+        if policy == AtimePolicy::Noatime {
+            return Ok(());
+        }
+
+        let now = Utc::now();
+        let Some(entity) = root.find(path).await? else {
+            return Ok(());
+        };
+
+        let metadata = entity.get_metadata();
+        let accessed_at = metadata.get_accessed_at().await?;
+        if !policy.should_update(
+            accessed_at,
+            *metadata.get_modified_at(),
+            *metadata.get_changed_at(),
+            now,
+        ) {
+            return Ok(());
+        }
+
+        self.invalidate_lookups(root, path).await?;
+        if let Some(entity) = root.find_mut(path).await? {
+            entity.get_metadata_mut().set_accessed_at(now).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Maps the owner and group `attr` sets as the exports squash them.
+    fn squash_attributes(&self, attr: &mut sattr3) {
+        if let set_uid3::uid(uid) = attr.uid {
+            attr.uid = set_uid3::uid(self.exports.squash_uid(uid));
+        }
+
+        if let set_gid3::gid(gid) = attr.gid {
+            attr.gid = set_gid3::gid(self.exports.squash_gid(gid));
+        }
+    }
+
+    /// Maps the owner and group of an entity a client just created as the exports squash them.
+    async fn squash_new_owner(&self, metadata: &mut Metadata<S>) -> Result<(), nfsstat3> {
+        if self.exports.get_squash() == &SquashMode::None {
+            return Ok(());
+        }
+
+        let squash_all = self.exports.get_squash() == &SquashMode::All;
+
+        let uid = match metadata.get_uid().await.map_err(nfsstat3::from)? {
+            Some(uid) => Some(self.exports.squash_uid(uid)),
+            None if squash_all => Some(*self.exports.get_anon_uid()),
+            None => None,
+        };
+        let gid = match metadata.get_gid().await.map_err(nfsstat3::from)? {
+            Some(gid) => Some(self.exports.squash_gid(gid)),
+            None if squash_all => Some(*self.exports.get_anon_gid()),
+            None => None,
+        };
+        metadata.chown(uid, gid).await.map_err(nfsstat3::from)
+    }
+
+    /// Helper method to update attributes on an entity's metadata
+    async fn update_attributes(metadata: &mut Metadata<S>, attr: &sattr3) -> Result<(), nfsstat3> {
+        // Update mode
+        if let set_mode3::mode(mode) = attr.mode {
+            metadata
+                .set_permissions(mode)
+                .await
+                .map_err(nfsstat3::from)?;
+        }
+
+        // Update ownership
+        let uid = match attr.uid {
+            set_uid3::Void => None,
+            set_uid3::uid(uid) => Some(uid),
+        };
+        let gid = match attr.gid {
+            set_gid3::Void => None,
+            set_gid3::gid(gid) => Some(gid),
+        };
+        metadata.chown(uid, gid).await.map_err(nfsstat3::from)?;
+
         // Update atime
         match attr.atime {
             set_atime::DONT_CHANGE => {}
             set_atime::SET_TO_SERVER_TIME => {
-                let now = Utc::now();
                 metadata
-                    .set_attribute(UNIX_ATIME_KEY, now.timestamp())
+                    .set_accessed_at(Utc::now())
                     .await
                     .map_err(nfsstat3::from)?;
             }
             set_atime::SET_TO_CLIENT_TIME(atime) => {
+                let atime = Utc
+                    .timestamp_opt(atime.seconds as i64, 0)
+                    .single()
+                    .ok_or(nfsstat3::NFS3ERR_INVAL)?;
                 metadata
-                    .set_attribute(UNIX_ATIME_KEY, atime.seconds)
+                    .set_accessed_at(atime)
                     .await
                     .map_err(nfsstat3::from)?;
             }
@@ -298,6 +1043,9 @@ where
             }
         }
 
+        // Any update of the attributes changes the entity
+        metadata.set_changed_at(Utc::now());
+
         Ok(())
     }
 
@@ -313,62 +1061,28 @@ where
                 EntityType::Dir => ftype3::NF3DIR,
                 EntityType::SymCidLink | EntityType::SymPathLink => ftype3::NF3LNK,
             },
-            // Default mode is 0o755 (rwxr-xr-x) if not set or invalid
+            // Entities without a recorded mode get the default mode of their type
             mode: metadata
-                .get_attribute(UNIX_MODE_KEY)
+                .get_mode()
                 .await
                 .map_err(nfsstat3::from)?
-                .and_then(|ipld| match &*ipld {
-                    Ipld::String(s) => s.parse().ok(),
-                    Ipld::Integer(i) => {
-                        if *i >= 0 {
-                            Some(i.abs() as u32)
-                        } else {
-                            None
-                        }
-                    }
-                    _ => None,
-                })
                 .unwrap_or(match metadata.get_entity_type() {
                     EntityType::File => DEFAULT_FILE_MODE,
                     EntityType::Dir => DEFAULT_DIR_MODE,
                     EntityType::SymCidLink | EntityType::SymPathLink => DEFAULT_SYMLINK_MODE,
                 }),
             nlink: 1, // We don't support hard links
-            // Default uid is 0 (root) if not set or invalid
+            // Entities without a recorded owner belong to the user running the server
             uid: metadata
-                .get_attribute(UNIX_UID_KEY)
+                .get_uid()
                 .await
                 .map_err(nfsstat3::from)?
-                .and_then(|ipld| match &*ipld {
-                    Ipld::String(s) => s.parse().ok(),
-                    Ipld::Integer(i) => {
-                        if *i >= 0 {
-                            Some(i.abs() as u32)
-                        } else {
-                            None
-                        }
-                    }
-                    _ => None,
-                })
-                .unwrap_or(507), // TODO: Maybe use the uid of the user that made the request
-            // Default gid is 0 (root) if not set or invalid
+                .unwrap_or_else(|| unistd::getuid().as_raw()),
             gid: metadata
-                .get_attribute(UNIX_GID_KEY)
+                .get_gid()
                 .await
                 .map_err(nfsstat3::from)?
-                .and_then(|ipld| match &*ipld {
-                    Ipld::String(s) => s.parse().ok(),
-                    Ipld::Integer(i) => {
-                        if *i >= 0 {
-                            Some(i.abs() as u32)
-                        } else {
-                            None
-                        }
-                    }
-                    _ => None,
-                })
-                .unwrap_or(507), // TODO: Maybe use the gid of the user that made the request
+                .unwrap_or_else(|| unistd::getgid().as_raw()),
             size,
             used: 0, // TODO: Space used is not tracked
             rdev: specdata3 {
@@ -377,22 +1091,189 @@ where
             },
             fsid: 0,    // Single filesystem
             fileid: id, // Use the provided fileid
-            atime: nfstime3 {
-                seconds: metadata.get_created_at().timestamp() as u32,
-                nseconds: 0,
-            },
-            mtime: nfstime3 {
-                seconds: metadata.get_modified_at().timestamp() as u32,
-                nseconds: 0,
-            },
-            ctime: nfstime3 {
-                seconds: metadata.get_created_at().timestamp() as u32,
-                nseconds: 0,
-            },
+            atime: to_nfstime(
+                &metadata
+                    .get_accessed_at()
+                    .await
+                    .map_err(nfsstat3::from)?
+                    .unwrap_or(*metadata.get_created_at()),
+            ),
+            mtime: to_nfstime(metadata.get_modified_at()),
+            ctime: to_nfstime(metadata.get_changed_at()),
         })
     }
 }
 
+impl<S> MonofsNFS<S>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    /// Serves the directory at `path` in the tree as the root from now on, creating it if it does
+    /// not exist yet. An empty path serves the whole tree again. See
+    /// [Subtrees](Self#subtrees).
+    ///
+    /// The root is checkpointed first, so writes made so far are kept in the tree. File IDs are
+    /// derived from paths, so handles held by clients are resolved within the new root, as they
+    /// are after [`set_root`](Self::set_root).
+    pub async fn set_subtree(&self, path: impl AsRef<str>) -> FsResult<()> {
+        let path = Utf8UnixPathBuf::from(path.as_ref().trim_matches('/'));
+        let cid = self.checkpoint().await?;
+        let store = self.get_store().await;
+        let subtree = (!path.as_str().is_empty()).then(|| ServedSubtree {
+            path,
+            tree: Dir::new(store),
+        });
+
+        // The directory served so far stays in place if the new one cannot be served
+        let previous = std::mem::replace(&mut *self.subtree.lock().await, subtree);
+        if let Err(e) = self.set_root(&cid).await {
+            *self.subtree.lock().await = previous;
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Checkpoints the root directory and returns its CID.
+    ///
+    /// Buffered writes are flushed first, so the returned CID captures the complete state of the
+    /// filesystem at this point, including every write acknowledged to clients. It can be passed
+    /// to [`set_root`](Self::set_root) later to return to it.
+    ///
+    /// If the server serves a subtree, the served directory is written back into the whole tree,
+    /// and the CID of the whole tree is returned.
+    pub async fn checkpoint(&self) -> FsResult<Cid> {
+        let mut root = self.root.lock().await;
+        let pending = self.write_back.lock().await.take_all();
+        self.apply_writes(&mut root, pending).await?;
+        let cid = self.checkpoint_subtree(&mut root).await?;
+        self.metrics.record_checkpoint();
+
+        // The tree is reloaded from the store, so every directory matches its CID again
+        self.lookups.lock().await.reset_dirty();
+        Ok(cid)
+    }
+
+    /// Merges `theirs`, a root recorded by another writer since the root was at `base`, into the
+    /// root directory, and returns the CID of the merged root.
+    ///
+    /// The root is checkpointed and replaced under the same lock, so writes made while the roots
+    /// are merged are not lost. Paths changed differently on both sides keep the version of this
+    /// filesystem. Without a base, both roots are taken to derive from an empty root.
+    pub async fn merge_root(&self, base: Option<Cid>, theirs: Cid) -> FsResult<Cid> {
+        let mut root = self.root.lock().await;
+        let pending = self.write_back.lock().await.take_all();
+        self.apply_writes(&mut root, pending).await?;
+        let ours = self.checkpoint_subtree(&mut root).await?;
+        self.metrics.record_checkpoint();
+
+        let store = root.get_store().clone();
+        let merged = management::merge_roots(store.clone(), base, ours, theirs).await?;
+        if merged == ours {
+            self.lookups.lock().await.reset_dirty();
+            return Ok(merged);
+        }
+
+        *root = self.load_subtree(&merged, store).await?;
+        self.lookups.lock().await.reset_dirty();
+        self.read_ahead.lock().await.clear();
+
+        let mut quota = self.quota.lock().await;
+        if quota.is_limited() {
+            quota.set_logical_bytes(get_logical_size(&root).await?);
+        }
+
+        self.metrics.record_root_restore();
+        self.notify(ChangeKind::Modified, "", Some(&merged));
+        Ok(merged)
+    }
+
+    /// Checkpoints `root` and returns its CID, or writes it back into the tree it was taken out
+    /// of and returns the CID of the tree if the server serves a subtree.
+    async fn checkpoint_subtree(&self, root: &mut Dir<S>) -> FsResult<Cid> {
+        let cid = root.checkpoint().await?;
+        let mut subtree = self.subtree.lock().await;
+        let Some(subtree) = subtree.as_mut() else {
+            return Ok(cid);
+        };
+
+        *get_subtree_dir(&mut subtree.tree, &subtree.path).await? = root.clone();
+        Ok(subtree.tree.checkpoint().await?)
+    }
+
+    /// Applies every buffered write to its file.
+    pub async fn flush_writes(&self) -> FsResult<()> {
+        let mut root = self.root.lock().await;
+        let pending = self.write_back.lock().await.take_all();
+        self.apply_writes(&mut root, pending).await
+    }
+
+    /// Applies the buffered writes that have waited for the configured flush interval.
+    ///
+    /// The NFS and FUSE servers call this periodically, so that writes to a file that is never
+    /// read or written again still reach the store.
+    pub async fn flush_expired_writes(&self) -> FsResult<()> {
+        let mut root = self.root.lock().await;
+        let pending = self.write_back.lock().await.take_expired();
+        self.apply_writes(&mut root, pending).await
+    }
+
+    /// Applies the buffered write of the file with file ID `id`, if any.
+    async fn flush_file(&self, root: &mut Dir<S>, id: fileid3) -> FsResult<()> {
+        let pending = self.write_back.lock().await.take(id);
+        self.apply_writes(root, pending.map(|write| (id, write)).into_iter().collect())
+            .await
+    }
+
+    /// Applies `pending` writes to their files in `root`.
+    ///
+    /// Writes to files that no longer exist are dropped. Every write is attempted even if an
+    /// earlier one fails, and the first error is returned.
+    async fn apply_writes(
+        &self,
+        root: &mut Dir<S>,
+        pending: Vec<(fileid3, PendingWrite)>,
+    ) -> FsResult<()> {
+        let mut result = Ok(());
+        for (id, write) in pending {
+            let Ok(path) = self.fileid_to_path(id).await else {
+                tracing::warn!("dropping buffered write to unknown file {}", id);
+                continue;
+            };
+
+            let outcome = match root.find_mut(&path).await {
+                Ok(Some(Entity::File(file))) => {
+                    // The file was modified when the write was acknowledged, not now
+                    let metadata = file.get_metadata();
+                    let times = (*metadata.get_modified_at(), *metadata.get_changed_at());
+                    let outcome = write_at(file, *write.get_offset(), write.get_data()).await;
+                    let metadata = file.get_metadata_mut();
+                    metadata.set_modified_at(times.0);
+                    metadata.set_changed_at(times.1);
+                    if outcome.is_ok() {
+                        self.notify(ChangeKind::Modified, &path, file.get_content());
+                    }
+                    outcome
+                }
+                Ok(_) => {
+                    tracing::warn!("dropping buffered write to {}, which is gone", path);
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            };
+
+            if let Err(e) = outcome {
+                tracing::error!(error = %e, "failed to flush buffered write to {}", path);
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+
+        result
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Trait Implementations
 //--------------------------------------------------------------------------------------------------
@@ -400,17 +1281,80 @@ where
 #[async_trait]
 impl<S> NFSFileSystem for MonofsNFS<S>
 where
-    S: IpldStoreSeekable + Send + Sync,
+    S: IpldStoreSeekable + Send + Sync + 'static,
 {
     fn root_dir(&self) -> fileid3 {
         0
     }
 
+    /// Returns the handle of the file with ID `id`, which is the ID itself, so that handles
+    /// remain valid across restarts of the server.
+    fn id_to_fh(&self, id: fileid3) -> nfs_fh3 {
+        nfs_fh3 {
+            data: id.to_le_bytes().to_vec(),
+        }
+    }
+
+    /// Returns the ID of the file with handle `fh`.
+    ///
+    /// Handles of the length `nfsserve` gives out by default are only valid until the server
+    /// restarts, so they are stale for any server that reads them.
+    fn fh_to_id(&self, fh: &nfs_fh3) -> Result<fileid3, nfsstat3> {
+        match <[u8; 8]>::try_from(fh.data.as_slice()) {
+            Ok(id) => Ok(fileid3::from_le_bytes(id)),
+            Err(_) if fh.data.len() == LEGACY_FILE_HANDLE_LEN => Err(nfsstat3::NFS3ERR_STALE),
+            Err(_) => Err(nfsstat3::NFS3ERR_BADHANDLE),
+        }
+    }
+
     fn capabilities(&self) -> VFSCapabilities {
         VFSCapabilities::ReadWrite
     }
 
+    #[tracing::instrument(
+        name = "nfs.fsinfo",
+        level = "debug",
+        skip_all,
+        fields(root_fileid = root_fileid)
+    )]
+    async fn fsinfo(&self, root_fileid: fileid3) -> Result<fsinfo3, nfsstat3> {
+        tracing::trace!("fsinfo: root_fileid: {}", root_fileid);
+
+        let obj_attributes = match self.getattr(root_fileid).await {
+            Ok(attr) => post_op_attr::attributes(attr),
+            Err(_) => post_op_attr::Void,
+        };
+
+        // Advertise the configured sizes as both the largest and the preferred ones, so clients
+        // that do not pick their own sizes use them too
+        let rsize = *self.transfer.get_rsize();
+        let wsize = *self.transfer.get_wsize();
+        Ok(fsinfo3 {
+            obj_attributes,
+            rtmax: rsize,
+            rtpref: rsize,
+            rtmult: NFS_TRANSFER_SIZE_MULTIPLE,
+            wtmax: wsize,
+            wtpref: wsize,
+            wtmult: NFS_TRANSFER_SIZE_MULTIPLE,
+            dtpref: *self.transfer.get_dtpref(),
+            maxfilesize: MAX_FILE_SIZE,
+            time_delta: nfstime3 {
+                seconds: 0,
+                nseconds: 1_000_000,
+            },
+            properties: FSF_SYMLINK | FSF_HOMOGENEOUS | FSF_CANSETTIME,
+        })
+    }
+
+    #[tracing::instrument(
+        name = "nfs.lookup",
+        level = "debug",
+        skip_all,
+        fields(dirid = dirid, name = ?filename)
+    )]
     async fn lookup(&self, dirid: fileid3, filename: &filename3) -> Result<fileid3, nfsstat3> {
+        let _timer = self.metrics.time(NfsOp::Lookup);
         tracing::trace!("lookup: dirid: {}, filename: {}", dirid, filename);
 
         // Convert filename bytes to string, ensuring valid UTF-8
@@ -429,32 +1373,37 @@ where
 
         tracing::trace!("parent_path: {}", parent_path);
 
-        // Get parent directory - handle root directory case specially
-        let parent_dir = if parent_path.is_empty() {
-            &*root
-        } else {
-            match root.find(&parent_path).await? {
-                Some(Entity::Dir(dir)) => dir,
-                Some(_) => return Err(nfsstat3::NFS3ERR_NOTDIR),
-                None => return Err(nfsstat3::NFS3ERR_NOENT),
-            }
-        };
-
         // Check if the entry exists
-        if !parent_dir.has_entity(filename_str).await? {
+        if self
+            .find_attributes(&root, &parent_path, filename_str)
+            .await?
+            .is_none()
+        {
             return Err(nfsstat3::NFS3ERR_NOENT);
         }
 
-        drop(root);
-
         // Construct full path
-        let full_path = join_path(&parent_path, filename_str);
+        let mut full_path = join_path(&parent_path, filename_str);
+
+        // Resolve the links the policy follows to the path they lead to
+        let policy = *self.follow_policy.lock().await;
+        if policy != FollowPolicy::Never {
+            full_path = root
+                .resolve_path(&full_path, policy)
+                .await?
+                .ok_or(nfsstat3::NFS3ERR_NOENT)?
+                .to_string();
+        }
+
+        drop(root);
 
         // Ensure path is registered and get its fileid
         self.ensure_path_registered_str(&full_path).await
     }
 
+    #[tracing::instrument(name = "nfs.getattr", level = "debug", skip_all, fields(id = id))]
     async fn getattr(&self, id: fileid3) -> Result<fattr3, nfsstat3> {
+        let _timer = self.metrics.time(NfsOp::Getattr);
         tracing::trace!("getattr: id: {}", id);
 
         // Get path from fileid
@@ -463,19 +1412,28 @@ where
         // Get root directory
         let root = self.root.lock().await;
 
-        // Get metadata
-        let (metadata, size) = if path.is_empty() {
-            (root.get_metadata(), 0)
-        } else {
-            let entity = root.find(&path).await?.ok_or(nfsstat3::NFS3ERR_NOENT)?;
-            (entity.get_metadata(), entity.get_size().await?)
-        };
+        // The root has no parent to look it up in
+        if path.is_empty() {
+            return Self::construct_attributes(root.get_metadata(), 0, id).await;
+        }
 
-        // Convert to NFS attributes
-        Self::construct_attributes(metadata, size, id).await
+        // Look the entity up in its parent directory
+        let (parent_path, name) = path.rsplit_once('/').unwrap_or(("", path.as_str()));
+        let mut attrs = self
+            .find_attributes(&root, parent_path, name)
+            .await?
+            .ok_or(nfsstat3::NFS3ERR_NOENT)?;
+        attrs.fileid = id;
+
+        // Account for writes that are still buffered
+        attrs.size = self.write_back.lock().await.get_size(id, attrs.size);
+
+        Ok(attrs)
     }
 
-    async fn setattr(&self, id: fileid3, setattr: sattr3) -> Result<fattr3, nfsstat3> {
+    #[tracing::instrument(name = "nfs.setattr", level = "debug", skip_all, fields(id = id))]
+    async fn setattr(&self, id: fileid3, mut setattr: sattr3) -> Result<fattr3, nfsstat3> {
+        let _timer = self.metrics.time(NfsOp::Setattr);
         tracing::trace!("setattr: id: {}, setattr: {:?}", id, setattr);
 
         // Get path from fileid
@@ -483,36 +1441,79 @@ where
 
         // Get root directory
         let mut root = self.root.lock().await;
+        self.invalidate_lookups(&root, &path).await?;
+
+        // Apply buffered writes first, as the size may change
+        self.flush_file(&mut root, id).await?;
 
-        // Get metadata
+        // Get metadata, resizing the file first if asked to
+        let mut truncated = None;
         let (metadata, size) = if path.is_empty() {
+            if let set_size3::size(_) = setattr.size {
+                return Err(nfsstat3::NFS3ERR_ISDIR);
+            }
+
             (root.get_metadata_mut(), 0)
         } else {
             let entity = root.find_mut(&path).await?.ok_or(nfsstat3::NFS3ERR_NOENT)?;
+            if let set_size3::size(new_size) = setattr.size {
+                let Entity::File(file) = entity else {
+                    return Err(match entity {
+                        Entity::Dir(_) => nfsstat3::NFS3ERR_ISDIR,
+                        _ => nfsstat3::NFS3ERR_INVAL,
+                    });
+                };
+
+                let mut quota = self.quota.lock().await;
+                let size = file.get_size().await?;
+                quota.check_write(new_size.saturating_sub(size), 0)?;
+                file.truncate(new_size).await?;
+                quota.grow(new_size.saturating_sub(size));
+                quota.shrink(size.saturating_sub(new_size));
+                truncated = Some(file.get_content().cloned());
+            }
+
             let size = entity.get_size().await?;
             (entity.get_metadata_mut(), size)
         };
 
         // Update all attributes
+        self.squash_attributes(&mut setattr);
         Self::update_attributes(metadata, &setattr).await?;
+        self.notify(ChangeKind::Modified, &path, None);
+        if let Some(cid) = truncated {
+            self.audit(AuditEvent::new(AuditOperation::Write, &path, cid.as_ref()))
+                .await;
+        }
 
         // Construct and return updated attributes directly
         Self::construct_attributes(metadata, size, id).await
     }
 
+    #[tracing::instrument(
+        name = "nfs.read",
+        level = "debug",
+        skip_all,
+        fields(id = id, offset = offset, count = count, bytes = tracing::field::Empty)
+    )]
     async fn read(
         &self,
         id: fileid3,
         offset: u64,
         count: u32,
     ) -> Result<(Vec<u8>, bool), nfsstat3> {
+        let _timer = self.metrics.time(NfsOp::Read);
         tracing::trace!("read: id: {}, offset: {}, count: {}", id, offset, count);
 
+        // Clients are told not to read more than rtmax at once, so a larger read is served short
+        let count = count.min(*self.transfer.get_rsize());
+
         // Get path from fileid
         let path = self.fileid_to_path(id).await?;
 
-        // Get root directory
-        let root = self.root.lock().await;
+        // Get root directory, with buffered writes to the file applied
+        let mut root = self.root.lock().await;
+        self.flush_file(&mut root, id).await?;
 
         // Get the file
         let entity = if path.is_empty() {
@@ -522,52 +1523,43 @@ where
         };
 
         // Ensure it's a file and read its content
-        match entity {
+        let (buffer, size) = match entity {
             Entity::File(file) => {
-                use tokio::io::{AsyncReadExt, AsyncSeekExt, SeekFrom};
-
-                if offset >= file.get_size().await? {
-                    return Ok((Vec::new(), true));
-                }
+                let size = file.get_size().await?;
 
-                let mut input_stream = file.get_input_stream().await.map_err(|e| {
-                    tracing::error!("Failed to get input stream: {}", e);
-                    nfsstat3::NFS3ERR_IO
-                })?;
-
-                // Seek to offset
-                input_stream
-                    .seek(SeekFrom::Start(offset))
+                // Only fetch the chunks overlapping the requested range, prefetching the ranges
+                // that follow if the file is read sequentially
+                let buffer = self
+                    .read_ahead
+                    .lock()
+                    .await
+                    .read(id, &file, offset, count as usize)
                     .await
                     .map_err(|e| {
-                        tracing::error!("Failed to seek: {}", e);
+                        tracing::error!("Failed to read: {}", e);
                         nfsstat3::NFS3ERR_IO
                     })?;
 
-                // Read requested bytes
-                let mut buffer = vec![0; count as usize];
-                let bytes_read = input_stream.read(&mut buffer).await.map_err(|e| {
-                    tracing::error!("Failed to read: {}", e);
-                    nfsstat3::NFS3ERR_IO
-                })?;
-
-                // Truncate buffer to actual bytes read
-                buffer.truncate(bytes_read);
+                (buffer, size)
+            }
+            _ => return Err(nfsstat3::NFS3ERR_NOTDIR),
+        };
 
-                // Check if we've reached the end by trying to read one more byte
-                let mut peek_buf = [0u8; 1];
-                let reached_end = input_stream.read(&mut peek_buf).await.map_err(|e| {
-                    tracing::error!("Failed to peek: {}", e);
-                    nfsstat3::NFS3ERR_IO
-                })? == 0;
+        self.record_access(&mut root, &path).await?;
 
-                Ok((buffer, reached_end))
-            }
-            _ => Err(nfsstat3::NFS3ERR_NOTDIR),
-        }
+        tracing::Span::current().record("bytes", buffer.len());
+        let reached_end = offset.saturating_add(buffer.len() as u64) >= size;
+        Ok((buffer, reached_end))
     }
 
+    #[tracing::instrument(
+        name = "nfs.write",
+        level = "debug",
+        skip_all,
+        fields(id = id, offset = offset, bytes = data.len())
+    )]
     async fn write(&self, id: fileid3, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
+        let _timer = self.metrics.time(NfsOp::Write);
         tracing::trace!("write: id: {}, offset: {}, data: {:?}", id, offset, data);
 
         // Get path from fileid
@@ -575,6 +1567,8 @@ where
 
         // Get root directory
         let mut root = self.root.lock().await;
+        self.invalidate_lookups(&root, &path).await?;
+        let mut write_back = self.write_back.lock().await;
 
         // Get the file
         let entity = if path.is_empty() {
@@ -583,121 +1577,95 @@ where
             root.find_mut(&path).await?.ok_or(nfsstat3::NFS3ERR_NOENT)?
         };
 
-        // Ensure it's a file and write its content
-        match entity {
-            Entity::File(file) => {
-                use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, SeekFrom};
-
-                // Get original file size
-                let original_size = file.get_size().await.map_err(|e| {
-                    tracing::error!("Failed to get original file size: {}", e);
-                    nfsstat3::NFS3ERR_IO
-                })?;
-
-                // Reject writes that would create holes (sparse files)
-                if offset > original_size {
-                    tracing::error!("Attempted to write at offset {} beyond file size {}, which would create a sparse file", offset, original_size);
-                    return Err(nfsstat3::NFS3ERR_INVAL);
-                }
+        let Entity::File(file) = entity else {
+            return Err(nfsstat3::NFS3ERR_NOTDIR);
+        };
 
-                // First checkpoint the file to create a versioned copy
-                let checkpoint_cid = file.checkpoint().await.map_err(|e| {
-                    tracing::error!("Failed to checkpoint file: {}", e);
-                    nfsstat3::NFS3ERR_IO
-                })?;
+        // Get the size the file has with its buffered writes applied
+        let original_size = file.get_size().await.map_err(|e| {
+            tracing::error!("Failed to get original file size: {}", e);
+            nfsstat3::NFS3ERR_IO
+        })?;
+        let original_size = write_back.get_size(id, original_size);
 
-                // Load the checkpointed version as our original file
-                let original_file = File::load(&checkpoint_cid, file.get_store().clone())
+        // Reject writes that would create holes (sparse files)
+        if offset > original_size {
+            tracing::error!("Attempted to write at offset {} beyond file size {}, which would create a sparse file", offset, original_size);
+            return Err(nfsstat3::NFS3ERR_INVAL);
+        }
+
+        // Reject writes that would take the filesystem past its quota. Buffered writes have yet to
+        // reach the store, so they count towards what the store is about to hold.
+        let mut quota = self.quota.lock().await;
+        let growth = offset
+            .saturating_add(data.len() as u64)
+            .saturating_sub(original_size);
+        quota.check_write(growth, write_back.get_dirty_bytes() + data.len() as u64)?;
+
+        if !write_back.is_enabled() {
+            write_at(file, offset, data).await.map_err(|e| {
+                tracing::error!("Failed to write: {}", e);
+                nfsstat3::NFS3ERR_IO
+            })?;
+            self.notify(ChangeKind::Modified, &path, file.get_content());
+        } else if !write_back.buffer(id, offset, data) {
+            // The write does not continue the buffered one, which has to be applied first
+            if let Some(pending) = write_back.take(id) {
+                write_at(file, *pending.get_offset(), pending.get_data())
                     .await
                     .map_err(|e| {
-                        tracing::error!("Failed to load checkpointed file: {}", e);
-                        nfsstat3::NFS3ERR_IO
-                    })?;
-
-                // Create output stream for the new version
-                let mut output = file.get_output_stream();
-
-                // If we're not writing at the start, copy existing data up to offset
-                if offset > 0 {
-                    let mut input = original_file.get_input_stream().await.map_err(|e| {
-                        tracing::error!("Failed to get input stream: {}", e);
-                        nfsstat3::NFS3ERR_IO
-                    })?;
-
-                    // Copy data up to offset
-                    let mut buffer = vec![0u8; offset as usize];
-                    let bytes_read = input.read(&mut buffer).await.map_err(|e| {
-                        tracing::error!("Failed to read existing data: {}", e);
-                        nfsstat3::NFS3ERR_IO
-                    })?;
-                    buffer.truncate(bytes_read);
-
-                    output.write_all(&buffer).await.map_err(|e| {
-                        tracing::error!("Failed to write existing data: {}", e);
-                        nfsstat3::NFS3ERR_IO
-                    })?;
-                }
-
-                // Write the new data
-                output.write_all(data).await.map_err(|e| {
-                    tracing::error!("Failed to write new data: {}", e);
-                    nfsstat3::NFS3ERR_IO
-                })?;
-
-                // If there's existing data after our write, append it
-                let end_offset = offset + data.len() as u64;
-                if end_offset < original_size {
-                    let mut input = original_file.get_input_stream().await.map_err(|e| {
-                        tracing::error!("Failed to get input stream: {}", e);
-                        nfsstat3::NFS3ERR_IO
-                    })?;
-
-                    // Seek to where we ended our write
-                    input.seek(SeekFrom::Start(end_offset)).await.map_err(|e| {
-                        tracing::error!("Failed to seek input stream: {}", e);
-                        nfsstat3::NFS3ERR_IO
-                    })?;
-
-                    // Read and write the remaining data
-                    let mut buffer = vec![0u8; (original_size - end_offset) as usize];
-                    let bytes_read = input.read(&mut buffer).await.map_err(|e| {
-                        tracing::error!("Failed to read remaining data: {}", e);
-                        nfsstat3::NFS3ERR_IO
-                    })?;
-                    buffer.truncate(bytes_read);
-
-                    output.write_all(&buffer).await.map_err(|e| {
-                        tracing::error!("Failed to write remaining data: {}", e);
+                        tracing::error!("Failed to flush buffered write: {}", e);
                         nfsstat3::NFS3ERR_IO
                     })?;
-                }
-
-                // Finalize the write
-                output.flush().await.map_err(|e| {
-                    tracing::error!("Failed to finalize write: {}", e);
-                    nfsstat3::NFS3ERR_IO
-                })?;
-
-                drop(output);
+                self.notify(ChangeKind::Modified, &path, file.get_content());
+            }
+            write_back.buffer(id, offset, data);
+        }
 
-                // Get updated attributes
-                let final_size = file.get_size().await.map_err(|e| {
-                    tracing::error!("Failed to get final file size: {}", e);
-                    nfsstat3::NFS3ERR_IO
-                })?;
+        // Buffered writes are recorded when they are acknowledged, before they store anything
+        let cid = match write_back.is_enabled() {
+            true => None,
+            false => file.get_content(),
+        };
+        self.audit(AuditEvent::new(AuditOperation::Write, &path, cid))
+            .await;
 
-                Self::construct_attributes(file.get_metadata(), final_size, id).await
-            }
-            _ => Err(nfsstat3::NFS3ERR_NOTDIR),
+        // The file is modified now, even if the write is only applied when it is flushed
+        file.get_metadata_mut().mark_modified(Utc::now());
+
+        // Get updated attributes
+        let final_size = file.get_size().await.map_err(|e| {
+            tracing::error!("Failed to get final file size: {}", e);
+            nfsstat3::NFS3ERR_IO
+        })?;
+        let final_size = write_back.get_size(id, final_size);
+        let attrs = Self::construct_attributes(file.get_metadata(), final_size, id).await?;
+        quota.grow(final_size.saturating_sub(original_size));
+        drop(quota);
+
+        // Flush everything once enough is buffered
+        if write_back.is_enabled() && write_back.is_full() {
+            let pending = write_back.take_all();
+            drop(write_back);
+            self.apply_writes(&mut root, pending).await?;
         }
+
+        Ok(attrs)
     }
 
+    #[tracing::instrument(
+        name = "nfs.create",
+        level = "debug",
+        skip_all,
+        fields(dirid = dirid, name = ?filename)
+    )]
     async fn create(
         &self,
         dirid: fileid3,
         filename: &filename3,
         attr: sattr3,
     ) -> Result<(fileid3, fattr3), nfsstat3> {
+        let _timer = self.metrics.time(NfsOp::Create);
         tracing::trace!(
             "create: dirid: {}, filename: {:?}, attr: {:?}",
             dirid,
@@ -717,6 +1685,7 @@ where
 
         // Get root directory
         let mut root = self.root.lock().await;
+        self.invalidate_lookups(&root, &parent_path).await?;
 
         // Get parent directory - handle root directory case specially
         let parent_dir = if parent_path.is_empty() {
@@ -734,6 +1703,9 @@ where
             return Err(nfsstat3::NFS3ERR_EXIST);
         }
 
+        // Refuse new entries once the store is full
+        self.quota.lock().await.check_store()?;
+
         // Create new file
         let entity = parent_dir.find_or_create(filename_str, true).await?;
 
@@ -742,17 +1714,18 @@ where
             // Set default mode if not specified
             if matches!(attr.mode, set_mode3::Void) {
                 file.get_metadata_mut()
-                    .set_attribute(UNIX_MODE_KEY, DEFAULT_FILE_MODE.to_string())
+                    .set_permissions(DEFAULT_FILE_MODE)
                     .await
                     .map_err(nfsstat3::from)?;
             }
 
             // Update all attributes
             Self::update_attributes(file.get_metadata_mut(), &attr).await?;
+            self.squash_new_owner(file.get_metadata_mut()).await?;
 
-            // Handle size separately since it requires truncating the file
-            if let set_size3::size(_) = attr.size {
-                file.truncate();
+            // Handle size separately since it requires resizing the file
+            if let set_size3::size(size) = attr.size {
+                file.truncate(size).await?;
             }
         } else {
             return Err(nfsstat3::NFS3ERR_INVAL);
@@ -762,6 +1735,9 @@ where
 
         // Construct full path and ensure it is registered
         let full_path = join_path(&parent_path, filename_str);
+        self.notify(ChangeKind::Created, &full_path, None);
+        self.audit(AuditEvent::new(AuditOperation::Create, &full_path, None))
+            .await;
 
         // Ensure path is registered and get its fileid
         let fileid = self.ensure_path_registered_str(&full_path).await?;
@@ -772,11 +1748,18 @@ where
         Ok((fileid, attrs))
     }
 
+    #[tracing::instrument(
+        name = "nfs.create_exclusive",
+        level = "debug",
+        skip_all,
+        fields(dirid = dirid, name = ?filename)
+    )]
     async fn create_exclusive(
         &self,
         dirid: fileid3,
         filename: &filename3,
     ) -> Result<fileid3, nfsstat3> {
+        let _timer = self.metrics.time(NfsOp::CreateExclusive);
         tracing::trace!(
             "create_exclusive: dirid: {}, filename: {:?}",
             dirid,
@@ -795,6 +1778,7 @@ where
 
         // Get root directory
         let mut root = self.root.lock().await;
+        self.invalidate_lookups(&root, &parent_path).await?;
 
         // Get parent directory - handle root directory case specially
         let parent_dir = if parent_path.is_empty() {
@@ -812,6 +1796,9 @@ where
             return Err(nfsstat3::NFS3ERR_EXIST);
         }
 
+        // Refuse new entries once the store is full
+        self.quota.lock().await.check_store()?;
+
         // Create new file with default attributes
         let entity = parent_dir.find_or_create(filename_str, true).await?;
 
@@ -819,9 +1806,10 @@ where
         if let Entity::File(ref mut file) = entity {
             // Set default mode
             file.get_metadata_mut()
-                .set_attribute(UNIX_MODE_KEY, DEFAULT_FILE_MODE.to_string())
+                .set_permissions(DEFAULT_FILE_MODE)
                 .await
                 .map_err(nfsstat3::from)?;
+            self.squash_new_owner(file.get_metadata_mut()).await?;
         } else {
             return Err(nfsstat3::NFS3ERR_INVAL);
         }
@@ -830,16 +1818,29 @@ where
 
         // Construct full path and ensure it is registered
         let full_path = join_path(&parent_path, filename_str);
+        self.notify(ChangeKind::Created, &full_path, None);
+        self.audit(AuditEvent::new(AuditOperation::Create, &full_path, None))
+            .await;
 
-        // Ensure path is registered and get its fileid
-        self.ensure_path_registered_str(&full_path).await
+        // Ensure path is registered and get its fileid. Its buffered writes are held until it is
+        // renamed, committed or left idle, like those of a temporary file
+        let id = self.ensure_path_registered_str(&full_path).await?;
+        self.write_back.lock().await.hold(id);
+        Ok(id)
     }
 
+    #[tracing::instrument(
+        name = "nfs.mkdir",
+        level = "debug",
+        skip_all,
+        fields(dirid = dirid, name = ?dirname)
+    )]
     async fn mkdir(
         &self,
         dirid: fileid3,
         dirname: &filename3,
     ) -> Result<(fileid3, fattr3), nfsstat3> {
+        let _timer = self.metrics.time(NfsOp::Mkdir);
         tracing::trace!("mkdir: dirid: {}, dirname: {:?}", dirid, dirname);
         // Convert dirname bytes to string, ensuring valid UTF-8
         let dirname_str = str::from_utf8(dirname).map_err(|_| nfsstat3::NFS3ERR_INVAL)?;
@@ -854,6 +1855,7 @@ where
 
         // Get root directory
         let mut root = self.root.lock().await;
+        self.invalidate_lookups(&root, &parent_path).await?;
 
         // Get parent directory - handle root directory case specially
         let parent_dir = if parent_path.is_empty() {
@@ -871,6 +1873,9 @@ where
             return Err(nfsstat3::NFS3ERR_EXIST);
         }
 
+        // Refuse new entries once the store is full
+        self.quota.lock().await.check_store()?;
+
         // Create new directory
         let entity = parent_dir.find_or_create(dirname_str, false).await?;
 
@@ -878,9 +1883,10 @@ where
         if let Entity::Dir(ref mut dir) = entity {
             // Set default mode
             dir.get_metadata_mut()
-                .set_attribute(UNIX_MODE_KEY, DEFAULT_DIR_MODE.to_string())
+                .set_permissions(DEFAULT_DIR_MODE)
                 .await
                 .map_err(nfsstat3::from)?;
+            self.squash_new_owner(dir.get_metadata_mut()).await?;
         } else {
             return Err(nfsstat3::NFS3ERR_INVAL);
         }
@@ -889,6 +1895,9 @@ where
 
         // Construct full path and ensure it is registered
         let full_path = join_path(&parent_path, dirname_str);
+        self.notify(ChangeKind::Created, &full_path, None);
+        self.audit(AuditEvent::new(AuditOperation::Create, &full_path, None))
+            .await;
 
         // Ensure path is registered and get its fileid
         let fileid = self.ensure_path_registered_str(&full_path).await?;
@@ -899,7 +1908,14 @@ where
         Ok((fileid, attrs))
     }
 
+    #[tracing::instrument(
+        name = "nfs.remove",
+        level = "debug",
+        skip_all,
+        fields(dirid = dirid, name = ?filename)
+    )]
     async fn remove(&self, dirid: fileid3, filename: &filename3) -> Result<(), nfsstat3> {
+        let _timer = self.metrics.time(NfsOp::Remove);
         tracing::trace!("remove: dirid: {}, filename: {:?}", dirid, filename);
 
         // Convert filename bytes to string, ensuring valid UTF-8
@@ -913,16 +1929,61 @@ where
         // Get parent directory path
         let parent_path = self.fileid_to_path(dirid).await?;
 
-        // Get root directory
-        let mut root = self.root.lock().await;
-
         // Construct the full path
         let full_path = join_path(&parent_path, filename_str);
 
+        // Get root directory. Buffered writes are applied first, as they are tracked by file ID,
+        // which may refer to a different file after the remove. The buffered write of a held file
+        // is discarded instead, so a temporary file that is removed never reaches the store. Other
+        // held files keep their writes held, unless a directory that may contain them is removed.
+        let mut root = self.root.lock().await;
+        self.invalidate_lookups(&root, &parent_path).await?;
+        let id = self
+            .get_path_registered(&self.path_to_symbols(&full_path).await?)
+            .await?;
+        let is_dir = matches!(root.find(&full_path).await?, Some(Entity::Dir(_)));
+        let mut write_back = self.write_back.lock().await;
+        let discarded = match id {
+            Some(id) if write_back.release(id) => write_back.take(id),
+            _ => None,
+        };
+        let pending = if is_dir {
+            write_back.take_all()
+        } else {
+            write_back.take_unheld()
+        };
+        drop(write_back);
+        self.apply_writes(&mut root, pending).await?;
+
+        // Measure what is removed before it is gone, including the discarded write
+        let mut quota = self.quota.lock().await;
+        let removed = if quota.is_limited() {
+            let stored = get_entity_logical_size(&root, &full_path).await?;
+            discarded.map_or(stored, |write| stored.max(write.end()))
+        } else {
+            0
+        };
+
         // Use Dir's remove operation
-        root.remove(&full_path).await.map_err(nfsstat3::from)
+        root.remove(&full_path).await.map_err(nfsstat3::from)?;
+        quota.shrink(removed);
+        self.notify(ChangeKind::Removed, &full_path, None);
+        self.audit(AuditEvent::new(AuditOperation::Remove, &full_path, None))
+            .await;
+        Ok(())
     }
 
+    #[tracing::instrument(
+        name = "nfs.rename",
+        level = "debug",
+        skip_all,
+        fields(
+            from_dirid = from_dirid,
+            from_name = ?from_filename,
+            to_dirid = to_dirid,
+            to_name = ?to_filename
+        )
+    )]
     async fn rename(
         &self,
         from_dirid: fileid3,
@@ -930,6 +1991,7 @@ where
         to_dirid: fileid3,
         to_filename: &filename3,
     ) -> Result<(), nfsstat3> {
+        let _timer = self.metrics.time(NfsOp::Rename);
         tracing::trace!(
             "rename: from_dirid: {}, from_filename: {:?}, to_dirid: {}, to_filename: {:?}",
             from_dirid,
@@ -956,19 +2018,65 @@ where
         let from_path = join_path(&from_dir_path, from_filename_str);
         let to_path = join_path(&to_dir_path, to_filename_str);
 
-        // Get root directory and use Dir's rename operation
+        // Get root directory and use Dir's rename operation. Buffered writes are applied first, as
+        // they are tracked by file ID, which may refer to a different file after the rename. A
+        // held file is released by renaming it. Other held files keep their writes held, unless a
+        // directory that may contain them is renamed.
         let mut root = self.root.lock().await;
+        self.invalidate_lookups(&root, &from_dir_path).await?;
+        self.invalidate_lookups(&root, &to_dir_path).await?;
+        let from_id = self
+            .get_path_registered(&self.path_to_symbols(&from_path).await?)
+            .await?;
+        let is_dir = matches!(root.find(&from_path).await?, Some(Entity::Dir(_)));
+        let mut write_back = self.write_back.lock().await;
+        if let Some(id) = from_id {
+            write_back.release(id);
+        }
+        let pending = if is_dir {
+            write_back.take_all()
+        } else {
+            write_back.take_unheld()
+        };
+        drop(write_back);
+        self.apply_writes(&mut root, pending).await?;
+
+        // Measure what the rename replaces, if anything, before it is gone
+        let mut quota = self.quota.lock().await;
+        let replaced = if quota.is_limited() && from_path != to_path {
+            get_entity_logical_size(&root, &to_path).await?
+        } else {
+            0
+        };
+
         root.rename(&from_path, &to_path)
             .await
-            .map_err(nfsstat3::from)
+            .map_err(nfsstat3::from)?;
+        quota.shrink(replaced);
+        if from_path != to_path {
+            self.notify(ChangeKind::Removed, &from_path, None);
+            self.notify(ChangeKind::Created, &to_path, None);
+            self.audit(
+                AuditEvent::new(AuditOperation::Rename, &from_path, None).with_new_path(&to_path),
+            )
+            .await;
+        }
+        Ok(())
     }
 
+    #[tracing::instrument(
+        name = "nfs.readdir",
+        level = "debug",
+        skip_all,
+        fields(dirid = dirid, start_after = start_after, max_entries = max_entries, entries = tracing::field::Empty)
+    )]
     async fn readdir(
         &self,
         dirid: fileid3,
         start_after: fileid3,
         max_entries: usize,
     ) -> Result<ReadDirResult, nfsstat3> {
+        let _timer = self.metrics.time(NfsOp::Readdir);
         tracing::trace!(
             "readdir: dirid: {}, start_after: {}, max_entries: {}",
             dirid,
@@ -993,43 +2101,57 @@ where
             }
         };
 
-        let mut entries = Vec::new();
-        let mut found_start = start_after == 0;
-        let mut has_more = false;
-
-        for (name, link) in dir.get_entries() {
-            // Skip entries until we find the start_after fileid
-            if !found_start {
-                let entry_path = join_path(&dir_path, name.as_str());
-
-                // Try to get existing fileid without creating a new one
-                if let Some(entry_id) = self.get_path_registered_str(&entry_path).await? {
-                    if entry_id == start_after {
-                        found_start = true;
-                    }
+        // Resume after the entry with the start_after fileid, which is looked up by name so that
+        // paging does not have to walk the entries before it
+        let cursor = if start_after == 0 {
+            None
+        } else {
+            let end = ReadDirResult {
+                entries: Vec::new(),
+                end: true,
+            };
+            let Ok(start_path) = self.fileid_to_path(start_after).await else {
+                return Ok(end);
+            };
+            match start_path
+                .rsplit_once('/')
+                .unwrap_or(("", start_path.as_str()))
+            {
+                (parent, name) if parent == dir_path => {
+                    Some(DirCursor::from(name.parse::<Utf8UnixPathSegment>()?))
                 }
-                continue;
+                _ => return Ok(end),
             }
+        };
 
-            // Resolve the entity to get its metadata
-            let entity = link.resolve_entity(dir.get_store().clone()).await?;
+        // List one entry past the page to tell whether there are more entries
+        let mut names = dir.list_after(cursor.as_ref());
+        let has_more = names.len() > max_entries;
+        names.truncate(max_entries);
+
+        // Get the attributes of the entries through the lookup cache, loading the entries it has
+        // no answer for concurrently. This also primes the cache for the LOOKUP and GETATTR calls
+        // clients usually make for each listed entry.
+        let attrs = stream::iter(&names)
+            .map(|name| self.get_entry_attributes(dir, name.as_str()))
+            .buffered(DIR_LOAD_CONCURRENCY)
+            .collect::<Vec<_>>()
+            .await;
 
-            // Get the full path for this entry
-            let entry_path = join_path(&dir_path, name.as_str());
+        let write_back = self.write_back.lock().await;
+        let mut entries = Vec::with_capacity(names.len());
+        for (name, attr) in names.into_iter().zip(attrs) {
+            let Some(mut attr) = attr? else {
+                continue;
+            };
 
             // Get or create fileid for this entry
+            let entry_path = join_path(&dir_path, name.as_str());
             let fileid = self.ensure_path_registered_str(&entry_path).await?;
 
-            // Construct attributes for this entry
-            let attr =
-                Self::construct_attributes(entity.get_metadata(), entity.get_size().await?, fileid)
-                    .await?;
-
-            // If we've reached max_entries, note that there are more entries and break
-            if entries.len() >= max_entries {
-                has_more = true;
-                break;
-            }
+            // Account for writes that are still buffered
+            attr.fileid = fileid;
+            attr.size = write_back.get_size(fileid, attr.size);
 
             entries.push(DirEntry {
                 fileid,
@@ -1038,12 +2160,19 @@ where
             });
         }
 
+        tracing::Span::current().record("entries", entries.len());
         Ok(ReadDirResult {
             entries,
             end: !has_more, // Set end to true only if we've processed all entries
         })
     }
 
+    #[tracing::instrument(
+        name = "nfs.symlink",
+        level = "debug",
+        skip_all,
+        fields(dirid = dirid, name = ?linkname)
+    )]
     async fn symlink(
         &self,
         dirid: fileid3,
@@ -1051,6 +2180,7 @@ where
         symlink: &nfspath3,
         attr: &sattr3,
     ) -> Result<(fileid3, fattr3), nfsstat3> {
+        let _timer = self.metrics.time(NfsOp::Symlink);
         tracing::trace!(
             "symlink: dirid: {}, linkname: {:?}, symlink: {:?}, attr: {:?}",
             dirid,
@@ -1075,6 +2205,7 @@ where
 
         // Get root directory
         let mut root = self.root.lock().await;
+        self.invalidate_lookups(&root, &parent_path).await?;
 
         // Get parent directory - handle root directory case specially
         let parent_dir = if parent_path.is_empty() {
@@ -1092,6 +2223,9 @@ where
             return Err(nfsstat3::NFS3ERR_EXIST);
         }
 
+        // Refuse new entries once the store is full
+        self.quota.lock().await.check_store()?;
+
         // Create new symlink
         let mut symlink = SymPathLink::with_path(parent_dir.get_store().clone(), target_path)
             .map_err(nfsstat3::from)?;
@@ -1100,13 +2234,14 @@ where
         if matches!(attr.mode, set_mode3::Void) {
             symlink
                 .get_metadata_mut()
-                .set_attribute(UNIX_MODE_KEY, DEFAULT_SYMLINK_MODE.to_string())
+                .set_permissions(DEFAULT_SYMLINK_MODE)
                 .await
                 .map_err(nfsstat3::from)?;
         }
 
         // Update all attributes
         Self::update_attributes(symlink.get_metadata_mut(), attr).await?;
+        self.squash_new_owner(symlink.get_metadata_mut()).await?;
 
         // Add symlink to parent directory
         parent_dir
@@ -1117,6 +2252,9 @@ where
 
         // Construct full path and ensure it is registered
         let full_path = join_path(&parent_path, linkname_str);
+        self.notify(ChangeKind::Created, &full_path, None);
+        self.audit(AuditEvent::new(AuditOperation::Create, &full_path, None))
+            .await;
 
         // Ensure path is registered and get its fileid
         let fileid = self.ensure_path_registered_str(&full_path).await?;
@@ -1127,7 +2265,9 @@ where
         Ok((fileid, attrs))
     }
 
+    #[tracing::instrument(name = "nfs.readlink", level = "debug", skip_all, fields(id = id))]
     async fn readlink(&self, id: fileid3) -> Result<nfspath3, nfsstat3> {
+        let _timer = self.metrics.time(NfsOp::Readlink);
         tracing::trace!("readlink: id: {}", id);
 
         // Get path from fileid
@@ -1154,18 +2294,57 @@ where
     }
 }
 
+impl<S> Clone for MonofsNFS<S>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            filenames: self.filenames.clone(),
+            fileid_to_path_map: self.fileid_to_path_map.clone(),
+            path_to_fileid_map: self.path_to_fileid_map.clone(),
+            new_file_ids: self.new_file_ids.clone(),
+            audit: self.audit.clone(),
+            write_back: self.write_back.clone(),
+            lookups: self.lookups.clone(),
+            read_ahead: self.read_ahead.clone(),
+            quota: self.quota.clone(),
+            follow_policy: self.follow_policy.clone(),
+            atime_policy: self.atime_policy.clone(),
+            subtree: self.subtree.clone(),
+            changes: self.changes.clone(),
+            metrics: self.metrics.clone(),
+            transfer: self.transfer,
+            exports: self.exports.clone(),
+        }
+    }
+}
+
 impl From<FsError> for nfsstat3 {
     fn from(error: FsError) -> Self {
         tracing::error!("Converting FsError to nfsstat3: {:?}", error);
         match error {
             FsError::PathNotFound(_) => nfsstat3::NFS3ERR_NOENT,
             FsError::InvalidPathComponent(_) => nfsstat3::NFS3ERR_INVAL,
+            FsError::IpldStore(StoreError::Custom(e))
+                if matches!(
+                    e.downcast::<FsError>(),
+                    Some(FsError::MemoryStoreFull { .. })
+                ) =>
+            {
+                nfsstat3::NFS3ERR_NOSPC
+            }
             FsError::IpldStore(_) => nfsstat3::NFS3ERR_IO,
             FsError::NotAFile(_) => nfsstat3::NFS3ERR_NOTDIR,
             FsError::NotADirectory(_) => nfsstat3::NFS3ERR_NOTDIR,
             FsError::NotASymCidLink(_) => nfsstat3::NFS3ERR_INVAL,
             FsError::NotASymPathLink(_) => nfsstat3::NFS3ERR_INVAL,
             FsError::BrokenSymCidLink(_) => nfsstat3::NFS3ERR_NOENT,
+            FsError::SymPathLinkEscapesRoot(_) => nfsstat3::NFS3ERR_ACCES,
+            FsError::LogicalQuotaExceeded { .. } => nfsstat3::NFS3ERR_DQUOT,
+            FsError::StoreQuotaExceeded { .. } => nfsstat3::NFS3ERR_NOSPC,
+            FsError::MemoryStoreFull { .. } => nfsstat3::NFS3ERR_NOSPC,
             _ => nfsstat3::NFS3ERR_IO,
         }
     }
@@ -1175,6 +2354,15 @@ impl From<FsError> for nfsstat3 {
 // Functions
 //--------------------------------------------------------------------------------------------------
 
+/// Runs `call` to the filesystem as the user `uid` and the group `gid`, which the audit log
+/// records the changes it makes as. See [`MonofsNFS::enable_audit`].
+pub async fn call_as<F>(uid: u32, gid: u32, call: F) -> F::Output
+where
+    F: Future,
+{
+    CALLER.scope((uid, gid), call).await
+}
+
 fn join_path(base_path: &str, name: &str) -> String {
     if base_path.is_empty() {
         name.to_string()
@@ -1183,12 +2371,107 @@ fn join_path(base_path: &str, name: &str) -> String {
     }
 }
 
+/// Converts `time` to the time of an NFS attribute.
+fn to_nfstime(time: &DateTime<Utc>) -> nfstime3 {
+    nfstime3 {
+        seconds: time.timestamp() as u32,
+        nseconds: time.timestamp_subsec_nanos(),
+    }
+}
+
+/// Returns the file ID derived from `path`, which is the same every time the server starts.
+///
+/// The root has file ID 0, and the IDs of other paths are taken from the hash of the path, from 1
+/// to [`MAX_FILEID`].
+fn get_path_fileid(path: &str) -> fileid3 {
+    let digest = Code::Blake3_256.digest(path.as_bytes());
+    let bytes = <[u8; 8]>::try_from(&digest.digest()[..8]).expect("digest has 32 bytes");
+    fileid3::from_le_bytes(bytes) % MAX_FILEID + 1
+}
+
+/// Returns whether `name` is one of the xattrs that read as CIDs, which nothing sets.
+fn is_cid_xattr(name: &str) -> bool {
+    name == CID_XATTR_NAME || name == CONTENT_CID_XATTR_NAME
+}
+
+/// Writes `data` at `offset` of `file`, keeping the content before and after it.
+///
+/// Writes at or past the end of the file, like those of a log being written, are appended
+/// without streaming the rest of the file through the store again.
+async fn write_at<S>(file: &mut File<S>, offset: u64, data: &[u8]) -> FsResult<()>
+where
+    S: IpldStoreSeekable + Send + Sync + 'static,
+{
+    if offset >= file.get_size().await? {
+        file.truncate(offset).await?;
+        return file.append(data).await;
+    }
+
+    let flags = OpenFlags::builder().write(true).build();
+    let mut stream = file.open_stream(flags).await?;
+    stream.seek(SeekFrom::Start(offset)).await?;
+    stream.write_all(data).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Returns the total size of the files in and below `dir`.
+#[async_recursion]
+async fn get_logical_size<S>(dir: &Dir<S>) -> FsResult<u64>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    let names = dir
+        .get_entry_names()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+
+    let mut size = 0;
+    for name in names {
+        size += match dir.get_entity(&name).await? {
+            Some(Entity::Dir(child)) => get_logical_size(child).await?,
+            Some(entity) => entity.get_size().await?,
+            None => 0,
+        };
+    }
+
+    Ok(size)
+}
+
+/// Returns the directory at `path` in `tree`, creating it if nothing is there.
+async fn get_subtree_dir<'a, S>(
+    tree: &'a mut Dir<S>,
+    path: &Utf8UnixPath,
+) -> FsResult<&'a mut Dir<S>>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    match tree.find_or_create(path.as_str(), false).await? {
+        Entity::Dir(dir) => Ok(dir),
+        _ => Err(FsError::NotADirectory(path.to_string())),
+    }
+}
+
+/// Returns the total size of the files at or below `path` in `root`, or 0 if nothing is there.
+async fn get_entity_logical_size<S>(root: &Dir<S>, path: &str) -> FsResult<u64>
+where
+    S: IpldStore + Send + Sync + 'static,
+{
+    match root.find(path).await? {
+        Some(Entity::Dir(dir)) => get_logical_size(dir).await,
+        Some(entity) => entity.get_size().await,
+        None => Ok(0),
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Tests
 //--------------------------------------------------------------------------------------------------
 
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::Ordering;
+
     use super::*;
 
     #[tokio::test]
@@ -1261,50 +2544,253 @@ mod tests {
         let server = MemoryMonofsNFS::new(MemoryStore::default());
         let filename = filename3::from("test.txt".as_bytes());
 
-        // Create a file first
-        let attr = sattr3::default();
-        let (created_id, _) = server.create(0, &filename, attr).await.unwrap();
+        // Create a file first
+        let attr = sattr3::default();
+        let (created_id, _) = server.create(0, &filename, attr).await.unwrap();
+
+        // Lookup the file
+        let looked_up_id = server.lookup(0, &filename).await.unwrap();
+        assert_eq!(created_id, looked_up_id);
+
+        // Try to lookup non-existent file
+        let nonexistent = filename3::from("nonexistent.txt".as_bytes());
+        let result = server.lookup(0, &nonexistent).await;
+        assert!(matches!(result, Err(nfsstat3::NFS3ERR_NOENT)));
+
+        // Try to lookup with invalid filename
+        let invalid_filename = filename3::from("test/invalid.txt".as_bytes());
+        let result = server.lookup(0, &invalid_filename).await;
+        assert!(matches!(result, Err(nfsstat3::NFS3ERR_INVAL)));
+    }
+
+    #[tokio::test]
+    async fn test_nfs_lookup_follow_policy() {
+        let server = MemoryMonofsNFS::new(MemoryStore::default());
+        let (file_id, _) = server
+            .create(
+                0,
+                &filename3::from("target.txt".as_bytes()),
+                sattr3::default(),
+            )
+            .await
+            .unwrap();
+        let link = filename3::from("link".as_bytes());
+        let (link_id, _) = server
+            .symlink(
+                0,
+                &link,
+                &nfspath3::from("target.txt".as_bytes()),
+                &sattr3::default(),
+            )
+            .await
+            .unwrap();
+        let escaping = filename3::from("escaping".as_bytes());
+        server
+            .symlink(
+                0,
+                &escaping,
+                &nfspath3::from("../etc".as_bytes()),
+                &sattr3::default(),
+            )
+            .await
+            .unwrap();
+
+        // Links are returned as they are by default
+        assert_eq!(server.lookup(0, &link).await.unwrap(), link_id);
+
+        // And resolved by the server when the policy follows them
+        server
+            .set_follow_policy(FollowPolicy::FinalComponent { max_depth: 10 })
+            .await;
+        assert_eq!(server.lookup(0, &link).await.unwrap(), file_id);
+        assert!(matches!(
+            server.lookup(0, &escaping).await,
+            Err(nfsstat3::NFS3ERR_ACCES)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_nfs_setattr() {
+        let server = MemoryMonofsNFS::new(MemoryStore::default());
+        let filename = filename3::from("test.txt".as_bytes());
+
+        // Create a file first
+        let attr = sattr3::default();
+        let (fileid, _) = server.create(0, &filename, attr).await.unwrap();
+
+        // Modify attributes
+        let mut new_attr = sattr3::default();
+        new_attr.mode = set_mode3::mode(0o600);
+        new_attr.uid = set_uid3::uid(1001);
+        new_attr.gid = set_gid3::gid(1001);
+
+        let updated_attrs = server.setattr(fileid, new_attr).await.unwrap();
+
+        // Verify attributes were updated
+        assert_eq!(updated_attrs.mode, 0o600);
+        assert_eq!(updated_attrs.uid, 1001);
+        assert_eq!(updated_attrs.gid, 1001);
+
+        // Try to setattr on non-existent file
+        let result = server.setattr(999, new_attr).await;
+        assert!(matches!(result, Err(nfsstat3::NFS3ERR_NOENT)));
+    }
+
+    #[tokio::test]
+    async fn test_nfs_default_ownership() {
+        let server = MemoryMonofsNFS::new(MemoryStore::default());
+        let filename = filename3::from("test.txt".as_bytes());
+
+        // Entities created without an owner belong to the user running the server
+        let (fileid, attrs) = server
+            .create(0, &filename, sattr3::default())
+            .await
+            .unwrap();
+        assert_eq!(attrs.mode, DEFAULT_FILE_MODE);
+        assert_eq!(attrs.uid, unistd::getuid().as_raw());
+        assert_eq!(attrs.gid, unistd::getgid().as_raw());
+
+        // Only permission bits are kept
+        let mut new_attr = sattr3::default();
+        new_attr.mode = set_mode3::mode(0o100600);
+        let updated_attrs = server.setattr(fileid, new_attr).await.unwrap();
+        assert_eq!(updated_attrs.mode, 0o600);
+    }
+
+    #[tokio::test]
+    async fn test_nfs_squash() {
+        let exports = ExportConfig::builder()
+            .squash(SquashMode::Root)
+            .anon_uid(65534)
+            .anon_gid(65533)
+            .build();
+        let server = MemoryMonofsNFS::new(MemoryStore::default()).with_exports(exports);
+
+        // Root is squashed whether it creates an entity or takes one over
+        let mut attr = sattr3::default();
+        attr.uid = set_uid3::uid(0);
+        attr.gid = set_gid3::gid(0);
+        let (fileid, attrs) = server
+            .create(0, &filename3::from("root.txt".as_bytes()), attr)
+            .await
+            .unwrap();
+        assert_eq!((attrs.uid, attrs.gid), (65534, 65533));
+
+        attr.uid = set_uid3::uid(1000);
+        let attrs = server.setattr(fileid, attr).await.unwrap();
+        assert_eq!((attrs.uid, attrs.gid), (1000, 65533));
+
+        // Every owner is squashed, including that of entities created without one
+        let exports = ExportConfig::builder()
+            .squash(SquashMode::All)
+            .anon_uid(65534)
+            .anon_gid(65533)
+            .build();
+        let server = MemoryMonofsNFS::new(MemoryStore::default()).with_exports(exports);
+        let (_, attrs) = server
+            .mkdir(0, &filename3::from("dir".as_bytes()))
+            .await
+            .unwrap();
+        assert_eq!((attrs.uid, attrs.gid), (65534, 65533));
+
+        let (fileid, _) = server
+            .create(
+                0,
+                &filename3::from("file.txt".as_bytes()),
+                sattr3::default(),
+            )
+            .await
+            .unwrap();
+        let attrs = server.setattr(fileid, attr).await.unwrap();
+        assert_eq!((attrs.uid, attrs.gid), (65534, 65533));
+    }
+
+    #[tokio::test]
+    async fn test_nfs_xattrs() {
+        let server = MemoryMonofsNFS::new(MemoryStore::default());
+        let filename = filename3::from("test.txt".as_bytes());
+        let (fileid, _) = server
+            .create(0, &filename, sattr3::default())
+            .await
+            .unwrap();
+
+        // Set xattrs on a file and on the root directory
+        server
+            .set_xattr(fileid, "user.comment", b"draft")
+            .await
+            .unwrap();
+        server.set_xattr(0, "user.owner", b"root").await.unwrap();
 
-        // Lookup the file
-        let looked_up_id = server.lookup(0, &filename).await.unwrap();
-        assert_eq!(created_id, looked_up_id);
+        let value = server.get_xattr(fileid, "user.comment").await.unwrap();
+        assert_eq!(value, Some(b"draft".to_vec()));
+        assert_eq!(
+            server.list_xattrs(fileid).await.unwrap(),
+            vec!["user.comment"]
+        );
+        assert_eq!(server.list_xattrs(0).await.unwrap(), vec!["user.owner"]);
+
+        // Xattrs are part of the checkpointed tree
+        let cid = server.checkpoint().await.unwrap();
+        assert!(server.remove_xattr(fileid, "user.comment").await.unwrap());
+        assert!(!server.remove_xattr(fileid, "user.comment").await.unwrap());
+        assert_eq!(
+            server.get_xattr(fileid, "user.comment").await.unwrap(),
+            None
+        );
 
-        // Try to lookup non-existent file
-        let nonexistent = filename3::from("nonexistent.txt".as_bytes());
-        let result = server.lookup(0, &nonexistent).await;
-        assert!(matches!(result, Err(nfsstat3::NFS3ERR_NOENT)));
+        server.set_root(&cid).await.unwrap();
+        let value = server.get_xattr(fileid, "user.comment").await.unwrap();
+        assert_eq!(value, Some(b"draft".to_vec()));
 
-        // Try to lookup with invalid filename
-        let invalid_filename = filename3::from("test/invalid.txt".as_bytes());
-        let result = server.lookup(0, &invalid_filename).await;
-        assert!(matches!(result, Err(nfsstat3::NFS3ERR_INVAL)));
+        // Try to get an xattr of a non-existent file
+        let result = server.get_xattr(999, "user.comment").await;
+        assert!(matches!(result, Err(nfsstat3::NFS3ERR_NOENT)));
     }
 
     #[tokio::test]
-    async fn test_nfs_setattr() {
-        let server = MemoryMonofsNFS::new(MemoryStore::default());
-        let filename = filename3::from("test.txt".as_bytes());
-
-        // Create a file first
-        let attr = sattr3::default();
-        let (fileid, _) = server.create(0, &filename, attr).await.unwrap();
+    async fn test_nfs_cid_xattrs() {
+        let config = WriteBackConfig::builder()
+            .flush_interval(std::time::Duration::from_secs(3600))
+            .build();
+        let server = &MemoryMonofsNFS::with_write_back(MemoryStore::default(), config);
+        let mut ids = Vec::new();
+        for name in ["a.txt", "b.txt"] {
+            let (id, _) = server
+                .create(0, &filename3::from(name.as_bytes()), sattr3::default())
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+        let get_cid = |id, name| async move {
+            let value = server.get_xattr(id, name).await.unwrap()?;
+            Some(Cid::try_from(str::from_utf8(&value).unwrap()).unwrap())
+        };
 
-        // Modify attributes
-        let mut new_attr = sattr3::default();
-        new_attr.mode = set_mode3::mode(0o600);
-        new_attr.uid = set_uid3::uid(1001);
-        new_attr.gid = set_gid3::gid(1001);
+        // Empty files have no content, and directories never do
+        assert_eq!(get_cid(ids[0], CONTENT_CID_XATTR_NAME).await, None);
+        assert_eq!(get_cid(0, CONTENT_CID_XATTR_NAME).await, None);
+        let empty_root = get_cid(0, CID_XATTR_NAME).await.unwrap();
 
-        let updated_attrs = server.setattr(fileid, new_attr).await.unwrap();
+        // Buffered writes are applied before the CIDs are read
+        for id in &ids {
+            server.write(*id, 0, b"Hello").await.unwrap();
+        }
+        let content = get_cid(ids[0], CONTENT_CID_XATTR_NAME).await.unwrap();
+        assert_eq!(get_cid(ids[1], CONTENT_CID_XATTR_NAME).await, Some(content));
+        assert_ne!(get_cid(0, CID_XATTR_NAME).await, Some(empty_root));
 
-        // Verify attributes were updated
-        assert_eq!(updated_attrs.mode, 0o600);
-        assert_eq!(updated_attrs.uid, 1001);
-        assert_eq!(updated_attrs.gid, 1001);
+        let cid = get_cid(ids[0], CID_XATTR_NAME).await.unwrap();
+        let root = server.root.lock().await;
+        let entity = root.find("a.txt").await.unwrap().unwrap();
+        assert_eq!(entity.get_cid().await.unwrap(), cid);
+        drop(root);
 
-        // Try to setattr on non-existent file
-        let result = server.setattr(999, new_attr).await;
-        assert!(matches!(result, Err(nfsstat3::NFS3ERR_NOENT)));
+        // The CIDs are neither listed nor set like other xattrs
+        assert!(server.list_xattrs(ids[0]).await.unwrap().is_empty());
+        let result = server.set_xattr(ids[0], CID_XATTR_NAME, b"cid").await;
+        assert!(matches!(result, Err(nfsstat3::NFS3ERR_PERM)));
+        let result = server.remove_xattr(ids[0], CONTENT_CID_XATTR_NAME).await;
+        assert!(matches!(result, Err(nfsstat3::NFS3ERR_PERM)));
     }
 
     #[tokio::test]
@@ -1932,4 +3418,656 @@ mod tests {
             Err(nfsstat3::NFS3ERR_NOENT)
         ));
     }
+
+    #[tokio::test]
+    async fn test_nfs_clone_file() {
+        let server = MemoryMonofsNFS::new(MemoryStore::default());
+        let (src_id, _) = server
+            .create(0, &filename3::from("src.txt".as_bytes()), sattr3::default())
+            .await
+            .unwrap();
+        let (dst_id, _) = server
+            .create(0, &filename3::from("dst.txt".as_bytes()), sattr3::default())
+            .await
+            .unwrap();
+        server.write(src_id, 0, b"Hello, World!").await.unwrap();
+
+        let attrs = server.clone_file(src_id, dst_id).await.unwrap();
+        assert_eq!(attrs.size, 13);
+        assert_eq!(attrs.fileid, dst_id);
+        let (data, _) = server.read(dst_id, 0, 13).await.unwrap();
+        assert_eq!(&data, b"Hello, World!");
+
+        // The clone is independent of its source
+        server.write(dst_id, 7, b"Clone").await.unwrap();
+        let (data, _) = server.read(src_id, 0, 13).await.unwrap();
+        assert_eq!(&data, b"Hello, World!");
+
+        // Only files can be cloned
+        let result = server.clone_file(0, dst_id).await;
+        assert!(matches!(result, Err(nfsstat3::NFS3ERR_INVAL)));
+    }
+
+    #[tokio::test]
+    async fn test_nfs_write_back() {
+        let config = WriteBackConfig::builder()
+            .max_dirty_bytes(16)
+            .flush_interval(std::time::Duration::from_secs(3600))
+            .build();
+        let server = MemoryMonofsNFS::with_write_back(MemoryStore::default(), config);
+        let (fileid, _) = server
+            .create(
+                0,
+                &filename3::from("test.txt".as_bytes()),
+                sattr3::default(),
+            )
+            .await
+            .unwrap();
+
+        // Sequential writes are buffered, but visible in the file's attributes
+        server.write(fileid, 0, b"Hello").await.unwrap();
+        let attrs = server.write(fileid, 5, b", World").await.unwrap();
+        assert_eq!(attrs.size, 12);
+        assert_eq!(server.getattr(fileid).await.unwrap().size, 12);
+        assert!(server.write_back.lock().await.contains(fileid));
+        match server.root.lock().await.find("test.txt").await.unwrap() {
+            Some(Entity::File(file)) => assert_eq!(file.get_size().await.unwrap(), 0),
+            _ => panic!("expected a file"),
+        }
+
+        // Reading applies them
+        let (data, eof) = server.read(fileid, 0, 12).await.unwrap();
+        assert_eq!(&data, b"Hello, World");
+        assert!(eof);
+        assert!(!server.write_back.lock().await.contains(fileid));
+
+        // A write elsewhere in the file applies the buffered one first
+        server.write(fileid, 12, b"!").await.unwrap();
+        server.write(fileid, 0, b"J").await.unwrap();
+        let (data, _) = server.read(fileid, 0, 13).await.unwrap();
+        assert_eq!(&data, b"Jello, World!");
+
+        // Buffering too much applies everything
+        server.write(fileid, 13, b" Hello, World!!!").await.unwrap();
+        assert!(!server.write_back.lock().await.contains(fileid));
+
+        // Writes are applied before the root is checkpointed
+        server.write(fileid, 29, b"?").await.unwrap();
+        let cid = server.checkpoint().await.unwrap();
+        server.set_root(&cid).await.unwrap();
+        let (data, _) = server.read(fileid, 0, 30).await.unwrap();
+        assert_eq!(&data, b"Jello, World! Hello, World!!!?");
+    }
+
+    #[tokio::test]
+    async fn test_nfs_temp_files() {
+        let interval = std::time::Duration::from_millis(200);
+        let config = WriteBackConfig::builder().flush_interval(interval).build();
+        let server = MemoryMonofsNFS::with_write_back(MemoryStore::default(), config);
+        let stored_size = |name: &'static str| {
+            let server = &server;
+            async move {
+                match server.root.lock().await.find(name).await.unwrap() {
+                    Some(Entity::File(file)) => file.get_size().await.unwrap(),
+                    _ => panic!("expected a file"),
+                }
+            }
+        };
+
+        // Writes to files created exclusively are held past the flush interval while the files
+        // are written to, unlike those of other files
+        let temp = filename3::from("a.tmp".as_bytes());
+        let temp_id = server.create_exclusive(0, &temp).await.unwrap();
+        server.write(temp_id, 0, b"scratch").await.unwrap();
+        let other = filename3::from("b.txt".as_bytes());
+        let (fileid, _) = server.create(0, &other, sattr3::default()).await.unwrap();
+        server.write(fileid, 0, b"flushed").await.unwrap();
+        tokio::time::sleep(interval).await;
+        server.write(temp_id, 7, b"!").await.unwrap();
+        server.flush_expired_writes().await.unwrap();
+        assert!(server.write_back.lock().await.contains(temp_id));
+        assert_eq!(stored_size("a.tmp").await, 0);
+        assert_eq!(stored_size("b.txt").await, 7);
+
+        // Removing the file discards them
+        server.remove(0, &temp).await.unwrap();
+        assert!(!server.write_back.lock().await.contains(temp_id));
+        assert!(!server.write_back.lock().await.is_held(temp_id));
+
+        // Renaming the file applies them
+        let temp_id = server.create_exclusive(0, &temp).await.unwrap();
+        server.write(temp_id, 0, b"kept").await.unwrap();
+        let target = filename3::from("a.txt".as_bytes());
+        server.rename(0, &temp, 0, &target).await.unwrap();
+        assert!(!server.write_back.lock().await.contains(temp_id));
+        assert_eq!(stored_size("a.txt").await, 4);
+
+        // Files left idle for the flush interval are released and flushed
+        let temp_id = server.create_exclusive(0, &temp).await.unwrap();
+        server.write(temp_id, 0, b"idle").await.unwrap();
+        tokio::time::sleep(interval).await;
+        server.flush_expired_writes().await.unwrap();
+        assert!(!server.write_back.lock().await.is_held(temp_id));
+        assert_eq!(stored_size("a.tmp").await, 4);
+
+        // Checkpointing, as a COMMIT does on disk, releases and flushes them too
+        let temp = filename3::from("c.tmp".as_bytes());
+        let temp_id = server.create_exclusive(0, &temp).await.unwrap();
+        server.write(temp_id, 0, b"committed").await.unwrap();
+        server.checkpoint().await.unwrap();
+        assert!(!server.write_back.lock().await.is_held(temp_id));
+        assert_eq!(stored_size("c.tmp").await, 9);
+    }
+
+    #[tokio::test]
+    async fn test_nfs_lookup_cache() {
+        let server = MemoryMonofsNFS::new(MemoryStore::default());
+        let filename = filename3::from("test.txt".as_bytes());
+        let missing = filename3::from("missing.txt".as_bytes());
+        let (fileid, _) = server
+            .create(0, &filename, sattr3::default())
+            .await
+            .unwrap();
+        let cid = server.checkpoint().await.unwrap();
+
+        // Lookups in an unchanged directory are cached, including names that do not exist
+        assert_eq!(server.getattr(fileid).await.unwrap().fileid, fileid);
+        assert!(matches!(
+            server.lookup(0, &missing).await,
+            Err(nfsstat3::NFS3ERR_NOENT)
+        ));
+        let mut lookups = server.lookups.lock().await;
+        assert!(matches!(lookups.get(&cid, "test.txt"), Some(Some(_))));
+        assert!(matches!(lookups.get(&cid, "missing.txt"), Some(None)));
+        drop(lookups);
+
+        // Changes are seen right away
+        server.create(0, &missing, sattr3::default()).await.unwrap();
+        assert!(server.lookup(0, &missing).await.is_ok());
+        server.write(fileid, 0, b"Hello").await.unwrap();
+        assert_eq!(server.getattr(fileid).await.unwrap().size, 5);
+        assert!(server.lookups.lock().await.get(&cid, "test.txt").is_none());
+
+        // The cached lookups still describe the old root
+        server.set_root(&cid).await.unwrap();
+        assert!(matches!(
+            server.lookup(0, &missing).await,
+            Err(nfsstat3::NFS3ERR_NOENT)
+        ));
+        assert_eq!(server.getattr(fileid).await.unwrap().size, 0);
+    }
+
+    #[tokio::test]
+    async fn test_nfs_readdir_primes_lookups() {
+        let server = MemoryMonofsNFS::new(MemoryStore::default());
+        for i in 0..5 {
+            let name = filename3::from(format!("file{}.txt", i).as_bytes());
+            server.create(0, &name, sattr3::default()).await.unwrap();
+        }
+        let cid = server.checkpoint().await.unwrap();
+
+        // Listing a page only loads the entries on it
+        let result = server.readdir(0, 0, 3).await.unwrap();
+        assert_eq!(result.entries.len(), 3);
+        assert!(!result.end);
+        let mut lookups = server.lookups.lock().await;
+        assert!(matches!(lookups.get(&cid, "file2.txt"), Some(Some(_))));
+        assert!(lookups.get(&cid, "file3.txt").is_none());
+        drop(lookups);
+
+        // Looking up the listed entries is answered from the cache
+        let (hits, _) = server.lookups.lock().await.get_hits_and_misses();
+        for entry in &result.entries {
+            assert_eq!(server.lookup(0, &entry.name).await.unwrap(), entry.fileid);
+            let attr = server.getattr(entry.fileid).await.unwrap();
+            assert_eq!(attr.fileid, entry.attr.fileid);
+            assert_eq!(attr.size, entry.attr.size);
+        }
+        let (new_hits, _) = server.lookups.lock().await.get_hits_and_misses();
+        assert!(new_hits >= hits + 6);
+
+        // The rest of the entries are listed after the last one
+        let last = result.entries.last().unwrap().fileid;
+        let result = server.readdir(0, last, 3).await.unwrap();
+        let names = result
+            .entries
+            .iter()
+            .map(|entry| str::from_utf8(&entry.name).unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["file3.txt", "file4.txt"]);
+        assert!(result.end);
+    }
+
+    #[tokio::test]
+    async fn test_nfs_transfer_sizes() {
+        let config = TransferConfig::builder()
+            .rsize(8 * 1024)
+            .wsize(16 * 1024)
+            .dtpref(4 * 1024)
+            .build();
+        let server = MemoryMonofsNFS::new(MemoryStore::default()).with_transfer(config);
+
+        // FSINFO advertises the configured sizes
+        let info = server.fsinfo(0).await.unwrap();
+        assert_eq!((info.rtmax, info.rtpref), (8 * 1024, 8 * 1024));
+        assert_eq!((info.wtmax, info.wtpref), (16 * 1024, 16 * 1024));
+        assert_eq!(info.dtpref, 4 * 1024);
+        assert!(matches!(info.obj_attributes, post_op_attr::attributes(_)));
+
+        // Reads past the read size are served short
+        let filename = filename3::from("test.txt".as_bytes());
+        let (fileid, _) = server
+            .create(0, &filename, sattr3::default())
+            .await
+            .unwrap();
+        let content = vec![7u8; 12 * 1024];
+        server.write(fileid, 0, &content).await.unwrap();
+        let (data, eof) = server.read(fileid, 0, 12 * 1024).await.unwrap();
+        assert_eq!(data.len(), 8 * 1024);
+        assert!(!eof);
+        let (data, eof) = server.read(fileid, 8 * 1024, 8 * 1024).await.unwrap();
+        assert_eq!(data.len(), 4 * 1024);
+        assert!(eof);
+    }
+
+    #[tokio::test]
+    async fn test_nfs_timestamps() {
+        let server = MemoryMonofsNFS::new(MemoryStore::default());
+        let filename = filename3::from("test.txt".as_bytes());
+        let newname = filename3::from("moved.txt".as_bytes());
+        let (fileid, created) = server
+            .create(0, &filename, sattr3::default())
+            .await
+            .unwrap();
+
+        // Writing modifies the file
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        let written = server.write(fileid, 0, b"Hello").await.unwrap();
+        assert!(to_nanos(written.mtime) > to_nanos(created.mtime));
+        assert_eq!(to_nanos(written.ctime), to_nanos(written.mtime));
+
+        // Changing its mode only changes it
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        let attr = sattr3 {
+            mode: set_mode3::mode(0o600),
+            ..Default::default()
+        };
+        let chmodded = server.setattr(fileid, attr).await.unwrap();
+        assert_eq!(to_nanos(chmodded.mtime), to_nanos(written.mtime));
+        assert!(to_nanos(chmodded.ctime) > to_nanos(written.ctime));
+
+        // So does moving it, which modifies the directory
+        let dir_before = server.getattr(0).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        server.rename(0, &filename, 0, &newname).await.unwrap();
+        let fileid = server.lookup(0, &newname).await.unwrap();
+        let moved = server.getattr(fileid).await.unwrap();
+        assert_eq!(to_nanos(moved.mtime), to_nanos(written.mtime));
+        assert!(to_nanos(moved.ctime) > to_nanos(chmodded.ctime));
+        let dir_after = server.getattr(0).await.unwrap();
+        assert!(to_nanos(dir_after.mtime) > to_nanos(dir_before.mtime));
+
+        // Reads record no access time by default
+        server.read(fileid, 0, 5).await.unwrap();
+        assert_eq!(
+            to_nanos(server.getattr(fileid).await.unwrap().atime),
+            to_nanos(moved.atime)
+        );
+
+        // Unless the atime policy asks for it, which does not modify the file
+        server.set_atime_policy(AtimePolicy::Strictatime).await;
+        let past = nfstime3 {
+            seconds: 1_000_000,
+            nseconds: 0,
+        };
+        let attr = sattr3 {
+            atime: set_atime::SET_TO_CLIENT_TIME(past),
+            ..Default::default()
+        };
+        let set = server.setattr(fileid, attr).await.unwrap();
+        assert_eq!(to_nanos(set.atime), to_nanos(past));
+        server.read(fileid, 0, 5).await.unwrap();
+        let read = server.getattr(fileid).await.unwrap();
+        assert!(read.atime.seconds > past.seconds);
+        assert_eq!(to_nanos(read.mtime), to_nanos(written.mtime));
+    }
+
+    #[tokio::test]
+    async fn test_nfs_setattr_size() {
+        let server = MemoryMonofsNFS::new(MemoryStore::default());
+        let filename = filename3::from("test.txt".as_bytes());
+        let (fileid, _) = server
+            .create(0, &filename, sattr3::default())
+            .await
+            .unwrap();
+        server.write(fileid, 0, b"Hello, World!").await.unwrap();
+
+        // Shrinking applies buffered writes before cutting them off
+        let attr = sattr3 {
+            size: set_size3::size(5),
+            ..Default::default()
+        };
+        assert_eq!(server.setattr(fileid, attr).await.unwrap().size, 5);
+        let (data, eof) = server.read(fileid, 0, 100).await.unwrap();
+        assert_eq!(data, b"Hello");
+        assert!(eof);
+
+        // Growing fills the file with zeros
+        let attr = sattr3 {
+            size: set_size3::size(8),
+            ..Default::default()
+        };
+        assert_eq!(server.setattr(fileid, attr).await.unwrap().size, 8);
+        let (data, _) = server.read(fileid, 0, 100).await.unwrap();
+        assert_eq!(data, b"Hello\0\0\0");
+
+        // Directories have no size to set
+        let attr = sattr3 {
+            size: set_size3::size(0),
+            ..Default::default()
+        };
+        assert!(matches!(
+            server.setattr(0, attr).await,
+            Err(nfsstat3::NFS3ERR_ISDIR)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_nfs_sequential_read() {
+        let server = MemoryMonofsNFS::new(MemoryStore::default());
+        let filename = filename3::from("test.txt".as_bytes());
+        let (fileid, _) = server
+            .create(0, &filename, sattr3::default())
+            .await
+            .unwrap();
+        let content = (0..100u8).collect::<Vec<_>>();
+        server.write(fileid, 0, &content).await.unwrap();
+
+        // Sequential reads are served from prefetches once the pattern is seen
+        let mut read = Vec::new();
+        loop {
+            let (data, eof) = server.read(fileid, read.len() as u64, 16).await.unwrap();
+            read.extend_from_slice(&data);
+            if eof {
+                break;
+            }
+        }
+        assert_eq!(read, content);
+
+        // Prefetches never outlive the content they were read from
+        server.write(fileid, 0, b"Hello").await.unwrap();
+        let (data, _) = server.read(fileid, 0, 5).await.unwrap();
+        assert_eq!(data, b"Hello");
+        let (data, _) = server.read(fileid, 5, 5).await.unwrap();
+        assert_eq!(data, &content[5..10]);
+    }
+
+    #[tokio::test]
+    async fn test_nfs_quota() {
+        let server = MemoryMonofsNFS::new(MemoryStore::default());
+        let dirname = filename3::from("dir".as_bytes());
+        let (dirid, _) = server.mkdir(0, &dirname).await.unwrap();
+        let filename = filename3::from("test.txt".as_bytes());
+        let (fileid, _) = server
+            .create(dirid, &filename, sattr3::default())
+            .await
+            .unwrap();
+        server.write(fileid, 0, b"Hello").await.unwrap();
+
+        // Existing files count towards the quota
+        let store_bytes = Arc::new(AtomicU64::new(0));
+        let quota = QuotaConfig::builder()
+            .max_logical_bytes(Some(10))
+            .max_store_bytes(Some(100))
+            .build();
+        server.set_quota(quota, store_bytes.clone()).await.unwrap();
+        assert_eq!(*server.get_usage().await.get_logical_bytes(), 5);
+
+        // Files may grow up to the logical limit, and be rewritten once it is reached
+        server.write(fileid, 5, b"World").await.unwrap();
+        assert!(matches!(
+            server.write(fileid, 10, b"!").await,
+            Err(nfsstat3::NFS3ERR_DQUOT)
+        ));
+        server.write(fileid, 0, b"Howdy").await.unwrap();
+        assert_eq!(*server.get_usage().await.get_logical_bytes(), 10);
+
+        // Removing files frees up room
+        server.remove(0, &dirname).await.unwrap();
+        assert_eq!(*server.get_usage().await.get_logical_bytes(), 0);
+
+        // Nothing more is written once the store is full
+        store_bytes.store(100, Ordering::Relaxed);
+        assert!(matches!(
+            server.create(0, &filename, sattr3::default()).await,
+            Err(nfsstat3::NFS3ERR_NOSPC)
+        ));
+        assert!(matches!(
+            server.mkdir(0, &dirname).await,
+            Err(nfsstat3::NFS3ERR_NOSPC)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_nfs_audit() {
+        let server = MemoryMonofsNFS::new(MemoryStore::default());
+        let filename = filename3::from("a.txt".as_bytes());
+        server
+            .create(0, &filename, sattr3::default())
+            .await
+            .unwrap();
+        server.enable_audit().await;
+        assert!(server.take_audit_events().await.is_empty());
+
+        // Changes are recorded as made by the caller, if there is one
+        let fileid = server.lookup(0, &filename).await.unwrap();
+        call_as(1000, 100, server.write(fileid, 0, b"Hello"))
+            .await
+            .unwrap();
+        let newname = filename3::from("b.txt".as_bytes());
+        server.rename(0, &filename, 0, &newname).await.unwrap();
+        server.remove(0, &newname).await.unwrap();
+
+        let events = server.take_audit_events().await;
+        let operations = events
+            .iter()
+            .map(|event| *event.get_operation())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            operations,
+            [
+                AuditOperation::Write,
+                AuditOperation::Rename,
+                AuditOperation::Remove
+            ]
+        );
+        assert_eq!(
+            (events[0].get_uid(), events[0].get_gid()),
+            (&Some(1000), &Some(100))
+        );
+        assert!(events[0].get_cid().is_some());
+        assert_eq!(events[1].get_uid(), &None);
+        assert_eq!(
+            (
+                events[1].get_path().as_str(),
+                events[1].get_new_path().as_deref()
+            ),
+            ("/a.txt", Some("/b.txt"))
+        );
+        assert!(server.take_audit_events().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_nfs_watch() {
+        let config = WriteBackConfig::builder()
+            .max_dirty_bytes(1024)
+            .flush_interval(std::time::Duration::from_secs(3600))
+            .build();
+        let server = MemoryMonofsNFS::with_write_back(MemoryStore::default(), config);
+        let mut changes = server.watch();
+
+        let dirname = filename3::from("dir".as_bytes());
+        let (dirid, _) = server.mkdir(0, &dirname).await.unwrap();
+        let filename = filename3::from("test.txt".as_bytes());
+        let (fileid, _) = server
+            .create(dirid, &filename, sattr3::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            changes.recv().await.unwrap(),
+            ChangeEvent::new(ChangeKind::Created, "dir", None)
+        );
+        assert_eq!(
+            changes.recv().await.unwrap(),
+            ChangeEvent::new(ChangeKind::Created, "dir/test.txt", None)
+        );
+
+        // Buffered writes are reported once they reach the file, with its new content
+        server.write(fileid, 0, b"Hello").await.unwrap();
+        assert!(changes.try_recv().is_err());
+        server.flush_writes().await.unwrap();
+        let event = changes.recv().await.unwrap();
+        assert_eq!(*event.get_kind(), ChangeKind::Modified);
+        assert_eq!(event.get_path(), "/dir/test.txt");
+        assert!(event.get_cid().is_some());
+
+        // Renames are reported as a removal and a creation
+        let newname = filename3::from("new.txt".as_bytes());
+        server
+            .rename(dirid, &filename, dirid, &newname)
+            .await
+            .unwrap();
+        assert_eq!(
+            changes.recv().await.unwrap(),
+            ChangeEvent::new(ChangeKind::Removed, "dir/test.txt", None)
+        );
+        assert_eq!(
+            changes.recv().await.unwrap(),
+            ChangeEvent::new(ChangeKind::Created, "dir/new.txt", None)
+        );
+
+        server.remove(0, &dirname).await.unwrap();
+        assert_eq!(
+            changes.recv().await.unwrap(),
+            ChangeEvent::new(ChangeKind::Removed, "dir", None)
+        );
+
+        // Replacing the root is reported as a change of the root
+        let cid = server.checkpoint().await.unwrap();
+        server.set_root(&cid).await.unwrap();
+        assert_eq!(
+            changes.recv().await.unwrap(),
+            ChangeEvent::new(ChangeKind::Modified, "", Some(&cid))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nfs_checkpoint_and_set_root() {
+        let server = MemoryMonofsNFS::new(MemoryStore::default());
+        let filename = filename3::from("test.txt".as_bytes());
+
+        // Capture the tree with a single file
+        server
+            .create(0, &filename, sattr3::default())
+            .await
+            .unwrap();
+        let cid = server.checkpoint().await.unwrap();
+
+        // Diverge from the checkpoint through a clone sharing the same root
+        let clone = server.clone();
+        clone.remove(0, &filename).await.unwrap();
+        assert!(matches!(
+            server.lookup(0, &filename).await,
+            Err(nfsstat3::NFS3ERR_NOENT)
+        ));
+
+        // Restoring the checkpoint brings the file back
+        server.set_root(&cid).await.unwrap();
+        assert!(clone.lookup(0, &filename).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_nfs_subtree() {
+        let store = MemoryStore::default();
+        let server = MemoryMonofsNFS::new(store.clone());
+        let shared = filename3::from("shared.txt".as_bytes());
+        let private = filename3::from("private.txt".as_bytes());
+
+        // Serve a directory that does not exist yet, next to a file outside of it
+        server.create(0, &shared, sattr3::default()).await.unwrap();
+        server.set_subtree("/home/alice").await.unwrap();
+        assert!(matches!(
+            server.lookup(0, &shared).await,
+            Err(nfsstat3::NFS3ERR_NOENT)
+        ));
+
+        // Changes are written back under the path, and the rest of the tree is kept
+        server.create(0, &private, sattr3::default()).await.unwrap();
+        let cid = server.checkpoint().await.unwrap();
+        let tree = Dir::load(&cid, store.clone()).await.unwrap();
+        assert!(tree.find("shared.txt").await.unwrap().is_some());
+        assert!(tree.find("home/alice/private.txt").await.unwrap().is_some());
+
+        // Roots of the whole tree are narrowed down to the subtree
+        server.set_root(&cid).await.unwrap();
+        assert!(server.lookup(0, &private).await.is_ok());
+
+        // A path that is not a directory cannot be served
+        assert!(server.set_subtree("home/alice/private.txt").await.is_err());
+
+        // An empty path serves the whole tree again
+        server.set_subtree("").await.unwrap();
+        assert!(server.lookup(0, &shared).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_nfs_file_handles() {
+        let store = MemoryStore::default();
+        let server = MemoryMonofsNFS::new(store.clone());
+        let dirname = filename3::from("dir".as_bytes());
+        let filename = filename3::from("test.txt".as_bytes());
+        let (dirid, _) = server.mkdir(0, &dirname).await.unwrap();
+        let (fileid, _) = server
+            .create(dirid, &filename, sattr3::default())
+            .await
+            .unwrap();
+        let cid = server.checkpoint().await.unwrap();
+        let file_ids = server.take_new_file_ids().await;
+        assert_eq!(file_ids.len(), 2);
+        assert!(server.take_new_file_ids().await.is_empty());
+
+        // Handles carry the file ID
+        let fh = server.id_to_fh(fileid);
+        assert!(matches!(server.fh_to_id(&fh), Ok(id) if id == fileid));
+        assert!(matches!(
+            server.fh_to_id(&nfs_fh3 { data: vec![0; 16] }),
+            Err(nfsstat3::NFS3ERR_STALE)
+        ));
+        assert!(matches!(
+            server.fh_to_id(&nfs_fh3 { data: vec![0; 3] }),
+            Err(nfsstat3::NFS3ERR_BADHANDLE)
+        ));
+
+        // A restarted server resolves the handles it is given back without a lookup
+        let restarted = MemoryMonofsNFS::new(store.clone());
+        restarted.set_root(&cid).await.unwrap();
+        assert!(restarted.getattr(fileid).await.is_err());
+        restarted.restore_file_ids(file_ids).await;
+        assert!(matches!(restarted.fh_to_id(&fh), Ok(id) if id == fileid));
+        assert_eq!(
+            restarted.fileid_to_path(fileid).await.unwrap(),
+            "dir/test.txt"
+        );
+        assert!(restarted.getattr(fileid).await.is_ok());
+
+        // And derives the same file IDs for the paths it looks up
+        let restarted = MemoryMonofsNFS::new(store);
+        restarted.set_root(&cid).await.unwrap();
+        assert_eq!(restarted.lookup(0, &dirname).await.unwrap(), dirid);
+        assert_eq!(restarted.lookup(dirid, &filename).await.unwrap(), fileid);
+    }
+
+    fn to_nanos(time: nfstime3) -> u64 {
+        time.seconds as u64 * 1_000_000_000 + time.nseconds as u64
+    }
 }
@@ -0,0 +1,177 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use crate::{config::QuotaConfig, management::QuotaUsage, FsError, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Keeps track of how much a filesystem holds and refuses changes that would take it past its
+/// quota.
+///
+/// The size of the files is tracked by the filesystem as it changes them. The bytes the store
+/// takes up are counted by the store itself as it writes blocks, in a counter shared with the
+/// tracker.
+#[derive(Debug)]
+pub(crate) struct QuotaTracker {
+    /// The limits to enforce.
+    config: QuotaConfig,
+
+    /// The total size of the files.
+    logical_bytes: u64,
+
+    /// The bytes the store takes up.
+    store_bytes: Arc<AtomicU64>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl QuotaTracker {
+    /// Creates a tracker that enforces `config`, with the store's size counted in `store_bytes`.
+    pub(crate) fn new(config: QuotaConfig, store_bytes: Arc<AtomicU64>) -> Self {
+        Self {
+            config,
+            logical_bytes: 0,
+            store_bytes,
+        }
+    }
+
+    /// Creates a tracker that does not limit anything.
+    pub(crate) fn unlimited() -> Self {
+        Self::new(QuotaConfig::unlimited(), Arc::new(AtomicU64::new(0)))
+    }
+
+    /// Returns whether the tracker limits anything, and so needs to track sizes at all.
+    pub(crate) fn is_limited(&self) -> bool {
+        self.config.is_limited()
+    }
+
+    /// Checks that the files may grow by `growth` bytes, given that about `pending` bytes still
+    /// have to be written to the store along with the growth.
+    ///
+    /// Writes that do not grow the files are always allowed by the logical limit, so files can be
+    /// rewritten in place once it is reached.
+    pub(crate) fn check_write(&self, growth: u64, pending: u64) -> FsResult<()> {
+        if let Some(max) = *self.config.get_max_logical_bytes() {
+            let used = self.logical_bytes.saturating_add(growth);
+            if growth > 0 && used > max {
+                return Err(FsError::LogicalQuotaExceeded { used, max });
+            }
+        }
+
+        if let Some(max) = *self.config.get_max_store_bytes() {
+            let used = self.get_store_bytes().saturating_add(pending);
+            if used > max {
+                return Err(FsError::StoreQuotaExceeded { used, max });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the store has room left for new entries.
+    pub(crate) fn check_store(&self) -> FsResult<()> {
+        if let Some(max) = *self.config.get_max_store_bytes() {
+            let used = self.get_store_bytes();
+            if used >= max {
+                return Err(FsError::StoreQuotaExceeded { used, max });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Records that the files grew by `bytes`.
+    pub(crate) fn grow(&mut self, bytes: u64) {
+        self.logical_bytes = self.logical_bytes.saturating_add(bytes);
+    }
+
+    /// Records that the files shrank by `bytes`.
+    pub(crate) fn shrink(&mut self, bytes: u64) {
+        self.logical_bytes = self.logical_bytes.saturating_sub(bytes);
+    }
+
+    /// Records that the files take up `bytes` in total, after they were measured.
+    pub(crate) fn set_logical_bytes(&mut self, bytes: u64) {
+        self.logical_bytes = bytes;
+    }
+
+    /// Returns the limits the tracker enforces.
+    pub(crate) fn get_config(&self) -> &QuotaConfig {
+        &self.config
+    }
+
+    /// Returns how much the filesystem holds.
+    pub(crate) fn get_usage(&self) -> QuotaUsage {
+        QuotaUsage::new(self.logical_bytes, self.get_store_bytes())
+    }
+
+    /// Returns the counter of the bytes the store takes up, shared with the store.
+    pub(crate) fn get_store_bytes_counter(&self) -> Arc<AtomicU64> {
+        self.store_bytes.clone()
+    }
+
+    /// Returns the bytes the store takes up.
+    fn get_store_bytes(&self) -> u64 {
+        self.store_bytes.load(Ordering::Relaxed)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_tracker_checks_limits() {
+        let store_bytes = Arc::new(AtomicU64::new(0));
+        let config = QuotaConfig::builder()
+            .max_logical_bytes(Some(10))
+            .max_store_bytes(Some(100))
+            .build();
+        let mut quota = QuotaTracker::new(config, store_bytes.clone());
+
+        // The files may grow up to the logical limit
+        assert!(quota.check_write(10, 10).is_ok());
+        quota.grow(8);
+        assert!(quota.check_write(2, 2).is_ok());
+        assert!(matches!(
+            quota.check_write(3, 3),
+            Err(FsError::LogicalQuotaExceeded { used: 11, max: 10 })
+        ));
+
+        // Rewrites are allowed past the logical limit, as long as the store has room
+        quota.grow(4);
+        assert!(quota.check_write(0, 5).is_ok());
+        quota.shrink(4);
+        assert_eq!(*quota.get_usage().get_logical_bytes(), 8);
+
+        // The store may not grow past its limit
+        store_bytes.store(98, Ordering::Relaxed);
+        assert!(quota.check_store().is_ok());
+        assert!(matches!(
+            quota.check_write(0, 3),
+            Err(FsError::StoreQuotaExceeded {
+                used: 101,
+                max: 100
+            })
+        ));
+        store_bytes.store(100, Ordering::Relaxed);
+        assert!(quota.check_store().is_err());
+        assert_eq!(*quota.get_usage().get_store_bytes(), 100);
+
+        // Nothing is limited by default
+        let quota = QuotaTracker::unlimited();
+        assert!(!quota.is_limited());
+        assert!(quota.check_write(u64::MAX, u64::MAX).is_ok());
+        assert!(quota.check_store().is_ok());
+    }
+}
@@ -0,0 +1,264 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use ipldstore::{ipld::cid::Cid, IpldStoreSeekable};
+use nfsserve::nfs::fileid3;
+use tokio::{sync::Semaphore, task::JoinHandle};
+
+use crate::{filesystem::File, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How many reads past the current one are prefetched once a file is read sequentially.
+pub(crate) const READ_AHEAD_WINDOWS: u64 = 4;
+
+/// How many prefetches may fetch from the store at the same time, across all files.
+pub(crate) const READ_AHEAD_CONCURRENCY: usize = 16;
+
+/// How many files the NFS server tracks read patterns for.
+pub(crate) const READ_AHEAD_MAX_STREAMS: usize = 64;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Prefetches the content of files that are read sequentially.
+///
+/// Every read fetches its chunks from the store one after another, so a client reading a file
+/// from start to end waits on the store's latency for each read in turn. Once a read starts where
+/// the previous read of the same file ended, with the same length, the next few reads of that
+/// length are fetched in the background, at most [`READ_AHEAD_CONCURRENCY`] at a time, and
+/// handed out when the client asks for them.
+///
+/// Prefetched data is tied to the CID of the content it was read from, so it is discarded as soon
+/// as the file changes.
+#[derive(Debug)]
+pub(crate) struct ReadAhead {
+    /// Limits how many prefetches fetch from the store at the same time.
+    permits: Arc<Semaphore>,
+
+    /// The read pattern of each file, by file ID.
+    streams: HashMap<fileid3, ReadStream>,
+
+    /// How many reads were served from a prefetch.
+    hits: u64,
+
+    /// How many reads were fetched from the store when they were asked for.
+    misses: u64,
+}
+
+/// How a file has been read so far, and the reads prefetched for it.
+#[derive(Debug)]
+struct ReadStream {
+    /// The CID of the content being read.
+    content: Cid,
+
+    /// Where the last read ended.
+    next: u64,
+
+    /// How many bytes the last read asked for.
+    len: usize,
+
+    /// The prefetched reads, by offset.
+    windows: BTreeMap<u64, JoinHandle<FsResult<Vec<u8>>>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ReadAhead {
+    /// Creates a prefetcher that runs up to `concurrency` prefetches at the same time.
+    pub(crate) fn new(concurrency: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(concurrency)),
+            streams: HashMap::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Reads up to `len` bytes of `file`, which has ID `id`, starting at `offset`.
+    ///
+    /// The read is served from a prefetch if one was made for exactly this range, and prefetches
+    /// the reads that follow it if it continues the previous read.
+    pub(crate) async fn read<S>(
+        &mut self,
+        id: fileid3,
+        file: &File<S>,
+        offset: u64,
+        len: usize,
+    ) -> FsResult<Vec<u8>>
+    where
+        S: IpldStoreSeekable + Send + Sync + 'static,
+    {
+        let Some(content) = file.get_content().copied() else {
+            self.forget(id);
+            return Ok(Vec::new());
+        };
+
+        // Dropping a stream cancels its prefetches
+        let stream = self
+            .streams
+            .remove(&id)
+            .filter(|stream| stream.content == content);
+        let sequential =
+            matches!(&stream, Some(stream) if stream.next == offset && stream.len == len);
+        let mut stream = match stream {
+            Some(stream) if sequential => stream,
+            _ => ReadStream::new(content),
+        };
+
+        let data = match stream.windows.remove(&offset) {
+            Some(window) => match window.await {
+                Ok(result) => {
+                    self.hits += 1;
+                    result?
+                }
+                Err(e) => {
+                    tracing::warn!("prefetch of file {} at {} failed: {}", id, offset, e);
+                    self.misses += 1;
+                    file.read_at(offset, len).await?
+                }
+            },
+            None => {
+                self.misses += 1;
+                file.read_at(offset, len).await?
+            }
+        };
+
+        stream.next = offset.saturating_add(len as u64);
+        stream.len = len;
+
+        if sequential && len > 0 {
+            let size = file.get_size().await?;
+            for window in 0..READ_AHEAD_WINDOWS {
+                let start = stream.next.saturating_add(window * len as u64);
+                if start >= size {
+                    break;
+                }
+
+                stream
+                    .windows
+                    .entry(start)
+                    .or_insert_with(|| self.prefetch(file, start, len));
+            }
+        }
+
+        if !self.streams.contains_key(&id) && self.streams.len() >= READ_AHEAD_MAX_STREAMS {
+            if let Some(evicted) = self.streams.keys().next().copied() {
+                self.streams.remove(&evicted);
+            }
+        }
+
+        self.streams.insert(id, stream);
+        Ok(data)
+    }
+
+    /// Stops tracking the file with ID `id` and cancels its prefetches.
+    pub(crate) fn forget(&mut self, id: fileid3) {
+        self.streams.remove(&id);
+    }
+
+    /// Stops tracking every file and cancels all prefetches.
+    pub(crate) fn clear(&mut self) {
+        self.streams.clear();
+    }
+
+    /// Returns how many reads were served from a prefetch, and how many were not.
+    pub(crate) fn get_hits_and_misses(&self) -> (u64, u64) {
+        (self.hits, self.misses)
+    }
+
+    /// Starts fetching `len` bytes of `file` at `offset` in the background.
+    fn prefetch<S>(&self, file: &File<S>, offset: u64, len: usize) -> JoinHandle<FsResult<Vec<u8>>>
+    where
+        S: IpldStoreSeekable + Send + Sync + 'static,
+    {
+        let permits = self.permits.clone();
+        let file = file.clone();
+        tokio::spawn(async move {
+            // The semaphore is never closed, so this only waits for a permit
+            let _permit = permits.acquire_owned().await;
+            file.read_at(offset, len).await
+        })
+    }
+}
+
+impl ReadStream {
+    /// Creates a stream for content with CID `content` that has not been read yet.
+    fn new(content: Cid) -> Self {
+        Self {
+            content,
+            next: 0,
+            len: 0,
+            windows: BTreeMap::new(),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Drop for ReadStream {
+    fn drop(&mut self) {
+        for window in self.windows.values() {
+            window.abort();
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::MemoryStore;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_ahead_prefetches_sequential_reads() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let content = (0..40u8).collect::<Vec<_>>();
+        let file = File::with_content(store.clone(), content.as_slice()).await?;
+        let mut read_ahead = ReadAhead::new(2);
+
+        // The first read has nothing to continue
+        assert_eq!(read_ahead.read(1, &file, 0, 8).await?, &content[0..8]);
+        assert!(read_ahead.streams[&1].windows.is_empty());
+
+        // A sequential read prefetches what follows, up to the end of the file
+        assert_eq!(read_ahead.read(1, &file, 8, 8).await?, &content[8..16]);
+        let offsets = read_ahead.streams[&1]
+            .windows
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, vec![16, 24, 32]);
+
+        // Prefetched reads are handed out
+        assert_eq!(read_ahead.read(1, &file, 16, 8).await?, &content[16..24]);
+        assert!(!read_ahead.streams[&1].windows.contains_key(&16));
+        assert_eq!(read_ahead.read(1, &file, 24, 8).await?, &content[24..32]);
+        assert_eq!(read_ahead.read(1, &file, 32, 8).await?, &content[32..40]);
+
+        // A read elsewhere cancels the prefetches
+        assert_eq!(read_ahead.read(1, &file, 4, 4).await?, &content[4..8]);
+        assert!(read_ahead.streams[&1].windows.is_empty());
+
+        // Prefetches of old content are never handed out
+        read_ahead.read(1, &file, 8, 4).await?;
+        let changed = File::with_content(store, [0u8; 40].as_slice()).await?;
+        assert_eq!(read_ahead.read(1, &changed, 12, 4).await?, vec![0u8; 4]);
+        assert_eq!(read_ahead.get_hits_and_misses(), (3, 5));
+
+        Ok(())
+    }
+}
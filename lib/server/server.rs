@@ -1,10 +1,55 @@
+use fuser::MountOption;
 use getset::Getters;
-use nfsserve::tcp::{NFSTcp, NFSTcpListener};
-use std::path::PathBuf;
+use ipldstore::ipld::cid::Cid;
+use std::{
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicU64, Arc},
+    time::Duration,
+};
+use tokio::{
+    fs,
+    runtime::Handle,
+    signal::unix::{signal, SignalKind},
+    sync::broadcast::error::RecvError,
+    task::JoinHandle,
+    time,
+};
 
-use crate::store::FlatFsStore;
+use crate::{
+    config::{
+        AtimePolicy, ChunkerConfig, CompressionConfig, Durability, EncryptionKey, ExportConfig,
+        QuotaConfig, ThrottleConfig, TransferConfig, WriteBackConfig,
+    },
+    management::{
+        self, FsAuditLog, FsFileHandles, FsHead, FsMemoryStore, FsMirrors, FsQuota, FsRemote,
+        FsReplicas, FsStoreHash, FsSubtree,
+    },
+    store::{open_remote_store, FlatFsStore},
+    utils::path::CONTROL_SOCKET_FILENAME,
+    FsResult,
+};
 
-use super::MonofsNFS;
+use super::{
+    ChangeKind, ControlServer, DiskSync, MonofsFuse, MonofsNFS, NfsListener, SharedMounts, Throttle,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How often a server looks for buffered writes that have waited for the flush interval.
+const WRITE_BACK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a server waits after a directory changes before logging an intent for its root, so
+/// that bursts of changes are logged together.
+const INTENT_LOG_DELAY: Duration = Duration::from_secs(1);
+
+/// How often a server records the file IDs it registered since it last recorded them.
+const FILE_HANDLE_RECORD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the server of an audited filesystem adds the changes it applied since it last did
+/// to the audit log.
+const AUDIT_RECORD_INTERVAL: Duration = Duration::from_secs(1);
 
 //--------------------------------------------------------------------------------------------------
 // Types
@@ -23,6 +68,87 @@ pub struct MonofsServer {
 
     /// The port to listen on.
     port: u32,
+
+    /// The filesystem database.
+    fs_db_path: Option<PathBuf>,
+
+    /// The mount directory identifying the filesystem in the database.
+    mount_dir: Option<PathBuf>,
+
+    /// How file contents are split into blocks.
+    chunker: ChunkerConfig,
+
+    /// How blocks are compressed at rest.
+    compression: CompressionConfig,
+
+    /// The key blocks are encrypted with at rest, if any.
+    #[getset(skip)]
+    encryption: Option<EncryptionKey>,
+
+    /// How writes are buffered before they are flushed to the store.
+    write_back: WriteBackConfig,
+
+    /// How much data NFS clients move in a single request.
+    transfer: TransferConfig,
+
+    /// Which NFS clients may connect, and who they act as.
+    exports: ExportConfig,
+
+    /// How many requests NFS clients may make, and how many bytes they may move, each second.
+    throttle: ThrottleConfig,
+
+    /// When reads record the time a file was last accessed.
+    atime_policy: AtimePolicy,
+
+    /// When new block files are flushed to disk.
+    durability: Durability,
+}
+
+/// The quota of a filesystem being served, as recorded in its fs database.
+#[derive(Debug)]
+struct ServedQuota {
+    /// Where the quota and the filesystem's usage are recorded.
+    record: FsQuota,
+
+    /// What the quota limits.
+    config: QuotaConfig,
+
+    /// The bytes the store takes up, kept up to date by the store.
+    store_bytes: Arc<AtomicU64>,
+}
+
+/// A server that mounts a content-addressed store directly through FUSE.
+/// This server uses a flat filesystem store as its backing store.
+#[derive(Debug, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct MonofsFuseServer {
+    /// The path to the store.
+    store_dir: PathBuf,
+
+    /// The directory to mount the filesystem at.
+    mount_dir: PathBuf,
+
+    /// The filesystem database.
+    fs_db_path: Option<PathBuf>,
+
+    /// How file contents are split into blocks.
+    chunker: ChunkerConfig,
+
+    /// How blocks are compressed at rest.
+    compression: CompressionConfig,
+
+    /// The key blocks are encrypted with at rest, if any.
+    #[getset(skip)]
+    encryption: Option<EncryptionKey>,
+
+    /// How writes are buffered before they are flushed to the store.
+    write_back: WriteBackConfig,
+
+    /// When reads record the time a file was last accessed.
+    atime_policy: AtimePolicy,
+
+    /// When new block files are flushed to disk.
+    durability: Durability,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -36,20 +162,764 @@ impl MonofsServer {
             store_dir: store_dir.into(),
             host: host.into(),
             port,
+            fs_db_path: None,
+            mount_dir: None,
+            chunker: ChunkerConfig::default(),
+            compression: CompressionConfig::default(),
+            encryption: None,
+            write_back: WriteBackConfig::default(),
+            transfer: TransferConfig::default(),
+            exports: ExportConfig::default(),
+            throttle: ThrottleConfig::unlimited(),
+            atime_policy: AtimePolicy::default(),
+            durability: Durability::default(),
         }
     }
 
+    /// Tracks the filesystem's head in the given fs database.
+    ///
+    /// The server then starts from the recorded head, records a new head when it shuts down, and
+    /// serves control requests on a socket next to the database.
+    pub fn with_fs_db(
+        mut self,
+        fs_db_path: impl Into<PathBuf>,
+        mount_dir: impl Into<PathBuf>,
+    ) -> Self {
+        self.fs_db_path = Some(fs_db_path.into());
+        self.mount_dir = Some(mount_dir.into());
+        self
+    }
+
+    /// Splits file contents into blocks as described by `chunker`.
+    pub fn with_chunker(mut self, chunker: ChunkerConfig) -> Self {
+        self.chunker = chunker;
+        self
+    }
+
+    /// Compresses new blocks as described by `compression`.
+    pub fn with_compression(mut self, compression: CompressionConfig) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Encrypts new blocks with `key`, and decrypts encrypted blocks with it.
+    pub fn with_encryption(mut self, key: Option<EncryptionKey>) -> Self {
+        self.encryption = key;
+        self
+    }
+
+    /// Buffers writes as described by `write_back` before flushing them to the store.
+    pub fn with_write_back(mut self, write_back: WriteBackConfig) -> Self {
+        self.write_back = write_back;
+        self
+    }
+
+    /// Advertises the transfer sizes described by `transfer` to NFS clients.
+    pub fn with_transfer(mut self, transfer: TransferConfig) -> Self {
+        self.transfer = transfer;
+        self
+    }
+
+    /// Serves the NFS clients `exports` allows, squashing the owners they set as it asks.
+    pub fn with_exports(mut self, exports: ExportConfig) -> Self {
+        self.exports = exports;
+        self
+    }
+
+    /// Limits how fast NFS clients are served as described by `throttle`, across all of them and
+    /// for each connection, including the clients of the mounts sharing the server.
+    pub fn with_throttle(mut self, throttle: ThrottleConfig) -> Self {
+        self.throttle = throttle;
+        self
+    }
+
+    /// Records the time files are accessed as `policy` asks.
+    pub fn with_atime_policy(mut self, policy: AtimePolicy) -> Self {
+        self.atime_policy = policy;
+        self
+    }
+
+    /// Flushes new block files to disk as `durability` asks.
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
     /// Starts the NFS server and blocks until it is shut down.
     pub async fn start(&self) -> anyhow::Result<()> {
         // Create the store and NFS filesystem
-        let store = FlatFsStore::new(&self.store_dir);
-        let fs = MonofsNFS::new(store);
+        self.chunker.validate()?;
+        self.compression.validate()?;
+        self.transfer.validate()?;
+        self.exports.validate()?;
+        self.throttle.validate()?;
+        let throttle = Throttle::new(self.throttle);
+        let quota = match (&self.fs_db_path, &self.mount_dir) {
+            (Some(fs_db_path), Some(mount_dir)) => {
+                load_quota(fs_db_path, mount_dir, &self.store_dir).await?
+            }
+            _ => None,
+        };
+        let mut store = FlatFsStore::with_chunker_config(&self.store_dir, &self.chunker)
+            .with_compression(self.compression)
+            .with_encryption(self.encryption.clone())
+            .with_durability(self.durability)
+            .with_index()
+            .await?;
+        if let Some(quota) = &quota {
+            store = store.with_usage(quota.store_bytes.clone());
+        }
+        if let (Some(fs_db_path), Some(mount_dir)) = (&self.fs_db_path, &self.mount_dir) {
+            store = load_remote(store, fs_db_path, mount_dir).await?;
+            store = load_mirrors(store, fs_db_path, mount_dir).await?;
+            store = load_replicas(store, fs_db_path, mount_dir).await?;
+            store = load_memory(store, fs_db_path, mount_dir).await?;
+            store = load_hash_function(store, fs_db_path, mount_dir).await?;
+        }
+        let fs = MonofsNFS::with_write_back(store.clone(), self.write_back)
+            .with_transfer(self.transfer)
+            .with_exports(self.exports.clone());
+        fs.set_atime_policy(self.atime_policy).await;
+        if let Some(quota) = &quota {
+            fs.set_quota(quota.config, quota.store_bytes.clone())
+                .await?;
+        }
+        let flush = spawn_write_back_flush(&fs, &self.write_back);
+
+        // Restore the filesystem's head and start accepting control requests, including requests
+        // to serve more mounts of the same data
+        let control = match (&self.fs_db_path, &self.mount_dir) {
+            (Some(fs_db_path), Some(mount_dir)) => {
+                let shared = Arc::new(
+                    SharedMounts::new(
+                        store.clone(),
+                        fs_db_path,
+                        &self.host,
+                        mount_dir,
+                        self.write_back,
+                        self.transfer,
+                        self.atime_policy,
+                    )
+                    .await?
+                    .with_exports(self.exports.clone())
+                    .with_throttle(throttle.clone()),
+                );
+                let (head, handles, audit, disk_sync, task) = start_control(
+                    &fs,
+                    fs_db_path,
+                    mount_dir,
+                    control_socket_path(fs_db_path),
+                    Some(shared.clone()),
+                    quota.as_ref().map(|quota| &quota.record),
+                )
+                .await?;
+                shared.restore().await?;
+                Some((head, handles, audit, disk_sync, task, fs_db_path, shared))
+            }
+            _ => None,
+        };
 
-        // Create and start the NFS listener
+        // Create and start the NFS listener, which flushes the filesystem to disk when clients
+        // commit their writes
         let addr = format!("{}:{}", self.host, self.port);
-        let listener = NFSTcpListener::bind(&addr, fs).await?;
-        listener.handle_forever().await?;
+        let disk_sync = control
+            .as_ref()
+            .and_then(|(_, _, _, disk_sync, ..)| disk_sync.clone());
+        let listener =
+            NfsListener::bind(&addr, fs.clone(), &self.exports, &throttle, disk_sync).await?;
+        tokio::select! {
+            result = listener.handle_forever() => result?,
+            result = wait_for_shutdown() => result?,
+        }
+
+        // Record the final state of the filesystem and the mounts sharing it, which applies the
+        // writes that are still buffered
+        if let Some((head, handles, audit, _, task, fs_db_path, shared)) = control {
+            shared.stop_all().await?;
+            stop_task(task).await;
+            let cid = stop_control(
+                &fs,
+                &head,
+                &handles,
+                &audit,
+                quota.as_ref().map(|quota| &quota.record),
+                &control_socket_path(fs_db_path),
+            )
+            .await?;
+            tracing::info!("stopped at filesystem root {}", cid);
+        }
+
+        if let Some(flush) = flush {
+            flush.abort();
+        }
+
+        Ok(())
+    }
+}
+
+impl MonofsFuseServer {
+    /// Creates a new MonofsFuseServer with the given store path and mount directory.
+    pub fn new(store_dir: impl Into<PathBuf>, mount_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            store_dir: store_dir.into(),
+            mount_dir: mount_dir.into(),
+            fs_db_path: None,
+            chunker: ChunkerConfig::default(),
+            compression: CompressionConfig::default(),
+            encryption: None,
+            write_back: WriteBackConfig::default(),
+            atime_policy: AtimePolicy::default(),
+            durability: Durability::default(),
+        }
+    }
+
+    /// Tracks the filesystem's head in the given fs database.
+    ///
+    /// The server then starts from the recorded head, records a new head when it shuts down, and
+    /// serves control requests on a socket next to the database.
+    pub fn with_fs_db(mut self, fs_db_path: impl Into<PathBuf>) -> Self {
+        self.fs_db_path = Some(fs_db_path.into());
+        self
+    }
+
+    /// Splits file contents into blocks as described by `chunker`.
+    pub fn with_chunker(mut self, chunker: ChunkerConfig) -> Self {
+        self.chunker = chunker;
+        self
+    }
+
+    /// Compresses new blocks as described by `compression`.
+    pub fn with_compression(mut self, compression: CompressionConfig) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Encrypts new blocks with `key`, and decrypts encrypted blocks with it.
+    pub fn with_encryption(mut self, key: Option<EncryptionKey>) -> Self {
+        self.encryption = key;
+        self
+    }
+
+    /// Buffers writes as described by `write_back` before flushing them to the store.
+    pub fn with_write_back(mut self, write_back: WriteBackConfig) -> Self {
+        self.write_back = write_back;
+        self
+    }
+
+    /// Records the time files are accessed as `policy` asks.
+    pub fn with_atime_policy(mut self, policy: AtimePolicy) -> Self {
+        self.atime_policy = policy;
+        self
+    }
+
+    /// Flushes new block files to disk as `durability` asks.
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Mounts the filesystem and blocks until the process receives SIGINT or SIGTERM, at which
+    /// point the filesystem is unmounted.
+    pub async fn start(&self) -> anyhow::Result<()> {
+        // Create the store and FUSE filesystem
+        self.chunker.validate()?;
+        self.compression.validate()?;
+        let quota = match &self.fs_db_path {
+            Some(fs_db_path) => load_quota(fs_db_path, &self.mount_dir, &self.store_dir).await?,
+            None => None,
+        };
+        let mut store = FlatFsStore::with_chunker_config(&self.store_dir, &self.chunker)
+            .with_compression(self.compression)
+            .with_encryption(self.encryption.clone())
+            .with_durability(self.durability)
+            .with_index()
+            .await?;
+        if let Some(quota) = &quota {
+            store = store.with_usage(quota.store_bytes.clone());
+        }
+        if let Some(fs_db_path) = &self.fs_db_path {
+            store = load_remote(store, fs_db_path, &self.mount_dir).await?;
+            store = load_mirrors(store, fs_db_path, &self.mount_dir).await?;
+            store = load_replicas(store, fs_db_path, &self.mount_dir).await?;
+            store = load_memory(store, fs_db_path, &self.mount_dir).await?;
+            store = load_hash_function(store, fs_db_path, &self.mount_dir).await?;
+        }
+        let fs = MonofsNFS::with_write_back(store, self.write_back);
+        fs.set_atime_policy(self.atime_policy).await;
+        if let Some(quota) = &quota {
+            fs.set_quota(quota.config, quota.store_bytes.clone())
+                .await?;
+        }
+        let flush = spawn_write_back_flush(&fs, &self.write_back);
+
+        // Restore the filesystem's head and start accepting control requests
+        let control = match &self.fs_db_path {
+            Some(fs_db_path) => {
+                let socket_path = control_socket_path(fs_db_path);
+                let (head, handles, audit, _, task) = start_control(
+                    &fs,
+                    fs_db_path,
+                    &self.mount_dir,
+                    socket_path,
+                    None,
+                    quota.as_ref().map(|quota| &quota.record),
+                )
+                .await?;
+                Some((head, handles, audit, task, fs_db_path))
+            }
+            None => None,
+        };
+
+        // Mount the filesystem. The session serves requests on its own thread.
+        let options = [
+            MountOption::FSName("monofs".to_string()),
+            MountOption::Subtype("monofs".to_string()),
+            MountOption::RW,
+        ];
+        let adapter =
+            MonofsFuse::new(fs.clone(), Handle::current()).with_stats_dir(&self.store_dir);
+        let session = fuser::spawn_mount2(adapter, &self.mount_dir, &options)?;
+
+        // Wait for a shutdown signal, then unmount by dropping the session
+        wait_for_shutdown().await?;
+        drop(session);
+
+        // Record the final state of the filesystem, which applies the writes that are still
+        // buffered
+        if let Some((head, handles, audit, task, fs_db_path)) = control {
+            stop_task(task).await;
+            let cid = stop_control(
+                &fs,
+                &head,
+                &handles,
+                &audit,
+                quota.as_ref().map(|quota| &quota.record),
+                &control_socket_path(fs_db_path),
+            )
+            .await?;
+            tracing::info!("stopped at filesystem root {}", cid);
+        }
+
+        if let Some(flush) = flush {
+            flush.abort();
+        }
 
         Ok(())
     }
 }
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Loads the filesystem's recorded head into `fs` and spawns its control server on
+/// `socket_path`.
+///
+/// Intents left behind by a server that crashed are replayed first, so the filesystem resumes
+/// from the last root that was checkpointed completely. From then on, an intent is logged for
+/// the root whenever its directories change, see [`log_intents`].
+///
+/// If the filesystem has a quota, its usage is recorded in `quota` now and whenever its head is.
+///
+/// If the filesystem is recorded to serve a subtree of its root, `fs` serves it from the start,
+/// see [`MonofsNFS::set_subtree`].
+///
+/// The file IDs recorded by earlier runs of the server are restored into `fs`, so the file handles
+/// clients still hold remain valid, and the IDs it registers from now on are recorded too, see
+/// [`record_file_handles`].
+///
+/// If the store of `fs` keeps new blocks in memory, roots are neither logged nor recorded as the
+/// head while the filesystem is served, as their blocks would be gone after a crash. The final
+/// root is only recorded by [`stop_control`], once its blocks are spilled to disk. Otherwise
+/// the filesystem's blocks and fs database are flushed to disk whenever its head is checkpointed,
+/// and by the returned [`DiskSync`] when clients commit their writes.
+///
+/// If the filesystem keeps an audit log, `fs` records the changes it applies from now on, see
+/// [`record_audit_log`].
+///
+/// Returns the head, the file handle records, the audit log and the disk sync, if the filesystem
+/// is on disk, along with the task serving control requests, logging intents, recording file
+/// handles and adding to the audit log.
+pub(super) async fn start_control(
+    fs: &MonofsNFS<FlatFsStore>,
+    fs_db_path: &Path,
+    mount_dir: &Path,
+    socket_path: PathBuf,
+    shared_mounts: Option<Arc<SharedMounts>>,
+    quota: Option<&FsQuota>,
+) -> FsResult<(
+    FsHead,
+    FsFileHandles,
+    FsAuditLog,
+    Option<DiskSync>,
+    JoinHandle<()>,
+)> {
+    let head = FsHead::new(fs_db_path, mount_dir).await?;
+    if let Some(cid) = head.recover(&fs.get_store().await).await? {
+        tracing::warn!("recovered filesystem head {} after a crash", cid);
+    }
+
+    // Every root the server loads is narrowed down to the subtree it serves
+    if let Some(path) = FsSubtree::new(fs_db_path, mount_dir).await?.get().await? {
+        tracing::info!("serving {} as the root", path);
+        fs.set_subtree(&path).await?;
+    }
+
+    // The roots the server records derive from the head it restores
+    if let Some(cid) = head.load().await? {
+        tracing::info!("restoring filesystem head {}", cid);
+        fs.set_root(&cid).await?;
+    }
+
+    // Handles given out before the server restarted refer to the same paths again
+    let handles = FsFileHandles::new(fs_db_path, mount_dir).await?;
+    fs.restore_file_ids(handles.get_all().await?).await;
+
+    // Changes are recorded if the filesystem was initialized to keep an audit log
+    let audit = FsAuditLog::new(fs_db_path, mount_dir).await?;
+    if audit.is_enabled().await? {
+        fs.enable_audit().await;
+    }
+
+    let in_memory = fs.get_store().await.get_memory_config().is_some();
+    let tracked_head = (!in_memory).then(|| head.clone());
+    let disk_sync = (!in_memory).then(|| DiskSync::new(fs.clone(), head.clone(), fs_db_path));
+    let mut control = ControlServer::new(fs.clone(), tracked_head, socket_path);
+    if let Some(disk_sync) = &disk_sync {
+        control = control.with_disk_sync(disk_sync.clone());
+    }
+    if let Some(shared_mounts) = shared_mounts {
+        control = control.with_shared_mounts(shared_mounts);
+    }
+    if let Some(quota) = quota {
+        quota.set_usage(&fs.get_usage().await).await?;
+        control = control.with_quota(quota.clone());
+    }
+
+    let intents = (!in_memory).then(|| log_intents(fs.clone(), head.clone()));
+    let recorded = record_file_handles(fs.clone(), handles.clone());
+    let audited = record_audit_log(fs.clone(), audit.clone());
+    let task = tokio::spawn(async move {
+        let serve = async {
+            if let Err(e) = control.serve().await {
+                tracing::error!(error = %e, "control server stopped");
+            }
+        };
+        let intents = async {
+            if let Some(intents) = intents {
+                intents.await;
+            }
+        };
+        tokio::join!(serve, intents, recorded, audited);
+    });
+
+    Ok((head, handles, audit, disk_sync, task))
+}
+
+/// Checkpoints `fs` as the filesystem's new head, records its usage in `quota` if it has one,
+/// the file IDs it registered in `handles` and the changes it applied in `audit`, and removes the
+/// control socket at `socket_path`.
+///
+/// If the store of `fs` keeps new blocks in memory, the blocks of the final root are spilled to
+/// disk before it is recorded, or the root is discarded if the store is not set to spill.
+///
+/// Heads recorded by other writers since the server last recorded one are merged into the final
+/// root, see [`FsHead::update`].
+///
+/// Returns the final root.
+pub(super) async fn stop_control(
+    fs: &MonofsNFS<FlatFsStore>,
+    head: &FsHead,
+    handles: &FsFileHandles,
+    audit: &FsAuditLog,
+    quota: Option<&FsQuota>,
+    socket_path: &Path,
+) -> FsResult<Cid> {
+    let cid = fs.checkpoint().await?;
+    let store = fs.get_store().await;
+    let record = match store.get_memory_config() {
+        None => true,
+        Some(memory) if *memory.get_spill_on_detach() => {
+            let spilled = store.spill_memory(&cid).await?;
+            tracing::info!("spilled {} blocks of root {} to disk", spilled, cid);
+            true
+        }
+        Some(_) => {
+            tracing::info!("discarding root {} kept in memory", cid);
+            false
+        }
+    };
+    let cid = if record {
+        let merge = |base, _ours, theirs| fs.merge_root(base, theirs);
+        let recorded = head.update(&cid, "unmount", merge).await?;
+
+        // Merging with a head recorded by another writer writes new blocks, into memory too
+        if recorded != cid && store.get_memory_config().is_some() {
+            store.spill_memory(&recorded).await?;
+        }
+        recorded
+    } else {
+        cid
+    };
+    if let Some(quota) = quota {
+        quota.set_usage(&fs.get_usage().await).await?;
+    }
+
+    if let Err(e) = record_new_file_ids(fs, handles).await {
+        tracing::warn!(error = %e, "failed to record file handles");
+    }
+
+    if let Err(e) = record_new_audit_events(fs, audit).await {
+        tracing::warn!(error = %e, "failed to add to the audit log");
+    }
+
+    if let Err(e) = fs::remove_file(socket_path).await {
+        tracing::warn!(error = %e, "failed to remove control socket");
+    }
+
+    Ok(cid)
+}
+
+/// Logs an intent in `head` for the root of `fs` whenever entities are created or removed, so
+/// directory updates survive a crash of the server.
+///
+/// Changes are batched for [`INTENT_LOG_DELAY`] before the root is checkpointed. Writes to files
+/// do not log intents of their own, so they are still buffered by write-back, but every write
+/// applied by the time of a checkpoint is captured by it.
+async fn log_intents(fs: MonofsNFS<FlatFsStore>, head: FsHead) {
+    let mut changes = fs.watch();
+    loop {
+        match changes.recv().await {
+            Ok(event) if *event.get_kind() == ChangeKind::Modified => continue,
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => return,
+        }
+
+        // Changes made until the checkpoint are captured by it
+        time::sleep(INTENT_LOG_DELAY).await;
+        changes = changes.resubscribe();
+
+        let result = async {
+            let cid = fs.checkpoint().await?;
+            head.log_intent(&cid, "checkpoint").await
+        };
+        if let Err(e) = result.await {
+            tracing::error!(error = %e, "failed to log intent");
+        }
+    }
+}
+
+/// Records the file IDs `fs` registers in `handles` every [`FILE_HANDLE_RECORD_INTERVAL`], so
+/// that a server restarted after a crash still resolves all but the most recent of the handles
+/// clients hold.
+async fn record_file_handles(fs: MonofsNFS<FlatFsStore>, handles: FsFileHandles) {
+    let mut interval = time::interval(FILE_HANDLE_RECORD_INTERVAL);
+    interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        if let Err(e) = record_new_file_ids(&fs, &handles).await {
+            tracing::error!(error = %e, "failed to record file handles");
+        }
+    }
+}
+
+/// Records the file IDs `fs` registered since they were last recorded in `handles`.
+async fn record_new_file_ids(fs: &MonofsNFS<FlatFsStore>, handles: &FsFileHandles) -> FsResult<()> {
+    let file_ids = fs.take_new_file_ids().await;
+    if file_ids.is_empty() {
+        return Ok(());
+    }
+
+    handles.record(&file_ids).await
+}
+
+/// Adds the changes `fs` applies to `audit` every [`AUDIT_RECORD_INTERVAL`], if it records them.
+/// Changes applied since the last time are lost if the server crashes.
+async fn record_audit_log(fs: MonofsNFS<FlatFsStore>, audit: FsAuditLog) {
+    let mut interval = time::interval(AUDIT_RECORD_INTERVAL);
+    interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        if let Err(e) = record_new_audit_events(&fs, &audit).await {
+            tracing::error!(error = %e, "failed to add to the audit log");
+        }
+    }
+}
+
+/// Adds the changes `fs` applied since they were last added to `audit`.
+async fn record_new_audit_events(fs: &MonofsNFS<FlatFsStore>, audit: &FsAuditLog) -> FsResult<()> {
+    let events = fs.take_audit_events().await;
+    if events.is_empty() {
+        return Ok(());
+    }
+
+    audit.record(&events).await
+}
+
+/// Spawns a task that flushes the buffered writes of `fs` once they have waited for the flush
+/// interval of `write_back`.
+///
+/// Returns `None` if `write_back` does not buffer writes.
+pub(super) fn spawn_write_back_flush(
+    fs: &MonofsNFS<FlatFsStore>,
+    write_back: &WriteBackConfig,
+) -> Option<JoinHandle<()>> {
+    if !write_back.is_enabled() {
+        return None;
+    }
+
+    let fs = fs.clone();
+    Some(tokio::spawn(async move {
+        let mut interval = time::interval(WRITE_BACK_CHECK_INTERVAL);
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Err(e) = fs.flush_expired_writes().await {
+                tracing::error!(error = %e, "failed to flush buffered writes");
+            }
+        }
+    }))
+}
+
+/// Loads the quota recorded for the filesystem mounted at `mount_dir`, if it limits anything.
+///
+/// The store's size is counted from what the blocks in `store_dir` take up now, and kept up to
+/// date by the store the counter is given to.
+async fn load_quota(
+    fs_db_path: &Path,
+    mount_dir: &Path,
+    store_dir: &Path,
+) -> FsResult<Option<ServedQuota>> {
+    let record = FsQuota::new(fs_db_path, mount_dir).await?;
+    let config = record.get().await?;
+    if !config.is_limited() {
+        return Ok(None);
+    }
+
+    let (_, store_size) = management::get_blocks_usage(store_dir).await?;
+    tracing::info!(
+        "enforcing quota {:?} on a store of {} bytes",
+        config,
+        store_size
+    );
+
+    Ok(Some(ServedQuota {
+        record,
+        config,
+        store_bytes: Arc::new(AtomicU64::new(store_size)),
+    }))
+}
+
+/// Keeps the blocks of `store` in the remote store recorded for the filesystem, if it has one.
+async fn load_remote(
+    store: FlatFsStore,
+    fs_db_path: &Path,
+    mount_dir: &Path,
+) -> FsResult<FlatFsStore> {
+    let Some(remote) = FsRemote::new(fs_db_path, mount_dir).await?.get().await? else {
+        return Ok(store);
+    };
+
+    let location = remote.get_location()?;
+    tracing::info!(
+        "keeping blocks in {}, with up to {} bytes cached locally",
+        location,
+        remote.get_cache_max_bytes()
+    );
+
+    Ok(store.with_remote(open_remote_store(&location)?, *remote.get_cache_max_bytes()))
+}
+
+/// Copies the block files of `store` to the mirrors recorded for the filesystem, and repairs its
+/// blocks from them, if it has any.
+async fn load_mirrors(
+    store: FlatFsStore,
+    fs_db_path: &Path,
+    mount_dir: &Path,
+) -> FsResult<FlatFsStore> {
+    let mirrors = FsMirrors::new(fs_db_path, mount_dir).await?.list().await?;
+    if mirrors.is_empty() {
+        return Ok(store);
+    }
+
+    tracing::info!("mirroring blocks to {} directories", mirrors.len());
+    Ok(store.with_mirrors(mirrors))
+}
+
+/// Repairs the corrupt and missing blocks of `store` from the replicas recorded for the
+/// filesystem, if it has any.
+async fn load_replicas(
+    store: FlatFsStore,
+    fs_db_path: &Path,
+    mount_dir: &Path,
+) -> FsResult<FlatFsStore> {
+    let replicas = FsReplicas::new(fs_db_path, mount_dir).await?.open().await?;
+    if replicas.is_empty() {
+        return Ok(store);
+    }
+
+    tracing::info!("repairing blocks from {} replicas", replicas.len());
+    Ok(store.with_replicas(replicas))
+}
+
+/// Keeps the blocks `store` writes in memory if the filesystem is recorded to keep them there.
+async fn load_memory(
+    store: FlatFsStore,
+    fs_db_path: &Path,
+    mount_dir: &Path,
+) -> FsResult<FlatFsStore> {
+    let Some(memory) = FsMemoryStore::new(fs_db_path, mount_dir)
+        .await?
+        .get()
+        .await?
+    else {
+        return Ok(store);
+    };
+
+    tracing::info!(
+        "keeping new blocks in memory, up to {} bytes",
+        memory.get_max_bytes()
+    );
+
+    Ok(store.with_memory(memory))
+}
+
+/// Makes the CIDs of the blocks `store` writes with the hash function recorded for the
+/// filesystem mounted at `mount_dir`.
+async fn load_hash_function(
+    store: FlatFsStore,
+    fs_db_path: &Path,
+    mount_dir: &Path,
+) -> FsResult<FlatFsStore> {
+    let hash_function = FsStoreHash::new(fs_db_path, mount_dir).await?.get().await?;
+    tracing::info!("making block CIDs with {}", hash_function);
+
+    Ok(store.with_hash_function(hash_function))
+}
+
+/// Stops `task` and waits for it to finish, so that it cannot log an intent after the final head
+/// is recorded.
+pub(super) async fn stop_task(task: JoinHandle<()>) {
+    task.abort();
+    let _ = task.await;
+}
+
+/// Returns the path of the control socket, which lives next to the fs database.
+pub(crate) fn control_socket_path(fs_db_path: &Path) -> PathBuf {
+    fs_db_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(CONTROL_SOCKET_FILENAME)
+}
+
+/// Waits until the process receives SIGINT or SIGTERM.
+async fn wait_for_shutdown() -> std::io::Result<()> {
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sigint = signal(SignalKind::interrupt())?;
+    tokio::select! {
+        _ = sigterm.recv() => tracing::info!("received SIGTERM, shutting down"),
+        _ = sigint.recv() => tracing::info!("received SIGINT, shutting down"),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,308 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use ipldstore::ipld::cid::Cid;
+use sqlx::{Pool, Row, Sqlite};
+use tokio::{sync::Mutex, task::JoinHandle};
+
+use crate::{
+    config::{AtimePolicy, ExportConfig, PortRange, TransferConfig, WriteBackConfig},
+    management::{self, FsAuditLog, FsFileHandles, FsHead},
+    store::FlatFsStore,
+    utils::path::{CONTROL_SOCKET_FILENAME, MFS_DIR_SUFFIX},
+    FsError, FsResult,
+};
+
+use super::{server, MonofsNFS, NfsListener, Throttle};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The mounts an NFS server serves next to its own filesystem.
+///
+/// Shared mounts use the same blocks and fs database as the server's own filesystem, but each one
+/// has a root of its own, tracked under its mount directory. This is how different snapshots or
+/// branches of the same data are exposed at the same time without duplicating any storage.
+///
+/// The mounts are recorded in the `shared_mounts` table, so a restarted server serves them again
+/// on the same ports.
+pub(crate) struct SharedMounts {
+    /// The store shared by every mount.
+    store: FlatFsStore,
+
+    /// The filesystem database shared by every mount.
+    fs_db_path: PathBuf,
+
+    /// The filesystem database.
+    fs_db: Pool<Sqlite>,
+
+    /// The address the mounts are served on.
+    host: String,
+
+    /// The mount directory of the server's own filesystem.
+    served_by: PathBuf,
+
+    /// How the mounts buffer writes before flushing them to the store.
+    write_back: WriteBackConfig,
+
+    /// How much data clients of the mounts move in a single request.
+    transfer: TransferConfig,
+
+    /// Which clients may connect to the mounts, and who they act as.
+    exports: ExportConfig,
+
+    /// Limits how fast clients of the mounts are served, sharing the global limits with the
+    /// server's own filesystem.
+    throttle: Throttle,
+
+    /// When reads through the mounts record the time a file was last accessed.
+    atime_policy: AtimePolicy,
+
+    /// The mounts being served, by mount directory.
+    mounts: Mutex<HashMap<PathBuf, SharedMount>>,
+}
+
+/// A mount being served by [`SharedMounts`].
+struct SharedMount {
+    /// The filesystem served at the mount.
+    fs: MonofsNFS<FlatFsStore>,
+
+    /// The head of the filesystem.
+    head: FsHead,
+
+    /// The file handle records of the filesystem.
+    handles: FsFileHandles,
+
+    /// The audit log of the filesystem.
+    audit: FsAuditLog,
+
+    /// The tasks serving NFS and control requests for the mount.
+    tasks: Vec<JoinHandle<()>>,
+
+    /// The task flushing buffered writes of the mount, if it buffers writes.
+    flush: Option<JoinHandle<()>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl SharedMounts {
+    /// Creates an empty set of mounts sharing `store` and the database at `fs_db_path` with the
+    /// filesystem mounted at `served_by`, and buffering writes, sizing transfers and recording
+    /// access times like it as described by `write_back`, `transfer` and `atime_policy`.
+    pub(crate) async fn new(
+        store: FlatFsStore,
+        fs_db_path: impl Into<PathBuf>,
+        host: impl Into<String>,
+        served_by: impl Into<PathBuf>,
+        write_back: WriteBackConfig,
+        transfer: TransferConfig,
+        atime_policy: AtimePolicy,
+    ) -> FsResult<Self> {
+        let fs_db_path = fs_db_path.into();
+        Ok(Self {
+            store,
+            fs_db: management::get_db_pool(&fs_db_path).await?,
+            fs_db_path,
+            host: host.into(),
+            served_by: served_by.into(),
+            write_back,
+            transfer,
+            exports: ExportConfig::default(),
+            throttle: Throttle::default(),
+            atime_policy,
+            mounts: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Serves the mounts to the clients `exports` allows, squashing the owners they set as it
+    /// asks, like the server's own filesystem.
+    pub(crate) fn with_exports(mut self, exports: ExportConfig) -> Self {
+        self.exports = exports;
+        self
+    }
+
+    /// Serves the mounts as fast as `throttle` lets their clients, like the server's own
+    /// filesystem.
+    pub(crate) fn with_throttle(mut self, throttle: Throttle) -> Self {
+        self.throttle = throttle;
+        self
+    }
+
+    /// Returns the address the mounts are served on.
+    pub(crate) fn get_host(&self) -> &String {
+        &self.host
+    }
+
+    /// Serves every mount recorded for this server, e.g. after the server was restarted.
+    pub(crate) async fn restore(&self) -> FsResult<()> {
+        let records = sqlx::query("SELECT mount_dir, port FROM shared_mounts WHERE served_by = ?")
+            .bind(self.served_by.to_string_lossy().to_string())
+            .fetch_all(&self.fs_db)
+            .await?;
+
+        for record in records {
+            let mount_dir = PathBuf::from(record.get::<String, _>("mount_dir"));
+            let port = record.get::<u32, _>("port");
+            if let Err(e) = self.serve(&mount_dir, port).await {
+                tracing::error!(error = %e, "failed to serve {}", mount_dir.display());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Starts serving the filesystem tracked under `mount_dir` on a free port, and records the
+    /// mount so that it is served again after a restart.
+    ///
+    /// Returns the port the mount is served on.
+    pub(crate) async fn add(&self, mount_dir: &Path) -> FsResult<u32> {
+        if self.mounts.lock().await.contains_key(mount_dir) {
+            return Err(FsError::AlreadyMounted(
+                mount_dir.to_string_lossy().to_string(),
+            ));
+        }
+
+        let port =
+            management::find_available_port_in_range(&self.host, PortRange::default()).await?;
+        self.serve(mount_dir, port).await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO shared_mounts (mount_dir, served_by, host, port)
+            VALUES (?, ?, ?, ?)
+            "#,
+        )
+        .bind(mount_dir.to_string_lossy().to_string())
+        .bind(self.served_by.to_string_lossy().to_string())
+        .bind(&self.host)
+        .bind(port)
+        .execute(&self.fs_db)
+        .await?;
+
+        Ok(port)
+    }
+
+    /// Stops serving the filesystem tracked under `mount_dir` and forgets the mount.
+    ///
+    /// Returns the final root of the filesystem, which is recorded as its head.
+    pub(crate) async fn remove(&self, mount_dir: &Path) -> FsResult<Cid> {
+        let mount = self.mounts.lock().await.remove(mount_dir).ok_or_else(|| {
+            FsError::InvalidOperation(format!(
+                "{} is not a shared mount of this server",
+                mount_dir.display()
+            ))
+        })?;
+
+        let cid = mount.stop(mount_dir).await?;
+        sqlx::query("DELETE FROM shared_mounts WHERE mount_dir = ?")
+            .bind(mount_dir.to_string_lossy().to_string())
+            .execute(&self.fs_db)
+            .await?;
+
+        Ok(cid)
+    }
+
+    /// Stops serving every mount, recording their heads. The mounts stay recorded so that they
+    /// are served again the next time the server starts.
+    pub(crate) async fn stop_all(&self) -> FsResult<()> {
+        let mounts = std::mem::take(&mut *self.mounts.lock().await);
+        for (mount_dir, mount) in mounts {
+            mount.stop(&mount_dir).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Serves the filesystem tracked under `mount_dir` on `port`.
+    async fn serve(&self, mount_dir: &Path, port: u32) -> FsResult<()> {
+        let fs = MonofsNFS::with_write_back(self.store.clone(), self.write_back)
+            .with_transfer(self.transfer)
+            .with_exports(self.exports.clone());
+        fs.set_atime_policy(self.atime_policy).await;
+        let (head, handles, audit, disk_sync, control) = server::start_control(
+            &fs,
+            &self.fs_db_path,
+            mount_dir,
+            get_socket_path(mount_dir),
+            None,
+            None,
+        )
+        .await?;
+
+        let addr = format!("{}:{}", self.host, port);
+        let listener =
+            NfsListener::bind(&addr, fs.clone(), &self.exports, &self.throttle, disk_sync).await;
+        let listener = match listener {
+            Ok(listener) => listener,
+            Err(e) => {
+                control.abort();
+                return Err(e);
+            }
+        };
+
+        let nfs = tokio::spawn(async move {
+            if let Err(e) = listener.handle_forever().await {
+                tracing::error!(error = %e, "shared mount listener stopped");
+            }
+        });
+
+        let flush = server::spawn_write_back_flush(&fs, &self.write_back);
+
+        tracing::info!("serving {} on {}", mount_dir.display(), addr);
+        self.mounts.lock().await.insert(
+            mount_dir.to_path_buf(),
+            SharedMount {
+                fs,
+                head,
+                handles,
+                audit,
+                tasks: vec![nfs, control],
+                flush,
+            },
+        );
+
+        Ok(())
+    }
+}
+
+impl SharedMount {
+    /// Stops serving the mount at `mount_dir` and records its final root.
+    async fn stop(self, mount_dir: &Path) -> FsResult<Cid> {
+        for task in self.tasks {
+            server::stop_task(task).await;
+        }
+
+        // Recording the root applies the buffered writes, so a flush in progress is allowed to
+        // finish rather than being cut short
+        let result = server::stop_control(
+            &self.fs,
+            &self.head,
+            &self.handles,
+            &self.audit,
+            None,
+            &get_socket_path(mount_dir),
+        )
+        .await;
+        if let Some(flush) = self.flush {
+            flush.abort();
+        }
+
+        result
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the path of the control socket of the shared mount at `mount_dir`, which lives in
+/// the mount's own data directory.
+fn get_socket_path(mount_dir: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.{}", mount_dir.display(), MFS_DIR_SUFFIX))
+        .join(CONTROL_SOCKET_FILENAME)
+}
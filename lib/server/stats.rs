@@ -0,0 +1,123 @@
+use std::{io, path::Path};
+
+use getset::Getters;
+use nix::sys::statvfs;
+
+use crate::{config::QuotaConfig, management::QuotaUsage, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// How much space a filesystem has, and how many files it can still hold, as reported to tools
+/// like `df`.
+///
+/// The space starts out as that of the host filesystem holding the store, and is narrowed down
+/// by the filesystem's quota, if it has one. The logical limit is what the files may take up, so
+/// when it is set it is reported as the size of the filesystem.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct FsStats {
+    /// The size of the filesystem in bytes.
+    total_bytes: u64,
+
+    /// The bytes that are free.
+    free_bytes: u64,
+
+    /// The bytes that are free for unprivileged users.
+    available_bytes: u64,
+
+    /// The number of files the filesystem can hold.
+    total_files: u64,
+
+    /// The number of files that can still be created.
+    free_files: u64,
+
+    /// The longest file name supported, in bytes.
+    max_name_len: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl FsStats {
+    /// Returns the space of the host filesystem holding `path`.
+    pub fn from_host(path: impl AsRef<Path>) -> FsResult<Self> {
+        let stats = statvfs::statvfs(path.as_ref()).map_err(io::Error::from)?;
+
+        let fragment_size = u64::from(stats.fragment_size());
+        Ok(Self {
+            total_bytes: u64::from(stats.blocks()) * fragment_size,
+            free_bytes: u64::from(stats.blocks_free()) * fragment_size,
+            available_bytes: u64::from(stats.blocks_available()) * fragment_size,
+            total_files: u64::from(stats.files()),
+            free_files: u64::from(stats.files_free()),
+            max_name_len: u64::from(stats.name_max()),
+        })
+    }
+
+    /// Narrows the space down to what `quota` leaves of it, given that the filesystem holds
+    /// `usage`.
+    pub fn limit(mut self, quota: &QuotaConfig, usage: &QuotaUsage) -> Self {
+        if let Some(max) = *quota.get_max_store_bytes() {
+            self.take_up_to(max, *usage.get_store_bytes());
+        }
+
+        if let Some(max) = *quota.get_max_logical_bytes() {
+            self.take_up_to(max, *usage.get_logical_bytes());
+        }
+
+        self
+    }
+
+    /// Makes the filesystem `max` bytes in size with `used` of them taken, unless less space is
+    /// free.
+    fn take_up_to(&mut self, max: u64, used: u64) {
+        let left = max.saturating_sub(used);
+        self.total_bytes = max;
+        self.free_bytes = self.free_bytes.min(left);
+        self.available_bytes = self.available_bytes.min(left);
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_fs_stats_limit() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let host = FsStats::from_host(temp_dir.path())?;
+        assert!(host.total_bytes > 0);
+        assert!(host.available_bytes <= host.free_bytes);
+
+        // Without a quota, the host's space is reported
+        let usage = QuotaUsage::new(300, 500);
+        assert_eq!(host.limit(&QuotaConfig::unlimited(), &usage), host);
+
+        // The store limit caps the space at what the store may still take up
+        let quota = QuotaConfig::builder().max_store_bytes(Some(2000)).build();
+        let stats = host.limit(&quota, &usage);
+        assert_eq!(stats.total_bytes, 2000);
+        assert_eq!(stats.available_bytes, host.available_bytes.min(1500));
+
+        // The logical limit is the size of the filesystem, with the store limit still applying
+        let quota = QuotaConfig::builder()
+            .max_logical_bytes(Some(1000))
+            .max_store_bytes(Some(600))
+            .build();
+        let stats = host.limit(&quota, &usage);
+        assert_eq!(stats.total_bytes, 1000);
+        assert_eq!(stats.free_bytes, host.free_bytes.min(100));
+        assert_eq!(stats.total_files, host.total_files);
+
+        Ok(())
+    }
+}
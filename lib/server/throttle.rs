@@ -0,0 +1,194 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::time::{self, Instant};
+
+use crate::config::ThrottleConfig;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Enforces the global limits of a [`ThrottleConfig`] across every connection of a server, and
+/// hands out the per-client limits to each connection.
+///
+/// Clones share the global limits, so a server passes a clone to every listener it starts,
+/// including the ones of the mounts sharing it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Throttle {
+    /// The limits to enforce.
+    config: ThrottleConfig,
+
+    /// The requests served each second across all connections, if limited.
+    ops: Option<Arc<RateLimiter>>,
+
+    /// The bytes moved each second across all connections, if limited.
+    bytes: Option<Arc<RateLimiter>>,
+}
+
+/// Enforces the limits of a [`Throttle`] on a single client connection.
+#[derive(Debug)]
+pub(crate) struct ConnectionThrottle {
+    /// The global limits, shared with the other connections.
+    global: Throttle,
+
+    /// The requests served each second on this connection, if limited.
+    ops: Option<RateLimiter>,
+
+    /// The bytes moved each second on this connection, if limited.
+    bytes: Option<RateLimiter>,
+}
+
+/// A token bucket refilled at a fixed rate, holding up to a second's worth of tokens.
+///
+/// Taking more tokens than the bucket holds leaves it in debt, which whoever takes tokens next
+/// waits out, so requests larger than the rate still get through, only more slowly.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    /// The tokens added each second, which is also the most the bucket holds.
+    rate: f64,
+
+    /// The tokens in the bucket, negative while in debt, and when they were last counted.
+    state: Mutex<(f64, Instant)>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl Throttle {
+    /// Creates a throttle that enforces `config`.
+    pub(crate) fn new(config: ThrottleConfig) -> Self {
+        Self {
+            config,
+            ops: config
+                .get_max_ops_per_sec()
+                .map(|rate| Arc::new(RateLimiter::new(rate.into()))),
+            bytes: config
+                .get_max_bytes_per_sec()
+                .map(|rate| Arc::new(RateLimiter::new(rate))),
+        }
+    }
+
+    /// Returns whether the throttle limits anything, and so needs to see every request at all.
+    pub(crate) fn is_limited(&self) -> bool {
+        self.config.is_limited()
+    }
+
+    /// Returns the throttle of a new client connection.
+    pub(crate) fn connection(&self) -> ConnectionThrottle {
+        ConnectionThrottle {
+            global: self.clone(),
+            ops: self
+                .config
+                .get_client_max_ops_per_sec()
+                .map(|rate| RateLimiter::new(rate.into())),
+            bytes: self
+                .config
+                .get_client_max_bytes_per_sec()
+                .map(RateLimiter::new),
+        }
+    }
+}
+
+impl ConnectionThrottle {
+    /// Waits until the limits allow a request of `len` bytes.
+    pub(crate) async fn admit_call(&self, len: usize) {
+        self.wait(1, len as u64).await;
+    }
+
+    /// Waits until the limits allow a reply of `len` bytes.
+    pub(crate) async fn admit_reply(&self, len: usize) {
+        self.wait(0, len as u64).await;
+    }
+
+    /// Takes `ops` and `bytes` from every limit, waiting for the one furthest in debt.
+    async fn wait(&self, ops: u64, bytes: u64) {
+        let now = Instant::now();
+        let delay = [
+            (self.ops.as_ref(), ops),
+            (self.global.ops.as_deref(), ops),
+            (self.bytes.as_ref(), bytes),
+            (self.global.bytes.as_deref(), bytes),
+        ]
+        .into_iter()
+        .filter_map(|(limiter, amount)| Some(limiter?.reserve(amount, now)))
+        .max()
+        .unwrap_or_default();
+
+        if !delay.is_zero() {
+            time::sleep(delay).await;
+        }
+    }
+}
+
+impl RateLimiter {
+    /// Creates a full bucket refilled with `rate` tokens each second.
+    pub(crate) fn new(rate: u64) -> Self {
+        let rate = rate as f64;
+        Self {
+            rate,
+            state: Mutex::new((rate, Instant::now())),
+        }
+    }
+
+    /// Takes `amount` tokens at `now`, returning how long to wait until the bucket is out of
+    /// debt.
+    pub(crate) fn reserve(&self, amount: u64, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let (tokens, last) = *state;
+        let elapsed = now.saturating_duration_since(last).as_secs_f64();
+        let tokens = (tokens + elapsed * self.rate).min(self.rate) - amount as f64;
+        *state = (tokens, now.max(last));
+
+        if tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-tokens / self.rate)
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter_reserve() {
+        let limiter = RateLimiter::new(100);
+        let start = Instant::now();
+
+        // A full bucket lets a second's worth through at once
+        assert_eq!(limiter.reserve(100, start), Duration::ZERO);
+
+        // Past that, the caller waits for the bucket to refill
+        assert_eq!(limiter.reserve(50, start), Duration::from_millis(500));
+
+        // Tokens taken while in debt add to the wait
+        let later = start + Duration::from_millis(500);
+        assert_eq!(limiter.reserve(100, later), Duration::from_secs(1));
+
+        // The bucket never holds more than a second's worth
+        let much_later = later + Duration::from_secs(60);
+        assert_eq!(limiter.reserve(100, much_later), Duration::ZERO);
+        assert!(limiter.reserve(1, much_later) > Duration::ZERO);
+
+        // Throttles only limit what their configuration asks for
+        assert!(!Throttle::default().is_limited());
+        let throttle = Throttle::new(
+            ThrottleConfig::builder()
+                .client_max_ops_per_sec(Some(10))
+                .build(),
+        );
+        let connection = throttle.connection();
+        assert!(throttle.is_limited());
+        assert!(throttle.ops.is_none() && throttle.bytes.is_none());
+        assert!(connection.ops.is_some() && connection.bytes.is_none());
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+
+use getset::Getters;
+use ipldstore::ipld::cid::Cid;
+use serde::{Deserialize, Serialize};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// How many change events are kept for watchers that have yet to receive them. Watchers that fall
+/// further behind miss the oldest events.
+pub(crate) const CHANGE_CHANNEL_CAPACITY: usize = 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// What happened to an entity in a filesystem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// The entity was created, or moved to the path.
+    Created,
+
+    /// The content or attributes of the entity changed.
+    Modified,
+
+    /// The entity was removed, or moved away from the path.
+    Removed,
+}
+
+/// A change made to an entity of a running filesystem.
+///
+/// Paths are absolute within the filesystem, so the root directory is `/`. Replacing the whole
+/// tree, e.g. when restoring a snapshot, is reported as the root being modified.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct ChangeEvent {
+    /// What happened to the entity.
+    kind: ChangeKind,
+
+    /// The path of the entity.
+    path: String,
+
+    /// The CID of the content written to a file, or of the new root directory. Changes that did
+    /// not store anything yet, like creating an entity, have no CID.
+    cid: Option<String>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl ChangeEvent {
+    /// Creates an event for a change of `kind` to the entity at `path`, which is relative to the
+    /// root directory.
+    pub fn new(kind: ChangeKind, path: &str, cid: Option<&Cid>) -> Self {
+        Self {
+            kind,
+            path: format!("/{}", path.trim_matches('/')),
+            cid: cid.map(|cid| cid.to_string()),
+        }
+    }
+
+    /// Returns whether the entity is `path_prefix` or lies below it.
+    ///
+    /// ## Example
+    /// ```
+    /// use monofs::server::{ChangeEvent, ChangeKind};
+    ///
+    /// let event = ChangeEvent::new(ChangeKind::Created, "out/report.txt", None);
+    /// assert!(event.is_under("/out"));
+    /// assert!(event.is_under("out/"));
+    /// assert!(event.is_under("/"));
+    /// assert!(!event.is_under("/output"));
+    /// ```
+    pub fn is_under(&self, path_prefix: &str) -> bool {
+        let prefix = path_prefix.trim_matches('/');
+        if prefix.is_empty() {
+            return true;
+        }
+
+        match self.path[1..].strip_prefix(prefix) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangeKind::Created => write!(f, "created"),
+            ChangeKind::Modified => write!(f, "modified"),
+            ChangeKind::Removed => write!(f, "removed"),
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use std::{collections::HashMap, time::Instant};
+
+use getset::Getters;
+use nfsserve::nfs::fileid3;
+
+use crate::config::WriteBackConfig;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The most files held at once. Holding another file releases the one idle the longest.
+const MAX_HELD_FILES: usize = 1024;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Writes that were acknowledged to clients but not yet applied to their files.
+///
+/// Each file has at most one pending write, a run of contiguous bytes starting at some offset.
+/// Sequential writes, which is what compilers, linkers and `cp` produce, keep extending the run,
+/// so a file written in many small pieces is stored once rather than once per piece. A write that
+/// does not continue the run has to wait for the run to be flushed.
+///
+/// Files can be held, which keeps their pending writes from being flushed when they expire, so
+/// that a temporary file that is removed soon after it is written never reaches the store. Holding
+/// ends when the file is released, when it has not been written to for the flush interval, when
+/// [`MAX_HELD_FILES`] others were held after it was last written to, or when every pending write
+/// is flushed.
+#[derive(Debug)]
+pub(crate) struct WriteBackCache {
+    /// When pending writes are flushed.
+    config: WriteBackConfig,
+
+    /// The pending write of each file, by file ID.
+    pending: HashMap<fileid3, PendingWrite>,
+
+    /// The number of bytes pending across all files.
+    dirty_bytes: u64,
+
+    /// The IDs of the files whose pending writes are held, with when each was held or last
+    /// written to.
+    held: HashMap<fileid3, Instant>,
+}
+
+/// Contiguous bytes waiting to be written to a file.
+#[derive(Debug, Getters)]
+#[getset(get = "pub(crate) with_prefix")]
+pub(crate) struct PendingWrite {
+    /// Where in the file the bytes go.
+    offset: u64,
+
+    /// The bytes to write.
+    data: Vec<u8>,
+
+    /// When the first of the bytes was buffered.
+    since: Instant,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl WriteBackCache {
+    /// Creates an empty cache that flushes as described by `config`.
+    pub(crate) fn new(config: WriteBackConfig) -> Self {
+        Self {
+            config,
+            pending: HashMap::new(),
+            dirty_bytes: 0,
+            held: HashMap::new(),
+        }
+    }
+
+    /// Returns whether writes are buffered at all.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.config.is_enabled()
+    }
+
+    /// Returns whether enough bytes are pending that everything should be flushed.
+    pub(crate) fn is_full(&self) -> bool {
+        self.dirty_bytes >= *self.config.get_max_dirty_bytes()
+    }
+
+    /// Returns the number of bytes pending across all files.
+    pub(crate) fn get_dirty_bytes(&self) -> u64 {
+        self.dirty_bytes
+    }
+
+    /// Returns whether the file with ID `id` has a pending write.
+    pub(crate) fn contains(&self, id: fileid3) -> bool {
+        self.pending.contains_key(&id)
+    }
+
+    /// Returns whether the pending writes of the file with ID `id` are held.
+    pub(crate) fn is_held(&self, id: fileid3) -> bool {
+        self.held.contains_key(&id)
+    }
+
+    /// Holds the pending writes of the file with ID `id` until it is released, or until it has
+    /// been idle for the flush interval.
+    ///
+    /// If [`MAX_HELD_FILES`] files are already held, the one idle the longest is released.
+    pub(crate) fn hold(&mut self, id: fileid3) {
+        self.held.insert(id, Instant::now());
+        if self.held.len() > MAX_HELD_FILES {
+            let idlest = self
+                .held
+                .iter()
+                .min_by_key(|(_, last_written)| **last_written)
+                .map(|(id, _)| *id);
+            if let Some(idlest) = idlest {
+                self.held.remove(&idlest);
+            }
+        }
+    }
+
+    /// Releases the file with ID `id`, so its pending write is flushed like any other. Returns
+    /// whether it was held.
+    pub(crate) fn release(&mut self, id: fileid3) -> bool {
+        self.held.remove(&id).is_some()
+    }
+
+    /// Returns the size of the file with ID `id` once its pending write is applied, given the size
+    /// of its stored content.
+    pub(crate) fn get_size(&self, id: fileid3, stored_size: u64) -> u64 {
+        match self.pending.get(&id) {
+            Some(write) => stored_size.max(write.end()),
+            None => stored_size,
+        }
+    }
+
+    /// Buffers `data` to be written at `offset` of the file with ID `id`.
+    ///
+    /// Returns `false`, buffering nothing, if the file has a pending write that `data` does not
+    /// continue. That write must be flushed first.
+    pub(crate) fn buffer(&mut self, id: fileid3, offset: u64, data: &[u8]) -> bool {
+        match self.pending.get_mut(&id) {
+            Some(write) if write.end() != offset => return false,
+            Some(write) => write.data.extend_from_slice(data),
+            None => {
+                self.pending.insert(
+                    id,
+                    PendingWrite {
+                        offset,
+                        data: data.to_vec(),
+                        since: Instant::now(),
+                    },
+                );
+            }
+        }
+
+        if let Some(last_written) = self.held.get_mut(&id) {
+            *last_written = Instant::now();
+        }
+
+        self.dirty_bytes += data.len() as u64;
+        true
+    }
+
+    /// Removes and returns the pending write of the file with ID `id`.
+    pub(crate) fn take(&mut self, id: fileid3) -> Option<PendingWrite> {
+        let write = self.pending.remove(&id)?;
+        self.dirty_bytes -= write.data.len() as u64;
+        Some(write)
+    }
+
+    /// Removes and returns every pending write, releasing every held file.
+    pub(crate) fn take_all(&mut self) -> Vec<(fileid3, PendingWrite)> {
+        self.dirty_bytes = 0;
+        self.held.clear();
+        self.pending.drain().collect()
+    }
+
+    /// Removes and returns the pending writes of the files that are not held.
+    pub(crate) fn take_unheld(&mut self) -> Vec<(fileid3, PendingWrite)> {
+        self.take_unheld_where(|_| true)
+    }
+
+    /// Removes and returns the pending writes of the files that are not held and have waited for
+    /// the flush interval.
+    ///
+    /// Held files that have not been written to for the flush interval are released first, so
+    /// their pending writes are returned too.
+    pub(crate) fn take_expired(&mut self) -> Vec<(fileid3, PendingWrite)> {
+        let interval = *self.config.get_flush_interval();
+        self.held
+            .retain(|_, last_written| last_written.elapsed() < interval);
+        self.take_unheld_where(|write| write.since.elapsed() >= interval)
+    }
+
+    /// Discards every pending write and releases every held file.
+    pub(crate) fn clear(&mut self) {
+        self.pending.clear();
+        self.held.clear();
+        self.dirty_bytes = 0;
+    }
+
+    /// Removes and returns the pending writes of the files that are not held that `predicate`
+    /// accepts.
+    fn take_unheld_where(
+        &mut self,
+        predicate: impl Fn(&PendingWrite) -> bool,
+    ) -> Vec<(fileid3, PendingWrite)> {
+        let ids = self
+            .pending
+            .iter()
+            .filter(|(id, write)| !self.held.contains_key(id) && predicate(write))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        ids.into_iter()
+            .filter_map(|id| self.take(id).map(|write| (id, write)))
+            .collect()
+    }
+}
+
+impl PendingWrite {
+    /// Returns the offset just past the last pending byte.
+    pub(crate) fn end(&self) -> u64 {
+        self.offset + self.data.len() as u64
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_write_back_cache_buffers_contiguous_writes() {
+        let config = WriteBackConfig::builder().max_dirty_bytes(8).build();
+        let mut cache = WriteBackCache::new(config);
+
+        assert!(cache.buffer(1, 2, b"abc"));
+        assert!(cache.buffer(1, 5, b"de"));
+        assert_eq!(cache.get_size(1, 4), 7);
+        assert_eq!(cache.get_size(1, 10), 10);
+        assert_eq!(cache.get_size(2, 4), 4);
+
+        // A write that does not continue the run is refused
+        assert!(!cache.buffer(1, 2, b"x"));
+        assert!(!cache.is_full());
+
+        assert!(cache.buffer(2, 0, b"fgh"));
+        assert!(cache.is_full());
+
+        let write = cache.take(1).unwrap();
+        assert_eq!(*write.get_offset(), 2);
+        assert_eq!(write.get_data(), b"abcde");
+        assert!(!cache.contains(1));
+        assert!(!cache.is_full());
+
+        assert_eq!(cache.take_all().len(), 1);
+        assert!(cache.take(2).is_none());
+    }
+
+    #[test]
+    fn test_write_back_cache_take_expired() {
+        let config = WriteBackConfig::builder()
+            .flush_interval(Duration::ZERO)
+            .build();
+        let mut cache = WriteBackCache::new(config);
+        cache.buffer(1, 0, b"abc");
+        assert_eq!(cache.take_expired().len(), 1);
+
+        let config = WriteBackConfig::builder()
+            .flush_interval(Duration::from_secs(3600))
+            .build();
+        let mut cache = WriteBackCache::new(config);
+        cache.buffer(1, 0, b"abc");
+        assert!(cache.take_expired().is_empty());
+        assert!(cache.contains(1));
+    }
+
+    #[test]
+    fn test_write_back_cache_hold() {
+        let config = WriteBackConfig::builder()
+            .flush_interval(Duration::from_secs(3600))
+            .build();
+        let mut cache = WriteBackCache::new(config);
+        cache.hold(1);
+        cache.buffer(1, 0, b"abc");
+        cache.buffer(2, 0, b"de");
+
+        // Held files are left out until they are released
+        let taken = cache.take_unheld();
+        assert_eq!(taken.len(), 1);
+        assert_eq!(taken[0].0, 2);
+        assert!(cache.take_expired().is_empty());
+        assert!(cache.take_unheld().is_empty());
+        assert!(cache.is_held(1));
+
+        assert!(cache.release(1));
+        assert!(!cache.release(1));
+        assert_eq!(cache.take_unheld().len(), 1);
+
+        // Taking every write releases every file
+        cache.hold(3);
+        cache.buffer(3, 0, b"f");
+        assert_eq!(cache.take_all().len(), 1);
+        assert!(!cache.is_held(3));
+    }
+
+    #[test]
+    fn test_write_back_cache_hold_bounds() {
+        // Held files idle for the flush interval are released and flushed
+        let config = WriteBackConfig::builder()
+            .flush_interval(Duration::ZERO)
+            .build();
+        let mut cache = WriteBackCache::new(config);
+        cache.hold(1);
+        cache.hold(2);
+        cache.buffer(1, 0, b"abc");
+        let taken = cache.take_expired();
+        assert_eq!(taken.len(), 1);
+        assert_eq!(taken[0].0, 1);
+        assert!(!cache.is_held(1) && !cache.is_held(2));
+
+        // Holding more than the most files releases the one idle the longest
+        let config = WriteBackConfig::builder()
+            .flush_interval(Duration::from_secs(3600))
+            .build();
+        let mut cache = WriteBackCache::new(config);
+        for id in 0..MAX_HELD_FILES as fileid3 {
+            cache.hold(id);
+        }
+        std::thread::sleep(Duration::from_millis(1));
+        cache.buffer(0, 0, b"abc");
+        cache.hold(MAX_HELD_FILES as fileid3);
+        assert!(cache.is_held(0));
+        assert!(cache.is_held(MAX_HELD_FILES as fileid3));
+        assert_eq!(cache.held.len(), MAX_HELD_FILES);
+    }
+}
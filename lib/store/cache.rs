@@ -0,0 +1,244 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use super::{RemoteStore, PACKS_SUBDIR};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The remote store behind a local store, and which of the local block files are copies that
+/// can be evicted to make room.
+///
+/// Block files are evicted least recently used first once they take up more than the cache size.
+/// The files already in the blocks directory are tracked from the first time the cache is used,
+/// oldest first, as if they had been used in the order they were last written.
+#[derive(Debug)]
+pub(crate) struct BlockCache {
+    /// The remote store holding every block.
+    remote: Arc<dyn RemoteStore>,
+
+    /// The most bytes of block files kept.
+    max_bytes: u64,
+
+    /// The tracked block files.
+    state: Mutex<CacheState>,
+}
+
+/// The block files tracked by a [`BlockCache`].
+#[derive(Debug, Default)]
+struct CacheState {
+    /// Whether the files already in the blocks directory are tracked.
+    loaded: bool,
+
+    /// The tracked files, by path.
+    entries: HashMap<PathBuf, CacheEntry>,
+
+    /// The tracked files, by when they were last used.
+    by_use: BTreeMap<u64, PathBuf>,
+
+    /// The total size of the tracked files.
+    bytes: u64,
+
+    /// Counts uses, to order them.
+    clock: u64,
+}
+
+/// A block file tracked by a [`BlockCache`].
+#[derive(Debug, Clone, Copy)]
+struct CacheEntry {
+    /// The size of the file.
+    size: u64,
+
+    /// When the file was last used.
+    last_used: u64,
+
+    /// Whether the block is known to be in the remote store.
+    uploaded: bool,
+}
+
+/// A block file evicted from a [`BlockCache`], to be removed from the blocks directory.
+#[derive(Debug, Clone)]
+pub(crate) struct EvictedBlock {
+    /// The path of the file.
+    pub(crate) path: PathBuf,
+
+    /// Whether the block is known to be in the remote store. If not, it must be uploaded before
+    /// the file is removed.
+    pub(crate) uploaded: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl BlockCache {
+    /// Creates a cache in front of `remote` that keeps at most `max_bytes` of block files.
+    pub(crate) fn new(remote: Arc<dyn RemoteStore>, max_bytes: u64) -> Self {
+        Self {
+            remote,
+            max_bytes,
+            state: Default::default(),
+        }
+    }
+
+    /// Returns the remote store holding every block.
+    pub(crate) fn get_remote(&self) -> &Arc<dyn RemoteStore> {
+        &self.remote
+    }
+
+    /// Records that the block file at `path`, of `size` bytes, was just written.
+    pub(crate) fn insert(&self, blocks_dir: &Path, path: &Path, size: u64, uploaded: bool) {
+        let mut state = self.lock(blocks_dir);
+        state.remove(path);
+        state.insert(path.to_path_buf(), size, uploaded);
+    }
+
+    /// Records that the block file at `path` was just read.
+    ///
+    /// Files written by other processes are tracked from their first read.
+    pub(crate) fn touch(&self, blocks_dir: &Path, path: &Path) {
+        let mut state = self.lock(blocks_dir);
+        let (size, uploaded) = match state.remove(path) {
+            Some(entry) => (entry.size, entry.uploaded),
+            None => match std::fs::metadata(path) {
+                Ok(metadata) => (metadata.len(), false),
+                Err(_) => return,
+            },
+        };
+        state.insert(path.to_path_buf(), size, uploaded);
+    }
+
+    /// Stops tracking the least recently used block files until the rest fit in the cache, and
+    /// returns them.
+    pub(crate) fn take_evictions(&self, blocks_dir: &Path) -> Vec<EvictedBlock> {
+        let mut state = self.lock(blocks_dir);
+        let mut evicted = Vec::new();
+        while state.bytes > self.max_bytes {
+            let Some((_, path)) = state.by_use.pop_first() else {
+                break;
+            };
+            if let Some(entry) = state.entries.remove(&path) {
+                state.bytes -= entry.size;
+                evicted.push(EvictedBlock {
+                    path,
+                    uploaded: entry.uploaded,
+                });
+            }
+        }
+
+        evicted
+    }
+
+    /// Locks the tracked files, tracking the files in `blocks_dir` first if they are not yet.
+    fn lock(&self, blocks_dir: &Path) -> std::sync::MutexGuard<'_, CacheState> {
+        let mut state = self.state.lock().expect("block cache lock poisoned");
+        if !state.loaded {
+            state.load(blocks_dir);
+        }
+
+        state
+    }
+}
+
+impl CacheState {
+    /// Tracks the block files in `blocks_dir`, oldest first.
+    fn load(&mut self, blocks_dir: &Path) {
+        let mut files = Vec::new();
+        let mut pending = vec![blocks_dir.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                if metadata.is_dir() {
+                    if entry.file_name() != PACKS_SUBDIR {
+                        pending.push(entry.path());
+                    }
+                    continue;
+                }
+
+                let name = entry.file_name().to_string_lossy().to_string();
+                if metadata.is_file() && super::get_block_name(&name).is_some() {
+                    files.push((metadata.modified().ok(), entry.path(), metadata.len()));
+                }
+            }
+        }
+
+        files.sort();
+        for (_, path, size) in files {
+            self.insert(path, size, false);
+        }
+        self.loaded = true;
+    }
+
+    /// Tracks the file at `path` as the most recently used.
+    fn insert(&mut self, path: PathBuf, size: u64, uploaded: bool) {
+        self.clock += 1;
+        self.by_use.insert(self.clock, path.clone());
+        self.entries.insert(
+            path,
+            CacheEntry {
+                size,
+                last_used: self.clock,
+                uploaded,
+            },
+        );
+        self.bytes += size;
+    }
+
+    /// Stops tracking the file at `path`, returning how it was tracked.
+    fn remove(&mut self, path: &Path) -> Option<CacheEntry> {
+        let entry = self.entries.remove(path)?;
+        self.by_use.remove(&entry.last_used);
+        self.bytes -= entry.size;
+        Some(entry)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use crate::store::DirRemoteStore;
+
+    use super::*;
+
+    #[test]
+    fn test_block_cache_evicts_least_recently_used() {
+        let temp = TempDir::new().unwrap();
+        let remote = Arc::new(DirRemoteStore::new(temp.path().join("remote")));
+        let cache = BlockCache::new(remote, 100);
+        let blocks_dir = temp.path().join("blocks");
+
+        cache.insert(&blocks_dir, Path::new("a"), 40, true);
+        cache.insert(&blocks_dir, Path::new("b"), 40, false);
+        assert!(cache.take_evictions(&blocks_dir).is_empty());
+
+        // Reading `a` makes `b` the least recently used
+        cache.touch(&blocks_dir, Path::new("a"));
+        cache.insert(&blocks_dir, Path::new("c"), 40, true);
+        let evicted = cache.take_evictions(&blocks_dir);
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].path, Path::new("b"));
+        assert!(!evicted[0].uploaded);
+
+        cache.insert(&blocks_dir, Path::new("d"), 90, true);
+        let evicted = cache
+            .take_evictions(&blocks_dir)
+            .into_iter()
+            .map(|block| block.path)
+            .collect::<Vec<_>>();
+        assert_eq!(evicted, [PathBuf::from("a"), PathBuf::from("c")]);
+    }
+}
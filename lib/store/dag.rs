@@ -0,0 +1,337 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use futures::stream::{FuturesUnordered, StreamExt};
+use getset::Getters;
+use ipldstore::{ipld::cid::Cid, Codec, IpldStore, StoreResult};
+
+use super::load_links;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The number of blocks a [`DagWalker`] loads at once by default.
+pub const DEFAULT_WALK_CONCURRENCY: usize = 32;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Walks the blocks reachable from a set of roots, loading many of them at once.
+///
+/// Links are followed the way [`get_links`](super::get_links) finds them, so the `previous` link
+/// of entities is skipped. Every block is visited once however many blocks link to it, which also
+/// keeps a walk from looping on a cyclic graph. Blocks that are linked to but missing from the
+/// store are recorded as missing instead of failing the walk.
+///
+/// ## Example
+/// ```
+/// use ipldstore::MemoryStore;
+/// use monofs::{filesystem::Dir, store::DagWalker};
+///
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let store = MemoryStore::default();
+/// let mut dir = Dir::new(store.clone());
+/// dir.find_or_create("foo/bar.txt", true).await?;
+/// let root = dir.checkpoint().await?;
+///
+/// let dag = DagWalker::new(&store).with_concurrency(4).walk([root]).await?;
+///
+/// assert!(dag.contains(&root));
+/// assert!(dag.get_missing().is_empty());
+/// assert_eq!(dag.get_postorder([root]).last(), Some(&root));
+/// # Ok(())
+/// # }
+/// ```
+pub struct DagWalker<'a, S> {
+    /// The store holding the blocks.
+    store: &'a S,
+
+    /// The most blocks loaded at once.
+    concurrency: usize,
+
+    /// Whether raw blocks are checked for in the store, so that missing ones are recorded.
+    check_raw: bool,
+}
+
+/// How far a [`DagWalker`] has got, as reported to its progress callback.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct DagWalkProgress {
+    /// The number of blocks visited so far.
+    visited: u64,
+
+    /// The number of blocks found but not yet visited.
+    pending: u64,
+}
+
+/// The blocks visited by a [`DagWalker`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct WalkedDag {
+    /// The links of every visited block, with a CID repeated for every link to it. Missing and
+    /// skipped blocks have no links.
+    links: HashMap<Cid, Vec<Cid>>,
+
+    /// The visited blocks that are missing from the store.
+    missing: HashSet<Cid>,
+
+    /// The visited blocks that were not descended into.
+    skipped: HashSet<Cid>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl<'a, S> DagWalker<'a, S>
+where
+    S: IpldStore,
+{
+    /// Creates a walker over the blocks in `store`.
+    pub fn new(store: &'a S) -> Self {
+        Self {
+            store,
+            concurrency: DEFAULT_WALK_CONCURRENCY,
+            check_raw: false,
+        }
+    }
+
+    /// Sets the most blocks loaded at once, at least one.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Checks raw blocks for in the store too, so that missing file contents are recorded.
+    ///
+    /// Raw blocks have no links, so by default they are visited without touching the store.
+    pub fn with_raw_checks(mut self) -> Self {
+        self.check_raw = true;
+        self
+    }
+
+    /// Walks every block reachable from `roots`.
+    pub async fn walk(&self, roots: impl IntoIterator<Item = Cid>) -> StoreResult<WalkedDag> {
+        self.walk_with(roots, |_| false, |_| {}).await
+    }
+
+    /// Walks every block reachable from `roots`, reporting every visited block to `progress`.
+    ///
+    /// Blocks for which `skip` returns `true` are visited without being loaded, so they have no
+    /// links and the blocks below them are only visited if something else links to them.
+    pub async fn walk_with(
+        &self,
+        roots: impl IntoIterator<Item = Cid>,
+        skip: impl Fn(&Cid) -> bool + Send,
+        mut progress: impl FnMut(&DagWalkProgress) + Send,
+    ) -> StoreResult<WalkedDag> {
+        let mut dag = WalkedDag::default();
+        let mut found = HashSet::new();
+        let mut pending = roots
+            .into_iter()
+            .filter(|cid| found.insert(*cid))
+            .collect::<VecDeque<_>>();
+
+        let mut loading = FuturesUnordered::new();
+        loop {
+            while loading.len() < self.concurrency {
+                let Some(cid) = pending.pop_front() else {
+                    break;
+                };
+
+                if skip(&cid) {
+                    dag.skipped.insert(cid);
+                    dag.links.insert(cid, Vec::new());
+                    continue;
+                }
+
+                loading.push(async move { (cid, self.load(&cid).await) });
+            }
+
+            let Some((cid, links)) = loading.next().await else {
+                break;
+            };
+
+            match links? {
+                Some(links) => {
+                    pending.extend(links.iter().filter(|link| found.insert(**link)));
+                    dag.links.insert(cid, links);
+                }
+                None => {
+                    dag.missing.insert(cid);
+                    dag.links.insert(cid, Vec::new());
+                }
+            }
+
+            progress(&DagWalkProgress {
+                visited: dag.links.len() as u64,
+                pending: (pending.len() + loading.len()) as u64,
+            });
+        }
+
+        Ok(dag)
+    }
+
+    /// Returns the links of the block at `cid`, or `None` if it is missing from the store.
+    async fn load(&self, cid: &Cid) -> StoreResult<Option<Vec<Cid>>> {
+        let codec: Codec = cid.codec().try_into()?;
+        if self.check_raw && !matches!(codec, Codec::DagCbor) && !self.store.has(cid).await {
+            return Ok(None);
+        }
+
+        load_links(self.store, cid).await
+    }
+}
+
+impl WalkedDag {
+    /// Returns the number of visited blocks.
+    pub fn len(&self) -> usize {
+        self.links.len()
+    }
+
+    /// Returns whether no block was visited.
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    /// Returns whether the block at `cid` was visited.
+    pub fn contains(&self, cid: &Cid) -> bool {
+        self.links.contains_key(cid)
+    }
+
+    /// Returns the CIDs of the visited blocks.
+    pub fn into_reachable(self) -> HashSet<Cid> {
+        self.links.into_keys().collect()
+    }
+
+    /// Returns the blocks that link to each visited block, with a CID repeated for every link.
+    pub fn get_parents(&self) -> HashMap<Cid, Vec<Cid>> {
+        let mut parents = HashMap::<Cid, Vec<Cid>>::new();
+        for (cid, links) in &self.links {
+            for link in links {
+                parents.entry(*link).or_default().push(*cid);
+            }
+        }
+
+        parents
+    }
+
+    /// Returns the visited blocks reachable from `roots` in a depth-first walk, every block
+    /// before the blocks it links to and those in the order they are linked.
+    pub fn get_preorder(&self, roots: impl IntoIterator<Item = Cid>) -> Vec<Cid> {
+        let mut order = Vec::new();
+        let mut seen = HashSet::new();
+        let mut pending = roots.into_iter().collect::<Vec<_>>();
+        pending.reverse();
+        while let Some(cid) = pending.pop() {
+            if !self.contains(&cid) || !seen.insert(cid) {
+                continue;
+            }
+
+            order.push(cid);
+            pending.extend(self.links[&cid].iter().rev());
+        }
+
+        order
+    }
+
+    /// Returns the visited blocks reachable from `roots` in a depth-first walk, every block after
+    /// the blocks it links to.
+    pub fn get_postorder(&self, roots: impl IntoIterator<Item = Cid>) -> Vec<Cid> {
+        let mut order = Vec::new();
+        let mut seen = HashSet::new();
+        for root in roots {
+            let mut stack = vec![(root, false)];
+            while let Some((cid, expanded)) = stack.pop() {
+                if expanded {
+                    order.push(cid);
+                } else if self.contains(&cid) && seen.insert(cid) {
+                    stack.push((cid, true));
+                    stack.extend(self.links[&cid].iter().map(|link| (*link, false)));
+                }
+            }
+        }
+
+        order
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::MemoryStore;
+
+    use crate::filesystem::{Dir, File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dag_walker() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut dir = Dir::new(store.clone());
+        let mut content = None;
+        for name in ["a.txt", "b.txt"] {
+            let file = File::with_content(store.clone(), b"shared".as_slice()).await?;
+            content = file.get_content().copied();
+            dir.put_adapted_file(name, file).await?;
+        }
+        let content = content.unwrap();
+        let root = dir.checkpoint().await?;
+
+        // Every concurrency visits the same blocks, each once
+        let serial = DagWalker::new(&store)
+            .with_concurrency(1)
+            .walk([root])
+            .await?;
+        let mut reports = Vec::new();
+        let dag = DagWalker::new(&store)
+            .walk_with(
+                [root, root],
+                |_| false,
+                |progress| reports.push(progress.clone()),
+            )
+            .await?;
+        assert_eq!(dag, serial);
+        assert_eq!(reports.len(), dag.len());
+        assert_eq!(reports.last().unwrap().get_visited(), &(dag.len() as u64));
+        assert_eq!(reports.last().unwrap().get_pending(), &0);
+        assert!(dag.contains(&content));
+
+        // Blocks come after the blocks linking to them, or before
+        let postorder = dag.get_postorder([root]);
+        let preorder = dag.get_preorder([root]);
+        assert_eq!(postorder.len(), dag.len());
+        assert_eq!(postorder.last(), Some(&root));
+        assert_eq!(preorder.first(), Some(&root));
+        for (cid, links) in dag.get_links() {
+            let position = postorder.iter().position(|c| c == cid).unwrap();
+            for link in links {
+                assert!(postorder.iter().position(|c| c == link).unwrap() < position);
+            }
+        }
+        assert!(!dag.get_parents()[&content].is_empty());
+
+        // Skipped blocks are not descended into, and missing blocks are recorded
+        let dag = DagWalker::new(&store)
+            .walk_with([root], |cid| cid == &root, |_| {})
+            .await?;
+        assert_eq!(dag.len(), 1);
+        assert!(dag.get_skipped().contains(&root));
+
+        let missing = ipldstore::generate_cid(Codec::Raw, b"missing");
+        let dag = DagWalker::new(&store).walk([missing]).await?;
+        assert!(dag.get_missing().is_empty());
+        let dag = DagWalker::new(&store)
+            .with_raw_checks()
+            .walk([root, missing])
+            .await?;
+        assert_eq!(dag.get_missing(), &HashSet::from([missing]));
+
+        Ok(())
+    }
+}
@@ -1,17 +1,32 @@
-use std::{collections::HashSet, path::PathBuf, pin::Pin, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::SystemTime,
+};
 
 use async_trait::async_trait;
 use bytes::Bytes;
-use futures::StreamExt;
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use futures::{StreamExt, TryStreamExt};
 use getset::Getters;
 use ipldstore::{
+    codetable::MultihashDigest,
     ipld::{cid::Cid, codec::Links},
     Chunker, Codec, FastCDCChunker, FixedSizeChunker, FlatLayout, IpldReferences, IpldStore,
     IpldStoreSeekable, Layout, LayoutSeekable, RawStore, StoreError, StoreResult,
     DEFAULT_MAX_NODE_BLOCK_SIZE,
 };
 use microsandbox_utils::SeekableReader;
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_ipld_dagcbor::codec::DagCborCodec;
 use tokio::{
     fs::{self, File},
@@ -19,6 +34,47 @@ use tokio::{
 };
 use typed_builder::TypedBuilder;
 
+use crate::{
+    config::{
+        ChunkerConfig, CompressionConfig, Durability, EncryptionKey, HashFunction,
+        MemoryStoreConfig,
+    },
+    FsError,
+};
+
+use super::{
+    BlockCache, BlockIndex, IndexedBlock, MemoryBlocks, PackIndex, PackWriter, PackedBlock,
+    RemoteStore, PACKS_SUBDIR, PACK_INDEX_EXTENSION,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The extension of block files holding zstd-compressed data.
+pub(crate) const COMPRESSED_BLOCK_EXTENSION: &str = "zst";
+
+/// The extension of block files holding encrypted data.
+pub(crate) const ENCRYPTED_BLOCK_EXTENSION: &str = "enc";
+
+/// The size in bytes of the nonce an encrypted block is sealed with.
+const BLOCK_NONCE_SIZE: usize = 24;
+
+/// How much smaller, in percent, compressing a block has to make it for the block to be stored
+/// compressed.
+const MIN_COMPRESSION_SAVINGS_PERCENT: usize = 10;
+
+/// Tells apart the temporary files the same process writes block files and packfiles through,
+/// like those of blocks fetched from a remote store or copied to a mirror.
+static FETCH_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// The most files and directories flushed to disk at once when a batch of block writes is done.
+const BATCH_FLUSH_CONCURRENCY: usize = 32;
+
+/// The most block files written without being flushed that a store keeps track of. A store that
+/// wrote more flushes every block file it holds when it is synced.
+const MAX_UNSYNCED_BLOCKS: usize = 100_000;
+
 //--------------------------------------------------------------------------------------------------
 // Types: FlatFsStore
 //--------------------------------------------------------------------------------------------------
@@ -65,6 +121,33 @@ pub enum DirLevels {
     Two,
 }
 
+/// How the data in a block file is encoded, which the file's extension records.
+///
+/// Blocks written before compression was supported have no extension and hold their data as is,
+/// so stores created by earlier versions keep working, and a store may hold blocks in any mix of
+/// formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum BlockFormat {
+    /// The data is stored as is, in a file named after the hex digest of the block's CID.
+    Plain,
+
+    /// The data is compressed with zstd, in a file named like a plain block with the
+    /// [`COMPRESSED_BLOCK_EXTENSION`] extension.
+    Zstd,
+
+    /// The data, plain or compressed, is encrypted with XChaCha20-Poly1305, in a file named after
+    /// a keyed hash of the digest of the block's CID, see
+    /// [`EncryptionKey::get_block_name`], with the [`ENCRYPTED_BLOCK_EXTENSION`] extension.
+    ///
+    /// The file holds a byte telling the format of the data once decrypted, then the nonce, then
+    /// the ciphertext of the block's CID, prefixed with its length in a byte, followed by the
+    /// data. The format byte and the file's name are authenticated along with the ciphertext, so
+    /// an encrypted block cannot be swapped for another one without that being detected, and the
+    /// CID of a block can be told from its file with the key.
+    Encrypted,
+}
+
 /// A flat filesystem store that organizes blocks in a configurable directory structure based on
 /// the CID digest.
 ///
@@ -105,6 +188,30 @@ pub enum DirLevels {
 /// The store uses a configurable chunking strategy to split data into smaller blocks. The chunker
 /// is configurable via the `chunker` field. The layout strategy is configurable via the `layout`
 /// field.
+///
+/// ## Compression
+///
+/// New blocks are optionally compressed with zstd, as configured by the `compression` field. A
+/// block is stored compressed only if that makes it meaningfully smaller, and compressed blocks
+/// are named with a `.zst` extension. Blocks are read back in whatever format they were written
+/// in, so compression can be turned on or off for an existing store at any time.
+///
+/// ## Encryption
+///
+/// New blocks are optionally encrypted, after being compressed, with the key in the `encryption`
+/// field, and encrypted blocks are named with a `.enc` extension. Reading an encrypted block
+/// requires the key. Encryption hides what blocks contain, but leaks the following:
+///
+/// - Encrypted block files are named after a keyed hash of the digest of their CID, so that
+///   someone without the key cannot confirm that the store holds a block whose content they
+///   know, such as a well-known file. The names still tell blocks apart, and blocks written
+///   before encryption was enabled keep their plain names.
+/// - Blocks are compressed before they are encrypted, so the size of an encrypted block tells how
+///   well its content compresses. If an attacker can get chosen data stored in the same block as
+///   a secret, the sizes of the blocks can reveal the secret. Compression can be turned off
+///   where that matters.
+/// - Reference counts are stored in the clear, so how many blocks there are and how they are
+///   shared is visible.
 #[derive(Debug, Clone, TypedBuilder, Getters)]
 #[getset(get = "pub with_prefix")]
 pub struct FlatFsStoreImpl<C = FastCDCChunker, L = FlatLayout>
@@ -131,6 +238,90 @@ where
     /// Whether to enable reference counting for garbage collection.
     #[builder(default = true)]
     enable_refcount: bool,
+
+    /// How new blocks are compressed.
+    #[builder(default = CompressionConfig::disabled())]
+    compression: CompressionConfig,
+
+    /// The key new blocks are encrypted with, and encrypted blocks are decrypted with.
+    #[builder(default)]
+    #[getset(skip)]
+    encryption: Option<EncryptionKey>,
+
+    /// Counts the bytes the store's block files take up on disk, if tracked.
+    #[builder(default)]
+    #[getset(skip)]
+    usage: Option<Arc<AtomicU64>>,
+
+    /// The blocks in the store's packfiles, shared by clones of the store.
+    #[builder(default, setter(skip))]
+    #[getset(skip)]
+    packs: Arc<RwLock<PackIndex>>,
+
+    /// The remote store holding every block, with the block files as a cache in front of it, if
+    /// any.
+    #[builder(default, setter(skip))]
+    #[getset(skip)]
+    cache: Option<Arc<BlockCache>>,
+
+    /// The replica stores good copies of corrupt or missing blocks are fetched from, in order.
+    #[builder(default, setter(skip))]
+    #[getset(skip)]
+    replicas: Vec<Arc<dyn RemoteStore>>,
+
+    /// The directories holding copies of every block file and packfile of the store, laid out
+    /// like its own directory.
+    #[builder(default, setter(skip))]
+    #[getset(skip)]
+    mirrors: Vec<PathBuf>,
+
+    /// The blocks written to memory instead of to their files, if new blocks are kept in memory.
+    #[builder(default, setter(skip))]
+    #[getset(skip)]
+    memory: Option<Arc<MemoryBlocks>>,
+
+    /// The block files of the store, kept in memory, if they are indexed.
+    #[builder(default, setter(skip))]
+    #[getset(skip)]
+    index: Option<Arc<BlockIndex>>,
+
+    /// When new block files are flushed to disk.
+    #[builder(default)]
+    durability: Durability,
+
+    /// The hash function the CIDs of new blocks are made with.
+    #[builder(default)]
+    hash_function: HashFunction,
+
+    /// The block files written as part of the current batch, if the store's writes are batched.
+    #[builder(default, setter(skip))]
+    #[getset(skip)]
+    batch: Option<Arc<WriteBatch>>,
+
+    /// The block files written without being flushed to disk since the store was last synced,
+    /// shared by clones of the store.
+    #[builder(default, setter(skip))]
+    #[getset(skip)]
+    unsynced: Arc<UnsyncedBlocks>,
+}
+
+/// The block files written as part of a batch of writes, flushed to disk together once the batch
+/// is done.
+#[derive(Debug, Default)]
+struct WriteBatch {
+    /// The paths of the block files.
+    files: Mutex<Vec<PathBuf>>,
+}
+
+/// The block files a store wrote without flushing them to disk, flushed together when the store
+/// is synced with [`sync`](FlatFsStoreImpl::sync).
+#[derive(Debug, Default)]
+struct UnsyncedBlocks {
+    /// The paths of the block files.
+    files: Mutex<HashSet<PathBuf>>,
+
+    /// Whether there were too many block files to keep track of.
+    overflowed: AtomicBool,
 }
 
 /// A flat filesystem store that organizes blocks in a configurable directory structure based on
@@ -176,6 +367,31 @@ pub type FlatFsStore = FlatFsStoreImpl<FastCDCChunker, FlatLayout>;
 /// A [`FlatFsStoreImpl`] with a [`FixedSizeChunker`] for chunking and [`FlatLayout`] for layout.
 pub type FlatFsStoreFixed = FlatFsStoreImpl<FixedSizeChunker, FlatLayout>;
 
+//--------------------------------------------------------------------------------------------------
+// Methods: BlockFormat
+//--------------------------------------------------------------------------------------------------
+
+impl BlockFormat {
+    /// Returns the byte identifying the format inside an encrypted block.
+    fn to_byte(self) -> u8 {
+        match self {
+            BlockFormat::Plain => 0,
+            BlockFormat::Zstd => 1,
+            BlockFormat::Encrypted => 2,
+        }
+    }
+
+    /// Returns the format identified by a byte inside an encrypted block, which is never itself
+    /// encrypted.
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(BlockFormat::Plain),
+            1 => Some(BlockFormat::Zstd),
+            _ => None,
+        }
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Methods: FlatFsStore
 //--------------------------------------------------------------------------------------------------
@@ -197,6 +413,19 @@ where
             chunker: Default::default(),
             layout: Default::default(),
             enable_refcount: true,
+            compression: CompressionConfig::disabled(),
+            encryption: None,
+            usage: None,
+            packs: Default::default(),
+            cache: None,
+            replicas: Vec::new(),
+            mirrors: Vec::new(),
+            memory: None,
+            index: None,
+            durability: Durability::default(),
+            hash_function: HashFunction::default(),
+            batch: None,
+            unsynced: Default::default(),
         }
     }
 
@@ -205,21 +434,846 @@ where
         self.enable_refcount
     }
 
+    /// Compresses new blocks as described by `compression`.
+    ///
+    /// Existing blocks are left as they are and can still be read.
+    pub fn with_compression(mut self, compression: CompressionConfig) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Encrypts new blocks with `key`, and decrypts encrypted blocks with it.
+    ///
+    /// Existing blocks are left as they are and can still be read.
+    pub fn with_encryption(mut self, key: Option<EncryptionKey>) -> Self {
+        self.encryption = key;
+        self
+    }
+
+    /// Adds the size of every block file the store writes to `usage`, and subtracts the size of
+    /// every block file it removes.
+    ///
+    /// Clones of the store share the counter. Blocks written or removed by other stores over the
+    /// same path are not counted.
+    pub fn with_usage(mut self, usage: Arc<AtomicU64>) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    /// Keeps every block in `remote` too, and only keeps up to `cache_max_bytes` of block files.
+    ///
+    /// New blocks are written to `remote` as they are written to their files. Once the block
+    /// files take up more than `cache_max_bytes`, the least recently used are removed, after
+    /// uploading any that `remote` does not have yet, and blocks that have no file are fetched
+    /// from `remote` when they are read. Packed blocks are always kept.
+    ///
+    /// Clones of the store share the cache.
+    pub fn with_remote(mut self, remote: Arc<dyn RemoteStore>, cache_max_bytes: u64) -> Self {
+        self.cache = Some(Arc::new(BlockCache::new(remote, cache_max_bytes)));
+        self
+    }
+
+    /// Repairs corrupt and missing blocks from `replicas`, tried in order, as they are read.
+    ///
+    /// With replicas, the data of every block read is checked against its CID. A block whose data
+    /// does not match, or that is missing from the store and from its remote store, is fetched
+    /// from the first replica holding a good copy and written back to its file. Replicas hold
+    /// blocks as objects, like a remote store does, e.g. the remote store of another filesystem
+    /// with the same blocks.
+    pub fn with_replicas(mut self, replicas: Vec<Arc<dyn RemoteStore>>) -> Self {
+        self.replicas = replicas;
+        self
+    }
+
+    /// Keeps a copy of every block file and packfile of the store in each of `mirrors`, e.g. on
+    /// other disks, so the blocks survive losing any one of them.
+    ///
+    /// Block files are copied as they are written, and their copies updated and removed with
+    /// them. With mirrors, the data of every block read is checked against its CID like with
+    /// replicas, and a block whose data does not match or that is missing is repaired from the
+    /// first mirror holding a good copy, before any replica is tried. Copies of packfiles are
+    /// not read from. A mirror that cannot be written to is logged and left behind, to be caught
+    /// up with by [`resilver_mfs`](crate::management::resilver_mfs).
+    pub fn with_mirrors(mut self, mirrors: Vec<PathBuf>) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+
+    /// Keeps new blocks in memory, as described by `memory`, instead of writing them to their
+    /// files.
+    ///
+    /// Blocks that already have a file are still read from it. Blocks kept in memory are lost
+    /// when the last clone of the store is dropped, unless they are written to their files with
+    /// [`spill_memory`](Self::spill_memory) first. Clones of the store share the blocks.
+    pub fn with_memory(mut self, memory: MemoryStoreConfig) -> Self {
+        self.memory = Some(Arc::new(MemoryBlocks::new(memory)));
+        self
+    }
+
+    /// Indexes the block files of the store in memory, so looking for a block takes at most one
+    /// stat of its file instead of one for every file it could be in.
+    ///
+    /// The index is loaded by walking the store's directory once, and kept up to date with the
+    /// block files the store writes and removes. Clones of the store share the index. Whether the
+    /// store has a block is answered from the index, so blocks written by other stores over the
+    /// same path since it was loaded are not seen by [`has`](IpldStore::has), though they can
+    /// still be read. Writing such a block again keeps its file as it is.
+    pub async fn with_index(mut self) -> StoreResult<Self> {
+        self.index = Some(Arc::new(BlockIndex::load(&self.path).await?));
+        Ok(self)
+    }
+
+    /// Flushes new block files to disk as `durability` asks.
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Makes the CIDs of new blocks with `hash_function`.
+    ///
+    /// Blocks already stored keep their CIDs, and can still be read whichever hash function they
+    /// were made with.
+    pub fn with_hash_function(mut self, hash_function: HashFunction) -> Self {
+        self.hash_function = hash_function;
+        self
+    }
+
+    /// Returns the directories holding copies of the store's block files and packfiles.
+    pub fn get_mirrors(&self) -> &[PathBuf] {
+        &self.mirrors
+    }
+
+    /// Flushes the block files the store wrote without flushing them since it was last synced to
+    /// disk, along with the directories they are in.
+    ///
+    /// Only stores whose [`Durability`] leaves block files to the operating system have any to
+    /// flush, and only the block files of this store are flushed, not the rest of the disk. A
+    /// store that wrote too many block files to keep track of flushes every block file it holds.
+    /// Clones of the store share what is left to flush.
+    pub async fn sync(&self) -> StoreResult<()> {
+        let files = match self.unsynced.take() {
+            Some(files) => files.into_iter().collect::<Vec<_>>(),
+            None => list_store_files(&self.path).await?,
+        };
+
+        let mut dirs = HashSet::new();
+        for file in &files {
+            dirs.extend(
+                file.ancestors()
+                    .skip(1)
+                    .take_while(|dir| dir.starts_with(&self.path))
+                    .map(Path::to_path_buf),
+            );
+        }
+
+        flush_paths(files).await?;
+        flush_paths(dirs).await
+    }
+
+    /// Returns how new blocks are kept in memory, if they are.
+    pub fn get_memory_config(&self) -> Option<&MemoryStoreConfig> {
+        self.memory.as_ref().map(|memory| memory.get_config())
+    }
+
+    /// Writes the blocks kept in memory that are reachable from `root` to their files, and
+    /// stops keeping them in memory.
+    ///
+    /// Spilled blocks are written as any other new block would be, so they are compressed,
+    /// encrypted and written to the remote store as configured, and count the references of the
+    /// nodes among them. Blocks that are not reachable from `root` stay in memory only.
+    ///
+    /// Returns the number of blocks spilled.
+    pub async fn spill_memory(&self, root: &Cid) -> StoreResult<u64> {
+        let Some(memory) = &self.memory else {
+            return Ok(0);
+        };
+
+        let disk = Self {
+            memory: None,
+            ..self.clone()
+        };
+        let mut spilled = Vec::new();
+        for cid in super::collect_reachable(self, [*root]).await? {
+            let Some(bytes) = memory.get(&cid) else {
+                continue;
+            };
+            if disk.keep_block(&cid) {
+                memory.remove(&cid);
+                continue;
+            }
+
+            disk.write_new_block(&cid, &bytes).await?;
+            spilled.push((cid, bytes));
+        }
+
+        // References are counted once every spilled block has its file
+        for (cid, bytes) in &spilled {
+            let codec: Codec = cid.codec().try_into()?;
+            if codec == Codec::DagCbor {
+                let refs = DagCborCodec::links(bytes)
+                    .map_err(StoreError::custom)?
+                    .collect::<Vec<_>>();
+                disk.increment_reference_counts(refs.iter()).await?;
+            }
+            memory.remove(cid);
+        }
+
+        Ok(spilled.len() as u64)
+    }
+
+    /// Returns the data of the block with the given CID, whatever its codec.
+    pub(crate) async fn get_block_data(&self, cid: &Cid) -> StoreResult<Bytes> {
+        self.read_block(cid).await
+    }
+
+    /// Returns the path of the file holding the block with the given CID, if it is stored in a
+    /// file of its own
+    pub(crate) fn get_block_file(&self, cid: &Cid) -> Option<PathBuf> {
+        self.find_block(cid).map(|(block_path, _)| block_path)
+    }
+
+    /// Stores `bytes`, received from another store, as the block with the given CID.
+    ///
+    /// Fails if `bytes` do not hash to `cid`. The references of a node are counted as if it was
+    /// put with [`put_node`](IpldStore::put_node), so nodes should be stored after the blocks
+    /// they link to.
+    ///
+    /// Returns whether the block was new.
+    pub(crate) async fn put_block_data(&self, cid: &Cid, bytes: &[u8]) -> StoreResult<bool> {
+        let codec: Codec = cid.codec().try_into()?;
+        let hash_function = HashFunction::of_cid(cid).ok_or_else(|| {
+            StoreError::custom(FsError::UnsupportedHashFunction(format!(
+                "{:#x}",
+                cid.hash().code()
+            )))
+        })?;
+        if hash_function.generate_cid(codec, bytes) != *cid {
+            return Err(StoreError::custom(anyhow::anyhow!(
+                "block data does not match its CID: {}",
+                cid
+            )));
+        }
+
+        if self.keep_block(cid) {
+            return Ok(false);
+        }
+
+        self.write_new_block(cid, bytes).await?;
+        if codec == Codec::DagCbor {
+            let refs = DagCborCodec::links(bytes)
+                .map_err(StoreError::custom)?
+                .collect::<Vec<_>>();
+            self.increment_reference_counts(refs.iter()).await?;
+        }
+
+        Ok(true)
+    }
+
     /// Get the path for a given CID using the configured directory structure
     fn get_block_path(&self, cid: &Cid) -> PathBuf {
-        let digest = hex::encode(cid.hash().digest());
+        self.get_named_path(&hex::encode(cid.hash().digest()))
+    }
+
+    /// Get the path for a block file named `name`, without its extension, using the configured
+    /// directory structure
+    fn get_named_path(&self, name: &str) -> PathBuf {
         match self.dir_levels {
-            DirLevels::Zero => self.path.join(&digest),
+            DirLevels::Zero => self.path.join(name),
             DirLevels::One => {
-                let first = &digest[0..2];
-                self.path.join(first).join(&digest)
+                let first = &name[0..2];
+                self.path.join(first).join(name)
             }
             DirLevels::Two => {
-                let first = &digest[0..2];
-                let second = &digest[2..4];
-                self.path.join(first).join(second).join(&digest)
+                let first = &name[0..2];
+                let second = &name[2..4];
+                self.path.join(first).join(second).join(name)
+            }
+        }
+    }
+
+    /// Get the path of the file holding the block with the given CID in the given format
+    ///
+    /// Fails for an encrypted block if the store has no key to name it with.
+    fn get_block_path_in(&self, cid: &Cid, format: BlockFormat) -> StoreResult<PathBuf> {
+        Ok(self.get_named_path_in(&self.get_block_name_in(cid, format)?, format))
+    }
+
+    /// Get the path of the block file named `name`, without its extension, holding a block in the
+    /// given format
+    fn get_named_path_in(&self, name: &str, format: BlockFormat) -> PathBuf {
+        let block_path = self.get_named_path(name);
+        match format {
+            BlockFormat::Plain => block_path,
+            BlockFormat::Zstd => block_path.with_extension(COMPRESSED_BLOCK_EXTENSION),
+            BlockFormat::Encrypted => block_path.with_extension(ENCRYPTED_BLOCK_EXTENSION),
+        }
+    }
+
+    /// Returns the name, without its extension, of the file holding the block with the given CID
+    /// in the given format
+    ///
+    /// Plain and compressed blocks are named after the hex digest of their CID, and encrypted
+    /// blocks after a keyed hash of it. Fails for an encrypted block if the store has no key.
+    fn get_block_name_in(&self, cid: &Cid, format: BlockFormat) -> StoreResult<String> {
+        match format {
+            BlockFormat::Encrypted => self
+                .encryption
+                .as_ref()
+                .map(|key| key.get_block_name(cid.hash().digest()))
+                .ok_or_else(|| StoreError::custom(FsError::MissingEncryptionKey(*cid))),
+            _ => Ok(hex::encode(cid.hash().digest())),
+        }
+    }
+
+    /// Returns every name, without its extension, that the file holding the block with the given
+    /// CID can have in a format the store can read, the name of an encrypted file first.
+    ///
+    /// Packed blocks and the objects of remote stores are keyed by the same names.
+    pub(crate) fn get_block_names(&self, cid: &Cid) -> Vec<String> {
+        [BlockFormat::Encrypted, BlockFormat::Plain]
+            .into_iter()
+            .filter_map(|format| self.get_block_name_in(cid, format).ok())
+            .collect()
+    }
+
+    /// Returns a clone of the store whose block writes are part of a new batch, to be flushed with
+    /// [`flush_batch`](Self::flush_batch), if the store's durability batches writes and its
+    /// writes are not already part of a batch
+    fn start_batch(&self) -> Option<Self> {
+        (self.durability == Durability::Batch && self.batch.is_none()).then(|| Self {
+            batch: Some(Default::default()),
+            ..self.clone()
+        })
+    }
+
+    /// Flushes the block files written as part of the store's batch to disk, along with the
+    /// directories they are in
+    async fn flush_batch(&self) -> StoreResult<()> {
+        let Some(batch) = &self.batch else {
+            return Ok(());
+        };
+
+        let files = std::mem::take(&mut *batch.files.lock().expect("write batch lock poisoned"));
+        let dirs = files
+            .iter()
+            .filter_map(|file| file.parent().map(Path::to_path_buf))
+            .collect::<HashSet<_>>();
+        flush_paths(files).await?;
+        flush_paths(dirs).await
+    }
+
+    /// Flushes the block file just written to `file` at `block_path` to disk, as the store's
+    /// durability asks
+    async fn flush_block(&self, file: &mut File, block_path: &Path) -> StoreResult<()> {
+        match (self.durability, &self.batch) {
+            (Durability::Relaxed, _) => {
+                self.unsynced.insert(block_path);
+                Ok(())
+            }
+            (Durability::Batch, Some(batch)) => {
+                // The file is flushed through another handle once the batch is done
+                file.flush().await.map_err(StoreError::custom)?;
+                batch
+                    .files
+                    .lock()
+                    .expect("write batch lock poisoned")
+                    .push(block_path.to_path_buf());
+                Ok(())
+            }
+            _ => {
+                file.sync_all().await.map_err(StoreError::custom)?;
+                match block_path.parent() {
+                    Some(dir) => flush_paths([dir.to_path_buf()]).await,
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    /// Finds the file holding the block with the given CID, along with the block's format
+    ///
+    /// A block the index knows of is looked for in its indexed file first. Otherwise every file
+    /// the block could be in is looked for, and the index is updated with what is found.
+    fn find_block(&self, cid: &Cid) -> Option<(PathBuf, BlockFormat)> {
+        let Some(index) = &self.index else {
+            return [
+                BlockFormat::Plain,
+                BlockFormat::Zstd,
+                BlockFormat::Encrypted,
+            ]
+            .into_iter()
+            .filter_map(|format| Some((self.get_block_path_in(cid, format).ok()?, format)))
+            .find(|(block_path, _)| block_path.exists());
+        };
+
+        let names = self.get_block_names(cid);
+        for name in &names {
+            if let Some(block) = index.get(name) {
+                let block_path = self.get_named_path_in(name, block.format);
+                if block_path.exists() {
+                    return Some((block_path, block.format));
+                }
+            }
+        }
+
+        let found = [
+            BlockFormat::Plain,
+            BlockFormat::Zstd,
+            BlockFormat::Encrypted,
+        ]
+        .into_iter()
+        .find_map(|format| {
+            let name = self.get_block_name_in(cid, format).ok()?;
+            let block_path = self.get_named_path_in(&name, format);
+            let size = std::fs::metadata(&block_path).ok()?.len();
+            Some((name, block_path, IndexedBlock { format, size }))
+        });
+        match found {
+            Some((name, block_path, block)) => {
+                index.insert(&name, block);
+                Some((block_path, block.format))
+            }
+            None => {
+                names.iter().for_each(|name| index.remove(name));
+                None
+            }
+        }
+    }
+
+    /// Finds the packed block with the given CID, refreshing the pack index if the block is not in
+    /// it and packs were added since it was last read
+    fn find_packed_block(&self, cid: &Cid) -> StoreResult<Option<PackedBlock>> {
+        let names = self.get_block_names(cid);
+        let packs_dir = self.path.join(PACKS_SUBDIR);
+        {
+            let packs = self.packs.read().expect("pack index lock poisoned");
+            if let Some(block) = names.iter().find_map(|name| packs.get(name)) {
+                return Ok(Some(block.clone()));
+            }
+            if !packs.is_stale(&packs_dir) {
+                return Ok(None);
+            }
+        }
+
+        let mut packs = self.packs.write().expect("pack index lock poisoned");
+        packs.refresh(&packs_dir)?;
+        Ok(names.iter().find_map(|name| packs.get(name)).cloned())
+    }
+
+    /// Returns whether the block with the given CID is stored locally, either in memory, in its
+    /// own file or packed
+    ///
+    /// With an index, a block it does not know of is taken not to have a file, without looking
+    /// for one.
+    fn has_block(&self, cid: &Cid) -> bool {
+        let has_file = match &self.index {
+            Some(index) => {
+                let names = self.get_block_names(cid);
+                names.iter().any(|name| index.contains(name)) && self.find_block(cid).is_some()
+            }
+            None => self.find_block(cid).is_some(),
+        };
+
+        matches!(&self.memory, Some(memory) if memory.contains(cid))
+            || has_file
+            || matches!(self.find_packed_block(cid), Ok(Some(_)))
+    }
+
+    /// Returns whether the block with the given CID is stored locally, like
+    /// [`has_block`](Self::has_block), for a block about to be put again
+    ///
+    /// The file of the block is marked as just written, so that a garbage collection that started
+    /// before the block was put again keeps it. A file that is gone by the time it is marked, as
+    /// a collection removed it meanwhile, is taken not to hold the block, so that it is written
+    /// anew.
+    fn keep_block(&self, cid: &Cid) -> bool {
+        self.has_block(cid) && self.touch_block(cid)
+    }
+
+    /// Marks the file of the block with the given CID, which was found to be stored locally, as
+    /// just written, and returns whether the block is still stored
+    ///
+    /// A block without a file is only still stored if it is in memory or packed. A file that was
+    /// found but is gone now may have been moved aside by a collection about to remove it, so the
+    /// block is then taken not to be stored.
+    fn touch_block(&self, cid: &Cid) -> bool {
+        match self.find_block(cid) {
+            Some((block_path, _)) => touch_file(&block_path).is_ok(),
+            None => {
+                matches!(&self.memory, Some(memory) if memory.contains(cid))
+                    || matches!(self.find_packed_block(cid), Ok(Some(_)))
+            }
+        }
+    }
+
+    /// Reads the data of the block with the given CID, decrypting and decompressing it if needed
+    async fn read_block(&self, cid: &Cid) -> StoreResult<Bytes> {
+        if let Some(bytes) = self.memory.as_ref().and_then(|memory| memory.get(cid)) {
+            return Ok(bytes);
+        }
+
+        if let Some((block_path, format)) = self.find_block(cid) {
+            if let Ok(mut file) = File::open(&block_path).await {
+                if let Some(cache) = &self.cache {
+                    cache.touch(&self.path, &block_path);
+                }
+                if !self.is_repairable() {
+                    return self.read_block_data(&mut file, cid, format).await;
+                }
+
+                let data = self.read_stored_data(&mut file).await?;
+                return self.check_block_data(cid, format, data).await;
+            }
+        }
+
+        // The block may also have been packed or evicted since its file was found
+        if let Some(block) = self.find_packed_block(cid)? {
+            let data = block.read().await?;
+            if !self.is_repairable() {
+                return Ok(self
+                    .decode_block_data(cid, block.get_format(), data)?
+                    .into());
+            }
+
+            return self.check_block_data(cid, block.get_format(), data).await;
+        }
+
+        match self.fetch_remote_block(cid).await {
+            Err(StoreError::BlockNotFound(_)) if self.is_repairable() => {
+                tracing::warn!("block {} is missing, repairing it", cid);
+                self.repair_block(cid)
+                    .await?
+                    .ok_or(StoreError::BlockNotFound(*cid))
+            }
+            result => result,
+        }
+    }
+
+    /// Decodes the data of the block with the given CID, stored in `format`, and checks it against
+    /// the CID, repairing the block from a mirror or replica if it does not match or cannot be
+    /// decoded
+    async fn check_block_data(
+        &self,
+        cid: &Cid,
+        format: BlockFormat,
+        data: Vec<u8>,
+    ) -> StoreResult<Bytes> {
+        // Without the key, encrypted blocks cannot be told apart from corrupt ones
+        if format == BlockFormat::Encrypted && self.encryption.is_none() {
+            return Ok(self.decode_block_data(cid, format, data)?.into());
+        }
+
+        if let Ok(bytes) = self.decode_block_data(cid, format, data) {
+            if is_block_data_valid(cid, &bytes) {
+                return Ok(bytes.into());
+            }
+        }
+
+        tracing::warn!("block {} is corrupt, repairing it", cid);
+        self.repair_block(cid).await?.ok_or_else(|| {
+            StoreError::custom(anyhow::anyhow!(
+                "block {} is corrupt and no mirror or replica holds a good copy of it",
+                cid
+            ))
+        })
+    }
+
+    /// Returns whether corrupt and missing blocks can be repaired, from mirrors or replicas
+    fn is_repairable(&self) -> bool {
+        !self.mirrors.is_empty() || !self.replicas.is_empty()
+    }
+
+    /// Fetches a good copy of the block with the given CID from the first mirror or replica
+    /// holding one, and writes it to its file in place of any corrupt one.
+    ///
+    /// Returns the decoded data of the block, or `None` if no mirror or replica holds a good
+    /// copy.
+    async fn repair_block(&self, cid: &Cid) -> StoreResult<Option<Bytes>> {
+        let is_good = |format, data: &[u8]| {
+            matches!(
+                self.decode_block_data(cid, format, data.to_vec()),
+                Ok(bytes) if is_block_data_valid(cid, &bytes)
+            )
+        };
+        let mut repaired = None;
+        for name in self.get_block_names(cid) {
+            repaired = self.fetch_good_copy(&name, is_good).await;
+            if repaired.is_some() {
+                break;
+            }
+        }
+        let Some((format, data, refcount)) = repaired else {
+            return Ok(None);
+        };
+
+        self.replace_block_file(cid, format, &data, refcount)
+            .await?;
+        tracing::info!("repaired block {}", cid);
+        Ok(Some(self.decode_block_data(cid, format, data)?.into()))
+    }
+
+    /// Replaces the corrupt block file at `block_path` with a good copy from the first mirror or
+    /// replica holding one.
+    ///
+    /// Returns `false`, leaving the file as it is, if no mirror or replica holds a good copy.
+    pub(crate) async fn repair_block_file(&self, block_path: &Path) -> StoreResult<bool> {
+        let file_name = block_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let name = get_block_name(&file_name).ok_or_else(|| {
+            StoreError::custom(anyhow::anyhow!(
+                "not a block file: {}",
+                block_path.display()
+            ))
+        })?;
+
+        let repaired = self
+            .fetch_good_copy(name, |format, data| {
+                matches!(
+                    self.verify_block_data(name, format, data.to_vec()),
+                    Ok(true)
+                )
+            })
+            .await;
+        let Some((format, data, refcount)) = repaired else {
+            return Ok(false);
+        };
+
+        let cid = self
+            .get_stored_cid(name, format, &data)?
+            .ok_or_else(|| StoreError::custom(anyhow::anyhow!("invalid block {}", name)))?;
+        self.replace_block_file(&cid, format, &data, refcount)
+            .await?;
+        tracing::info!("repaired block {}", name);
+        Ok(true)
+    }
+
+    /// Returns the format and data of the block stored under `name` in the first mirror, or else
+    /// the first replica, holding a copy of it that `is_good` accepts, if any, along with the
+    /// reference count of a mirror's copy
+    async fn fetch_good_copy(
+        &self,
+        name: &str,
+        is_good: impl Fn(BlockFormat, &[u8]) -> bool,
+    ) -> Option<(BlockFormat, Vec<u8>, Option<u64>)> {
+        if let Some((format, data, refcount)) = self.read_mirror_copy(name, &is_good).await {
+            return Some((format, data, Some(refcount)));
+        }
+
+        self.fetch_replica_object(name, is_good)
+            .await
+            .map(|(format, data)| (format, data, None))
+    }
+
+    /// Returns the format, data and reference count of the block file stored under `name` in the
+    /// first mirror holding a copy of it that `is_good` accepts, if any
+    async fn read_mirror_copy(
+        &self,
+        name: &str,
+        is_good: &impl Fn(BlockFormat, &[u8]) -> bool,
+    ) -> Option<(BlockFormat, Vec<u8>, u64)> {
+        for format in [
+            BlockFormat::Plain,
+            BlockFormat::Zstd,
+            BlockFormat::Encrypted,
+        ] {
+            for mirror_path in self.get_mirror_paths(&self.get_named_path_in(name, format)) {
+                let mut file = match File::open(&mirror_path).await {
+                    Ok(file) => file,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                    Err(e) => {
+                        tracing::warn!(error = %e, "failed to open {}", mirror_path.display());
+                        continue;
+                    }
+                };
+
+                let refcount = match self.enable_refcount {
+                    true => self.read_refcount(&mut file).await.unwrap_or_default(),
+                    false => 0,
+                };
+                match self.read_stored_data(&mut file).await {
+                    Ok(data) if is_good(format, &data) => return Some((format, data, refcount)),
+                    _ => tracing::warn!(
+                        "{} is a corrupt copy of block {}",
+                        mirror_path.display(),
+                        name
+                    ),
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Returns the format and data of the block stored under `name` in the first replica holding
+    /// a copy of it that `is_good` accepts, if any
+    async fn fetch_replica_object(
+        &self,
+        name: &str,
+        is_good: impl Fn(BlockFormat, &[u8]) -> bool,
+    ) -> Option<(BlockFormat, Vec<u8>)> {
+        for replica in &self.replicas {
+            let object = match replica.get(name).await {
+                Ok(Some(object)) => object,
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::warn!(error = %e, "failed to fetch block {} from {:?}", name, replica);
+                    continue;
+                }
+            };
+
+            match decode_remote_object(&object) {
+                Ok((format, data)) if is_good(format, data) => {
+                    return Some((format, data.to_vec()))
+                }
+                _ => tracing::warn!(
+                    "replica {:?} holds a corrupt copy of block {}",
+                    replica,
+                    name
+                ),
+            }
+        }
+
+        None
+    }
+
+    /// Writes `data`, stored in `format`, to the file of the block with the given CID, in place of
+    /// any file the block already has, whose reference count is kept. A block without a file
+    /// gets the reference count `refcount`, or zero.
+    async fn replace_block_file(
+        &self,
+        cid: &Cid,
+        format: BlockFormat,
+        data: &[u8],
+        refcount: Option<u64>,
+    ) -> StoreResult<()> {
+        let mut refcount = refcount.unwrap_or_default();
+        if let Some((block_path, _)) = self.find_block(cid) {
+            if self.enable_refcount {
+                if let Ok(mut file) = File::open(&block_path).await {
+                    refcount = self.read_refcount(&mut file).await.unwrap_or_default();
+                }
             }
+            self.remove_block(&block_path).await?;
+        }
+
+        let (block_path, size) = self
+            .write_fetched_block(cid, format, data, refcount)
+            .await?;
+        if let Some(cache) = &self.cache {
+            cache.insert(&self.path, &block_path, size, false);
+            self.evict_blocks().await?;
         }
+
+        Ok(())
+    }
+
+    /// Fetches the block with the given CID from the remote store into its file, and returns its
+    /// decoded data
+    async fn fetch_remote_block(&self, cid: &Cid) -> StoreResult<Bytes> {
+        let cache = self.cache.as_ref().ok_or(StoreError::BlockNotFound(*cid))?;
+        let mut object = None;
+        for name in self.get_block_names(cid) {
+            object = cache.get_remote().get(&name).await?;
+            if object.is_some() {
+                break;
+            }
+        }
+        let object = object.ok_or(StoreError::BlockNotFound(*cid))?;
+        let (format, data) = decode_remote_object(&object)?;
+        let bytes = self.decode_block_data(cid, format, data.to_vec())?;
+
+        let (block_path, size) = self.write_fetched_block(cid, format, data, 0).await?;
+        cache.insert(&self.path, &block_path, size, true);
+        self.evict_blocks().await?;
+
+        Ok(bytes.into())
+    }
+
+    /// Writes `data`, stored in `format` and fetched from another store, to the file of the block
+    /// with the given CID, with the reference count `refcount`
+    ///
+    /// Returns the path and size of the file.
+    async fn write_fetched_block(
+        &self,
+        cid: &Cid,
+        format: BlockFormat,
+        data: &[u8],
+        refcount: u64,
+    ) -> StoreResult<(PathBuf, u64)> {
+        // Write the file under another name first, as other readers may open it any time
+        let name = self.get_block_name_in(cid, format)?;
+        let block_path = self.get_named_path_in(&name, format);
+        self.ensure_directories(&block_path).await?;
+        let temp_path = block_path.with_extension(format!(
+            "fetch-{}-{}",
+            std::process::id(),
+            FETCH_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut file_data = Vec::with_capacity(8 + data.len());
+        if self.enable_refcount {
+            file_data.extend_from_slice(&refcount.to_be_bytes());
+        }
+        file_data.extend_from_slice(data);
+        fs::write(&temp_path, &file_data)
+            .await
+            .map_err(StoreError::custom)?;
+        fs::rename(&temp_path, &block_path)
+            .await
+            .map_err(StoreError::custom)?;
+
+        self.copy_to_mirrors(&block_path).await;
+
+        let size = file_data.len() as u64;
+        if let Some(index) = &self.index {
+            index.insert(&name, IndexedBlock { format, size });
+        }
+        if let Some(usage) = &self.usage {
+            usage.fetch_add(size, Ordering::Relaxed);
+        }
+
+        Ok((block_path, size))
+    }
+
+    /// Removes the least recently used block files until the rest fit in the cache, uploading
+    /// the blocks the remote store does not have yet first
+    async fn evict_blocks(&self) -> StoreResult<()> {
+        let Some(cache) = &self.cache else {
+            return Ok(());
+        };
+
+        for evicted in cache.take_evictions(&self.path) {
+            let file_name = evicted
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let Some(name) = get_block_name(&file_name) else {
+                continue;
+            };
+
+            // The file may have been removed by garbage collection or another store
+            let mut file = match File::open(&evicted.path).await {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(StoreError::custom(e)),
+            };
+
+            if !evicted.uploaded && !cache.get_remote().has(name).await? {
+                let data = self.read_stored_data(&mut file).await?;
+                let format = get_block_format(&evicted.path);
+                cache
+                    .get_remote()
+                    .put(name, encode_remote_object(format, &data))
+                    .await?;
+            }
+
+            drop(file);
+            self.remove_block(&evicted.path).await?;
+        }
+
+        Ok(())
     }
 
     /// Ensure the parent directories exist for a given block path
@@ -255,8 +1309,20 @@ where
         Ok(())
     }
 
-    /// Reads the block data from a file (skipping the refcount if enabled)
-    async fn read_block_data(&self, file: &mut File) -> StoreResult<Bytes> {
+    /// Reads the data of the block with the given CID from a file (skipping the refcount if
+    /// enabled), decrypting and decompressing it if needed
+    async fn read_block_data(
+        &self,
+        file: &mut File,
+        cid: &Cid,
+        format: BlockFormat,
+    ) -> StoreResult<Bytes> {
+        let data = self.read_stored_data(file).await?;
+        Ok(self.decode_block_data(cid, format, data)?.into())
+    }
+
+    /// Reads the data stored in a block file (skipping the refcount if enabled), as it is encoded
+    async fn read_stored_data(&self, file: &mut File) -> StoreResult<Vec<u8>> {
         if self.enable_refcount {
             file.seek(SeekFrom::Start(8))
                 .await
@@ -270,13 +1336,272 @@ where
         file.read_to_end(&mut data)
             .await
             .map_err(StoreError::custom)?;
-        Ok(data.into())
+
+        Ok(data)
+    }
+
+    /// Checks that the data held by the block file at `block_path` hashes to the digest the file
+    /// is named after.
+    ///
+    /// Returns `false` if the data does not match or cannot be decoded. Fails if the file cannot
+    /// be read, or if it is encrypted and the store has no key to decrypt it with.
+    pub(crate) async fn verify_block_file(&self, block_path: &Path) -> StoreResult<bool> {
+        let file_name = block_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let name = get_block_name(&file_name).ok_or_else(|| {
+            StoreError::custom(anyhow::anyhow!(
+                "not a block file: {}",
+                block_path.display()
+            ))
+        })?;
+
+        let mut file = File::open(block_path).await.map_err(StoreError::custom)?;
+        let data = self.read_stored_data(&mut file).await?;
+        self.verify_block_data(name, get_block_format(block_path), data)
+    }
+
+    /// Checks that the data of every packed block hashes to the digest the name it is indexed
+    /// under was made from.
+    ///
+    /// Returns the name and packfile of every block that does not match or cannot be decoded,
+    /// along with how many blocks were checked. Fails like
+    /// [`verify_block_file`](Self::verify_block_file).
+    pub(crate) async fn verify_packed_blocks(&self) -> StoreResult<(Vec<(String, PathBuf)>, u64)> {
+        let blocks = {
+            let mut packs = self.packs.write().expect("pack index lock poisoned");
+            packs.refresh(&self.path.join(PACKS_SUBDIR))?;
+            packs.get_blocks().clone()
+        };
+
+        let mut corrupt = Vec::new();
+        for (name, block) in &blocks {
+            let data = block.read().await?;
+            if !self.verify_block_data(name, block.get_format(), data)? {
+                corrupt.push((name.clone(), block.get_pack().to_path_buf()));
+            }
+        }
+
+        Ok((corrupt, blocks.len() as u64))
+    }
+
+    /// Checks that `data`, stored in `format` in a file named `name`, decodes to data that hashes
+    /// to the digest the name was made from, with any supported hash function.
+    fn verify_block_data(
+        &self,
+        name: &str,
+        format: BlockFormat,
+        data: Vec<u8>,
+    ) -> StoreResult<bool> {
+        let Some(cid) = self.get_stored_cid(name, format, &data)? else {
+            return Ok(false);
+        };
+
+        // The hash function of a plain or compressed block is not part of its file name
+        Ok(match self.decode_block_data(&cid, format, data) {
+            Ok(decoded) => HashFunction::ALL.iter().any(|hash_function| {
+                hash_function.get_code().digest(&decoded).digest() == cid.hash().digest()
+            }),
+            Err(_) => false,
+        })
+    }
+
+    /// Returns the CID of the block stored as `data`, in `format`, in a file named `name`, or
+    /// `None` if the data is not that of a block with that name.
+    ///
+    /// Only the digest of a CID is part of the name of a plain or compressed block's file, so it
+    /// is returned in a CID with the raw codec and the default hash function, which locates the
+    /// same file. Encrypted blocks hold their CID, which is decrypted. Fails for them if the store
+    /// has no key.
+    fn get_stored_cid(
+        &self,
+        name: &str,
+        format: BlockFormat,
+        data: &[u8],
+    ) -> StoreResult<Option<Cid>> {
+        if format != BlockFormat::Encrypted {
+            let hash = HashFunction::default()
+                .get_code()
+                .wrap(&hex::decode(name).map_err(StoreError::custom)?)
+                .map_err(StoreError::custom)?;
+            return Ok(Some(Cid::new_v1(Codec::Raw.into(), hash)));
+        }
+
+        let key = self.encryption.as_ref().ok_or_else(|| {
+            StoreError::custom(anyhow::anyhow!(
+                "block {} is encrypted and no encryption key was given",
+                name
+            ))
+        })?;
+        Ok(open_block(key, name, data)
+            .map(|(cid, _, _)| cid)
+            .filter(|cid| key.get_block_name(cid.hash().digest()) == name))
+    }
+
+    /// Moves the blocks held by the files at `block_paths` into packfiles, starting a new pack
+    /// once one holds `max_pack_size` bytes.
+    ///
+    /// Block files are only removed once the pack holding them is durable, so the blocks stay
+    /// readable throughout. Files that no longer exist are skipped.
+    ///
+    /// Returns how many packs were written and how many blocks were moved into them.
+    pub(crate) async fn pack_block_files(
+        &self,
+        block_paths: &[PathBuf],
+        max_pack_size: u64,
+    ) -> StoreResult<(u64, u64)> {
+        let packs_dir = self.path.join(PACKS_SUBDIR);
+        let mut writer = PackWriter::create(&packs_dir).await?;
+        let mut packed = Vec::new();
+        let (mut pack_count, mut block_count) = (0, 0);
+
+        for block_path in block_paths {
+            let file_name = block_path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let Some(name) = get_block_name(&file_name) else {
+                continue;
+            };
+
+            let mut file = match File::open(block_path).await {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(StoreError::custom(e)),
+            };
+            let data = self.read_stored_data(&mut file).await?;
+            writer
+                .add(name, get_block_format(block_path), &data)
+                .await?;
+            packed.push(block_path);
+
+            if writer.len() >= max_pack_size {
+                self.copy_pack_to_mirrors(&writer.finish().await?).await;
+                pack_count += 1;
+                for block_path in packed.drain(..) {
+                    self.remove_block(block_path).await?;
+                    block_count += 1;
+                }
+                writer = PackWriter::create(&packs_dir).await?;
+            }
+        }
+
+        if writer.is_empty() {
+            writer.discard().await?;
+        } else {
+            self.copy_pack_to_mirrors(&writer.finish().await?).await;
+            pack_count += 1;
+            for block_path in packed {
+                self.remove_block(block_path).await?;
+                block_count += 1;
+            }
+        }
+
+        Ok((pack_count, block_count))
+    }
+
+    /// Decodes the data of the block with the given CID stored in the given format
+    fn decode_block_data(
+        &self,
+        cid: &Cid,
+        format: BlockFormat,
+        data: Vec<u8>,
+    ) -> StoreResult<Vec<u8>> {
+        match format {
+            BlockFormat::Plain => Ok(data),
+            BlockFormat::Zstd => {
+                zstd::stream::decode_all(data.as_slice()).map_err(StoreError::custom)
+            }
+            BlockFormat::Encrypted => {
+                let key = self
+                    .encryption
+                    .as_ref()
+                    .ok_or_else(|| StoreError::custom(FsError::MissingEncryptionKey(*cid)))?;
+                let name = key.get_block_name(cid.hash().digest());
+                let (format, data) = open_block(key, &name, &data)
+                    .filter(|(stored_cid, _, _)| stored_cid == cid)
+                    .map(|(_, format, data)| (format, data))
+                    .ok_or_else(|| StoreError::custom(FsError::BlockDecryptionFailed(*cid)))?;
+                self.decode_block_data(cid, format, data)
+            }
+        }
+    }
+
+    /// Encodes the data of the block with the given CID in the format new blocks are written in,
+    /// compressing it if enabled and worthwhile, and encrypting it if enabled
+    fn encode_block_data<'a>(
+        &self,
+        cid: &Cid,
+        bytes: &'a [u8],
+    ) -> StoreResult<(BlockFormat, Cow<'a, [u8]>)> {
+        let (format, data) = self.compress_block_data(bytes)?;
+        match &self.encryption {
+            Some(key) => Ok((
+                BlockFormat::Encrypted,
+                Cow::Owned(seal_block(key, cid, format, &data)?),
+            )),
+            None => Ok((format, data)),
+        }
+    }
+
+    /// Compresses block data if enabled and worthwhile
+    fn compress_block_data<'a>(
+        &self,
+        bytes: &'a [u8],
+    ) -> StoreResult<(BlockFormat, Cow<'a, [u8]>)> {
+        if !self.compression.get_enabled() {
+            return Ok((BlockFormat::Plain, Cow::Borrowed(bytes)));
+        }
+
+        let compressed = zstd::bulk::compress(bytes, *self.compression.get_level())
+            .map_err(StoreError::custom)?;
+
+        // Data that is already compressed, like images and archives, is not worth decompressing
+        if compressed.len() * 100 > bytes.len() * (100 - MIN_COMPRESSION_SAVINGS_PERCENT) {
+            return Ok((BlockFormat::Plain, Cow::Borrowed(bytes)));
+        }
+
+        Ok((BlockFormat::Zstd, Cow::Owned(compressed)))
     }
 
-    /// Writes a new block with initial refcount
-    async fn write_new_block(&self, block_path: &PathBuf, bytes: &[u8]) -> StoreResult<()> {
-        self.ensure_directories(block_path).await?;
-        let mut file = File::create(block_path).await.map_err(StoreError::custom)?;
+    /// Writes a new block with initial refcount, or keeps it in memory if new blocks are
+    async fn write_new_block(&self, cid: &Cid, bytes: &[u8]) -> StoreResult<()> {
+        if let Some(memory) = &self.memory {
+            return memory
+                .insert(*cid, Bytes::copy_from_slice(bytes))
+                .map_err(StoreError::custom);
+        }
+
+        let (format, bytes) = self.encode_block_data(cid, bytes)?;
+        let name = self.get_block_name_in(cid, format)?;
+        let block_path = self.get_named_path_in(&name, format);
+        self.ensure_directories(&block_path).await?;
+        let mut file = match &self.index {
+            // The index does not know of the files other stores wrote since it was loaded, which
+            // are kept as they are, reference count included
+            Some(index) => match File::options()
+                .write(true)
+                .create_new(true)
+                .open(&block_path)
+                .await
+            {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    touch_file(&block_path).map_err(StoreError::custom)?;
+                    let size = fs::metadata(&block_path)
+                        .await
+                        .map_err(StoreError::custom)?
+                        .len();
+                    index.insert(&name, IndexedBlock { format, size });
+                    return Ok(());
+                }
+                Err(e) => return Err(StoreError::custom(e)),
+            },
+            None => File::create(&block_path)
+                .await
+                .map_err(StoreError::custom)?,
+        };
 
         if self.enable_refcount {
             // Write initial refcount (0)
@@ -286,32 +1611,332 @@ where
         }
 
         // Write block data
-        file.write_all(bytes).await.map_err(StoreError::custom)?;
+        file.write_all(&bytes).await.map_err(StoreError::custom)?;
+        self.flush_block(&mut file, &block_path).await?;
+        if !self.mirrors.is_empty() {
+            // The copies are read from the file, through another handle
+            file.flush().await.map_err(StoreError::custom)?;
+            self.copy_to_mirrors(&block_path).await;
+        }
+
+        let refcount_size = if self.enable_refcount {
+            std::mem::size_of::<u64>() as u64
+        } else {
+            0
+        };
+        if let Some(index) = &self.index {
+            let size = refcount_size + bytes.len() as u64;
+            index.insert(&name, IndexedBlock { format, size });
+        }
+        if let Some(usage) = &self.usage {
+            usage.fetch_add(refcount_size + bytes.len() as u64, Ordering::Relaxed);
+        }
+
+        // Write the block through to the remote store. If that fails, the block is uploaded
+        // before its file is evicted instead.
+        if let Some(cache) = &self.cache {
+            let uploaded = cache
+                .get_remote()
+                .put(&name, encode_remote_object(format, &bytes))
+                .await;
+            let size = refcount_size + bytes.len() as u64;
+            cache.insert(&self.path, &block_path, size, uploaded.is_ok());
+            uploaded?;
+            self.evict_blocks().await?;
+        }
+
         Ok(())
     }
 
+    /// Removes the block file at `block_path`.
+    pub(crate) async fn remove_block(&self, block_path: &Path) -> StoreResult<()> {
+        let size = fs::metadata(block_path)
+            .await
+            .map_err(StoreError::custom)?
+            .len();
+        fs::remove_file(block_path)
+            .await
+            .map_err(StoreError::custom)?;
+        self.remove_mirror_copies(block_path).await;
+
+        if let (Some(index), Some(file_name)) = (&self.index, block_path.file_name()) {
+            index.remove_file(&file_name.to_string_lossy());
+        }
+        if let Some(usage) = &self.usage {
+            // Saturate, as the counter may not have included the block
+            let _ = usage.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                Some(used.saturating_sub(size))
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Moves the block file at `block_path` into `quarantine_dir`, out of the store, so the
+    /// block reads as missing instead of as corrupt data.
+    ///
+    /// Returns the path the file was moved to.
+    pub(crate) async fn quarantine_block(
+        &self,
+        block_path: &Path,
+        quarantine_dir: &Path,
+    ) -> StoreResult<PathBuf> {
+        let file_name = block_path.file_name().ok_or_else(|| {
+            StoreError::custom(anyhow::anyhow!(
+                "not a block file: {}",
+                block_path.display()
+            ))
+        })?;
+        let size = fs::metadata(block_path)
+            .await
+            .map_err(StoreError::custom)?
+            .len();
+
+        let quarantine_path = quarantine_dir.join(file_name);
+        fs::create_dir_all(quarantine_dir)
+            .await
+            .map_err(StoreError::custom)?;
+        fs::rename(block_path, &quarantine_path)
+            .await
+            .map_err(StoreError::custom)?;
+
+        if let Some(index) = &self.index {
+            index.remove_file(&file_name.to_string_lossy());
+        }
+        if let Some(usage) = &self.usage {
+            let _ = usage.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                Some(used.saturating_sub(size))
+            });
+        }
+
+        Ok(quarantine_path)
+    }
+
+    /// Returns the paths of the copies of the file at `path`, in the store's directory, in each
+    /// of the store's mirrors.
+    pub(crate) fn get_mirror_paths(&self, path: &Path) -> Vec<PathBuf> {
+        let Ok(relative) = path.strip_prefix(&self.path) else {
+            return Vec::new();
+        };
+
+        self.mirrors
+            .iter()
+            .map(|mirror| mirror.join(relative))
+            .collect()
+    }
+
+    /// Copies the file at `path`, in the store's directory, to each of the store's mirrors.
+    ///
+    /// A mirror the file cannot be copied to is logged and left behind, as the file itself was
+    /// written.
+    pub(crate) async fn copy_to_mirrors(&self, path: &Path) {
+        for mirror_path in self.get_mirror_paths(path) {
+            if let Err(e) = self.copy_to_mirror(path, &mirror_path).await {
+                tracing::error!(
+                    error = %e,
+                    "failed to copy {} to {}",
+                    path.display(),
+                    mirror_path.display()
+                );
+            }
+        }
+    }
+
+    /// Copies the packfile at `pack_path` and its index to each of the store's mirrors, the index
+    /// last, as stores only read packs that have one.
+    async fn copy_pack_to_mirrors(&self, pack_path: &Path) {
+        self.copy_to_mirrors(pack_path).await;
+        self.copy_to_mirrors(&pack_path.with_extension(PACK_INDEX_EXTENSION))
+            .await;
+    }
+
+    /// Copies the file at `path` to `mirror_path`, in place of any file there, and flushes the
+    /// copy to disk as the store's durability asks.
+    async fn copy_to_mirror(&self, path: &Path, mirror_path: &Path) -> StoreResult<()> {
+        // Copy the file under another name first, as other readers may open it any time
+        if let Some(parent) = mirror_path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(StoreError::custom)?;
+        }
+        let temp_path = mirror_path.with_extension(format!(
+            "mirror-{}-{}",
+            std::process::id(),
+            FETCH_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::copy(path, &temp_path)
+            .await
+            .map_err(StoreError::custom)?;
+        fs::rename(&temp_path, mirror_path)
+            .await
+            .map_err(StoreError::custom)?;
+
+        match (self.durability, &self.batch) {
+            (Durability::Relaxed, _) => Ok(()),
+            (Durability::Batch, Some(batch)) => {
+                batch
+                    .files
+                    .lock()
+                    .expect("write batch lock poisoned")
+                    .push(mirror_path.to_path_buf());
+                Ok(())
+            }
+            _ => {
+                let dir = mirror_path.parent().map(Path::to_path_buf);
+                flush_paths(std::iter::once(mirror_path.to_path_buf()).chain(dir)).await
+            }
+        }
+    }
+
+    /// Removes the copies of the file at `path`, in the store's directory, from the store's
+    /// mirrors.
+    ///
+    /// Copies that are already gone are skipped, and those that cannot be removed are logged
+    /// and left behind for garbage collection.
+    pub(crate) async fn remove_mirror_copies(&self, path: &Path) {
+        for mirror_path in self.get_mirror_paths(path) {
+            match fs::remove_file(&mirror_path).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    tracing::error!(error = %e, "failed to remove {}", mirror_path.display())
+                }
+            }
+        }
+    }
+
+    /// Updates the reference count in the copies of the block file at `block_path` in the
+    /// store's mirrors
+    async fn write_mirror_refcounts(&self, block_path: &Path, refcount: u64) {
+        for mirror_path in self.get_mirror_paths(block_path) {
+            let written = async {
+                let mut file = File::options()
+                    .write(true)
+                    .open(&mirror_path)
+                    .await
+                    .map_err(StoreError::custom)?;
+                self.write_refcount(&mut file, refcount).await
+            }
+            .await;
+            if let Err(e) = written {
+                tracing::error!(
+                    error = %e,
+                    "failed to update the reference count of {}",
+                    mirror_path.display()
+                );
+            }
+        }
+    }
+
     /// Increments reference counts for the given CIDs
     async fn increment_reference_counts(
         &self,
         cids: impl Iterator<Item = &Cid>,
     ) -> StoreResult<()> {
-        if !self.enable_refcount {
+        // Blocks kept in memory count their references once they are spilled
+        if !self.enable_refcount || self.memory.is_some() {
             return Ok(());
         }
 
-        for cid in cids {
-            let block_path = self.get_block_path(cid);
-            if let Ok(mut file) = File::options()
-                .read(true)
-                .write(true)
-                .open(&block_path)
-                .await
-            {
-                let refcount = self.read_refcount(&mut file).await?;
-                self.write_refcount(&mut file, refcount + 1).await?;
-            }
+        for cid in cids {
+            let Some((block_path, _)) = self.find_block(cid) else {
+                continue;
+            };
+
+            if let Ok(mut file) = File::options()
+                .read(true)
+                .write(true)
+                .open(&block_path)
+                .await
+            {
+                let refcount = self.read_refcount(&mut file).await?;
+                self.write_refcount(&mut file, refcount + 1).await?;
+                self.write_mirror_refcounts(&block_path, refcount + 1).await;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<L> FlatFsStoreImpl<FastCDCChunker, L>
+where
+    L: Layout + Default,
+{
+    /// Creates a new `FlatFsStore` with the given root path that splits contents into chunks as
+    /// described by `config`. Reference counting is enabled by default.
+    pub fn with_chunker_config(path: impl Into<PathBuf>, config: &ChunkerConfig) -> Self {
+        Self {
+            path: path.into(),
+            dir_levels: DirLevels::default(),
+            chunker: Arc::new(config.to_chunker()),
+            layout: Default::default(),
+            enable_refcount: true,
+            compression: CompressionConfig::disabled(),
+            encryption: None,
+            usage: None,
+            packs: Default::default(),
+            cache: None,
+            replicas: Vec::new(),
+            mirrors: Vec::new(),
+            memory: None,
+            index: None,
+            durability: Durability::default(),
+            hash_function: HashFunction::default(),
+            batch: None,
+            unsynced: Default::default(),
+        }
+    }
+}
+
+impl UnsyncedBlocks {
+    /// Records that the block file at `path` was written without being flushed.
+    fn insert(&self, path: &Path) {
+        if self.overflowed.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let mut files = self.files.lock().expect("unsynced blocks lock poisoned");
+        files.insert(path.to_path_buf());
+        if files.len() > MAX_UNSYNCED_BLOCKS {
+            files.clear();
+            self.overflowed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the block files written without being flushed and forgets them, or `None` if there
+    /// were too many of them to keep track of.
+    fn take(&self) -> Option<HashSet<PathBuf>> {
+        let mut files = self.files.lock().expect("unsynced blocks lock poisoned");
+        let files = std::mem::take(&mut *files);
+        (!self.overflowed.swap(false, Ordering::Relaxed)).then_some(files)
+    }
+}
+
+impl<C, L> FlatFsStoreImpl<C, L>
+where
+    C: Chunker + Default + Clone + Send + Sync,
+    L: Layout + Default + Clone + Send + Sync,
+{
+    /// Stores each of `blocks` as a raw block, as [`put_raw_block`](RawStore::put_raw_block)
+    /// does, as a single batch.
+    ///
+    /// With [`Durability::Batch`], the new block files are flushed to disk together once all of
+    /// them are written, instead of one by one.
+    ///
+    /// Returns the CIDs of the blocks, in order.
+    pub async fn put_many(&self, blocks: impl IntoIterator<Item = Bytes>) -> StoreResult<Vec<Cid>> {
+        let batch = self.start_batch();
+        let store = batch.as_ref().unwrap_or(self);
+        let mut cids = Vec::new();
+        for bytes in blocks {
+            cids.push(store.put_raw_block(bytes).await?);
+        }
+
+        if let Some(batch) = &batch {
+            batch.flush_batch().await?;
         }
-        Ok(())
+
+        Ok(cids)
     }
 }
 
@@ -340,11 +1965,10 @@ where
         }
 
         // Create CID and store the block
-        let cid = ipldstore::generate_cid(Codec::DagCbor, &bytes);
-        let block_path = self.get_block_path(&cid);
+        let cid = self.hash_function.generate_cid(Codec::DagCbor, &bytes);
 
-        if !block_path.exists() {
-            self.write_new_block(&block_path, &bytes).await?;
+        if !self.keep_block(&cid) {
+            self.write_new_block(&cid, &bytes).await?;
             // Increment reference counts for referenced blocks
             self.increment_reference_counts(data.get_references())
                 .await?;
@@ -356,7 +1980,9 @@ where
     async fn put_bytes(&self, reader: impl AsyncRead + Send + Sync) -> StoreResult<Cid> {
         tracing::trace!("putting bytes");
         let chunk_stream = self.chunker.chunk(reader).await?;
-        let mut cid_stream = self.layout.organize(chunk_stream, self.clone()).await?;
+        let batch = self.start_batch();
+        let store = batch.clone().unwrap_or_else(|| self.clone());
+        let mut cid_stream = self.layout.organize(chunk_stream, store).await?;
 
         // Take the last CID from the stream
         let mut cid = cid_stream.next().await.unwrap()?;
@@ -364,6 +1990,11 @@ where
             cid = result?;
         }
 
+        // The chunks and the nodes linking them are flushed together
+        if let Some(batch) = &batch {
+            batch.flush_batch().await?;
+        }
+
         Ok(cid)
     }
 
@@ -371,12 +2002,7 @@ where
     where
         T: DeserializeOwned,
     {
-        let block_path = self.get_block_path(cid);
-        let mut file = File::open(&block_path)
-            .await
-            .map_err(|_| StoreError::BlockNotFound(*cid))?;
-
-        let bytes = self.read_block_data(&mut file).await?;
+        let bytes = self.read_block(cid).await?;
         match cid.codec().try_into()? {
             Codec::DagCbor => serde_ipld_dagcbor::from_slice(&bytes).map_err(StoreError::custom),
             codec => Err(StoreError::UnexpectedBlockCodec(Codec::DagCbor, codec)),
@@ -392,7 +2018,34 @@ where
     }
 
     async fn has(&self, cid: &Cid) -> bool {
-        self.get_block_path(cid).exists()
+        if self.has_block(cid) {
+            return true;
+        }
+
+        // A block missing from the store is repaired from a mirror holding a copy when it is read
+        let has_mirror_copy = [
+            BlockFormat::Plain,
+            BlockFormat::Zstd,
+            BlockFormat::Encrypted,
+        ]
+        .into_iter()
+        .filter_map(|format| self.get_block_path_in(cid, format).ok())
+        .flat_map(|block_path| self.get_mirror_paths(&block_path))
+        .any(|mirror_path| mirror_path.exists());
+        if has_mirror_copy {
+            return true;
+        }
+
+        let Some(cache) = &self.cache else {
+            return false;
+        };
+        for name in self.get_block_names(cid) {
+            if matches!(cache.get_remote().has(&name).await, Ok(true)) {
+                return true;
+            }
+        }
+
+        false
     }
 
     async fn get_supported_codecs(&self) -> HashSet<Codec> {
@@ -408,90 +2061,103 @@ where
 
     async fn get_block_count(&self) -> StoreResult<u64> {
         let mut count = 0;
-        match self.dir_levels {
-            DirLevels::Zero => {
-                // Count all files in the root directory
-                let mut entries = fs::read_dir(&self.path).await.map_err(StoreError::custom)?;
-                while let Some(entry) = entries.next_entry().await.map_err(StoreError::custom)? {
-                    if entry
-                        .file_type()
-                        .await
-                        .map_err(StoreError::custom)?
-                        .is_file()
+        if let Some(index) = &self.index {
+            count = index.len() as u64;
+        } else {
+            match self.dir_levels {
+                DirLevels::Zero => {
+                    // Count all files in the root directory
+                    let mut entries = fs::read_dir(&self.path).await.map_err(StoreError::custom)?;
+                    while let Some(entry) =
+                        entries.next_entry().await.map_err(StoreError::custom)?
                     {
-                        count += 1;
+                        if entry
+                            .file_type()
+                            .await
+                            .map_err(StoreError::custom)?
+                            .is_file()
+                        {
+                            count += 1;
+                        }
                     }
                 }
-            }
-            DirLevels::One => {
-                // Count all files in first-level subdirectories
-                let mut entries = fs::read_dir(&self.path).await.map_err(StoreError::custom)?;
-                while let Some(dir_entry) =
-                    entries.next_entry().await.map_err(StoreError::custom)?
-                {
-                    if dir_entry
-                        .file_type()
-                        .await
-                        .map_err(StoreError::custom)?
-                        .is_dir()
+                DirLevels::One => {
+                    // Count all files in first-level subdirectories
+                    let mut entries = fs::read_dir(&self.path).await.map_err(StoreError::custom)?;
+                    while let Some(dir_entry) =
+                        entries.next_entry().await.map_err(StoreError::custom)?
                     {
-                        let mut subdir_entries = fs::read_dir(dir_entry.path())
-                            .await
-                            .map_err(StoreError::custom)?;
-                        while let Some(file_entry) = subdir_entries
-                            .next_entry()
+                        // Packfiles are counted by the blocks they hold below
+                        if dir_entry.file_name() == PACKS_SUBDIR {
+                            continue;
+                        }
+
+                        if dir_entry
+                            .file_type()
                             .await
                             .map_err(StoreError::custom)?
+                            .is_dir()
                         {
-                            if file_entry
-                                .file_type()
+                            let mut subdir_entries = fs::read_dir(dir_entry.path())
+                                .await
+                                .map_err(StoreError::custom)?;
+                            while let Some(file_entry) = subdir_entries
+                                .next_entry()
                                 .await
                                 .map_err(StoreError::custom)?
-                                .is_file()
                             {
-                                count += 1;
+                                if file_entry
+                                    .file_type()
+                                    .await
+                                    .map_err(StoreError::custom)?
+                                    .is_file()
+                                {
+                                    count += 1;
+                                }
                             }
                         }
                     }
                 }
-            }
-            DirLevels::Two => {
-                // Count all files in second-level subdirectories
-                let mut entries = fs::read_dir(&self.path).await.map_err(StoreError::custom)?;
-                while let Some(l1_entry) = entries.next_entry().await.map_err(StoreError::custom)? {
-                    if l1_entry
-                        .file_type()
-                        .await
-                        .map_err(StoreError::custom)?
-                        .is_dir()
+                DirLevels::Two => {
+                    // Count all files in second-level subdirectories
+                    let mut entries = fs::read_dir(&self.path).await.map_err(StoreError::custom)?;
+                    while let Some(l1_entry) =
+                        entries.next_entry().await.map_err(StoreError::custom)?
                     {
-                        let mut l2_entries = fs::read_dir(l1_entry.path())
+                        if l1_entry
+                            .file_type()
                             .await
-                            .map_err(StoreError::custom)?;
-                        while let Some(l2_entry) =
-                            l2_entries.next_entry().await.map_err(StoreError::custom)?
+                            .map_err(StoreError::custom)?
+                            .is_dir()
                         {
-                            if l2_entry
-                                .file_type()
+                            let mut l2_entries = fs::read_dir(l1_entry.path())
                                 .await
-                                .map_err(StoreError::custom)?
-                                .is_dir()
+                                .map_err(StoreError::custom)?;
+                            while let Some(l2_entry) =
+                                l2_entries.next_entry().await.map_err(StoreError::custom)?
                             {
-                                let mut file_entries = fs::read_dir(l2_entry.path())
-                                    .await
-                                    .map_err(StoreError::custom)?;
-                                while let Some(file_entry) = file_entries
-                                    .next_entry()
+                                if l2_entry
+                                    .file_type()
                                     .await
                                     .map_err(StoreError::custom)?
+                                    .is_dir()
                                 {
-                                    if file_entry
-                                        .file_type()
+                                    let mut file_entries = fs::read_dir(l2_entry.path())
+                                        .await
+                                        .map_err(StoreError::custom)?;
+                                    while let Some(file_entry) = file_entries
+                                        .next_entry()
                                         .await
                                         .map_err(StoreError::custom)?
-                                        .is_file()
                                     {
-                                        count += 1;
+                                        if file_entry
+                                            .file_type()
+                                            .await
+                                            .map_err(StoreError::custom)?
+                                            .is_file()
+                                        {
+                                            count += 1;
+                                        }
                                     }
                                 }
                             }
@@ -500,6 +2166,14 @@ where
                 }
             }
         }
+
+        let mut packs = self.packs.write().expect("pack index lock poisoned");
+        packs.refresh(&self.path.join(PACKS_SUBDIR))?;
+        count += packs.get_blocks().len() as u64;
+        if let Some(memory) = &self.memory {
+            count += memory.len() as u64;
+        }
+
         Ok(count)
     }
 
@@ -513,7 +2187,9 @@ where
         }
 
         let mut removed_cids = HashSet::new();
-        let block_path = self.get_block_path(cid);
+        let Some((block_path, format)) = self.find_block(cid) else {
+            return Ok(removed_cids);
+        };
 
         // Check if the CID exists and has refcount of exactly 0
         let refs = {
@@ -526,7 +2202,7 @@ where
                 let count = self.read_refcount(&mut file).await?;
                 if count == 0 {
                     // Try to deserialize the block to get its references
-                    let bytes = self.read_block_data(&mut file).await?;
+                    let bytes = self.read_block_data(&mut file, cid, format).await?;
                     let codec: Codec = cid.codec().try_into()?;
 
                     // Drop file handle before potential deletion
@@ -540,18 +2216,14 @@ where
                                 .collect::<Vec<_>>();
 
                             // Remove the block since refcount is 0
-                            fs::remove_file(&block_path)
-                                .await
-                                .map_err(StoreError::custom)?;
+                            self.remove_block(&block_path).await?;
                             removed_cids.insert(*cid);
 
                             Some(refs)
                         }
                         Codec::Raw => {
                             // For raw blocks, just remove them if refcount is 0
-                            fs::remove_file(&block_path)
-                                .await
-                                .map_err(StoreError::custom)?;
+                            self.remove_block(&block_path).await?;
                             removed_cids.insert(*cid);
                             None
                         }
@@ -568,7 +2240,10 @@ where
         // Process dependencies if we had any
         if let Some(refs) = refs {
             for ref_cid in refs {
-                let block_path = self.get_block_path(&ref_cid);
+                let Some((block_path, _)) = self.find_block(&ref_cid) else {
+                    continue;
+                };
+
                 // Decrement refcount and check if we should collect
                 let should_collect = {
                     if let Ok(mut file) = File::options()
@@ -580,6 +2255,7 @@ where
                         let count = self.read_refcount(&mut file).await?;
                         if count > 0 {
                             self.write_refcount(&mut file, count - 1).await?;
+                            self.write_mirror_refcounts(&block_path, count - 1).await;
                             count == 1 // Will be 0 after decrement
                         } else {
                             false
@@ -615,23 +2291,17 @@ where
             }
         }
 
-        let cid = ipldstore::generate_cid(Codec::Raw, bytes.as_ref());
-        let block_path = self.get_block_path(&cid);
+        let cid = self.hash_function.generate_cid(Codec::Raw, bytes.as_ref());
 
-        if !block_path.exists() {
-            self.write_new_block(&block_path, &bytes).await?;
+        if !self.keep_block(&cid) {
+            self.write_new_block(&cid, &bytes).await?;
         }
 
         Ok(cid)
     }
 
     async fn get_raw_block(&self, cid: &Cid) -> StoreResult<Bytes> {
-        let block_path = self.get_block_path(cid);
-        let mut file = File::open(&block_path)
-            .await
-            .map_err(|_| StoreError::BlockNotFound(*cid))?;
-
-        let bytes = self.read_block_data(&mut file).await?;
+        let bytes = self.read_block(cid).await?;
         match cid.codec().try_into()? {
             Codec::Raw => Ok(bytes),
             codec => Err(StoreError::UnexpectedBlockCodec(Codec::Raw, codec)),
@@ -661,6 +2331,189 @@ where
     }
 }
 
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the name of the block held by a file named `file_name`, the file name without its
+/// extension, or `None` if the name is not that of a block file.
+///
+/// The name of a plain or compressed block is the hex digest of its CID, and that of an encrypted
+/// block a keyed hash of it, see [`EncryptionKey::get_block_name`].
+pub(crate) fn get_block_name(file_name: &str) -> Option<&str> {
+    let name = file_name
+        .strip_suffix(COMPRESSED_BLOCK_EXTENSION)
+        .or_else(|| file_name.strip_suffix(ENCRYPTED_BLOCK_EXTENSION))
+        .and_then(|name| name.strip_suffix('.'))
+        .unwrap_or(file_name);
+
+    (!name.is_empty() && name.chars().all(|c| c.is_ascii_hexdigit())).then_some(name)
+}
+
+/// Returns whether `data` hashes to the digest of `cid` with the hash function `cid` was made
+/// with, or whether the hash function is not one monofs can check.
+fn is_block_data_valid(cid: &Cid, data: &[u8]) -> bool {
+    HashFunction::of_cid(cid).is_none_or(|hash_function| {
+        hash_function.get_code().digest(data).digest() == cid.hash().digest()
+    })
+}
+
+/// Returns the format of the data held by the block file at `block_path`, which its extension
+/// records.
+pub(crate) fn get_block_format(block_path: &Path) -> BlockFormat {
+    match block_path.extension().and_then(|ext| ext.to_str()) {
+        Some(COMPRESSED_BLOCK_EXTENSION) => BlockFormat::Zstd,
+        Some(ENCRYPTED_BLOCK_EXTENSION) => BlockFormat::Encrypted,
+        _ => BlockFormat::Plain,
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Flushes the files or directories at `paths` to disk, a few at a time.
+async fn flush_paths(paths: impl IntoIterator<Item = PathBuf>) -> StoreResult<()> {
+    futures::stream::iter(paths)
+        .map(|path| async move {
+            let file = File::open(&path).await.map_err(StoreError::custom)?;
+            file.sync_all().await.map_err(StoreError::custom)
+        })
+        .buffer_unordered(BATCH_FLUSH_CONCURRENCY)
+        .try_collect()
+        .await
+}
+
+/// Marks the file at `path` as modified just now.
+fn touch_file(path: &Path) -> std::io::Result<()> {
+    std::fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
+/// Returns the paths of every block file in the store at `dir`, leaving out its packfiles, which
+/// are flushed as they are written.
+async fn list_store_files(dir: &Path) -> StoreResult<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = match fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(StoreError::custom(e)),
+        };
+        while let Some(entry) = entries.next_entry().await.map_err(StoreError::custom)? {
+            let file_type = entry.file_type().await.map_err(StoreError::custom)?;
+            if file_type.is_dir() && entry.file_name() != PACKS_SUBDIR {
+                dirs.push(entry.path());
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Returns the object holding the data of a block, stored in `format`, in a remote store.
+///
+/// The object is the data prefixed with the byte identifying its format.
+fn encode_remote_object(format: BlockFormat, data: &[u8]) -> Bytes {
+    let mut object = Vec::with_capacity(1 + data.len());
+    object.push(format.to_byte());
+    object.extend_from_slice(data);
+    object.into()
+}
+
+/// Returns the format and data of a block held by an object in a remote store.
+fn decode_remote_object(object: &[u8]) -> StoreResult<(BlockFormat, &[u8])> {
+    let format = match object.first() {
+        Some(&byte) if byte == BlockFormat::Encrypted.to_byte() => Some(BlockFormat::Encrypted),
+        Some(&byte) => BlockFormat::from_byte(byte),
+        None => None,
+    }
+    .ok_or_else(|| StoreError::custom(anyhow::anyhow!("invalid remote block object")))?;
+
+    Ok((format, &object[1..]))
+}
+
+/// Returns the data authenticated along with the data of an encrypted block in a file named
+/// `name`.
+fn get_block_aad(name: &str, format: BlockFormat) -> Vec<u8> {
+    let mut aad = vec![format.to_byte()];
+    aad.extend_from_slice(name.as_bytes());
+    aad
+}
+
+/// Encrypts the data of the block with the given CID, stored in the given format once decrypted,
+/// along with the CID.
+fn seal_block(
+    key: &EncryptionKey,
+    cid: &Cid,
+    format: BlockFormat,
+    data: &[u8],
+) -> StoreResult<Vec<u8>> {
+    let cid_bytes = cid.to_bytes();
+    let mut plaintext = Vec::with_capacity(1 + cid_bytes.len() + data.len());
+    plaintext.push(u8::try_from(cid_bytes.len()).map_err(StoreError::custom)?);
+    plaintext.extend_from_slice(&cid_bytes);
+    plaintext.extend_from_slice(data);
+
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key.as_bytes()));
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let aad = get_block_aad(&key.get_block_name(cid.hash().digest()), format);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: &plaintext,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| StoreError::custom(anyhow::anyhow!("failed to encrypt block {}", cid)))?;
+
+    let mut sealed = Vec::with_capacity(1 + BLOCK_NONCE_SIZE + ciphertext.len());
+    sealed.push(format.to_byte());
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypts the encrypted block in a file named `name`, returning its CID and its data along
+/// with the format the data is stored in, or `None` if the block cannot be decrypted.
+fn open_block(
+    key: &EncryptionKey,
+    name: &str,
+    sealed: &[u8],
+) -> Option<(Cid, BlockFormat, Vec<u8>)> {
+    if sealed.len() < 1 + BLOCK_NONCE_SIZE {
+        return None;
+    }
+
+    let (format, rest) = sealed.split_at(1);
+    let (nonce, ciphertext) = rest.split_at(BLOCK_NONCE_SIZE);
+    let format = BlockFormat::from_byte(format[0])?;
+
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key.as_bytes()));
+    let aad = get_block_aad(name, format);
+    let plaintext = cipher
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: &aad,
+            },
+        )
+        .ok()?;
+
+    let (&cid_len, rest) = plaintext.split_first()?;
+    let cid_bytes = rest.get(..cid_len as usize)?;
+    let cid = Cid::try_from(cid_bytes).ok()?;
+    let data = rest[cid_len as usize..].to_vec();
+    Some((cid, format, data))
+}
+
 //--------------------------------------------------------------------------------------------------
 // Tests
 //--------------------------------------------------------------------------------------------------
@@ -1111,6 +2964,508 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_flatfsstore_compression() -> anyhow::Result<()> {
+        let (store, _temp) = fixtures::setup_store(DirLevels::One).await;
+        let store = store.with_compression(CompressionConfig::default());
+
+        // Compressible blocks are stored compressed
+        let text = "All work and no play makes Jack a dull boy. ".repeat(100);
+        let text_cid = store.put_raw_block(text.clone().into_bytes()).await?;
+        let text_path = store.get_block_path(&text_cid);
+        assert!(!text_path.exists());
+        assert!(text_path
+            .with_extension(COMPRESSED_BLOCK_EXTENSION)
+            .exists());
+        assert_eq!(
+            store.get_raw_block(&text_cid).await?.as_ref(),
+            text.as_bytes()
+        );
+
+        // Incompressible blocks are stored as is
+        let mut state = 0x2545_f491_u32;
+        let noise: Vec<u8> = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        let noise_cid = store.put_raw_block(noise.clone()).await?;
+        assert!(store.get_block_path(&noise_cid).exists());
+        assert_eq!(store.get_raw_block(&noise_cid).await?.as_ref(), noise);
+
+        // Nodes are compressed too and keep counting references
+        let node = TestNode {
+            name: "test".repeat(100),
+            value: 42,
+            refs: vec![text_cid, noise_cid],
+        };
+        let node_cid = store.put_node(&node).await?;
+        assert_eq!(store.get_node::<TestNode>(&node_cid).await?, node);
+        assert_eq!(store.get_block_count().await?, 3);
+
+        // Blocks are readable whatever the store's own compression setting
+        let plain = FlatFsStore::new(store.get_path());
+        assert_eq!(
+            plain.get_raw_block(&text_cid).await?.as_ref(),
+            text.as_bytes()
+        );
+        assert_eq!(plain.get_node::<TestNode>(&node_cid).await?, node);
+
+        // Garbage collection finds compressed blocks
+        let removed = store.garbage_collect(&node_cid).await?;
+        assert_eq!(removed, HashSet::from([node_cid, text_cid, noise_cid]));
+        assert_eq!(store.get_block_count().await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_flatfsstore_encryption() -> anyhow::Result<()> {
+        let (store, _temp) = fixtures::setup_store(DirLevels::One).await;
+        let plain_cid = store
+            .put_raw_block(b"Written before encryption".to_vec())
+            .await?;
+
+        let key = EncryptionKey::generate();
+        let store = store
+            .with_compression(CompressionConfig::default())
+            .with_encryption(Some(key.clone()));
+
+        // Blocks are stored encrypted, compressed first if worthwhile
+        let text = "All work and no play makes Jack a dull boy. ".repeat(100);
+        let text_cid = store.put_raw_block(text.clone().into_bytes()).await?;
+        let text_path = store.get_block_path_in(&text_cid, BlockFormat::Encrypted)?;
+        let stored = fs::read(&text_path).await?;
+        assert!(stored.len() < text.len());
+        assert!(!stored.windows(8).any(|window| window == b"All work"));
+        assert_eq!(
+            store.get_raw_block(&text_cid).await?.as_ref(),
+            text.as_bytes()
+        );
+
+        let node = TestNode {
+            name: "secret".to_string(),
+            value: 42,
+            refs: vec![text_cid, plain_cid],
+        };
+        let node_cid = store.put_node(&node).await?;
+        assert_eq!(store.get_node::<TestNode>(&node_cid).await?, node);
+
+        // Blocks written before encryption was enabled can still be read
+        assert_eq!(
+            store.get_raw_block(&plain_cid).await?.as_ref(),
+            b"Written before encryption"
+        );
+
+        // Encrypted blocks cannot be read without the right key
+        let unkeyed = FlatFsStore::new(store.get_path());
+        assert!(unkeyed.get_raw_block(&text_cid).await.is_err());
+        let wrong = unkeyed.with_encryption(Some(EncryptionKey::generate()));
+        assert!(wrong.get_raw_block(&text_cid).await.is_err());
+
+        // Nor can they be moved to where another block is expected
+        assert!(store.verify_block_file(&text_path).await?);
+        let node_path = store.get_block_path_in(&node_cid, BlockFormat::Encrypted)?;
+        fs::copy(&text_path, &node_path).await?;
+        assert!(store.get_node::<TestNode>(&node_cid).await.is_err());
+        assert!(!store.verify_block_file(&node_path).await?);
+
+        // Encrypted block files are named after a keyed hash of the digest, not the digest
+        let digest = hex::encode(text_cid.hash().digest());
+        let name = text_path.file_name().unwrap().to_string_lossy();
+        assert_eq!(
+            get_block_name(&name),
+            Some(key.get_block_name(text_cid.hash().digest()).as_str())
+        );
+        assert!(!name.contains(&digest));
+        assert!(!store.get_block_path(&text_cid).exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_flatfsstore_usage() -> anyhow::Result<()> {
+        let (store, temp) = fixtures::setup_store(DirLevels::One).await;
+        let usage = Arc::new(AtomicU64::new(0));
+        let store = store.with_usage(usage.clone());
+
+        // Every written block file is counted, and blocks that already exist are not
+        let raw_cid = store.put_raw_block(b"counted".to_vec()).await?;
+        store.put_raw_block(b"counted".to_vec()).await?;
+        let node = TestNode {
+            name: "usage".to_string(),
+            value: 1,
+            refs: vec![raw_cid],
+        };
+        let node_cid = store.put_node(&node).await?;
+        let (_, on_disk) = crate::management::get_blocks_usage(temp.path()).await?;
+        assert_eq!(usage.load(Ordering::Relaxed), on_disk);
+
+        // Removed block files are no longer counted
+        store.garbage_collect(&node_cid).await?;
+        assert_eq!(usage.load(Ordering::Relaxed), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_flatfsstore_remote_cache() -> anyhow::Result<()> {
+        let (store, temp) = fixtures::setup_store(DirLevels::One).await;
+        let remote_dir = TempDir::new()?;
+        let remote = Arc::new(crate::store::DirRemoteStore::new(remote_dir.path()));
+
+        // Each block file takes up 15 bytes, so only the last two written are kept
+        let store = store.with_remote(remote.clone(), 40);
+        let mut cids = Vec::new();
+        for i in 0..4 {
+            cids.push(
+                store
+                    .put_raw_block(format!("block-{}", i).into_bytes())
+                    .await?,
+            );
+        }
+        for cid in &cids {
+            assert!(remote.has(&hex::encode(cid.hash().digest())).await?);
+        }
+        assert!(store.find_block(&cids[0]).is_none());
+        assert!(store.find_block(&cids[1]).is_none());
+        assert!(store.find_block(&cids[3]).is_some());
+
+        // Evicted blocks are still there, and are fetched back when read
+        assert!(store.has(&cids[0]).await);
+        assert_eq!(store.get_raw_block(&cids[0]).await?.as_ref(), b"block-0");
+        assert!(store.find_block(&cids[0]).is_some());
+        assert!(store.find_block(&cids[2]).is_none());
+
+        // A store without the remote store only sees the cached blocks
+        let local = FlatFsStore::new(temp.path());
+        assert!(local.get_raw_block(&cids[0]).await.is_ok());
+        assert!(local.get_raw_block(&cids[1]).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_flatfsstore_read_repair() -> anyhow::Result<()> {
+        let (store, temp) = fixtures::setup_store(DirLevels::One).await;
+        let replica_dir = TempDir::new()?;
+        let replica = Arc::new(crate::store::DirRemoteStore::new(replica_dir.path()));
+
+        // The replica gets every block as it is written
+        let writer = store.clone().with_remote(replica.clone(), u64::MAX);
+        let corrupt = writer.put_raw_block(b"corrupt".to_vec()).await?;
+        let missing = writer.put_raw_block(b"missing".to_vec()).await?;
+        let scrubbed = writer.put_raw_block(b"scrubbed".to_vec()).await?;
+        let (corrupt_path, _) = store.find_block(&corrupt).unwrap();
+        let (missing_path, _) = store.find_block(&missing).unwrap();
+        let (scrubbed_path, _) = store.find_block(&scrubbed).unwrap();
+
+        let garbage = [3u64.to_be_bytes().as_slice(), b"garbage"].concat();
+        fs::write(&corrupt_path, &garbage).await?;
+        fs::write(&scrubbed_path, &garbage).await?;
+        fs::remove_file(&missing_path).await?;
+
+        // Without replicas, corrupt data is read as it is and missing blocks are not found
+        assert_eq!(store.get_raw_block(&corrupt).await?.as_ref(), b"garbage");
+        assert!(store.get_raw_block(&missing).await.is_err());
+
+        // With replicas, reads repair the blocks, keeping their reference counts
+        let repairing = store.with_replicas(vec![replica as Arc<dyn RemoteStore>]);
+        assert_eq!(
+            repairing.get_raw_block(&corrupt).await?.as_ref(),
+            b"corrupt"
+        );
+        assert_eq!(
+            repairing.get_raw_block(&missing).await?.as_ref(),
+            b"missing"
+        );
+        let mut file = File::open(&corrupt_path).await?;
+        assert_eq!(repairing.read_refcount(&mut file).await?, 3);
+        let local = FlatFsStore::new(temp.path());
+        assert_eq!(local.get_raw_block(&corrupt).await?.as_ref(), b"corrupt");
+        assert_eq!(local.get_raw_block(&missing).await?.as_ref(), b"missing");
+
+        // Block files found corrupt by a scrub are repaired in place
+        assert!(!repairing.verify_block_file(&scrubbed_path).await?);
+        assert!(repairing.repair_block_file(&scrubbed_path).await?);
+        assert!(repairing.verify_block_file(&scrubbed_path).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_flatfsstore_mirrors() -> anyhow::Result<()> {
+        let (store, temp) = fixtures::setup_store(DirLevels::One).await;
+        let mirror_dir = TempDir::new()?;
+        let store = store.with_mirrors(vec![mirror_dir.path().to_path_buf()]);
+
+        // Block files are copied to the mirror as they are written, reference counts included
+        let leaf = store.put_raw_block(b"leaf".to_vec()).await?;
+        let node = store
+            .put_node(&TestNode {
+                name: "node".to_string(),
+                value: 1,
+                refs: vec![leaf],
+            })
+            .await?;
+        let (leaf_path, _) = store.find_block(&leaf).unwrap();
+        let mirror_path = mirror_dir.path().join(leaf_path.strip_prefix(temp.path())?);
+        assert_eq!(fs::read(&leaf_path).await?, fs::read(&mirror_path).await?);
+        let mut file = File::open(&mirror_path).await?;
+        assert_eq!(store.read_refcount(&mut file).await?, 1);
+
+        // A block whose file is lost is still there, and is repaired from the mirror when read
+        fs::remove_file(&leaf_path).await?;
+        assert!(store.has(&leaf).await);
+        assert_eq!(store.get_raw_block(&leaf).await?.as_ref(), b"leaf");
+        let mut file = File::open(&leaf_path).await?;
+        assert_eq!(store.read_refcount(&mut file).await?, 1);
+
+        // Corrupt block files are repaired from the mirror too
+        fs::write(
+            &leaf_path,
+            [1u64.to_be_bytes().as_slice(), b"lead"].concat(),
+        )
+        .await?;
+        assert_eq!(store.get_raw_block(&leaf).await?.as_ref(), b"leaf");
+
+        // Removing blocks removes their copies
+        let removed = store.garbage_collect(&node).await?;
+        assert_eq!(removed.len(), 2);
+        assert!(!mirror_path.exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_flatfsstore_memory_spill() -> anyhow::Result<()> {
+        let (store, temp) = fixtures::setup_store(DirLevels::One).await;
+        let existing = store.put_raw_block(b"on disk".to_vec()).await?;
+
+        let config = MemoryStoreConfig::builder().max_bytes(1024).build();
+        let store = store.with_memory(config);
+        let data_cid = store.put_raw_block(b"in memory".to_vec()).await?;
+        let node = TestNode {
+            name: "test".to_string(),
+            value: 42,
+            refs: vec![data_cid, existing],
+        };
+        let node_cid = store.put_node(&node).await?;
+        let garbage = store.put_raw_block(b"garbage".to_vec()).await?;
+
+        // New blocks have no file, but blocks that do are still read
+        assert!(store.find_block(&data_cid).is_none());
+        assert!(store.find_block(&node_cid).is_none());
+        assert_eq!(store.get_raw_block(&data_cid).await?.as_ref(), b"in memory");
+        assert_eq!(store.get_raw_block(&existing).await?.as_ref(), b"on disk");
+        assert_eq!(store.get_block_count().await?, 4);
+
+        // Writes past the size cap fail
+        let result = store.put_raw_block(vec![0u8; 1024]).await;
+        assert!(result.is_err());
+
+        // Only the blocks reachable from the root are spilled
+        assert_eq!(store.spill_memory(&node_cid).await?, 2);
+        let disk = FlatFsStore::new(temp.path());
+        let retrieved_node: TestNode = disk.get_node(&node_cid).await?;
+        assert_eq!(retrieved_node, node);
+        assert_eq!(disk.get_raw_block(&data_cid).await?.as_ref(), b"in memory");
+        assert!(!disk.has(&garbage).await);
+
+        // Spilled nodes count their references
+        let (block_path, _) = disk.find_block(&existing).unwrap();
+        let mut file = File::open(&block_path).await?;
+        assert_eq!(disk.read_refcount(&mut file).await?, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_flatfsstore_index() -> anyhow::Result<()> {
+        let (store, temp) = fixtures::setup_store(DirLevels::One).await;
+        let existing = store.put_raw_block(b"before".to_vec()).await?;
+        let store = store.with_index().await?;
+
+        // Blocks written before and after the index was loaded are found
+        let data_cid = store.put_raw_block(b"after".to_vec()).await?;
+        let node = TestNode {
+            name: "test".to_string(),
+            value: 42,
+            refs: vec![data_cid, existing],
+        };
+        let node_cid = store.put_node(&node).await?;
+        assert!(store.has(&existing).await);
+        assert!(store.has(&node_cid).await);
+        assert_eq!(store.get_block_count().await?, 3);
+
+        // Blocks written by another store are read, and writing them again keeps their files
+        let other = FlatFsStore::new(temp.path());
+        let other_cid = other.put_raw_block(b"other".to_vec()).await?;
+        let (block_path, _) = other.find_block(&other_cid).unwrap();
+        let mut file = File::options().write(true).open(&block_path).await?;
+        other.write_refcount(&mut file, 2).await?;
+        assert!(!store.has(&other_cid).await);
+        assert_eq!(store.get_raw_block(&other_cid).await?.as_ref(), b"other");
+        store.put_raw_block(b"other".to_vec()).await?;
+        let mut file = File::open(&block_path).await?;
+        assert_eq!(store.read_refcount(&mut file).await?, 2);
+        assert_eq!(store.get_block_count().await?, 4);
+
+        // Removed blocks are forgotten, whoever removed them
+        store.garbage_collect(&node_cid).await?;
+        assert!(!store.has(&node_cid).await);
+        assert!(!store.has(&existing).await);
+        assert_eq!(store.get_block_count().await?, 1);
+        fs::remove_file(&block_path).await?;
+        assert!(!store.has(&other_cid).await);
+        assert_eq!(store.get_block_count().await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_flatfsstore_hash_function() -> anyhow::Result<()> {
+        let (store, temp) = fixtures::setup_store(DirLevels::One).await;
+        let store = store.with_hash_function(HashFunction::Sha2_256);
+
+        // New blocks are made with the store's hash function
+        let data_cid = store.put_raw_block(b"hashed".to_vec()).await?;
+        let node = TestNode {
+            name: "test".to_string(),
+            value: 42,
+            refs: vec![data_cid],
+        };
+        let node_cid = store.put_node(&node).await?;
+        for cid in [data_cid, node_cid] {
+            assert_eq!(HashFunction::of_cid(&cid), Some(HashFunction::Sha2_256));
+            let (block_path, _) = store.find_block(&cid).unwrap();
+            assert!(store.verify_block_file(&block_path).await?);
+        }
+
+        // Stores with another hash function read them, and take them from other stores
+        let other = FlatFsStore::new(temp.path());
+        let retrieved: TestNode = other.get_node(&node_cid).await?;
+        assert_eq!(retrieved, node);
+        let copy = FlatFsStore::new(temp.path().join("copy"));
+        let bytes = other.get_block_data(&data_cid).await?;
+        assert!(copy.put_block_data(&data_cid, &bytes).await?);
+        assert_ne!(other.put_raw_block(b"hashed".to_vec()).await?, data_cid);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_flatfsstore_put_many() -> anyhow::Result<()> {
+        for durability in [Durability::Relaxed, Durability::Batch, Durability::Block] {
+            let (store, _temp) = fixtures::setup_store(DirLevels::One).await;
+            let store = store.with_durability(durability);
+
+            // Blocks are stored in order, including ones given twice
+            let blocks = [b"first".as_slice(), b"second", b"first"].map(Bytes::from_static);
+            let cids = store.put_many(blocks.clone()).await?;
+            assert_eq!(cids.len(), 3);
+            assert_eq!(cids[0], cids[2]);
+            for (cid, bytes) in cids.iter().zip(&blocks) {
+                assert_eq!(&store.get_raw_block(cid).await?, bytes);
+            }
+            assert_eq!(store.get_block_count().await?, 2);
+
+            // Contents put as one batch are read back whole
+            let data: Vec<u8> = (0..(DEFAULT_MAX_CHUNK_SIZE * 2) as usize)
+                .map(|i| (i % 251) as u8)
+                .collect();
+            let cid = store.put_bytes(&data[..]).await?;
+            let mut reader = store.get_bytes(&cid).await?;
+            let mut read = Vec::new();
+            reader.read_to_end(&mut read).await?;
+            assert_eq!(read, data);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_flatfsstore_sync() -> anyhow::Result<()> {
+        let (store, _temp) = fixtures::setup_store(DirLevels::One).await;
+        let store = store.with_durability(Durability::Relaxed);
+
+        // Block files written without being flushed are flushed by the next sync only
+        let cid = store.put_raw_block(b"unsynced".to_vec()).await?;
+        assert_eq!(store.unsynced.files.lock().unwrap().len(), 1);
+        store.sync().await?;
+        assert!(store.unsynced.files.lock().unwrap().is_empty());
+        store.sync().await?;
+
+        // Stores that lost track of their block files flush all of them
+        store.unsynced.overflowed.store(true, Ordering::Relaxed);
+        store.sync().await?;
+        assert!(!store.unsynced.overflowed.load(Ordering::Relaxed));
+        assert_eq!(store.get_raw_block(&cid).await?, b"unsynced".as_slice());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_flatfsstore_keep_block_moved_aside() -> anyhow::Result<()> {
+        let (store, _temp) = fixtures::setup_store(DirLevels::One).await;
+        let cid = store.put_raw_block(b"kept".to_vec()).await?;
+        let (block_path, _) = store.find_block(&cid).unwrap();
+
+        // A collection moves the file aside after the block was found, but before it is marked
+        assert!(store.has_block(&cid));
+        fs::rename(&block_path, block_path.with_extension("removing")).await?;
+        assert!(!store.touch_block(&cid));
+
+        // So putting the block again writes its file anew
+        assert_eq!(store.put_raw_block(b"kept".to_vec()).await?, cid);
+        assert!(fs::try_exists(&block_path).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_flatfsstore_chunker_config_prepend() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config = ChunkerConfig::builder()
+            .min_size(1024)
+            .desired_size(4 * 1024)
+            .max_size(16 * 1024)
+            .build();
+        let store = FlatFsStore::with_chunker_config(temp_dir.path(), &config);
+
+        // Pseudo-random data so that chunk boundaries depend on the content
+        let mut state = 0x2545_f491_u32;
+        let data: Vec<u8> = (0..256 * 1024)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+
+        let original = store.put_bytes(&data[..]).await?;
+        let prepended = store
+            .put_bytes(&[b"abc".as_slice(), &data[..]].concat()[..])
+            .await?;
+
+        // Prepending a few bytes only changes the chunks at the start of the file
+        let original_blocks = crate::store::collect_reachable(&store, [original]).await?;
+        let prepended_blocks = crate::store::collect_reachable(&store, [prepended]).await?;
+        let shared = original_blocks.intersection(&prepended_blocks).count();
+        assert!(
+            shared * 2 > original_blocks.len(),
+            "only {} of {} blocks are shared",
+            shared,
+            original_blocks.len()
+        );
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
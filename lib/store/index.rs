@@ -0,0 +1,315 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use ipldstore::{StoreError, StoreResult};
+use tokio::fs;
+
+use super::{get_block_format, get_block_name, BlockFormat, PACKS_SUBDIR};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The bits of the bloom filter for every block it is sized for.
+const BLOOM_BITS_PER_BLOCK: usize = 16;
+
+/// The bits of the bloom filter set for every block, which with [`BLOOM_BITS_PER_BLOCK`] bits per
+/// block makes about one in two thousand lookups of an unknown block a false positive.
+const BLOOM_HASHES: u64 = 8;
+
+/// The fewest blocks the bloom filter is sized for.
+const MIN_BLOOM_CAPACITY: usize = 1 << 12;
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The block files of a store, kept in memory so that looking for a block does not take a stat of
+/// every file it could be in.
+///
+/// A bloom filter answers most lookups of blocks the store does not have, which are the
+/// lookups of every new block written, and a map from the first half of each block's name tells
+/// the format and size of its file. Blocks are keyed by half their name, the digest of their CID
+/// or a keyed hash of it, to keep the map small, so a block the index knows of still has to be
+/// checked for on disk, which also catches files removed by other processes. The filter is
+/// rebuilt twice as large whenever the map outgrows it.
+#[derive(Debug)]
+pub(crate) struct BlockIndex {
+    /// The indexed blocks.
+    state: RwLock<IndexState>,
+}
+
+/// A block file known to a [`BlockIndex`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct IndexedBlock {
+    /// How the block's data is encoded, which names its file.
+    pub(crate) format: BlockFormat,
+
+    /// The size of the block's file.
+    pub(crate) size: u64,
+}
+
+/// The blocks known to a [`BlockIndex`].
+#[derive(Debug)]
+struct IndexState {
+    /// Tells whether a block may be in the map.
+    filter: BloomFilter,
+
+    /// The blocks, keyed by the first half of their name.
+    blocks: HashMap<u128, IndexedBlock>,
+
+    /// The total size of the block files.
+    bytes: u64,
+}
+
+/// A bloom filter over the keys of blocks.
+#[derive(Debug)]
+struct BloomFilter {
+    /// The bits, a power of two of them.
+    bits: Vec<u64>,
+
+    /// The most blocks the filter was sized for.
+    capacity: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl BlockIndex {
+    /// Indexes the block files under `path`, the directory of a store, leaving out its packs.
+    pub(crate) async fn load(path: &Path) -> StoreResult<Self> {
+        let mut blocks = HashMap::new();
+        let mut pending = vec![path.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                // A store creates its directory with its first block
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(StoreError::custom(e)),
+            };
+
+            while let Some(entry) = entries.next_entry().await.map_err(StoreError::custom)? {
+                let file_type = entry.file_type().await.map_err(StoreError::custom)?;
+                if file_type.is_dir() {
+                    if entry.file_name() != PACKS_SUBDIR {
+                        pending.push(entry.path());
+                    }
+                    continue;
+                }
+
+                let name = entry.file_name().to_string_lossy().to_string();
+                let Some(key) = get_block_name(&name)
+                    .filter(|_| file_type.is_file())
+                    .and_then(get_hex_key)
+                else {
+                    continue;
+                };
+
+                let size = entry.metadata().await.map_err(StoreError::custom)?.len();
+                let format = get_block_format(&entry.path());
+                blocks.insert(key, IndexedBlock { format, size });
+            }
+        }
+
+        let mut filter = BloomFilter::with_capacity(blocks.len() * 2);
+        blocks.keys().for_each(|key| filter.insert(*key));
+        let bytes = blocks.values().map(|block| block.size).sum();
+        Ok(Self {
+            state: RwLock::new(IndexState {
+                filter,
+                blocks,
+                bytes,
+            }),
+        })
+    }
+
+    /// Returns the block file named `name`, without its extension, if the index knows of one.
+    pub(crate) fn get(&self, name: &str) -> Option<IndexedBlock> {
+        let key = get_hex_key(name)?;
+        let state = self.read();
+        if !state.filter.may_contain(key) {
+            return None;
+        }
+
+        state.blocks.get(&key).copied()
+    }
+
+    /// Returns whether the index knows of a block file named `name`, without its extension.
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Records the block file named `name`, without its extension.
+    pub(crate) fn insert(&self, name: &str, block: IndexedBlock) {
+        if let Some(key) = get_hex_key(name) {
+            self.write().insert(key, block);
+        }
+    }
+
+    /// Forgets the block file named `name`, without its extension.
+    pub(crate) fn remove(&self, name: &str) {
+        if let Some(key) = get_hex_key(name) {
+            self.write().remove(key);
+        }
+    }
+
+    /// Forgets the block file named `file_name`, with its extension.
+    pub(crate) fn remove_file(&self, file_name: &str) {
+        if let Some(name) = get_block_name(file_name) {
+            self.remove(name);
+        }
+    }
+
+    /// Returns the number of indexed block files.
+    pub(crate) fn len(&self) -> usize {
+        self.read().blocks.len()
+    }
+
+    /// Returns the total size of the indexed block files.
+    pub(crate) fn get_bytes(&self) -> u64 {
+        self.read().bytes
+    }
+
+    /// Locks the index for reading.
+    fn read(&self) -> RwLockReadGuard<'_, IndexState> {
+        self.state.read().expect("block index lock poisoned")
+    }
+
+    /// Locks the index for writing.
+    fn write(&self) -> RwLockWriteGuard<'_, IndexState> {
+        self.state.write().expect("block index lock poisoned")
+    }
+}
+
+impl IndexState {
+    /// Records the block with the given key, growing the filter if it is full.
+    fn insert(&mut self, key: u128, block: IndexedBlock) {
+        if let Some(previous) = self.blocks.insert(key, block) {
+            self.bytes = self.bytes.saturating_sub(previous.size);
+        }
+        self.bytes += block.size;
+
+        if self.blocks.len() > self.filter.capacity {
+            self.filter = BloomFilter::with_capacity(self.filter.capacity * 2);
+            self.blocks.keys().for_each(|key| self.filter.insert(*key));
+        } else {
+            self.filter.insert(key);
+        }
+    }
+
+    /// Forgets the block with the given key. The filter keeps it, as bits cannot be unset.
+    fn remove(&mut self, key: u128) {
+        if let Some(block) = self.blocks.remove(&key) {
+            self.bytes = self.bytes.saturating_sub(block.size);
+        }
+    }
+}
+
+impl BloomFilter {
+    /// Creates an empty filter sized for `capacity` blocks.
+    fn with_capacity(capacity: usize) -> Self {
+        let capacity = capacity.max(MIN_BLOOM_CAPACITY);
+        let bits = (capacity * BLOOM_BITS_PER_BLOCK).next_power_of_two();
+        Self {
+            bits: vec![0; bits / 64],
+            capacity,
+        }
+    }
+
+    /// Sets the bits of `key`.
+    fn insert(&mut self, key: u128) {
+        for bit in get_bits(key, self.bits.len() * 64) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Returns whether `key` may have been inserted, which is certain if it returns `false`.
+    fn may_contain(&self, key: u128) -> bool {
+        get_bits(key, self.bits.len() * 64).all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the key of the block named `name`, a hex digest or keyed hash, the first half of the
+/// bytes it encodes.
+fn get_hex_key(name: &str) -> Option<u128> {
+    let mut key = [0u8; 16];
+    let bytes = hex::decode(&name[..name.len().min(32)]).ok()?;
+    key[..bytes.len()].copy_from_slice(&bytes);
+    Some(u128::from_be_bytes(key))
+}
+
+/// Returns the bits of a filter of `len` bits that `key` sets, by double hashing. The key is
+/// already uniformly distributed, being part of a hash.
+fn get_bits(key: u128, len: usize) -> impl Iterator<Item = usize> {
+    let (h1, h2) = (key as u64, (key >> 64) as u64 | 1);
+    let mask = len as u64 - 1;
+    (0..BLOOM_HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) & mask) as usize)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::{ipld::cid::Cid, Codec, IpldStore, RawStore};
+    use tempfile::TempDir;
+
+    use crate::store::FlatFsStore;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_block_index() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let store = FlatFsStore::new(temp.path());
+        let mut cids = Vec::new();
+        for i in 0..3u8 {
+            cids.push(store.put_raw_block(vec![i; 100]).await?);
+        }
+
+        // Block files are indexed with their format and size, and others are not
+        let name = |cid: &Cid| hex::encode(cid.hash().digest());
+        let index = BlockIndex::load(temp.path()).await?;
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.get_bytes(), 3 * 108);
+        let block = index.get(&name(&cids[0])).unwrap();
+        assert_eq!(block.format, BlockFormat::Plain);
+        assert_eq!(block.size, 108);
+        let unknown = name(&ipldstore::generate_cid(Codec::Raw, b"unknown"));
+        assert!(!index.contains(&unknown));
+
+        // The index keeps up with blocks written and removed, growing past its initial size
+        index.remove(&name(&cids[0]));
+        assert!(!index.contains(&name(&cids[0])));
+        assert_eq!(index.get_bytes(), 2 * 108);
+        for i in 0..(MIN_BLOOM_CAPACITY * 3) as u32 {
+            let cid = ipldstore::generate_cid(Codec::Raw, &i.to_be_bytes());
+            index.insert(
+                &name(&cid),
+                IndexedBlock {
+                    format: BlockFormat::Zstd,
+                    size: 1,
+                },
+            );
+        }
+        assert!(index.contains(&name(&cids[1])) && index.contains(&name(&cids[2])));
+        assert!(!index.contains(&unknown));
+        assert_eq!(index.len(), MIN_BLOOM_CAPACITY * 3 + 2);
+
+        // A missing store directory is an empty index
+        let index = BlockIndex::load(&temp.path().join("missing")).await?;
+        assert_eq!(index.len(), 0);
+        assert!(store.has(&cids[1]).await);
+
+        Ok(())
+    }
+}
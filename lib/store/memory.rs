@@ -0,0 +1,140 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+use bytes::Bytes;
+use ipldstore::ipld::cid::Cid;
+
+use crate::{config::MemoryStoreConfig, FsError, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// The blocks a store keeps in memory instead of in their files, up to a size cap.
+///
+/// Blocks are kept as they were given, without compression, encryption or a reference count.
+#[derive(Debug)]
+pub(crate) struct MemoryBlocks {
+    /// How the blocks are kept.
+    config: MemoryStoreConfig,
+
+    /// The blocks kept.
+    state: Mutex<MemoryState>,
+}
+
+/// The blocks kept by [`MemoryBlocks`].
+#[derive(Debug, Default)]
+struct MemoryState {
+    /// The blocks, by CID.
+    blocks: HashMap<Cid, Bytes>,
+
+    /// The total size of the blocks.
+    bytes: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl MemoryBlocks {
+    /// Creates an empty set of blocks kept as described by `config`.
+    pub(crate) fn new(config: MemoryStoreConfig) -> Self {
+        Self {
+            config,
+            state: Default::default(),
+        }
+    }
+
+    /// Returns how the blocks are kept.
+    pub(crate) fn get_config(&self) -> &MemoryStoreConfig {
+        &self.config
+    }
+
+    /// Returns the block with the given CID, if kept.
+    pub(crate) fn get(&self, cid: &Cid) -> Option<Bytes> {
+        self.lock().blocks.get(cid).cloned()
+    }
+
+    /// Returns whether the block with the given CID is kept.
+    pub(crate) fn contains(&self, cid: &Cid) -> bool {
+        self.lock().blocks.contains_key(cid)
+    }
+
+    /// Returns the number of blocks kept.
+    pub(crate) fn len(&self) -> usize {
+        self.lock().blocks.len()
+    }
+
+    /// Keeps `bytes` as the block with the given CID.
+    ///
+    /// Fails with [`FsError::MemoryStoreFull`] if the block does not fit under the size cap.
+    pub(crate) fn insert(&self, cid: Cid, bytes: Bytes) -> FsResult<()> {
+        let mut state = self.lock();
+        if state.blocks.contains_key(&cid) {
+            return Ok(());
+        }
+
+        let used = state.bytes + bytes.len() as u64;
+        let max = *self.config.get_max_bytes();
+        if used > max {
+            return Err(FsError::MemoryStoreFull { used, max });
+        }
+
+        state.bytes = used;
+        state.blocks.insert(cid, bytes);
+        Ok(())
+    }
+
+    /// Stops keeping the block with the given CID.
+    pub(crate) fn remove(&self, cid: &Cid) {
+        let mut state = self.lock();
+        if let Some(bytes) = state.blocks.remove(cid) {
+            state.bytes -= bytes.len() as u64;
+        }
+    }
+
+    /// Locks the blocks kept.
+    fn lock(&self) -> MutexGuard<'_, MemoryState> {
+        self.state.lock().expect("memory blocks lock poisoned")
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::Codec;
+
+    use super::*;
+
+    #[test]
+    fn test_memory_blocks_size_cap() -> anyhow::Result<()> {
+        let config = MemoryStoreConfig::builder().max_bytes(10).build();
+        let memory = MemoryBlocks::new(config);
+
+        let first = ipldstore::generate_cid(Codec::Raw, b"123456");
+        memory.insert(first, Bytes::from_static(b"123456"))?;
+        assert!(memory.contains(&first));
+
+        // Keeping the same block again takes no room
+        memory.insert(first, Bytes::from_static(b"123456"))?;
+        assert_eq!(memory.len(), 1);
+
+        let second = ipldstore::generate_cid(Codec::Raw, b"7890ab");
+        assert!(matches!(
+            memory.insert(second, Bytes::from_static(b"7890ab")),
+            Err(FsError::MemoryStoreFull { used: 12, max: 10 })
+        ));
+
+        memory.remove(&first);
+        memory.insert(second, Bytes::from_static(b"7890ab"))?;
+        assert_eq!(memory.get(&second), Some(Bytes::from_static(b"7890ab")));
+        assert_eq!(memory.get(&first), None);
+
+        Ok(())
+    }
+}
@@ -1,13 +1,27 @@
 //! Stores for the filesystem.
 
+mod cache;
+mod dag;
 mod flatfsstore;
+mod index;
 mod layeredfsstore;
 mod membufferstore;
+mod memory;
+mod pack;
+mod remote;
+mod walk;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
 //--------------------------------------------------------------------------------------------------
 
+pub(crate) use cache::*;
+pub use dag::*;
 pub use flatfsstore::*;
+pub(crate) use index::*;
 pub use layeredfsstore::*;
 pub use membufferstore::*;
+pub(crate) use memory::*;
+pub(crate) use pack::*;
+pub use remote::*;
+pub use walk::*;
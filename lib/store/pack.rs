@@ -0,0 +1,292 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use ipldstore::{
+    codetable::{Code, MultihashDigest},
+    StoreError, StoreResult,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, SeekFrom},
+};
+
+use super::BlockFormat;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The subdirectory of a store holding its packfiles.
+pub(crate) const PACKS_SUBDIR: &str = "packs";
+
+/// The extension of packfiles, which hold the data of many blocks back to back.
+pub(crate) const PACK_EXTENSION: &str = "pack";
+
+/// The extension of pack indexes, which tell where each block of a packfile is.
+pub(crate) const PACK_INDEX_EXTENSION: &str = "idx";
+
+/// The extension of pack indexes that are still being written.
+const TEMP_INDEX_EXTENSION: &str = "idx.tmp";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// Where a block is in a packfile, as recorded in the pack's index.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PackEntry {
+    /// The name of the block, the hex digest of its CID or a keyed hash of it if encrypted.
+    digest: String,
+
+    /// How the block's data is encoded.
+    format: BlockFormat,
+
+    /// Where the block's data starts in the packfile.
+    offset: u64,
+
+    /// The length of the block's data.
+    len: u64,
+}
+
+/// A block stored in a packfile.
+#[derive(Debug, Clone)]
+pub(crate) struct PackedBlock {
+    /// The packfile holding the block.
+    pack: Arc<PathBuf>,
+
+    /// How the block's data is encoded.
+    format: BlockFormat,
+
+    /// Where the block's data starts in the packfile.
+    offset: u64,
+
+    /// The length of the block's data.
+    len: u64,
+}
+
+/// The blocks in the packfiles of a store, keyed by their name, the hex digest of their CID or a
+/// keyed hash of it if encrypted.
+///
+/// Packs are only ever added while a store is open, so the index is refreshed whenever a block
+/// cannot be found and the packs directory has changed since it was last read.
+#[derive(Debug, Default)]
+pub(crate) struct PackIndex {
+    /// The packed blocks.
+    blocks: HashMap<String, PackedBlock>,
+
+    /// The pack indexes that were loaded.
+    loaded: HashSet<PathBuf>,
+
+    /// When the packs directory was last modified, as of the last refresh.
+    modified_at: Option<SystemTime>,
+}
+
+/// Writes blocks into a new packfile.
+///
+/// The pack is written under a temporary name and only becomes visible to stores once
+/// [`finish`](Self::finish) writes its index, so a pack that was cut short is never read.
+pub(crate) struct PackWriter {
+    /// The packs directory.
+    dir: PathBuf,
+
+    /// The temporary path of the packfile.
+    temp_path: PathBuf,
+
+    /// The packfile.
+    file: File,
+
+    /// The blocks written so far.
+    entries: Vec<PackEntry>,
+
+    /// The size of the packfile so far.
+    len: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl PackedBlock {
+    /// Returns how the block's data is encoded.
+    pub(crate) fn get_format(&self) -> BlockFormat {
+        self.format
+    }
+
+    /// Returns the packfile holding the block.
+    pub(crate) fn get_pack(&self) -> &Path {
+        &self.pack
+    }
+
+    /// Reads the block's data from its packfile, as it is encoded.
+    pub(crate) async fn read(&self) -> StoreResult<Vec<u8>> {
+        let mut file = File::open(self.pack.as_path())
+            .await
+            .map_err(StoreError::custom)?;
+        file.seek(SeekFrom::Start(self.offset))
+            .await
+            .map_err(StoreError::custom)?;
+
+        let mut data = vec![0; self.len as usize];
+        file.read_exact(&mut data)
+            .await
+            .map_err(StoreError::custom)?;
+
+        Ok(data)
+    }
+}
+
+impl PackIndex {
+    /// Returns the packed block named `digest`, if any.
+    pub(crate) fn get(&self, digest: &str) -> Option<&PackedBlock> {
+        self.blocks.get(digest)
+    }
+
+    /// Returns every packed block, keyed by its name.
+    pub(crate) fn get_blocks(&self) -> &HashMap<String, PackedBlock> {
+        &self.blocks
+    }
+
+    /// Returns whether the packs directory `dir` changed since the index was last refreshed.
+    pub(crate) fn is_stale(&self, dir: &Path) -> bool {
+        let modified_at = std::fs::metadata(dir)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        modified_at != self.modified_at
+    }
+
+    /// Loads the indexes of the packs in `dir` that have not been loaded yet.
+    pub(crate) fn refresh(&mut self, dir: &Path) -> StoreResult<()> {
+        let modified_at = match std::fs::metadata(dir) {
+            Ok(metadata) => metadata.modified().ok(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(StoreError::custom(e)),
+        };
+
+        for entry in std::fs::read_dir(dir).map_err(StoreError::custom)? {
+            let index_path = entry.map_err(StoreError::custom)?.path();
+            let is_index =
+                index_path.extension().and_then(|ext| ext.to_str()) == Some(PACK_INDEX_EXTENSION);
+            if !is_index || self.loaded.contains(&index_path) {
+                continue;
+            }
+
+            let bytes = std::fs::read(&index_path).map_err(StoreError::custom)?;
+            let entries =
+                serde_json::from_slice::<Vec<PackEntry>>(&bytes).map_err(StoreError::custom)?;
+            let pack = Arc::new(index_path.with_extension(PACK_EXTENSION));
+            for entry in entries {
+                let block = PackedBlock {
+                    pack: pack.clone(),
+                    format: entry.format,
+                    offset: entry.offset,
+                    len: entry.len,
+                };
+                self.blocks.insert(entry.digest, block);
+            }
+
+            self.loaded.insert(index_path);
+        }
+
+        self.modified_at = modified_at;
+        Ok(())
+    }
+}
+
+impl PackWriter {
+    /// Starts a new packfile in the packs directory `dir`.
+    pub(crate) async fn create(dir: impl Into<PathBuf>) -> StoreResult<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).await.map_err(StoreError::custom)?;
+
+        let temp_path = dir.join(format!("tmp-{}.{}", std::process::id(), PACK_EXTENSION));
+        let file = File::create(&temp_path).await.map_err(StoreError::custom)?;
+
+        Ok(Self {
+            dir,
+            temp_path,
+            file,
+            entries: Vec::new(),
+            len: 0,
+        })
+    }
+
+    /// Returns the size of the packfile so far.
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns whether no block was written yet.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Appends the data of the block named `digest`, encoded in `format`.
+    pub(crate) async fn add(
+        &mut self,
+        digest: &str,
+        format: BlockFormat,
+        data: &[u8],
+    ) -> StoreResult<()> {
+        self.file
+            .write_all(data)
+            .await
+            .map_err(StoreError::custom)?;
+        self.entries.push(PackEntry {
+            digest: digest.to_string(),
+            format,
+            offset: self.len,
+            len: data.len() as u64,
+        });
+        self.len += data.len() as u64;
+
+        Ok(())
+    }
+
+    /// Removes the packfile without making it visible.
+    pub(crate) async fn discard(self) -> StoreResult<()> {
+        drop(self.file);
+        fs::remove_file(&self.temp_path)
+            .await
+            .map_err(StoreError::custom)
+    }
+
+    /// Makes the packfile durable and visible, naming it after the digest of its index.
+    ///
+    /// Returns the path of the packfile.
+    pub(crate) async fn finish(mut self) -> StoreResult<PathBuf> {
+        self.file.sync_all().await.map_err(StoreError::custom)?;
+
+        let index = serde_json::to_vec(&self.entries).map_err(StoreError::custom)?;
+        let name = format!(
+            "pack-{}",
+            hex::encode(Code::Blake3_256.digest(&index).digest())
+        );
+        let pack_path = self.dir.join(&name).with_extension(PACK_EXTENSION);
+        let index_path = self.dir.join(&name).with_extension(PACK_INDEX_EXTENSION);
+
+        // The index is written last, as stores only read packs that have one
+        let temp_index_path = self.temp_path.with_extension(TEMP_INDEX_EXTENSION);
+        let mut index_file = File::create(&temp_index_path)
+            .await
+            .map_err(StoreError::custom)?;
+        index_file
+            .write_all(&index)
+            .await
+            .map_err(StoreError::custom)?;
+        index_file.sync_all().await.map_err(StoreError::custom)?;
+
+        fs::rename(&self.temp_path, &pack_path)
+            .await
+            .map_err(StoreError::custom)?;
+        fs::rename(&temp_index_path, &index_path)
+            .await
+            .map_err(StoreError::custom)?;
+
+        Ok(pack_path)
+    }
+}
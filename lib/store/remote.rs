@@ -0,0 +1,238 @@
+use std::{
+    fmt,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use ipldstore::{StoreError, StoreResult};
+use tokio::fs;
+
+use crate::{config::RemoteLocation, FsResult};
+
+#[cfg(feature = "s3")]
+use object_store::{
+    aws::{AmazonS3, AmazonS3Builder},
+    path::Path as ObjectPath,
+    ObjectStore, PutPayload,
+};
+
+#[cfg(not(feature = "s3"))]
+use crate::FsError;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Tells apart the temporary files of objects being written by the same process.
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// A slow store that holds the blocks of a [`FlatFsStore`](super::FlatFsStore) behind its local
+/// blocks directory.
+///
+/// Blocks are stored as opaque objects keyed by the hex digest of their CID. An object holds the
+/// block's data as it is stored locally, compressed or encrypted, so the remote store never sees
+/// more of a block than the local disk does.
+#[async_trait]
+pub trait RemoteStore: fmt::Debug + Send + Sync {
+    /// Returns the object stored under `digest`, if any.
+    async fn get(&self, digest: &str) -> StoreResult<Option<Bytes>>;
+
+    /// Stores `data` under `digest`, replacing any object already stored under it.
+    async fn put(&self, digest: &str, data: Bytes) -> StoreResult<()>;
+
+    /// Returns whether an object is stored under `digest`.
+    async fn has(&self, digest: &str) -> StoreResult<bool>;
+}
+
+/// A [`RemoteStore`] that keeps objects in a directory of the host, e.g. a network share.
+///
+/// Objects are nested in subdirectories named after the first two characters of their digest,
+/// like the blocks of a local store.
+#[derive(Debug, Clone)]
+pub struct DirRemoteStore {
+    /// The directory holding the objects.
+    path: PathBuf,
+}
+
+/// A [`RemoteStore`] that keeps objects in an S3 bucket.
+#[cfg(feature = "s3")]
+#[derive(Debug)]
+pub struct S3RemoteStore {
+    /// The bucket.
+    bucket: AmazonS3,
+
+    /// The prefix of the keys of the objects.
+    prefix: String,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Methods
+//--------------------------------------------------------------------------------------------------
+
+impl DirRemoteStore {
+    /// Creates a store keeping objects in the directory at `path`, which is created when the
+    /// first object is stored.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Returns the path of the object stored under `digest`.
+    fn get_object_path(&self, digest: &str) -> PathBuf {
+        let prefix = digest.get(0..2).unwrap_or(digest);
+        self.path.join(prefix).join(digest)
+    }
+}
+
+#[cfg(feature = "s3")]
+impl S3RemoteStore {
+    /// Creates a store keeping objects in `bucket` under `prefix`, configured from the `AWS_*`
+    /// environment variables.
+    pub fn new(bucket: &str, prefix: &str) -> FsResult<Self> {
+        let bucket = AmazonS3Builder::from_env()
+            .with_bucket_name(bucket)
+            .build()
+            .map_err(crate::FsError::custom)?;
+
+        Ok(Self {
+            bucket,
+            prefix: prefix.to_string(),
+        })
+    }
+
+    /// Returns the key of the object stored under `digest`.
+    fn get_object_path(&self, digest: &str) -> ObjectPath {
+        if self.prefix.is_empty() {
+            ObjectPath::from(digest)
+        } else {
+            ObjectPath::from(format!("{}/{}", self.prefix, digest))
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Opens the remote store at `location`.
+///
+/// Fails for S3 buckets if monofs was built without the `s3` feature.
+pub fn open_remote_store(location: &RemoteLocation) -> FsResult<Arc<dyn RemoteStore>> {
+    match location {
+        RemoteLocation::Dir(path) => Ok(Arc::new(DirRemoteStore::new(path))),
+        #[cfg(feature = "s3")]
+        RemoteLocation::S3 { bucket, prefix } => Ok(Arc::new(S3RemoteStore::new(bucket, prefix)?)),
+        #[cfg(not(feature = "s3"))]
+        RemoteLocation::S3 { .. } => Err(FsError::InvalidRemoteConfig(format!(
+            "{} needs monofs to be built with the s3 feature",
+            location
+        ))),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Trait Implementations
+//--------------------------------------------------------------------------------------------------
+
+#[async_trait]
+impl RemoteStore for DirRemoteStore {
+    async fn get(&self, digest: &str) -> StoreResult<Option<Bytes>> {
+        match fs::read(self.get_object_path(digest)).await {
+            Ok(data) => Ok(Some(data.into())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(StoreError::custom(e)),
+        }
+    }
+
+    async fn put(&self, digest: &str, data: Bytes) -> StoreResult<()> {
+        let object_path = self.get_object_path(digest);
+        if let Some(parent) = object_path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(StoreError::custom)?;
+        }
+
+        // Readers never see a partly written object
+        let temp_path = object_path.with_extension(format!(
+            "{}-{}.tmp",
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&temp_path, &data)
+            .await
+            .map_err(StoreError::custom)?;
+        fs::rename(&temp_path, &object_path)
+            .await
+            .map_err(StoreError::custom)
+    }
+
+    async fn has(&self, digest: &str) -> StoreResult<bool> {
+        fs::try_exists(self.get_object_path(digest))
+            .await
+            .map_err(StoreError::custom)
+    }
+}
+
+#[cfg(feature = "s3")]
+#[async_trait]
+impl RemoteStore for S3RemoteStore {
+    async fn get(&self, digest: &str) -> StoreResult<Option<Bytes>> {
+        match self.bucket.get(&self.get_object_path(digest)).await {
+            Ok(result) => Ok(Some(result.bytes().await.map_err(StoreError::custom)?)),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(StoreError::custom(e)),
+        }
+    }
+
+    async fn put(&self, digest: &str, data: Bytes) -> StoreResult<()> {
+        self.bucket
+            .put(&self.get_object_path(digest), PutPayload::from(data))
+            .await
+            .map_err(StoreError::custom)?;
+
+        Ok(())
+    }
+
+    async fn has(&self, digest: &str) -> StoreResult<bool> {
+        match self.bucket.head(&self.get_object_path(digest)).await {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(StoreError::custom(e)),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dir_remote_store() -> anyhow::Result<()> {
+        let temp = TempDir::new()?;
+        let store = DirRemoteStore::new(temp.path().join("remote"));
+
+        assert!(!store.has("abcd").await?);
+        assert_eq!(store.get("abcd").await?, None);
+
+        store.put("abcd", Bytes::from_static(b"data")).await?;
+        assert!(store.has("abcd").await?);
+        assert_eq!(store.get("abcd").await?, Some(Bytes::from_static(b"data")));
+        assert!(temp.path().join("remote").join("ab").join("abcd").exists());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::HashSet;
+
+use getset::Getters;
+use ipldstore::{
+    ipld::{cid::Cid, ipld::Ipld},
+    Codec, IpldStore, Storable, StoreResult,
+};
+
+use crate::{
+    filesystem::{Dir, Entity},
+    FsResult,
+};
+
+use super::DagWalker;
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The field entities use to link to their previous version.
+const PREVIOUS_FIELD: &str = "previous";
+
+//--------------------------------------------------------------------------------------------------
+// Types
+//--------------------------------------------------------------------------------------------------
+
+/// An entity found by [`walk_entities`].
+#[derive(Getters)]
+#[getset(get = "pub with_prefix")]
+pub struct WalkedEntity<S>
+where
+    S: IpldStore,
+{
+    /// The path of the entity relative to where the walk started, empty for the entity the walk
+    /// started at.
+    path: String,
+
+    /// How many directories below where the walk started the entity is.
+    depth: usize,
+
+    /// The CID of the entity.
+    cid: Cid,
+
+    /// The entity.
+    entity: Entity<S>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Collects the CIDs of every block reachable from `roots`, including the roots themselves.
+///
+/// Links are followed through every DAG-CBOR node, so entity metadata, directory entries and the
+/// chunk layouts of file contents are all included. The `previous` link of an entity is not
+/// followed: older versions are only reachable if something else, like a snapshot, refers to
+/// them.
+///
+/// Blocks that are referenced but missing from the store are included in the result without
+/// failing the walk. Blocks are loaded concurrently by a [`DagWalker`].
+///
+/// ## Arguments
+/// * `store` - The store holding the blocks
+/// * `roots` - The CIDs to start walking from
+///
+/// ## Returns
+/// The set of reachable CIDs
+pub async fn collect_reachable<S>(
+    store: &S,
+    roots: impl IntoIterator<Item = Cid>,
+) -> StoreResult<HashSet<Cid>>
+where
+    S: IpldStore,
+{
+    Ok(DagWalker::new(store).walk(roots).await?.into_reachable())
+}
+
+/// Returns the CIDs the block at `cid` links to.
+///
+/// Like [`collect_reachable`], this skips the `previous` link of entities. Raw blocks and blocks
+/// that are missing from the store have no links.
+pub async fn get_links<S>(store: &S, cid: &Cid) -> StoreResult<Vec<Cid>>
+where
+    S: IpldStore,
+{
+    Ok(load_links(store, cid).await?.unwrap_or_default())
+}
+
+/// Returns the CIDs the block at `cid` links to, as [`get_links`] does, or `None` if it is a
+/// DAG-CBOR block missing from the store. Raw blocks are not looked for.
+pub(crate) async fn load_links<S>(store: &S, cid: &Cid) -> StoreResult<Option<Vec<Cid>>>
+where
+    S: IpldStore,
+{
+    let mut links = Vec::new();
+
+    // Raw blocks hold file contents and have no links
+    let codec: Codec = cid.codec().try_into()?;
+    if !matches!(codec, Codec::DagCbor) {
+        return Ok(Some(links));
+    }
+
+    if !store.has(cid).await {
+        return Ok(None);
+    }
+
+    match store.get_node::<Ipld>(cid).await? {
+        Ipld::Map(fields) => {
+            for (key, value) in &fields {
+                if key != PREVIOUS_FIELD {
+                    collect_links(value, &mut links);
+                }
+            }
+        }
+        node => collect_links(&node, &mut links),
+    }
+
+    Ok(Some(links))
+}
+
+/// Walks the entities below the entity at `root` and returns them, along with the entity itself.
+///
+/// Where [`collect_reachable`] walks blocks, this walks the tree of named entities they make up.
+/// The entity at `root` comes first, and every directory is followed by its entries in name order
+/// and the entries below them, the way `tree` prints them. Symbolic links are not followed.
+///
+/// ## Arguments
+/// * `store` - The store holding the blocks
+/// * `root` - The CID of the entity to start walking from
+/// * `max_depth` - How many directories down to walk at most, or `None` to walk the whole tree
+pub async fn walk_entities<S>(
+    store: &S,
+    root: &Cid,
+    max_depth: Option<usize>,
+) -> FsResult<Vec<WalkedEntity<S>>>
+where
+    S: IpldStore + Send + Sync,
+{
+    let mut walked = Vec::new();
+    let mut pending = vec![WalkedEntity {
+        path: String::new(),
+        depth: 0,
+        cid: *root,
+        entity: Entity::load(root, store.clone()).await?,
+    }];
+
+    while let Some(walked_entity) = pending.pop() {
+        if let Entity::Dir(dir) = &walked_entity.entity {
+            if !matches!(max_depth, Some(max_depth) if walked_entity.depth >= max_depth) {
+                let entries = get_sorted_entries(dir, &walked_entity).await?;
+                pending.extend(entries.into_iter().rev());
+            }
+        }
+
+        walked.push(walked_entity);
+    }
+
+    Ok(walked)
+}
+
+/// Returns the entries of `dir`, the entity `parent` was walked to, in name order.
+async fn get_sorted_entries<S>(
+    dir: &Dir<S>,
+    parent: &WalkedEntity<S>,
+) -> FsResult<Vec<WalkedEntity<S>>>
+where
+    S: IpldStore + Send + Sync,
+{
+    let mut entries = dir.get_entries().collect::<Vec<_>>();
+    entries.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+
+    let mut walked = Vec::with_capacity(entries.len());
+    for (name, link) in entries {
+        let path = if parent.path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}/{}", parent.path, name)
+        };
+
+        walked.push(WalkedEntity {
+            path,
+            depth: parent.depth + 1,
+            cid: link.resolve_cid::<S>().await?,
+            entity: link.resolve_entity(dir.get_store().clone()).await?.clone(),
+        });
+    }
+
+    Ok(walked)
+}
+
+/// Pushes every link found in `ipld` onto `links`.
+fn collect_links(ipld: &Ipld, links: &mut Vec<Cid>) {
+    match ipld {
+        Ipld::Link(cid) => links.push(*cid),
+        Ipld::List(items) => items.iter().for_each(|item| collect_links(item, links)),
+        Ipld::Map(fields) => fields
+            .values()
+            .for_each(|value| collect_links(value, links)),
+        _ => {}
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use ipldstore::MemoryStore;
+
+    use crate::filesystem::{Dir, File};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_collect_reachable_skips_previous_versions() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut dir = Dir::new(store.clone());
+
+        let file = File::with_content(store.clone(), b"old".as_slice()).await?;
+        let old_content = *file.get_content().unwrap();
+        dir.put_adapted_file("file.txt", file).await?;
+        let old_root = dir.checkpoint().await?;
+
+        let file = File::with_content(store.clone(), b"new".as_slice()).await?;
+        let new_content = *file.get_content().unwrap();
+        dir.put_adapted_file("file.txt", file).await?;
+        let new_root = dir.checkpoint().await?;
+
+        let reachable = collect_reachable(&store, [new_root]).await?;
+        assert!(reachable.contains(&new_root));
+        assert!(reachable.contains(&new_content));
+        assert!(!reachable.contains(&old_root));
+        assert!(!reachable.contains(&old_content));
+
+        // Both versions are kept when both are roots
+        let reachable = collect_reachable(&store, [old_root, new_root]).await?;
+        assert!(reachable.contains(&old_content));
+        assert!(reachable.contains(&new_content));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_walk_entities() -> anyhow::Result<()> {
+        let store = MemoryStore::default();
+        let mut root = Dir::new(store.clone());
+        root.find_or_create("b/c.txt", true).await?;
+        root.find_or_create("b/d/e.txt", true).await?;
+        root.find_or_create("a.txt", true).await?;
+        let cid = root.checkpoint().await?;
+
+        let walked = walk_entities(&store, &cid, None).await?;
+        let paths = walked
+            .iter()
+            .map(|walked| (walked.get_path().as_str(), *walked.get_depth()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                ("", 0),
+                ("a.txt", 1),
+                ("b", 1),
+                ("b/c.txt", 2),
+                ("b/d", 2),
+                ("b/d/e.txt", 3)
+            ]
+        );
+        assert_eq!(walked[0].get_cid(), &cid);
+        assert!(walked[1].get_entity().is_file());
+
+        let walked = walk_entities(&store, &cid, Some(1)).await?;
+        assert_eq!(walked.len(), 3);
+
+        Ok(())
+    }
+}
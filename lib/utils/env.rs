@@ -6,3 +6,37 @@
 
 /// Environment variable for the mfsrun binary path
 pub const MFSRUN_EXE_ENV_VAR: &str = "MFSRUN_EXE";
+
+/// Environment variable for the key encrypting a filesystem at rest, in hex
+pub const ENCRYPTION_KEY_ENV_VAR: &str = "MONOFS_ENCRYPTION_KEY";
+
+/// Environment variable for the path of a file holding the key encrypting a filesystem at rest
+pub const ENCRYPTION_KEY_FILE_ENV_VAR: &str = "MONOFS_ENCRYPTION_KEY_FILE";
+
+/// Environment variable for the key signing the roots a filesystem records, in hex
+pub const SIGNING_KEY_ENV_VAR: &str = "MONOFS_SIGNING_KEY";
+
+/// Environment variable for the path of a file holding the key signing the roots a filesystem
+/// records
+pub const SIGNING_KEY_FILE_ENV_VAR: &str = "MONOFS_SIGNING_KEY_FILE";
+
+/// Environment variable for the filter deciding which logs and spans are recorded
+pub const LOG_FILTER_ENV_VAR: &str = "RUST_LOG";
+
+/// Environment variable for the OpenTelemetry collector spans are exported to over OTLP
+pub const OTLP_ENDPOINT_ENV_VAR: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// Environment variable for the path of the user's configuration file
+pub const USER_CONFIG_ENV_VAR: &str = "MONOFS_CONFIG";
+
+/// Environment variable for the address new filesystems serve NFS on by default
+pub const HOST_ENV_VAR: &str = "MONOFS_HOST";
+
+/// Environment variable for the ports new filesystems search for a free NFS port in by default
+pub const PORT_RANGE_ENV_VAR: &str = "MONOFS_PORT_RANGE";
+
+/// Environment variable for the mechanism new filesystems are mounted with by default
+pub const BACKEND_ENV_VAR: &str = "MONOFS_BACKEND";
+
+/// Environment variable for the directory where the mounts on the host are registered
+pub const MOUNT_REGISTRY_ENV_VAR: &str = "MONOFS_REGISTRY_DIR";
@@ -0,0 +1,49 @@
+//! Helpers for serializing management results as JSON.
+//!
+//! CIDs serialize to their binary IPLD form by default, which is of no use to the tools reading
+//! the JSON output of the CLI, so results serialize them as strings with these helpers instead.
+
+use std::fmt::Display;
+
+use serde::Serializer;
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Serializes `value` as the string it displays as.
+///
+/// ## Example
+/// ```
+/// use ipldstore::ipld::cid::Cid;
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct Root {
+///     #[serde(serialize_with = "monofs::utils::serialize_display")]
+///     cid: Cid,
+/// }
+///
+/// let cid: Cid = "bafyreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku".parse().unwrap();
+/// let json = serde_json::to_string(&Root { cid }).unwrap();
+/// assert_eq!(json, format!(r#"{{"cid":"{}"}}"#, cid));
+/// ```
+pub fn serialize_display<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Display,
+    S: Serializer,
+{
+    serializer.collect_str(value)
+}
+
+/// Serializes `value` as the string it displays as, or as null if it is missing.
+pub fn serialize_optional_display<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Display,
+    S: Serializer,
+{
+    match value {
+        Some(value) => serializer.collect_str(value),
+        None => serializer.serialize_none(),
+    }
+}
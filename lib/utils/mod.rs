@@ -2,7 +2,9 @@
 
 pub mod dir;
 pub mod env;
+pub mod json;
 pub mod path;
+pub mod process;
 
 //--------------------------------------------------------------------------------------------------
 // Exports
@@ -10,4 +12,6 @@ pub mod path;
 
 pub use dir::*;
 pub use env::*;
+pub use json::*;
 pub use path::*;
+pub use process::*;
@@ -17,15 +17,36 @@ pub const LOG_SUBDIR: &str = "log";
 /// The directory where the filesystem's blocks are stored
 pub const BLOCKS_SUBDIR: &str = "blocks";
 
+/// The directory where corrupt blocks found by the scrubber are moved to
+pub const QUARANTINE_SUBDIR: &str = "quarantine";
+
 /// The filename of the database that stores the filesystem's metadata
 pub const FS_DB_FILENAME: &str = "fs.db";
 
 /// The name of the symlink that links to the actual filesystem data
 pub const MFS_LINK_FILENAME: &str = ".mfs_link";
 
+/// The filename of the unix socket used to control a running filesystem server
+pub const CONTROL_SOCKET_FILENAME: &str = "control.sock";
+
+/// The filename of the unix socket the supervisor serves its gRPC control API on
+pub const GRPC_SOCKET_FILENAME: &str = "grpc.sock";
+
 /// The prefix for mfsrun log files
 pub const MFSRUN_LOG_PREFIX: &str = "mfsrun";
 
+/// The directory under the user's configuration directory holding monofs configuration
+pub const USER_CONFIG_SUBDIR: &str = "monofs";
+
+/// The filename of the user's configuration file
+pub const USER_CONFIG_FILENAME: &str = "config.toml";
+
+/// The directory under the user's state directory holding monofs state
+pub const USER_STATE_SUBDIR: &str = "monofs";
+
+/// The directory under the monofs state directory where mounts are registered
+pub const MOUNT_REGISTRY_SUBDIR: &str = "mounts";
+
 //--------------------------------------------------------------------------------------------------
 // Functions
 //--------------------------------------------------------------------------------------------------
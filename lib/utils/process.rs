@@ -0,0 +1,120 @@
+//! Helpers for checking on and stopping processes by PID, and for what the current process may do.
+//!
+//! Filesystems record the PIDs of their supervisors and servers, which other processes check on
+//! and stop later by signaling them.
+//!
+//! PIDs that do not name a single process, like 0, which is signaled as the caller's process
+//! group, or those too large for a PID, which are signaled as other groups, are taken not to
+//! exist.
+
+use nix::{
+    errno::Errno,
+    sys::signal::{self, Signal},
+    unistd::{self, Pid},
+};
+
+use crate::{FsError, FsResult};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns whether a process with the given PID exists.
+///
+/// ## Example
+/// ```
+/// use monofs::utils;
+///
+/// assert!(utils::is_process_running(std::process::id()));
+/// ```
+pub fn is_process_running(pid: u32) -> bool {
+    // A process owned by another user exists even though it may not be signaled
+    is_valid_pid(pid)
+        && matches!(
+            signal::kill(Pid::from_raw(pid as i32), None),
+            Ok(()) | Err(Errno::EPERM)
+        )
+}
+
+/// Returns whether the current process runs with the privileges needed to mount filesystems
+/// itself, which means its effective user is root.
+pub fn is_privileged() -> bool {
+    unistd::geteuid().is_root()
+}
+
+/// Asks the process with the given PID to exit by sending it `SIGTERM`, so that it may clean up
+/// before exiting.
+///
+/// ## Returns
+/// Whether the process existed
+pub fn terminate_process(pid: u32) -> FsResult<bool> {
+    send_signal(pid, Signal::SIGTERM)
+}
+
+/// Kills the process with the given PID by sending it `SIGKILL`, without letting it clean up.
+///
+/// ## Returns
+/// Whether the process existed
+pub fn kill_process(pid: u32) -> FsResult<bool> {
+    send_signal(pid, Signal::SIGKILL)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions: Helpers
+//--------------------------------------------------------------------------------------------------
+
+/// Returns whether `pid` may name a single process.
+fn is_valid_pid(pid: u32) -> bool {
+    pid != 0 && i32::try_from(pid).is_ok()
+}
+
+/// Sends `signal` to the process with the given PID, returning whether the process existed.
+fn send_signal(pid: u32, signal: Signal) -> FsResult<bool> {
+    if !is_valid_pid(pid) {
+        return Ok(false);
+    }
+
+    match signal::kill(Pid::from_raw(pid as i32), signal) {
+        Ok(()) => Ok(true),
+        Err(Errno::ESRCH) => Ok(false),
+        Err(e) => Err(FsError::custom(e)),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::process::Stdio;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_process_control() -> anyhow::Result<()> {
+        let mut child = tokio::process::Command::new("sleep")
+            .arg("30")
+            .stdout(Stdio::null())
+            .spawn()?;
+        let pid = child.id().unwrap();
+        assert!(is_process_running(pid));
+
+        assert!(terminate_process(pid)?);
+        child.wait().await?;
+        assert!(!is_process_running(pid));
+
+        // Once reaped, the process is gone for good
+        assert!(!terminate_process(pid)?);
+        assert!(!kill_process(pid)?);
+
+        // PIDs that would signal process groups name no process
+        for pid in [0, u32::MAX] {
+            assert!(!is_process_running(pid));
+            assert!(!terminate_process(pid)?);
+            assert!(!kill_process(pid)?);
+        }
+
+        Ok(())
+    }
+}